@@ -0,0 +1,247 @@
+//! Minimal ZIP writer for exports. Entries are stored uncompressed: the
+//! messages and PDFs that go into these archives gain little from deflate,
+//! and every unzip tool reads stored entries. A reader for the Office
+//! documents search indexes sits at the end.
+
+use chrono::{Datelike, Local, Timelike};
+use flate2::read::DeflateDecoder;
+use std::io::{self, Read, Write};
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+/// Bit 11: names are UTF-8.
+const UTF8_NAMES: u16 = 1 << 11;
+const VERSION: u16 = 20;
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+struct Entry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+pub struct ZipWriter<W: Write> {
+    out: W,
+    written: u64,
+    entries: Vec<Entry>,
+    time: u16,
+    date: u16,
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "archive exceeds ZIP size limits",
+    )
+}
+
+impl<W: Write> ZipWriter<W> {
+    pub fn new(out: W) -> Self {
+        let now = Local::now();
+        Self {
+            out,
+            written: 0,
+            entries: Vec::new(),
+            time: ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16,
+            date: ((((now.year().max(1980) - 1980) as u32) << 9) | (now.month() << 5) | now.day())
+                as u16,
+        }
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    pub fn add(&mut self, name: &str, data: &[u8]) -> io::Result<()> {
+        let offset = u32::try_from(self.written).map_err(|_| too_large())?;
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let entry = Entry {
+            name: name.to_string(),
+            crc: crc32fast::hash(data),
+            size,
+            offset,
+        };
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&UTF8_NAMES.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // stored
+        header.extend_from_slice(&self.time.to_le_bytes());
+        header.extend_from_slice(&self.date.to_le_bytes());
+        header.extend_from_slice(&entry.crc.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(name.as_bytes());
+        self.put(&header)?;
+        self.put(data)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the central directory and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let directory_offset = u32::try_from(self.written).map_err(|_| too_large())?;
+        let entries = std::mem::take(&mut self.entries);
+        for entry in &entries {
+            let mut header = Vec::with_capacity(46 + entry.name.len());
+            header.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+            header.extend_from_slice(&VERSION.to_le_bytes());
+            header.extend_from_slice(&VERSION.to_le_bytes());
+            header.extend_from_slice(&UTF8_NAMES.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&self.time.to_le_bytes());
+            header.extend_from_slice(&self.date.to_le_bytes());
+            header.extend_from_slice(&entry.crc.to_le_bytes());
+            header.extend_from_slice(&entry.size.to_le_bytes());
+            header.extend_from_slice(&entry.size.to_le_bytes());
+            header.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            // Extra field, comment, disk number, internal and external
+            // attributes.
+            header.extend_from_slice(&[0u8; 12]);
+            header.extend_from_slice(&entry.offset.to_le_bytes());
+            header.extend_from_slice(entry.name.as_bytes());
+            self.put(&header)?;
+        }
+        let directory_size =
+            u32::try_from(self.written).map_err(|_| too_large())? - directory_offset;
+        let count = u16::try_from(entries.len()).map_err(|_| too_large())?;
+
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY.to_le_bytes());
+        end.extend_from_slice(&[0u8; 4]); // disk numbers
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&count.to_le_bytes());
+        end.extend_from_slice(&directory_size.to_le_bytes());
+        end.extend_from_slice(&directory_offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes());
+        self.put(&end)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// The entries of a ZIP archive whose names `wanted` accepts, decompressed
+/// and cut off at `max_bytes` each, in archive order. Entries compressed
+/// other than by deflate are skipped, and an archive whose central
+/// directory cannot be read yields nothing.
+pub fn read_entries(
+    bytes: &[u8],
+    wanted: impl Fn(&str) -> bool,
+    max_bytes: u64,
+) -> Vec<(String, Vec<u8>)> {
+    const END_LEN: usize = 22;
+    const CENTRAL_LEN: usize = 46;
+    const LOCAL_LEN: usize = 30;
+
+    let u16_at = |offset: usize| -> Option<usize> {
+        let field = bytes.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes([field[0], field[1]]) as usize)
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        let field = bytes.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+    };
+
+    let mut found = Vec::new();
+    let Some(search_from) = bytes.len().checked_sub(END_LEN) else {
+        return found;
+    };
+    let Some(end) = (search_from.saturating_sub(u16::MAX as usize)..=search_from)
+        .rev()
+        .find(|offset| u32_at(*offset) == Some(END_OF_CENTRAL_DIRECTORY))
+    else {
+        return found;
+    };
+    let (Some(count), Some(directory)) = (u16_at(end + 10), u32_at(end + 16)) else {
+        return found;
+    };
+
+    let mut offset = directory as usize;
+    for _ in 0..count {
+        if u32_at(offset) != Some(CENTRAL_HEADER) {
+            break;
+        }
+        let (Some(method), Some(compressed), Some(name_len), Some(extra_len), Some(comment_len)) = (
+            u16_at(offset + 10),
+            u32_at(offset + 20),
+            u16_at(offset + 28),
+            u16_at(offset + 30),
+            u16_at(offset + 32),
+        ) else {
+            break;
+        };
+        let local = u32_at(offset + 42).map(|local| local as usize);
+        let name = bytes
+            .get(offset + CENTRAL_LEN..offset + CENTRAL_LEN + name_len)
+            .map(String::from_utf8_lossy)
+            .map(|name| name.into_owned());
+        offset += CENTRAL_LEN + name_len + extra_len + comment_len;
+
+        let (Some(name), Some(local)) = (name, local) else {
+            continue;
+        };
+        if !wanted(&name) || u32_at(local) != Some(LOCAL_HEADER) {
+            continue;
+        }
+        let (Some(local_name_len), Some(local_extra_len)) =
+            (u16_at(local + 26), u16_at(local + 28))
+        else {
+            continue;
+        };
+        let start = local + LOCAL_LEN + local_name_len + local_extra_len;
+        let Some(data) = bytes.get(start..start.saturating_add(compressed as usize)) else {
+            continue;
+        };
+        let mut content = Vec::new();
+        match method as u16 {
+            STORED => content.extend_from_slice(&data[..data.len().min(max_bytes as usize)]),
+            DEFLATED => {
+                // A damaged stream still gives what was read before it.
+                let _ = DeflateDecoder::new(data)
+                    .take(max_bytes)
+                    .read_to_end(&mut content);
+            }
+            _ => continue,
+        }
+        found.push((name, content));
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::DeflateEncoder, Compression};
+
+    #[test]
+    fn reads_back_stored_and_deflated_entries() {
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add("a.txt", b"first").unwrap();
+        zip.add("b.txt", b"second").unwrap();
+        let archive = zip.finish().unwrap();
+        let entries = read_entries(&archive, |name| name == "b.txt", 1024);
+        assert_eq!(entries, vec![("b.txt".to_string(), b"second".to_vec())]);
+        assert!(read_entries(b"not a zip", |_| true, 1024).is_empty());
+
+        // Rewrite the stored entry as deflated, the way Office saves files.
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&b"x".repeat(4096)).unwrap();
+        let packed = encoder.finish().unwrap();
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add("c.xml", &packed).unwrap();
+        let mut archive = zip.finish().unwrap();
+        archive[8] = DEFLATED as u8;
+        let central = archive.len() - 22 - 46 - "c.xml".len();
+        archive[central + 10] = DEFLATED as u8;
+        let entries = read_entries(&archive, |_| true, 100);
+        assert_eq!(entries[0].1, b"x".repeat(100));
+    }
+}
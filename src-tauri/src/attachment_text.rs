@@ -0,0 +1,681 @@
+//! Text of PDF and Office attachments, for search. Both formats are read
+//! with only the parsing search needs. For a PDF that is the strings its
+//! pages draw, decoded through the fonts' `ToUnicode` maps when there are
+//! any (fonts are not told apart, which is rarely visible in practice).
+//! For Word, Excel, PowerPoint, and OpenDocument files it is the text runs
+//! of the XML inside the archive. Scanned PDFs hold no text to find, and
+//! legacy binary `.doc`/`.xls` files are not read.
+
+use flate2::read::ZlibDecoder;
+use once_cell::sync::Lazy;
+use regex::bytes::Regex as BytesRegex;
+use regex::Regex;
+use std::collections::HashMap;
+use std::io::Read;
+
+use crate::archive;
+use crate::attachments;
+
+/// Text kept per message, across all of its documents.
+pub const MAX_TEXT_CHARS: usize = 100_000;
+
+/// Larger attachments are not opened.
+const MAX_DOCUMENT_BYTES: usize = 25 * 1024 * 1024;
+/// Most bytes read out of one compressed stream or archive entry.
+const MAX_INFLATED_BYTES: u64 = 16 * 1024 * 1024;
+/// Widest `bfrange` read from a `ToUnicode` map.
+const MAX_RANGE: u32 = 0xffff;
+/// `TJ` offsets (thousandths of an em) past this read as a word gap.
+const TJ_SPACE: f64 = -150.0;
+
+/// Streams that hold no page text: images, embedded fonts, metadata, and
+/// cross-reference or object streams.
+static SKIPPED_STREAM: Lazy<BytesRegex> = Lazy::new(|| {
+    BytesRegex::new(concat!(
+        r"/Subtype\s*/(?:Image|Type1C|CIDFontType0C|OpenType|XML)\b",
+        r"|/Type\s*/(?:XRef|ObjStm|Metadata|EmbeddedFile)\b|/Length[123]\b",
+    ))
+    .expect("skipped stream pattern is valid")
+});
+static FILTER: Lazy<BytesRegex> = Lazy::new(|| {
+    BytesRegex::new(r"/Filter\s*(\[[^\]]*\]|/[A-Za-z0-9]+)").expect("filter pattern is valid")
+});
+static FILTER_NAME: Lazy<BytesRegex> =
+    Lazy::new(|| BytesRegex::new(r"/([A-Za-z0-9]+)").expect("filter name pattern is valid"));
+static BFCHAR: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)beginbfchar(.*?)endbfchar").expect("bfchar pattern is valid"));
+static BFCHAR_PAIR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"<([0-9A-Fa-f]+)>\s*<([0-9A-Fa-f]*)>").expect("bfchar pair pattern is valid")
+});
+static BFRANGE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)beginbfrange(.*?)endbfrange").expect("bfrange pattern is valid"));
+static BFRANGE_ENTRY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"<([0-9A-Fa-f]+)>\s*<([0-9A-Fa-f]+)>\s*(?:<([0-9A-Fa-f]*)>|\[([^\]]*)\])")
+        .expect("bfrange entry pattern is valid")
+});
+static HEX_STRING: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"<([0-9A-Fa-f]*)>").expect("hex string pattern is valid"));
+/// Tags that end a paragraph, cell, or line, or stand for a tab or space.
+static XML_BREAK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"</(?:w:p|w:tc|a:p|si|text:p|text:h|table:table-cell)>",
+        r"|<(?:w:br|w:tab|w:cr|a:br|text:tab|text:s|text:line-break)\b[^>]*>",
+    ))
+    .expect("xml break pattern is valid")
+});
+static XML_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)<[^>]*>").expect("xml tag pattern is valid"));
+static XML_ENTITY: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"&(?:#x([0-9A-Fa-f]+)|#([0-9]+)|(amp|lt|gt|quot|apos));")
+        .expect("xml entity pattern is valid")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Pdf,
+    Word,
+    Spreadsheet,
+    Presentation,
+    OpenDocument,
+}
+
+fn kind(filename: &str, content_type: &str) -> Option<Kind> {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.trim().to_lowercase())
+        .unwrap_or_default();
+    let content_type = content_type.to_lowercase();
+    match extension.as_str() {
+        "pdf" => return Some(Kind::Pdf),
+        "docx" | "docm" | "dotx" => return Some(Kind::Word),
+        "xlsx" | "xlsm" | "xltx" => return Some(Kind::Spreadsheet),
+        "pptx" | "pptm" | "ppsx" => return Some(Kind::Presentation),
+        "odt" | "ods" | "odp" => return Some(Kind::OpenDocument),
+        _ => {}
+    }
+    if content_type == "application/pdf" {
+        Some(Kind::Pdf)
+    } else if content_type.contains("wordprocessingml") {
+        Some(Kind::Word)
+    } else if content_type.contains("spreadsheetml") {
+        Some(Kind::Spreadsheet)
+    } else if content_type.contains("presentationml") {
+        Some(Kind::Presentation)
+    } else if content_type.starts_with("application/vnd.oasis.opendocument.") {
+        Some(Kind::OpenDocument)
+    } else {
+        None
+    }
+}
+
+/// Whether [`extract`] reads attachments of this name and type.
+pub fn is_indexable(filename: &str, content_type: &str) -> bool {
+    kind(filename, content_type).is_some()
+}
+
+/// The text of one document, whitespace collapsed, or `None` when it is
+/// not a type read here or has no text.
+pub fn extract(filename: &str, content_type: &str, bytes: &[u8]) -> Option<String> {
+    if bytes.len() > MAX_DOCUMENT_BYTES {
+        return None;
+    }
+    let raw = match kind(filename, content_type)? {
+        Kind::Pdf => pdf_text(bytes),
+        Kind::Word => office_text(bytes, |name| {
+            name == "word/document.xml"
+                || name == "word/footnotes.xml"
+                || name == "word/endnotes.xml"
+                || ((name.starts_with("word/header") || name.starts_with("word/footer"))
+                    && name.ends_with(".xml"))
+        }),
+        Kind::Spreadsheet => office_text(bytes, |name| name == "xl/sharedStrings.xml"),
+        Kind::Presentation => office_text(bytes, |name| {
+            name.starts_with("ppt/slides/slide") && name.ends_with(".xml")
+        }),
+        Kind::OpenDocument => office_text(bytes, |name| name == "content.xml"),
+    };
+    let text = collapse_whitespace(&raw, MAX_TEXT_CHARS);
+    (!text.is_empty()).then_some(text)
+}
+
+/// The text of every readable document attached to a raw message, and how
+/// many documents had any.
+pub fn message_text(raw: &[u8]) -> (String, usize) {
+    let mut text = String::new();
+    let mut documents = 0;
+    for (attachment, bytes) in attachments::list_with_content(raw) {
+        if !is_indexable(&attachment.filename, &attachment.content_type) {
+            continue;
+        }
+        let Some(found) = extract(&attachment.filename, &attachment.content_type, &bytes) else {
+            continue;
+        };
+        documents += 1;
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(&found);
+    }
+    (collapse_whitespace(&text, MAX_TEXT_CHARS), documents)
+}
+
+fn office_text(bytes: &[u8], wanted: impl Fn(&str) -> bool) -> String {
+    archive::read_entries(bytes, wanted, MAX_INFLATED_BYTES)
+        .into_iter()
+        .map(|(_, xml)| xml_text(&String::from_utf8_lossy(&xml)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn xml_text(xml: &str) -> String {
+    let spaced = XML_BREAK.replace_all(xml, " ");
+    let text = XML_TAG.replace_all(&spaced, "");
+    XML_ENTITY
+        .replace_all(&text, |caps: &regex::Captures<'_>| {
+            let code = caps
+                .get(1)
+                .and_then(|hex| u32::from_str_radix(hex.as_str(), 16).ok())
+                .or_else(|| caps.get(2).and_then(|dec| dec.as_str().parse().ok()));
+            match (code, caps.get(3).map(|name| name.as_str())) {
+                (Some(code), _) => char::from_u32(code).map(String::from).unwrap_or_default(),
+                (None, Some("amp")) => "&".into(),
+                (None, Some("lt")) => "<".into(),
+                (None, Some("gt")) => ">".into(),
+                (None, Some("quot")) => "\"".into(),
+                (None, Some("apos")) => "'".into(),
+                _ => String::new(),
+            }
+        })
+        .into_owned()
+}
+
+/// Collapses whitespace so phrases broken across lines still match, and
+/// keeps at most `max_chars`.
+fn collapse_whitespace(text: &str, max_chars: usize) -> String {
+    let mut collapsed = String::new();
+    let mut chars = 0;
+    for word in text.split_whitespace() {
+        let len = word.chars().count();
+        if chars + len + 1 > max_chars {
+            break;
+        }
+        if chars > 0 {
+            collapsed.push(' ');
+            chars += 1;
+        }
+        collapsed.push_str(word);
+        chars += len;
+    }
+    collapsed
+}
+
+fn pdf_text(bytes: &[u8]) -> String {
+    let streams = pdf_streams(bytes);
+    let mut unicode = ToUnicode::default();
+    for stream in &streams {
+        if contains(stream, b"begincmap") {
+            unicode.read(&String::from_utf8_lossy(stream));
+        }
+    }
+    let mut text = String::new();
+    for stream in &streams {
+        if !contains(stream, b"begincmap") && contains(stream, b"BT") {
+            content_text(stream, &unicode, &mut text);
+            text.push(' ');
+        }
+    }
+    text
+}
+
+/// The decoded content of every stream that may hold page text or a
+/// `ToUnicode` map.
+fn pdf_streams(bytes: &[u8]) -> Vec<Vec<u8>> {
+    let mut streams = Vec::new();
+    let mut from = 0;
+    while let Some(found) = find(&bytes[from..], b"stream") {
+        let keyword = from + found;
+        from = keyword + b"stream".len();
+        // `endstream` ends in the same letters.
+        if keyword >= 3 && &bytes[keyword - 3..keyword] == b"end" {
+            continue;
+        }
+        let mut start = from;
+        if bytes.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if bytes.get(start) != Some(&b'\n') {
+            continue;
+        }
+        start += 1;
+        let dictionary_start = rfind(&bytes[..keyword], b"obj").unwrap_or(0);
+        let dictionary = &bytes[dictionary_start..keyword];
+        let Some(length) = find(&bytes[start..], b"endstream") else {
+            break;
+        };
+        let end = start + length;
+        from = end;
+        if SKIPPED_STREAM.is_match(dictionary) {
+            continue;
+        }
+        let data = &bytes[start..end];
+        let filters = FILTER
+            .captures(dictionary)
+            .map(|caps| {
+                FILTER_NAME
+                    .captures_iter(&caps[1])
+                    .map(|name| name[1].to_vec())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        match filters.as_slice() {
+            [] => streams.push(data.to_vec()),
+            [name] if name == b"FlateDecode" || name == b"Fl" => {
+                let mut inflated = Vec::new();
+                // A damaged stream still gives what was read before it.
+                let _ = ZlibDecoder::new(data)
+                    .take(MAX_INFLATED_BYTES)
+                    .read_to_end(&mut inflated);
+                streams.push(inflated);
+            }
+            _ => {}
+        }
+    }
+    streams
+}
+
+/// Character codes to text, from the fonts' `ToUnicode` maps, by code
+/// width in bytes.
+#[derive(Debug, Default)]
+struct ToUnicode {
+    one_byte: HashMap<u32, String>,
+    two_byte: HashMap<u32, String>,
+}
+
+impl ToUnicode {
+    fn read(&mut self, cmap: &str) {
+        for block in BFCHAR.captures_iter(cmap) {
+            for pair in BFCHAR_PAIR.captures_iter(&block[1]) {
+                if let Ok(code) = u32::from_str_radix(&pair[1], 16) {
+                    self.insert(pair[1].len(), code, utf16_hex(&pair[2]));
+                }
+            }
+        }
+        for block in BFRANGE.captures_iter(cmap) {
+            for entry in BFRANGE_ENTRY.captures_iter(&block[1]) {
+                let (Ok(low), Ok(high)) = (
+                    u32::from_str_radix(&entry[1], 16),
+                    u32::from_str_radix(&entry[2], 16),
+                ) else {
+                    continue;
+                };
+                if high < low || high - low > MAX_RANGE {
+                    continue;
+                }
+                let width = entry[1].len();
+                if let Some(list) = entry.get(4) {
+                    let targets = HEX_STRING.captures_iter(list.as_str());
+                    for (code, target) in (low..=high).zip(targets) {
+                        self.insert(width, code, utf16_hex(&target[1]));
+                    }
+                    continue;
+                }
+                let Some(base) = entry.get(3).map(|target| utf16_units(target.as_str())) else {
+                    continue;
+                };
+                let Some((&last, prefix)) = base.split_last() else {
+                    continue;
+                };
+                for code in low..=high {
+                    let mut units = prefix.to_vec();
+                    units.push(last.wrapping_add((code - low) as u16));
+                    self.insert(width, code, String::from_utf16_lossy(&units));
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, hex_digits: usize, code: u32, text: String) {
+        match hex_digits {
+            2 => self.one_byte.insert(code, text),
+            4 => self.two_byte.insert(code, text),
+            _ => None,
+        };
+    }
+
+    /// Text for a string's bytes: through a map when it covers every code,
+    /// otherwise as a single-byte encoding.
+    fn decode(&self, bytes: &[u8]) -> String {
+        if let Some(text) = bytes.strip_prefix(&[0xfe, 0xff]) {
+            let units = text
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>();
+            return String::from_utf16_lossy(&units);
+        }
+        let pairs = bytes.chunks_exact(2);
+        if !self.two_byte.is_empty() && pairs.remainder().is_empty() {
+            let mapped = pairs
+                .map(|pair| {
+                    self.two_byte
+                        .get(&u32::from(u16::from_be_bytes([pair[0], pair[1]])))
+                })
+                .collect::<Option<Vec<_>>>();
+            if let Some(parts) = mapped {
+                return parts.into_iter().map(String::as_str).collect();
+            }
+        }
+        if !self.one_byte.is_empty() {
+            let mapped = bytes
+                .iter()
+                .map(|byte| self.one_byte.get(&u32::from(*byte)))
+                .collect::<Option<Vec<_>>>();
+            if let Some(parts) = mapped {
+                return parts.into_iter().map(String::as_str).collect();
+            }
+        }
+        bytes.iter().map(|byte| single_byte_char(*byte)).collect()
+    }
+}
+
+/// A byte as Windows-1252 text, which most simple PDF fonts use. Control
+/// bytes become spaces.
+fn single_byte_char(byte: u8) -> char {
+    match byte {
+        0x91 | 0x92 => '\'',
+        0x93 | 0x94 => '"',
+        0x96 | 0x97 => '-',
+        0x00..=0x1f | 0x7f..=0x9f => ' ',
+        _ => char::from(byte),
+    }
+}
+
+fn utf16_units(hex: &str) -> Vec<u16> {
+    hex.as_bytes()
+        .chunks(4)
+        .filter_map(|unit| u16::from_str_radix(std::str::from_utf8(unit).ok()?, 16).ok())
+        .collect()
+}
+
+fn utf16_hex(hex: &str) -> String {
+    String::from_utf16_lossy(&utf16_units(hex))
+}
+
+enum Operand {
+    Text(Vec<u8>),
+    Number(f64),
+    Array(Vec<Operand>),
+    Other,
+}
+
+/// Appends the text a content stream draws.
+fn content_text(data: &[u8], unicode: &ToUnicode, out: &mut String) {
+    let mut operands: Vec<Operand> = Vec::new();
+    let mut arrays: Vec<Vec<Operand>> = Vec::new();
+    let mut index = 0;
+    while index < data.len() {
+        let byte = data[index];
+        let (operand, next) = match byte {
+            b'(' => {
+                let (text, next) = literal_string(data, index + 1);
+                (Operand::Text(text), next)
+            }
+            b'<' if data.get(index + 1) == Some(&b'<') => (Operand::Other, index + 2),
+            b'<' => {
+                let end = data[index..]
+                    .iter()
+                    .position(|byte| *byte == b'>')
+                    .map_or(data.len(), |offset| index + offset);
+                (Operand::Text(hex_bytes(&data[index + 1..end])), end + 1)
+            }
+            b'[' => {
+                arrays.push(Vec::new());
+                index += 1;
+                continue;
+            }
+            b']' => {
+                let items = arrays.pop().unwrap_or_default();
+                (Operand::Array(items), index + 1)
+            }
+            b'%' => {
+                index += data[index..]
+                    .iter()
+                    .position(|byte| matches!(byte, b'\r' | b'\n'))
+                    .unwrap_or(data.len() - index);
+                continue;
+            }
+            b'/' => (Operand::Other, token_end(data, index + 1)),
+            b'0'..=b'9' | b'-' | b'+' | b'.' => {
+                let end = token_end(data, index + 1);
+                let number = std::str::from_utf8(&data[index..end])
+                    .ok()
+                    .and_then(|value| value.parse().ok());
+                (number.map_or(Operand::Other, Operand::Number), end)
+            }
+            _ if byte.is_ascii_whitespace() || byte == b'>' || byte == b')' => {
+                index += 1;
+                continue;
+            }
+            _ => {
+                let end = token_end(data, index + 1);
+                index = run_operator(&data[index..end], &operands, unicode, out, data, end);
+                operands.clear();
+                continue;
+            }
+        };
+        match arrays.last_mut() {
+            Some(array) => array.push(operand),
+            None => operands.push(operand),
+        }
+        index = next;
+    }
+}
+
+/// Applies one operator and returns where reading continues.
+fn run_operator(
+    operator: &[u8],
+    operands: &[Operand],
+    unicode: &ToUnicode,
+    out: &mut String,
+    data: &[u8],
+    end: usize,
+) -> usize {
+    match operator {
+        b"Tj" | b"'" | b"\"" => {
+            if operator != b"Tj" {
+                out.push(' ');
+            }
+            if let Some(Operand::Text(text)) = operands.last() {
+                out.push_str(&unicode.decode(text));
+            }
+        }
+        b"TJ" => {
+            if let Some(Operand::Array(items)) = operands.last() {
+                for item in items {
+                    match item {
+                        Operand::Text(text) => out.push_str(&unicode.decode(text)),
+                        Operand::Number(offset) if *offset < TJ_SPACE => out.push(' '),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        b"Td" | b"TD" | b"T*" | b"Tm" | b"BT" | b"ET" => out.push(' '),
+        // Inline image data runs to `EI` and may contain anything.
+        b"ID" => {
+            return find(&data[end..], b"EI").map_or(data.len(), |offset| end + offset + 2);
+        }
+        _ => {}
+    }
+    end
+}
+
+/// Reads a literal string whose opening parenthesis precedes `start`;
+/// returns its bytes and the position after its closing parenthesis.
+fn literal_string(data: &[u8], start: usize) -> (Vec<u8>, usize) {
+    let mut text = Vec::new();
+    let mut depth = 1;
+    let mut index = start;
+    while index < data.len() {
+        let byte = data[index];
+        index += 1;
+        match byte {
+            b'\\' => {
+                let Some(&escaped) = data.get(index) else {
+                    break;
+                };
+                index += 1;
+                match escaped {
+                    b'n' => text.push(b'\n'),
+                    b'r' => text.push(b'\r'),
+                    b't' => text.push(b'\t'),
+                    b'b' => text.push(0x08),
+                    b'f' => text.push(0x0c),
+                    b'0'..=b'7' => {
+                        let mut value = u32::from(escaped - b'0');
+                        for _ in 0..2 {
+                            match data.get(index) {
+                                Some(digit @ b'0'..=b'7') => {
+                                    value = value * 8 + u32::from(digit - b'0');
+                                    index += 1;
+                                }
+                                _ => break,
+                            }
+                        }
+                        text.push(value as u8);
+                    }
+                    // A backslash at the end of a line continues the string.
+                    b'\r' => {
+                        if data.get(index) == Some(&b'\n') {
+                            index += 1;
+                        }
+                    }
+                    b'\n' => {}
+                    other => text.push(other),
+                }
+            }
+            b'(' => {
+                depth += 1;
+                text.push(byte);
+            }
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+                text.push(byte);
+            }
+            _ => text.push(byte),
+        }
+    }
+    (text, index)
+}
+
+fn hex_bytes(hex: &[u8]) -> Vec<u8> {
+    let digits = hex
+        .iter()
+        .filter_map(|byte| char::from(*byte).to_digit(16))
+        .map(|digit| digit as u8)
+        .collect::<Vec<_>>();
+    // An odd final digit is followed by an implied zero.
+    digits
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair.get(1).copied().unwrap_or(0))
+        .collect()
+}
+
+/// The end of a name, number, or operator starting before `start`.
+fn token_end(data: &[u8], start: usize) -> usize {
+    data[start..]
+        .iter()
+        .position(|byte| byte.is_ascii_whitespace() || b"()<>[]{}/%".contains(byte))
+        .map_or(data.len(), |offset| start + offset)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .rposition(|window| window == needle)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    find(haystack, needle).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::ZipWriter;
+    use flate2::{write::ZlibEncoder, Compression};
+    use std::io::Write;
+
+    fn pdf_object(number: usize, dictionary: &str, stream: &[u8]) -> Vec<u8> {
+        let mut object = format!(
+            "{number} 0 obj\n<< {dictionary} /Length {} >>\nstream\n",
+            stream.len()
+        )
+        .into_bytes();
+        object.extend_from_slice(stream);
+        object.extend_from_slice(b"\nendstream\nendobj\n");
+        object
+    }
+
+    #[test]
+    fn reads_pdf_page_text() {
+        let cmap = b"/CIDInit /ProcSet findresource begin begincmap\n\
+                     2 beginbfchar <0001> <0041> <0002> <0067> endbfchar\n\
+                     1 beginbfrange <0003> <0005> <0072> endbfrange\n\
+                     endcmap end";
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(b"BT /F2 12 Tf <000100020003000400050002> Tj ET")
+            .unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        pdf.extend(pdf_object(
+            4,
+            "",
+            b"BT /F1 12 Tf 72 700 Td (Residential lease) Tj\n\
+              0 -14 Td [(agree) 20 (ment,) -400 (\\(signed\\)\\041)] TJ ET",
+        ));
+        pdf.extend(pdf_object(5, "", cmap));
+        pdf.extend(pdf_object(6, "/Filter /FlateDecode", &compressed));
+        pdf.extend(pdf_object(
+            7,
+            "/Subtype /Image /Filter /DCTDecode",
+            b"BT (x) Tj",
+        ));
+        pdf.extend_from_slice(b"trailer\n<< >>\n%%EOF\n");
+
+        assert_eq!(
+            extract("lease.pdf", "application/octet-stream", &pdf).as_deref(),
+            Some("Residential lease agreement, (signed)! Agrstg")
+        );
+    }
+
+    #[test]
+    fn reads_office_document_text() {
+        let document = concat!(
+            r#"<w:document><w:body><w:p><w:r><w:t>Lease</w:t></w:r>"#,
+            r#"<w:r><w:t xml:space="preserve"> agree</w:t></w:r><w:r><w:t>ment</w:t></w:r></w:p>"#,
+            r#"<w:p><w:r><w:t>Tom &amp; Jerry&#x2019;s</w:t></w:r></w:p></w:body></w:document>"#,
+        );
+        let mut zip = ZipWriter::new(Vec::new());
+        zip.add("[Content_Types].xml", b"<Types/>").unwrap();
+        zip.add("word/document.xml", document.as_bytes()).unwrap();
+        let docx = zip.finish().unwrap();
+
+        assert_eq!(
+            extract("lease.docx", "", &docx).as_deref(),
+            Some("Lease agreement Tom & Jerry\u{2019}s")
+        );
+        assert!(!is_indexable("photo.jpg", "image/jpeg"));
+        assert_eq!(extract("empty.docx", "", b"PK"), None);
+    }
+}
@@ -0,0 +1,304 @@
+//! Attachments of a raw message and static checks on them. A part is risky
+//! when its name or type says it runs code (programs, scripts, macro-enabled
+//! Office files), when its name hides the real type behind a harmless one
+//! (`invoice.pdf.exe`, right-to-left override characters), or when it is an
+//! archive too large to be ordinary mail or that expands far beyond its size.
+//! Nothing is opened or executed; only names, types, and sizes are read.
+
+use mailparse::{parse_mail, DispositionType, MailHeaderMap, ParsedMail};
+use serde::{Deserialize, Serialize};
+
+/// Inline images larger than this are not shown.
+const INLINE_IMAGE_MAX_BYTES: usize = 5 * 1024 * 1024;
+/// Archives larger than this are flagged outright.
+const ARCHIVE_MAX_BYTES: usize = 10 * 1024 * 1024;
+/// Zip contents larger than this, once expanded, are flagged.
+const ARCHIVE_MAX_EXPANDED_BYTES: u64 = 1024 * 1024 * 1024;
+/// Zips expanding to more than this multiple of their size are flagged.
+const ARCHIVE_MAX_RATIO: u64 = 100;
+
+const EXECUTABLE_EXTENSIONS: &[&str] = &[
+    "exe", "com", "scr", "pif", "msi", "msp", "msc", "bat", "cmd", "cpl", "dll", "sys", "lnk",
+    "reg", "hta", "inf", "scf", "jar", "apk", "app", "appx", "msix", "gadget",
+];
+const SCRIPT_EXTENSIONS: &[&str] = &[
+    "js",
+    "jse",
+    "vbs",
+    "vbe",
+    "ws",
+    "wsf",
+    "wsc",
+    "wsh",
+    "ps1",
+    "psm1",
+    "psd1",
+    "sh",
+    "command",
+    "scpt",
+    "applescript",
+];
+const MACRO_EXTENSIONS: &[&str] = &[
+    "docm", "dotm", "xlsm", "xltm", "xlam", "xlsb", "pptm", "potm", "ppam", "ppsm", "sldm",
+];
+const ARCHIVE_EXTENSIONS: &[&str] = &[
+    "zip", "rar", "7z", "gz", "tgz", "tar", "bz2", "xz", "cab", "arj", "lz", "ace", "iso", "img",
+    "vhd", "vhdx",
+];
+/// Types an attacker dresses a program up as.
+const DECOY_EXTENSIONS: &[&str] = &[
+    "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "txt", "rtf", "jpg", "jpeg", "png", "gif",
+    "htm", "html", "zip", "mp3", "mp4",
+];
+/// Characters that reorder or hide the visible end of a filename.
+const DISGUISE_CHARS: &[char] = &['\u{202e}', '\u{202d}', '\u{200f}', '\u{2066}', '\u{2067}'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentRisk {
+    Executable,
+    Script,
+    MacroDocument,
+    DoubleExtension,
+    OversizedArchive,
+}
+
+impl AttachmentRisk {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AttachmentRisk::Executable => "executable",
+            AttachmentRisk::Script => "script",
+            AttachmentRisk::MacroDocument => "macro_document",
+            AttachmentRisk::DoubleExtension => "double_extension",
+            AttachmentRisk::OversizedArchive => "oversized_archive",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    /// Position among the message's attachments; stable for a given message.
+    pub index: usize,
+    pub filename: String,
+    pub content_type: String,
+    pub size: usize,
+    pub risks: Vec<AttachmentRisk>,
+}
+
+impl Attachment {
+    pub fn is_risky(&self) -> bool {
+        !self.risks.is_empty()
+    }
+}
+
+/// An image part that HTML bodies show through a `cid:` URL.
+#[derive(Debug, Clone)]
+pub struct InlineImage {
+    /// The `Content-ID`, without angle brackets.
+    pub content_id: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// Every attachment of a raw message, checked.
+pub fn list(raw: &[u8]) -> Vec<Attachment> {
+    parts(raw)
+        .into_iter()
+        .map(|(attachment, _)| attachment)
+        .collect()
+}
+
+/// Every attachment of a raw message, checked, with its decoded content.
+pub fn list_with_content(raw: &[u8]) -> Vec<(Attachment, Vec<u8>)> {
+    parts(raw)
+}
+
+/// The attachment at `index` and its decoded content.
+pub fn extract(raw: &[u8], index: usize) -> Option<(Attachment, Vec<u8>)> {
+    parts(raw)
+        .into_iter()
+        .find(|(attachment, _)| attachment.index == index)
+}
+
+/// The image parts of a raw message that carry a `Content-ID`, whether or
+/// not they are also listed as attachments.
+pub fn inline_images(raw: &[u8]) -> Vec<InlineImage> {
+    let Ok(parsed) = parse_mail(raw) else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    collect_inline_images(&parsed, &mut found);
+    found
+}
+
+fn collect_inline_images(part: &ParsedMail, found: &mut Vec<InlineImage>) {
+    if !part.subparts.is_empty() {
+        for subpart in &part.subparts {
+            collect_inline_images(subpart, found);
+        }
+        return;
+    }
+    let content_type = part.ctype.mimetype.to_lowercase();
+    if !content_type.starts_with("image/") {
+        return;
+    }
+    let Some(content_id) = part
+        .headers
+        .get_first_value("Content-ID")
+        .map(|id| {
+            id.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        })
+        .filter(|id| !id.is_empty())
+    else {
+        return;
+    };
+    let Ok(data) = part.get_body_raw() else {
+        return;
+    };
+    if data.len() <= INLINE_IMAGE_MAX_BYTES {
+        found.push(InlineImage {
+            content_id,
+            content_type,
+            data,
+        });
+    }
+}
+
+fn parts(raw: &[u8]) -> Vec<(Attachment, Vec<u8>)> {
+    let Ok(parsed) = parse_mail(raw) else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    collect(&parsed, &mut found);
+    found
+}
+
+fn collect(part: &ParsedMail, found: &mut Vec<(Attachment, Vec<u8>)>) {
+    if !part.subparts.is_empty() {
+        for subpart in &part.subparts {
+            collect(subpart, found);
+        }
+        return;
+    }
+    let disposition = part.get_content_disposition();
+    let filename = disposition
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    let is_attachment = disposition.disposition == DispositionType::Attachment;
+    let Some(filename) = filename.or_else(|| is_attachment.then(|| "attachment".to_string()))
+    else {
+        return;
+    };
+    let Ok(bytes) = part.get_body_raw() else {
+        return;
+    };
+    let content_type = part.ctype.mimetype.to_lowercase();
+    let attachment = Attachment {
+        index: found.len(),
+        risks: assess(&filename, &content_type, &bytes),
+        filename,
+        content_type,
+        size: bytes.len(),
+    };
+    found.push((attachment, bytes));
+}
+
+/// Static checks on one attachment.
+pub fn assess(filename: &str, content_type: &str, bytes: &[u8]) -> Vec<AttachmentRisk> {
+    let name = filename.trim().trim_end_matches(['.', ' ']).to_lowercase();
+    let mut extensions = name
+        .rsplit('.')
+        .map(str::trim)
+        .take_while(|extension| !extension.is_empty())
+        .collect::<Vec<_>>();
+    if extensions.len() > 1 {
+        // The last piece is the name itself, not an extension.
+        extensions.pop();
+    } else {
+        extensions.clear();
+    }
+    let last = extensions.first().copied().unwrap_or_default();
+    let content_type = content_type.to_lowercase();
+
+    let mut risks = Vec::new();
+    let runs_code = if EXECUTABLE_EXTENSIONS.contains(&last)
+        || content_type == "application/x-msdownload"
+        || content_type == "application/x-msdos-program"
+    {
+        risks.push(AttachmentRisk::Executable);
+        true
+    } else if SCRIPT_EXTENSIONS.contains(&last) {
+        risks.push(AttachmentRisk::Script);
+        true
+    } else {
+        false
+    };
+    if MACRO_EXTENSIONS.contains(&last) || content_type.contains("macroenabled") {
+        risks.push(AttachmentRisk::MacroDocument);
+    }
+    let decoy = extensions
+        .get(1)
+        .is_some_and(|inner| DECOY_EXTENSIONS.contains(inner));
+    if (runs_code && decoy) || filename.contains(DISGUISE_CHARS) {
+        risks.push(AttachmentRisk::DoubleExtension);
+    }
+    if ARCHIVE_EXTENSIONS.contains(&last) && oversized_archive(bytes) {
+        risks.push(AttachmentRisk::OversizedArchive);
+    }
+    risks
+}
+
+fn oversized_archive(bytes: &[u8]) -> bool {
+    if bytes.len() > ARCHIVE_MAX_BYTES {
+        return true;
+    }
+    zip_expanded_size(bytes).is_some_and(|expanded| {
+        expanded > ARCHIVE_MAX_EXPANDED_BYTES
+            || expanded > (bytes.len() as u64).max(1) * ARCHIVE_MAX_RATIO
+    })
+}
+
+/// Total uncompressed size recorded in a zip's central directory, or `None`
+/// when `bytes` is not a zip that can be read that way.
+fn zip_expanded_size(bytes: &[u8]) -> Option<u64> {
+    const END_SIGNATURE: u32 = 0x0605_4b50;
+    const ENTRY_SIGNATURE: u32 = 0x0201_4b50;
+    const END_LEN: usize = 22;
+    const ENTRY_LEN: usize = 46;
+
+    let u16_at = |offset: usize| -> Option<usize> {
+        let field = bytes.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes([field[0], field[1]]) as usize)
+    };
+    let u32_at = |offset: usize| -> Option<u32> {
+        let field = bytes.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([field[0], field[1], field[2], field[3]]))
+    };
+
+    // The end record sits at most a maximum-length comment from the end.
+    let search_from = bytes.len().checked_sub(END_LEN)?;
+    let end = (search_from.saturating_sub(u16::MAX as usize)..=search_from)
+        .rev()
+        .find(|offset| u32_at(*offset) == Some(END_SIGNATURE))?;
+    let entries = u16_at(end + 10)?;
+    let mut offset = u32_at(end + 16)? as usize;
+
+    let mut total = 0u64;
+    for _ in 0..entries {
+        if u32_at(offset)? != ENTRY_SIGNATURE {
+            return None;
+        }
+        total += u64::from(u32_at(offset + 24)?);
+        let name_len = u16_at(offset + 28)?;
+        let extra_len = u16_at(offset + 30)?;
+        let comment_len = u16_at(offset + 32)?;
+        offset += ENTRY_LEN + name_len + extra_len + comment_len;
+    }
+    Some(total)
+}
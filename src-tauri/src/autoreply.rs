@@ -0,0 +1,103 @@
+//! Local out-of-office responder. Settings live per account in app settings;
+//! each sender is answered at most once per activation (see the
+//! `autoreply_log` table) and automated mail is never answered, so two
+//! responders cannot loop.
+
+use crate::classifier;
+use crate::models::EmailSummary;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoReplySettings {
+    pub enabled: bool,
+    /// Unix seconds; mail received before this is ignored.
+    pub starts_at: Option<i64>,
+    /// Unix seconds; the responder stops after this.
+    pub ends_at: Option<i64>,
+    pub template_id: Option<i64>,
+    /// Addresses or `@domain` suffixes to answer. Empty means every sender.
+    #[serde(default)]
+    pub only_senders: Vec<String>,
+    /// When the responder was last switched on; keys the once-per-sender log.
+    #[serde(default)]
+    pub activated_at: i64,
+}
+
+impl AutoReplySettings {
+    pub fn setting_key(account_email: &str) -> String {
+        format!("autoreply:{account_email}")
+    }
+}
+
+/// Why a message was not answered. Useful in logs, never shown to senders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    Disabled,
+    OutsideWindow,
+    AutoSubmitted,
+    AutomatedSender,
+    OwnAddress,
+    NotMatched,
+}
+
+/// Decides whether `message` qualifies for an auto-reply. The per-sender
+/// "already answered" check happens in storage so it stays atomic.
+pub fn evaluate(
+    settings: &AutoReplySettings,
+    account_email: &str,
+    message: &EmailSummary,
+    now: i64,
+) -> Result<(), SkipReason> {
+    if !settings.enabled || settings.template_id.is_none() {
+        return Err(SkipReason::Disabled);
+    }
+    if settings.ends_at.is_some_and(|end| now > end) {
+        return Err(SkipReason::OutsideWindow);
+    }
+
+    // Only mail that arrived while the responder was active gets a reply, so
+    // a full resync never answers old messages.
+    let received = message
+        .date
+        .as_deref()
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        .map(|date| date.timestamp())
+        .ok_or(SkipReason::OutsideWindow)?;
+    let window_start = settings
+        .starts_at
+        .unwrap_or(settings.activated_at)
+        .max(settings.activated_at);
+    if received < window_start || settings.ends_at.is_some_and(|end| received > end) {
+        return Err(SkipReason::OutsideWindow);
+    }
+
+    if message.auto_submitted {
+        return Err(SkipReason::AutoSubmitted);
+    }
+
+    let sender = message.sender.email.trim().to_lowercase();
+    if sender == account_email {
+        return Err(SkipReason::OwnAddress);
+    }
+    if !sender.contains('@') || classifier::is_automated_sender(&sender) {
+        return Err(SkipReason::AutomatedSender);
+    }
+
+    if !settings.only_senders.is_empty() {
+        let matched = settings.only_senders.iter().any(|pattern| {
+            let pattern = pattern.trim().to_lowercase();
+            if pattern.starts_with('@') {
+                sender.ends_with(&pattern)
+            } else {
+                sender == pattern
+            }
+        });
+        if !matched {
+            return Err(SkipReason::NotMatched);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+//! Pictures for senders, from three sources tried in order: the logo the
+//! sender's domain publishes through BIMI, the domain's favicon, and
+//! Gravatar. Each is off until the user turns it on, since every lookup
+//! tells someone outside who writes to them: BIMI and favicons reveal the
+//! sender's domain, Gravatar a hash of the address. Results, including
+//! finding nothing, are cached on disk for a week.
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::fs;
+use tracing::warn;
+
+use crate::brand;
+
+/// App setting holding the [`AvatarSettings`] as JSON.
+pub const SETTING_KEY: &str = "avatars";
+/// Folder in the data directory holding cached lookups.
+pub const CACHE_DIR: &str = "avatars";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(8);
+const CACHE_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+const MAX_IMAGE_BYTES: usize = 256 * 1024;
+const GRAVATAR_URL: &str = "https://gravatar.com/avatar";
+const GRAVATAR_SIZE: u32 = 96;
+/// Hex digits of the hash used in cache file names.
+const CACHE_NAME_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AvatarSettings {
+    pub bimi: bool,
+    pub favicon: bool,
+    pub gravatar: bool,
+}
+
+impl AvatarSettings {
+    pub fn any_enabled(&self) -> bool {
+        self.bimi || self.favicon || self.gravatar
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AvatarSource {
+    Bimi,
+    Favicon,
+    Gravatar,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SenderAvatar {
+    pub source: AvatarSource,
+    /// The image as a `data:` URI, ready for an `<img>`.
+    pub data_uri: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    fetched_at: i64,
+    avatar: Option<SenderAvatar>,
+}
+
+pub struct AvatarResolver {
+    client: reqwest::Client,
+    cache_dir: PathBuf,
+    settings: AvatarSettings,
+}
+
+impl AvatarResolver {
+    pub fn new(cache_dir: PathBuf, settings: AvatarSettings) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .map_err(|err| err.to_string())?;
+        Ok(Self {
+            client,
+            cache_dir,
+            settings,
+        })
+    }
+
+    /// The picture for `sender`, from the cache when it is fresh enough.
+    /// `None` when every enabled source came up empty.
+    pub async fn resolve(&self, sender: &str) -> Option<SenderAvatar> {
+        let sender = sender.trim().to_lowercase();
+        let (_, domain) = sender.rsplit_once('@')?;
+        if !self.settings.any_enabled() || !domain.contains('.') {
+            return None;
+        }
+        let path = self.cache_path(&sender);
+        if let Some(entry) = read_cache(&path).await {
+            if Utc::now().timestamp() - entry.fetched_at < CACHE_TTL_SECS {
+                return entry.avatar;
+            }
+        }
+
+        let avatar = self.fetch(&sender, domain).await;
+        let entry = CacheEntry {
+            fetched_at: Utc::now().timestamp(),
+            avatar,
+        };
+        if let Err(err) = write_cache(&path, &entry).await {
+            warn!(?err, "failed to cache sender avatar");
+        }
+        entry.avatar
+    }
+
+    async fn fetch(&self, sender: &str, domain: &str) -> Option<SenderAvatar> {
+        if self.settings.bimi {
+            if let Some(url) = brand::bimi_logo_url(&self.client, domain).await {
+                if let Some(avatar) = self.image(&url, AvatarSource::Bimi).await {
+                    return Some(avatar);
+                }
+            }
+        }
+        if self.settings.favicon {
+            let url = format!("https://{domain}/favicon.ico");
+            if let Some(avatar) = self.image(&url, AvatarSource::Favicon).await {
+                return Some(avatar);
+            }
+        }
+        if self.settings.gravatar {
+            let hash = hex::encode(Sha256::digest(sender.as_bytes()));
+            let url = format!("{GRAVATAR_URL}/{hash}?d=404&s={GRAVATAR_SIZE}");
+            return self.image(&url, AvatarSource::Gravatar).await;
+        }
+        None
+    }
+
+    /// Downloads an image, refusing anything that is not one or is too
+    /// large to inline.
+    async fn image(&self, url: &str, source: AvatarSource) -> Option<SenderAvatar> {
+        let response = self.client.get(url).send().await.ok()?;
+        if !response.status().is_success()
+            || response
+                .content_length()
+                .is_some_and(|len| len > MAX_IMAGE_BYTES as u64)
+        {
+            return None;
+        }
+        let content_type = response
+            .headers()
+            .get("Content-Type")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_lowercase())
+            .filter(|value| value.starts_with("image/"))?;
+        let bytes = response.bytes().await.ok()?;
+        if bytes.is_empty() || bytes.len() > MAX_IMAGE_BYTES {
+            return None;
+        }
+        Some(SenderAvatar {
+            source,
+            data_uri: format!(
+                "data:{content_type};base64,{}",
+                general_purpose::STANDARD.encode(&bytes)
+            ),
+        })
+    }
+
+    /// Lookups made with other sources enabled are cached apart, so
+    /// turning a source off never shows what it found.
+    fn cache_path(&self, sender: &str) -> PathBuf {
+        let key = format!("{sender}\n{:?}", self.settings);
+        let digest = hex::encode(Sha256::digest(key.as_bytes()));
+        self.cache_dir
+            .join(format!("{}.json", &digest[..CACHE_NAME_LEN]))
+    }
+}
+
+async fn read_cache(path: &Path) -> Option<CacheEntry> {
+    let raw = fs::read(path).await.ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+async fn write_cache(path: &Path, entry: &CacheEntry) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    let raw = serde_json::to_vec(entry).map_err(|err| err.to_string())?;
+    fs::write(path, raw).await.map_err(|err| err.to_string())
+}
@@ -0,0 +1,156 @@
+//! Reading and writing sender blocklists. Entries are either full addresses
+//! or whole domains; domains are kept as `@example.com`, the form the
+//! sender rules (and IMAP `FROM` searches) match on.
+//!
+//! Supported inputs: plain lists with one address or domain per line,
+//! hosts files (`0.0.0.0 example.com`), and Mozilla/Disconnect-style JSON
+//! lists such as the ones behind Firefox tracking protection, where domains
+//! sit in nested arrays under `categories`.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlocklistFormat {
+    Plain,
+    Hosts,
+    Mozilla,
+}
+
+impl BlocklistFormat {
+    /// Guesses the format from the content.
+    pub fn detect(text: &str) -> Self {
+        let trimmed = text.trim_start();
+        if trimmed.starts_with('{') || trimmed.starts_with('[') {
+            return BlocklistFormat::Mozilla;
+        }
+        let hosts_like = content_lines(text).take(20).any(|line| {
+            line.split_whitespace()
+                .next()
+                .is_some_and(|first| first.parse::<std::net::IpAddr>().is_ok())
+        });
+        if hosts_like {
+            BlocklistFormat::Hosts
+        } else {
+            BlocklistFormat::Plain
+        }
+    }
+}
+
+fn content_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('!'))
+}
+
+/// Normalized, de-duplicated entries. Lines that are neither an address nor
+/// a domain are skipped.
+pub fn parse(text: &str, format: BlocklistFormat) -> Result<Vec<String>, String> {
+    let mut entries = BTreeSet::new();
+    match format {
+        BlocklistFormat::Plain => {
+            for line in content_lines(text) {
+                if let Some(entry) = normalize_entry(line) {
+                    entries.insert(entry);
+                }
+            }
+        }
+        BlocklistFormat::Hosts => {
+            for line in content_lines(text) {
+                let mut fields = line.split_whitespace();
+                let first = fields.next().unwrap_or_default();
+                // Hosts files list the address first; bare names are tolerated.
+                let hosts: Vec<&str> = if first.parse::<std::net::IpAddr>().is_ok() {
+                    fields.collect()
+                } else {
+                    std::iter::once(first).chain(fields).collect()
+                };
+                for host in hosts {
+                    if matches!(
+                        host,
+                        "localhost" | "localhost.localdomain" | "broadcasthost"
+                    ) {
+                        continue;
+                    }
+                    if let Some(entry) = normalize_domain(host) {
+                        entries.insert(entry);
+                    }
+                }
+            }
+        }
+        BlocklistFormat::Mozilla => {
+            let json: Value = serde_json::from_str(text)
+                .map_err(|err| format!("Invalid blocklist JSON: {err}"))?;
+            let root = json.get("categories").unwrap_or(&json);
+            collect_json_domains(root, &mut entries);
+        }
+    }
+    Ok(entries.into_iter().collect())
+}
+
+fn collect_json_domains(value: &Value, entries: &mut BTreeSet<String>) {
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                match item {
+                    Value::String(text) => {
+                        if let Some(entry) = normalize_entry(text) {
+                            entries.insert(entry);
+                        }
+                    }
+                    other => collect_json_domains(other, entries),
+                }
+            }
+        }
+        // Object keys are category and organization names, not domains.
+        Value::Object(map) => {
+            for child in map.values() {
+                collect_json_domains(child, entries);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `user@example.com` stays an address; `example.com`, `@example.com`, and
+/// `*.example.com` become `@example.com`.
+pub fn normalize_entry(raw: &str) -> Option<String> {
+    let value = raw
+        .trim()
+        .trim_matches(|c| c == '<' || c == '>')
+        .to_lowercase();
+    match value.split_once('@') {
+        Some((local, domain)) if !local.is_empty() => {
+            let domain = normalize_domain(domain)?;
+            Some(format!("{local}{domain}"))
+        }
+        Some((_, domain)) => normalize_domain(domain),
+        None => normalize_domain(&value),
+    }
+}
+
+fn normalize_domain(raw: &str) -> Option<String> {
+    let domain = raw
+        .trim()
+        .trim_start_matches("*.")
+        .trim_matches('.')
+        .to_lowercase();
+    let valid = domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    valid.then(|| format!("@{domain}"))
+}
+
+/// A plain list that [`parse`] reads back: domains without the `@`,
+/// addresses as they are.
+pub fn to_plain(entries: &[String]) -> String {
+    let mut output = String::from("# PersonalMailClient sender blocklist\n");
+    for entry in entries {
+        output.push_str(entry.strip_prefix('@').unwrap_or(entry));
+        output.push('\n');
+    }
+    output
+}
@@ -0,0 +1,216 @@
+//! Readable text from cached message bodies. Sync stores the raw
+//! `BODY[TEXT]` section (the first few KB, still MIME-encoded), so the
+//! boundary has to be recovered from the body itself before its parts can
+//! be decoded.
+
+use mailparse::{parse_mail, MailHeaderMap, ParsedMail};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use secrecy::zeroize::{Zeroize, Zeroizing};
+
+static HTML_HIDDEN: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(script|style|head)\b.*?</(script|style|head)>")
+        .expect("hidden html pattern is valid")
+});
+static HTML_BREAK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)<(br|/p|/div|/tr|/li|/h[1-6])\b[^>]*>").expect("html break pattern is valid")
+});
+static HTML_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)<[^>]*>").expect("html tag pattern is valid"));
+static REPLY_HEADER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^(on\s.{4,200}\swrote:|-{2,}\s*original message\s*-{2,}|from:\s.+\ssent:\s)")
+        .expect("reply header pattern is valid")
+});
+
+/// Decoded text parts of a cached body, split by content type. Zeroed
+/// when dropped, like the decrypted body they came from.
+#[derive(Debug, Default)]
+pub struct BodyParts {
+    pub plain: Vec<String>,
+    pub html: Vec<String>,
+}
+
+impl Drop for BodyParts {
+    fn drop(&mut self) {
+        self.plain.zeroize();
+        self.html.zeroize();
+    }
+}
+
+pub fn decode_body_parts(raw: &[u8]) -> BodyParts {
+    let text = String::from_utf8_lossy(raw);
+    let mut parts = BodyParts::default();
+    let Some(boundary) = leading_boundary(&text) else {
+        // Single-part bodies arrive without their headers, so the transfer
+        // encoding has to be guessed.
+        let text = if text.contains("=3D") || text.contains("=\r\n") {
+            String::from_utf8_lossy(&decode_quoted_printable(text.as_bytes())).into_owned()
+        } else {
+            text.into_owned()
+        };
+        if looks_like_html(&text) {
+            parts.html.push(text);
+        } else {
+            parts.plain.push(text.trim().to_string());
+        }
+        return parts;
+    };
+
+    let delimiter = format!("--{boundary}");
+    for section in text.split(delimiter.as_str()).skip(1) {
+        if section.starts_with("--") {
+            break;
+        }
+        let Ok(part) = parse_mail(section.trim_start_matches(['\r', '\n']).as_bytes()) else {
+            continue;
+        };
+        collect_text(&part, &mut parts.plain, &mut parts.html);
+    }
+    parts
+}
+
+/// A complete RFC 822 message, as fetched for printing or transfer.
+#[derive(Debug, Default)]
+pub struct FullMessage {
+    /// Display headers in print order, decoded; absent ones are skipped.
+    pub headers: Vec<(&'static str, String)>,
+    pub parts: BodyParts,
+}
+
+pub fn decode_full_message(raw: &[u8]) -> Option<FullMessage> {
+    let parsed = parse_mail(raw).ok()?;
+    let mut message = FullMessage::default();
+    for name in ["From", "To", "Cc", "Date", "Subject"] {
+        if let Some(value) = parsed.get_headers().get_first_value(name) {
+            message.headers.push((name, value));
+        }
+    }
+    collect_text(&parsed, &mut message.parts.plain, &mut message.parts.html);
+    Some(message)
+}
+
+/// Decodes a cached body to plain text, preferring `text/plain` parts and
+/// falling back to tag-stripped HTML.
+pub fn decode_body_text(raw: &[u8]) -> Zeroizing<String> {
+    parts_text(&decode_body_parts(raw))
+}
+
+/// The plain parts, or the HTML parts as text when there are none.
+pub fn parts_text(parts: &BodyParts) -> Zeroizing<String> {
+    Zeroizing::new(if !parts.plain.is_empty() {
+        parts.plain.join("\n\n")
+    } else if !parts.html.is_empty() {
+        let html = Zeroizing::new(parts.html.join("\n"));
+        html_to_text(&html)
+    } else {
+        String::new()
+    })
+}
+
+fn decode_quoted_printable(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut index = 0;
+    while index < input.len() {
+        if input[index] != b'=' {
+            output.push(input[index]);
+            index += 1;
+            continue;
+        }
+        let rest = &input[index + 1..];
+        if rest.starts_with(b"\r\n") {
+            index += 3;
+        } else if rest.starts_with(b"\n") {
+            index += 2;
+        } else if let Some(byte) = rest
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            output.push(byte);
+            index += 3;
+        } else {
+            output.push(b'=');
+            index += 1;
+        }
+    }
+    output
+}
+
+fn leading_boundary(text: &str) -> Option<&str> {
+    let first = text.lines().find(|line| !line.trim().is_empty())?.trim();
+    let boundary = first.strip_prefix("--")?;
+    (!boundary.is_empty() && !boundary.contains(' ')).then_some(boundary)
+}
+
+fn collect_text(part: &ParsedMail, plain: &mut Vec<String>, html: &mut Vec<String>) {
+    if !part.subparts.is_empty() {
+        for subpart in &part.subparts {
+            collect_text(subpart, plain, html);
+        }
+        return;
+    }
+    let is_attachment = part
+        .get_headers()
+        .get_first_value("Content-Disposition")
+        .is_some_and(|value| value.to_lowercase().starts_with("attachment"));
+    if is_attachment {
+        return;
+    }
+    let Ok(body) = part.get_body() else {
+        return;
+    };
+    match part.ctype.mimetype.as_str() {
+        "text/plain" => plain.push(body.trim().to_string()),
+        "text/html" => html.push(body),
+        _ => {}
+    }
+}
+
+fn looks_like_html(text: &str) -> bool {
+    let lowered = text.to_lowercase();
+    ["<html", "<body", "<div", "<p>", "<table", "<br"]
+        .iter()
+        .any(|marker| lowered.contains(marker))
+}
+
+pub fn html_to_text(html: &str) -> String {
+    let visible = HTML_HIDDEN.replace_all(html, " ");
+    let broken = HTML_BREAK.replace_all(&visible, "\n");
+    let stripped = HTML_TAG.replace_all(&broken, " ");
+    let decoded = stripped
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'");
+    decoded
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Drops quoted replies, forwarded history, and the signature so only the
+/// sender's new text remains.
+pub fn strip_quotes_and_signature(text: &str) -> String {
+    let mut kept = Vec::new();
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed == "--" || REPLY_HEADER.is_match(trimmed) {
+            break;
+        }
+        if trimmed.starts_with('>') {
+            continue;
+        }
+        if trimmed.starts_with("Sent from my ") && kept.len() > 1 {
+            break;
+        }
+        kept.push(line.trim_end());
+    }
+    while kept.last().is_some_and(|line| line.is_empty()) {
+        kept.pop();
+    }
+    kept.join("\n").trim().to_string()
+}
@@ -0,0 +1,165 @@
+//! Delivery failure reports (RFC 3464 DSNs). A bounce arrives as a
+//! `multipart/report; report-type=delivery-status` message with one block
+//! of fields per recipient and, usually, the headers of the message that
+//! failed. Sync only fetches envelopes, so likely bounces are picked by
+//! sender and subject first and their full source is fetched afterwards.
+
+use crate::models::EmailSummary;
+use mailparse::{parse_headers, parse_mail, MailHeaderMap, ParsedMail};
+
+const BOUNCE_LOCAL_PARTS: &[&str] = &["mailer-daemon", "postmaster"];
+
+const BOUNCE_SUBJECTS: &[&str] = &[
+    "undeliverable",
+    "undelivered mail",
+    "delivery status notification",
+    "delivery failure",
+    "delivery has failed",
+    "mail delivery failed",
+    "failure notice",
+    "returned mail",
+    "returned to sender",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedRecipient {
+    pub email: String,
+    /// Enhanced status code, e.g. `5.1.1`.
+    pub status: Option<String>,
+    /// The remote server's reply, without the `smtp;` type prefix.
+    pub diagnostic: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryReport {
+    pub failed: Vec<FailedRecipient>,
+    /// Headers of the message that failed, when the report includes them.
+    pub original_message_id: Option<String>,
+    pub original_subject: Option<String>,
+}
+
+/// Whether a synced message looks like a bounce worth fetching in full.
+pub fn is_candidate(summary: &EmailSummary) -> bool {
+    let sender = summary.sender.email.trim().to_lowercase();
+    let local = sender.split('@').next().unwrap_or_default();
+    if BOUNCE_LOCAL_PARTS.contains(&local) {
+        return true;
+    }
+    let subject = summary.subject.to_lowercase();
+    BOUNCE_SUBJECTS
+        .iter()
+        .any(|pattern| subject.contains(pattern))
+}
+
+/// Reads a delivery report from a message's full source. `None` when the
+/// message is not a DSN or names no failed recipient.
+pub fn parse(raw: &[u8]) -> Option<DeliveryReport> {
+    let parsed = parse_mail(raw).ok()?;
+    let mut report = DeliveryReport::default();
+
+    let is_report = parsed
+        .ctype
+        .mimetype
+        .eq_ignore_ascii_case("multipart/report")
+        && parsed
+            .ctype
+            .params
+            .get("report-type")
+            .is_some_and(|value| value.eq_ignore_ascii_case("delivery-status"));
+    if is_report {
+        for part in &parsed.subparts {
+            match part.ctype.mimetype.to_ascii_lowercase().as_str() {
+                "message/delivery-status" => {
+                    if let Ok(body) = part.get_body_raw() {
+                        report.failed.extend(failed_recipients(&body));
+                    }
+                }
+                "text/rfc822-headers" | "message/rfc822" => read_original(part, &mut report),
+                _ => {}
+            }
+        }
+    }
+
+    // Exim and some hosted services skip the report and name the failures
+    // in a header instead.
+    if report.failed.is_empty() {
+        if let Some(value) = parsed.get_headers().get_first_value("X-Failed-Recipients") {
+            report.failed = value
+                .split(',')
+                .map(|address| address.trim().to_lowercase())
+                .filter(|address| address.contains('@'))
+                .map(|email| FailedRecipient {
+                    email,
+                    status: None,
+                    diagnostic: None,
+                })
+                .collect();
+        }
+    }
+
+    (!report.failed.is_empty()).then_some(report)
+}
+
+/// The recipients whose `Action` is `failed`, from the body of a
+/// `message/delivery-status` part: a block of per-message fields followed by
+/// one block per recipient, separated by blank lines.
+fn failed_recipients(body: &[u8]) -> Vec<FailedRecipient> {
+    let text = String::from_utf8_lossy(body).replace("\r\n", "\n");
+    text.split("\n\n")
+        .filter_map(|block| {
+            let (headers, _) = parse_headers(block.trim().as_bytes()).ok()?;
+            let action = headers.get_first_value("Action")?;
+            if !action.trim().eq_ignore_ascii_case("failed") {
+                return None;
+            }
+            let recipient = headers
+                .get_first_value("Final-Recipient")
+                .or_else(|| headers.get_first_value("Original-Recipient"))?;
+            let email = strip_type(&recipient)
+                .trim_matches(['<', '>'])
+                .to_lowercase();
+            if !email.contains('@') {
+                return None;
+            }
+            Some(FailedRecipient {
+                email,
+                status: headers
+                    .get_first_value("Status")
+                    .map(|value| value.trim().to_string()),
+                diagnostic: headers
+                    .get_first_value("Diagnostic-Code")
+                    .map(|value| strip_type(&value).to_string()),
+            })
+        })
+        .collect()
+}
+
+fn read_original(part: &ParsedMail<'_>, report: &mut DeliveryReport) {
+    let Ok(raw) = part.get_body_raw() else {
+        return;
+    };
+    let Ok((headers, _)) = parse_headers(&raw) else {
+        return;
+    };
+    report.original_message_id = headers
+        .get_first_value("Message-ID")
+        .map(|value| normalize_message_id(&value))
+        .filter(|value| !value.is_empty());
+    report.original_subject = headers
+        .get_first_value("Subject")
+        .map(|value| value.trim().to_string());
+}
+
+/// Drops the address or diagnostic type, as in `rfc822; user@example.com`.
+fn strip_type(value: &str) -> &str {
+    value.split_once(';').map_or(value, |(_, rest)| rest).trim()
+}
+
+/// A Message-ID as stored for outgoing mail: lowercase, without brackets.
+pub fn normalize_message_id(value: &str) -> String {
+    value
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_lowercase()
+}
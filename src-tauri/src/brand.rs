@@ -0,0 +1,267 @@
+//! Whether mail really comes from the brand it names. A domain vouches for
+//! its mail with a DMARC policy at enforcement and shows a logo through
+//! BIMI; a message counts as the domain's when the receiving server found
+//! it DMARC-aligned. Only all three together mark a sender as verified,
+//! which a lookalike domain cannot fake without owning the real one.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::dns;
+
+/// How long a domain's DNS records are trusted before they are read again.
+pub const CHECK_TTL_SECS: i64 = 24 * 60 * 60;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DmarcPolicy {
+    None,
+    Quarantine,
+    Reject,
+}
+
+impl DmarcPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DmarcPolicy::None => "none",
+            DmarcPolicy::Quarantine => "quarantine",
+            DmarcPolicy::Reject => "reject",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => Some(DmarcPolicy::None),
+            "quarantine" => Some(DmarcPolicy::Quarantine),
+            "reject" => Some(DmarcPolicy::Reject),
+            _ => None,
+        }
+    }
+
+    /// BIMI only honours domains that act on failing mail.
+    pub fn enforced(self) -> bool {
+        !matches!(self, DmarcPolicy::None)
+    }
+}
+
+/// What a domain publishes in DNS.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DomainBrand {
+    pub domain: String,
+    pub dmarc_policy: Option<DmarcPolicy>,
+    pub bimi_logo: Option<String>,
+    /// The BIMI `a=` evidence document (a Verified Mark Certificate).
+    pub bimi_authority: Option<String>,
+}
+
+impl DomainBrand {
+    /// Whether mail aligned with this domain earns the verified badge.
+    pub fn verifiable(&self) -> bool {
+        self.dmarc_policy.is_some_and(DmarcPolicy::enforced) && self.bimi_logo.is_some()
+    }
+}
+
+/// Reads a domain's DMARC and BIMI records, falling back to its
+/// organizational domain for each as the specifications do. Fails only
+/// when DNS cannot be reached, so a failure is never taken for a domain
+/// without records.
+pub async fn check_domain(client: &reqwest::Client, domain: &str) -> Result<DomainBrand, String> {
+    let candidates = lookup_domains(domain);
+    let mut dmarc_policy = None;
+    for candidate in &candidates {
+        let records = dns::txt_records(client, &format!("_dmarc.{candidate}")).await?;
+        dmarc_policy = records.iter().find_map(|record| dmarc_record(record));
+        if dmarc_policy.is_some() {
+            break;
+        }
+    }
+    let mut bimi = None;
+    for candidate in &candidates {
+        let records = dns::txt_records(client, &format!("default._bimi.{candidate}")).await?;
+        bimi = records.iter().find_map(|record| bimi_record(record));
+        if bimi.is_some() {
+            break;
+        }
+    }
+    let (bimi_logo, bimi_authority) = bimi.unzip();
+    Ok(DomainBrand {
+        domain: domain.to_string(),
+        dmarc_policy,
+        bimi_logo,
+        bimi_authority: bimi_authority.flatten(),
+    })
+}
+
+/// A client for [`check_domain`].
+pub fn client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+/// The logo URL a domain publishes in its BIMI record.
+pub async fn bimi_logo_url(client: &reqwest::Client, domain: &str) -> Option<String> {
+    for candidate in lookup_domains(domain) {
+        let records = dns::txt_records(client, &format!("default._bimi.{candidate}"))
+            .await
+            .ok()?;
+        if let Some((logo, _)) = records.iter().find_map(|record| bimi_record(record)) {
+            return Some(logo);
+        }
+    }
+    None
+}
+
+/// The last two labels of `domain`. Close enough to the public-suffix
+/// answer for the domains brands send from.
+pub fn organizational_domain(domain: &str) -> String {
+    let labels = domain.trim_end_matches('.').split('.').collect::<Vec<_>>();
+    labels[labels.len().saturating_sub(2)..].join(".")
+}
+
+/// Whether the receiving server found a message DMARC-aligned with
+/// `from_domain`, read from the topmost `Authentication-Results` header.
+/// Without a `dmarc=` result, a passing DKIM signature or SPF check for the
+/// same organizational domain counts. `None` when there is no header.
+pub fn header_alignment(raw_headers: &str, from_domain: &str) -> Option<bool> {
+    let unfolded = raw_headers.replace("\r\n ", " ").replace("\r\n\t", " ");
+    let results = unfolded.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("authentication-results")
+            .then(|| value.to_string())
+    })?;
+    let from_org = organizational_domain(&from_domain.to_lowercase());
+    let aligned = |domain: &str| {
+        let domain = domain.trim().trim_start_matches('@').to_lowercase();
+        let domain = domain.rsplit('@').next().unwrap_or_default();
+        !domain.is_empty() && organizational_domain(domain) == from_org
+    };
+
+    let mut fallback = false;
+    // The first part names the server that checked; the rest are results.
+    for result in results.split(';').skip(1) {
+        let mut words = result.split_whitespace();
+        let Some((method, outcome)) = words.next().and_then(|word| word.split_once('=')) else {
+            continue;
+        };
+        let passed = outcome.eq_ignore_ascii_case("pass");
+        match method.to_ascii_lowercase().as_str() {
+            "dmarc" => return Some(passed),
+            "dkim" | "spf" if passed => {
+                fallback |= words.any(|word| {
+                    word.split_once('=').is_some_and(|(property, value)| {
+                        matches!(
+                            property.to_ascii_lowercase().as_str(),
+                            "header.d" | "header.i" | "smtp.mailfrom"
+                        ) && aligned(value)
+                    })
+                });
+            }
+            _ => {}
+        }
+    }
+    Some(fallback)
+}
+
+fn lookup_domains(domain: &str) -> Vec<String> {
+    let domain = domain.trim_end_matches('.').to_lowercase();
+    let organizational = organizational_domain(&domain);
+    if organizational == domain {
+        vec![domain]
+    } else {
+        vec![domain, organizational]
+    }
+}
+
+/// `name=value` tags of a DMARC or BIMI record, the version tag first.
+fn record_tags(record: &str) -> Vec<(String, &str)> {
+    record
+        .split(';')
+        .filter_map(|tag| {
+            let (name, value) = tag.split_once('=')?;
+            Some((name.trim().to_ascii_lowercase(), value.trim()))
+        })
+        .collect()
+}
+
+/// The `p=` policy of a `v=DMARC1` record. A partial `pct=` rollout
+/// enforces nothing for the rest of the mail, so it counts as `none`.
+fn dmarc_record(record: &str) -> Option<DmarcPolicy> {
+    let tags = record_tags(record);
+    let (version, value) = tags.first()?;
+    if version != "v" || !value.eq_ignore_ascii_case("DMARC1") {
+        return None;
+    }
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| *value)
+    };
+    let policy = DmarcPolicy::parse(tag("p")?)?;
+    let partial = tag("pct").is_some_and(|pct| pct.trim() != "100");
+    Some(if partial { DmarcPolicy::None } else { policy })
+}
+
+/// The https logo and evidence URLs of a `v=BIMI1` record.
+fn bimi_record(record: &str) -> Option<(String, Option<String>)> {
+    let tags = record_tags(record);
+    let (version, value) = tags.first()?;
+    if version != "v" || !value.eq_ignore_ascii_case("BIMI1") {
+        return None;
+    }
+    let https = |name: &str| {
+        tags.iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.to_string())
+            .filter(|url| url.to_ascii_lowercase().starts_with("https://"))
+    };
+    Some((https("l")?, https("a")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_dmarc_and_bimi_records() {
+        assert_eq!(
+            dmarc_record("v=DMARC1; p=reject; rua=mailto:d@example.com"),
+            Some(DmarcPolicy::Reject)
+        );
+        assert_eq!(
+            dmarc_record("v=DMARC1; p=quarantine; pct=20"),
+            Some(DmarcPolicy::None)
+        );
+        assert_eq!(dmarc_record("v=spf1 -all"), None);
+        assert_eq!(
+            bimi_record("v=BIMI1; l=https://example.com/logo.svg; a=https://example.com/vmc.pem"),
+            Some((
+                "https://example.com/logo.svg".to_string(),
+                Some("https://example.com/vmc.pem".to_string())
+            ))
+        );
+        assert_eq!(bimi_record("v=BIMI1; l=http://example.com/logo.svg"), None);
+    }
+
+    #[test]
+    fn alignment_comes_from_the_topmost_results() {
+        let headers = "Authentication-Results: mx.example.net;\r\n dkim=pass \
+                       header.i=@mail.shop.example header.s=s1;\r\n spf=pass \
+                       smtp.mailfrom=bounce@esp.example\r\n\
+                       Authentication-Results: forged.example; dmarc=pass\r\n";
+        assert_eq!(header_alignment(headers, "shop.example"), Some(true));
+        assert_eq!(header_alignment(headers, "paypal.example"), Some(false));
+
+        let dmarc = "Authentication-Results: mx.example.net; dkim=pass header.d=shop.example; \
+                     dmarc=fail header.from=shop.example\r\n";
+        assert_eq!(header_alignment(dmarc, "shop.example"), Some(false));
+        assert_eq!(
+            header_alignment("Precedence: bulk\r\n", "shop.example"),
+            None
+        );
+    }
+}
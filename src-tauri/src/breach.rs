@@ -0,0 +1,411 @@
+//! Security alerts from breach data. Sender domains are matched against a
+//! local copy of a breach list (the JSON Have I Been Pwned publishes at
+//! `/api/v3/breaches`), and bursts of password-reset mail are flagged,
+//! more urgently when they come from a breached site. Optionally, account
+//! addresses are checked against a k-anonymity range endpoint: only the
+//! first five hex digits of the address's SHA-256 leave the machine, and
+//! the match happens here.
+
+use crate::lookalike::Lookalike;
+use crate::models::uid_strings;
+use chrono::{DateTime, NaiveDate, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// App setting holding the [`BreachCheckSettings`] as JSON.
+pub const SETTING_KEY: &str = "breach_check";
+/// How far back messages are considered.
+pub const ALERT_WINDOW_DAYS: i64 = 30;
+
+/// Password resets within this span count as one burst.
+const FLOOD_WINDOW_SECS: i64 = 24 * 60 * 60;
+const FLOOD_MIN_MESSAGES: usize = 3;
+const RANGE_PREFIX_LEN: usize = 5;
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+static PASSWORD_RESET: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"(?i)\b(?:reset (?:your )?password|password (?:reset|change|recovery)",
+        r"|forgot (?:your )?password|change your password|recover your account)\b",
+    ))
+    .expect("password reset pattern is valid")
+});
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BreachCheckSettings {
+    /// Look account addresses up at `range_url`. Off by default, since
+    /// even a hash prefix tells the endpoint something.
+    pub check_accounts: bool,
+    /// Range endpoint queried as `{range_url}/{prefix}`. It answers with the
+    /// uppercase hex suffixes it knows for that prefix, one per line,
+    /// optionally followed by `:count`.
+    pub range_url: Option<String>,
+}
+
+impl BreachCheckSettings {
+    /// Trims the URL; rejects one that is not https, or checking accounts
+    /// without one.
+    pub fn normalized(mut self) -> Result<Self, String> {
+        self.range_url = self
+            .range_url
+            .map(|url| url.trim().trim_end_matches('/').to_string())
+            .filter(|url| !url.is_empty());
+        match &self.range_url {
+            Some(url) if !url.to_lowercase().starts_with("https://") => {
+                Err("The range endpoint must use https".into())
+            }
+            None if self.check_accounts => {
+                Err("Set a range endpoint to check account addresses".into())
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+/// A breached site.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Breach {
+    pub name: String,
+    pub domain: String,
+    #[serde(default)]
+    pub breach_date: Option<String>,
+    /// When the breach became known; alerts only cover mail after it.
+    #[serde(default)]
+    pub added_date: Option<String>,
+    #[serde(default)]
+    pub data_classes: Vec<String>,
+}
+
+impl Breach {
+    fn known_since(&self) -> Option<i64> {
+        let value = self.added_date.as_deref().or(self.breach_date.as_deref())?;
+        DateTime::parse_from_rfc3339(value)
+            .map(|date| date.timestamp())
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+                    .map(|date| date.and_utc().timestamp())
+            })
+    }
+}
+
+/// Breaches from a JSON list. Entries without a domain cannot be matched
+/// against senders and are dropped.
+pub fn parse_breaches(text: &str) -> Result<Vec<Breach>, String> {
+    let breaches: Vec<Breach> =
+        serde_json::from_str(text).map_err(|err| format!("Invalid breach data: {err}"))?;
+    Ok(breaches
+        .into_iter()
+        .filter_map(|mut breach| {
+            breach.domain = breach.domain.trim().trim_start_matches('.').to_lowercase();
+            (!breach.domain.is_empty()).then_some(breach)
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// Mail from a site that was breached.
+    BreachedSender,
+    /// Several password resets in a short time.
+    PasswordResetFlood,
+    /// The account address appears in breach data.
+    AccountInBreach,
+    /// Mail from a domain imitating a trusted or popular one.
+    LookalikeSender,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SecurityAlert {
+    pub kind: AlertKind,
+    pub severity: Severity,
+    pub account_email: String,
+    pub message: String,
+    /// Name of the breach behind the alert, if any.
+    pub breach: Option<String>,
+    #[serde(with = "uid_strings")]
+    pub uids: Vec<u32>,
+    /// When the newest message behind the alert arrived.
+    pub latest_at: Option<i64>,
+    /// The imitated domain, for lookalike senders.
+    pub lookalike: Option<Lookalike>,
+}
+
+/// A recent message, as much of it as the checks read.
+#[derive(Debug, Clone)]
+pub struct SecurityMessage {
+    pub uid: u32,
+    pub sender_email: String,
+    pub subject: String,
+    pub date_ts: Option<i64>,
+}
+
+pub fn is_password_reset(subject: &str) -> bool {
+    PASSWORD_RESET.is_match(subject)
+}
+
+/// Alerts for one account's recent messages. `breaches` is keyed by
+/// domain; subdomains of a breached domain match too.
+pub fn alerts(
+    account_email: &str,
+    messages: &[SecurityMessage],
+    breaches: &HashMap<String, Breach>,
+) -> Vec<SecurityAlert> {
+    let breach_for = |message: &SecurityMessage| {
+        let (_, domain) = message.sender_email.rsplit_once('@')?;
+        let breach = parent_domains(domain).find_map(|domain| breaches.get(domain))?;
+        let after_breach = match (breach.known_since(), message.date_ts) {
+            (Some(known), Some(date)) => date >= known,
+            _ => true,
+        };
+        after_breach.then_some(breach)
+    };
+
+    let mut alerts = Vec::new();
+    let mut by_breach: HashMap<&str, (&Breach, Vec<&SecurityMessage>)> = HashMap::new();
+    for message in messages {
+        if let Some(breach) = breach_for(message) {
+            by_breach
+                .entry(breach.name.as_str())
+                .or_insert_with(|| (breach, Vec::new()))
+                .1
+                .push(message);
+        }
+    }
+    for (breach, found) in by_breach.into_values() {
+        alerts.push(SecurityAlert {
+            kind: AlertKind::BreachedSender,
+            severity: Severity::Warning,
+            account_email: account_email.to_string(),
+            message: format!(
+                "{} message(s) from {}, which was breached{}",
+                found.len(),
+                breach.domain,
+                exposed(breach)
+            ),
+            breach: Some(breach.name.clone()),
+            uids: found.iter().map(|message| message.uid).collect(),
+            latest_at: found.iter().filter_map(|message| message.date_ts).max(),
+            lookalike: None,
+        });
+    }
+
+    let mut resets = messages
+        .iter()
+        .filter(|message| message.date_ts.is_some() && is_password_reset(&message.subject))
+        .collect::<Vec<_>>();
+    resets.sort_by_key(|message| message.date_ts);
+    if let Some(burst) = largest_burst(&resets) {
+        let breach = burst.iter().find_map(|message| breach_for(message));
+        let (severity, message) = match breach {
+            Some(breach) => (
+                Severity::Critical,
+                format!(
+                    "{} password resets within a day, including from breached {}",
+                    burst.len(),
+                    breach.domain
+                ),
+            ),
+            None => (
+                Severity::Warning,
+                format!("{} password resets within a day", burst.len()),
+            ),
+        };
+        alerts.push(SecurityAlert {
+            kind: AlertKind::PasswordResetFlood,
+            severity,
+            account_email: account_email.to_string(),
+            message,
+            breach: breach.map(|breach| breach.name.clone()),
+            uids: burst.iter().map(|message| message.uid).collect(),
+            latest_at: burst.last().and_then(|message| message.date_ts),
+            lookalike: None,
+        });
+    }
+
+    alerts.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then_with(|| b.latest_at.cmp(&a.latest_at))
+    });
+    alerts
+}
+
+/// The most password resets inside one flood window, if enough for a flood.
+fn largest_burst<'a>(resets: &[&'a SecurityMessage]) -> Option<Vec<&'a SecurityMessage>> {
+    let mut best: &[&SecurityMessage] = &[];
+    let mut start = 0;
+    for end in 0..resets.len() {
+        let end_ts = resets[end].date_ts.unwrap_or_default();
+        while resets[start].date_ts.unwrap_or_default() < end_ts - FLOOD_WINDOW_SECS {
+            start += 1;
+        }
+        if end + 1 - start > best.len() {
+            best = &resets[start..=end];
+        }
+    }
+    (best.len() >= FLOOD_MIN_MESSAGES).then(|| best.to_vec())
+}
+
+fn parent_domains(domain: &str) -> impl Iterator<Item = &str> {
+    let domain = domain.trim_end_matches('.');
+    std::iter::successors(Some(domain), |current| {
+        current
+            .split_once('.')
+            .map(|(_, parent)| parent)
+            .filter(|parent| parent.contains('.'))
+    })
+}
+
+fn exposed(breach: &Breach) -> String {
+    if breach.data_classes.is_empty() {
+        return String::new();
+    }
+    format!(
+        " ({} exposed)",
+        breach.data_classes.join(", ").to_lowercase()
+    )
+}
+
+/// The alert for an account address found by [`address_in_range`].
+pub fn account_alert(account_email: &str) -> SecurityAlert {
+    SecurityAlert {
+        kind: AlertKind::AccountInBreach,
+        severity: Severity::Critical,
+        account_email: account_email.to_string(),
+        message: format!("{account_email} appears in breach data; change its password"),
+        breach: None,
+        uids: Vec::new(),
+        latest_at: Some(Utc::now().timestamp()),
+        lookalike: None,
+    }
+}
+
+/// Whether the range endpoint knows `email`. Only a short prefix of the
+/// address's hash is sent.
+pub async fn address_in_range(range_url: &str, email: &str) -> Result<bool, String> {
+    let digest = hex::encode_upper(Sha256::digest(email.trim().to_lowercase().as_bytes()));
+    let (prefix, suffix) = digest.split_at(RANGE_PREFIX_LEN);
+    let client = reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .map_err(|err| err.to_string())?;
+    let body = client
+        .get(format!("{range_url}/{prefix}"))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| format!("breach range lookup failed: {err}"))?
+        .text()
+        .await
+        .map_err(|err| format!("breach range lookup failed: {err}"))?;
+    Ok(range_contains(&body, suffix))
+}
+
+fn range_contains(body: &str, suffix: &str) -> bool {
+    body.lines()
+        .filter_map(|line| line.split(':').next())
+        .any(|candidate| candidate.trim().eq_ignore_ascii_case(suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(uid: u32, sender: &str, subject: &str, date_ts: i64) -> SecurityMessage {
+        SecurityMessage {
+            uid,
+            sender_email: sender.into(),
+            subject: subject.into(),
+            date_ts: Some(date_ts),
+        }
+    }
+
+    fn breaches() -> HashMap<String, Breach> {
+        let breaches = parse_breaches(
+            r#"[{"Name":"Shop","Domain":"Shop.example","AddedDate":"2024-05-01T00:00:00Z",
+                 "DataClasses":["Email addresses","Passwords"]},
+                {"Name":"NoDomain","Domain":""}]"#,
+        )
+        .unwrap();
+        assert_eq!(breaches.len(), 1);
+        breaches
+            .into_iter()
+            .map(|breach| (breach.domain.clone(), breach))
+            .collect()
+    }
+
+    #[test]
+    fn flags_mail_from_breached_domains_after_the_breach() {
+        let added = 1_714_521_600;
+        let messages = [
+            message(1, "news@mail.shop.example", "Weekly deals", added + 60),
+            message(2, "news@shop.example", "Old newsletter", added - 60),
+            message(3, "friend@other.example", "Lunch?", added + 60),
+        ];
+        let found = alerts("me@example.com", &messages, &breaches());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, AlertKind::BreachedSender);
+        assert_eq!(found[0].uids, vec![1]);
+        assert!(found[0].message.contains("passwords exposed"));
+    }
+
+    #[test]
+    fn password_reset_bursts_are_critical_after_a_breach() {
+        let start = 1_714_521_600 + 3600;
+        let messages = [
+            message(1, "no-reply@shop.example", "Reset your password", start),
+            message(
+                2,
+                "security@bank.example",
+                "Password reset requested",
+                start + 600,
+            ),
+            message(
+                3,
+                "help@mail.example",
+                "Forgot your password?",
+                start + 1200,
+            ),
+            message(
+                4,
+                "help@mail.example",
+                "Forgot your password?",
+                start + 5 * 86_400,
+            ),
+        ];
+        let found = alerts("me@example.com", &messages, &breaches());
+        let flood = found
+            .iter()
+            .find(|alert| alert.kind == AlertKind::PasswordResetFlood)
+            .unwrap();
+        assert_eq!(flood.severity, Severity::Critical);
+        assert_eq!(flood.uids, vec![1, 2, 3]);
+        assert_eq!(found[0].kind, AlertKind::PasswordResetFlood);
+
+        let quiet = alerts("me@example.com", &messages[1..], &HashMap::new());
+        assert!(quiet.is_empty());
+    }
+
+    #[test]
+    fn range_match_ignores_counts_and_case() {
+        assert!(range_contains("0A1B:3\r\nabc123:1\n", "ABC123"));
+        assert!(!range_contains("0A1B:3\n", "ABC123"));
+    }
+}
@@ -0,0 +1,290 @@
+//! Pushes events read from invitations to a CalDAV calendar the user
+//! configures, so they show up in their real calendar. Each event is stored
+//! at a resource named after its `UID`, so pushing it again updates it in
+//! place; a server that already holds the `UID` elsewhere (because the user
+//! accepted the invitation in another client) is left alone. The password
+//! lives in the OS keychain.
+
+use chrono::{Local, TimeZone, Utc};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+use crate::calendar::{escape, fold, CalendarEvent, EventStatus};
+
+/// App setting holding the [`CalDavSettings`] as JSON.
+pub const SETTING_KEY: &str = "caldav";
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(20);
+const PRODUCT_ID: &str = "-//PersonalMailClient//Calendar export//EN";
+/// Hex digits of the UID hash used in resource names.
+const RESOURCE_NAME_LEN: usize = 32;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CalDavSettings {
+    pub enabled: bool,
+    /// The calendar collection, e.g.
+    /// `https://caldav.example.com/calendars/me/personal/`.
+    pub calendar_url: Option<String>,
+    pub username: Option<String>,
+}
+
+impl CalDavSettings {
+    /// Trims the fields and ends the URL with a slash; rejects a URL that is
+    /// not https, or enabling without a URL and username.
+    pub fn normalized(mut self) -> Result<Self, String> {
+        self.calendar_url = self
+            .calendar_url
+            .map(|url| format!("{}/", url.trim().trim_end_matches('/')))
+            .filter(|url| url != "/");
+        self.username = self
+            .username
+            .map(|username| username.trim().to_string())
+            .filter(|username| !username.is_empty());
+        if let Some(url) = &self.calendar_url {
+            if !url.to_lowercase().starts_with("https://") {
+                return Err("The calendar URL must use https".into());
+            }
+        }
+        if self.enabled && (self.calendar_url.is_none() || self.username.is_none()) {
+            return Err("Set the calendar URL and username to export events".into());
+        }
+        Ok(self)
+    }
+}
+
+/// Keychain entry holding the password for `username`.
+pub fn keychain_key(username: &str) -> String {
+    format!("caldav:{username}")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PushStatus {
+    Pushed,
+    /// The calendar already holds an event with this `UID`.
+    AlreadyPresent,
+    Failed,
+}
+
+impl PushStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PushStatus::Pushed => "pushed",
+            PushStatus::AlreadyPresent => "already_present",
+            PushStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pushed" => Some(PushStatus::Pushed),
+            "already_present" => Some(PushStatus::AlreadyPresent),
+            "failed" => Some(PushStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// Result of one push, as recorded in the sync log.
+#[derive(Debug, Clone)]
+pub struct PushOutcome {
+    pub status: PushStatus,
+    pub etag: Option<String>,
+    pub detail: Option<String>,
+}
+
+/// Where `event_uid` lives in the calendar. UIDs may hold characters that
+/// are awkward in a URL, so the name is a hash of it.
+pub fn resource_url(calendar_url: &str, event_uid: &str) -> String {
+    let digest = hex::encode(Sha256::digest(event_uid.as_bytes()));
+    format!("{calendar_url}{}.ics", &digest[..RESOURCE_NAME_LEN])
+}
+
+/// A one-event iCalendar document for `event`.
+pub fn to_ics(event: &CalendarEvent) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{PRODUCT_ID}"),
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}", escape(&event.event_uid)),
+        format!("SEQUENCE:{}", event.sequence),
+        format!("DTSTAMP:{}", utc_stamp(Utc::now().timestamp())),
+    ];
+    if event.all_day {
+        lines.push(format!(
+            "DTSTART;VALUE=DATE:{}",
+            local_date(event.starts_at)
+        ));
+        lines.push(format!("DTEND;VALUE=DATE:{}", local_date(event.ends_at)));
+    } else {
+        lines.push(format!("DTSTART:{}", utc_stamp(event.starts_at)));
+        lines.push(format!("DTEND:{}", utc_stamp(event.ends_at)));
+    }
+    lines.push(format!("SUMMARY:{}", escape(&event.summary)));
+    if let Some(location) = &event.location {
+        lines.push(format!("LOCATION:{}", escape(location)));
+    }
+    let status = match event.status {
+        EventStatus::Confirmed => "CONFIRMED",
+        EventStatus::Tentative => "TENTATIVE",
+        EventStatus::Cancelled => "CANCELLED",
+    };
+    lines.push(format!("STATUS:{status}"));
+    if !event.busy {
+        lines.push("TRANSP:TRANSPARENT".to_string());
+    }
+    lines.push("END:VEVENT".to_string());
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold(line))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+        + "\r\n"
+}
+
+/// Writes `event` to its resource in the calendar.
+pub async fn put_event(
+    client: &reqwest::Client,
+    settings: &CalDavSettings,
+    password: &SecretString,
+    event: &CalendarEvent,
+) -> PushOutcome {
+    let (Some(calendar_url), Some(username)) = (&settings.calendar_url, &settings.username) else {
+        return failed("CalDAV export is not configured".into());
+    };
+    let response = client
+        .put(resource_url(calendar_url, &event.event_uid))
+        .basic_auth(username, Some(password.expose_secret()))
+        .header("Content-Type", "text/calendar; charset=utf-8")
+        .body(to_ics(event))
+        .send()
+        .await;
+    let response = match response {
+        Ok(response) => response,
+        Err(err) => return failed(format!("CalDAV request failed: {err}")),
+    };
+    let status = response.status();
+    let etag = response
+        .headers()
+        .get("ETag")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    if status.is_success() {
+        return PushOutcome {
+            status: PushStatus::Pushed,
+            etag,
+            detail: None,
+        };
+    }
+    let body = response.text().await.unwrap_or_default();
+    if body.contains("no-uid-conflict") {
+        return PushOutcome {
+            status: PushStatus::AlreadyPresent,
+            etag: None,
+            detail: None,
+        };
+    }
+    failed(format!("CalDAV server answered {status}"))
+}
+
+/// A client for [`put_event`].
+pub fn client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+fn failed(detail: String) -> PushOutcome {
+    PushOutcome {
+        status: PushStatus::Failed,
+        etag: None,
+        detail: Some(detail),
+    }
+}
+
+fn utc_stamp(timestamp: i64) -> String {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .unwrap_or_default()
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+fn local_date(timestamp: i64) -> String {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|date| date.format("%Y%m%d").to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::{parse_ics, FOLD_AT};
+
+    #[test]
+    fn exported_events_read_back_the_same() {
+        let event = CalendarEvent {
+            event_uid: "abc@example.com".into(),
+            sequence: 3,
+            summary: format!("Review; budget, plans\n{}", "é".repeat(60)),
+            location: Some("Room 4".into()),
+            starts_at: Utc
+                .with_ymd_and_hms(2026, 10, 22, 13, 0, 0)
+                .unwrap()
+                .timestamp(),
+            ends_at: Utc
+                .with_ymd_and_hms(2026, 10, 22, 14, 0, 0)
+                .unwrap()
+                .timestamp(),
+            all_day: false,
+            status: EventStatus::Confirmed,
+            busy: true,
+        };
+        let ics = to_ics(&event);
+        assert!(ics.lines().all(|line| line.trim_end().len() <= FOLD_AT));
+        assert_eq!(parse_ics(&ics, "me@example.com"), vec![event]);
+    }
+
+    #[test]
+    fn settings_need_an_https_calendar() {
+        let settings = CalDavSettings {
+            enabled: true,
+            calendar_url: Some(" https://dav.example.com/cal/me ".into()),
+            username: Some("me".into()),
+        };
+        assert_eq!(
+            settings.normalized().unwrap().calendar_url.as_deref(),
+            Some("https://dav.example.com/cal/me/")
+        );
+        let plain = CalDavSettings {
+            calendar_url: Some("http://dav.example.com/".into()),
+            ..CalDavSettings::default()
+        };
+        assert!(plain.normalized().is_err());
+        let incomplete = CalDavSettings {
+            enabled: true,
+            ..CalDavSettings::default()
+        };
+        assert!(incomplete.normalized().is_err());
+    }
+
+    #[test]
+    fn resources_are_named_after_the_uid() {
+        let url = resource_url("https://dav.example.com/cal/", "a/b c@example.com");
+        assert_eq!(
+            url,
+            resource_url("https://dav.example.com/cal/", "a/b c@example.com")
+        );
+        assert!(url.starts_with("https://dav.example.com/cal/") && url.ends_with(".ics"));
+        assert!(!url["https://dav.example.com/cal/".len()..].contains(['/', ' ', '@']));
+    }
+}
@@ -0,0 +1,680 @@
+//! Calendar events from invitations in mail, and free/busy answers built on
+//! them. Events are read from `text/calendar` parts and `.ics` attachments;
+//! a later invitation for the same event replaces an earlier one. Times
+//! given with a `TZID` are taken as local time, and recurring events count
+//! at their first occurrence only.
+
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use mailparse::{parse_mail, ParsedMail};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::models::EmailSummary;
+
+/// Hours of the day a question about a whole day covers.
+const WORKDAY_HOURS: (u32, u32) = (9, 18);
+const MORNING_HOURS: (u32, u32) = (9, 12);
+const AFTERNOON_HOURS: (u32, u32) = (12, 17);
+const EVENING_HOURS: (u32, u32) = (17, 21);
+/// Lines are folded at this many bytes.
+pub(crate) const FOLD_AT: usize = 75;
+const CANDIDATE_SUBJECTS: &[&str] = &[
+    "invitation",
+    "invite",
+    "meeting",
+    "event",
+    "calendar",
+    "appointment",
+    "rescheduled",
+    "canceled",
+    "cancelled",
+];
+const WEEKDAYS: &[(&str, Weekday)] = &[
+    ("monday", Weekday::Mon),
+    ("mon", Weekday::Mon),
+    ("tuesday", Weekday::Tue),
+    ("tues", Weekday::Tue),
+    ("tue", Weekday::Tue),
+    ("wednesday", Weekday::Wed),
+    ("wed", Weekday::Wed),
+    ("thursday", Weekday::Thu),
+    ("thurs", Weekday::Thu),
+    ("thur", Weekday::Thu),
+    ("thu", Weekday::Thu),
+    ("friday", Weekday::Fri),
+    ("fri", Weekday::Fri),
+    ("saturday", Weekday::Sat),
+    ("sat", Weekday::Sat),
+    ("sunday", Weekday::Sun),
+    ("sun", Weekday::Sun),
+];
+
+static WORD: Lazy<Regex> = Lazy::new(|| Regex::new(r"[a-z]+").expect("word pattern is valid"));
+static ISO_DATE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b(\d{4}-\d{2}-\d{2})\b").expect("date pattern is valid"));
+static DURATION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^P(?:(\d+)W)?(?:(\d+)D)?(?:T(?:(\d+)H)?(?:(\d+)M)?(?:(\d+)S)?)?$")
+        .expect("duration pattern is valid")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventStatus {
+    Confirmed,
+    Tentative,
+    Cancelled,
+}
+
+impl EventStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventStatus::Confirmed => "confirmed",
+            EventStatus::Tentative => "tentative",
+            EventStatus::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "confirmed" => Some(EventStatus::Confirmed),
+            "tentative" => Some(EventStatus::Tentative),
+            "cancelled" => Some(EventStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarEvent {
+    /// The invitation's `UID`, shared by every update to the event.
+    pub event_uid: String,
+    pub sequence: i64,
+    pub summary: String,
+    pub location: Option<String>,
+    /// Unix seconds.
+    pub starts_at: i64,
+    pub ends_at: i64,
+    pub all_day: bool,
+    pub status: EventStatus,
+    /// False for cancelled, declined, and free-time (`TRANSP:TRANSPARENT`)
+    /// events.
+    pub busy: bool,
+}
+
+/// What an availability check covers: an explicit range in Unix seconds, or
+/// a question such as "am I free Thursday afternoon?".
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailabilityRange {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub question: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AvailabilityBlock {
+    pub start: i64,
+    pub end: i64,
+    pub busy: bool,
+    /// Summaries of the events filling a busy block.
+    pub events: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Availability {
+    /// Busy and free blocks covering each window asked about, in order.
+    pub blocks: Vec<AvailabilityBlock>,
+    /// Nothing is booked anywhere in the range.
+    pub free: bool,
+}
+
+/// Whether a newly synced message may carry an invitation worth fetching.
+pub fn is_candidate(summary: &EmailSummary) -> bool {
+    let subject = summary.subject.to_lowercase();
+    CANDIDATE_SUBJECTS.iter().any(|hint| subject.contains(hint))
+        || summary.sender.email.to_lowercase().starts_with("calendar")
+}
+
+/// Every event in the calendar parts of a raw message. `account_email`
+/// decides whether the user declined an event.
+pub fn extract(raw: &[u8], account_email: &str) -> Vec<CalendarEvent> {
+    let Ok(parsed) = parse_mail(raw) else {
+        return Vec::new();
+    };
+    let mut events = Vec::new();
+    collect(&parsed, account_email, &mut events);
+    events
+}
+
+fn collect(part: &ParsedMail, account_email: &str, events: &mut Vec<CalendarEvent>) {
+    if !part.subparts.is_empty() {
+        for subpart in &part.subparts {
+            collect(subpart, account_email, events);
+        }
+        return;
+    }
+    let mimetype = part.ctype.mimetype.to_lowercase();
+    let is_ics = part
+        .get_content_disposition()
+        .params
+        .get("filename")
+        .or_else(|| part.ctype.params.get("name"))
+        .is_some_and(|name| name.trim().to_lowercase().ends_with(".ics"));
+    if mimetype != "text/calendar" && mimetype != "application/ics" && !is_ics {
+        return;
+    }
+    let Ok(body) = part.get_body() else {
+        return;
+    };
+    for event in parse_ics(&body, account_email) {
+        // Calendars often attach the same invitation twice, inline and as a
+        // file.
+        if !events.iter().any(|seen| seen.event_uid == event.event_uid) {
+            events.push(event);
+        }
+    }
+}
+
+/// The events of an iCalendar document.
+pub fn parse_ics(text: &str, account_email: &str) -> Vec<CalendarEvent> {
+    let account = account_email.trim().to_lowercase();
+    let mut cancelled_by_method = false;
+    let mut events = Vec::new();
+    let mut current: Option<Vec<(String, String, String)>> = None;
+    let mut nested = 0usize;
+
+    for line in unfold(text) {
+        let Some((name, params, value)) = split_property(&line) else {
+            continue;
+        };
+        match (name.as_str(), value.to_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") => current = Some(Vec::new()),
+            ("END", "VEVENT") => {
+                if let Some(properties) = current.take() {
+                    events.extend(build_event(&properties, &account));
+                }
+            }
+            ("BEGIN", _) if current.is_some() => nested += 1,
+            ("END", _) if current.is_some() => nested = nested.saturating_sub(1),
+            ("METHOD", "CANCEL") => cancelled_by_method = true,
+            _ => {
+                if let (Some(properties), 0) = (current.as_mut(), nested) {
+                    properties.push((name, params, value));
+                }
+            }
+        }
+    }
+
+    if cancelled_by_method {
+        for event in &mut events {
+            event.status = EventStatus::Cancelled;
+            event.busy = false;
+        }
+    }
+    events
+}
+
+fn build_event(properties: &[(String, String, String)], account: &str) -> Option<CalendarEvent> {
+    let property = |wanted: &str| {
+        properties
+            .iter()
+            .find(|(name, _, _)| name == wanted)
+            .map(|(_, params, value)| (params.as_str(), value.as_str()))
+    };
+
+    let event_uid = property("UID")?.1.trim().to_string();
+    let (start_params, start_value) = property("DTSTART")?;
+    let (starts_at, all_day) = parse_date_time(start_params, start_value)?;
+    let ends_at = match property("DTEND") {
+        Some((params, value)) => parse_date_time(params, value)?.0,
+        None => match property("DURATION").and_then(|(_, value)| parse_duration(value)) {
+            Some(duration) => starts_at + duration,
+            None if all_day => starts_at + Duration::days(1).num_seconds(),
+            None => starts_at,
+        },
+    };
+
+    let status = match property("STATUS").map(|(_, value)| value.trim().to_uppercase()) {
+        Some(value) if value == "CANCELLED" => EventStatus::Cancelled,
+        Some(value) if value == "TENTATIVE" => EventStatus::Tentative,
+        _ => EventStatus::Confirmed,
+    };
+    let declined = properties.iter().any(|(name, params, value)| {
+        name == "ATTENDEE"
+            && value.trim().to_lowercase().trim_start_matches("mailto:") == account
+            && param(params, "PARTSTAT").is_some_and(|stat| stat.eq_ignore_ascii_case("DECLINED"))
+    });
+    let transparent = property("TRANSP")
+        .is_some_and(|(_, value)| value.trim().eq_ignore_ascii_case("TRANSPARENT"));
+
+    Some(CalendarEvent {
+        event_uid,
+        sequence: property("SEQUENCE")
+            .and_then(|(_, value)| value.trim().parse().ok())
+            .unwrap_or(0),
+        summary: property("SUMMARY")
+            .map(|(_, value)| unescape(value))
+            .unwrap_or_default(),
+        location: property("LOCATION")
+            .map(|(_, value)| unescape(value))
+            .filter(|value| !value.is_empty()),
+        starts_at,
+        ends_at: ends_at.max(starts_at),
+        all_day,
+        busy: status != EventStatus::Cancelled && !declined && !transparent,
+        status,
+    })
+}
+
+// iCalendar and vCard share the line syntax read and written below.
+
+/// Joins folded lines back together.
+pub(crate) fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.trim_end().to_string()),
+        }
+    }
+    lines
+}
+
+/// `NAME;PARAMS:value`, with the name uppercased. Colons inside quoted
+/// parameters do not end the name.
+pub(crate) fn split_property(line: &str) -> Option<(String, String, String)> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(index, ch)| match ch {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(index),
+        _ => None,
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let (name, params) = head.split_once(';').unwrap_or((head, ""));
+    Some((
+        name.trim().to_uppercase(),
+        params.to_string(),
+        value.to_string(),
+    ))
+}
+
+pub(crate) fn param<'a>(params: &'a str, wanted: &str) -> Option<&'a str> {
+    params.split(';').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case(wanted)
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// Unix seconds and whether the value is a date without a time. UTC times
+/// end in `Z`; other times are read as local.
+fn parse_date_time(params: &str, value: &str) -> Option<(i64, bool)> {
+    let value = value.trim();
+    let is_date = param(params, "VALUE").is_some_and(|kind| kind.eq_ignore_ascii_case("DATE"))
+        || value.len() == 8;
+    if is_date {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some((local_timestamp(date.and_time(NaiveTime::MIN))?, true));
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        let naive = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some((Utc.from_utc_datetime(&naive).timestamp(), false));
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    Some((local_timestamp(naive)?, false))
+}
+
+/// Seconds in an iCalendar duration such as `PT1H30M`.
+fn parse_duration(value: &str) -> Option<i64> {
+    let caps = DURATION.captures(value.trim().trim_start_matches('+'))?;
+    let field = |index: usize| -> i64 {
+        caps.get(index)
+            .and_then(|found| found.as_str().parse().ok())
+            .unwrap_or(0)
+    };
+    Some(field(1) * 7 * 86_400 + field(2) * 86_400 + field(3) * 3_600 + field(4) * 60 + field(5))
+}
+
+pub(crate) fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.trim().chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+pub(crate) fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Splits a line into pieces of at most [`FOLD_AT`] bytes, never inside a
+/// character; continuation lines start with a space.
+pub(crate) fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / FOLD_AT * 3);
+    let mut width = 0;
+    for ch in line.chars() {
+        if width + ch.len_utf8() > FOLD_AT {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(ch);
+        width += ch.len_utf8();
+    }
+    folded
+}
+
+fn local_timestamp(naive: NaiveDateTime) -> Option<i64> {
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|date| date.timestamp())
+}
+
+/// The windows, in Unix seconds, that a question about availability covers:
+/// the days it names ("today", "tomorrow", weekdays, "this week", "next
+/// week", "the weekend", `YYYY-MM-DD`), narrowed to a part of the day when
+/// it names one. Whole days mean working hours. Past time is dropped.
+/// `None` when no day is named.
+pub fn resolve_question(question: &str, now: DateTime<Local>) -> Option<Vec<(i64, i64)>> {
+    let lowered = question.to_lowercase();
+    let words = WORD
+        .find_iter(&lowered)
+        .map(|found| found.as_str())
+        .collect::<Vec<_>>();
+    let has = |wanted: &str| words.contains(&wanted);
+    let has_pair = |first: &str, second: &str| {
+        words
+            .windows(2)
+            .any(|pair| pair[0] == first && pair[1] == second)
+    };
+    let today = now.date_naive();
+
+    let mut days = Vec::new();
+    let this_part = ["morning", "afternoon", "evening"]
+        .iter()
+        .any(|part| has_pair("this", part));
+    if has("today") || has("tonight") || this_part {
+        days.push(today);
+    }
+    if has("tomorrow") {
+        days.push(today + Duration::days(1));
+    }
+    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+    if has_pair("next", "week") {
+        days.extend((0..5).map(|offset| monday + Duration::days(7 + offset)));
+    } else if has_pair("this", "week") {
+        days.extend((0..5).map(|offset| monday + Duration::days(offset)));
+    }
+    if has("weekend") {
+        days.extend((5..7).map(|offset| monday + Duration::days(offset)));
+    }
+    for (index, word) in words.iter().enumerate() {
+        let Some((_, weekday)) = WEEKDAYS.iter().find(|(name, _)| name == word) else {
+            continue;
+        };
+        let ahead = (weekday.num_days_from_monday() as i64
+            - today.weekday().num_days_from_monday() as i64)
+            .rem_euclid(7);
+        let skip_today = index > 0 && words[index - 1] == "next" && ahead == 0;
+        days.push(today + Duration::days(if skip_today { 7 } else { ahead }));
+    }
+    for caps in ISO_DATE.captures_iter(&lowered) {
+        if let Ok(date) = NaiveDate::parse_from_str(&caps[1], "%Y-%m-%d") {
+            days.push(date);
+        }
+    }
+    if days.is_empty() {
+        return None;
+    }
+    days.sort_unstable();
+    days.dedup();
+
+    let (from, to) = if has("morning") {
+        MORNING_HOURS
+    } else if has("afternoon") {
+        AFTERNOON_HOURS
+    } else if has("evening") || has("tonight") {
+        EVENING_HOURS
+    } else {
+        WORKDAY_HOURS
+    };
+    let now = now.timestamp();
+    Some(
+        days.into_iter()
+            .filter_map(|day| {
+                let start = local_timestamp(day.and_hms_opt(from, 0, 0)?)?;
+                let end = local_timestamp(day.and_hms_opt(to, 0, 0)?)?;
+                (end > now).then_some((start.max(now), end))
+            })
+            .collect(),
+    )
+}
+
+/// Working hours of the next `count` weekdays, starting today.
+pub fn upcoming_workdays(now: DateTime<Local>, count: usize) -> Vec<(i64, i64)> {
+    let (from, to) = WORKDAY_HOURS;
+    let now_ts = now.timestamp();
+    (0..)
+        .map(|offset| now.date_naive() + Duration::days(offset))
+        .filter(|day| !matches!(day.weekday(), Weekday::Sat | Weekday::Sun))
+        .filter_map(|day| {
+            let start = local_timestamp(day.and_hms_opt(from, 0, 0)?)?;
+            let end = local_timestamp(day.and_hms_opt(to, 0, 0)?)?;
+            (end > now_ts).then_some((start.max(now_ts), end))
+        })
+        .take(count)
+        .collect()
+}
+
+/// Busy and free blocks across `windows`. Overlapping events merge into one
+/// busy block; only busy events count.
+pub fn availability(events: &[CalendarEvent], windows: &[(i64, i64)]) -> Availability {
+    let mut busy = events
+        .iter()
+        .filter(|event| event.busy && event.ends_at > event.starts_at)
+        .collect::<Vec<_>>();
+    busy.sort_by_key(|event| (event.starts_at, event.ends_at));
+
+    let mut blocks = Vec::new();
+    for &(window_start, window_end) in windows {
+        let mut cursor = window_start;
+        for event in &busy {
+            let start = event.starts_at.max(window_start);
+            let end = event.ends_at.min(window_end);
+            if start >= end {
+                continue;
+            }
+            if start > cursor {
+                blocks.push(AvailabilityBlock {
+                    start: cursor,
+                    end: start,
+                    busy: false,
+                    events: Vec::new(),
+                });
+            }
+            match blocks.last_mut() {
+                Some(last) if last.busy && start <= last.end => {
+                    last.end = last.end.max(end);
+                    last.events.push(event.summary.clone());
+                }
+                _ => blocks.push(AvailabilityBlock {
+                    start,
+                    end,
+                    busy: true,
+                    events: vec![event.summary.clone()],
+                }),
+            }
+            cursor = cursor.max(end);
+        }
+        if cursor < window_end {
+            blocks.push(AvailabilityBlock {
+                start: cursor,
+                end: window_end,
+                busy: false,
+                events: Vec::new(),
+            });
+        }
+    }
+    Availability {
+        free: blocks.iter().all(|block| !block.busy),
+        blocks,
+    }
+}
+
+/// The free blocks as lines like "Thu 22 Oct, 13:00-15:30", for a reply.
+pub fn describe_free(availability: &Availability) -> String {
+    availability
+        .blocks
+        .iter()
+        .filter(|block| !block.busy)
+        .filter_map(|block| {
+            let start = Local.timestamp_opt(block.start, 0).single()?;
+            let end = Local.timestamp_opt(block.end, 0).single()?;
+            Some(format!(
+                "{}-{}",
+                start.format("%a %-d %b, %H:%M"),
+                end.format("%H:%M")
+            ))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(day: u32, hour: u32, minute: u32) -> i64 {
+        Local
+            .with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .unwrap()
+            .timestamp()
+    }
+
+    fn event(summary: &str, starts_at: i64, ends_at: i64) -> CalendarEvent {
+        CalendarEvent {
+            event_uid: summary.to_string(),
+            sequence: 0,
+            summary: summary.to_string(),
+            location: None,
+            starts_at,
+            ends_at,
+            all_day: false,
+            status: EventStatus::Confirmed,
+            busy: true,
+        }
+    }
+
+    #[test]
+    fn parses_invitations() {
+        let ics = "BEGIN:VCALENDAR\r\nMETHOD:REQUEST\r\nBEGIN:VEVENT\r\n\
+                   UID:abc@example.com\r\nSEQUENCE:2\r\nSUMMARY:Design review\\, round 2\r\n\
+                   DTSTART:20261022T130000Z\r\nDURATION:PT1H30M\r\n\
+                   ATTENDEE;CN=\"Me: Myself\";PARTSTAT=NEEDS-ACTION:mailto:Me@Example.com\r\n\
+                   BEGIN:VALARM\r\nSUMMARY:Reminder\r\nEND:VALARM\r\nEND:VEVENT\r\n\
+                   BEGIN:VEVENT\r\nUID:offsite\r\nDTSTART;VALUE=DATE:20261023\r\n\
+                   SUMMARY:Offsite\r\n  day\r\nATTENDEE;PARTSTAT=DECLINED:mailto:me@example.com\r\n\
+                   END:VEVENT\r\nEND:VCALENDAR\r\n";
+        let events = parse_ics(ics, "me@example.com");
+        assert_eq!(events.len(), 2);
+
+        let review = &events[0];
+        assert_eq!(review.summary, "Design review, round 2");
+        assert_eq!(review.sequence, 2);
+        let start = Utc.with_ymd_and_hms(2026, 10, 22, 13, 0, 0).unwrap();
+        assert_eq!(review.starts_at, start.timestamp());
+        assert_eq!(review.ends_at - review.starts_at, 90 * 60);
+        assert!(review.busy);
+
+        let offsite = &events[1];
+        assert_eq!(offsite.summary, "Offsite day");
+        assert!(offsite.all_day);
+        assert_eq!(offsite.starts_at, local(23, 0, 0));
+        assert!(!offsite.busy);
+    }
+
+    #[test]
+    fn cancellations_free_the_slot() {
+        let ics = "BEGIN:VCALENDAR\nMETHOD:CANCEL\nBEGIN:VEVENT\nUID:x\n\
+                   DTSTART:20261022T100000\nDTEND:20261022T110000\nEND:VEVENT\nEND:VCALENDAR\n";
+        let events = parse_ics(ics, "me@example.com");
+        assert_eq!(events[0].status, EventStatus::Cancelled);
+        assert!(!events[0].busy);
+    }
+
+    #[test]
+    fn resolves_questions_to_windows() {
+        // Friday 16 October 2026, 10:30.
+        let now = Local.with_ymd_and_hms(2026, 10, 16, 10, 30, 0).unwrap();
+        assert_eq!(
+            resolve_question("Am I free Thursday afternoon?", now),
+            Some(vec![(local(22, 12, 0), local(22, 17, 0))])
+        );
+        assert_eq!(
+            resolve_question("anything today?", now),
+            Some(vec![(now.timestamp(), local(16, 18, 0))])
+        );
+        assert_eq!(
+            resolve_question("next friday morning", now),
+            Some(vec![(local(23, 9, 0), local(23, 12, 0))])
+        );
+        assert_eq!(
+            resolve_question("this morning", now),
+            Some(vec![(now.timestamp(), local(16, 12, 0))])
+        );
+        assert_eq!(resolve_question("how about soon", now), None);
+    }
+
+    #[test]
+    fn merges_busy_time_and_fills_the_gaps() {
+        let window = (local(22, 12, 0), local(22, 17, 0));
+        let mut cancelled = event("Cancelled", local(22, 15, 0), local(22, 16, 0));
+        cancelled.busy = false;
+        let events = [
+            event("Lunch", local(22, 11, 30), local(22, 13, 0)),
+            event("Review", local(22, 14, 0), local(22, 15, 0)),
+            event("Sync", local(22, 14, 30), local(22, 15, 30)),
+            cancelled,
+        ];
+        let found = availability(&events, &[window]);
+        let spans = found
+            .blocks
+            .iter()
+            .map(|block| (block.start, block.end, block.busy))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                (local(22, 12, 0), local(22, 13, 0), true),
+                (local(22, 13, 0), local(22, 14, 0), false),
+                (local(22, 14, 0), local(22, 15, 30), true),
+                (local(22, 15, 30), local(22, 17, 0), false),
+            ]
+        );
+        assert_eq!(found.blocks[2].events, vec!["Review", "Sync"]);
+        assert!(!found.free);
+        assert!(availability(&[], &[window]).free);
+    }
+}
@@ -0,0 +1,410 @@
+//! CardDAV client for the contacts table. A sync pulls the address book
+//! (only cards whose ETag changed are downloaded) and pushes the local
+//! contacts the user opted in, one by one. Writes are conditional on the
+//! ETag last seen, so a card edited on a phone in the meantime is never
+//! overwritten blindly: the next pull sees both versions and the configured
+//! [`ConflictPolicy`] picks one. The password lives in the OS keychain.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{Method, StatusCode};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::flag_sync::ConflictPolicy;
+
+/// App setting holding the [`CardDavSettings`] as JSON.
+pub const SETTING_KEY: &str = "carddav";
+/// Cards requested per multiget report.
+pub const MULTIGET_BATCH: usize = 50;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:getetag/><d:resourcetype/></d:prop></d:propfind>"#;
+
+static RESPONSE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(?:[a-z0-9]+:)?response\b[^>]*>(.*?)</(?:[a-z0-9]+:)?response\s*>")
+        .expect("response pattern is valid")
+});
+static HREF: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(?:[a-z0-9]+:)?href\b[^>]*>(.*?)</(?:[a-z0-9]+:)?href\s*>")
+        .expect("href pattern is valid")
+});
+static ETAG: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(?:[a-z0-9]+:)?getetag\b[^>]*>(.*?)</(?:[a-z0-9]+:)?getetag\s*>")
+        .expect("etag pattern is valid")
+});
+static ADDRESS_DATA: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"(?is)<(?:[a-z0-9]+:)?address-data\b[^>]*>(.*?)",
+        r"</(?:[a-z0-9]+:)?address-data\s*>",
+    ))
+    .expect("address data pattern is valid")
+});
+static COLLECTION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)<(?:[a-z0-9]+:)?collection\b").expect("collection pattern is valid")
+});
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CardDavSettings {
+    pub enabled: bool,
+    /// The address book collection, e.g.
+    /// `https://dav.example.com/addressbooks/me/contacts/`.
+    pub addressbook_url: Option<String>,
+    pub username: Option<String>,
+    /// Which version wins when a card changed both here and on the server.
+    pub conflict_policy: ConflictPolicy,
+}
+
+impl CardDavSettings {
+    /// Trims the fields and ends the URL with a slash; rejects a URL that is
+    /// not https, or enabling without a URL and username.
+    pub fn normalized(mut self) -> Result<Self, String> {
+        self.addressbook_url = self
+            .addressbook_url
+            .map(|url| format!("{}/", url.trim().trim_end_matches('/')))
+            .filter(|url| url != "/");
+        self.username = self
+            .username
+            .map(|username| username.trim().to_string())
+            .filter(|username| !username.is_empty());
+        if let Some(url) = &self.addressbook_url {
+            if !url.to_lowercase().starts_with("https://") {
+                return Err("The address book URL must use https".into());
+            }
+        }
+        if self.enabled && (self.addressbook_url.is_none() || self.username.is_none()) {
+            return Err("Set the address book URL and username to sync contacts".into());
+        }
+        Ok(self)
+    }
+}
+
+/// Keychain entry holding the password for `username`.
+pub fn keychain_key(username: &str) -> String {
+    format!("carddav:{username}")
+}
+
+/// A card on the server, and its content when it was downloaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteCard {
+    /// Path on the server, as it answered.
+    pub href: String,
+    pub etag: Option<String>,
+    pub vcard: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PutResult {
+    /// Written; the new ETag when the server sent one.
+    Stored(Option<String>),
+    /// The card changed on the server since it was last pulled.
+    Conflict,
+    Failed(String),
+}
+
+/// An authenticated connection to one address book.
+pub struct Client {
+    http: reqwest::Client,
+    addressbook_url: String,
+    username: String,
+    password: SecretString,
+}
+
+impl Client {
+    pub fn new(settings: &CardDavSettings, password: SecretString) -> Result<Self, String> {
+        let (Some(addressbook_url), Some(username)) =
+            (settings.addressbook_url.clone(), settings.username.clone())
+        else {
+            return Err("CardDAV sync is not configured".into());
+        };
+        let http = reqwest::Client::builder()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .map_err(|err| err.to_string())?;
+        Ok(Self {
+            http,
+            addressbook_url,
+            username,
+            password,
+        })
+    }
+
+    /// Every card in the address book with its ETag, without content.
+    pub async fn list(&self) -> Result<Vec<RemoteCard>, String> {
+        let body = self
+            .send(
+                Method::from_bytes(b"PROPFIND").expect("PROPFIND is a valid method"),
+                &self.addressbook_url,
+                PROPFIND_BODY.to_string(),
+                Some("1"),
+            )
+            .await?;
+        Ok(parse_multistatus(&body))
+    }
+
+    /// The cards at `hrefs`, with content.
+    pub async fn fetch(&self, hrefs: &[String]) -> Result<Vec<RemoteCard>, String> {
+        let hrefs = hrefs
+            .iter()
+            .map(|href| format!("<d:href>{}</d:href>", xml_escape(href)))
+            .collect::<String>();
+        let body = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<c:addressbook-multiget xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:carddav">
+<d:prop><d:getetag/><c:address-data/></d:prop>{hrefs}</c:addressbook-multiget>"#
+        );
+        let body = self
+            .send(
+                Method::from_bytes(b"REPORT").expect("REPORT is a valid method"),
+                &self.addressbook_url,
+                body,
+                Some("1"),
+            )
+            .await?;
+        Ok(parse_multistatus(&body)
+            .into_iter()
+            .filter(|card| card.vcard.is_some())
+            .collect())
+    }
+
+    /// Writes a card. With an ETag the write only succeeds if the server
+    /// still holds that version; a card without `href` is only created.
+    pub async fn put(
+        &self,
+        href: Option<&str>,
+        uid: &str,
+        vcard: String,
+        etag: Option<&str>,
+    ) -> (String, PutResult) {
+        let target = href.map_or_else(
+            || resource_url(&self.addressbook_url, uid),
+            |href| self.resolve(href),
+        );
+        let mut request = self
+            .http
+            .put(&target)
+            .basic_auth(&self.username, Some(self.password.expose_secret()))
+            .header("Content-Type", "text/vcard; charset=utf-8")
+            .body(vcard);
+        request = match (href, etag) {
+            (_, Some(etag)) => request.header("If-Match", etag),
+            (None, None) => request.header("If-None-Match", "*"),
+            // The server never sent an ETag for this card.
+            (Some(_), None) => request,
+        };
+        let href = href.map_or_else(|| url_path(&target), str::to_string);
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(err) => {
+                return (
+                    href,
+                    PutResult::Failed(format!("CardDAV request failed: {err}")),
+                )
+            }
+        };
+        let status = response.status();
+        if status == StatusCode::PRECONDITION_FAILED {
+            return (href, PutResult::Conflict);
+        }
+        if !status.is_success() {
+            return (
+                href,
+                PutResult::Failed(format!("CardDAV server answered {status}")),
+            );
+        }
+        let etag = response
+            .headers()
+            .get("ETag")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        (href, PutResult::Stored(etag))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        body: String,
+        depth: Option<&str>,
+    ) -> Result<String, String> {
+        let mut request = self
+            .http
+            .request(method, url)
+            .basic_auth(&self.username, Some(self.password.expose_secret()))
+            .header("Content-Type", "application/xml; charset=utf-8")
+            .body(body);
+        if let Some(depth) = depth {
+            request = request.header("Depth", depth);
+        }
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("CardDAV request failed: {err}"))?
+            .text()
+            .await
+            .map_err(|err| format!("CardDAV request failed: {err}"))
+    }
+
+    /// An absolute URL for an href the server gave, which is usually a path.
+    fn resolve(&self, href: &str) -> String {
+        if href.starts_with("https://") || href.starts_with("http://") {
+            return href.to_string();
+        }
+        let origin_end = self
+            .addressbook_url
+            .find("://")
+            .and_then(|scheme| {
+                self.addressbook_url[scheme + 3..]
+                    .find('/')
+                    .map(|path| scheme + 3 + path)
+            })
+            .unwrap_or(self.addressbook_url.len());
+        format!("{}{href}", &self.addressbook_url[..origin_end])
+    }
+}
+
+/// Where a new card with `uid` is created.
+fn resource_url(addressbook_url: &str, uid: &str) -> String {
+    let name = uid
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '-' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    format!("{addressbook_url}{name}.vcf")
+}
+
+fn url_path(url: &str) -> String {
+    url.find("://")
+        .and_then(|scheme| {
+            url[scheme + 3..]
+                .find('/')
+                .map(|path| &url[scheme + 3 + path..])
+        })
+        .unwrap_or(url)
+        .to_string()
+}
+
+/// The card responses in a WebDAV multistatus body. The collection itself
+/// is left out.
+fn parse_multistatus(body: &str) -> Vec<RemoteCard> {
+    RESPONSE
+        .captures_iter(body)
+        .filter(|caps| !COLLECTION.is_match(&caps[1]))
+        .filter_map(|caps| {
+            let response = &caps[1];
+            let href = xml_unescape(HREF.captures(response)?[1].trim());
+            Some(RemoteCard {
+                href,
+                etag: ETAG
+                    .captures(response)
+                    .map(|etag| xml_unescape(etag[1].trim())),
+                vcard: ADDRESS_DATA
+                    .captures(response)
+                    .map(|data| xml_unescape(&data[1]))
+                    .filter(|data| !data.trim().is_empty()),
+            })
+        })
+        .collect()
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn xml_unescape(value: &str) -> String {
+    let value = value.trim();
+    if let Some(data) = value
+        .strip_prefix("<![CDATA[")
+        .and_then(|rest| rest.strip_suffix("]]>"))
+    {
+        return data.to_string();
+    }
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#13;", "\r")
+        .replace("&#xD;", "\r")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_multistatus_responses() {
+        let body = r#"<?xml version="1.0"?>
+<D:multistatus xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:carddav">
+  <D:response><D:href>/book/</D:href><D:propstat><D:prop>
+    <D:resourcetype><D:collection/><C:addressbook/></D:resourcetype>
+  </D:prop></D:propstat></D:response>
+  <D:response><D:href>/book/a%20b.vcf</D:href><D:propstat><D:prop>
+    <D:getetag>&quot;e1&quot;</D:getetag>
+    <C:address-data>BEGIN:VCARD&#13;
+FN:A &amp; B&#13;
+END:VCARD</C:address-data>
+  </D:prop></D:propstat></D:response>
+  <response xmlns="DAV:"><href>/book/c.vcf</href><propstat><prop>
+    <getetag>"e2"</getetag></prop></propstat></response>
+</D:multistatus>"#;
+        let cards = parse_multistatus(body);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].href, "/book/a%20b.vcf");
+        assert_eq!(cards[0].etag.as_deref(), Some("\"e1\""));
+        assert_eq!(
+            cards[0].vcard.as_deref(),
+            Some("BEGIN:VCARD\r\nFN:A & B\r\nEND:VCARD")
+        );
+        assert_eq!(cards[1].href, "/book/c.vcf");
+        assert_eq!(cards[1].vcard, None);
+    }
+
+    #[test]
+    fn settings_need_an_https_address_book() {
+        let settings = CardDavSettings {
+            enabled: true,
+            addressbook_url: Some("https://dav.example.com/book".into()),
+            username: Some(" me ".into()),
+            ..CardDavSettings::default()
+        }
+        .normalized()
+        .unwrap();
+        assert_eq!(
+            settings.addressbook_url.as_deref(),
+            Some("https://dav.example.com/book/")
+        );
+        assert_eq!(settings.username.as_deref(), Some("me"));
+        assert!(CardDavSettings {
+            addressbook_url: Some("http://dav.example.com/".into()),
+            ..CardDavSettings::default()
+        }
+        .normalized()
+        .is_err());
+    }
+
+    #[test]
+    fn new_cards_get_safe_resource_names() {
+        assert_eq!(
+            resource_url("https://dav.example.com/book/", "urn:uuid:1/2"),
+            "https://dav.example.com/book/urn_uuid_1_2.vcf"
+        );
+        assert_eq!(
+            url_path("https://dav.example.com/book/x.vcf"),
+            "/book/x.vcf"
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! Fast local classification for obvious mail (newsletters, receipts,
+//! shipping notices, security codes, calendar traffic). Bulk analysis runs
+//! these rules first and only escalates ambiguous messages to the LLM.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Minimum confidence a rule match needs before the LLM is skipped.
+pub const FAST_PATH_MIN_CONFIDENCE: f64 = 0.85;
+
+#[derive(Debug, Clone)]
+pub struct FastClassification {
+    pub tags: Vec<String>,
+    pub priority: String,
+    pub confidence: f64,
+    pub rules: Vec<&'static str>,
+}
+
+enum Field {
+    Sender,
+    Subject,
+    Text,
+}
+
+struct Rule {
+    name: &'static str,
+    field: Field,
+    pattern: Regex,
+    tags: &'static [&'static str],
+    priority: &'static str,
+    confidence: f64,
+}
+
+fn rule(
+    name: &'static str,
+    field: Field,
+    pattern: &str,
+    tags: &'static [&'static str],
+    priority: &'static str,
+    confidence: f64,
+) -> Rule {
+    Rule {
+        name,
+        field,
+        pattern: Regex::new(pattern).expect("classifier rule pattern is valid"),
+        tags,
+        priority,
+        confidence,
+    }
+}
+
+static RULES: Lazy<Vec<Rule>> = Lazy::new(|| {
+    vec![
+        rule(
+            "newsletter",
+            Field::Text,
+            r"(?i)\bunsubscribe\b|view (this email )?in (your )?browser|manage (your )?(email )?preferences",
+            &["newsletter"],
+            "low",
+            0.9,
+        ),
+        rule(
+            "receipt",
+            Field::Text,
+            r"(?i)\b(receipt|order confirmation|payment received|amount paid|invoice (no\.?|number|#))\b",
+            &["receipts", "billing"],
+            "low",
+            0.88,
+        ),
+        rule(
+            "shipping",
+            Field::Text,
+            r"(?i)\b(has shipped|out for delivery|tracking number|was delivered|shipment)\b",
+            &["shipping", "updates"],
+            "normal",
+            0.88,
+        ),
+        rule(
+            "security",
+            Field::Text,
+            r"(?i)\b(verification code|one-time (pass)?code|reset your password|password reset|new sign-in|security alert)\b",
+            &["security", "system-alert"],
+            "high",
+            0.9,
+        ),
+        rule(
+            "calendar",
+            Field::Subject,
+            r"(?i)^(updated )?(invitation|accepted|declined|tentative|canceled event|cancelled event):",
+            &["meeting", "event"],
+            "normal",
+            0.92,
+        ),
+        rule(
+            "social",
+            Field::Sender,
+            r"(?i)@([a-z0-9-]+\.)*(facebookmail|linkedin|twitter|x|instagram|pinterest)\.com$",
+            &["social"],
+            "low",
+            0.87,
+        ),
+    ]
+});
+
+static AUTOMATED_SENDER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)^(no[-_.]?reply|do[-_.]?not[-_.]?reply|notifications?|mailer-daemon|bounces?)[@+]",
+    )
+    .expect("automated sender pattern is valid")
+});
+
+/// True for no-reply, notification, and bounce mailbox names.
+pub fn is_automated_sender(sender_email: &str) -> bool {
+    AUTOMATED_SENDER.is_match(sender_email.trim())
+}
+
+/// Runs the rule set against a message. Returns `None` when no rule fires;
+/// callers compare `confidence` with [`FAST_PATH_MIN_CONFIDENCE`] before
+/// trusting the result.
+pub fn classify(
+    sender_email: &str,
+    subject: &str,
+    snippet: Option<&str>,
+) -> Option<FastClassification> {
+    let sender = sender_email.trim();
+    let text = format!("{subject}\n{}", snippet.unwrap_or_default());
+
+    let matched = RULES
+        .iter()
+        .filter(|rule| {
+            let haystack = match rule.field {
+                Field::Sender => sender,
+                Field::Subject => subject.trim(),
+                Field::Text => text.as_str(),
+            };
+            rule.pattern.is_match(haystack)
+        })
+        .collect::<Vec<_>>();
+
+    if matched.is_empty() {
+        return None;
+    }
+
+    let mut tags = Vec::new();
+    for rule in &matched {
+        for tag in rule.tags {
+            if !tags.iter().any(|existing: &String| existing == tag) {
+                tags.push(tag.to_string());
+            }
+        }
+    }
+
+    let priority = matched
+        .iter()
+        .map(|rule| rule.priority)
+        .max_by_key(|priority| priority_rank(priority))
+        .unwrap_or("normal")
+        .to_string();
+
+    // Several unrelated rules firing means the message is harder to call.
+    let mut confidence = matched
+        .iter()
+        .map(|rule| rule.confidence)
+        .fold(0.0_f64, f64::max);
+    confidence -= 0.1 * (matched.len() - 1) as f64;
+    if AUTOMATED_SENDER.is_match(sender) {
+        confidence += 0.05;
+    }
+
+    Some(FastClassification {
+        tags,
+        priority,
+        confidence: confidence.clamp(0.0, 0.99),
+        rules: matched.iter().map(|rule| rule.name).collect(),
+    })
+}
+
+fn priority_rank(priority: &str) -> u8 {
+    match priority {
+        "critical" => 3,
+        "high" => 2,
+        "normal" => 1,
+        _ => 0,
+    }
+}
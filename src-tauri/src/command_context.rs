@@ -0,0 +1,133 @@
+//! Shared account handling for Tauri commands. Commands take the address
+//! the frontend sent; [`CommandContext`] normalizes it, finds the connected
+//! account, and checks the provider, so every command rejects a bad account
+//! with the same message. Saved accounts are reconnected on first use, so
+//! commands keep working after a restart without a manual reconnect.
+
+use crate::keychain::fetch_password_from_keychain;
+use crate::models::{AppState, Credentials, Provider};
+use crate::providers::ProviderError;
+use tracing::{info, warn};
+
+pub const NOT_CONNECTED: &str = "Account is not connected";
+
+/// The form addresses are stored and keyed under.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// [`normalize_email`], rejecting an empty address.
+pub fn require_email(email: &str) -> Result<String, String> {
+    let normalized = normalize_email(email);
+    if normalized.is_empty() {
+        return Err("Email address is required".into());
+    }
+    Ok(normalized)
+}
+
+/// Turns a provider failure into the message shown to the user.
+pub fn provider_error_to_message(error: ProviderError) -> String {
+    match error {
+        ProviderError::Authentication(message) => message,
+        ProviderError::Network(message) => format!("Network error: {message}"),
+        ProviderError::Imap(message) => format!("IMAP error: {message}"),
+        ProviderError::Other(message) => message,
+    }
+}
+
+/// A connected account a command acts on.
+#[derive(Debug, Clone)]
+pub struct CommandContext {
+    pub account_email: String,
+    pub credentials: Credentials,
+}
+
+impl CommandContext {
+    /// Resolves `email` to a connected account.
+    pub async fn connected(state: &AppState, email: &str) -> Result<Self, String> {
+        let account_email = normalize_email(email);
+        let credentials = credentials(state, &account_email)
+            .await
+            .ok_or_else(|| NOT_CONNECTED.to_string())?;
+        Ok(Self {
+            account_email,
+            credentials,
+        })
+    }
+
+    /// Like [`CommandContext::connected`], for commands that name the
+    /// provider too; a different stored provider is an error.
+    pub async fn for_provider(
+        state: &AppState,
+        email: &str,
+        provider: Provider,
+    ) -> Result<Self, String> {
+        let context = Self::connected(state, email).await?;
+        if context.credentials.provider != provider {
+            warn!(
+                account = %context.account_email,
+                stored_provider = ?context.credentials.provider,
+                requested_provider = ?provider,
+                "provider mismatch"
+            );
+            return Err("Provider mismatch for stored credentials".into());
+        }
+        Ok(context)
+    }
+}
+
+/// Credentials for an account, or `None` when it is neither connected nor
+/// saved with a keychain password. For commands that can work offline;
+/// `account_email` must already be normalized.
+pub async fn credentials(state: &AppState, account_email: &str) -> Option<Credentials> {
+    if let Some(credentials) = state.accounts.read().await.get(account_email) {
+        return Some(credentials.clone());
+    }
+    rehydrate(state, account_email).await
+}
+
+/// Connects a saved account from its stored settings and keychain password.
+/// No mail is fetched; the next sync does that.
+async fn rehydrate(state: &AppState, account_email: &str) -> Option<Credentials> {
+    let record = match state.storage.account_by_email(account_email).await {
+        Ok(record) => record?,
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to load saved account");
+            return None;
+        }
+    };
+    let password = match fetch_password_from_keychain(account_email) {
+        Ok(password) => password?,
+        Err(err) => {
+            warn!(account = %account_email, %err, "failed to read keychain password");
+            return None;
+        }
+    };
+    let auth = record.auth();
+    let credentials = Credentials::new(
+        record.provider,
+        account_email.to_string(),
+        password,
+        record.custom_host,
+        record.custom_port,
+    )
+    .with_auth(auth);
+
+    {
+        let mut accounts = state.accounts.write().await;
+        if let Some(existing) = accounts.get(account_email) {
+            return Some(existing.clone());
+        }
+        accounts.insert(account_email.to_string(), credentials.clone());
+    }
+    info!(account = %account_email, "reconnected saved account from keychain");
+
+    if let Err(err) = state
+        .remote_delete
+        .resume_account(credentials.clone())
+        .await
+    {
+        warn!(account = %account_email, ?err, "failed to resume pending remote deletes");
+    }
+    Some(credentials)
+}
@@ -0,0 +1,416 @@
+//! Where the app keeps its database, master key, models, attachments,
+//! backups, and cached sender pictures.
+//!
+//! By default that is Tauri's app data directory. Users can move it to any
+//! folder (an external drive, say); the choice is recorded in a small
+//! pointer file that always stays in the default directory. Models and
+//! attachments can additionally live on volumes of their own. Portable mode
+//! overrides all of this: when a `portable` marker sits next to the
+//! executable, everything lives in a `data` folder beside it.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+pub const DATABASE_FILE: &str = "mail_cache.db";
+const LOCATION_FILE: &str = "data_location.json";
+const PORTABLE_MARKER: &str = "portable";
+const PORTABLE_DIR: &str = "data";
+/// Left in each folder data is copied into, so leaving a folder only ever
+/// removes what the app put there.
+const OWNED_MARKER: &str = ".personal-mail-client";
+
+/// Everything besides the database that moves with the data directory.
+/// Stores with their own location are simply absent here.
+const MANAGED_ENTRIES: [&str; 5] = ["master.key", "models", "attachments", "backups", "avatars"];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DataLocation {
+    /// `None` means the default directory.
+    path: Option<PathBuf>,
+    /// Directory a relocation copied from, removed on the next start.
+    previous: Option<PathBuf>,
+    models: Option<PathBuf>,
+    attachments: Option<PathBuf>,
+}
+
+/// Large stores that can be placed apart from the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Store {
+    Models,
+    /// Attachments saved without a destination of their own, by account.
+    Attachments,
+}
+
+impl Store {
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Store::Models => "models",
+            Store::Attachments => "attachments",
+        }
+    }
+
+    fn override_in(self, location: &mut DataLocation) -> &mut Option<PathBuf> {
+        match self {
+            Store::Models => &mut location.models,
+            Store::Attachments => &mut location.attachments,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StoreInfo {
+    pub store: Store,
+    pub path: PathBuf,
+    /// True when the store has its own location rather than the data folder.
+    pub custom: bool,
+    pub used_bytes: u64,
+    pub available_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DataDirectoryInfo {
+    pub path: PathBuf,
+    pub default_path: PathBuf,
+    pub portable: bool,
+    /// Where portable mode keeps data; `None` if the executable's folder is unknown.
+    pub portable_path: Option<PathBuf>,
+}
+
+pub fn default_dir(handle: &AppHandle) -> io::Result<PathBuf> {
+    handle
+        .path_resolver()
+        .app_data_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "App data directory not available"))
+}
+
+fn executable_dir() -> Option<PathBuf> {
+    std::env::current_exe()
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+}
+
+fn portable_dir() -> Option<PathBuf> {
+    executable_dir()
+        .filter(|dir| dir.join(PORTABLE_MARKER).exists())
+        .map(|dir| dir.join(PORTABLE_DIR))
+}
+
+fn read_location(default: &Path) -> DataLocation {
+    fs::read(default.join(LOCATION_FILE))
+        .ok()
+        .and_then(|raw| serde_json::from_slice(&raw).ok())
+        .unwrap_or_default()
+}
+
+fn write_location(default: &Path, location: &DataLocation) -> io::Result<()> {
+    fs::create_dir_all(default)?;
+    let raw = serde_json::to_vec_pretty(location)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    // Write-then-rename so a crash never leaves a half-written pointer.
+    let staging = default.join(format!("{LOCATION_FILE}.tmp"));
+    fs::write(&staging, raw)?;
+    fs::rename(&staging, default.join(LOCATION_FILE))
+}
+
+/// The directory in use: portable, then user-chosen, then the default.
+pub fn resolve(handle: &AppHandle) -> io::Result<PathBuf> {
+    match portable_dir() {
+        Some(dir) => Ok(dir),
+        None => configured_dir(handle),
+    }
+}
+
+/// The user-chosen or default directory, ignoring portable mode.
+pub fn configured_dir(handle: &AppHandle) -> io::Result<PathBuf> {
+    let default = default_dir(handle)?;
+    Ok(read_location(&default).path.unwrap_or(default))
+}
+
+pub fn info(handle: &AppHandle) -> io::Result<DataDirectoryInfo> {
+    Ok(DataDirectoryInfo {
+        path: resolve(handle)?,
+        default_path: default_dir(handle)?,
+        portable: portable_dir().is_some(),
+        portable_path: executable_dir().map(|dir| dir.join(PORTABLE_DIR)),
+    })
+}
+
+/// Where `store` lives: its own location if one is set (ignored in portable
+/// mode), otherwise a folder inside the data directory.
+pub fn store_dir(handle: &AppHandle, store: Store) -> io::Result<PathBuf> {
+    if portable_dir().is_none() {
+        let mut location = read_location(&default_dir(handle)?);
+        if let Some(path) = store.override_in(&mut location).take() {
+            return Ok(path);
+        }
+    }
+    Ok(resolve(handle)?.join(store.dir_name()))
+}
+
+pub fn store_info(handle: &AppHandle, store: Store) -> io::Result<StoreInfo> {
+    let path = store_dir(handle, store)?;
+    Ok(StoreInfo {
+        store,
+        custom: path != resolve(handle)?.join(store.dir_name()),
+        used_bytes: dir_size(&path),
+        available_bytes: available_space(&path).ok(),
+        path,
+    })
+}
+
+/// Records where `store` lives; `None` puts it back in the data directory.
+pub fn set_store_location(handle: &AppHandle, store: Store, path: Option<&Path>) -> io::Result<()> {
+    let default = default_dir(handle)?;
+    let mut location = read_location(&default);
+    *store.override_in(&mut location) = path.map(Path::to_path_buf);
+    write_location(&default, &location)
+}
+
+/// Free bytes on the volume holding `path`, which need not exist yet.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No existing parent folder"))?;
+    fs2::available_space(existing)
+}
+
+pub fn dir_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| dir_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Checks that `current` can be moved to `target`: no nesting, nothing in
+/// the way, and room for `needed` bytes.
+pub fn validate_store_target(current: &Path, target: &Path, needed: u64) -> Result<(), String> {
+    validate_paths(current, target)?;
+    let occupied = fs::read_dir(target)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    if occupied {
+        return Err("Choose an empty folder".into());
+    }
+    let available = available_space(target).map_err(|err| err.to_string())?;
+    if available < needed {
+        return Err(format!(
+            "Not enough free space: {needed} bytes needed, {available} available"
+        ));
+    }
+    Ok(())
+}
+
+/// Copies `from` into `to`, then removes `from`. A failed copy leaves the
+/// original in place; once the copy is complete, leftovers that cannot be
+/// removed are not treated as an error.
+pub fn move_store(from: &Path, to: &Path) -> io::Result<u64> {
+    if !from.exists() {
+        fs::create_dir_all(to)?;
+        return Ok(0);
+    }
+    let copied = copy_recursive(from, to)?;
+    let _ = fs::remove_dir_all(from);
+    Ok(copied)
+}
+
+fn validate_paths(current: &Path, target: &Path) -> Result<(), String> {
+    if !target.is_absolute() {
+        return Err("Choose an absolute folder path".into());
+    }
+    if target == current {
+        return Err("Data is already stored in that folder".into());
+    }
+    if target.starts_with(current) || current.starts_with(target) {
+        return Err("The new folder cannot contain, or be inside, the current one".into());
+    }
+    Ok(())
+}
+
+/// Rejects targets that would overwrite or nest inside the current data.
+/// The target must be empty, apart from the `default` directory, which
+/// keeps the location pointer and may only lack the app's own data.
+pub fn validate_target(current: &Path, target: &Path, default: &Path) -> Result<(), String> {
+    validate_paths(current, target)?;
+    if target.join(DATABASE_FILE).exists() {
+        return Err("That folder already contains a mail database".into());
+    }
+    let occupied = if target == default {
+        MANAGED_ENTRIES
+            .iter()
+            .any(|name| target.join(name).exists())
+    } else {
+        fs::read_dir(target)
+            .map(|mut entries| entries.next().is_some())
+            .unwrap_or(false)
+    };
+    if occupied {
+        return Err("Choose an empty folder".into());
+    }
+    Ok(())
+}
+
+/// Copies the key, models, and backups from `from` to `to`, and marks `to`
+/// as the app's. The database is not included; callers snapshot it
+/// separately so the copy is consistent.
+pub fn copy_managed_entries(from: &Path, to: &Path) -> io::Result<u64> {
+    fs::create_dir_all(to)?;
+    fs::write(to.join(OWNED_MARKER), b"")?;
+    let mut copied = 0;
+    for name in MANAGED_ENTRIES {
+        let source = from.join(name);
+        if source.exists() {
+            copied += copy_recursive(&source, &to.join(name))?;
+        }
+    }
+    Ok(copied)
+}
+
+fn copy_recursive(source: &Path, target: &Path) -> io::Result<u64> {
+    if !source.is_dir() {
+        return fs::copy(source, target);
+    }
+    fs::create_dir_all(target)?;
+    let mut copied = 0;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        copied += copy_recursive(&entry.path(), &target.join(entry.file_name()))?;
+    }
+    Ok(copied)
+}
+
+/// Points the app at `target` from the next start on. Passing the default
+/// directory clears the override.
+pub fn set_location(handle: &AppHandle, target: &Path, previous: &Path) -> io::Result<()> {
+    let default = default_dir(handle)?;
+    let mut location = read_location(&default);
+    location.path = (target != default).then(|| target.to_path_buf());
+    location.previous = Some(previous.to_path_buf());
+    write_location(&default, &location)
+}
+
+/// Creates or removes the portable marker. `previous` is cleaned up on the
+/// next start like any other relocation.
+pub fn set_portable(handle: &AppHandle, enabled: bool, previous: &Path) -> io::Result<()> {
+    let marker = executable_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Executable folder not found"))?
+        .join(PORTABLE_MARKER);
+    if enabled {
+        fs::write(&marker, b"")?;
+    } else {
+        remove_if_present(&marker)?;
+    }
+
+    let default = default_dir(handle)?;
+    let mut location = read_location(&default);
+    location.previous = Some(previous.to_path_buf());
+    write_location(&default, &location)
+}
+
+/// Removes the data a relocation left behind. Called once the database has
+/// opened from its new home, so a failed move never loses the original.
+pub fn finish_relocation(handle: &AppHandle) -> io::Result<Option<PathBuf>> {
+    let default = default_dir(handle)?;
+    let mut location = read_location(&default);
+    let Some(previous) = location.previous.take() else {
+        return Ok(None);
+    };
+    write_location(&default, &location)?;
+    if previous == resolve(handle)? {
+        return Ok(None);
+    }
+
+    // The default and portable folders are the app's even without a marker.
+    let portable = executable_dir().map(|dir| dir.join(PORTABLE_DIR));
+    let owned = previous == default || Some(&previous) == portable.as_ref();
+    Ok(remove_owned_data(&previous, owned)?.then_some(previous))
+}
+
+/// Removes the database and [`MANAGED_ENTRIES`] from `dir` if it is `owned`
+/// or carries the marker. Returns whether it did.
+fn remove_owned_data(dir: &Path, owned: bool) -> io::Result<bool> {
+    let marker = dir.join(OWNED_MARKER);
+    if !owned && !marker.exists() {
+        return Ok(false);
+    }
+    for suffix in ["", "-wal", "-shm", "-journal"] {
+        remove_if_present(&dir.join(format!("{DATABASE_FILE}{suffix}")))?;
+    }
+    for name in MANAGED_ENTRIES {
+        remove_if_present(&dir.join(name))?;
+    }
+    remove_if_present(&marker)?;
+    Ok(true)
+}
+
+fn remove_if_present(path: &Path) -> io::Result<()> {
+    let removed = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    match removed {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_must_be_empty_unless_they_are_the_default() {
+        let root = tempfile::tempdir().unwrap();
+        let current = root.path().join("current");
+        let default = root.path().join("default");
+        let target = root.path().join("photos");
+        assert!(validate_target(&current, &target, &default).is_ok());
+
+        fs::create_dir_all(target.join("models")).unwrap();
+        fs::write(target.join("holiday.jpg"), b"jpeg").unwrap();
+        assert_eq!(
+            validate_target(&current, &target, &default),
+            Err("Choose an empty folder".into())
+        );
+
+        fs::create_dir_all(&default).unwrap();
+        fs::write(default.join(LOCATION_FILE), b"{}").unwrap();
+        assert!(validate_target(&current, &default, &default).is_ok());
+        fs::create_dir_all(default.join("backups")).unwrap();
+        assert!(validate_target(&current, &default, &default).is_err());
+    }
+
+    #[test]
+    fn only_folders_the_app_owns_are_cleaned_up() {
+        let root = tempfile::tempdir().unwrap();
+        let user = root.path().join("user");
+        fs::create_dir_all(user.join("models")).unwrap();
+        assert!(!remove_owned_data(&user, false).unwrap());
+        assert!(user.join("models").exists());
+
+        let moved = root.path().join("moved");
+        copy_managed_entries(&user, &moved).unwrap();
+        fs::write(moved.join(DATABASE_FILE), b"db").unwrap();
+        fs::write(moved.join("notes.txt"), b"mine").unwrap();
+        assert!(remove_owned_data(&moved, false).unwrap());
+        assert!(!moved.join("models").exists());
+        assert!(!moved.join(DATABASE_FILE).exists());
+        assert!(!moved.join(OWNED_MARKER).exists());
+        assert!(moved.join("notes.txt").exists());
+    }
+}
@@ -0,0 +1,126 @@
+//! Recently decrypted subjects and snippets, so listing the same messages
+//! again (sender groups, then a message list) does not re-run AES-GCM.
+//! Entries are keyed by account, folder, and UID and only match while the
+//! row's
+//! `updated_at` is unchanged. The cache is bounded by the bytes it holds
+//! and evicts the least recently used entries first.
+
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, HashMap};
+
+/// Roughly 8 MiB of decrypted text.
+pub const DEFAULT_CAPACITY_BYTES: usize = 8 * 1024 * 1024;
+/// Bookkeeping charged per entry on top of the text itself.
+const ENTRY_OVERHEAD: usize = 96;
+
+type Key = (String, String, u32);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decrypted {
+    pub subject: String,
+    /// `None` until a listing that needed the snippet decrypted it.
+    pub snippet: Option<Option<String>>,
+}
+
+impl Decrypted {
+    fn size(&self) -> usize {
+        let snippet = self.snippet.as_ref().and_then(Option::as_ref);
+        self.subject.len() + snippet.map_or(0, String::len)
+    }
+}
+
+struct Entry {
+    updated_at: i64,
+    value: Decrypted,
+    last_used: u64,
+    size: usize,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<Key, Entry>,
+    /// Entries by last use; the first key is the next to evict.
+    recency: BTreeMap<u64, Key>,
+    bytes: usize,
+    clock: u64,
+}
+
+impl Inner {
+    fn remove(&mut self, key: &Key) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+            self.bytes -= entry.size;
+        }
+    }
+}
+
+pub struct DecryptCache {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+impl DecryptCache {
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            capacity: capacity_bytes,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    pub fn get(&self, account: &str, folder: &str, uid: u32, updated_at: i64) -> Option<Decrypted> {
+        let key = (account.to_string(), folder.to_string(), uid);
+        let mut inner = self.inner.lock();
+        inner.clock += 1;
+        let tick = inner.clock;
+        let entry = inner.entries.get_mut(&key)?;
+        if entry.updated_at != updated_at {
+            return None;
+        }
+        let previous = std::mem::replace(&mut entry.last_used, tick);
+        let value = entry.value.clone();
+        inner.recency.remove(&previous);
+        inner.recency.insert(tick, key);
+        Some(value)
+    }
+
+    pub fn insert(&self, account: &str, folder: &str, uid: u32, updated_at: i64, value: Decrypted) {
+        let key = (account.to_string(), folder.to_string(), uid);
+        let size = value.size() + key.0.len() + key.1.len() + ENTRY_OVERHEAD;
+        if size > self.capacity {
+            return;
+        }
+        let mut inner = self.inner.lock();
+        inner.remove(&key);
+        while inner.bytes + size > self.capacity {
+            let Some((_, oldest)) = inner.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = inner.entries.remove(&oldest) {
+                inner.bytes -= entry.size;
+            }
+        }
+        inner.clock += 1;
+        let tick = inner.clock;
+        inner.recency.insert(tick, key.clone());
+        inner.bytes += size;
+        inner.entries.insert(
+            key,
+            Entry {
+                updated_at,
+                value,
+                last_used: tick,
+                size,
+            },
+        );
+    }
+
+    pub fn invalidate(&self, account: &str, folder: &str, uid: u32) {
+        self.inner
+            .lock()
+            .remove(&(account.to_string(), folder.to_string(), uid));
+    }
+
+    pub fn clear(&self) {
+        *self.inner.lock() = Inner::default();
+    }
+}
@@ -0,0 +1,135 @@
+//! DNS lookups over HTTPS. The standard library only resolves addresses,
+//! and MX or TXT records are needed for server discovery and brand checks.
+//! Each lookup tells the resolver which domain is being asked about, so the
+//! ones the app makes on its own are off until the user allows them in
+//! [`DnsSettings`].
+
+use serde::{Deserialize, Serialize};
+
+/// App setting holding the [`DnsSettings`] as JSON.
+pub const SETTING_KEY: &str = "dns";
+
+const DNS_OVER_HTTPS_URL: &str = "https://dns.google/resolve";
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DnsSettings {
+    /// Read senders' DMARC and BIMI records to verify brands.
+    pub brand_checks: bool,
+    /// Read the MX records of an address's domain during server discovery.
+    pub autodiscover_mx: bool,
+}
+
+#[derive(Deserialize)]
+struct DnsResponse {
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DnsAnswer>,
+}
+
+#[derive(Deserialize)]
+struct DnsAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+/// Record types by their DNS number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+    Mx,
+    Txt,
+}
+
+impl RecordType {
+    fn name(self) -> &'static str {
+        match self {
+            RecordType::Mx => "MX",
+            RecordType::Txt => "TXT",
+        }
+    }
+
+    fn code(self) -> u16 {
+        match self {
+            RecordType::Mx => 15,
+            RecordType::Txt => 16,
+        }
+    }
+}
+
+/// The data of each `record_type` record at `name`, as presentation text.
+/// Empty when the name has none; CNAMEs followed on the way are left out.
+pub async fn lookup(
+    client: &reqwest::Client,
+    name: &str,
+    record_type: RecordType,
+) -> Result<Vec<String>, String> {
+    let response = client
+        .get(DNS_OVER_HTTPS_URL)
+        .query(&[("name", name), ("type", record_type.name())])
+        .send()
+        .await
+        .map_err(|err| format!("DNS lookup failed: {err}"))?;
+    let parsed = response
+        .json::<DnsResponse>()
+        .await
+        .map_err(|err| format!("DNS lookup failed: {err}"))?;
+    Ok(parsed
+        .answer
+        .into_iter()
+        .filter(|answer| answer.record_type == record_type.code())
+        .map(|answer| answer.data)
+        .collect())
+}
+
+/// TXT records at `name`, each joined from its quoted strings.
+pub async fn txt_records(client: &reqwest::Client, name: &str) -> Result<Vec<String>, String> {
+    let records = lookup(client, name, RecordType::Txt).await?;
+    Ok(records.iter().map(|data| txt_text(data)).collect())
+}
+
+/// A long TXT record arrives as several quoted strings that form one value.
+fn txt_text(data: &str) -> String {
+    let data = data.trim();
+    if !data.starts_with('"') {
+        return data.to_string();
+    }
+    let mut text = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    for ch in data.chars() {
+        match ch {
+            _ if escaped => {
+                text.push(ch);
+                escaped = false;
+            }
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ if quoted => text.push(ch),
+            _ => {}
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_are_off_unless_allowed() {
+        let settings: DnsSettings = serde_json::from_str("{}").unwrap();
+        assert!(!settings.brand_checks && !settings.autodiscover_mx);
+        let settings: DnsSettings = serde_json::from_str(r#"{"brandChecks":true}"#).unwrap();
+        assert!(settings.brand_checks && !settings.autodiscover_mx);
+    }
+
+    #[test]
+    fn joins_quoted_txt_strings() {
+        assert_eq!(
+            txt_text(r#""v=BIMI1; l=https://example.com/" "logo.svg; a=""#),
+            "v=BIMI1; l=https://example.com/logo.svg; a="
+        );
+        assert_eq!(txt_text(r#""say \"hi\"""#), r#"say "hi""#);
+        assert_eq!(txt_text("v=DMARC1; p=reject"), "v=DMARC1; p=reject");
+    }
+}
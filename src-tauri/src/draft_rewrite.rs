@@ -0,0 +1,230 @@
+//! Rewriting a draft through the LLM: more formal, shorter, friendlier, or
+//! with grammar fixed. The result comes back with a word-level diff so the
+//! UI can show what the model changed before the user accepts it.
+
+use serde::{Deserialize, Serialize};
+
+/// Longest draft, in characters, sent for rewriting.
+pub const MAX_DRAFT_CHARS: usize = 6000;
+/// Word-level diffs above this many tokens per side fall back to replacing
+/// the whole text, keeping the comparison table small.
+const MAX_DIFF_TOKENS: usize = 3000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RewriteInstruction {
+    MoreFormal,
+    Shorter,
+    Friendlier,
+    FixGrammar,
+}
+
+impl RewriteInstruction {
+    fn describe(self) -> &'static str {
+        match self {
+            RewriteInstruction::MoreFormal => {
+                "Make it more formal and professional without changing what it says."
+            }
+            RewriteInstruction::Shorter => {
+                "Make it noticeably shorter while keeping every request, date, and number."
+            }
+            RewriteInstruction::Friendlier => {
+                "Make it warmer and friendlier without changing what it says."
+            }
+            RewriteInstruction::FixGrammar => {
+                "Fix spelling, grammar, and punctuation only. Keep the wording otherwise."
+            }
+        }
+    }
+
+    /// Completion budget for a draft of `chars` characters. Shortening
+    /// never needs more room than the draft itself.
+    pub fn max_tokens(self, chars: usize) -> usize {
+        let draft_tokens = chars / 3 + 32;
+        match self {
+            RewriteInstruction::Shorter => draft_tokens,
+            _ => draft_tokens + draft_tokens / 4,
+        }
+        .min(2048)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DraftRewrite {
+    pub instruction: RewriteInstruction,
+    pub rewritten: String,
+    /// Equal and deleted chunks spell out the original; equal and inserted
+    /// chunks spell out the rewrite.
+    pub changes: Vec<Change>,
+}
+
+/// Checks the draft's length; returns it trimmed.
+pub fn validate(text: &str) -> Result<&str, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("The draft is empty".into());
+    }
+    if text.chars().count() > MAX_DRAFT_CHARS {
+        return Err(format!(
+            "Drafts over {MAX_DRAFT_CHARS} characters are too long to rewrite"
+        ));
+    }
+    Ok(text)
+}
+
+pub fn prompt(text: &str, instruction: RewriteInstruction) -> String {
+    format!(
+        "Rewrite the email draft between the markers. {}\n\
+         Reply with the rewritten draft only, no preamble or notes.\n\
+         <<<DRAFT\n{text}\nDRAFT>>>\n",
+        instruction.describe()
+    )
+}
+
+/// The rewrite with the model's preamble, markers, and code fences removed.
+pub fn clean_output(raw: &str) -> Option<String> {
+    let mut text = raw.trim();
+    if let Some((first, rest)) = text.split_once('\n') {
+        let first = first.trim().to_lowercase();
+        if first.starts_with("here is") || first.starts_with("here's") || first.starts_with("```") {
+            text = rest.trim();
+        }
+    }
+    let text = text
+        .trim_start_matches("<<<DRAFT")
+        .trim_end_matches("DRAFT>>>")
+        .trim_end_matches("```")
+        .trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+pub fn rewrite(original: &str, rewritten: String, instruction: RewriteInstruction) -> DraftRewrite {
+    DraftRewrite {
+        instruction,
+        changes: diff(original, &rewritten),
+        rewritten,
+    }
+}
+
+/// A word-level diff, from the longest common subsequence of words and
+/// the whitespace between them.
+pub fn diff(original: &str, rewritten: &str) -> Vec<Change> {
+    let old = tokens(original);
+    let new = tokens(rewritten);
+    if old.len() > MAX_DIFF_TOKENS || new.len() > MAX_DIFF_TOKENS {
+        let mut changes = Vec::new();
+        push(&mut changes, ChangeKind::Delete, original);
+        push(&mut changes, ChangeKind::Insert, rewritten);
+        return changes;
+    }
+
+    // lengths[i][j]: common subsequence length of old[i..] and new[j..].
+    let mut lengths = vec![vec![0u16; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            push(&mut changes, ChangeKind::Equal, old[i]);
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            push(&mut changes, ChangeKind::Delete, old[i]);
+            i += 1;
+        } else {
+            push(&mut changes, ChangeKind::Insert, new[j]);
+            j += 1;
+        }
+    }
+    changes
+}
+
+/// Runs of whitespace and of everything else, in order.
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+    for (index, ch) in text.char_indices() {
+        let space = ch.is_whitespace();
+        if in_space.is_some_and(|previous| previous != space) {
+            tokens.push(&text[start..index]);
+            start = index;
+        }
+        in_space = Some(space);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+fn push(changes: &mut Vec<Change>, kind: ChangeKind, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match changes.last_mut() {
+        Some(last) if last.kind == kind => last.text.push_str(text),
+        _ => changes.push(Change {
+            kind,
+            text: text.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_words_and_reassembles_both_sides() {
+        let original = "hey can you send the report today";
+        let rewritten = "Hello, could you send the report today?";
+        let changes = diff(original, rewritten);
+        let side = |skip: ChangeKind| {
+            changes
+                .iter()
+                .filter(|change| change.kind != skip)
+                .map(|change| change.text.as_str())
+                .collect::<String>()
+        };
+        assert_eq!(side(ChangeKind::Insert), original);
+        assert_eq!(side(ChangeKind::Delete), rewritten);
+        assert!(changes.contains(&Change {
+            kind: ChangeKind::Equal,
+            text: " you send the report ".into(),
+        }));
+    }
+
+    #[test]
+    fn strips_model_preamble() {
+        assert_eq!(
+            clean_output("Here is the rewritten draft:\nDear Ann,\nThanks.\n"),
+            Some("Dear Ann,\nThanks.".into())
+        );
+        assert_eq!(clean_output("  "), None);
+        assert!(validate(&"x".repeat(MAX_DRAFT_CHARS + 1)).is_err());
+    }
+}
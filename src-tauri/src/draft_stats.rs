@@ -0,0 +1,281 @@
+//! Quick statistics for a draft being composed: counts, reading time, a
+//! Flesch-Kincaid grade, a rough tone, and phrases the user asked to be
+//! warned about. Everything is plain string work so the UI can call it on
+//! every pause in typing.
+
+use serde::{Deserialize, Serialize};
+
+/// App setting holding the [`DraftCheckSettings`] as JSON.
+pub const SETTING_KEY: &str = "draft_checks";
+/// Average silent reading speed for English prose.
+const WORDS_PER_MINUTE: usize = 238;
+const MAX_RISKY_PHRASES: usize = 200;
+
+const DEFAULT_RISKY_PHRASES: &[&str] = &[
+    "as per my last email",
+    "per my last email",
+    "i guarantee",
+    "100% guaranteed",
+    "off the record",
+    "delete this email",
+    "don't tell",
+    "not my problem",
+    "wire the money",
+    "legally binding",
+];
+
+const FORMAL_WORDS: &[&str] = &[
+    "regards",
+    "sincerely",
+    "furthermore",
+    "therefore",
+    "kindly",
+    "pursuant",
+    "herewith",
+    "accordingly",
+    "respectfully",
+    "dear",
+];
+const FRIENDLY_WORDS: &[&str] = &[
+    "thanks", "thank", "cheers", "great", "awesome", "glad", "happy", "hi", "hey", "love",
+];
+const URGENT_WORDS: &[&str] = &[
+    "urgent",
+    "asap",
+    "immediately",
+    "deadline",
+    "today",
+    "now",
+    "critical",
+];
+const NEGATIVE_WORDS: &[&str] = &[
+    "unacceptable",
+    "disappointed",
+    "frustrated",
+    "failed",
+    "wrong",
+    "complaint",
+    "never",
+    "annoyed",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DraftCheckSettings {
+    /// Phrases flagged in drafts, matched case-insensitively.
+    pub risky_phrases: Vec<String>,
+}
+
+impl Default for DraftCheckSettings {
+    fn default() -> Self {
+        Self {
+            risky_phrases: DEFAULT_RISKY_PHRASES
+                .iter()
+                .map(|phrase| phrase.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl DraftCheckSettings {
+    /// Lowercases, trims, and de-duplicates the phrases.
+    pub fn normalized(mut self) -> Result<Self, String> {
+        let mut phrases = self
+            .risky_phrases
+            .iter()
+            .map(|phrase| phrase.split_whitespace().collect::<Vec<_>>().join(" "))
+            .map(|phrase| phrase.to_lowercase())
+            .filter(|phrase| !phrase.is_empty())
+            .collect::<Vec<_>>();
+        phrases.sort();
+        phrases.dedup();
+        if phrases.len() > MAX_RISKY_PHRASES {
+            return Err(format!(
+                "At most {MAX_RISKY_PHRASES} risky phrases can be set"
+            ));
+        }
+        self.risky_phrases = phrases;
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tone {
+    Neutral,
+    Formal,
+    Friendly,
+    Urgent,
+    Negative,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FlaggedPhrase {
+    pub phrase: String,
+    /// Character offsets into the draft, end exclusive.
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DraftStats {
+    pub word_count: usize,
+    pub char_count: usize,
+    pub sentence_count: usize,
+    pub reading_time_secs: u64,
+    /// Flesch-Kincaid grade level; `None` for drafts without words.
+    pub readability_grade: Option<f64>,
+    pub tone: Tone,
+    pub flagged: Vec<FlaggedPhrase>,
+}
+
+pub fn analyze(text: &str, settings: &DraftCheckSettings) -> DraftStats {
+    let words = text
+        .split_whitespace()
+        .map(|word| word.trim_matches(|ch: char| !ch.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>();
+    let sentence_count = text
+        .split(['.', '!', '?'])
+        .filter(|sentence| sentence.chars().any(char::is_alphanumeric))
+        .count();
+
+    let readability_grade = (!words.is_empty()).then(|| {
+        let syllables: usize = words.iter().map(|word| syllables(word)).sum();
+        let words_per_sentence = words.len() as f64 / sentence_count.max(1) as f64;
+        let syllables_per_word = syllables as f64 / words.len() as f64;
+        let grade = 0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59;
+        (grade.max(0.0) * 10.0).round() / 10.0
+    });
+
+    DraftStats {
+        word_count: words.len(),
+        char_count: text.chars().count(),
+        sentence_count,
+        reading_time_secs: (words.len() * 60).div_ceil(WORDS_PER_MINUTE) as u64,
+        readability_grade,
+        tone: tone(text, &words),
+        flagged: flag_phrases(text, &settings.risky_phrases),
+    }
+}
+
+fn tone(text: &str, words: &[&str]) -> Tone {
+    let lowered = words
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>();
+    let count = |list: &[&str]| {
+        lowered
+            .iter()
+            .filter(|word| list.contains(&word.as_str()))
+            .count()
+    };
+    let shouted = words
+        .iter()
+        .filter(|word| word.len() > 2 && word.chars().all(|ch| ch.is_uppercase()))
+        .count();
+    let exclamations = text.matches('!').count();
+
+    let negative = count(NEGATIVE_WORDS) + shouted;
+    let urgent = count(URGENT_WORDS) + exclamations.saturating_sub(1);
+    let formal = count(FORMAL_WORDS);
+    let friendly = count(FRIENDLY_WORDS) + exclamations.min(1);
+    let best = [
+        (negative, Tone::Negative),
+        (urgent, Tone::Urgent),
+        (formal, Tone::Formal),
+        (friendly, Tone::Friendly),
+    ]
+    .into_iter()
+    .max_by_key(|(score, _)| *score)
+    .filter(|(score, _)| *score >= 2 || (*score == 1 && words.len() < 40));
+    best.map_or(Tone::Neutral, |(_, tone)| tone)
+}
+
+/// Occurrences of each phrase, by character offset, in draft order.
+fn flag_phrases(text: &str, phrases: &[String]) -> Vec<FlaggedPhrase> {
+    // Offsets are taken in a lowercase copy; characters whose lowercase
+    // form changes length would skew them, so those fall back to no match.
+    let lowered = text.to_lowercase();
+    if lowered.len() != text.len() {
+        return Vec::new();
+    }
+    let mut flagged = Vec::new();
+    for phrase in phrases.iter().filter(|phrase| !phrase.is_empty()) {
+        for (byte_start, _) in lowered.match_indices(phrase.as_str()) {
+            let byte_end = byte_start + phrase.len();
+            let bounded = |ch: Option<char>| !matches!(ch, Some(ch) if ch.is_alphanumeric());
+            if !bounded(lowered[..byte_start].chars().next_back())
+                || !bounded(lowered[byte_end..].chars().next())
+            {
+                continue;
+            }
+            let start = text[..byte_start].chars().count();
+            flagged.push(FlaggedPhrase {
+                phrase: phrase.clone(),
+                start,
+                end: start + text[byte_start..byte_end].chars().count(),
+            });
+        }
+    }
+    flagged.sort_by_key(|flag| (flag.start, flag.end));
+    flagged
+}
+
+/// Vowel groups, less a silent final `e`; at least one per word.
+fn syllables(word: &str) -> usize {
+    let word = word.to_lowercase();
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for ch in word.chars() {
+        let vowel = matches!(ch, 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+    if word.ends_with('e') && !word.ends_with("le") && count > 1 {
+        count -= 1;
+    }
+    count.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_and_grades_a_draft() {
+        let stats = analyze(
+            "Hi Sam. The cat sat on the mat. Thanks!",
+            &DraftCheckSettings::default(),
+        );
+        assert_eq!(stats.word_count, 9);
+        assert_eq!(stats.sentence_count, 3);
+        assert_eq!(stats.reading_time_secs, 3);
+        assert_eq!(stats.readability_grade, Some(0.0));
+        assert_eq!(stats.tone, Tone::Friendly);
+        assert_eq!(
+            analyze("", &DraftCheckSettings::default()).readability_grade,
+            None
+        );
+    }
+
+    #[test]
+    fn flags_configured_phrases_on_word_boundaries() {
+        let settings = DraftCheckSettings {
+            risky_phrases: vec!["  Per my LAST email ".into(), "now".into()],
+        }
+        .normalized()
+        .unwrap();
+        let stats = analyze("As per my last email, we know.", &settings);
+        assert_eq!(
+            stats.flagged,
+            vec![FlaggedPhrase {
+                phrase: "per my last email".into(),
+                start: 3,
+                end: 20,
+            }]
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! Events sent to the frontend. Every payload is a serde struct here,
+//! serialized in camelCase with a `schemaVersion` field next to its own
+//! fields, so the UI can tell which shape it got and any change to a
+//! payload is a change to this file. Send them with [`emit`].
+
+use crate::models::{uid_option, uid_string, uid_strings};
+use crate::otp::OtpKind;
+use crate::storage::{
+    AccountMigration, FlagConflict, FocusMessage, SenderRule, StorageHealthReport,
+};
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use tracing::warn;
+
+/// Bumped whenever a payload here changes shape.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A payload and the event name it travels under.
+pub trait Event: Serialize {
+    const NAME: &'static str;
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Envelope<'a, E> {
+    schema_version: u32,
+    #[serde(flatten)]
+    payload: &'a E,
+}
+
+/// The JSON the frontend receives for `event`.
+pub fn to_value<E: Event>(event: &E) -> serde_json::Result<Value> {
+    serde_json::to_value(Envelope {
+        schema_version: SCHEMA_VERSION,
+        payload: event,
+    })
+}
+
+/// Sends `event` to every window. A failure is logged rather than
+/// returned: an event nobody hears is no reason to fail the work it reports.
+pub fn emit<E: Event>(app: &AppHandle, event: &E) {
+    let result = to_value(event)
+        .map_err(|err| err.to_string())
+        .and_then(|value| app.emit_all(E::NAME, value).map_err(|err| err.to_string()));
+    if let Err(err) = result {
+        warn!(event = E::NAME, %err, "failed to emit event");
+    }
+}
+
+/// One batch of a full mailbox sync was stored.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncProgress {
+    pub email: String,
+    pub batch: usize,
+    pub total_batches: usize,
+    pub fetched: usize,
+    pub stored: usize,
+    pub elapsed_ms: u64,
+}
+
+impl Event for SyncProgress {
+    const NAME: &'static str = "full-sync-progress";
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkAnalysisStatus {
+    #[default]
+    Starting,
+    Processed,
+    Error,
+    Completed,
+}
+
+/// Progress of a bulk analysis run. The counters are always set; the rest
+/// depends on `status`: `starting` names the run's settings, `processed`
+/// and `error` the message, and `completed` how long the run took.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkAnalysisProgress {
+    pub run_id: String,
+    pub status: BulkAnalysisStatus,
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub pending: usize,
+    pub model_id: Option<String>,
+    pub validator_model_id: Option<String>,
+    pub timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accounts: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub force: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_path: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", with = "uid_option")]
+    pub message_uid: Option<u32>,
+    /// Which step failed: `llm`, `parse`, `normalize`, or `storage`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// `rules` or `llm`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub classifier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<BulkAnalysisResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+impl Event for BulkAnalysisProgress {
+    const NAME: &'static str = "llm-bulk-analysis-progress";
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkAnalysisResult {
+    pub summary: Option<String>,
+    pub sentiment: Option<String>,
+    pub tags: Vec<String>,
+    pub confidence: Option<f64>,
+    pub metadata: Value,
+}
+
+/// Messages queued for deletion on the server.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteDeleteQueued {
+    pub account_email: String,
+    #[serde(with = "uid_strings")]
+    pub uids: Vec<u32>,
+    pub message_keys: Vec<String>,
+}
+
+impl Event for RemoteDeleteQueued {
+    const NAME: &'static str = "remote-delete-queued";
+}
+
+/// Outcomes of one batch of queued server-side deletes.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteDeleteStatus {
+    pub account_email: String,
+    pub updates: Vec<RemoteDeleteUpdate>,
+}
+
+impl Event for RemoteDeleteStatus {
+    const NAME: &'static str = "remote-delete-status";
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteDeleteUpdate {
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub message_key: String,
+    pub remote_deleted_at: Option<i64>,
+    pub remote_error: Option<String>,
+}
+
+/// Throughput of the remote delete queue. Also what
+/// `get_remote_delete_metrics` returns, without the schema version.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteDeleteMetrics {
+    pub account_email: String,
+    pub latest: RemoteDeleteMetricsSnapshot,
+    pub history: Vec<RemoteDeleteMetricsHistoryEntry>,
+}
+
+impl Event for RemoteDeleteMetrics {
+    const NAME: &'static str = "remote-delete-metrics";
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteDeleteMetricsSnapshot {
+    pub account_email: String,
+    pub timestamp: i64,
+    pub mode: String,
+    pub batch_size: usize,
+    pub processed: usize,
+    pub failed: usize,
+    pub pending: usize,
+    pub total_pending: usize,
+    pub rate_per_minute: f64,
+    pub override_mode: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteDeleteMetricsHistoryEntry {
+    pub timestamp: i64,
+    pub processed: usize,
+    pub mode: String,
+    pub pending: usize,
+}
+
+/// A one-time code or password-reset link arrived. Sent before any other
+/// post-sync work so the code can be copied at once; it is erased at
+/// `expires_at`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OtpReceived {
+    pub account_email: String,
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub kind: OtpKind,
+    pub code: Option<String>,
+    pub link: Option<String>,
+    pub sender_email: String,
+    pub subject: String,
+    pub expires_at: i64,
+}
+
+impl Event for OtpReceived {
+    const NAME: &'static str = "otp-received";
+}
+
+/// IDLE saw mail arrive in an account's INBOX. Sent after the incremental
+/// sync it set off, so the cache already holds the new messages unless
+/// `synced` is false.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewMail {
+    pub account_email: String,
+    /// Messages now in INBOX.
+    pub exists: u32,
+    pub synced: bool,
+}
+
+impl Event for NewMail {
+    const NAME: &'static str = "new-mail";
+}
+
+/// Cached INBOX messages the server no longer has, as when another client
+/// deleted them. The UI removes them by `message_keys`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagesRemoved {
+    pub account_email: String,
+    #[serde(with = "uid_strings")]
+    pub uids: Vec<u32>,
+    pub message_keys: Vec<String>,
+}
+
+impl Event for MessagesRemoved {
+    const NAME: &'static str = "messages-removed";
+}
+
+/// A change to cached messages: shown before the server confirmed it as
+/// [`LocalUpdateApplied`], or undone because the server refused it as
+/// [`LocalUpdateRolledBack`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalUpdate {
+    pub account_email: String,
+    /// `set_flags`, `clear_flags`, `mute_thread`, or a bulk action name.
+    pub action: String,
+    #[serde(with = "uid_strings")]
+    pub uids: Vec<u32>,
+    /// The flags (space-separated) or folder the change names.
+    pub target: Option<String>,
+    /// Why the server refused the change; only set on rollback.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct LocalUpdateApplied(pub LocalUpdate);
+
+impl Event for LocalUpdateApplied {
+    const NAME: &'static str = "local-update-applied";
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct LocalUpdateRolledBack(pub LocalUpdate);
+
+impl Event for LocalUpdateRolledBack {
+    const NAME: &'static str = "local-update-rolled-back";
+}
+
+/// Flag edits that met a change made on another client, and which side
+/// kept each one.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlagConflicts {
+    pub account_email: String,
+    pub conflicts: Vec<FlagConflict>,
+}
+
+impl Event for FlagConflicts {
+    const NAME: &'static str = "flag-conflicts";
+}
+
+/// Urgent mail let through focus mode as it arrived.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusAlert {
+    pub account_email: String,
+    pub messages: Vec<FocusMessage>,
+}
+
+impl Event for FocusAlert {
+    const NAME: &'static str = "focus-alert";
+}
+
+/// The mail focus mode held back, sent when a review time passes.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusReview {
+    pub messages: Vec<FocusMessage>,
+}
+
+impl Event for FocusReview {
+    const NAME: &'static str = "focus-review";
+}
+
+/// Senders whose mute ran out, with the status they went back to.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SenderMutesExpired {
+    pub rules: Vec<SenderRule>,
+}
+
+impl Event for SenderMutesExpired {
+    const NAME: &'static str = "sender-mutes-expired";
+}
+
+/// Bytes of a model download so far; `progress` is a percentage.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelDownloadProgress {
+    pub model_id: String,
+    pub downloaded: u64,
+    pub total: u64,
+    pub progress: u32,
+    pub bytes_per_sec: u64,
+    pub eta_secs: Option<u64>,
+}
+
+impl Event for ModelDownloadProgress {
+    const NAME: &'static str = "model-download-progress";
+}
+
+/// The startup integrity check finished.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageHealth {
+    pub report: StorageHealthReport,
+}
+
+impl Event for StorageHealth {
+    const NAME: &'static str = "storage-health";
+}
+
+/// One step of wiping local data: `stopping-sync`, `keychain`,
+/// `database`, `models`, then `done`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WipeProgress {
+    pub step: String,
+    pub detail: Value,
+}
+
+impl Event for WipeProgress {
+    const NAME: &'static str = "wipe-progress";
+}
+
+/// A folder copy between accounts saved a checkpoint.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationProgress {
+    pub migration: AccountMigration,
+}
+
+impl Event for MigrationProgress {
+    const NAME: &'static str = "account-migration-progress";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn payloads_carry_the_schema_version_in_camel_case() {
+        let event = RemoteDeleteStatus {
+            account_email: "me@example.com".into(),
+            updates: vec![RemoteDeleteUpdate {
+                uid: 7,
+                message_key: "k7".into(),
+                remote_deleted_at: Some(1_700_000_000),
+                remote_error: None,
+            }],
+        };
+        assert_eq!(
+            to_value(&event).unwrap(),
+            json!({
+                "schemaVersion": SCHEMA_VERSION,
+                "accountEmail": "me@example.com",
+                "updates": [{
+                    "uid": "7",
+                    "messageKey": "k7",
+                    "remoteDeletedAt": 1_700_000_000,
+                    "remoteError": null,
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn bulk_progress_omits_fields_its_status_does_not_use() {
+        let event = BulkAnalysisProgress {
+            run_id: "run".into(),
+            status: BulkAnalysisStatus::Completed,
+            duration_ms: Some(12),
+            ..Default::default()
+        };
+        let value = to_value(&event).unwrap();
+        assert_eq!(value["status"], "completed");
+        assert_eq!(value["durationMs"], 12);
+        assert!(value.get("messageUid").is_none());
+        assert!(value["modelId"].is_null());
+    }
+
+    #[test]
+    fn local_updates_share_one_shape_under_two_names() {
+        let update = LocalUpdate {
+            account_email: "me@example.com".into(),
+            action: "move".into(),
+            uids: vec![3, 4],
+            target: Some("Archive".into()),
+            error: Some("refused".into()),
+        };
+        assert_eq!(LocalUpdateApplied::NAME, "local-update-applied");
+        assert_eq!(
+            to_value(&LocalUpdateRolledBack(update)).unwrap(),
+            json!({
+                "schemaVersion": SCHEMA_VERSION,
+                "accountEmail": "me@example.com",
+                "action": "move",
+                "uids": ["3", "4"],
+                "target": "Archive",
+                "error": "refused",
+            })
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Reconciling flag edits made here with the flags the server reports.
+//! Edits are applied to the cache at once and wait in
+//! `pending_flag_changes` until they reach the server. If a sync finds that
+//! another client changed the message's flags in the meantime and the server
+//! disagrees with an edit, the policy for that flag picks the winner and the
+//! conflict is recorded instead of being silently overwritten.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// App setting holding the [`FlagPolicies`] as JSON.
+pub const SETTING_KEY: &str = "flag_conflict_policies";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    #[default]
+    ServerWins,
+    LocalWins,
+    NewestWins,
+}
+
+impl ConflictPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConflictPolicy::ServerWins => "server-wins",
+            ConflictPolicy::LocalWins => "local-wins",
+            ConflictPolicy::NewestWins => "newest-wins",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "server-wins" => Some(ConflictPolicy::ServerWins),
+            "local-wins" => Some(ConflictPolicy::LocalWins),
+            "newest-wins" => Some(ConflictPolicy::NewestWins),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FlagPolicies {
+    /// Used for flags without their own entry.
+    pub default: ConflictPolicy,
+    /// Per flag, keyed by its [`flag_key`] (`seen`, `$Important`, ...).
+    pub flags: BTreeMap<String, ConflictPolicy>,
+}
+
+impl FlagPolicies {
+    pub fn policy_for(&self, flag: &str) -> ConflictPolicy {
+        let flag = flag_key(flag);
+        self.flags
+            .iter()
+            .find(|(name, _)| same_flag(name, &flag))
+            .map_or(self.default, |(_, policy)| *policy)
+    }
+}
+
+/// The cached form of a flag, whichever spelling it came in: system flags
+/// lowercase without the backslash (`\Seen` is `seen`), keywords as they
+/// are. Pending changes, cached flags, and policies all use it.
+pub fn flag_key(flag: &str) -> String {
+    let flag = flag.trim();
+    let system = flag.trim_start_matches('\\').to_lowercase();
+    match system.as_str() {
+        "seen" | "answered" | "flagged" | "deleted" | "draft" => system,
+        _ => flag.to_string(),
+    }
+}
+
+/// Whether two flags name the same one; IMAP flag names ignore case.
+fn same_flag(left: &str, right: &str) -> bool {
+    flag_key(left).eq_ignore_ascii_case(&flag_key(right))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Winner {
+    Local,
+    Server,
+}
+
+impl Winner {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Winner::Local => "local",
+            Winner::Server => "server",
+        }
+    }
+}
+
+/// Picks the side that keeps a conflicting flag. `refreshed_at` is when the
+/// cached flags last matched the server and `observed_at` when the change
+/// was seen. IMAP does not say when a flag changed, so newest-wins dates the
+/// server's change to the middle of that window.
+pub fn resolve(
+    policy: ConflictPolicy,
+    local_changed_at: i64,
+    refreshed_at: i64,
+    observed_at: i64,
+) -> Winner {
+    match policy {
+        ConflictPolicy::ServerWins => Winner::Server,
+        ConflictPolicy::LocalWins => Winner::Local,
+        ConflictPolicy::NewestWins => {
+            let server_changed_at = refreshed_at + (observed_at - refreshed_at) / 2;
+            if local_changed_at >= server_changed_at {
+                Winner::Local
+            } else {
+                Winner::Server
+            }
+        }
+    }
+}
+
+/// Whether a space-separated flag list contains `flag`, in any spelling.
+pub fn has_flag(flags: Option<&str>, flag: &str) -> bool {
+    flags
+        .unwrap_or_default()
+        .split_whitespace()
+        .any(|value| same_flag(value, flag))
+}
+
+/// The flag list with `flag` added or removed; `None` when nothing is left.
+pub fn with_flag(flags: Option<&str>, flag: &str, enabled: bool) -> Option<String> {
+    let mut values = flags
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter(|value| !same_flag(value, flag))
+        .map(flag_key)
+        .collect::<Vec<_>>();
+    if enabled {
+        values.push(flag_key(flag));
+    }
+    (!values.is_empty()).then(|| values.join(" "))
+}
+
+/// Whether two flag lists hold the same flags, in any order or spelling.
+pub fn same_flags(left: Option<&str>, right: Option<&str>) -> bool {
+    let set = |flags: Option<&str>| {
+        flags
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|flag| flag_key(flag).to_lowercase())
+            .collect::<BTreeSet<_>>()
+    };
+    set(left) == set(right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_and_cached_spellings_are_the_same_flag() {
+        let policies = FlagPolicies {
+            default: ConflictPolicy::ServerWins,
+            flags: BTreeMap::from([
+                ("\\Seen".to_string(), ConflictPolicy::LocalWins),
+                ("$Important".to_string(), ConflictPolicy::NewestWins),
+            ]),
+        };
+        assert_eq!(policies.policy_for("seen"), ConflictPolicy::LocalWins);
+        assert_eq!(
+            policies.policy_for("$important"),
+            ConflictPolicy::NewestWins
+        );
+        assert_eq!(policies.policy_for("flagged"), ConflictPolicy::ServerWins);
+
+        assert!(has_flag(Some("seen flagged"), "\\Seen"));
+        assert_eq!(
+            with_flag(Some("seen \\Flagged"), "\\Seen", false).as_deref(),
+            Some("flagged")
+        );
+        assert_eq!(
+            with_flag(Some("flagged"), "\\Seen", true).as_deref(),
+            Some("flagged seen")
+        );
+        assert!(same_flags(Some("\\Seen $Work"), Some("$work seen")));
+    }
+}
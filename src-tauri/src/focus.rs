@@ -0,0 +1,207 @@
+//! Focus mode. During the configured hours only urgent mail (from a VIP, of
+//! high priority, or addressed straight to the user) raises a notification
+//! and shows in the focused listing; the rest is held for the next scheduled
+//! review, which reports it in one batch. Mail from a noisy sender (see
+//! [`crate::noise`]) gets through only from a VIP. Times are local.
+
+use crate::classifier;
+use crate::models::EmailSummary;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// App setting holding the [`FocusSettings`] as JSON.
+pub const SETTING_KEY: &str = "focus_mode";
+/// App setting holding when held mail was last reviewed (Unix seconds).
+pub const LAST_REVIEW_KEY: &str = "focus_mode:last_review";
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const PRIORITIES: &[&str] = &["low", "normal", "high", "critical"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FocusSettings {
+    pub enabled: bool,
+    pub windows: Vec<FocusWindow>,
+    /// Addresses or `@domain` suffixes that always get through.
+    pub vip_senders: Vec<String>,
+    /// Priorities that always get through.
+    pub priorities: Vec<String>,
+    /// Let through mail that names the account in `To`.
+    pub direct_to_me: bool,
+    /// Minutes after local midnight at which held mail is reported.
+    pub review_times: Vec<u32>,
+}
+
+impl Default for FocusSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            windows: Vec::new(),
+            vip_senders: Vec::new(),
+            priorities: vec!["high".into(), "critical".into()],
+            direct_to_me: true,
+            review_times: Vec::new(),
+        }
+    }
+}
+
+/// Focus hours on some days of the week. A window that ends before it
+/// starts runs past midnight into the next day.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusWindow {
+    /// 0 is Monday.
+    pub days: Vec<u32>,
+    pub start_minute: u32,
+    pub end_minute: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusReason {
+    Vip,
+    Priority,
+    DirectToMe,
+}
+
+impl FocusReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FocusReason::Vip => "vip",
+            FocusReason::Priority => "priority",
+            FocusReason::DirectToMe => "direct_to_me",
+        }
+    }
+}
+
+impl FocusSettings {
+    /// Lowercases and deduplicates the lists; rejects days, minutes, or
+    /// priorities out of range.
+    pub fn normalized(mut self) -> Result<Self, String> {
+        for window in &mut self.windows {
+            if window.days.iter().any(|day| *day > 6) {
+                return Err("Focus days run from 0 (Monday) to 6 (Sunday)".into());
+            }
+            if window.start_minute >= MINUTES_PER_DAY || window.end_minute >= MINUTES_PER_DAY {
+                return Err("Focus hours must fall within one day".into());
+            }
+            window.days.sort_unstable();
+            window.days.dedup();
+        }
+        if self
+            .review_times
+            .iter()
+            .any(|minute| *minute >= MINUTES_PER_DAY)
+        {
+            return Err("Review times must fall within one day".into());
+        }
+        self.review_times.sort_unstable();
+        self.review_times.dedup();
+
+        self.vip_senders = normalized_list(&self.vip_senders);
+        self.priorities = normalized_list(&self.priorities);
+        if let Some(unknown) = self
+            .priorities
+            .iter()
+            .find(|priority| !PRIORITIES.contains(&priority.as_str()))
+        {
+            return Err(format!("Unknown priority '{unknown}'"));
+        }
+        Ok(self)
+    }
+
+    /// Whether `now` falls in focus hours.
+    pub fn is_active(&self, now: DateTime<Local>) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let today = now.weekday().num_days_from_monday();
+        let yesterday = (today + 6) % 7;
+        let minute = now.hour() * 60 + now.minute();
+        self.windows.iter().any(|window| {
+            let (start, end) = (window.start_minute, window.end_minute);
+            if start <= end {
+                window.days.contains(&today) && minute >= start && minute < end
+            } else {
+                (window.days.contains(&today) && minute >= start)
+                    || (window.days.contains(&yesterday) && minute < end)
+            }
+        })
+    }
+
+    /// Why `message` should get through focus mode, if it should.
+    /// `priority` is the stored analysis priority; without one the rule
+    /// classifier is asked. A `noisy` sender needs to be a VIP.
+    pub fn urgency(
+        &self,
+        account_email: &str,
+        message: &EmailSummary,
+        priority: Option<&str>,
+        noisy: bool,
+    ) -> Option<FocusReason> {
+        let sender = message.sender.email.trim().to_lowercase();
+        let vip = self.vip_senders.iter().any(|entry| {
+            if entry.starts_with('@') {
+                sender.ends_with(entry.as_str())
+            } else {
+                sender == *entry
+            }
+        });
+        if vip {
+            return Some(FocusReason::Vip);
+        }
+        if noisy {
+            return None;
+        }
+
+        let priority = priority.map(str::to_string).or_else(|| {
+            classifier::classify(&sender, &message.subject, None).map(|result| result.priority)
+        });
+        if priority.is_some_and(|priority| self.priorities.contains(&priority)) {
+            return Some(FocusReason::Priority);
+        }
+
+        let direct = message
+            .to
+            .iter()
+            .any(|address| address.eq_ignore_ascii_case(account_email));
+        if self.direct_to_me && direct && !message.auto_submitted {
+            return Some(FocusReason::DirectToMe);
+        }
+        None
+    }
+
+    /// Whether a review time has passed since `last_review_at`.
+    pub fn review_due(&self, last_review_at: i64, now: DateTime<Local>) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let latest = self
+            .review_times
+            .iter()
+            .filter_map(|minute| {
+                let time = NaiveTime::from_hms_opt(minute / 60, minute % 60, 0)?;
+                let today = Local
+                    .from_local_datetime(&now.date_naive().and_time(time))
+                    .earliest()?;
+                Some(if today <= now {
+                    today
+                } else {
+                    today - Duration::days(1)
+                })
+            })
+            .max();
+        latest.is_some_and(|latest| latest.timestamp() > last_review_at)
+    }
+}
+
+fn normalized_list(values: &[String]) -> Vec<String> {
+    let mut values = values
+        .iter()
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>();
+    values.sort_unstable();
+    values.dedup();
+    values
+}
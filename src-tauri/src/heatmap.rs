@@ -0,0 +1,92 @@
+//! When mail arrives: cached INBOX messages counted by local day of week and
+//! hour, overall and per analysis tag, for the inbox heat map chart.
+
+use chrono::{Datelike, Local, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Unix-second bounds on the received date; either side may be open.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeatmapRange {
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
+/// Message counts as seven rows, Monday first, of 24 local hours.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeatmapGrid {
+    pub total: u32,
+    pub counts: Vec<[u32; 24]>,
+}
+
+impl HeatmapGrid {
+    fn new() -> Self {
+        Self {
+            total: 0,
+            counts: vec![[0; 24]; 7],
+        }
+    }
+
+    fn add(&mut self, weekday: usize, hour: usize) {
+        self.total += 1;
+        self.counts[weekday][hour] += 1;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TagHeatmap {
+    pub tag: String,
+    #[serde(flatten)]
+    pub grid: HeatmapGrid,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InboxHeatmap {
+    pub account_email: String,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub overall: HeatmapGrid,
+    /// One grid per tag, busiest first. Untagged mail only counts overall.
+    pub tags: Vec<TagHeatmap>,
+}
+
+/// Builds the heat map from `(received_at, tags)` pairs.
+pub fn build(
+    account_email: &str,
+    range: HeatmapRange,
+    samples: &[(i64, Vec<String>)],
+) -> InboxHeatmap {
+    let mut overall = HeatmapGrid::new();
+    let mut tags: HashMap<&str, HeatmapGrid> = HashMap::new();
+    for (received_at, message_tags) in samples {
+        let Some(date) = Local.timestamp_opt(*received_at, 0).single() else {
+            continue;
+        };
+        let weekday = date.weekday().num_days_from_monday() as usize;
+        let hour = date.hour() as usize;
+        overall.add(weekday, hour);
+        for tag in message_tags {
+            tags.entry(tag.as_str())
+                .or_insert_with(HeatmapGrid::new)
+                .add(weekday, hour);
+        }
+    }
+
+    let mut tags = tags
+        .into_iter()
+        .map(|(tag, grid)| TagHeatmap {
+            tag: tag.to_string(),
+            grid,
+        })
+        .collect::<Vec<_>>();
+    tags.sort_by(|a, b| b.grid.total.cmp(&a.grid.total).then(a.tag.cmp(&b.tag)));
+
+    InboxHeatmap {
+        account_email: account_email.to_string(),
+        since: range.since,
+        until: range.until,
+        overall,
+        tags,
+    }
+}
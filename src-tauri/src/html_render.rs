@@ -0,0 +1,312 @@
+//! How message HTML is prepared for display. Active content is always
+//! dropped; trackers and remote resources are handled according to a
+//! [`RenderPolicy`]. Blocked resources keep their URL in a `data-blocked-*`
+//! attribute and are listed in the result, so the UI can load them later
+//! (directly or through a proxy) once the user allows it. Links can be
+//! disabled the same way, their targets kept in `data-disabled-*`. Images
+//! the message carries itself (`cid:` URLs) are inlined as `data:` URIs.
+
+use crate::attachments::InlineImage;
+use crate::links;
+use crate::trackers;
+use base64::{engine::general_purpose, Engine as _};
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::Serialize;
+
+static ACTIVE_CONTENT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(?:script|iframe|object)\b.*?</(?:script|iframe|object)\s*>|<embed\b[^>]*>")
+        .expect("active content pattern is valid")
+});
+static IMG_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<img\b[^>]*>").expect("img tag pattern is valid"));
+static IMG_SOURCE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)(\s)(src|srcset)(\s*=\s*)("[^"]*"|'[^']*'|[^\s>]+)"#)
+        .expect("img source pattern is valid")
+});
+static BACKGROUND_ATTR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)(\s)background(\s*=\s*)("[^"]*"|'[^']*'|[^\s>]+)"#)
+        .expect("background attribute pattern is valid")
+});
+static CSS_URL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)url\(\s*(?:"([^"]*)"|'([^']*)'|([^)\s]*))\s*\)"#)
+        .expect("css url pattern is valid")
+});
+static LINK_TARGET_TAG: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(?:a|area|form)\b[^>]*>").expect("link target tag pattern is valid")
+});
+static LINK_TARGET: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)(\s)(href|action)(\s*=)").expect("link target pattern is valid")
+});
+static LINK_TAG: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<link\b[^>]*?\bhref\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))[^>]*>"#)
+        .expect("link tag pattern is valid")
+});
+/// A `cid:` URL in an attribute or a CSS `url()`.
+static CID_URL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)((?:=|\()\s*["']?)cid:([^"'\s)>]+)"#).expect("cid url pattern is valid")
+});
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderPolicy {
+    pub strip_trackers: bool,
+    pub block_remote_images: bool,
+    /// Keeps links and form targets from being followed.
+    pub disable_links: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceKind {
+    Image,
+    Background,
+    Stylesheet,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockedResource {
+    pub kind: ResourceKind,
+    pub url: String,
+    pub domain: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RenderedHtml {
+    pub html: String,
+    pub trackers_removed: usize,
+    pub links_rewritten: usize,
+    pub links_disabled: usize,
+    pub blocked_resources: Vec<BlockedResource>,
+    /// `cid:` references shown from the message's own parts.
+    pub inline_images: usize,
+    /// `cid:` references with no matching part.
+    pub missing_inline_images: usize,
+}
+
+pub fn render(html: &str, policy: &RenderPolicy) -> RenderedHtml {
+    let mut rendered = RenderedHtml {
+        html: ACTIVE_CONTENT.replace_all(html, "").into_owned(),
+        ..RenderedHtml::default()
+    };
+    // Trackers go first so pixels are removed outright rather than being
+    // offered back as blocked images.
+    if policy.strip_trackers {
+        let cleaned = trackers::strip(&rendered.html);
+        rendered.html = cleaned.html;
+        rendered.trackers_removed = cleaned.trackers_removed;
+        rendered.links_rewritten = cleaned.links_rewritten;
+    }
+    if policy.block_remote_images {
+        let mut blocked = Vec::new();
+        rendered.html = block_remote_resources(&rendered.html, &mut blocked);
+        rendered.blocked_resources = blocked;
+    }
+    if policy.disable_links {
+        let (html, disabled) = disable_links(&rendered.html);
+        rendered.html = html;
+        rendered.links_disabled = disabled;
+    }
+    rendered
+}
+
+/// Whether `html` refers to images by `cid:` URL.
+pub fn has_inline_images(html: &str) -> bool {
+    CID_URL.is_match(html)
+}
+
+/// Points `cid:` references at `data:` URIs of the matching images, so they
+/// show without anything being fetched.
+pub fn resolve_inline_images(rendered: &mut RenderedHtml, images: &[InlineImage]) {
+    let mut resolved = 0;
+    let mut missing = 0;
+    let html = CID_URL.replace_all(&rendered.html, |reference: &Captures| {
+        let wanted = percent_decode(&reference[2]);
+        let image = images
+            .iter()
+            .find(|image| image.content_id.eq_ignore_ascii_case(&wanted));
+        let Some(image) = image else {
+            missing += 1;
+            return reference[0].to_string();
+        };
+        resolved += 1;
+        format!(
+            "{}data:{};base64,{}",
+            &reference[1],
+            image.content_type,
+            general_purpose::STANDARD.encode(&image.data)
+        )
+    });
+    rendered.html = html.into_owned();
+    rendered.inline_images = resolved;
+    rendered.missing_inline_images = missing;
+}
+
+/// `cid:` URLs escape the Content-ID as URLs do (RFC 2392).
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| bytes.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn disable_links(html: &str) -> (String, usize) {
+    let mut disabled = 0;
+    let html = LINK_TARGET_TAG
+        .replace_all(html, |tag: &Captures| {
+            LINK_TARGET
+                .replace_all(&tag[0], |target: &Captures| {
+                    disabled += 1;
+                    format!("{}data-disabled-{}{}", &target[1], &target[2], &target[3])
+                })
+                .into_owned()
+        })
+        .into_owned();
+    (html, disabled)
+}
+
+fn block_remote_resources(html: &str, blocked: &mut Vec<BlockedResource>) -> String {
+    let html = IMG_TAG.replace_all(html, |tag: &Captures| {
+        IMG_SOURCE
+            .replace_all(&tag[0], |source: &Captures| {
+                let value = unquote(&source[4]);
+                // `srcset` lists candidates as "url width, url width".
+                let remote = value
+                    .split(',')
+                    .filter_map(|candidate| candidate.split_whitespace().next())
+                    .filter(|url| is_remote(url))
+                    .collect::<Vec<_>>();
+                if remote.is_empty() {
+                    return source[0].to_string();
+                }
+                for url in remote {
+                    record(blocked, ResourceKind::Image, url);
+                }
+                format!(
+                    "{}data-blocked-{}{}{}",
+                    &source[1], &source[2], &source[3], &source[4]
+                )
+            })
+            .into_owned()
+    });
+
+    let html = BACKGROUND_ATTR.replace_all(&html, |attr: &Captures| {
+        let value = unquote(&attr[3]);
+        if !is_remote(value) {
+            return attr[0].to_string();
+        }
+        record(blocked, ResourceKind::Background, value);
+        format!(
+            "{}data-blocked-background{}{}",
+            &attr[1], &attr[2], &attr[3]
+        )
+    });
+
+    let html = CSS_URL.replace_all(&html, |css: &Captures| {
+        let value = first_capture(css);
+        if !is_remote(value) {
+            return css[0].to_string();
+        }
+        record(blocked, ResourceKind::Background, value);
+        "url()".to_string()
+    });
+
+    LINK_TAG
+        .replace_all(&html, |link: &Captures| {
+            let value = first_capture(link);
+            if !is_remote(value) {
+                return link[0].to_string();
+            }
+            record(blocked, ResourceKind::Stylesheet, value);
+            String::new()
+        })
+        .into_owned()
+}
+
+fn record(blocked: &mut Vec<BlockedResource>, kind: ResourceKind, url: &str) {
+    let url = url.replace("&amp;", "&");
+    if blocked.iter().any(|resource| resource.url == url) {
+        return;
+    }
+    let absolute = if url.starts_with("//") {
+        format!("https:{url}")
+    } else {
+        url.clone()
+    };
+    blocked.push(BlockedResource {
+        kind,
+        domain: links::url_domain(&absolute),
+        url,
+    });
+}
+
+fn is_remote(url: &str) -> bool {
+    let lowered = url.trim().to_lowercase();
+    lowered.starts_with("http://") || lowered.starts_with("https://") || lowered.starts_with("//")
+}
+
+fn unquote(value: &str) -> &str {
+    value.trim_matches(|c| c == '"' || c == '\'').trim()
+}
+
+fn first_capture<'a>(captures: &Captures<'a>) -> &'a str {
+    captures
+        .iter()
+        .skip(1)
+        .flatten()
+        .next()
+        .map_or("", |value| value.as_str().trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inlines_images_the_message_carries() {
+        let mut rendered = render(
+            r#"<img src="cid:logo%40x"><td style="background:url(cid:bg)"><img src=cid:x>"#,
+            &RenderPolicy {
+                block_remote_images: true,
+                ..RenderPolicy::default()
+            },
+        );
+        assert!(has_inline_images(&rendered.html));
+        let images = [
+            InlineImage {
+                content_id: "Logo@x".into(),
+                content_type: "image/png".into(),
+                data: b"png".to_vec(),
+            },
+            InlineImage {
+                content_id: "bg".into(),
+                content_type: "image/gif".into(),
+                data: b"gif".to_vec(),
+            },
+        ];
+        resolve_inline_images(&mut rendered, &images);
+        let html = &rendered.html;
+        assert!(html.starts_with(r#"<img src="data:image/png;base64,cG5n">"#));
+        assert!(html.contains("url(data:image/gif;base64,Z2lm)"));
+        assert!(html.ends_with("<img src=cid:x>"));
+        assert_eq!(
+            (rendered.inline_images, rendered.missing_inline_images),
+            (2, 1)
+        );
+    }
+}
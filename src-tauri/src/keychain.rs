@@ -0,0 +1,76 @@
+//! App passwords, or refresh tokens for OAuth accounts, in the OS keychain,
+//! keyed by account address. Debug builds read `EMAIL_PASSWORD` instead, so
+//! development runs don't prompt.
+
+use keyring::{Entry, Error as KeyringError};
+use secrecy::{ExposeSecret, SecretString};
+use tracing::info;
+
+const KEYCHAIN_SERVICE: &str = "PersonalMailClient";
+
+fn keychain_entry(email: &str) -> Result<Entry, String> {
+    Entry::new(KEYCHAIN_SERVICE, email).map_err(|err| err.to_string())
+}
+
+pub fn store_password_in_keychain(email: &str, password: &SecretString) -> Result<(), String> {
+    // In development, you can use environment variables instead of keychain
+    // Set EMAIL_PASSWORD environment variable to avoid keychain prompts
+    if cfg!(debug_assertions) {
+        info!("Development mode: skipping keychain storage. Use EMAIL_PASSWORD env var instead.");
+        return Ok(());
+    }
+
+    let entry = keychain_entry(email)?;
+    entry
+        .set_password(password.expose_secret())
+        .map_err(|err| err.to_string())
+}
+
+pub fn fetch_password_from_keychain(email: &str) -> Result<Option<SecretString>, String> {
+    // In development, check environment variable first
+    if cfg!(debug_assertions) {
+        if let Ok(password) = std::env::var("EMAIL_PASSWORD") {
+            info!("Using password from EMAIL_PASSWORD environment variable");
+            return Ok(Some(SecretString::new(password)));
+        }
+    }
+
+    let entry = keychain_entry(email)?;
+    match entry.get_password() {
+        Ok(password) => Ok(Some(SecretString::new(password))),
+        Err(KeyringError::NoEntry) => Ok(None),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+pub fn password_exists_in_keychain(email: &str) -> Result<bool, String> {
+    // In development, check environment variable first
+    if cfg!(debug_assertions) && std::env::var("EMAIL_PASSWORD").is_ok() {
+        return Ok(true);
+    }
+
+    let entry = keychain_entry(email)?;
+    match entry.get_password() {
+        Ok(password) => {
+            // Wrapped so the copy is zeroed as it drops.
+            drop(SecretString::new(password));
+            Ok(true)
+        }
+        Err(KeyringError::NoEntry) => Ok(false),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+pub fn delete_password_from_keychain(email: &str) -> Result<(), String> {
+    // In development, no keychain entry to delete
+    if cfg!(debug_assertions) {
+        info!("Development mode: no keychain entry to delete");
+        return Ok(());
+    }
+
+    let entry = keychain_entry(email)?;
+    match entry.delete_password() {
+        Ok(_) | Err(KeyringError::NoEntry) => Ok(()),
+        Err(err) => Err(err.to_string()),
+    }
+}
@@ -1,5 +1,79 @@
+//! The mail client's engine, usable without its window. The Tauri app in
+//! `main.rs` is one client of this crate; command-line tools and tests
+//! link it the same way. The parts meant for that use are:
+//!
+//! - [`storage`]: the encrypted local cache. [`storage::Storage::open`]
+//!   takes a data directory (see [`data_dir`]) and returns a cheap, `Clone`
+//!   handle whose methods are async and safe to call from any task.
+//! - [`providers`]: talking to mail servers. Each operation takes the
+//!   account's [`models::Credentials`] and runs its blocking IMAP work off
+//!   the async runtime.
+//! - [`llm`]: local model inference, with [`llm_mock`] standing in when no
+//!   model is wanted.
+//! - [`models`]: the account, credential, and message types the others
+//!   share.
+//!
+//! Everything else is public so the app can reach it, but may change with
+//! the app's needs. With the `test-support` feature, [`test_support`]
+//! offers an in-process IMAP server and fixture mail.
+
+pub mod archive;
+pub mod attachment_text;
+pub mod attachments;
+pub mod autoreply;
+pub mod avatars;
+pub mod blocklist;
+pub mod body_text;
+pub mod bounces;
+pub mod brand;
+pub mod breach;
+pub mod caldav;
+pub mod calendar;
+pub mod carddav;
+pub mod classifier;
+pub mod command_context;
+pub mod data_dir;
+pub mod decrypt_cache;
+pub mod dns;
+pub mod draft_rewrite;
+pub mod draft_stats;
+pub mod events;
+pub mod flag_sync;
+pub mod focus;
+pub mod heatmap;
+pub mod html_render;
+pub mod keychain;
+pub mod links;
 pub mod llm;
+pub mod llm_mock;
+pub mod llm_policy;
+pub mod lookalike;
+pub mod mail_merge;
+pub mod message_query;
+pub mod migration;
+pub mod model_download;
 pub mod models;
+pub mod noise;
+pub mod ocr;
+pub mod otp;
+pub mod outbox;
+pub mod pdf;
+pub mod policy;
 pub mod providers;
+pub mod quarantine;
+pub mod redact;
+pub mod relationships;
 pub mod remote_delete;
+pub mod send_guard;
+pub mod send_insights;
+pub mod settings_bundle;
+pub mod spam;
 pub mod storage;
+pub mod subject_lines;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+pub mod threads;
+pub mod topics;
+pub mod trackers;
+pub mod uploads;
+pub mod vcard;
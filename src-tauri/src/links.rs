@@ -0,0 +1,103 @@
+//! URL extraction from cached bodies. Each link keeps the domain it really
+//! points at and, for HTML anchors, the text shown to the reader, so a
+//! link that displays one domain but leads to another can be flagged.
+
+use crate::body_text;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashSet;
+
+const MAX_LINKS_PER_MESSAGE: usize = 100;
+
+static ANCHOR: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<a\b[^>]*?\bhref\s*=\s*["']([^"']+)["'][^>]*>(.*?)</a\s*>"#)
+        .expect("anchor pattern is valid")
+});
+static BARE_URL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)\bhttps?://[^\s<>"'()\[\]]+"#).expect("bare url pattern is valid")
+});
+static DOMAIN_IN_TEXT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b((?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+[a-z]{2,})\b")
+        .expect("domain pattern is valid")
+});
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedLink {
+    pub url: String,
+    /// Host the link actually opens, lowercased.
+    pub domain: String,
+    pub display_text: Option<String>,
+    /// The anchor text names a different domain than the link opens.
+    pub mismatch: bool,
+}
+
+/// Links in a cached body, anchors first, without duplicates.
+pub fn extract_links(raw: &[u8]) -> Vec<ExtractedLink> {
+    let parts = body_text::decode_body_parts(raw);
+    let mut links = Vec::new();
+    let mut seen = HashSet::new();
+
+    for html in &parts.html {
+        for anchor in ANCHOR.captures_iter(html) {
+            let url = anchor[1].trim().replace("&amp;", "&");
+            let display = body_text::html_to_text(&anchor[2]);
+            let display = (!display.is_empty()).then_some(display);
+            push_link(&mut links, &mut seen, url, display);
+        }
+    }
+    for text in parts.plain.iter().chain(parts.html.iter()) {
+        for found in BARE_URL.find_iter(text) {
+            let url = found.as_str().trim_end_matches(['.', ',', ';', '!', '?']);
+            push_link(&mut links, &mut seen, url.to_string(), None);
+        }
+    }
+
+    links.truncate(MAX_LINKS_PER_MESSAGE);
+    links
+}
+
+fn push_link(
+    links: &mut Vec<ExtractedLink>,
+    seen: &mut HashSet<String>,
+    url: String,
+    display_text: Option<String>,
+) {
+    let Some(domain) = url_domain(&url) else {
+        return;
+    };
+    if !seen.insert(url.clone()) {
+        return;
+    }
+    let mismatch = display_text
+        .as_deref()
+        .and_then(|text| DOMAIN_IN_TEXT.captures(text))
+        .map(|captures| base_domain(&captures[1].to_lowercase()) != base_domain(&domain))
+        .unwrap_or(false);
+    links.push(ExtractedLink {
+        url,
+        domain,
+        display_text,
+        mismatch,
+    });
+}
+
+/// Host of an http(s) URL. Credentials before `@` are dropped, since
+/// `https://bank.com@evil.example/` really opens `evil.example`.
+pub fn url_domain(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return None;
+    }
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = host.split(':').next()?.trim_end_matches('.').to_lowercase();
+    (!host.is_empty()).then_some(host)
+}
+
+/// Last two labels of a host. Good enough to tell `paypal.com` from
+/// `paypal.com.evil.example`, which is what matters here.
+pub fn base_domain(host: &str) -> String {
+    let labels = host.rsplit('.').take(2).collect::<Vec<_>>();
+    labels.into_iter().rev().collect::<Vec<_>>().join(".")
+}
@@ -1,16 +1,106 @@
+//! Local model inference. [`LlmService`] loads a GGUF model with llama.cpp
+//! and runs prompts in priority order; the mock backend answers with canned
+//! replies instead. The privacy policy, and prompt redaction when it is
+//! turned on, apply to both.
+
+use std::collections::VecDeque;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use llama_cpp::{
     standard_sampler::StandardSampler, LlamaModel, LlamaParams, LlamaSession, SessionParams,
 };
 use parking_lot::{Mutex, RwLock};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
 use tracing::warn;
 
+use crate::llm_mock::MockResponses;
+use crate::llm_policy::{Backend, DataClass, LlmPrivacyPolicy};
+use crate::redact;
+
+/// Environment variable that picks the backend (`llama` or `mock`) over the
+/// saved setting, e.g. for test runs.
+pub const BACKEND_ENV: &str = "LLM_BACKEND";
+
 /// Default number of tokens to generate when replying to user prompts.
 const DEFAULT_COMPLETION_TOKENS: usize = 128;
 
+/// Context window of every session created by this service.
+const SESSION_CONTEXT_TOKENS: usize = 4096;
+
+/// Tokens kept free for tokenizer drift between the budget and the real prompt.
+const PROMPT_SAFETY_MARGIN: usize = 32;
+
+/// Completions allowed to run at once; further requests wait in their lane.
+const MAX_ACTIVE_COMPLETIONS: usize = 3;
+
+/// A queued request that has waited this long is served ahead of higher lanes.
+const LANE_STARVATION_LIMIT: Duration = Duration::from_secs(20);
+
+/// Scheduling lane for a completion request, highest priority first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestPriority {
+    Interactive,
+    Validation,
+    Bulk,
+}
+
+impl RequestPriority {
+    const ALL: [RequestPriority; 3] = [
+        RequestPriority::Interactive,
+        RequestPriority::Validation,
+        RequestPriority::Bulk,
+    ];
+
+    fn lane(self) -> usize {
+        match self {
+            RequestPriority::Interactive => 0,
+            RequestPriority::Validation => 1,
+            RequestPriority::Bulk => 2,
+        }
+    }
+}
+
+/// What answers prompts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmBackend {
+    /// The configured llama model, in process.
+    #[default]
+    Llama,
+    /// Canned replies from [`MockResponses`]; needs no model file. Used for
+    /// tests and dry-run analysis.
+    Mock,
+}
+
+impl LlmBackend {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "llama" => Some(LlmBackend::Llama),
+            "mock" => Some(LlmBackend::Mock),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LlmBackend::Llama => "llama",
+            LlmBackend::Mock => "mock",
+        }
+    }
+
+    /// The backend [`BACKEND_ENV`] asks for, if it names one.
+    pub fn from_env() -> Option<Self> {
+        std::env::var(BACKEND_ENV)
+            .ok()
+            .and_then(|value| Self::parse(&value))
+    }
+}
+
 /// System prompt injected before every completion so the local model stays on task.
 const SYSTEM_PROMPT: &str = r#"You are "Personal Mail Copilot", a focused assistant embedded in an email
 product. Answer only with useful, direct help related to the user's request.
@@ -33,14 +123,182 @@ struct LlmInner {
     model_path: RwLock<Option<PathBuf>>,
     model: Mutex<Option<LlamaModel>>,
     last_error: RwLock<Option<String>>,
-    session_pool: Mutex<Vec<LlamaSession>>, // reused sessions to avoid repeated Metal init
+    session_pool: Mutex<Vec<PrefixedSession>>, // reused sessions to avoid repeated Metal init
+    /// Session holding only the system prompt; new sessions are forked from it.
+    prefix_template: Mutex<Option<PrefixedSession>>,
+    scheduler: Arc<Scheduler>,
+    redact_prompts: AtomicBool,
+    /// Where completions run, for the privacy policy. Both the llama model
+    /// and the mock are in process.
+    backend: Backend,
+    completions: RwLock<LlmBackend>,
+    mock: RwLock<MockResponses>,
+    privacy_policy: RwLock<LlmPrivacyPolicy>,
+}
+
+/// A session whose first `prefix_tokens` context tokens are the system prompt.
+/// Truncating back to that point keeps the prompt's KV cache warm.
+struct PrefixedSession {
+    session: LlamaSession,
+    prefix_tokens: usize,
 }
 
 #[derive(Debug, Serialize, Clone)]
 pub struct LlmStatus {
+    pub backend: LlmBackend,
     pub configured_path: Option<String>,
     pub loaded: bool,
     pub last_error: Option<String>,
+    pub queue: LlmQueueStatus,
+    pub redact_prompts: bool,
+}
+
+/// Timing for a single completion, used by model benchmarks.
+#[derive(Debug, Clone)]
+pub struct CompletionProfile {
+    pub output: String,
+    pub output_tokens: usize,
+    pub time_to_first_token: Duration,
+    pub total: Duration,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LlmQueueStatus {
+    pub active: usize,
+    pub capacity: usize,
+    pub interactive_waiting: usize,
+    pub validation_waiting: usize,
+    pub bulk_waiting: usize,
+    pub completed: u64,
+}
+
+struct Waiter {
+    enqueued_at: Instant,
+    grant: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    active: usize,
+    lanes: [VecDeque<Waiter>; 3],
+    completed: u64,
+}
+
+/// Priority gate in front of the model: interactive > validation > bulk, with
+/// aging so a long interactive session cannot starve a bulk run forever.
+#[derive(Default)]
+struct Scheduler {
+    state: Mutex<SchedulerState>,
+}
+
+struct Permit {
+    scheduler: Arc<Scheduler>,
+}
+
+impl Scheduler {
+    async fn acquire(self: &Arc<Self>, priority: RequestPriority) -> Permit {
+        let receiver = {
+            let mut state = self.state.lock();
+            let queued = state.lanes.iter().any(|lane| !lane.is_empty());
+            if state.active < MAX_ACTIVE_COMPLETIONS && !queued {
+                state.active += 1;
+                return Permit {
+                    scheduler: self.clone(),
+                };
+            }
+            let (grant, receiver) = oneshot::channel();
+            state.lanes[priority.lane()].push_back(Waiter {
+                enqueued_at: Instant::now(),
+                grant,
+            });
+            receiver
+        };
+
+        let mut pending = PendingGrant {
+            receiver: Some(receiver),
+            scheduler: self.clone(),
+        };
+        if let Some(receiver) = pending.receiver.as_mut() {
+            let _ = receiver.await;
+        }
+        pending.receiver = None;
+        Permit {
+            scheduler: self.clone(),
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock();
+        state.active = state.active.saturating_sub(1);
+        state.completed += 1;
+
+        while state.active < MAX_ACTIVE_COMPLETIONS {
+            let Some(lane) = next_lane(&state.lanes) else {
+                break;
+            };
+            let Some(waiter) = state.lanes[lane].pop_front() else {
+                break;
+            };
+            // A closed receiver means the caller gave up; hand the slot onward.
+            if waiter.grant.send(()).is_ok() {
+                state.active += 1;
+            }
+        }
+    }
+
+    fn status(&self) -> LlmQueueStatus {
+        let state = self.state.lock();
+        let waiting = |priority: RequestPriority| state.lanes[priority.lane()].len();
+        LlmQueueStatus {
+            active: state.active,
+            capacity: MAX_ACTIVE_COMPLETIONS,
+            interactive_waiting: waiting(RequestPriority::Interactive),
+            validation_waiting: waiting(RequestPriority::Validation),
+            bulk_waiting: waiting(RequestPriority::Bulk),
+            completed: state.completed,
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.scheduler.release();
+    }
+}
+
+/// Returns a slot granted to a caller that was cancelled before it woke up.
+struct PendingGrant {
+    receiver: Option<oneshot::Receiver<()>>,
+    scheduler: Arc<Scheduler>,
+}
+
+impl Drop for PendingGrant {
+    fn drop(&mut self) {
+        if let Some(mut receiver) = self.receiver.take() {
+            receiver.close();
+            if receiver.try_recv().is_ok() {
+                self.scheduler.release();
+            }
+        }
+    }
+}
+
+/// Highest non-empty lane, unless a lower lane's oldest request has starved.
+fn next_lane(lanes: &[VecDeque<Waiter>; 3]) -> Option<usize> {
+    let starved = RequestPriority::ALL.iter().rev().find_map(|priority| {
+        let lane = priority.lane();
+        lanes[lane]
+            .front()
+            .filter(|waiter| waiter.enqueued_at.elapsed() >= LANE_STARVATION_LIMIT)
+            .map(|_| lane)
+    });
+    starved.or_else(|| {
+        RequestPriority::ALL
+            .iter()
+            .map(|priority| priority.lane())
+            .find(|lane| !lanes[*lane].is_empty())
+    })
 }
 
 impl LlmService {
@@ -51,6 +309,13 @@ impl LlmService {
                 model: Mutex::new(None),
                 last_error: RwLock::new(None),
                 session_pool: Mutex::new(Vec::new()),
+                prefix_template: Mutex::new(None),
+                scheduler: Arc::new(Scheduler::default()),
+                redact_prompts: AtomicBool::new(false),
+                backend: Backend::Local,
+                completions: RwLock::new(LlmBackend::default()),
+                mock: RwLock::new(MockResponses::default()),
+                privacy_policy: RwLock::new(LlmPrivacyPolicy::default()),
             }),
         }
     }
@@ -61,12 +326,58 @@ impl LlmService {
         let last_error = self.inner.last_error.read().clone();
 
         LlmStatus {
+            backend: self.backend(),
             configured_path: path.map(|p| p.display().to_string()),
             loaded,
             last_error,
+            queue: self.inner.scheduler.status(),
+            redact_prompts: self.redacts_prompts(),
         }
     }
 
+    /// Masks addresses, phone numbers, and one-time codes in prompts before
+    /// they reach the model. Off by default: the bundled model runs in
+    /// process, so this only matters for backends that send prompts away.
+    pub fn set_redact_prompts(&self, enabled: bool) {
+        self.inner.redact_prompts.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn redacts_prompts(&self) -> bool {
+        self.inner.redact_prompts.load(Ordering::Relaxed)
+    }
+
+    pub fn backend(&self) -> LlmBackend {
+        *self.inner.completions.read()
+    }
+
+    /// Switches backends for the next prompt. The llama model stays loaded
+    /// while the mock is in use.
+    pub fn set_backend(&self, backend: LlmBackend) {
+        *self.inner.completions.write() = backend;
+    }
+
+    pub fn set_mock_responses(&self, responses: MockResponses) {
+        *self.inner.mock.write() = responses;
+    }
+
+    pub fn privacy_policy(&self) -> LlmPrivacyPolicy {
+        self.inner.privacy_policy.read().clone()
+    }
+
+    pub fn set_privacy_policy(&self, policy: LlmPrivacyPolicy) {
+        *self.inner.privacy_policy.write() = policy;
+    }
+
+    /// Whether a prompt carrying `classes` would be accepted, for callers
+    /// that can leave optional data out instead of being rejected.
+    pub fn permits(&self, classes: &[DataClass]) -> bool {
+        self.inner
+            .privacy_policy
+            .read()
+            .check(self.inner.backend, classes)
+            .is_ok()
+    }
+
     pub fn configured_path(&self) -> Option<PathBuf> {
         self.inner.model_path.read().clone()
     }
@@ -75,6 +386,7 @@ impl LlmService {
         *self.inner.model.lock() = None;
         *self.inner.last_error.write() = None;
         self.inner.session_pool.lock().clear();
+        *self.inner.prefix_template.lock() = None;
     }
 
     pub fn set_model_path(&self, path: Option<PathBuf>) -> Result<(), String> {
@@ -84,6 +396,7 @@ impl LlmService {
         }
         *self.inner.model.lock() = None;
         self.inner.session_pool.lock().clear();
+        *self.inner.prefix_template.lock() = None;
 
         if let Some(ref model_path) = path {
             match self.load_model(model_path) {
@@ -103,16 +416,121 @@ impl LlmService {
         }
     }
 
+    /// Runs an interactive completion; it jumps ahead of queued background work.
     pub async fn analyze_prompt(
         &self,
         prompt: String,
         max_tokens: Option<usize>,
+        classes: &[DataClass],
     ) -> Result<String, String> {
+        self.analyze_prompt_with_priority(prompt, max_tokens, RequestPriority::Interactive, classes)
+            .await
+    }
+
+    /// Runs a completion for a prompt carrying `classes` of mail data. The
+    /// privacy policy is checked first; a prompt it does not allow is
+    /// rejected before it reaches the backend.
+    pub async fn analyze_prompt_with_priority(
+        &self,
+        prompt: String,
+        max_tokens: Option<usize>,
+        priority: RequestPriority,
+        classes: &[DataClass],
+    ) -> Result<String, String> {
+        let checked = self
+            .inner
+            .privacy_policy
+            .read()
+            .check(self.inner.backend, classes);
+        if let Err(violation) = checked {
+            warn!(
+                backend = ?violation.backend,
+                denied = ?violation.denied,
+                "LLM prompt rejected by privacy policy"
+            );
+            return Err(violation.to_string());
+        }
+        let prompt = if self.redacts_prompts() {
+            redact::redact(&prompt).into_owned()
+        } else {
+            prompt
+        };
+        if self.backend() == LlmBackend::Mock {
+            return Ok(self.inner.mock.read().reply(&prompt));
+        }
+        let permit = self.inner.scheduler.acquire(priority).await;
         let service = self.clone();
         let max_tokens = max_tokens.unwrap_or(DEFAULT_COMPLETION_TOKENS);
-        tokio::task::spawn_blocking(move || service.analyze_prompt_sync(&prompt, max_tokens))
-            .await
-            .map_err(|err| err.to_string())?
+        tokio::task::spawn_blocking(move || {
+            let result = service.analyze_prompt_sync(&prompt, max_tokens);
+            drop(permit);
+            result
+        })
+        .await
+        .map_err(|err| err.to_string())?
+    }
+
+    /// Runs a completion while recording time-to-first-token and token counts.
+    /// Benchmarks use bundled sample mail, so no privacy policy applies.
+    pub async fn profile_prompt(
+        &self,
+        prompt: String,
+        max_tokens: usize,
+    ) -> Result<CompletionProfile, String> {
+        if self.backend() == LlmBackend::Mock {
+            let output = self.inner.mock.read().reply(&prompt);
+            return Ok(CompletionProfile {
+                output_tokens: output.split_whitespace().count(),
+                output,
+                time_to_first_token: Duration::ZERO,
+                total: Duration::ZERO,
+            });
+        }
+        let permit = self.inner.scheduler.acquire(RequestPriority::Bulk).await;
+        let service = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = service.profile_prompt_sync(&prompt, max_tokens);
+            drop(permit);
+            result
+        })
+        .await
+        .map_err(|err| err.to_string())?
+    }
+
+    fn profile_prompt_sync(
+        &self,
+        prompt: &str,
+        max_tokens: usize,
+    ) -> Result<CompletionProfile, String> {
+        let mut cached = self.checkout_session()?;
+        let started = Instant::now();
+        let result = self
+            .prime_session(&mut cached, prompt)
+            .and_then(|_| {
+                cached
+                    .session
+                    .start_completing_with(StandardSampler::default(), max_tokens)
+                    .map_err(|err| format!("failed to start completion: {err}"))
+            })
+            .map(|handle| {
+                let mut output = String::new();
+                let mut output_tokens = 0usize;
+                let mut time_to_first_token = None;
+                for piece in handle.into_strings() {
+                    time_to_first_token.get_or_insert_with(|| started.elapsed());
+                    output.push_str(&piece);
+                    output_tokens += 1;
+                }
+                let total = started.elapsed();
+                CompletionProfile {
+                    output: output.trim().to_string(),
+                    output_tokens,
+                    time_to_first_token: time_to_first_token.unwrap_or(total),
+                    total,
+                }
+            });
+        self.return_session(cached);
+        result
     }
 
     fn analyze_prompt_sync(&self, prompt: &str, max_tokens: usize) -> Result<String, String> {
@@ -124,26 +542,15 @@ impl LlmService {
 
     fn run_completion(
         &self,
-        session: &mut LlamaSession,
+        cached: &mut PrefixedSession,
         prompt: &str,
         max_tokens: usize,
     ) -> Result<String, String> {
-        session
-            .truncate_context(0)
-            .map_err(|err| format!("failed to clear llama session context: {err}"))?;
-
-        let full_prompt = format!(
-            "{system}\n\nUser: {prompt}\nAssistant:",
-            system = SYSTEM_PROMPT,
-            prompt = prompt.trim()
-        );
-
-        session
-            .advance_context(&full_prompt)
-            .map_err(|err| format!("failed to load prompt into llama session: {err}"))?;
+        self.prime_session(cached, prompt)?;
 
         let sampler = StandardSampler::default();
-        let handle = session
+        let handle = cached
+            .session
             .start_completing_with(sampler, max_tokens)
             .map_err(|err| format!("failed to start completion: {err}"))?;
 
@@ -151,34 +558,132 @@ impl LlmService {
         Ok(output.trim().to_string())
     }
 
-    fn checkout_session(&self) -> Result<LlamaSession, String> {
-        if let Some(mut session) = self.inner.session_pool.lock().pop() {
-            if let Err(err) = session.truncate_context(0) {
-                warn!(?err, "failed to clear cached llama session; recreating");
+    /// Loads the per-request suffix; the system prompt is already in the
+    /// session's KV cache.
+    fn prime_session(&self, cached: &mut PrefixedSession, prompt: &str) -> Result<(), String> {
+        cached
+            .session
+            .truncate_context(cached.prefix_tokens)
+            .map_err(|err| format!("failed to reset llama session context: {err}"))?;
+
+        cached
+            .session
+            .advance_context(prompt_suffix(prompt))
+            .map_err(|err| format!("failed to load prompt into llama session: {err}"))
+    }
+
+    /// Trims `text` so that `template` (with the text appended) plus the system
+    /// prompt and `max_completion_tokens` fit the context window, measured with
+    /// the loaded model's tokenizer. Fails when no model is loaded yet so callers
+    /// can fall back to character-based clipping.
+    pub fn fit_text_to_budget(
+        &self,
+        template: &str,
+        text: &str,
+        max_completion_tokens: usize,
+    ) -> Result<String, String> {
+        let model = self
+            .inner
+            .model
+            .lock()
+            .as_ref()
+            .cloned()
+            .ok_or_else(|| "No local LLM model loaded".to_string())?;
+
+        let fixed = count_tokens(&model, &wrap_prompt(template))?;
+        let budget = SESSION_CONTEXT_TOKENS
+            .saturating_sub(fixed + max_completion_tokens + PROMPT_SAFETY_MARGIN);
+
+        if count_tokens(&model, text)? <= budget {
+            return Ok(text.to_string());
+        }
+        if budget == 0 {
+            return Ok(String::new());
+        }
+
+        // Binary search for the longest character prefix that still fits.
+        let boundaries = text
+            .char_indices()
+            .map(|(index, _)| index)
+            .chain(std::iter::once(text.len()))
+            .collect::<Vec<_>>();
+        let (mut low, mut high) = (0usize, boundaries.len() - 1);
+        while low < high {
+            let middle = (low + high).div_ceil(2);
+            let candidate = format!("{}…", &text[..boundaries[middle]]);
+            if count_tokens(&model, &candidate)? <= budget {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+
+        if low == 0 {
+            return Ok(String::new());
+        }
+        Ok(format!("{}…", text[..boundaries[low]].trim_end()))
+    }
+
+    fn checkout_session(&self) -> Result<PrefixedSession, String> {
+        if let Some(mut cached) = self.inner.session_pool.lock().pop() {
+            if let Err(err) = cached.session.truncate_context(cached.prefix_tokens) {
+                warn!(?err, "failed to reset cached llama session; recreating");
             } else {
-                return Ok(session);
+                return Ok(cached);
+            }
+        }
+
+        let mut template = self.inner.prefix_template.lock();
+        if let Some(existing) = template.as_ref() {
+            match existing.session.deep_copy() {
+                Ok(session) => {
+                    return Ok(PrefixedSession {
+                        session,
+                        prefix_tokens: existing.prefix_tokens,
+                    })
+                }
+                Err(err) => warn!(?err, "failed to fork system prompt session; rebuilding"),
             }
         }
 
         let model = self.ensure_model()?;
-        model
+        let mut session = model
             .create_session(SessionParams {
-                n_ctx: 4096,
+                n_ctx: SESSION_CONTEXT_TOKENS as u32,
                 n_batch: 2048,
                 ..Default::default()
             })
-            .map_err(|err| format!("failed to create llama session: {err}"))
+            .map_err(|err| format!("failed to create llama session: {err}"))?;
+        session
+            .advance_context(prompt_prefix())
+            .map_err(|err| format!("failed to load system prompt into llama session: {err}"))?;
+        let prefix_tokens = session.context_size();
+
+        match session.deep_copy() {
+            Ok(copy) => {
+                *template = Some(PrefixedSession {
+                    session: copy,
+                    prefix_tokens,
+                })
+            }
+            Err(err) => warn!(?err, "failed to cache system prompt session"),
+        }
+
+        Ok(PrefixedSession {
+            session,
+            prefix_tokens,
+        })
     }
 
-    fn return_session(&self, mut session: LlamaSession) {
-        if let Err(err) = session.truncate_context(0) {
+    fn return_session(&self, mut cached: PrefixedSession) {
+        if let Err(err) = cached.session.truncate_context(cached.prefix_tokens) {
             warn!(
                 ?err,
-                "failed to clear llama session before caching; dropping session"
+                "failed to reset llama session before caching; dropping session"
             );
             return;
         }
-        self.inner.session_pool.lock().push(session);
+        self.inner.session_pool.lock().push(cached);
     }
 
     fn ensure_model(&self) -> Result<LlamaModel, String> {
@@ -209,6 +714,26 @@ impl LlmService {
     }
 }
 
+/// Static part of every prompt, kept warm in pooled sessions.
+fn prompt_prefix() -> String {
+    format!("{SYSTEM_PROMPT}\n\n")
+}
+
+fn prompt_suffix(prompt: &str) -> String {
+    format!("User: {prompt}\nAssistant:", prompt = prompt.trim())
+}
+
+fn wrap_prompt(prompt: &str) -> String {
+    format!("{}{}", prompt_prefix(), prompt_suffix(prompt))
+}
+
+fn count_tokens(model: &LlamaModel, text: &str) -> Result<usize, String> {
+    model
+        .tokenize_bytes(text.as_bytes(), false, false)
+        .map(|tokens| tokens.len())
+        .map_err(|err| format!("failed to tokenize prompt: {err}"))
+}
+
 impl Default for LlmService {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,141 @@
+//! Canned replies for the mock LLM backend. A rule pairs a regular
+//! expression with the reply for prompts it matches; `$1` or `${name}` in
+//! the reply is filled in from the match (write `$$` for a dollar sign).
+//! The user's rules are tried first, then built-in ones covering every
+//! prompt the app sends, so analysis, quick replies, subject suggestions,
+//! and rewrites all run without a model file and answer the same way every
+//! time.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// App setting holding the user's [`MockRule`]s as JSON.
+pub const SETTING_KEY: &str = "llm_mock_responses";
+
+/// Model id recorded on mock analyses, so a later run with a real model
+/// treats them as another model's results.
+pub const MODEL_ID: &str = "mock";
+
+/// The reply to a prompt no rule matches.
+const FALLBACK_REPLY: &str = "Dry run: no model was consulted for this prompt.";
+
+const BUILTIN_RULES: &[(&str, &str)] = &[
+    (
+        r"(?i)you are an email triage system",
+        concat!(
+            r#"{"summary": "Dry-run analysis: no model was consulted.", "#,
+            r#""sentiment": "neutral", "tags": [], "priority": "normal", "#,
+            r#""actionability": "informational", "risk": "none", "source_type": null, "#,
+            r#""thread_role": null, "lifecycle": "new", "confidence": 0.5, "#,
+            r#""rationale": "Canned reply from the mock backend.", "extractions": {}}"#,
+        ),
+    ),
+    (
+        r"(?i)^you check an email classification",
+        r#"{"valid": true, "confidence": 0.5, "notes": "Dry run: no model was consulted."}"#,
+    ),
+    (
+        r"(?i)you pick a canned reply",
+        r#"{"templateId": null, "reason": "Dry run: no model was consulted."}"#,
+    ),
+    (
+        r#""acknowledge": "\.\.\.""#,
+        concat!(
+            r#"{"acknowledge": "Thanks, got it.", "accept": "Sounds good, count me in.", "#,
+            r#""decline": "Thanks, but I can't make it."}"#,
+        ),
+    ),
+    (
+        r"(?i)^suggest \d+ different subject lines",
+        r#"["Quick update", "Following up on our conversation", "Next steps"]"#,
+    ),
+    // Rewrites hand the draft back unchanged.
+    (r"(?s)<<<DRAFT\n(.*)\nDRAFT>>>", "$1"),
+    (r"(?i)answer with one word, yes or no", "YES"),
+];
+
+/// A user-supplied canned reply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MockRule {
+    pub pattern: String,
+    pub response: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MockResponses {
+    rules: Vec<(Regex, String)>,
+}
+
+impl MockResponses {
+    /// `rules` ahead of the built-in ones. Fails on the first pattern that
+    /// is not a valid regular expression.
+    pub fn new(rules: &[MockRule]) -> Result<Self, String> {
+        let user = rules
+            .iter()
+            .map(|rule| (rule.pattern.as_str(), rule.response.as_str()));
+        let rules = user
+            .chain(BUILTIN_RULES.iter().copied())
+            .map(|(pattern, response)| {
+                Regex::new(pattern)
+                    .map(|regex| (regex, response.to_string()))
+                    .map_err(|err| format!("invalid mock pattern '{pattern}': {err}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn reply(&self, prompt: &str) -> String {
+        for (pattern, response) in &self.rules {
+            if let Some(captures) = pattern.captures(prompt) {
+                let mut reply = String::new();
+                captures.expand(response, &mut reply);
+                return reply;
+            }
+        }
+        FALLBACK_REPLY.to_string()
+    }
+}
+
+impl Default for MockResponses {
+    fn default() -> Self {
+        Self::new(&[]).expect("built-in mock patterns are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn built_in_replies_cover_the_app_prompts() {
+        let mock = MockResponses::default();
+        let triage = mock.reply("You are an email triage system. Analyze the email below");
+        let triage: Value = serde_json::from_str(&triage).unwrap();
+        assert_eq!(triage["priority"], "normal");
+        assert_eq!(
+            mock.reply("Rewrite the email draft.\n<<<DRAFT\nSee you at 5.\nDRAFT>>>\n"),
+            "See you at 5."
+        );
+        assert_eq!(mock.reply("Tell me a joke"), FALLBACK_REPLY);
+    }
+
+    #[test]
+    fn user_rules_win_and_bad_patterns_are_rejected() {
+        let mock = MockResponses::new(&[MockRule {
+            pattern: r"Subject: (\w+)".into(),
+            response: r#"{"summary": "About $1"}"#.into(),
+        }])
+        .unwrap();
+        assert_eq!(
+            mock.reply("You are an email triage system.\n- Subject: Invoice"),
+            r#"{"summary": "About Invoice"}"#
+        );
+
+        let invalid = MockResponses::new(&[MockRule {
+            pattern: "(".into(),
+            response: String::new(),
+        }]);
+        assert!(invalid.unwrap_err().contains("invalid mock pattern"));
+    }
+}
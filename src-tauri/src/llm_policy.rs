@@ -0,0 +1,156 @@
+//! Which kinds of mail data may reach which language model backend. Every
+//! prompt names the data classes it carries, and [`LlmService`] checks them
+//! against the policy before the prompt goes anywhere; a prompt carrying a
+//! class its backend may not see is rejected and logged.
+//!
+//! [`LlmService`]: crate::llm::LlmService
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// App setting holding the [`LlmPrivacyPolicy`] as JSON.
+pub const SETTING_KEY: &str = "llm_privacy_policy";
+
+/// A kind of data a prompt can carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataClass {
+    /// Sender address and display name.
+    Sender,
+    Subject,
+    Snippet,
+    /// Full body text.
+    Body,
+    /// Text recognized in image attachments.
+    Attachments,
+    /// Text the user typed into the assistant.
+    UserPrompt,
+}
+
+impl DataClass {
+    pub const ALL: [DataClass; 6] = [
+        DataClass::Sender,
+        DataClass::Subject,
+        DataClass::Snippet,
+        DataClass::Body,
+        DataClass::Attachments,
+        DataClass::UserPrompt,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DataClass::Sender => "sender",
+            DataClass::Subject => "subject",
+            DataClass::Snippet => "snippet",
+            DataClass::Body => "body",
+            DataClass::Attachments => "attachments",
+            DataClass::UserPrompt => "user_prompt",
+        }
+    }
+}
+
+/// Where a backend runs. Ordered from most to least private.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// In this process; prompts never leave the machine.
+    Local,
+    /// Another machine or service.
+    Remote,
+}
+
+/// The furthest backend a data class may reach.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Reach {
+    /// No backend at all.
+    None,
+    Local,
+    Remote,
+}
+
+impl Reach {
+    fn allows(self, backend: Backend) -> bool {
+        match self {
+            Reach::None => false,
+            Reach::Local => backend == Backend::Local,
+            Reach::Remote => true,
+        }
+    }
+}
+
+/// Reach per data class. Classes missing from `classes` use the defaults:
+/// headers may go anywhere, message content stays local.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LlmPrivacyPolicy {
+    pub classes: BTreeMap<DataClass, Reach>,
+}
+
+impl LlmPrivacyPolicy {
+    pub fn reach(&self, class: DataClass) -> Reach {
+        self.classes
+            .get(&class)
+            .copied()
+            .unwrap_or_else(|| default_reach(class))
+    }
+
+    /// Every class with its effective reach, for settings screens.
+    pub fn resolved(&self) -> Self {
+        Self {
+            classes: DataClass::ALL
+                .iter()
+                .map(|class| (*class, self.reach(*class)))
+                .collect(),
+        }
+    }
+
+    /// Ok when `backend` may see every class in `classes`.
+    pub fn check(&self, backend: Backend, classes: &[DataClass]) -> Result<(), PolicyViolation> {
+        let mut denied = classes
+            .iter()
+            .copied()
+            .filter(|class| !self.reach(*class).allows(backend))
+            .collect::<Vec<_>>();
+        if denied.is_empty() {
+            return Ok(());
+        }
+        denied.sort_unstable();
+        denied.dedup();
+        Err(PolicyViolation { backend, denied })
+    }
+}
+
+fn default_reach(class: DataClass) -> Reach {
+    match class {
+        DataClass::Sender | DataClass::Subject | DataClass::UserPrompt => Reach::Remote,
+        DataClass::Snippet | DataClass::Body | DataClass::Attachments => Reach::Local,
+    }
+}
+
+/// A prompt carried classes its backend may not see.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyViolation {
+    pub backend: Backend,
+    pub denied: Vec<DataClass>,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let classes = self
+            .denied
+            .iter()
+            .map(|class| class.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let backend = match self.backend {
+            Backend::Local => "the local model",
+            Backend::Remote => "a remote model",
+        };
+        write!(
+            f,
+            "The LLM privacy policy does not allow sending {classes} to {backend}"
+        )
+    }
+}
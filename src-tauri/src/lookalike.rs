@@ -0,0 +1,322 @@
+//! Lookalike sender domains. A sender's organizational domain is compared
+//! with domains the user already trusts (frequent senders, contacts, their
+//! own) and a short list of brands phishers imitate. Internationalized
+//! labels are decoded from punycode first, then two checks run: a
+//! homoglyph skeleton that folds characters which render alike (`0`/`o`,
+//! `rn`/`m`, Cyrillic `а`/`a`), and an edit distance for typos.
+
+use crate::brand::organizational_domain;
+use crate::breach::{AlertKind, SecurityAlert, SecurityMessage, Severity};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Senders with at least this many messages count as frequent contacts.
+pub const FREQUENT_SENDER_MIN_MESSAGES: i64 = 5;
+
+/// Domains commonly imitated in phishing mail.
+pub const POPULAR_DOMAINS: &[&str] = &[
+    "adobe.com",
+    "amazon.com",
+    "americanexpress.com",
+    "apple.com",
+    "bankofamerica.com",
+    "binance.com",
+    "chase.com",
+    "citibank.com",
+    "coinbase.com",
+    "dhl.com",
+    "docusign.com",
+    "dropbox.com",
+    "ebay.com",
+    "facebook.com",
+    "fedex.com",
+    "github.com",
+    "gmail.com",
+    "google.com",
+    "icloud.com",
+    "instagram.com",
+    "linkedin.com",
+    "microsoft.com",
+    "netflix.com",
+    "office.com",
+    "outlook.com",
+    "paypal.com",
+    "spotify.com",
+    "stripe.com",
+    "twitter.com",
+    "usps.com",
+    "walmart.com",
+    "wellsfargo.com",
+    "yahoo.com",
+];
+
+/// Shorter domains differ from others by one edit too often to be flagged
+/// for typos.
+const TYPO_MIN_LEN: usize = 5;
+/// Domains this long are flagged at two edits.
+const TWO_EDIT_MIN_LEN: usize = 10;
+
+// Punycode parameters from RFC 3492.
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LookalikeReason {
+    /// Renders like the other domain.
+    Homoglyph,
+    /// One or two keystrokes away from the other domain.
+    Typo,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lookalike {
+    /// The sender's organizational domain, decoded to Unicode.
+    pub domain: String,
+    /// The trusted or popular domain it imitates.
+    pub resembles: String,
+    pub reason: LookalikeReason,
+}
+
+/// The domain `domain` imitates, if any. `known` holds the user's trusted
+/// domains; a sender whose own domain is known or popular is never flagged.
+pub fn check<'a>(domain: &str, known: impl IntoIterator<Item = &'a str>) -> Option<Lookalike> {
+    let sender = organizational_domain(&to_unicode(domain)?);
+    let candidates = known
+        .into_iter()
+        .filter_map(to_unicode)
+        .map(|domain| organizational_domain(&domain))
+        .chain(POPULAR_DOMAINS.iter().map(|domain| domain.to_string()))
+        .collect::<HashSet<_>>();
+    if sender.is_empty() || candidates.contains(&sender) {
+        return None;
+    }
+
+    let sender_skeleton = skeleton(&sender);
+    let sender_chars = sender.chars().collect::<Vec<_>>();
+    let mut candidates = candidates.into_iter().collect::<Vec<_>>();
+    candidates.sort();
+    let found = |resembles: String, reason| {
+        Some(Lookalike {
+            domain: sender.clone(),
+            resembles,
+            reason,
+        })
+    };
+    if let Some(resembles) = candidates
+        .iter()
+        .find(|candidate| skeleton(candidate) == sender_skeleton)
+    {
+        return found(resembles.clone(), LookalikeReason::Homoglyph);
+    }
+    candidates
+        .into_iter()
+        .filter(|candidate| {
+            let len = candidate.chars().count();
+            let allowed = if len >= TWO_EDIT_MIN_LEN {
+                2
+            } else if len >= TYPO_MIN_LEN {
+                1
+            } else {
+                0
+            };
+            let candidate = candidate.chars().collect::<Vec<_>>();
+            allowed > 0 && edit_distance(&sender_chars, &candidate) <= allowed
+        })
+        .find_map(|candidate| found(candidate, LookalikeReason::Typo))
+}
+
+/// Alerts for messages from lookalike domains, one per domain.
+pub fn alerts(
+    account_email: &str,
+    messages: &[SecurityMessage],
+    known: &[String],
+) -> Vec<SecurityAlert> {
+    let mut checked: HashMap<String, Option<Lookalike>> = HashMap::new();
+    let mut by_domain: HashMap<String, (Lookalike, Vec<&SecurityMessage>)> = HashMap::new();
+    for message in messages {
+        let Some((_, domain)) = message.sender_email.rsplit_once('@') else {
+            continue;
+        };
+        let domain = domain.to_lowercase();
+        let lookalike = checked
+            .entry(domain.clone())
+            .or_insert_with(|| check(&domain, known.iter().map(String::as_str)));
+        if let Some(lookalike) = lookalike {
+            by_domain
+                .entry(lookalike.domain.clone())
+                .or_insert_with(|| (lookalike.clone(), Vec::new()))
+                .1
+                .push(message);
+        }
+    }
+
+    by_domain
+        .into_values()
+        .map(|(lookalike, found)| SecurityAlert {
+            kind: AlertKind::LookalikeSender,
+            severity: match lookalike.reason {
+                LookalikeReason::Homoglyph => Severity::Critical,
+                LookalikeReason::Typo => Severity::Warning,
+            },
+            account_email: account_email.to_string(),
+            message: format!(
+                "{} message(s) from {}, which resembles {}",
+                found.len(),
+                lookalike.domain,
+                lookalike.resembles
+            ),
+            breach: None,
+            uids: found.iter().map(|message| message.uid).collect(),
+            latest_at: found.iter().filter_map(|message| message.date_ts).max(),
+            lookalike: Some(lookalike),
+        })
+        .collect()
+}
+
+/// `domain` lowercased with punycode labels decoded; `None` when a label
+/// does not decode.
+fn to_unicode(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    let labels = domain
+        .split('.')
+        .map(|label| match label.strip_prefix("xn--") {
+            Some(encoded) => punycode_decode(encoded),
+            None => Some(label.to_string()),
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(labels.join("."))
+}
+
+/// Folds characters that render alike onto one ASCII spelling.
+fn skeleton(domain: &str) -> String {
+    let folded = domain
+        .chars()
+        .map(|c| match c {
+            '0' | 'о' | 'ο' | 'օ' => 'o',
+            '1' | 'i' | 'ı' | 'і' | 'ӏ' | 'ι' | 'ǀ' => 'l',
+            '3' | 'е' | 'ё' | 'ε' => 'e',
+            '5' | 'ѕ' => 's',
+            'а' | 'α' => 'a',
+            'с' | 'ϲ' => 'c',
+            'ԁ' => 'd',
+            'ɡ' | 'ց' => 'g',
+            'һ' => 'h',
+            'ј' => 'j',
+            'к' | 'κ' => 'k',
+            'п' | 'η' => 'n',
+            'р' | 'ρ' => 'p',
+            'ԛ' => 'q',
+            'г' => 'r',
+            'т' | 'τ' => 't',
+            'ս' | 'υ' => 'u',
+            'ν' | 'ѵ' => 'v',
+            'ԝ' | 'ω' => 'w',
+            'х' | 'χ' => 'x',
+            'у' | 'γ' => 'y',
+            '_' => '-',
+            other => other,
+        })
+        .collect::<String>();
+    folded
+        .replace("rn", "m")
+        .replace("vv", "w")
+        .replace("cl", "d")
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Decodes one punycode label (RFC 3492) without its `xn--` prefix.
+fn punycode_decode(input: &str) -> Option<String> {
+    let (basic, encoded) = input.rsplit_once('-').unwrap_or(("", input));
+    if !basic.is_ascii() {
+        return None;
+    }
+    let mut output = basic.chars().collect::<Vec<_>>();
+    let mut digits = encoded.bytes().peekable();
+    let (mut n, mut i, mut bias) = (128u32, 0u32, 72u32);
+    while digits.peek().is_some() {
+        let old_i = i;
+        let mut weight = 1u32;
+        let mut k = BASE;
+        loop {
+            let digit = match digits.next()? {
+                byte @ b'a'..=b'z' => u32::from(byte - b'a'),
+                byte @ b'0'..=b'9' => u32::from(byte - b'0') + 26,
+                _ => return None,
+            };
+            i = i.checked_add(digit.checked_mul(weight)?)?;
+            let threshold = if k <= bias {
+                T_MIN
+            } else {
+                (k - bias).min(T_MAX)
+            };
+            if digit < threshold {
+                break;
+            }
+            weight = weight.checked_mul(BASE - threshold)?;
+            k += BASE;
+        }
+        let len = output.len() as u32 + 1;
+        bias = adapt(i - old_i, len, old_i == 0);
+        n = n.checked_add(i / len)?;
+        i %= len;
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+    Some(output.into_iter().collect())
+}
+
+fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+    let mut delta = if first { delta / DAMP } else { delta / 2 };
+    delta += delta / points;
+    let mut k = 0;
+    while delta > (BASE - T_MIN) * T_MAX / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+    k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_homoglyphs_and_typos() {
+        let homoglyph = check("mail.paypa1.com", []).unwrap();
+        assert_eq!(homoglyph.domain, "paypa1.com");
+        assert_eq!(homoglyph.resembles, "paypal.com");
+        assert_eq!(homoglyph.reason, LookalikeReason::Homoglyph);
+
+        let idn = check("xn--80ak6aa92e.com", []).unwrap();
+        assert_eq!(idn.domain, "аррӏе.com");
+        assert_eq!(idn.resembles, "apple.com");
+
+        let typo = check("acme-widgest.com", ["acme-widgets.com"]).unwrap();
+        assert_eq!(typo.resembles, "acme-widgets.com");
+        assert_eq!(typo.reason, LookalikeReason::Typo);
+    }
+
+    #[test]
+    fn trusts_known_and_distant_domains() {
+        assert_eq!(check("news.paypal.com", []), None);
+        assert_eq!(check("paypa1.com", ["paypa1.com"]), None);
+        assert_eq!(check("example.org", ["example.com"]), None);
+        assert_eq!(check("rust-lang.org", []), None);
+    }
+}
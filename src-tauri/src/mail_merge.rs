@@ -0,0 +1,163 @@
+//! Mail merge: `{{placeholder}}` templates rendered per recipient, with
+//! recipients coming from an imported CSV or from cached contacts. Rendered
+//! messages are queued in the outbox (see `Storage::enqueue_outbox`).
+
+use std::collections::HashMap;
+
+/// Fields available to a template for one recipient. Keys are lowercase.
+pub type RecipientFields = HashMap<String, String>;
+
+#[derive(Debug, Clone)]
+pub struct RenderedMessage {
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Replaces every `{{name}}` in `template` with the matching field. Unknown
+/// placeholders are an error so half-personalized mail is never queued.
+pub fn render_placeholders(template: &str, fields: &RecipientFields) -> Result<String, String> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "Unclosed '{{' in template".to_string())?;
+        let key = after[..end].trim().to_lowercase();
+        let value = fields
+            .get(&key)
+            .ok_or_else(|| format!("Missing value for placeholder '{key}'"))?;
+        output.push_str(value);
+        rest = &after[end + 2..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Lists the distinct placeholder names used in `template`, in first-use order.
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let key = after[..end].trim().to_lowercase();
+        if !key.is_empty() && !names.contains(&key) {
+            names.push(key);
+        }
+        rest = &after[end + 2..];
+    }
+
+    names
+}
+
+pub fn render_for_recipients(
+    subject_template: &str,
+    body_template: &str,
+    recipients: &[RecipientFields],
+) -> (Vec<RenderedMessage>, Vec<String>) {
+    let mut rendered = Vec::new();
+    let mut errors = Vec::new();
+
+    for fields in recipients {
+        let Some(recipient) = fields.get("email").filter(|value| value.contains('@')) else {
+            errors.push("Recipient row is missing a valid email".to_string());
+            continue;
+        };
+        let result = render_placeholders(subject_template, fields).and_then(|subject| {
+            render_placeholders(body_template, fields).map(|body| (subject, body))
+        });
+        match result {
+            Ok((subject, body)) => rendered.push(RenderedMessage {
+                recipient: recipient.clone(),
+                subject,
+                body,
+            }),
+            Err(err) => errors.push(format!("{recipient}: {err}")),
+        }
+    }
+
+    (rendered, errors)
+}
+
+/// Parses CSV text whose header row names the fields; an `email` column is required.
+pub fn parse_recipients_csv(content: &str) -> Result<Vec<RecipientFields>, String> {
+    let mut rows = parse_csv(content).into_iter();
+    let header = rows
+        .next()
+        .ok_or_else(|| "Recipient CSV is empty".to_string())?
+        .into_iter()
+        .map(|column| column.trim().to_lowercase())
+        .collect::<Vec<_>>();
+
+    if !header.iter().any(|column| column == "email") {
+        return Err("Recipient CSV needs an 'email' column".into());
+    }
+
+    Ok(rows
+        .filter(|row| row.iter().any(|value| !value.trim().is_empty()))
+        .map(|row| {
+            header
+                .iter()
+                .cloned()
+                .zip(row.into_iter().map(|value| value.trim().to_string()))
+                .collect::<RecipientFields>()
+        })
+        .collect())
+}
+
+/// Builds template fields for a cached contact.
+pub fn contact_fields(email: &str, display_name: Option<&str>) -> RecipientFields {
+    let name = display_name
+        .map(str::trim)
+        .filter(|value| !value.is_empty() && !value.contains('@'))
+        .unwrap_or_default();
+    let first_name = name.split_whitespace().next().unwrap_or_default();
+
+    let mut fields = RecipientFields::new();
+    fields.insert("email".into(), email.to_string());
+    fields.insert("name".into(), name.to_string());
+    fields.insert("first_name".into(), first_name.to_string());
+    fields
+}
+
+/// Minimal RFC 4180 reader: quoted fields, doubled quotes, CRLF or LF rows.
+fn parse_csv(content: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match (ch, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(ch),
+        }
+    }
+
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows
+}
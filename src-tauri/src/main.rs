@@ -3,22 +3,101 @@
     windows_subsystem = "windows"
 )]
 
-use keyring::{Entry, Error as KeyringError};
-use oauth2::{
-    AuthorizationCode, ClientId, CsrfToken, PkceCodeChallenge, RedirectUrl, Scope, TokenResponse,
+use oauth2::basic::BasicTokenResponse;
+use oauth2::TokenResponse;
+use personal_mail_client::archive;
+use personal_mail_client::attachment_text;
+use personal_mail_client::attachments::{self, Attachment, InlineImage};
+use personal_mail_client::autoreply::{self, AutoReplySettings};
+use personal_mail_client::avatars::{self, AvatarResolver, AvatarSettings, SenderAvatar};
+use personal_mail_client::blocklist::{self, BlocklistFormat};
+use personal_mail_client::body_text::{self, BodyParts};
+use personal_mail_client::bounces;
+use personal_mail_client::brand::{self, DmarcPolicy, DomainBrand};
+use personal_mail_client::breach::{self, BreachCheckSettings, SecurityAlert};
+use personal_mail_client::caldav::{self, CalDavSettings, PushStatus};
+use personal_mail_client::calendar::{self, Availability, AvailabilityRange};
+use personal_mail_client::carddav::{self, CardDavSettings, PutResult};
+use personal_mail_client::classifier::{self, FAST_PATH_MIN_CONFIDENCE};
+use personal_mail_client::command_context::{
+    self, normalize_email, provider_error_to_message, require_email, CommandContext, NOT_CONNECTED,
+};
+use personal_mail_client::data_dir::{self, DataDirectoryInfo, Store, StoreInfo};
+use personal_mail_client::dns::{self, DnsSettings};
+use personal_mail_client::draft_rewrite::{self, DraftRewrite, RewriteInstruction};
+use personal_mail_client::draft_stats::{self, DraftCheckSettings, DraftStats};
+use personal_mail_client::events::{
+    self, BulkAnalysisProgress, BulkAnalysisResult, BulkAnalysisStatus, Event, FlagConflicts,
+    FocusAlert, FocusReview, LocalUpdate, LocalUpdateApplied, LocalUpdateRolledBack,
+    MessagesRemoved, ModelDownloadProgress, NewMail, OtpReceived, RemoteDeleteMetrics,
+    SenderMutesExpired, StorageHealth, SyncProgress, WipeProgress,
+};
+use personal_mail_client::flag_sync::{self, ConflictPolicy, FlagPolicies};
+use personal_mail_client::focus::{self, FocusSettings};
+use personal_mail_client::heatmap::{self, HeatmapRange, InboxHeatmap};
+use personal_mail_client::html_render::{self, RenderPolicy, RenderedHtml};
+use personal_mail_client::keychain::{
+    delete_password_from_keychain, fetch_password_from_keychain, password_exists_in_keychain,
+    store_password_in_keychain,
+};
+use personal_mail_client::message_query::{
+    FieldMask, MessageFilter, MessageQuery, MessageSort, QueryPlan, MAX_PAGE_SIZE,
 };
 use personal_mail_client::models::{
-    Account, AppState, ConnectAccountResponse, Credentials, EmailSummary, MailAddress, Provider,
-    SavedAccount, SyncHandle, SyncReport,
+    parse_uid, uid_string, Account, AppState, AuthMethod, ConnectAccountResponse, Credentials,
+    EmailSummary, MailAddress, Provider, SavedAccount, SyncHandle, SyncReport,
+};
+use personal_mail_client::noise::{self, NoiseScore};
+use personal_mail_client::ocr;
+use personal_mail_client::otp::{self, OneTimeCode, OtpSettings};
+use personal_mail_client::outbox;
+use personal_mail_client::pdf;
+use personal_mail_client::policy::{self, PolicyDecision};
+use personal_mail_client::providers::api_send::DeliveryPath;
+use personal_mail_client::providers::autodiscover::{self, AutodiscoverResult};
+use personal_mail_client::providers::demo::{self, DemoConfig};
+use personal_mail_client::providers::diagnostics::{self, ConnectionDiagnostics};
+use personal_mail_client::providers::folders::{self, FolderNode, FolderOperation, FolderStatus};
+use personal_mail_client::providers::idle::{self, IdleExit};
+use personal_mail_client::providers::oauth;
+use personal_mail_client::providers::preflight::{self, LoginIssue, PreflightReport};
+use personal_mail_client::providers::session::{self, SessionHealth};
+use personal_mail_client::providers::smtp::{AttachmentData, OutgoingMessage};
+use personal_mail_client::providers::{self, ProviderError, SizeRange};
+use personal_mail_client::quarantine::QuarantineReason;
+use personal_mail_client::redact::Redacted;
+use personal_mail_client::relationships::{self, RelationshipStats};
+use personal_mail_client::remote_delete::ModeOverride;
+use personal_mail_client::send_guard::{
+    self, OutgoingAttachment, RecipientReview, Recipients, SendPolicy,
 };
-use personal_mail_client::providers::{self, ProviderError};
-use personal_mail_client::remote_delete::{ModeOverride, RemoteDeleteMetricsResponse};
+use personal_mail_client::send_insights::{self, SendInsights, SentMessage};
+use personal_mail_client::settings_bundle::{
+    self, BundleAccount, BundleSenderRule, BundleTemplate, SettingsPayload, SignedBundle,
+};
+use personal_mail_client::spam::{self, SpamLabel};
 use personal_mail_client::storage::{
-    AnalysisInsert, AnalysisValidation, DeletedMessageRow, MessageForAnalysis, MessageInsert,
-    SenderStatus, Storage,
+    sender_domain, AccountMigration, AnalysisCorrection, AnalysisCoverage, AnalysisExample,
+    AnalysisInsert, AnalysisValidation, AuditEntry, AutoReplyLogEntry, BlockNote, BlocklistMerge,
+    Bounce, CalDavLogEntry, Collection, CollectionItem, Contact, DeletedMessageRow, EmailTemplate,
+    ExistingAnalysisRecord, FlagConflict, FocusDecision, FocusMessage, LlmBenchmark,
+    MailMergeStatus, MessageForAnalysis, MessageIdentity, MessageInsert, MessageLink, MessageRow,
+    OutboxAttachment, OutboxInsert, PendingFlagChange, PendingWrite, ReplySuggestion,
+    ReviewQueueItem, SenderProfile, SenderRule, SenderStatus, StaleAnalysisFilter, Storage,
+    StorageHealthReport, TopicMessage, TopicSummary, GLOBAL_SCOPE, MANUAL_ORIGIN,
+    SENDER_PROFILE_MODEL_ID, TOMBSTONE_MOVED,
 };
+use personal_mail_client::subject_lines;
+use personal_mail_client::threads::{self, MutedThread};
+use personal_mail_client::topics::{self, TopicDocument};
+use personal_mail_client::trackers::Tracker;
+use personal_mail_client::uploads::{self, AttachmentUpload, UploadFile, UploadSettings};
+use personal_mail_client::vcard::{self, Card, CardEdit};
+use secrecy::zeroize::{Zeroize, Zeroizing};
+use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -27,16 +106,21 @@ use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use tauri::{Manager, State};
 use tokio::fs;
-use tokio::io::AsyncWriteExt;
 use tokio::time::{self, Duration, MissedTickBehavior};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn, Level};
 use uuid::Uuid;
 use warp::Filter;
 
-use chrono::{DateTime, Duration as ChronoDuration, NaiveDate, Utc};
+use chrono::{DateTime, Duration as ChronoDuration, Local, NaiveDate, Utc};
 use futures_util::{stream, StreamExt};
-use personal_mail_client::llm::{LlmService, LlmStatus};
+use personal_mail_client::llm::{LlmBackend, LlmService, LlmStatus, RequestPriority};
+use personal_mail_client::llm_mock::{self, MockResponses, MockRule};
+use personal_mail_client::llm_policy::{self, DataClass, LlmPrivacyPolicy};
+use personal_mail_client::lookalike;
+use personal_mail_client::mail_merge;
+use personal_mail_client::migration::{self, MigrationOptions};
+use personal_mail_client::model_download::{self, DownloadProgress, DownloadSettings};
 
 fn init_tracing() {
     let _ = tracing_subscriber::fmt()
@@ -45,30 +129,111 @@ fn init_tracing() {
                 .unwrap_or_else(|_| "personal_mail_client=info,tauri=info".into()),
         )
         .with_max_level(Level::INFO)
+        .with_writer(Redacted(std::io::stdout))
         .try_init();
 }
 
+/// A cached message as the listing commands return it. Optional fields
+/// that are empty, or left out of the caller's field mask, are omitted.
 #[derive(Serialize)]
 struct MessageItem {
-    uid: String,
+    #[serde(with = "uid_string")]
+    uid: u32,
+    message_key: String,
     subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     snippet: Option<String>,
     status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     flags: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_summary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_sentiment: Option<String>,
     analysis_categories: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_metadata: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_model_id: Option<String>,
     analysis_analyzed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_analyzed_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_confidence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_validator_model_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_validation_status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_validation_confidence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_validation_notes: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     analysis_validated_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spam_score: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_bytes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thread_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct QueriedMessage {
+    sender_email: String,
+    sender_display: String,
+    #[serde(flatten)]
+    message: MessageItem,
+}
+
+#[derive(Serialize)]
+struct MessageQueryPage {
+    messages: Vec<QueriedMessage>,
+    next_cursor: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct CalDavExportReport {
+    pushed: usize,
+    already_present: usize,
+    failed: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct CardDavSyncReport {
+    pulled: usize,
+    pushed: usize,
+    conflicts: usize,
+    failed: usize,
+    /// Contacts whose card was deleted on the server.
+    unlinked: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct VCardImportReport {
+    /// Cards in the file.
+    cards: usize,
+    /// Cards added; the rest were already in the contacts table.
+    imported: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct VCardExportReport {
+    path: String,
+    contacts: usize,
+}
+
+#[derive(Serialize)]
+struct QuarantinedMessageResponse {
+    sender_email: String,
+    sender_display: String,
+    score: f64,
+    reasons: Vec<QuarantineReason>,
+    quarantined_at: i64,
+    #[serde(flatten)]
+    message: MessageItem,
 }
 
 #[derive(Serialize)]
@@ -76,18 +241,37 @@ struct SenderGroupResponse {
     sender_email: String,
     sender_display: String,
     status: String,
+    profile: Option<SenderProfile>,
+    block_note: Option<BlockNote>,
+    /// How ignorable the sender is; `None` without cached mail.
+    noise: Option<NoiseScore>,
     message_count: usize,
     messages: Vec<MessageItem>,
 }
 
 #[derive(Serialize)]
-struct SyncProgressPayload {
-    email: String,
-    batch: usize,
-    total_batches: usize,
-    fetched: usize,
-    stored: usize,
-    elapsed_ms: u64,
+struct SenderGroupHeaderResponse {
+    sender_email: String,
+    sender_display: String,
+    status: String,
+    profile: Option<SenderProfile>,
+    block_note: Option<BlockNote>,
+    noise: Option<NoiseScore>,
+    message_count: i64,
+    unread_count: i64,
+    latest_date: Option<String>,
+}
+
+/// A cleanup candidate: a sender whose mail the user mostly ignores.
+#[derive(Serialize)]
+struct NoisySenderResponse {
+    sender_email: String,
+    sender_display: String,
+    status: String,
+    message_count: i64,
+    unread_count: i64,
+    latest_date: Option<String>,
+    noise: NoiseScore,
 }
 
 const AUTO_WINDOW_DAYS: i64 = 30;
@@ -146,7 +330,8 @@ async fn run_provider_fetch(
     flow_label: &'static str,
     aggregation: &mut SyncAggregation,
 ) -> Result<WindowOutcome, String> {
-    let (mut batch_rx, producer_handle) = providers::fetch_all(credentials, since_uid, chunk, window)
+    let lite = lite_sync_enabled(storage, normalized_email).await;
+    let (mut batch_rx, producer_handle) = providers::fetch_all(credentials, since_uid, chunk, window, lite)
         .await
         .map_err(|err| {
             error!(account = %normalized_email, mode = flow_label, ?err, "mailbox fetch start failed");
@@ -156,6 +341,7 @@ async fn run_provider_fetch(
     let mut window_fetched = 0usize;
     let mut window_stored = 0usize;
     let mut totals_recorded = false;
+    let mut arrived = Vec::new();
 
     while let Some(batch_result) = batch_rx.recv().await {
         if batch_result.messages.is_empty() {
@@ -165,47 +351,58 @@ async fn run_provider_fetch(
         if !totals_recorded {
             aggregation.total_batches += batch_result.total;
             totals_recorded = true;
+            if let Some(uid_validity) = batch_result.uid_validity {
+                match storage
+                    .record_uid_validity(normalized_email, "INBOX", uid_validity)
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(purged) => {
+                        warn!(account = %normalized_email, purged, "INBOX UIDVALIDITY changed; dropped cached messages");
+                    }
+                    Err(err) => {
+                        error!(account = %normalized_email, ?err, "failed to record INBOX UIDVALIDITY");
+                    }
+                }
+            }
         }
 
         let mut inserts = Vec::with_capacity(batch_result.messages.len());
         let mut analyses = Vec::with_capacity(batch_result.messages.len());
+        let mut summaries = Vec::with_capacity(batch_result.messages.len());
 
         for envelope in batch_result.messages {
-            let flags_slice = if envelope.flags.is_empty() {
-                None
-            } else {
-                Some(envelope.flags.as_slice())
-            };
-
             let (insert, analysis) = build_records(
                 normalized_email,
                 &envelope.summary,
                 envelope.snippet.clone(),
                 envelope.body.clone(),
-                flags_slice,
+                Some(envelope.flags.as_slice()),
             );
 
             inserts.push(insert);
             analyses.push(analysis);
+            summaries.push(envelope.summary);
         }
 
         aggregation.total_fetched += inserts.len();
         window_fetched += inserts.len();
 
-        if let Err(err) = storage.upsert_messages(inserts).await {
-            error!(account = %normalized_email, mode = flow_label, ?err, "failed to persist messages after sync batch");
-        } else {
-            aggregation.total_stored += batch_result.fetched;
-            window_stored += batch_result.fetched;
-        }
-
-        if let Err(err) = storage.upsert_analysis(analyses).await {
-            error!(account = %normalized_email, mode = flow_label, ?err, "failed to persist analyses after sync batch");
+        match storage.upsert_batch(inserts, analyses).await {
+            Ok(conflicts) => {
+                aggregation.total_stored += batch_result.fetched;
+                window_stored += batch_result.fetched;
+                emit_flag_conflicts(app, normalized_email, conflicts);
+            }
+            Err(err) => {
+                error!(account = %normalized_email, mode = flow_label, ?err, "failed to persist sync batch");
+            }
         }
+        arrived.extend(summaries);
 
         aggregation.completed_batches += 1;
 
-        let payload = SyncProgressPayload {
+        let progress = SyncProgress {
             email: normalized_email.to_string(),
             batch: aggregation.completed_batches,
             total_batches: aggregation.total_batches,
@@ -213,12 +410,12 @@ async fn run_provider_fetch(
             stored: aggregation.total_stored,
             elapsed_ms: started.elapsed().as_millis() as u64,
         };
-
-        if let Err(err) = app.emit_to("main", "full-sync-progress", &payload) {
-            warn!(account = %normalized_email, mode = flow_label, ?err, "failed to emit sync progress event");
-        }
+        events::emit(app, &progress);
     }
 
+    // What was stored is processed even if the fetch stopped partway.
+    process_synced_mail(app, storage, credentials, normalized_email, &arrived).await;
+
     producer_handle
         .await
         .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))
@@ -228,6 +425,20 @@ async fn run_provider_fetch(
             provider_error_to_message(err)
         })?;
 
+    // The UI lists the account right after a sync; have the text ready.
+    if window_stored > 0 {
+        let storage = storage.clone();
+        let account = normalized_email.to_string();
+        tauri::async_runtime::spawn(async move {
+            if let Err(err) = storage
+                .prewarm_decrypt_cache(&account, DECRYPT_PREWARM_LIMIT)
+                .await
+            {
+                warn!(%account, ?err, "failed to pre-warm decrypt cache");
+            }
+        });
+    }
+
     Ok(WindowOutcome {
         fetched: window_fetched,
         stored: window_stored,
@@ -249,10 +460,92 @@ struct KnownModelResponse {
     downloaded: bool,
     active: bool,
     installed_size_bytes: Option<u64>,
+    benchmark: Option<LlmBenchmark>,
 }
 
-const KEYCHAIN_SERVICE: &str = "PersonalMailClient";
+/// Representative mail used by `benchmark_llm_model`: (sender, subject, snippet).
+const BENCHMARK_SAMPLES: &[(&str, &str, &str)] = &[
+    (
+        "billing@utility.example",
+        "Your March statement is ready",
+        "Your statement for account ending 4411 is now available. Amount due: $84.20 by April 12. Log in to view details or set up autopay.",
+    ),
+    (
+        "sam.rivera@example.com",
+        "Re: Thursday planning sync",
+        "Thanks for the notes. Can we move the sync to 3pm? I also need your feedback on the Q3 draft before Friday, especially the hiring section.",
+    ),
+    (
+        "no-reply@security.example",
+        "New sign-in to your account",
+        "We noticed a new sign-in from Chrome on Windows near Berlin. If this was you, no action is needed. Otherwise reset your password immediately.",
+    ),
+    (
+        "news@store.example",
+        "48 hours only: 30% off everything",
+        "Our spring sale is here. Use code SPRING30 at checkout. Free shipping over $50. Unsubscribe or manage preferences at any time.",
+    ),
+];
+
+const WIPE_TOKEN_SETTING_KEY: &str = "wipe_confirm_token";
+const WIPE_TOKEN_TTL_SECS: i64 = 120;
+/// Keychain entries saved for services rather than accounts. The keychain
+/// cannot be listed, so this is how a wipe finds them.
+const SERVICE_SECRETS_SETTING_KEY: &str = "service_keychain_entries";
+const STORAGE_HEALTH_SETTING_KEY: &str = "storage_health_last";
+/// Minimum age of the newest backup before startup writes another.
+const STORAGE_BACKUP_INTERVAL_SECS: i64 = 24 * 60 * 60;
 const LLM_MODEL_SETTING_KEY: &str = "llm_model_path";
+const LLM_REDACT_PROMPTS_SETTING_KEY: &str = "llm_redact_prompts";
+const LLM_BACKEND_SETTING_KEY: &str = "llm_backend";
+/// What template and quick reply prompts carry from a message.
+const MESSAGE_PREVIEW_CLASSES: &[DataClass] =
+    &[DataClass::Sender, DataClass::Subject, DataClass::Snippet];
+/// What draft rewrite and subject prompts carry: only the text the user
+/// typed.
+const DRAFT_REWRITE_CLASSES: &[DataClass] = &[DataClass::UserPrompt];
+/// What one-time code confirmation prompts carry.
+const OTP_CONFIRM_CLASSES: &[DataClass] = &[DataClass::Subject, DataClass::Body];
+const OTP_CONFIRM_MAX_TOKENS: usize = 4;
+/// Completion budget for checking a bulk analysis.
+const VALIDATION_MAX_TOKENS: usize = 128;
+/// What quick replies carry when they offer free times, which are read from
+/// invitations in message bodies.
+const REPLY_WITH_AVAILABILITY_CLASSES: &[DataClass] = &[
+    DataClass::Sender,
+    DataClass::Subject,
+    DataClass::Snippet,
+    DataClass::Body,
+];
+/// Weekdays of free time offered in a reply when the message names no day.
+const AVAILABILITY_REPLY_DAYS: usize = 3;
+/// Most events pushed to the CalDAV calendar in one export.
+const CALDAV_PUSH_BATCH: usize = 100;
+const MODELS_QUOTA_SETTING_KEY: &str = "models_quota_bytes";
+const MODEL_DOWNLOAD_SETTING_KEY: &str = "model_download_settings";
+const STRIP_TRACKERS_SETTING_KEY: &str = "strip_trackers";
+const TOMBSTONE_RETENTION_SETTING_KEY: &str = "tombstone_retention_days";
+/// How long deleted, moved, or vanished messages keep their cached rows.
+const DEFAULT_TOMBSTONE_RETENTION_DAYS: u32 = 30;
+const TOMBSTONE_PURGE_INTERVAL_SECS: u64 = 6 * 60 * 60;
+const SENDER_MUTE_CHECK_INTERVAL_SECS: u64 = 60;
+const FOCUS_REVIEW_CHECK_INTERVAL_SECS: u64 = 60;
+const OTP_EXPIRY_CHECK_INTERVAL_SECS: u64 = 30;
+const OUTBOX_DRAIN_INTERVAL_SECS: u64 = 30;
+/// Backoff between attempts to reopen a dropped IDLE connection.
+const IDLE_RETRY_MIN_SECS: u64 = 5;
+const IDLE_RETRY_MAX_SECS: u64 = 5 * 60;
+/// Newest INBOX messages fetched when IDLE reports new mail.
+const IDLE_SYNC_LIMIT: usize = 50;
+/// Held messages listed in one focus review.
+const FOCUS_REVIEW_LIMIT: usize = 200;
+/// Focus mode only judges mail received this recently, so a resync of old
+/// mail raises no alerts.
+const FOCUS_FRESH_SECS: i64 = 24 * 60 * 60;
+/// How many of the newest Sent folder messages insights look at.
+const SENT_SYNC_LIMIT: usize = 500;
+/// Free space a download must leave behind: 5% of the model, at least this.
+const MODEL_DOWNLOAD_MIN_HEADROOM_BYTES: u64 = 512 * 1024 * 1024;
 const DEFAULT_LLM_MODEL_ID: &str = "tinyllama-1.1b-q4";
 
 #[derive(Clone, Copy)]
@@ -349,9 +642,117 @@ const BULK_RISK_VALUES: &[&str] = &[
 const BULK_SOURCE_VALUES: &[&str] = &["human", "automated system", "bot/no-reply"];
 const BULK_THREAD_ROLE_VALUES: &[&str] = &["new thread", "reply", "forward", "digest"];
 const BULK_LIFECYCLE_VALUES: &[&str] = &["new", "snoozed", "pending", "done", "archived"];
+/// Actions [`bulk_action`] accepts.
+const BULK_ACTIONS: &[&str] = &[
+    "mark_read",
+    "mark_unread",
+    "label",
+    "unlabel",
+    "move",
+    "delete",
+    "block_sender",
+];
+/// Times a bulk move is tried on the server before it is undone locally.
+const BULK_MOVE_ATTEMPTS: u32 = 3;
+/// Wait before retrying a bulk move, multiplied by the attempt number.
+const BULK_MOVE_RETRY_SECS: u64 = 5;
+/// Failed pushes after which a queued flag edit is undone locally.
+const MAX_FLAG_PUSH_ATTEMPTS: u32 = 5;
 const BULK_ANALYSIS_CONCURRENCY: usize = 3;
 const DEFAULT_BULK_COMPLETION_TOKENS: usize = 512;
 const DEFAULT_BULK_SNIPPET_CHARS: usize = 2048;
+/// With full-body analysis on, snippets shorter than this are replaced by
+/// the cleaned body when it has more to say.
+const FULL_BODY_MIN_SNIPPET_CHARS: usize = 400;
+const FULL_BODY_MAX_CHARS: usize = 6000;
+/// Analyses below this confidence are routed to the human review queue.
+const REVIEW_CONFIDENCE_THRESHOLD: f64 = 0.6;
+/// Reply kinds offered as one-tap responses, in display order.
+const QUICK_REPLY_KINDS: &[&str] = &["acknowledge", "accept", "decline"];
+const QUICK_REPLY_MAX_CHARS: usize = 280;
+/// Default outbox pacing for mail merges, to stay under provider send limits.
+const DEFAULT_MERGE_RATE_PER_MINUTE: u32 = 20;
+/// UIDs per FETCH when hydrating lite-synced messages.
+const HYDRATE_BATCH_SIZE: usize = 50;
+/// Messages [`find_messages_by_size`] returns unless asked for more.
+const SIZE_SEARCH_DEFAULT_LIMIT: usize = 100;
+/// Newest messages decrypted into the storage cache after a sync stores mail.
+const DECRYPT_PREWARM_LIMIT: usize = 500;
+/// Messages with less text than this are treated as possibly image-only.
+const OCR_MAX_SNIPPET_CHARS: usize = 40;
+const OCR_BATCH_LIMIT: usize = 100;
+/// Messages fetched per attachment indexing run when no UIDs are given.
+const ATTACHMENT_INDEX_BATCH_LIMIT: usize = 200;
+/// How long a folder listing (and its STATUS counts) is reused.
+const FOLDER_TREE_TTL_SECS: u64 = 120;
+/// Upper bound on topics produced for one account.
+const MAX_TOPICS_PER_ACCOUNT: usize = 24;
+/// Minimum analyzed messages before a sender profile is trusted.
+const SENDER_PROFILE_MIN_SAMPLES: usize = 5;
+/// Share of a sender's mail that must agree before new mail is pre-filled.
+const SENDER_PROFILE_MIN_CONFIDENCE: f64 = 0.8;
+/// Maximum number of user corrections injected into each bulk prompt.
+const FEW_SHOT_EXAMPLE_LIMIT: usize = 5;
+/// Number of recent corrections loaded per run to pick relevant examples from.
+const FEW_SHOT_POOL_SIZE: usize = 500;
+/// Bump whenever `build_bulk_prompt` changes in a way that affects results.
+const BULK_PROMPT_TEMPLATE_VERSION: &str = "bulk-v5";
+
+/// Which cached messages a bulk analysis run should pick up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BulkTarget {
+    /// Only messages that have never been analyzed.
+    Unanalyzed,
+    /// Every message, re-analyzing existing results.
+    All,
+    /// Unanalyzed messages plus failed validations and results from another model.
+    Uncovered,
+    /// Only analyses flagged stale after a model, template, or taxonomy change.
+    Stale,
+}
+
+impl BulkTarget {
+    fn as_str(&self) -> &'static str {
+        match self {
+            BulkTarget::Unanalyzed => "unanalyzed",
+            BulkTarget::All => "all",
+            BulkTarget::Uncovered => "uncovered",
+            BulkTarget::Stale => "stale",
+        }
+    }
+
+    fn includes(
+        &self,
+        message: &MessageForAnalysis,
+        active_model_id: Option<&str>,
+        fingerprint: &str,
+    ) -> bool {
+        let existing = &message.existing_analysis;
+        // Analyses copied from a sender profile never carry the active model
+        // or fingerprint; they only go out of date when marked stale.
+        let from_profile = existing.model_id.as_deref() == Some(SENDER_PROFILE_MODEL_ID);
+        let outdated = existing.analyzed
+            && (existing.stale
+                || (!from_profile && existing.fingerprint.as_deref() != Some(fingerprint)));
+        match self {
+            BulkTarget::All => true,
+            BulkTarget::Unanalyzed => !existing.analyzed,
+            BulkTarget::Stale => outdated,
+            BulkTarget::Uncovered => {
+                if !existing.analyzed || existing.stale {
+                    return true;
+                }
+                if existing.validation_status.as_deref() == Some("failed") {
+                    return true;
+                }
+                match active_model_id {
+                    Some(active) => !from_profile && existing.model_id.as_deref() != Some(active),
+                    None => false,
+                }
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 struct NormalizedBulkAnalysis {
@@ -362,10 +763,22 @@ struct NormalizedBulkAnalysis {
     metadata: Value,
 }
 
-fn emit_bulk_event(app: &tauri::AppHandle, payload: Value) {
-    if let Err(err) = app.emit_all("llm-bulk-analysis-progress", payload) {
-        warn!(?err, "failed to emit llm bulk analysis event");
-    }
+/// Identifies the model, prompt template, and tag taxonomy that produced an analysis.
+fn analysis_fingerprint(model_id: Option<&str>, allowed_tags: &[String]) -> String {
+    let mut tags = allowed_tags
+        .iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .collect::<Vec<_>>();
+    tags.sort();
+    tags.dedup();
+
+    let mut hasher = Sha256::new();
+    hasher.update(model_id.unwrap_or("").as_bytes());
+    hasher.update(b"\n");
+    hasher.update(BULK_PROMPT_TEMPLATE_VERSION.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(tags.join(",").as_bytes());
+    hex::encode(hasher.finalize())
 }
 
 fn clip_text(input: &str, max_len: usize) -> String {
@@ -615,8 +1028,12 @@ fn normalize_bulk_output(
     })
 }
 
+/// Where a bulk run reports its progress: the UI, or a test collecting it.
+type ProgressSink = Arc<dyn Fn(&BulkAnalysisProgress) + Send + Sync>;
+
 async fn process_bulk_message(
-    app: tauri::AppHandle,
+    report: ProgressSink,
+    connected: Arc<HashMap<String, Credentials>>,
     storage: Storage,
     llm: LlmService,
     message: MessageForAnalysis,
@@ -626,100 +1043,88 @@ async fn process_bulk_message(
     skipped_existing: usize,
     max_tokens: usize,
     snippet_limit: usize,
+    full_body: bool,
     model_id: Option<String>,
     validator_model_id: Option<String>,
+    fingerprint: String,
+    fast_path: bool,
+    examples: Arc<Vec<AnalysisExample>>,
     completed: Arc<AtomicUsize>,
     failed: Arc<AtomicUsize>,
 ) {
-    let prompt = build_bulk_prompt(allowed_tags.as_slice(), &message, snippet_limit);
     let account_email = message.account_email.clone();
-    let message_uid = message.uid.clone();
+    let message_uid = message.uid;
 
-    let response = match llm.analyze_prompt(prompt, Some(max_tokens)).await {
-        Ok(text) => text,
-        Err(err) => {
-            let failed_now = failed.fetch_add(1, Ordering::SeqCst) + 1;
-            let completed_now = completed.load(Ordering::SeqCst);
-            let pending = total.saturating_sub(completed_now + failed_now);
-            emit_bulk_event(
-                &app,
-                json!({
-                    "runId": run_id.clone(),
-                    "status": "error",
-                    "stage": "llm",
-                    "error": err,
-                    "accountEmail": account_email,
-                    "messageUid": message_uid,
-                    "total": total,
-                    "completed": completed_now,
-                    "failed": failed_now,
-                    "skipped": skipped_existing,
-                    "pending": pending,
-                    "timestamp": Utc::now().timestamp(),
-                    "modelId": model_id.clone(),
-                    "validatorModelId": validator_model_id.clone(),
-                }),
-            );
-            return;
-        }
+    let fast_result = if fast_path {
+        classify_with_rules(&message, allowed_tags.as_slice())
+    } else {
+        None
+    };
+    let classifier_label = if fast_result.is_some() {
+        "rules"
+    } else {
+        "llm"
     };
 
-    let parsed = match parse_bulk_json(&response) {
-        Ok(value) => value,
-        Err(err) => {
-            let failed_now = failed.fetch_add(1, Ordering::SeqCst) + 1;
-            let completed_now = completed.load(Ordering::SeqCst);
-            let pending = total.saturating_sub(completed_now + failed_now);
-            emit_bulk_event(
-                &app,
-                json!({
-                    "runId": run_id.clone(),
-                    "status": "error",
-                    "stage": "parse",
-                    "error": err,
-                    "accountEmail": account_email,
-                    "messageUid": message_uid,
-                    "total": total,
-                    "completed": completed_now,
-                    "failed": failed_now,
-                    "skipped": skipped_existing,
-                    "pending": pending,
-                    "timestamp": Utc::now().timestamp(),
-                    "modelId": model_id.clone(),
-                    "validatorModelId": validator_model_id.clone(),
-                }),
-            );
-            return;
-        }
+    // Optional content the privacy policy keeps from the model is left out
+    // rather than failing the message.
+    let body = if full_body && fast_result.is_none() && llm.permits(&[DataClass::Body]) {
+        full_body_text(&storage, connected.get(&message.account_email), &message).await
+    } else {
+        None
+    };
+    let ocr_text = if fast_result.is_none() && llm.permits(&[DataClass::Attachments]) {
+        storage
+            .message_ocr_text(&message.account_email, &message.folder, message.uid)
+            .await
+            .unwrap_or_else(|err| {
+                warn!(uid = %message.uid, ?err, "failed to load OCR text for analysis");
+                None
+            })
+    } else {
+        None
     };
 
-    let normalized = match normalize_bulk_output(parsed, allowed_tags.as_slice()) {
-        Ok(value) => value,
-        Err(err) => {
-            let failed_now = failed.fetch_add(1, Ordering::SeqCst) + 1;
-            let completed_now = completed.load(Ordering::SeqCst);
-            let pending = total.saturating_sub(completed_now + failed_now);
-            emit_bulk_event(
-                &app,
-                json!({
-                    "runId": run_id.clone(),
-                    "status": "error",
-                    "stage": "normalize",
-                    "error": err,
-                    "accountEmail": account_email,
-                    "messageUid": message_uid,
-                    "total": total,
-                    "completed": completed_now,
-                    "failed": failed_now,
-                    "skipped": skipped_existing,
-                    "pending": pending,
-                    "timestamp": Utc::now().timestamp(),
-                    "modelId": model_id.clone(),
-                    "validatorModelId": validator_model_id.clone(),
-                }),
-            );
-            return;
-        }
+    let normalized = match fast_result {
+        Some(value) => value,
+        None => match run_bulk_llm(
+            &llm,
+            allowed_tags.as_slice(),
+            &message,
+            body.as_deref().map(String::as_str),
+            ocr_text.as_deref(),
+            snippet_limit,
+            max_tokens,
+            examples.as_slice(),
+        )
+        .await
+        {
+            Ok(value) => value,
+            Err((stage, err)) => {
+                let failed_now = failed.fetch_add(1, Ordering::SeqCst) + 1;
+                let completed_now = completed.load(Ordering::SeqCst);
+                let pending = total.saturating_sub(completed_now + failed_now);
+                let progress = BulkAnalysisProgress {
+                    run_id: run_id.clone(),
+                    status: BulkAnalysisStatus::Error,
+                    total,
+                    completed: completed_now,
+                    failed: failed_now,
+                    skipped: skipped_existing,
+                    pending,
+                    model_id: model_id.clone(),
+                    validator_model_id: validator_model_id.clone(),
+                    timestamp: Utc::now().timestamp(),
+                    account_email: Some(account_email),
+                    message_uid: Some(message_uid),
+                    stage: Some(stage.to_string()),
+                    error: Some(err),
+                    ..Default::default()
+                };
+                report(&progress);
+                return;
+            }
+        },
     };
 
     let summary = normalized.summary.clone();
@@ -731,7 +1136,7 @@ async fn process_bulk_message(
     if let Some(object) = metadata.as_object_mut() {
         object.insert("run_id".to_string(), json!(run_id.clone()));
         object.insert("account_email".to_string(), json!(account_email.clone()));
-        object.insert("uid".to_string(), json!(message_uid.clone()));
+        object.insert("uid".to_string(), json!(message_uid.to_string()));
         object.insert("tags".to_string(), json!(tags.clone()));
         object.insert("summary".to_string(), json!(summary.clone()));
         object.insert("sentiment".to_string(), json!(sentiment.clone()));
@@ -745,23 +1150,44 @@ async fn process_bulk_message(
         if let Some(model) = &model_id {
             object.insert("model_id".to_string(), json!(model));
         }
+        if classifier_label == "llm" {
+            let source = if body.is_some() { "body" } else { "snippet" };
+            object.insert("input_source".to_string(), json!(source));
+            object.insert("ocr_derived".to_string(), json!(ocr_text.is_some()));
+        }
+        match storage
+            .message_links(&message.account_email, &message.folder, message.uid)
+            .await
+        {
+            Ok(links) if !links.is_empty() => {
+                object.insert("link_signals".to_string(), link_signals(&links));
+            }
+            Ok(_) => {}
+            Err(err) => warn!(uid = %message.uid, ?err, "failed to load message links"),
+        }
+        if let Some((_, domain)) = message.sender_email.rsplit_once('@') {
+            match known_sender_domains(&storage, &message.account_email).await {
+                Ok(known) => {
+                    let known = known.iter().map(String::as_str);
+                    if let Some(found) = lookalike::check(domain, known) {
+                        object.insert("lookalike_sender".to_string(), json!(found));
+                    }
+                }
+                Err(err) => warn!(uid = %message.uid, %err, "failed to load known sender domains"),
+            }
+        }
     }
 
-    let validation = if let Some(validator) = &validator_model_id {
-        AnalysisValidation {
-            validator_model_id: Some(validator.clone()),
-            status: Some("pending".to_string()),
-            confidence: None,
-            notes: None,
-            validated_at: None,
-        }
-    } else {
-        AnalysisValidation::default()
+    let validation = match &validator_model_id {
+        Some(validator) => validate_bulk_analysis(&llm, &message, &normalized, validator).await,
+        None => AnalysisValidation::default(),
     };
 
     let analysis = AnalysisInsert {
+        message_key: Some(message.message_key.clone()),
         account_email: message.account_email.clone(),
-        uid: message.uid.clone(),
+        folder: message.folder.clone(),
+        uid: message.uid,
         summary: summary.clone(),
         sentiment: sentiment.clone(),
         categories: tags.clone(),
@@ -771,6 +1197,7 @@ async fn process_bulk_message(
         analyzed_at: Some(Utc::now().timestamp()),
         analysis_confidence: confidence,
         validation,
+        fingerprint: Some(fingerprint),
     };
 
     if let Err(err) = storage
@@ -781,25 +1208,24 @@ async fn process_bulk_message(
         let failed_now = failed.fetch_add(1, Ordering::SeqCst) + 1;
         let completed_now = completed.load(Ordering::SeqCst);
         let pending = total.saturating_sub(completed_now + failed_now);
-        emit_bulk_event(
-            &app,
-            json!({
-                "runId": run_id.clone(),
-                "status": "error",
-                "stage": "storage",
-                "error": err,
-                "accountEmail": account_email,
-                "messageUid": message_uid,
-                "total": total,
-                "completed": completed_now,
-                "failed": failed_now,
-                "skipped": skipped_existing,
-                "pending": pending,
-                "timestamp": Utc::now().timestamp(),
-                "modelId": model_id.clone(),
-                "validatorModelId": validator_model_id.clone(),
-            }),
-        );
+        let progress = BulkAnalysisProgress {
+            run_id: run_id.clone(),
+            status: BulkAnalysisStatus::Error,
+            total,
+            completed: completed_now,
+            failed: failed_now,
+            skipped: skipped_existing,
+            pending,
+            model_id: model_id.clone(),
+            validator_model_id: validator_model_id.clone(),
+            timestamp: Utc::now().timestamp(),
+            account_email: Some(account_email),
+            message_uid: Some(message_uid),
+            stage: Some("storage".to_string()),
+            error: Some(err),
+            ..Default::default()
+        };
+        report(&progress);
         return;
     }
 
@@ -807,36 +1233,301 @@ async fn process_bulk_message(
     let failed_now = failed.load(Ordering::SeqCst);
     let pending = total.saturating_sub(completed_now + failed_now);
 
-    emit_bulk_event(
-        &app,
-        json!({
-            "runId": run_id,
-            "status": "processed",
-            "accountEmail": account_email,
-            "messageUid": message_uid,
-            "total": total,
-            "completed": completed_now,
-            "failed": failed_now,
-            "skipped": skipped_existing,
-            "pending": pending,
-            "timestamp": Utc::now().timestamp(),
-            "modelId": model_id,
-            "validatorModelId": validator_model_id,
-            "result": {
-                "summary": summary,
-                "sentiment": sentiment,
-                "tags": tags,
-                "confidence": confidence,
-                "metadata": metadata,
-            }
+    let progress = BulkAnalysisProgress {
+        run_id,
+        status: BulkAnalysisStatus::Processed,
+        total,
+        completed: completed_now,
+        failed: failed_now,
+        skipped: skipped_existing,
+        pending,
+        model_id,
+        validator_model_id,
+        timestamp: Utc::now().timestamp(),
+        account_email: Some(account_email),
+        message_uid: Some(message_uid),
+        classifier: Some(classifier_label.to_string()),
+        result: Some(BulkAnalysisResult {
+            summary,
+            sentiment,
+            tags,
+            confidence,
+            metadata,
         }),
+        ..Default::default()
+    };
+    report(&progress);
+}
+
+/// Phishing signal from a message's links: how many disguise their target
+/// or point at a blocklisted domain, and which domains those are.
+fn link_signals(links: &[MessageLink]) -> Value {
+    let suspicious = links
+        .iter()
+        .filter(|link| link.mismatch || link.blocked)
+        .collect::<Vec<_>>();
+    let mut domains = suspicious
+        .iter()
+        .map(|link| link.domain.clone())
+        .collect::<Vec<_>>();
+    domains.sort();
+    domains.dedup();
+    json!({
+        "total": links.len(),
+        "mismatched": suspicious.iter().filter(|link| link.mismatch).count(),
+        "blocked": suspicious.iter().filter(|link| link.blocked).count(),
+        "suspicious_domains": domains,
+        "phishing_suspect": !suspicious.is_empty(),
+    })
+}
+
+/// Cleaned body text for messages whose snippet says too little, or `None`
+/// to analyze the snippet as usual. Headers-only accounts have no cached
+/// body, so theirs is fetched with `credentials` and dropped after analysis.
+async fn full_body_text(
+    storage: &Storage,
+    credentials: Option<&Credentials>,
+    message: &MessageForAnalysis,
+) -> Option<Zeroizing<String>> {
+    let snippet_len = message
+        .snippet
+        .as_deref()
+        .map_or(0, |snippet| snippet.len());
+    if snippet_len >= FULL_BODY_MIN_SNIPPET_CHARS {
+        return None;
+    }
+    let text = match storage
+        .message_body(&message.account_email, &message.folder, message.uid)
+        .await
+    {
+        Ok(Some(raw)) => body_text::decode_body_text(&raw),
+        Ok(None) => {
+            let parts =
+                transient_body_parts(storage, credentials?, &message.folder, message.uid).await?;
+            body_text::parts_text(&parts)
+        }
+        Err(err) => {
+            warn!(uid = %message.uid, ?err, "failed to load body for analysis");
+            return None;
+        }
+    };
+    let text = Zeroizing::new(body_text::strip_quotes_and_signature(&text));
+    (text.len() > snippet_len).then_some(text)
+}
+
+/// Sends a message through the model and normalizes the reply. Errors carry
+/// the stage that failed so progress events can report it.
+async fn run_bulk_llm(
+    llm: &LlmService,
+    allowed_tags: &[String],
+    message: &MessageForAnalysis,
+    body: Option<&str>,
+    ocr_text: Option<&str>,
+    snippet_limit: usize,
+    max_tokens: usize,
+    examples: &[AnalysisExample],
+) -> Result<NormalizedBulkAnalysis, (&'static str, String)> {
+    let relevant_examples =
+        select_relevant_examples(examples, &message.sender_email, FEW_SHOT_EXAMPLE_LIMIT);
+    let mut clipped_snippet = match body {
+        Some(body) => clip_text(body, FULL_BODY_MAX_CHARS),
+        None => clip_text(
+            message
+                .snippet
+                .as_deref()
+                .unwrap_or("(no snippet available)"),
+            snippet_limit,
+        ),
+    };
+    if let Some(ocr_text) = ocr_text {
+        clipped_snippet = format!(
+            "{clipped_snippet}\n\n[Text recognized in images]\n{}",
+            clip_text(ocr_text, FULL_BODY_MAX_CHARS)
+        );
+    }
+    let template = build_bulk_prompt(allowed_tags, message, "", &relevant_examples);
+    let budgeted_snippet = llm
+        .fit_text_to_budget(&template, &clipped_snippet, max_tokens)
+        .unwrap_or_else(|err| {
+            debug!(%err, "token budgeting unavailable; using character clipping");
+            clipped_snippet
+        });
+    let prompt = build_bulk_prompt(allowed_tags, message, &budgeted_snippet, &relevant_examples);
+    let mut classes = vec![DataClass::Sender, DataClass::Subject, DataClass::Snippet];
+    if body.is_some() {
+        classes.push(DataClass::Body);
+    }
+    if ocr_text.is_some() {
+        classes.push(DataClass::Attachments);
+    }
+
+    let response = llm
+        .analyze_prompt_with_priority(prompt, Some(max_tokens), RequestPriority::Bulk, &classes)
+        .await
+        .map_err(|err| ("llm", err))?;
+    let parsed = parse_bulk_json(&response).map_err(|err| ("parse", err))?;
+    normalize_bulk_output(parsed, allowed_tags).map_err(|err| ("normalize", err))
+}
+
+/// Asks the model to check a classification. It runs in the validation
+/// lane, so it goes ahead of the rest of the bulk run; a rejected result
+/// lands in the review queue.
+async fn validate_bulk_analysis(
+    llm: &LlmService,
+    message: &MessageForAnalysis,
+    analysis: &NormalizedBulkAnalysis,
+    validator_model_id: &str,
+) -> AnalysisValidation {
+    let prompt = format!(
+        "You check an email classification. Respond with JSON only: \
+         {{\"valid\": true or false, \"confidence\": number from 0-1, \"notes\": short string}}\n\n\
+         From: {sender}\nSubject: {subject}\n{snippet}\n\n\
+         Classification:\n- tags: [{tags}]\n- summary: {summary}\n- sentiment: {sentiment}",
+        sender = message.sender_email,
+        subject = clip_text(&message.subject, 240),
+        snippet = clip_text(message.snippet.as_deref().unwrap_or_default(), 600),
+        tags = analysis.tags.join(", "),
+        summary = analysis.summary.as_deref().unwrap_or("null"),
+        sentiment = analysis.sentiment.as_deref().unwrap_or("null"),
     );
+    let classes = [DataClass::Sender, DataClass::Subject, DataClass::Snippet];
+    let checked = llm
+        .analyze_prompt_with_priority(
+            prompt,
+            Some(VALIDATION_MAX_TOKENS),
+            RequestPriority::Validation,
+            &classes,
+        )
+        .await
+        .and_then(|raw| parse_bulk_json(&raw));
+
+    let mut validation = AnalysisValidation {
+        validator_model_id: Some(validator_model_id.to_string()),
+        validated_at: Some(Utc::now().timestamp()),
+        ..Default::default()
+    };
+    match checked {
+        Ok(parsed) => {
+            let status = match parsed.get("valid").and_then(Value::as_bool) {
+                Some(true) => "passed",
+                Some(false) => "failed",
+                None => "error",
+            };
+            validation.status = Some(status.to_string());
+            validation.confidence = parsed
+                .get("confidence")
+                .and_then(Value::as_f64)
+                .map(|value| value.clamp(0.0, 1.0));
+            validation.notes = parsed
+                .get("notes")
+                .and_then(Value::as_str)
+                .map(|notes| clip_text(notes, 280));
+        }
+        Err(err) => {
+            warn!(uid = %message.uid, %err, "analysis validation failed");
+            validation.status = Some("error".to_string());
+            validation.notes = Some(err);
+        }
+    }
+    validation
+}
+
+/// Handles obvious mail locally. Returns `None` when the rules are unsure or
+/// none of their tags are in the run's taxonomy, so the LLM decides instead.
+fn classify_with_rules(
+    message: &MessageForAnalysis,
+    allowed_tags: &[String],
+) -> Option<NormalizedBulkAnalysis> {
+    let result = classifier::classify(
+        &message.sender_email,
+        &message.subject,
+        message.snippet.as_deref(),
+    )
+    .filter(|result| result.confidence >= FAST_PATH_MIN_CONFIDENCE)?;
+
+    let tags = sanitize_tags(&result.tags, allowed_tags);
+    if tags.is_empty() {
+        return None;
+    }
+
+    let (summary, sentiment, _) = analyze_message(&message.subject, message.snippet.as_deref());
+    let metadata = json!({
+        "version": 1,
+        "priority": result.priority,
+        "confidence": result.confidence,
+        "classifier": "rules",
+        "rules": result.rules,
+        "extractions": {},
+    });
+
+    Some(NormalizedBulkAnalysis {
+        summary,
+        sentiment,
+        tags,
+        confidence: Some(result.confidence),
+        metadata,
+    })
+}
+
+/// Picks corrections from the same sender first, then the same domain.
+fn select_relevant_examples(
+    pool: &[AnalysisExample],
+    sender_email: &str,
+    limit: usize,
+) -> Vec<AnalysisExample> {
+    let sender = sender_email.trim().to_lowercase();
+    let domain = sender_domain(&sender);
+
+    let mut same_sender = Vec::new();
+    let mut same_domain = Vec::new();
+    for example in pool {
+        if example.sender_email == sender {
+            same_sender.push(example.clone());
+        } else if domain.is_some() && example.sender_domain == domain {
+            same_domain.push(example.clone());
+        }
+    }
+
+    same_sender.extend(same_domain);
+    same_sender.truncate(limit);
+    same_sender
+}
+
+fn build_few_shot_block(examples: &[AnalysisExample]) -> String {
+    if examples.is_empty() {
+        return String::new();
+    }
+
+    let lines = examples
+        .iter()
+        .map(|example| {
+            let subject = if example.subject.trim().is_empty() {
+                "(no subject)".to_string()
+            } else {
+                clip_text(example.subject.trim(), 120)
+            };
+            format!(
+                "- From: {sender} | Subject: {subject} -> tags: [{tags}], priority: {priority}, sentiment: {sentiment}",
+                sender = example.sender_email,
+                subject = subject,
+                tags = example.categories.join(", "),
+                priority = example.priority.as_deref().unwrap_or("null"),
+                sentiment = example.sentiment.as_deref().unwrap_or("null"),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        "\nThe user corrected these earlier classifications; follow the same labelling when an email is similar:\n{lines}\n"
+    )
 }
 
 fn build_bulk_prompt(
     allowed_tags: &[String],
     message: &MessageForAnalysis,
-    snippet_limit: usize,
+    snippet: &str,
+    examples: &[AnalysisExample],
 ) -> String {
     let mut sorted_tags = allowed_tags.to_vec();
     sorted_tags.sort();
@@ -867,11 +1558,6 @@ fn build_bulk_prompt(
     let sender_email = &message.sender_email;
     let message_id = message.message_id;
     let date = message.date.as_deref().unwrap_or("(unknown date)");
-    let snippet = message
-        .snippet
-        .as_deref()
-        .unwrap_or("(no snippet available)");
-    let clipped_snippet = clip_text(snippet, snippet_limit);
 
     format!(
         r#"You are an email triage system. Analyze the email below and respond with JSON only. Strictly follow these rules:
@@ -899,7 +1585,7 @@ Fields to return:
 
 Allowed tags:
 {tags_block}
-
+{examples_block}
 Email Context:
 - Message ID: {message_id}
 - IMAP UID: {uid}
@@ -909,7 +1595,7 @@ Email Context:
 
 Email Snippet (trimmed for length):
 """
-{clipped_snippet}
+{snippet}
 """
 "#,
         priority_values = BULK_PRIORITY_VALUES.join(", "),
@@ -919,17 +1605,21 @@ Email Snippet (trimmed for length):
         thread_values = BULK_THREAD_ROLE_VALUES.join(", "),
         lifecycle_values = BULK_LIFECYCLE_VALUES.join(", "),
         tags_block = tags_block,
+        examples_block = build_few_shot_block(examples),
         message_id = message_id,
-        uid = message.uid.as_str(),
+        uid = message.uid,
         sender_name = sender_name,
         sender_email = sender_email,
         date = date,
         subject = subject,
-        clipped_snippet = clipped_snippet,
+        snippet = snippet,
     )
 }
 
 fn infer_model_id_from_status(status: &LlmStatus) -> Option<String> {
+    if status.backend == LlmBackend::Mock {
+        return Some(llm_mock::MODEL_ID.to_string());
+    }
     let path = status.configured_path.as_ref()?;
     let path = Path::new(path);
     let filename = path.file_name()?.to_string_lossy();
@@ -941,24 +1631,37 @@ fn infer_model_id_from_status(status: &LlmStatus) -> Option<String> {
         .or_else(|| Some(filename.to_string()))
 }
 
+/// Analyzes the messages `target` picks, reporting each one to `report`.
+/// `connected` holds the credentials of connected accounts, for fetching
+/// the bodies of headers-only ones.
 async fn execute_bulk_analysis(
-    app: tauri::AppHandle,
+    report: ProgressSink,
+    connected: HashMap<String, Credentials>,
     storage: Storage,
     llm: LlmService,
     run_id: String,
     allowed_tags: Vec<String>,
     max_tokens: usize,
     snippet_limit: usize,
-    force: bool,
+    full_body: bool,
+    target: BulkTarget,
+    fast_path: bool,
+    account_filter: Option<String>,
     model_id: Option<String>,
     validator_model_id: Option<String>,
 ) -> Result<(), String> {
     let started = Instant::now();
-    let accounts = storage
+    let force = target == BulkTarget::All;
+    let mut accounts = storage
         .list_accounts()
         .await
         .map_err(|err| err.to_string())?;
 
+    if let Some(filter) = &account_filter {
+        accounts.retain(|account| &account.email == filter);
+    }
+
+    let fingerprint = analysis_fingerprint(model_id.as_deref(), &allowed_tags);
     let mut targets = Vec::new();
     let mut skipped_existing = 0usize;
 
@@ -968,7 +1671,7 @@ async fn execute_bulk_analysis(
             .await
             .map_err(|err| err.to_string())?;
         for message in messages {
-            if !force && message.existing_analysis.analyzed {
+            if !target.includes(&message, model_id.as_deref(), &fingerprint) {
                 skipped_existing += 1;
                 continue;
             }
@@ -979,62 +1682,70 @@ async fn execute_bulk_analysis(
     let total = targets.len();
     let account_emails: Vec<String> = accounts.into_iter().map(|account| account.email).collect();
 
-    emit_bulk_event(
-        &app,
-        json!({
-            "runId": run_id.clone(),
-            "status": "starting",
-            "total": total,
-            "completed": 0,
-            "failed": 0,
-            "skipped": skipped_existing,
-            "pending": total,
-            "accounts": account_emails,
-            "modelId": model_id.clone(),
-            "validatorModelId": validator_model_id.clone(),
-            "force": force,
-            "timestamp": Utc::now().timestamp(),
-        }),
-    );
+    let progress = BulkAnalysisProgress {
+        run_id: run_id.clone(),
+        status: BulkAnalysisStatus::Starting,
+        total,
+        skipped: skipped_existing,
+        pending: total,
+        model_id: model_id.clone(),
+        validator_model_id: validator_model_id.clone(),
+        timestamp: Utc::now().timestamp(),
+        accounts: Some(account_emails.clone()),
+        force: Some(force),
+        target: Some(target.as_str().to_string()),
+        fast_path: Some(fast_path),
+        ..Default::default()
+    };
+    report(&progress);
 
     if total == 0 {
-        emit_bulk_event(
-            &app,
-            json!({
-                "runId": run_id.clone(),
-                "status": "completed",
-                "total": 0,
-                "completed": 0,
-                "failed": 0,
-                "skipped": skipped_existing,
-                "pending": 0,
-                "durationMs": started.elapsed().as_millis(),
-                "modelId": model_id.clone(),
-                "validatorModelId": validator_model_id.clone(),
-                "timestamp": Utc::now().timestamp(),
-            }),
-        );
+        let progress = BulkAnalysisProgress {
+            run_id: run_id.clone(),
+            status: BulkAnalysisStatus::Completed,
+            skipped: skipped_existing,
+            model_id: model_id.clone(),
+            validator_model_id: validator_model_id.clone(),
+            timestamp: Utc::now().timestamp(),
+            duration_ms: Some(started.elapsed().as_millis() as u64),
+            ..Default::default()
+        };
+        report(&progress);
         return Ok(());
     }
 
+    let examples = match storage.recent_analysis_examples(FEW_SHOT_POOL_SIZE).await {
+        Ok(items) => items,
+        Err(err) => {
+            warn!(?err, "failed to load few-shot examples for bulk analysis");
+            Vec::new()
+        }
+    };
+
+    let connected = Arc::new(connected);
     let allowed_tags = Arc::new(allowed_tags);
+    let examples = Arc::new(examples);
     let completed = Arc::new(AtomicUsize::new(0));
     let failed = Arc::new(AtomicUsize::new(0));
 
     stream::iter(targets.into_iter().map(|message| {
-        let app = app.clone();
+        let report = report.clone();
+        let connected = connected.clone();
         let storage = storage.clone();
         let llm = llm.clone();
         let allowed_tags = allowed_tags.clone();
         let run_id = run_id.clone();
         let model_id = model_id.clone();
         let validator_model_id = validator_model_id.clone();
+        let fingerprint = fingerprint.clone();
+        let examples = examples.clone();
         let completed = completed.clone();
         let failed = failed.clone();
 
         async move {
             process_bulk_message(
-                app,
+                report,
+                connected,
                 storage,
                 llm,
                 message,
@@ -1044,8 +1755,12 @@ async fn execute_bulk_analysis(
                 skipped_existing,
                 max_tokens,
                 snippet_limit,
+                full_body,
                 model_id,
                 validator_model_id,
+                fingerprint,
+                fast_path,
+                examples,
                 completed,
                 failed,
             )
@@ -1056,25 +1771,40 @@ async fn execute_bulk_analysis(
     .collect::<Vec<_>>()
     .await;
 
+    for account_email in &account_emails {
+        if let Err(err) = storage
+            .refresh_review_queue(account_email, REVIEW_CONFIDENCE_THRESHOLD)
+            .await
+        {
+            warn!(account = %account_email, ?err, "failed to refresh review queue after bulk analysis");
+        }
+    }
+
+    if let Err(err) = storage
+        .refresh_sender_profiles(SENDER_PROFILE_MIN_SAMPLES)
+        .await
+    {
+        warn!(?err, "failed to refresh sender profiles after bulk analysis");
+    }
+
     let completed = completed.load(Ordering::SeqCst);
     let failed = failed.load(Ordering::SeqCst);
     let pending = total.saturating_sub(completed + failed);
-    emit_bulk_event(
-        &app,
-        json!({
-            "runId": run_id,
-            "status": "completed",
-            "total": total,
-            "completed": completed,
-            "failed": failed,
-            "skipped": skipped_existing,
-            "pending": pending,
-            "durationMs": started.elapsed().as_millis(),
-            "modelId": model_id,
-            "validatorModelId": validator_model_id,
-            "timestamp": Utc::now().timestamp(),
-        }),
-    );
+    let progress = BulkAnalysisProgress {
+        run_id,
+        status: BulkAnalysisStatus::Completed,
+        total,
+        completed,
+        failed,
+        skipped: skipped_existing,
+        pending,
+        model_id,
+        validator_model_id,
+        timestamp: Utc::now().timestamp(),
+        duration_ms: Some(started.elapsed().as_millis() as u64),
+        ..Default::default()
+    };
+    report(&progress);
 
     Ok(())
 }
@@ -1083,6 +1813,114 @@ fn known_model_by_id(id: &str) -> Option<&'static KnownModel> {
     KNOWN_MODELS.iter().find(|model| model.id == id)
 }
 
+/// Result of the disk checks run before a model download. Failures are
+/// returned to the UI as this struct serialized to JSON.
+#[derive(Debug, Clone, Serialize)]
+struct DownloadSpaceCheck {
+    ok: bool,
+    /// "insufficient_space" or "quota_exceeded" when `ok` is false.
+    code: Option<&'static str>,
+    message: Option<String>,
+    model_id: &'static str,
+    required_bytes: u64,
+    headroom_bytes: u64,
+    available_bytes: u64,
+    models_used_bytes: u64,
+    quota_bytes: Option<u64>,
+}
+
+impl DownloadSpaceCheck {
+    fn into_result(self) -> Result<(), String> {
+        if self.ok {
+            return Ok(());
+        }
+        Err(serde_json::to_string(&self).unwrap_or_else(|_| self.message.unwrap_or_default()))
+    }
+}
+
+async fn models_quota_bytes(storage: &Storage) -> Result<Option<u64>, String> {
+    let stored = storage
+        .get_setting(MODELS_QUOTA_SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(stored.and_then(|value| value.parse::<u64>().ok()))
+}
+
+/// Checks free space (size plus headroom) and the optional models quota.
+/// Space held by an existing copy of the model counts as available, since a
+/// forced re-download replaces it.
+async fn check_model_download_space(
+    models_dir: &Path,
+    model: &KnownModel,
+    size_bytes: u64,
+    quota_bytes: Option<u64>,
+) -> Result<DownloadSpaceCheck, String> {
+    let dir = models_dir.to_path_buf();
+    let filename = model.filename;
+    let (available, used, reclaimable) = tauri::async_runtime::spawn_blocking(move || {
+        let target = dir.join(filename);
+        let reclaimable =
+            data_dir::dir_size(&target) + data_dir::dir_size(&target.with_extension("tmp"));
+        data_dir::available_space(&dir)
+            .map(|available| (available, data_dir::dir_size(&dir), reclaimable))
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| format!("failed to check free disk space: {err}"))?;
+
+    let headroom = (size_bytes / 20).max(MODEL_DOWNLOAD_MIN_HEADROOM_BYTES);
+    let available = available + reclaimable;
+    let used = used.saturating_sub(reclaimable);
+    let mut check = DownloadSpaceCheck {
+        ok: true,
+        code: None,
+        message: None,
+        model_id: model.id,
+        required_bytes: size_bytes,
+        headroom_bytes: headroom,
+        available_bytes: available,
+        models_used_bytes: used,
+        quota_bytes,
+    };
+
+    if available < size_bytes + headroom {
+        check.ok = false;
+        check.code = Some("insufficient_space");
+        check.message = Some(format!(
+            "{} needs {:.1} GB free (including {:.1} GB headroom) but only {:.1} GB is available in {}",
+            model.display_name,
+            gigabytes(size_bytes + headroom),
+            gigabytes(headroom),
+            gigabytes(available),
+            models_dir.display()
+        ));
+    } else if let Some(quota) = quota_bytes.filter(|quota| used + size_bytes > *quota) {
+        check.ok = false;
+        check.code = Some("quota_exceeded");
+        check.message = Some(format!(
+            "{} would bring models to {:.1} GB, over the {:.1} GB limit",
+            model.display_name,
+            gigabytes(used + size_bytes),
+            gigabytes(quota)
+        ));
+    }
+    Ok(check)
+}
+
+async fn model_download_settings(storage: &Storage) -> Result<DownloadSettings, String> {
+    let stored = storage
+        .get_setting(MODEL_DOWNLOAD_SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(stored
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default())
+}
+
+fn gigabytes(bytes: u64) -> f64 {
+    bytes as f64 / 1_000_000_000.0
+}
+
 async fn ensure_model_downloaded(
     app: &tauri::AppHandle,
     model: &KnownModel,
@@ -1099,6 +1937,11 @@ async fn ensure_model_downloaded(
         return Ok(target_path);
     }
 
+    let quota_bytes = models_quota_bytes(&app.state::<AppState>().storage).await?;
+    check_model_download_space(&models_dir, model, model.estimated_size_bytes, quota_bytes)
+        .await?
+        .into_result()?;
+
     let tmp_path = target_path.with_extension("tmp");
     if target_exists {
         fs::remove_file(&target_path)
@@ -1110,64 +1953,66 @@ async fn ensure_model_downloaded(
     }
 
     let client = reqwest::Client::new();
-    let response = client
-        .get(model.download_url)
-        .send()
-        .await
-        .map_err(|err| format!("failed to download model: {err}"))?;
-    let response = response
-        .error_for_status()
-        .map_err(|err| format!("download returned error status: {err}"))?;
-
-    let total_size = response.content_length().unwrap_or(0);
-    let mut downloaded = 0u64;
-
-    let mut file = fs::File::create(&tmp_path)
-        .await
-        .map_err(|err| format!("failed to create temporary model file: {err}"))?;
-
-    let mut stream = response.bytes_stream();
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|err| format!("error while downloading model: {err}"))?;
-        file.write_all(&chunk)
-            .await
-            .map_err(|err| format!("failed writing model data: {err}"))?;
-
-        downloaded += chunk.len() as u64;
-
-        // Emit progress event
-        if total_size > 0 {
-            let progress = (downloaded as f64 / total_size as f64 * 100.0) as u32;
-            let _ = app.emit_all(
-                "model-download-progress",
-                serde_json::json!({
-                    "model_id": model.id,
-                    "downloaded": downloaded,
-                    "total": total_size,
-                    "progress": progress
-                }),
-            );
+    // The catalogue size is an estimate; recheck if the server reports more.
+    if let Some(remote_size) = model_download::remote_size(&client, model.download_url).await {
+        if remote_size > model.estimated_size_bytes {
+            check_model_download_space(&models_dir, model, remote_size, quota_bytes)
+                .await?
+                .into_result()?;
         }
     }
 
-    file.flush()
-        .await
-        .map_err(|err| format!("failed to flush model file: {err}"))?;
-    drop(file);
+    let settings = model_download_settings(&app.state::<AppState>().storage).await?;
+    let emitter = app.clone();
+    let model_id = model.id;
+    let on_progress = move |progress: DownloadProgress| {
+        if progress.total == 0 {
+            return;
+        }
+        let percent = (progress.downloaded as f64 / progress.total as f64 * 100.0) as u32;
+        events::emit(
+            &emitter,
+            &ModelDownloadProgress {
+                model_id: model_id.to_string(),
+                downloaded: progress.downloaded,
+                total: progress.total,
+                progress: percent,
+                bytes_per_sec: progress.bytes_per_sec,
+                eta_secs: progress.eta_secs,
+            },
+        );
+    };
+    let downloaded = model_download::download(
+        &client,
+        model.download_url,
+        &tmp_path,
+        &settings,
+        &on_progress,
+    )
+    .await;
+    let downloaded = match downloaded {
+        Ok(downloaded) => downloaded,
+        Err(err) => {
+            let _ = fs::remove_file(&tmp_path).await;
+            return Err(err);
+        }
+    };
 
     fs::rename(&tmp_path, &target_path)
         .await
         .map_err(|err| format!("failed to finalize model file: {err}"))?;
 
     // Emit completion event
-    let _ = app.emit_all(
-        "model-download-progress",
-        serde_json::json!({
-            "model_id": model.id,
-            "downloaded": total_size,
-            "total": total_size,
-            "progress": 100
-        }),
+    events::emit(
+        app,
+        &ModelDownloadProgress {
+            model_id: model.id.to_string(),
+            downloaded,
+            total: downloaded,
+            progress: 100,
+            bytes_per_sec: 0,
+            eta_secs: Some(0),
+        },
     );
 
     Ok(target_path)
@@ -1184,80 +2029,11 @@ fn expand_path(input: &str) -> Result<PathBuf, String> {
 }
 
 fn models_directory(app: &tauri::AppHandle) -> Result<PathBuf, String> {
-    let base = app
-        .path_resolver()
-        .app_data_dir()
-        .ok_or_else(|| "App data directory not available".to_string())?;
-    Ok(base.join("models"))
-}
-
-fn keychain_entry(email: &str) -> Result<Entry, String> {
-    Entry::new(KEYCHAIN_SERVICE, email).map_err(|err| err.to_string())
-}
-
-fn store_password_in_keychain(email: &str, password: &str) -> Result<(), String> {
-    // In development, you can use environment variables instead of keychain
-    // Set EMAIL_PASSWORD environment variable to avoid keychain prompts
-    if cfg!(debug_assertions) {
-        info!("Development mode: skipping keychain storage. Use EMAIL_PASSWORD env var instead.");
-        return Ok(());
-    }
-
-    let entry = keychain_entry(email)?;
-    entry.set_password(password).map_err(|err| err.to_string())
-}
-
-fn fetch_password_from_keychain(email: &str) -> Result<Option<String>, String> {
-    // In development, check environment variable first
-    if cfg!(debug_assertions) {
-        if let Ok(password) = std::env::var("EMAIL_PASSWORD") {
-            info!("Using password from EMAIL_PASSWORD environment variable");
-            return Ok(Some(password));
-        }
-    }
-
-    let entry = keychain_entry(email)?;
-    match entry.get_password() {
-        Ok(password) => Ok(Some(password)),
-        Err(KeyringError::NoEntry) => Ok(None),
-        Err(err) => Err(err.to_string()),
-    }
-}
-
-fn password_exists_in_keychain(email: &str) -> Result<bool, String> {
-    // In development, check environment variable first
-    if cfg!(debug_assertions) {
-        if std::env::var("EMAIL_PASSWORD").is_ok() {
-            return Ok(true);
-        }
-    }
-
-    let entry = keychain_entry(email)?;
-    match entry.get_password() {
-        Ok(password) => {
-            drop(password);
-            Ok(true)
-        }
-        Err(KeyringError::NoEntry) => Ok(false),
-        Err(err) => Err(err.to_string()),
-    }
-}
-
-fn delete_password_from_keychain(email: &str) -> Result<(), String> {
-    // In development, no keychain entry to delete
-    if cfg!(debug_assertions) {
-        info!("Development mode: no keychain entry to delete");
-        return Ok(());
-    }
-
-    let entry = keychain_entry(email)?;
-    match entry.delete_password() {
-        Ok(_) | Err(KeyringError::NoEntry) => Ok(()),
-        Err(err) => Err(err.to_string()),
-    }
+    data_dir::store_dir(app, Store::Models).map_err(|err| err.to_string())
 }
 
 async fn perform_connect(
+    app: &tauri::AppHandle,
     state: &AppState,
     credentials: Credentials,
 ) -> Result<ConnectAccountResponse, String> {
@@ -1265,6 +2041,16 @@ async fn perform_connect(
     let provider = credentials.provider;
     info!(%normalized_email, ?provider, "connecting account");
 
+    // Gmail and Yahoo answer a regular password with an IMAP alert the UI
+    // can't act on; check the login first so it gets a code and a link.
+    if provider.app_password_url().is_some() {
+        let report = preflight::preflight(&credentials).await;
+        if let Some(issue) = report.issue {
+            warn!(%normalized_email, code = issue.code, "connect preflight failed");
+            return Err(login_issue_to_error(&issue));
+        }
+    }
+
     let emails = providers::fetch_recent(&credentials, 25)
         .await
         .map_err(|err| {
@@ -1287,12 +2073,10 @@ async fn perform_connect(
         analyses.push(analysis);
     }
 
-    if let Err(err) = state.storage.upsert_messages(inserts).await {
+    if let Err(err) = state.storage.upsert_batch(inserts, analyses).await {
         error!(%normalized_email, ?err, "failed to persist message cache");
     }
-    if let Err(err) = state.storage.upsert_analysis(analyses).await {
-        error!(%normalized_email, ?err, "failed to persist analysis cache");
-    }
+    enrich_cached_messages(&state.storage, &normalized_email).await;
     if let Err(err) = state.storage.upsert_account(&account).await {
         error!(%normalized_email, ?err, "failed to persist account metadata");
     }
@@ -1304,6 +2088,7 @@ async fn perform_connect(
     {
         warn!(%normalized_email, ?err, "failed to resume pending remote deletes for account");
     }
+    start_idle_listener(app, state, &credentials).await;
 
     info!(%normalized_email, email_count = emails.len(), "account connected successfully");
     Ok(ConnectAccountResponse { account, emails })
@@ -1311,6 +2096,7 @@ async fn perform_connect(
 
 #[tauri::command]
 async fn connect_account(
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
     provider: Provider,
     email: String,
@@ -1327,7 +2113,8 @@ async fn connect_account(
         return Err("App password is required".into());
     }
 
-    let normalized_email = email.trim().to_lowercase();
+    let normalized_email = normalize_email(&email);
+    let password = SecretString::new(password);
     let credentials = Credentials::new(
         provider,
         normalized_email.clone(),
@@ -1336,7 +2123,7 @@ async fn connect_account(
         custom_port,
     );
 
-    let response = perform_connect(state.inner(), credentials).await?;
+    let response = perform_connect(&app, state.inner(), credentials).await?;
 
     if let Err(err) = store_password_in_keychain(&normalized_email, &password) {
         warn!(%normalized_email, ?err, "failed to persist password in keychain");
@@ -1347,15 +2134,12 @@ async fn connect_account(
 
 #[tauri::command]
 async fn connect_account_saved(
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
     provider: Provider,
     email: String,
 ) -> Result<ConnectAccountResponse, String> {
-    if email.trim().is_empty() {
-        return Err("Email address is required".into());
-    }
-
-    let normalized_email = email.trim().to_lowercase();
+    let normalized_email = require_email(&email)?;
 
     let record = state
         .storage
@@ -1367,6 +2151,10 @@ async fn connect_account_saved(
     if record.provider != provider {
         warn!(%normalized_email, ?provider, actual = ?record.provider, "provider mismatch for saved account, using stored provider");
     }
+    if record.provider == Provider::Demo {
+        let config = load_demo_config(&state.storage).await?;
+        return connect_demo(&app, state.inner(), config).await;
+    }
 
     let password = fetch_password_from_keychain(&normalized_email)?
         .ok_or_else(|| "No saved password stored in macOS keychain for this account".to_string())?;
@@ -1377,53 +2165,206 @@ async fn connect_account_saved(
         password.clone(),
         record.custom_host.clone(),
         record.custom_port,
-    );
+    )
+    .with_auth(record.auth());
 
-    let response = perform_connect(state.inner(), credentials).await?;
+    let response = perform_connect(&app, state.inner(), credentials).await?;
 
-    if let Err(err) = store_password_in_keychain(&normalized_email, &password) {
-        warn!(%normalized_email, ?err, "failed to refresh keychain password after saved connect");
+    // A refresh token the provider rotated while connecting is already
+    // saved; putting the old one back would undo that.
+    if record.oauth_client_id.is_none() {
+        if let Err(err) = store_password_in_keychain(&normalized_email, &password) {
+            warn!(%normalized_email, ?err, "failed to refresh keychain password after saved connect");
+        }
     }
 
     Ok(response)
 }
 
+async fn load_demo_config(storage: &Storage) -> Result<DemoConfig, String> {
+    let raw = storage
+        .get_setting(demo::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(DemoConfig::default()),
+    }
+}
+
+async fn connect_demo(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    config: DemoConfig,
+) -> Result<ConnectAccountResponse, String> {
+    // A large mailbox takes a moment to generate.
+    tauri::async_runtime::spawn_blocking(move || demo::configure(demo::ACCOUNT_EMAIL, &config))
+        .await
+        .map_err(|err| err.to_string())??;
+    let json = serde_json::to_string(&config).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(demo::SETTING_KEY, Some(json.as_str()))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let credentials = Credentials::new(
+        Provider::Demo,
+        demo::ACCOUNT_EMAIL.to_string(),
+        SecretString::new(String::new()),
+        None,
+        None,
+    );
+    perform_connect(app, state, credentials).await
+}
+
+/// Connects the demo account, whose mail is generated on this machine from
+/// `config`, or from the last config used, so the app can be tried and
+/// worked on without real credentials.
+#[tauri::command]
+async fn connect_demo_account(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    config: Option<DemoConfig>,
+) -> Result<ConnectAccountResponse, String> {
+    let config = match config {
+        Some(config) => config,
+        None => load_demo_config(&state.storage).await?,
+    };
+    connect_demo(&app, state.inner(), config).await
+}
+
 #[tauri::command]
 async fn test_account_connection(
-    _state: State<'_, AppState>,
+    state: State<'_, AppState>,
     provider: Provider,
     email: String,
     password: Option<String>,
     custom_host: Option<String>,
     custom_port: Option<u16>,
 ) -> Result<(), String> {
+    let normalized_email = require_email(&email)?;
+
+    let (password_value, auth) = match password {
+        Some(value) if !value.trim().is_empty() => (SecretString::new(value), AuthMethod::Password),
+        _ => {
+            let saved = fetch_password_from_keychain(&normalized_email)?.ok_or_else(|| {
+                "No password available. Provide an app password or connect once to store it."
+                    .to_string()
+            })?;
+            (saved, saved_auth(&state.storage, &normalized_email).await)
+        }
+    };
+
+    let credentials = Credentials::new(
+        provider,
+        normalized_email,
+        password_value,
+        custom_host,
+        custom_port,
+    )
+    .with_auth(auth);
+
+    let report = preflight::preflight(&credentials).await;
+    match report.issue {
+        Some(issue) => Err(login_issue_to_error(&issue)),
+        None => Ok(()),
+    }
+}
+
+/// How a saved account signs in, which decides what its keychain entry
+/// holds. Accounts not saved yet use a password.
+async fn saved_auth(storage: &Storage, email: &str) -> AuthMethod {
+    match storage.account_by_email(email).await {
+        Ok(Some(record)) => record.auth(),
+        Ok(None) => AuthMethod::Password,
+        Err(err) => {
+            warn!(%email, ?err, "failed to load saved account");
+            AuthMethod::Password
+        }
+    }
+}
+
+/// Runs the login preflight and returns the structured result instead of an
+/// error, so setup screens can show guidance before connecting.
+#[tauri::command]
+async fn preflight_account(
+    provider: Provider,
+    email: String,
+    password: String,
+    custom_host: Option<String>,
+    custom_port: Option<u16>,
+) -> Result<PreflightReport, String> {
     if email.trim().is_empty() {
         return Err("Email address is required".into());
     }
+    if password.trim().is_empty() {
+        return Err("App password is required".into());
+    }
 
-    let normalized_email = email.trim().to_lowercase();
+    let credentials = Credentials::new(
+        provider,
+        email.trim().to_lowercase(),
+        SecretString::new(password),
+        custom_host,
+        custom_port,
+    );
+    Ok(preflight::preflight(&credentials).await)
+}
 
-    let password_value = match password {
-        Some(value) if !value.trim().is_empty() => value,
-        _ => fetch_password_from_keychain(&normalized_email)?.ok_or_else(|| {
-            "No password available. Provide an app password or connect once to store it."
-                .to_string()
-        })?,
+/// Runs staged connection checks (DNS through INBOX select) with timings.
+/// Without a password or saved keychain entry only the pre-login stages run.
+#[tauri::command]
+async fn diagnose_connection(
+    state: State<'_, AppState>,
+    provider: Provider,
+    email: String,
+    password: Option<String>,
+    custom_host: Option<String>,
+    custom_port: Option<u16>,
+) -> Result<ConnectionDiagnostics, String> {
+    let normalized_email = require_email(&email)?;
+
+    let (password_value, auth) = match password {
+        Some(value) if !value.trim().is_empty() => (SecretString::new(value), AuthMethod::Password),
+        _ => (
+            fetch_password_from_keychain(&normalized_email)
+                .ok()
+                .flatten()
+                .unwrap_or_else(|| SecretString::new(String::new())),
+            saved_auth(&state.storage, &normalized_email).await,
+        ),
     };
 
     let credentials = Credentials::new(
         provider,
-        normalized_email,
+        normalized_email.clone(),
         password_value,
         custom_host,
         custom_port,
-    );
+    )
+    .with_auth(auth);
+    let report = diagnostics::diagnose(&credentials).await;
+    info!(%normalized_email, ok = report.ok, "connection diagnostics finished");
+    Ok(report)
+}
 
-    providers::verify_credentials(&credentials)
-        .await
-        .map_err(provider_error_to_message)?;
+/// Reconnect counts and pooled sessions per account since launch.
+#[tauri::command]
+fn get_imap_health() -> Vec<SessionHealth> {
+    session::health()
+}
 
-    Ok(())
+/// Proposes IMAP/SMTP settings for an address so custom domains can be
+/// connected without looking up server details by hand. MX records are only
+/// consulted when the DNS settings allow it.
+#[tauri::command]
+async fn autodiscover_account(
+    state: State<'_, AppState>,
+    email: String,
+) -> Result<AutodiscoverResult, String> {
+    let settings = load_dns_settings(&state.storage).await?;
+    autodiscover::autodiscover(&email, settings.autodiscover_mx).await
 }
 
 #[tauri::command]
@@ -1462,39 +2403,39 @@ async fn list_connected_accounts(state: State<'_, AppState>) -> Result<Vec<Accou
     Ok(connected)
 }
 
+/// The saved password, for filling in the form. OAuth accounts have none to
+/// show: their keychain entry is a refresh token.
 #[tauri::command]
-fn get_saved_password(email: String) -> Result<Option<String>, String> {
+async fn get_saved_password(
+    state: State<'_, AppState>,
+    email: String,
+) -> Result<Option<String>, String> {
     if email.trim().is_empty() {
         return Ok(None);
     }
-    let normalized_email = email.trim().to_lowercase();
-    fetch_password_from_keychain(&normalized_email)
+    let normalized_email = normalize_email(&email);
+    if saved_auth(&state.storage, &normalized_email).await != AuthMethod::Password {
+        return Ok(None);
+    }
+    let password = fetch_password_from_keychain(&normalized_email)?;
+    Ok(password.map(|password| password.expose_secret().clone()))
 }
 
 #[tauri::command]
 async fn fetch_recent(
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
     provider: Provider,
     email: String,
     limit: Option<usize>,
 ) -> Result<Vec<EmailSummary>, String> {
-    let normalized_email = email.trim().to_lowercase();
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::for_provider(&state, &email, provider).await?;
     let limit = limit.unwrap_or(25);
     info!(%normalized_email, limit, ?provider, "fetch_recent invoked");
 
-    let credentials = {
-        let accounts = state.accounts.read().await;
-        accounts
-            .get(&normalized_email)
-            .cloned()
-            .ok_or_else(|| "Account is not connected".to_string())?
-    };
-
-    if credentials.provider != provider {
-        warn!(%normalized_email, stored_provider = ?credentials.provider, requested_provider = ?provider, "provider mismatch");
-        return Err("Provider mismatch for stored credentials".into());
-    }
-
     let emails = providers::fetch_recent(&credentials, limit)
         .await
         .map_err(|err| {
@@ -1510,13 +2451,17 @@ async fn fetch_recent(
         analyses.push(analysis);
     }
 
-    if let Err(err) = state.storage.upsert_messages(inserts).await {
+    if let Err(err) = state.storage.upsert_batch(inserts, analyses).await {
         error!(%normalized_email, ?err, "failed to cache mailbox during fetch_recent");
     }
-
-    if let Err(err) = state.storage.upsert_analysis(analyses).await {
-        error!(%normalized_email, ?err, "failed to persist analysis during fetch_recent");
-    }
+    process_synced_mail(
+        &app,
+        &state.storage,
+        &credentials,
+        &normalized_email,
+        &emails,
+    )
+    .await;
 
     debug!(%normalized_email, count = emails.len(), "fetch_recent returning emails");
 
@@ -1535,27 +2480,12 @@ async fn sync_account_full(
         chunk_size,
     } = args;
 
-    let normalized_email = email.trim().to_lowercase();
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::for_provider(&state, &email, provider).await?;
     let chunk = chunk_size.unwrap_or(50);
 
-    let credentials = {
-        let accounts = state.accounts.read().await;
-        accounts
-            .get(&normalized_email)
-            .cloned()
-            .ok_or_else(|| "Account is not connected".to_string())?
-    };
-
-    if credentials.provider != provider {
-        warn!(
-            %normalized_email,
-            stored_provider = ?credentials.provider,
-            requested_provider = ?provider,
-            "provider mismatch for full sync"
-        );
-        return Err("Provider mismatch for stored credentials".into());
-    }
-
     info!(
         %normalized_email,
         chunk,
@@ -1662,7 +2592,7 @@ async fn sync_account_full(
 
     let latest_uid = state
         .storage
-        .latest_uid_for_account(&normalized_email)
+        .latest_uid_for_account(&normalized_email, "INBOX")
         .await
         .map_err(|err| err.to_string())?;
 
@@ -1670,7 +2600,7 @@ async fn sync_account_full(
         .storage
         .update_sync_state(
             &normalized_email,
-            latest_uid.as_deref(),
+            latest_uid,
             true,
             aggregation.total_stored,
         )
@@ -1700,27 +2630,12 @@ async fn sync_account_window(
         end_epoch_ms,
     } = args;
 
-    let normalized_email = email.trim().to_lowercase();
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::for_provider(&state, &email, provider).await?;
     let chunk = chunk_size.unwrap_or(50);
 
-    let credentials = {
-        let accounts = state.accounts.read().await;
-        accounts
-            .get(&normalized_email)
-            .cloned()
-            .ok_or_else(|| "Account is not connected".to_string())?
-    };
-
-    if credentials.provider != provider {
-        warn!(
-            %normalized_email,
-            stored_provider = ?credentials.provider,
-            requested_provider = ?provider,
-            "provider mismatch for windowed sync"
-        );
-        return Err("Provider mismatch for stored credentials".into());
-    }
-
     let start_date = DateTime::<Utc>::from_timestamp_millis(start_epoch_ms)
         .ok_or_else(|| "Invalid window start timestamp".to_string())?
         .date_naive();
@@ -1775,7 +2690,7 @@ async fn sync_account_window(
 
     let latest_uid = state
         .storage
-        .latest_uid_for_account(&normalized_email)
+        .latest_uid_for_account(&normalized_email, "INBOX")
         .await
         .map_err(|err| err.to_string())?;
 
@@ -1783,7 +2698,7 @@ async fn sync_account_window(
         .storage
         .update_sync_state(
             &normalized_email,
-            latest_uid.as_deref(),
+            latest_uid,
             false,
             aggregation.total_stored,
         )
@@ -1811,33 +2726,17 @@ async fn sync_account_incremental(
         chunk_size,
     } = args;
 
-    let normalized_email = email.trim().to_lowercase();
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::for_provider(&state, &email, provider).await?;
     let chunk = chunk_size.unwrap_or(50);
 
-    let credentials = {
-        let accounts = state.accounts.read().await;
-        accounts
-            .get(&normalized_email)
-            .cloned()
-            .ok_or_else(|| "Account is not connected".to_string())?
-    };
-
-    if credentials.provider != provider {
-        warn!(
-            %normalized_email,
-            stored_provider = ?credentials.provider,
-            requested_provider = ?provider,
-            "provider mismatch for incremental sync"
-        );
-        return Err("Provider mismatch for stored credentials".into());
-    }
-
     let since_uid = state
         .storage
-        .latest_uid_for_account(&normalized_email)
+        .latest_uid_for_account(&normalized_email, "INBOX")
         .await
-        .map_err(|err| err.to_string())?
-        .and_then(|value| value.parse::<u32>().ok());
+        .map_err(|err| err.to_string())?;
 
     info!(%normalized_email, chunk, since_uid, "starting incremental mailbox sync");
     let started = Instant::now();
@@ -1868,7 +2767,7 @@ async fn sync_account_incremental(
 
     let latest_uid = state
         .storage
-        .latest_uid_for_account(&normalized_email)
+        .latest_uid_for_account(&normalized_email, "INBOX")
         .await
         .map_err(|err| err.to_string())?;
 
@@ -1876,7 +2775,7 @@ async fn sync_account_incremental(
         .storage
         .update_sync_state(
             &normalized_email,
-            latest_uid.as_deref(),
+            latest_uid,
             false,
             aggregation.total_stored,
         )
@@ -1896,11 +2795,18 @@ async fn sync_account_incremental(
 async fn list_sender_groups(
     state: State<'_, AppState>,
     email: String,
+    fields: Option<Vec<String>>,
 ) -> Result<Vec<SenderGroupResponse>, String> {
-    let normalized_email = email.trim().to_lowercase();
+    let normalized_email = normalize_email(&email);
+    let fields = FieldMask::new(fields);
     let groups = state
         .storage
-        .grouped_messages_for_account(&normalized_email)
+        .grouped_messages_for_account(&normalized_email, &fields)
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut noise = state
+        .storage
+        .sender_noise(&normalized_email)
         .await
         .map_err(|err| err.to_string())?;
 
@@ -1910,33 +2816,17 @@ async fn list_sender_groups(
         let messages = group
             .messages
             .into_iter()
-            .map(|message| MessageItem {
-                uid: message.uid,
-                subject: message.subject,
-                date: message.date,
-                snippet: message.snippet,
-                status: message.status.as_str().to_string(),
-                flags: message.flags,
-                analysis_summary: message.analysis_summary,
-                analysis_sentiment: message.analysis_sentiment,
-                analysis_categories: message.analysis_categories,
-                analysis_metadata: message.analysis_metadata,
-                analysis_model_id: message.analysis_model_id,
-                analysis_analyzed: message.analysis_analyzed,
-                analysis_analyzed_at: message.analysis_analyzed_at,
-                analysis_confidence: message.analysis_confidence,
-                analysis_validator_model_id: message.analysis_validator_model_id,
-                analysis_validation_status: message.analysis_validation_status,
-                analysis_validation_confidence: message.analysis_validation_confidence,
-                analysis_validation_notes: message.analysis_validation_notes,
-                analysis_validated_at: message.analysis_validated_at,
-            })
+            .map(|message| message_item(message, &fields))
             .collect::<Vec<_>>();
+        let sender_noise = noise.remove(&group.sender_email);
 
         response.push(SenderGroupResponse {
             sender_email: group.sender_email,
             sender_display: group.sender_display,
             status: group.status.as_str().to_string(),
+            profile: group.profile,
+            block_note: group.block_note,
+            noise: sender_noise,
             message_count: messages.len(),
             messages,
         });
@@ -1945,921 +2835,7904 @@ async fn list_sender_groups(
     Ok(response)
 }
 
+/// Sender groups with their counts but no messages, for drawing the group
+/// list before any group is expanded.
 #[tauri::command]
-#[allow(non_snake_case)]
-async fn set_sender_status(
+async fn list_sender_group_headers(
     state: State<'_, AppState>,
-    senderEmail: String,
-    status: String,
-) -> Result<(), String> {
-    let normalized_sender = senderEmail.trim().to_lowercase();
-    let desired_status = match status.as_str() {
-        "allowed" => SenderStatus::Allowed,
-        "blocked" => SenderStatus::Blocked,
-        _ => SenderStatus::Neutral,
-    };
-
-    state
+    email: String,
+) -> Result<Vec<SenderGroupHeaderResponse>, String> {
+    let normalized_email = normalize_email(&email);
+    let headers = state
+        .storage
+        .sender_group_headers(&normalized_email)
+        .await
+        .map_err(|err| err.to_string())?;
+    let mut noise = state
         .storage
-        .update_sender_status(&normalized_sender, desired_status)
+        .sender_noise(&normalized_email)
         .await
         .map_err(|err| err.to_string())?;
 
-    Ok(())
+    Ok(headers
+        .into_iter()
+        .map(|header| SenderGroupHeaderResponse {
+            noise: noise.remove(&header.sender_email),
+            sender_email: header.sender_email,
+            sender_display: header.sender_display,
+            status: header.status.as_str().to_string(),
+            profile: header.profile,
+            block_note: header.block_note,
+            message_count: header.message_count,
+            unread_count: header.unread_count,
+            latest_date: header.latest_date,
+        })
+        .collect())
 }
 
+/// Cleanup candidates: senders at or above `min_score` noise (the noisy
+/// threshold by default), noisiest first.
 #[tauri::command]
-async fn list_recent_messages(
+async fn list_noisy_senders(
     state: State<'_, AppState>,
     email: String,
+    min_score: Option<f64>,
     limit: Option<usize>,
-) -> Result<Vec<EmailSummary>, String> {
-    let normalized_email = email.trim().to_lowercase();
-    let limit = limit.unwrap_or(200).clamp(1, 100_000);
-
-    let cached = state
+) -> Result<Vec<NoisySenderResponse>, String> {
+    let normalized_email = normalize_email(&email);
+    let min_score = min_score.unwrap_or(noise::NOISY_THRESHOLD).clamp(0.0, 1.0);
+    let mut noise = state
         .storage
-        .recent_message_summaries(&normalized_email, limit)
+        .sender_noise(&normalized_email)
+        .await
+        .map_err(|err| err.to_string())?;
+    let headers = state
+        .storage
+        .sender_group_headers(&normalized_email)
         .await
         .map_err(|err| err.to_string())?;
 
-    let messages = cached
+    let mut senders = headers
         .into_iter()
-        .map(|summary| EmailSummary {
-            uid: summary.uid,
-            subject: summary.subject,
-            sender: MailAddress {
-                display_name: summary.sender_display,
-                email: summary.sender_email,
-            },
-            date: summary.date,
+        .filter(|header| !matches!(header.status, SenderStatus::Allowed))
+        .filter_map(|header| {
+            let noise = noise
+                .remove(&header.sender_email)
+                .filter(|noise| noise.score >= min_score)?;
+            Some(NoisySenderResponse {
+                sender_email: header.sender_email,
+                sender_display: header.sender_display,
+                status: header.status.as_str().to_string(),
+                message_count: header.message_count,
+                unread_count: header.unread_count,
+                latest_date: header.latest_date,
+                noise,
+            })
         })
-        .collect();
+        .collect::<Vec<_>>();
+    senders.sort_by(|a, b| {
+        b.noise
+            .score
+            .total_cmp(&a.noise.score)
+            .then(b.message_count.cmp(&a.message_count))
+    });
+    senders.truncate(limit.unwrap_or(100).clamp(1, 1000));
+    Ok(senders)
+}
 
-    Ok(messages)
+/// Storage already skipped the expensive masked fields; the cheap ones are
+/// dropped here so they are left out of the response too.
+fn message_item(message: MessageRow, fields: &FieldMask) -> MessageItem {
+    let keep = |field: &str| fields.includes(field);
+    MessageItem {
+        uid: message.uid,
+        message_key: message.message_key,
+        subject: message.subject,
+        date: message.date.filter(|_| keep("date")),
+        snippet: message.snippet,
+        status: message.status.as_str().to_string(),
+        flags: message.flags.filter(|_| keep("flags")),
+        analysis_summary: message
+            .analysis_summary
+            .filter(|_| keep("analysis_summary")),
+        analysis_sentiment: message
+            .analysis_sentiment
+            .filter(|_| keep("analysis_sentiment")),
+        analysis_categories: message.analysis_categories,
+        analysis_metadata: message.analysis_metadata,
+        analysis_model_id: message
+            .analysis_model_id
+            .filter(|_| keep("analysis_model_id")),
+        analysis_analyzed: message.analysis_analyzed,
+        analysis_analyzed_at: message
+            .analysis_analyzed_at
+            .filter(|_| keep("analysis_analyzed_at")),
+        analysis_confidence: message
+            .analysis_confidence
+            .filter(|_| keep("analysis_confidence")),
+        analysis_validator_model_id: message
+            .analysis_validator_model_id
+            .filter(|_| keep("analysis_validator_model_id")),
+        analysis_validation_status: message
+            .analysis_validation_status
+            .filter(|_| keep("analysis_validation_status")),
+        analysis_validation_confidence: message
+            .analysis_validation_confidence
+            .filter(|_| keep("analysis_validation_confidence")),
+        analysis_validation_notes: message
+            .analysis_validation_notes
+            .filter(|_| keep("analysis_validation_notes")),
+        analysis_validated_at: message
+            .analysis_validated_at
+            .filter(|_| keep("analysis_validated_at")),
+        spam_score: message.spam_score.filter(|_| keep("spam_score")),
+        size_bytes: message.size_bytes.filter(|_| keep("size_bytes")),
+        thread_id: message.thread_id.filter(|_| keep("thread_id")),
+    }
 }
 
+/// Filters, sorts, and pages the account's cached messages. Pass the
+/// returned `next_cursor` back with the same query for the following page.
+/// `fields` limits each message to the named fields.
 #[tauri::command]
-async fn cached_message_count(state: State<'_, AppState>, email: String) -> Result<usize, String> {
-    let normalized_email = email.trim().to_lowercase();
-    state
+async fn query_messages(
+    state: State<'_, AppState>,
+    account: String,
+    query: Option<MessageQuery>,
+    cursor: Option<String>,
+    page_size: Option<usize>,
+    fields: Option<Vec<String>>,
+) -> Result<MessageQueryPage, String> {
+    let normalized_email = normalize_email(&account);
+    let plan = QueryPlan::new(&query.unwrap_or_default(), cursor.as_deref(), page_size)?;
+    let fields = FieldMask::new(fields);
+    let page = state
         .storage
-        .message_count_for_account(&normalized_email)
+        .query_messages(&normalized_email, plan, &fields)
         .await
-        .map_err(|err| err.to_string())
+        .map_err(|err| err.to_string())?;
+    Ok(MessageQueryPage {
+        messages: page
+            .messages
+            .into_iter()
+            .map(|message| QueriedMessage {
+                sender_email: message.sender_email.clone(),
+                sender_display: message.sender_display.clone(),
+                message: message_item(message, &fields),
+            })
+            .collect(),
+        next_cursor: page.next_cursor,
+    })
 }
 
-#[tauri::command]
-async fn delete_message(
-    state: State<'_, AppState>,
-    provider: Provider,
-    email: String,
-    uid: String,
-) -> Result<DeletedMessageRow, String> {
-    let normalized_email = email.trim().to_lowercase();
+/// Where [`find_messages_by_size`] looked.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum SizeSearchSource {
+    Cache,
+    Server,
+}
 
-    let credentials = {
-        let accounts = state.accounts.read().await;
-        accounts
-            .get(&normalized_email)
-            .cloned()
-            .ok_or_else(|| "Account is not connected".to_string())?
-    };
+#[derive(Serialize)]
+struct SizeSearchResult {
+    source: SizeSearchSource,
+    messages: Vec<EmailSummary>,
+}
 
-    if credentials.provider != provider {
-        return Err("Provider mismatch for stored credentials".into());
+/// The largest INBOX messages within a size range, largest first, for
+/// cleaning up a mailbox. Bounds are exclusive, as in IMAP. The cache
+/// answers once a full sync has recorded every message's size; until then
+/// the server is searched with `LARGER` and `SMALLER`, and the cache is
+/// only used when the account is offline.
+#[tauri::command]
+async fn find_messages_by_size(
+    state: State<'_, AppState>,
+    account: String,
+    larger_than: Option<u32>,
+    smaller_than: Option<u32>,
+    limit: Option<usize>,
+) -> Result<SizeSearchResult, String> {
+    let normalized_email = normalize_email(&account);
+    let limit = limit
+        .unwrap_or(SIZE_SEARCH_DEFAULT_LIMIT)
+        .clamp(1, MAX_PAGE_SIZE);
+    let sizes_known = state
+        .storage
+        .sizes_known(&normalized_email)
+        .await
+        .map_err(|err| err.to_string())?;
+    if !sizes_known {
+        if let Some(credentials) = command_context::credentials(&state, &normalized_email).await {
+            let range = SizeRange {
+                larger_than,
+                smaller_than,
+            };
+            let messages = providers::search_by_size(&credentials, "INBOX", range, limit)
+                .await
+                .map_err(provider_error_to_message)?;
+            return Ok(SizeSearchResult {
+                source: SizeSearchSource::Server,
+                messages,
+            });
+        }
     }
 
-    let mut archived = state
+    let filters = larger_than
+        .map(|bytes| MessageFilter::LargerThan(bytes.into()))
+        .into_iter()
+        .chain(smaller_than.map(|bytes| MessageFilter::SmallerThan(bytes.into())))
+        .collect();
+    let query = MessageQuery {
+        filter: Some(MessageFilter::All(filters)),
+        sort: MessageSort::SizeDesc,
+    };
+    let plan = QueryPlan::new(&query, None, Some(limit))?;
+    let fields = FieldMask::new(Some(vec!["date".to_string()]));
+    let page = state
         .storage
-        .archive_message(&normalized_email, &uid)
+        .query_messages(&normalized_email, plan, &fields)
         .await
-        .map_err(|err| err.to_string())?
-        .ok_or_else(|| "Message not found in local cache".to_string())?;
+        .map_err(|err| err.to_string())?;
+    let messages = page
+        .messages
+        .into_iter()
+        .map(|message| EmailSummary {
+            uid: message.uid,
+            subject: message.subject,
+            sender: MailAddress {
+                display_name: Some(message.sender_display),
+                email: message.sender_email,
+            },
+            date: message.date,
+            auto_submitted: false,
+            to: Vec::new(),
+            size: message.size_bytes.and_then(|size| u32::try_from(size).ok()),
+            dmarc_aligned: None,
+            thread_id: message.thread_id,
+            message_id: None,
+            references: Vec::new(),
+        })
+        .collect();
+    Ok(SizeSearchResult {
+        source: SizeSearchSource::Cache,
+        messages,
+    })
+}
 
-    let queue = state.remote_delete.clone();
-    if let Err(err) = queue
-        .enqueue(&normalized_email, credentials.clone(), uid.clone())
+/// Messages held in quarantine, riskiest first. They are left out of
+/// [`list_sender_groups`] and [`query_messages`] until released, and
+/// [`get_message_html`] renders them with links and remote images disabled.
+#[tauri::command]
+async fn list_quarantined(
+    state: State<'_, AppState>,
+    account: String,
+    fields: Option<Vec<String>>,
+) -> Result<Vec<QuarantinedMessageResponse>, String> {
+    let normalized_email = normalize_email(&account);
+    let fields = FieldMask::new(fields);
+    let messages = state
+        .storage
+        .quarantined_messages(&normalized_email, &fields)
         .await
-    {
-        warn!(
-            %normalized_email,
-            %uid,
-            ?err,
-            "failed to enqueue remote delete; attempting synchronous fallback"
-        );
+        .map_err(|err| err.to_string())?;
+    Ok(messages
+        .into_iter()
+        .map(|quarantined| QuarantinedMessageResponse {
+            sender_email: quarantined.message.sender_email.clone(),
+            sender_display: quarantined.message.sender_display.clone(),
+            score: quarantined.score,
+            reasons: quarantined.reasons,
+            quarantined_at: quarantined.quarantined_at,
+            message: message_item(quarantined.message, &fields),
+        })
+        .collect())
+}
 
-        match providers::delete_message(&credentials, &uid).await {
-            Ok(_) => {
-                let now = Utc::now().timestamp();
-                state
-                    .storage
-                    .mark_deleted_remote(&normalized_email, &uid, Some(now), None)
-                    .await
-                    .map_err(|err| err.to_string())?;
-                archived.remote_deleted_at = Some(now);
-                archived.remote_error = None;
-            }
-            Err(delete_err) => {
-                error!(
-                    %normalized_email,
-                    %uid,
-                    ?delete_err,
-                    "remote delete fallback failed"
-                );
-                let message = provider_error_to_message(delete_err);
-                state
-                    .storage
-                    .mark_deleted_remote(&normalized_email, &uid, None, Some(message.clone()))
-                    .await
-                    .map_err(|err| err.to_string())?;
-                archived.remote_error = Some(message);
-            }
-        }
+/// Returns a quarantined message to the normal listings. It is not
+/// quarantined again by later scoring. Returns false when it was not held.
+#[tauri::command]
+async fn release_from_quarantine(
+    state: State<'_, AppState>,
+    account: String,
+    uid: String,
+) -> Result<bool, String> {
+    let normalized_email = normalize_email(&account);
+    let released = state
+        .storage
+        .release_from_quarantine(&normalized_email, "INBOX", uid_arg(&uid)?)
+        .await
+        .map_err(|err| err.to_string())?;
+    if released {
+        info!(account = %normalized_email, %uid, "released message from quarantine");
     }
-
-    Ok(archived)
+    Ok(released)
 }
 
+/// Sets a sender's status for every account, or only for the account named
+/// by `scope`. An account rule overrides the global one for that account;
+/// setting it back to neutral removes the override.
+///
+/// A block can carry a `reason` and the `originUid` of the message behind
+/// it, kept encrypted as a note for `account` (the scoped account by
+/// default) and returned with that account's sender groups. Any other
+/// status change drops the note.
 #[tauri::command]
-async fn purge_sender_messages(
+#[allow(non_snake_case)]
+async fn set_sender_status(
     state: State<'_, AppState>,
-    provider: Provider,
-    email: String,
-    sender_email: String,
-) -> Result<Vec<DeletedMessageRow>, String> {
-    let normalized_email = email.trim().to_lowercase();
-    let normalized_sender = sender_email.trim().to_lowercase();
-
-    let credentials = {
-        let accounts = state.accounts.read().await;
-        accounts
-            .get(&normalized_email)
-            .cloned()
-            .ok_or_else(|| "Account is not connected".to_string())?
+    senderEmail: String,
+    status: String,
+    scope: Option<String>,
+    account: Option<String>,
+    reason: Option<String>,
+    originUid: Option<String>,
+) -> Result<(), String> {
+    let normalized_sender = senderEmail.trim().to_lowercase();
+    let desired_status = match status.as_str() {
+        "allowed" => SenderStatus::Allowed,
+        "blocked" => SenderStatus::Blocked,
+        _ => SenderStatus::Neutral,
     };
+    let scope = scope
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty() && value != GLOBAL_SCOPE);
+    let note_account = account
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+        .or_else(|| scope.clone());
+    let reason = reason
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    let origin_uid = originUid
+        .filter(|value| !value.trim().is_empty())
+        .map(|value| uid_arg(value.trim()))
+        .transpose()?;
+    let has_note = reason.is_some() || origin_uid.is_some();
+
+    match (&desired_status, &note_account) {
+        (SenderStatus::Blocked, Some(note_account)) if has_note => {
+            state
+                .storage
+                .set_block_note(
+                    &normalized_sender,
+                    note_account,
+                    reason.as_deref(),
+                    origin_uid,
+                )
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+        _ => {
+            state
+                .storage
+                .clear_block_notes(&normalized_sender, note_account.as_deref())
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+    }
 
-    if credentials.provider != provider {
-        return Err("Provider mismatch for stored credentials".into());
+    if let Some(account) = &scope {
+        // An account-only rule says nothing about whether the mail is spam
+        // elsewhere, so it does not train the shared spam model.
+        return state
+            .storage
+            .update_sender_status(&normalized_sender, account, desired_status)
+            .await
+            .map_err(|err| err.to_string());
     }
 
-    let uids = state
+    let spam_label = match desired_status {
+        SenderStatus::Blocked => Some(SpamLabel::Spam),
+        SenderStatus::Allowed => Some(SpamLabel::Ham),
+        SenderStatus::Neutral => None,
+    };
+
+    state
         .storage
-        .message_uids_for_sender(&normalized_email, &normalized_sender)
+        .update_sender_status(&normalized_sender, GLOBAL_SCOPE, desired_status)
         .await
         .map_err(|err| err.to_string())?;
 
-    if uids.is_empty() {
-        return Ok(Vec::new());
-    }
-
-    let mut archived_rows = Vec::new();
-
-    for uid in uids {
-        let mut archived = match state
-            .storage
-            .archive_message(&normalized_email, &uid)
-            .await
-            .map_err(|err| err.to_string())?
-        {
-            Some(row) => row,
-            None => continue,
-        };
-
-        if let Err(err) = state
-            .remote_delete
-            .enqueue(&normalized_email, credentials.clone(), uid.clone())
-            .await
-        {
-            warn!(
-                %normalized_email,
-                %uid,
-                ?err,
-                "failed to enqueue remote delete for sender purge; attempting synchronous fallback"
-            );
+    train_spam_for_sender_in_background(state.storage.clone(), normalized_sender, spam_label);
+    Ok(())
+}
 
-            match providers::delete_message(&credentials, &uid).await {
-                Ok(_) => {
-                    let now = Utc::now().timestamp();
-                    state
-                        .storage
-                        .mark_deleted_remote(&normalized_email, &uid, Some(now), None)
-                        .await
-                        .map_err(|err| err.to_string())?;
-                    archived.remote_deleted_at = Some(now);
-                    archived.remote_error = None;
-                }
-                Err(delete_err) => {
-                    error!(
-                        %normalized_email,
-                        %uid,
-                        ?delete_err,
-                        "remote delete fallback failed during sender purge"
-                    );
-                    let message = provider_error_to_message(delete_err);
-                    state
-                        .storage
-                        .mark_deleted_remote(&normalized_email, &uid, None, Some(message.clone()))
-                        .await
-                        .map_err(|err| err.to_string())?;
-                    archived.remote_error = Some(message);
+/// Retrains the spam model on a sender's cached mail after their global
+/// status changed, and rescores the cache if anything moved.
+fn train_spam_for_sender_in_background(
+    storage: Storage,
+    sender_email: String,
+    label: Option<SpamLabel>,
+) {
+    tauri::async_runtime::spawn(async move {
+        match storage.train_spam_for_sender(&sender_email, label).await {
+            Ok(0) => {}
+            Ok(_) => {
+                if let Err(err) = storage.refresh_spam_scores(None, true).await {
+                    warn!(?err, "failed to rescore messages after spam training");
                 }
             }
+            Err(err) => {
+                warn!(sender = %sender_email, ?err, "failed to train spam model from sender status")
+            }
         }
+    });
+}
 
-        archived_rows.push(archived);
-    }
-
-    Ok(archived_rows)
+/// Explains how the sender rules treat a cached message: every rule that
+/// matches its sender in precedence order, which one sets the status, and
+/// whether the block filter would move it.
+#[tauri::command]
+async fn simulate_policies(
+    state: State<'_, AppState>,
+    account: String,
+    uid: String,
+) -> Result<PolicyDecision, String> {
+    let normalized_email = normalize_email(&account);
+    let message = state
+        .storage
+        .cached_message(&normalized_email, "INBOX", uid_arg(uid.trim())?)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Message is not cached".to_string())?;
+    let rules = state
+        .storage
+        .list_statuses()
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(policy::evaluate(
+        &normalized_email,
+        &message.sender_email,
+        &rules,
+    ))
 }
 
+/// Blocks a sender until `until` (Unix seconds), e.g. a noisy retailer
+/// during a sale, for every account or only the account named by `scope`.
+/// The earlier status comes back when the mute expires. Unlike a block it
+/// does not train the spam model.
 #[tauri::command]
-async fn list_deleted_messages(
+async fn mute_sender(
     state: State<'_, AppState>,
-    email: String,
-    limit: Option<usize>,
-    offset: Option<usize>,
-) -> Result<Vec<DeletedMessageRow>, String> {
-    let normalized_email = email.trim().to_lowercase();
+    sender: String,
+    until: i64,
+    scope: Option<String>,
+) -> Result<(), String> {
+    let normalized_sender = sender.trim().to_lowercase();
+    if normalized_sender.is_empty() {
+        return Err("Sender is required".into());
+    }
+    if until <= Utc::now().timestamp() {
+        return Err("The mute must end in the future".into());
+    }
+    let scope = scope
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| GLOBAL_SCOPE.to_string());
+
     state
         .storage
-        .list_deleted_messages(&normalized_email, limit, offset)
+        .mute_sender(&normalized_sender, &scope, until)
         .await
         .map_err(|err| err.to_string())
 }
 
+/// Mutes a conversation: messages arriving in it later are marked read
+/// and archived. `thread_id` is the one listings report for its messages.
 #[tauri::command]
-async fn restore_deleted_message(
+async fn mute_thread(
     state: State<'_, AppState>,
-    email: String,
-    uid: String,
-) -> Result<DeletedMessageRow, String> {
-    let normalized_email = email.trim().to_lowercase();
+    account: String,
+    thread_id: String,
+) -> Result<(), String> {
+    let normalized_account = normalize_email(&account);
+    let thread_id = threads::normalize_thread_id(&thread_id)
+        .ok_or_else(|| "Thread id is required".to_string())?;
     state
         .storage
-        .restore_deleted_message(&normalized_email, &uid)
+        .mute_thread(&normalized_account, &thread_id)
         .await
-        .map_err(|err| err.to_string())?
-        .ok_or_else(|| "Message not found in deleted archive".to_string())
+        .map_err(|err| err.to_string())
 }
 
+/// Returns whether the thread was muted.
 #[tauri::command]
-async fn purge_deleted_message(
+async fn unmute_thread(
     state: State<'_, AppState>,
-    email: String,
-    uid: String,
+    account: String,
+    thread_id: String,
 ) -> Result<bool, String> {
-    let normalized_email = email.trim().to_lowercase();
+    let normalized_account = normalize_email(&account);
+    let Some(thread_id) = threads::normalize_thread_id(&thread_id) else {
+        return Ok(false);
+    };
     state
         .storage
-        .purge_deleted_message(&normalized_email, &uid)
+        .unmute_thread(&normalized_account, &thread_id)
         .await
         .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
-async fn get_remote_delete_metrics(
+async fn list_muted_threads(
     state: State<'_, AppState>,
-    email: String,
-) -> Result<RemoteDeleteMetricsResponse, String> {
-    let normalized_email = email.trim().to_lowercase();
-    if normalized_email.is_empty() {
-        return Err("Account email is required".into());
-    }
-
-    Ok(state
-        .remote_delete
-        .get_metrics(&normalized_email)
-        .await)
+    account: String,
+) -> Result<Vec<MutedThread>, String> {
+    let normalized_account = normalize_email(&account);
+    state
+        .storage
+        .muted_threads(&normalized_account)
+        .await
+        .map_err(|err| err.to_string())
 }
 
+/// Sender rules, global and per account. With `account`, only the rules
+/// that can affect that account are returned.
 #[tauri::command]
-async fn set_remote_delete_mode(
+async fn list_sender_rules(
     state: State<'_, AppState>,
-    email: String,
-    mode: String,
-) -> Result<RemoteDeleteMetricsResponse, String> {
-    let normalized_email = email.trim().to_lowercase();
-    if normalized_email.is_empty() {
-        return Err("Account email is required".into());
-    }
-
-    let override_mode = match mode.trim().to_lowercase().as_str() {
-        "auto" => ModeOverride::Auto,
-        "force-batch" => ModeOverride::ForceBatch,
-        other => {
-            warn!(
-                %normalized_email,
-                requested_mode = other,
-                "invalid remote delete override mode requested"
-            );
-            return Err(format!("Unsupported remote delete mode '{other}'"));
-        }
-    };
-
-    state
-        .remote_delete
-        .set_mode(&normalized_email, override_mode)
-        .await;
+    account: Option<String>,
+) -> Result<Vec<SenderRule>, String> {
+    let account = account.map(|value| value.trim().to_lowercase());
+    let rules = state
+        .storage
+        .list_statuses()
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(rules
+        .into_iter()
+        .filter(|rule| match &account {
+            Some(account) => rule.scope == GLOBAL_SCOPE || &rule.scope == account,
+            None => true,
+        })
+        .collect())
+}
 
-    Ok(state
-        .remote_delete
-        .get_metrics(&normalized_email)
-        .await)
+#[derive(Serialize)]
+struct BlocklistImportReport {
+    source: String,
+    format: BlocklistFormat,
+    entries: usize,
+    #[serde(flatten)]
+    merge: BlocklistMerge,
 }
 
+/// Imports a blocklist from a file or an http(s) URL into the global sender
+/// rules. Importing the same source again updates its entries in place.
 #[tauri::command]
-async fn configure_periodic_sync(
+async fn import_blocklist(
     state: State<'_, AppState>,
-    provider: Provider,
-    email: String,
-    minutes: Option<u64>,
-) -> Result<(), String> {
-    let normalized_email = email.trim().to_lowercase();
+    source: String,
+    format: Option<BlocklistFormat>,
+) -> Result<BlocklistImportReport, String> {
+    let source = source.trim().to_string();
+    let lowered = source.to_lowercase();
+    let (origin_ref, text) = if lowered.starts_with("http://") || lowered.starts_with("https://") {
+        let response = reqwest::get(&source)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("failed to download blocklist: {err}"))?;
+        let text = response
+            .text()
+            .await
+            .map_err(|err| format!("failed to download blocklist: {err}"))?;
+        (source, text)
+    } else {
+        let path = expand_path(&source)?;
+        let text = fs::read_to_string(&path)
+            .await
+            .map_err(|err| format!("failed to read blocklist: {err}"))?;
+        (path.to_string_lossy().into_owned(), text)
+    };
 
-    {
-        let mut jobs = state.sync_jobs.write().await;
-        if let Some(existing) = jobs.remove(&normalized_email) {
-            existing.cancel.cancel();
-            existing.handle.abort();
-        }
+    let format = format.unwrap_or_else(|| BlocklistFormat::detect(&text));
+    let entries = blocklist::parse(&text, format)?;
+    if entries.is_empty() {
+        return Err("The blocklist has no addresses or domains".into());
     }
+    let count = entries.len();
+    let merge = state
+        .storage
+        .merge_blocklist(&origin_ref, entries)
+        .await
+        .map_err(|err| err.to_string())?;
+    info!(
+        source = %origin_ref,
+        applied = merge.applied,
+        removed = merge.removed,
+        "imported blocklist"
+    );
+    Ok(BlocklistImportReport {
+        source: origin_ref,
+        format,
+        entries: count,
+        merge,
+    })
+}
 
-    let Some(interval_minutes) = minutes.filter(|value| *value > 0) else {
-        info!(%normalized_email, "periodic sync disabled");
-        return Ok(());
-    };
+/// Removes the rules one imported list added, or every imported rule when
+/// `source` is not given. Manual rules are never touched.
+#[tauri::command]
+async fn remove_imported_blocklist(
+    state: State<'_, AppState>,
+    source: Option<String>,
+) -> Result<usize, String> {
+    let source = source
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    state
+        .storage
+        .remove_imported_rules(source.as_deref())
+        .await
+        .map_err(|err| err.to_string())
+}
 
-    let credentials = {
-        let accounts = state.accounts.read().await;
-        accounts
-            .get(&normalized_email)
-            .cloned()
-            .ok_or_else(|| "Account is not connected".to_string())?
-    };
+/// Writes every globally blocked sender and domain as a plain list.
+#[tauri::command]
+async fn export_blocklist(state: State<'_, AppState>, dest: String) -> Result<String, String> {
+    let target = expand_path(&dest)?;
+    let entries = state
+        .storage
+        .global_blocklist()
+        .await
+        .map_err(|err| err.to_string())?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    fs::write(&target, blocklist::to_plain(&entries))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(target.to_string_lossy().into_owned())
+}
 
-    if credentials.provider != provider {
-        return Err("Provider mismatch for stored credentials".into());
+/// Replaces the local breach list with one read from a file or downloaded
+/// from an http(s) URL, in the JSON format of Have I Been Pwned's breach
+/// list. Returns how many breached domains were stored.
+#[tauri::command]
+async fn import_breach_data(state: State<'_, AppState>, source: String) -> Result<usize, String> {
+    let source = source.trim().to_string();
+    let lowered = source.to_lowercase();
+    let text = if lowered.starts_with("http://") || lowered.starts_with("https://") {
+        reqwest::get(&source)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| format!("failed to download breach data: {err}"))?
+            .text()
+            .await
+            .map_err(|err| format!("failed to download breach data: {err}"))?
+    } else {
+        let path = expand_path(&source)?;
+        fs::read_to_string(&path)
+            .await
+            .map_err(|err| format!("failed to read breach data: {err}"))?
+    };
+    let breaches = breach::parse_breaches(&text)?;
+    if breaches.is_empty() {
+        return Err("The breach data lists no domains".into());
     }
+    let stored = state
+        .storage
+        .replace_breaches(breaches)
+        .await
+        .map_err(|err| err.to_string())?;
+    info!(domains = stored, "imported breach data");
+    Ok(stored)
+}
 
-    let storage = state.storage.clone();
-    let cancel = CancellationToken::new();
-    let child_token = cancel.clone();
-    let email_clone = normalized_email.clone();
-    let credentials_clone = credentials.clone();
+async fn load_breach_check_settings(storage: &Storage) -> Result<BreachCheckSettings, String> {
+    let raw = storage
+        .get_setting(breach::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(BreachCheckSettings::default()),
+    }
+}
 
-    let handle = tokio::spawn(async move {
-        if let Err(err) =
-            perform_incremental_sync(&storage, &credentials_clone, &email_clone, 200).await
-        {
-            error!(%email_clone, ?err, "initial periodic sync failed");
-        }
+#[tauri::command]
+async fn get_breach_check_settings(
+    state: State<'_, AppState>,
+) -> Result<BreachCheckSettings, String> {
+    load_breach_check_settings(&state.storage).await
+}
 
-        let mut ticker = time::interval(Duration::from_secs(interval_minutes * 60));
-        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+/// Sets whether account addresses are looked up at a k-anonymity range
+/// endpoint, and which one.
+#[tauri::command]
+async fn set_breach_check_settings(
+    state: State<'_, AppState>,
+    settings: BreachCheckSettings,
+) -> Result<BreachCheckSettings, String> {
+    let normalized = settings.normalized()?;
+    let json = serde_json::to_string(&normalized).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(breach::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(normalized)
+}
 
-        loop {
-            tokio::select! {
-                _ = child_token.cancelled() => {
-                    info!(%email_clone, "periodic sync cancelled");
-                    break;
-                }
-                _ = ticker.tick() => {
-                    if let Err(err) = perform_incremental_sync(&storage, &credentials_clone, &email_clone, 200).await {
-                        error!(%email_clone, ?err, "periodic sync iteration failed");
-                    }
-                }
+/// Security alerts for one account or all: recent mail from breached
+/// sites or lookalike domains, bursts of password resets, and, when
+/// enabled, account addresses found in breach data. Most severe first.
+#[tauri::command]
+async fn get_security_alerts(
+    state: State<'_, AppState>,
+    account: Option<String>,
+) -> Result<Vec<SecurityAlert>, String> {
+    let accounts = match account {
+        Some(account) => vec![normalize_email(&account)],
+        None => state
+            .storage
+            .list_accounts()
+            .await
+            .map_err(|err| err.to_string())?
+            .into_iter()
+            .map(|record| record.email)
+            .collect(),
+    };
+    let settings = load_breach_check_settings(&state.storage).await?;
+    let breaches = state
+        .storage
+        .breaches()
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|breach| (breach.domain.clone(), breach))
+        .collect::<HashMap<_, _>>();
+    let since = Utc::now().timestamp() - breach::ALERT_WINDOW_DAYS * 24 * 60 * 60;
+
+    let mut alerts = Vec::new();
+    for account_email in accounts {
+        if let (true, Some(range_url)) = (settings.check_accounts, &settings.range_url) {
+            match breach::address_in_range(range_url, &account_email).await {
+                Ok(true) => alerts.push(breach::account_alert(&account_email)),
+                Ok(false) => {}
+                Err(err) => warn!(account = %account_email, %err, "breach range lookup failed"),
             }
         }
+        let messages = state
+            .storage
+            .security_messages(&account_email, since)
+            .await
+            .map_err(|err| err.to_string())?;
+        alerts.extend(breach::alerts(&account_email, &messages, &breaches));
+        let known = known_sender_domains(&state.storage, &account_email).await?;
+        alerts.extend(lookalike::alerts(&account_email, &messages, &known));
+    }
+    alerts.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then_with(|| b.latest_at.cmp(&a.latest_at))
     });
+    Ok(alerts)
+}
 
-    {
-        let mut jobs = state.sync_jobs.write().await;
-        jobs.insert(normalized_email, SyncHandle { cancel, handle });
+/// The lookalike checks' trusted domains for an account: its frequent
+/// senders, saved contacts, and the account's own domain.
+async fn known_sender_domains(
+    storage: &Storage,
+    account_email: &str,
+) -> Result<Vec<String>, String> {
+    let mut known = storage
+        .known_sender_domains(account_email)
+        .await
+        .map_err(|err| err.to_string())?;
+    if let Some((_, domain)) = account_email.rsplit_once('@') {
+        known.push(domain.to_string());
     }
-
-    Ok(())
+    Ok(known)
 }
 
+/// Trains the spam model on the headers currently in the provider's Junk folder.
 #[tauri::command]
-async fn apply_block_filter(
+async fn train_spam_from_junk(
     state: State<'_, AppState>,
-    provider: Provider,
     email: String,
-    target_folder: Option<String>,
+    limit: Option<usize>,
 ) -> Result<usize, String> {
-    let normalized_email = email.trim().to_lowercase();
-    let folder = target_folder.unwrap_or_else(|| "Blocked".to_string());
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::connected(&state, &email).await?;
 
-    let credentials = {
-        let accounts = state.accounts.read().await;
-        accounts
-            .get(&normalized_email)
-            .cloned()
-            .ok_or_else(|| "Account is not connected".to_string())?
-    };
+    let summaries = providers::fetch_recent_in_folder(
+        &credentials,
+        credentials.provider.junk_folder(),
+        limit.unwrap_or(200),
+    )
+    .await
+    .map_err(|err| err.to_string())?;
+
+    let documents = summaries
+        .iter()
+        .map(|summary| {
+            (
+                format!("junk:{normalized_email}:{}", summary.uid),
+                spam::tokenize(&summary.sender.email, &summary.subject, None),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let trained = state
+        .storage
+        .train_spam_documents(documents, SpamLabel::Spam)
+        .await
+        .map_err(|err| err.to_string())?;
 
-    if credentials.provider != provider {
-        return Err("Provider mismatch for stored credentials".into());
+    if trained > 0 {
+        state
+            .storage
+            .refresh_spam_scores(None, true)
+            .await
+            .map_err(|err| err.to_string())?;
     }
 
-    let statuses = state
+    Ok(trained)
+}
+
+#[tauri::command]
+async fn list_recent_messages(
+    state: State<'_, AppState>,
+    email: String,
+    limit: Option<usize>,
+) -> Result<Vec<EmailSummary>, String> {
+    let normalized_email = normalize_email(&email);
+    let limit = limit.unwrap_or(200).clamp(1, 100_000);
+
+    let cached = state
         .storage
-        .list_statuses()
+        .recent_message_summaries(&normalized_email, limit)
         .await
         .map_err(|err| err.to_string())?;
 
-    let blocked: Vec<String> = statuses
+    let messages = cached
         .into_iter()
-        .filter(|(_, status)| matches!(status, SenderStatus::Blocked))
-        .map(|(sender, _)| sender)
+        .map(|summary| EmailSummary {
+            uid: summary.uid,
+            subject: summary.subject,
+            sender: MailAddress {
+                display_name: summary.sender_display,
+                email: summary.sender_email,
+            },
+            date: summary.date,
+            auto_submitted: false,
+            to: Vec::new(),
+            size: None,
+            dmarc_aligned: None,
+            thread_id: None,
+            message_id: None,
+            references: Vec::new(),
+        })
         .collect();
 
-    if blocked.is_empty() {
-        return Ok(0);
-    }
+    Ok(messages)
+}
 
-    providers::move_blocked_to_folder(&credentials, &blocked, &folder)
+#[tauri::command]
+async fn cached_message_count(state: State<'_, AppState>, email: String) -> Result<usize, String> {
+    let normalized_email = normalize_email(&email);
+    state
+        .storage
+        .message_count_for_account(&normalized_email)
         .await
-        .map_err(|err| provider_error_to_message(err))
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
-async fn disconnect_account(state: State<'_, AppState>, email: String) -> Result<(), String> {
-    let normalized_email = email.trim().to_lowercase();
-    let mut accounts = state.accounts.write().await;
-    if accounts.remove(&normalized_email).is_none() {
-        warn!(%normalized_email, "disconnect_account requested but account not found");
-        return Err("Account not found".into());
-    }
-    drop(accounts);
+async fn delete_message(
+    state: State<'_, AppState>,
+    provider: Provider,
+    email: String,
+    uid: String,
+) -> Result<DeletedMessageRow, String> {
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::for_provider(&state, &email, provider).await?;
+    let uid = uid_arg(&uid)?;
 
-    if let Err(err) = state.storage.remove_account(&normalized_email).await {
-        error!(%normalized_email, ?err, "failed to remove persisted account metadata");
-    }
-    if let Err(err) = delete_password_from_keychain(&normalized_email) {
-        warn!(%normalized_email, ?err, "failed to delete keychain password during disconnect");
+    let mut archived = state
+        .storage
+        .archive_message(&normalized_email, "INBOX", uid)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Message not found in local cache".to_string())?;
+
+    delete_remotely(
+        state.inner(),
+        &credentials,
+        &normalized_email,
+        &mut archived,
+    )
+    .await?;
+    Ok(archived)
+}
+
+/// Queues the server-side delete of a message already archived locally.
+/// When the queue is unavailable the delete runs at once, and its outcome
+/// is recorded on the archived row.
+async fn delete_remotely(
+    state: &AppState,
+    credentials: &Credentials,
+    account_email: &str,
+    archived: &mut DeletedMessageRow,
+) -> Result<(), String> {
+    let target = archived.remote_delete();
+    let uid = target.uid;
+    let Err(err) = state
+        .remote_delete
+        .enqueue(account_email, credentials.clone(), target.clone())
+        .await
+    else {
+        return Ok(());
+    };
+    warn!(
+        account = %account_email,
+        %uid,
+        ?err,
+        "failed to enqueue remote delete; attempting synchronous fallback"
+    );
+
+    match providers::delete_message(credentials, &target.folder, uid, target.uid_validity).await {
+        Ok(_) => {
+            let now = Utc::now().timestamp();
+            state
+                .storage
+                .mark_deleted_remote(&target.message_key, Some(now), None)
+                .await
+                .map_err(|err| err.to_string())?;
+            archived.remote_deleted_at = Some(now);
+            archived.remote_error = None;
+        }
+        Err(delete_err) => {
+            error!(account = %account_email, %uid, ?delete_err, "remote delete fallback failed");
+            let message = provider_error_to_message(delete_err);
+            state
+                .storage
+                .mark_deleted_remote(&target.message_key, None, Some(message.clone()))
+                .await
+                .map_err(|err| err.to_string())?;
+            archived.remote_error = Some(message);
+        }
     }
-    info!(%normalized_email, "account disconnected");
     Ok(())
 }
 
 #[tauri::command]
-async fn oauth(client_id: String, provider: String) -> Result<String, String> {
-    let (auth_url, token_url, scope) = match provider.as_str() {
-        "gmail" => (
-            "https://accounts.google.com/o/oauth2/v2/auth",
-            "https://oauth2.googleapis.com/token",
-            "https://mail.google.com/",
-        ),
-        "outlook" => (
-            "https://login.microsoftonline.com/common/oauth2/v2.0/authorize",
-            "https://login.microsoftonline.com/common/oauth2/v2.0/token",
-            "https://outlook.office.com/IMAP.AccessAsUser.All",
-        ),
-        "yahoo" => (
-            "https://api.login.yahoo.com/oauth2/request_auth",
-            "https://api.login.yahoo.com/oauth2/get_token",
-            "mail-r",
-        ),
-        _ => return Err("Unsupported provider".to_string()),
-    };
+async fn purge_sender_messages(
+    state: State<'_, AppState>,
+    provider: Provider,
+    email: String,
+    sender_email: String,
+) -> Result<Vec<DeletedMessageRow>, String> {
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::for_provider(&state, &email, provider).await?;
+    let normalized_sender = sender_email.trim().to_lowercase();
 
-    let client = oauth2::basic::BasicClient::new(
-        ClientId::new(client_id),
-        None, // no secret for public clients
-        oauth2::AuthUrl::new(auth_url.to_string()).map_err(|e| e.to_string())?,
-        Some(oauth2::TokenUrl::new(token_url.to_string()).map_err(|e| e.to_string())?),
-    )
-    .set_redirect_uri(
-        RedirectUrl::new("http://localhost:8080".to_string()).map_err(|e| e.to_string())?,
-    );
+    let uids = state
+        .storage
+        .message_uids_for_sender(&normalized_email, "INBOX", &normalized_sender)
+        .await
+        .map_err(|err| err.to_string())?;
 
-    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    if uids.is_empty() {
+        return Ok(Vec::new());
+    }
 
-    let (auth_url_final, _csrf_token) = client
-        .authorize_url(CsrfToken::new_random)
-        .add_scope(Scope::new(scope.to_string()))
-        .set_pkce_challenge(pkce_challenge)
-        .url();
+    let mut archived_rows = Vec::new();
 
-    // Open the URL
-    Command::new("open")
-        .arg(&auth_url_final.to_string())
-        .spawn()
-        .map_err(|e| format!("Failed to open browser: {}", e))?;
+    for uid in uids {
+        let mut archived = match state
+            .storage
+            .archive_message(&normalized_email, "INBOX", uid)
+            .await
+            .map_err(|err| err.to_string())?
+        {
+            Some(row) => row,
+            None => continue,
+        };
 
-    // Start server
-    let code_shared = Arc::new(Mutex::new(None::<String>));
+        delete_remotely(
+            state.inner(),
+            &credentials,
+            &normalized_email,
+            &mut archived,
+        )
+        .await?;
+        archived_rows.push(archived);
+    }
+
+    Ok(archived_rows)
+}
+
+#[tauri::command]
+async fn list_deleted_messages(
+    state: State<'_, AppState>,
+    email: String,
+    limit: Option<usize>,
+    offset: Option<usize>,
+) -> Result<Vec<DeletedMessageRow>, String> {
+    let normalized_email = normalize_email(&email);
+    state
+        .storage
+        .list_deleted_messages(&normalized_email, limit, offset)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Looks up the account, folder, and UID behind a message key.
+#[tauri::command]
+async fn resolve_message_key(
+    state: State<'_, AppState>,
+    message_key: String,
+) -> Result<MessageIdentity, String> {
+    state
+        .storage
+        .message_by_key(message_key.trim())
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Unknown message key".to_string())
+}
+
+#[tauri::command]
+async fn restore_deleted_message(
+    state: State<'_, AppState>,
+    email: String,
+    uid: String,
+) -> Result<DeletedMessageRow, String> {
+    let normalized_email = normalize_email(&email);
+    state
+        .storage
+        .restore_deleted_message(&normalized_email, "INBOX", uid_arg(&uid)?)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Message not found in deleted archive".to_string())
+}
+
+#[tauri::command]
+async fn purge_deleted_message(
+    state: State<'_, AppState>,
+    email: String,
+    uid: String,
+) -> Result<bool, String> {
+    let normalized_email = normalize_email(&email);
+    state
+        .storage
+        .purge_deleted_message(&normalized_email, "INBOX", uid_arg(&uid)?)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_remote_delete_metrics(
+    state: State<'_, AppState>,
+    email: String,
+) -> Result<RemoteDeleteMetrics, String> {
+    let normalized_email = normalize_email(&email);
+    if normalized_email.is_empty() {
+        return Err("Account email is required".into());
+    }
+
+    Ok(state
+        .remote_delete
+        .get_metrics(&normalized_email)
+        .await)
+}
+
+#[tauri::command]
+async fn set_remote_delete_mode(
+    state: State<'_, AppState>,
+    email: String,
+    mode: String,
+) -> Result<RemoteDeleteMetrics, String> {
+    let normalized_email = normalize_email(&email);
+    if normalized_email.is_empty() {
+        return Err("Account email is required".into());
+    }
+
+    let override_mode = match mode.trim().to_lowercase().as_str() {
+        "auto" => ModeOverride::Auto,
+        "force-batch" => ModeOverride::ForceBatch,
+        other => {
+            warn!(
+                %normalized_email,
+                requested_mode = other,
+                "invalid remote delete override mode requested"
+            );
+            return Err(format!("Unsupported remote delete mode '{other}'"));
+        }
+    };
+
+    state
+        .remote_delete
+        .set_mode(&normalized_email, override_mode)
+        .await;
+
+    Ok(state
+        .remote_delete
+        .get_metrics(&normalized_email)
+        .await)
+}
+
+#[tauri::command]
+async fn configure_periodic_sync(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    provider: Provider,
+    email: String,
+    minutes: Option<u64>,
+) -> Result<(), String> {
+    let normalized_email = normalize_email(&email);
+
+    {
+        let mut jobs = state.sync_jobs.write().await;
+        if let Some(existing) = jobs.remove(&normalized_email) {
+            existing.cancel.cancel();
+            existing.handle.abort();
+        }
+    }
+
+    let Some(interval_minutes) = minutes.filter(|value| *value > 0) else {
+        info!(%normalized_email, "periodic sync disabled");
+        return Ok(());
+    };
+
+    let credentials = CommandContext::for_provider(&state, &normalized_email, provider)
+        .await?
+        .credentials;
+
+    let storage = state.storage.clone();
+    let cancel = CancellationToken::new();
+    let child_token = cancel.clone();
+    let email_clone = normalized_email.clone();
+    let credentials_clone = credentials.clone();
+
+    let handle = tokio::spawn(async move {
+        if let Err(err) =
+            perform_incremental_sync(&app, &storage, &credentials_clone, &email_clone, 200).await
+        {
+            error!(%email_clone, ?err, "initial periodic sync failed");
+        }
+
+        let mut ticker = time::interval(Duration::from_secs(interval_minutes * 60));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            tokio::select! {
+                _ = child_token.cancelled() => {
+                    info!(%email_clone, "periodic sync cancelled");
+                    break;
+                }
+                _ = ticker.tick() => {
+                    if let Err(err) = perform_incremental_sync(&app, &storage, &credentials_clone, &email_clone, 200).await {
+                        error!(%email_clone, ?err, "periodic sync iteration failed");
+                    }
+                }
+            }
+        }
+    });
+
+    {
+        let mut jobs = state.sync_jobs.write().await;
+        jobs.insert(normalized_email, SyncHandle { cancel, handle });
+    }
+
+    Ok(())
+}
+
+/// Moves mail from blocked senders out of INBOX. Senders the allow-list
+/// protects stay, even inside a blocked domain (see `policy`).
+#[tauri::command]
+async fn apply_block_filter(
+    state: State<'_, AppState>,
+    provider: Provider,
+    email: String,
+    target_folder: Option<String>,
+) -> Result<usize, String> {
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::for_provider(&state, &email, provider).await?;
+    let folder = target_folder.unwrap_or_else(|| "Blocked".to_string());
+
+    let rules = state
+        .storage
+        .list_statuses()
+        .await
+        .map_err(|err| err.to_string())?;
+    let targets = policy::block_targets(&normalized_email, &rules);
+
+    if targets.is_empty() {
+        return Ok(0);
+    }
+
+    providers::move_blocked_to_folder(&credentials, &targets, &folder)
+        .await
+        .map_err(|err| provider_error_to_message(err))
+}
+
+/// Returns the account's folder hierarchy with EXISTS/UNSEEN counts. The
+/// listing is cached for `FOLDER_TREE_TTL_SECS` unless `refresh` is set.
+#[tauri::command]
+async fn get_folder_tree(
+    state: State<'_, AppState>,
+    email: String,
+    refresh: Option<bool>,
+) -> Result<Vec<FolderNode>, String> {
+    let normalized_email = normalize_email(&email);
+    let folders =
+        cached_folder_list(state.inner(), &normalized_email, refresh.unwrap_or(false)).await?;
+    Ok(folders::build_tree(&folders))
+}
+
+async fn cached_folder_list(
+    state: &AppState,
+    account_email: &str,
+    refresh: bool,
+) -> Result<Vec<FolderStatus>, String> {
+    if !refresh {
+        let cache = state.folder_cache.read().await;
+        if let Some((fetched_at, folders)) = cache.get(account_email) {
+            if fetched_at.elapsed() < Duration::from_secs(FOLDER_TREE_TTL_SECS) {
+                return Ok(folders.clone());
+            }
+        }
+    }
+
+    let credentials = CommandContext::connected(state, account_email)
+        .await?
+        .credentials;
+    let folders = providers::list_folders(&credentials)
+        .await
+        .map_err(provider_error_to_message)?;
+
+    state
+        .folder_cache
+        .write()
+        .await
+        .insert(account_email.to_string(), (Instant::now(), folders.clone()));
+    Ok(folders)
+}
+
+#[tauri::command]
+async fn create_folder(
+    state: State<'_, AppState>,
+    email: String,
+    path: String,
+) -> Result<Vec<FolderNode>, String> {
+    let path = path.trim().to_string();
+    run_folder_operation(state.inner(), &email, FolderOperation::Create { path }).await
+}
+
+#[tauri::command]
+async fn rename_folder(
+    state: State<'_, AppState>,
+    email: String,
+    from: String,
+    to: String,
+) -> Result<Vec<FolderNode>, String> {
+    let to = to.trim().to_string();
+    run_folder_operation(state.inner(), &email, FolderOperation::Rename { from, to }).await
+}
+
+#[tauri::command]
+async fn delete_folder(
+    state: State<'_, AppState>,
+    email: String,
+    path: String,
+) -> Result<Vec<FolderNode>, String> {
+    run_folder_operation(state.inner(), &email, FolderOperation::Delete { path }).await
+}
+
+#[tauri::command]
+async fn set_folder_subscribed(
+    state: State<'_, AppState>,
+    email: String,
+    path: String,
+    subscribed: bool,
+) -> Result<Vec<FolderNode>, String> {
+    let operation = FolderOperation::SetSubscribed { path, subscribed };
+    run_folder_operation(state.inner(), &email, operation).await
+}
+
+/// Copies a message into another folder. Copies of cached INBOX messages are
+/// recorded locally and the target folder's counts are refreshed next time.
+#[tauri::command]
+async fn copy_message(
+    state: State<'_, AppState>,
+    email: String,
+    uid: String,
+    source_folder: Option<String>,
+    target_folder: String,
+) -> Result<Vec<String>, String> {
+    let normalized_email = normalize_email(&email);
+    let source_folder = source_folder.unwrap_or_else(|| "INBOX".to_string());
+    let target_folder = target_folder.trim().to_string();
+    if target_folder.is_empty() {
+        return Err("Target folder is required".into());
+    }
+    if source_folder == target_folder {
+        return Err("Source and target folders are the same".into());
+    }
+
+    let credentials = CommandContext::connected(&state, &normalized_email)
+        .await?
+        .credentials;
+
+    let uid = uid_arg(&uid)?;
+    providers::copy_message(&credentials, uid, &source_folder, &target_folder)
+        .await
+        .map_err(provider_error_to_message)?;
+    state.folder_cache.write().await.remove(&normalized_email);
+
+    // The cache only holds INBOX, so only those UIDs identify a cached row.
+    if !source_folder.eq_ignore_ascii_case("INBOX") {
+        return Ok(vec![target_folder]);
+    }
+    state
+        .storage
+        .record_message_copy(&normalized_email, "INBOX", uid, &target_folder)
+        .await
+        .map_err(|err| err.to_string())?;
+    state
+        .storage
+        .message_folders(&normalized_email, "INBOX", uid)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Applies a folder change on the server, mirrors it in the folder cache, and
+/// returns the updated tree.
+async fn run_folder_operation(
+    state: &AppState,
+    email: &str,
+    operation: FolderOperation,
+) -> Result<Vec<FolderNode>, String> {
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::connected(state, email).await?;
+
+    providers::manage_folder(&credentials, operation.clone())
+        .await
+        .map_err(provider_error_to_message)?;
+    info!(%normalized_email, ?operation, "folder updated");
+
+    let association = match &operation {
+        FolderOperation::Rename { from, to } => Some((from.as_str(), Some(to.as_str()))),
+        FolderOperation::Delete { path } => Some((path.as_str(), None)),
+        _ => None,
+    };
+    if let Some((from, to)) = association {
+        if let Err(err) = state
+            .storage
+            .update_message_folder(&normalized_email, from, to)
+            .await
+        {
+            warn!(%normalized_email, ?err, "failed to update cached folder associations");
+        }
+    }
+
+    let cached = {
+        let mut cache = state.folder_cache.write().await;
+        cache.get_mut(&normalized_email).map(|(_, folders)| {
+            operation.apply_to(folders);
+            folders.clone()
+        })
+    };
+    let folders = match cached {
+        Some(folders) => folders,
+        None => cached_folder_list(state, &normalized_email, true).await?,
+    };
+    Ok(folders::build_tree(&folders))
+}
+
+/// Starts copying a folder from one connected account into another, or
+/// resumes the unfinished run for the same pair. Progress arrives as
+/// `account-migration-progress` events; the returned row is the checkpoint
+/// the run starts from.
+#[tauri::command]
+async fn migrate_account(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    source_email: String,
+    target_email: String,
+    options: Option<MigrationOptions>,
+) -> Result<AccountMigration, String> {
+    let source_email = source_email.trim().to_lowercase();
+    let target_email = target_email.trim().to_lowercase();
+    let options = options.unwrap_or_default();
+    let (source_folder, target_folder) = options.folders();
+    if source_email == target_email && source_folder == target_folder {
+        return Err("Source and target are the same folder".into());
+    }
+
+    let source = command_context::credentials(&state, &source_email)
+        .await
+        .ok_or_else(|| format!("Account {source_email} is not connected"))?;
+    let target = command_context::credentials(&state, &target_email)
+        .await
+        .ok_or_else(|| format!("Account {target_email} is not connected"))?;
+
+    let migration = state
+        .storage
+        .start_or_resume_migration(&source_email, &target_email, &source_folder, &target_folder)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let job_key = format!("migration:{}", migration.id);
+    let mut jobs = state.sync_jobs.write().await;
+    if jobs
+        .get(&job_key)
+        .is_some_and(|job| !job.handle.is_finished())
+    {
+        return Err("This migration is already running".into());
+    }
+
+    info!(
+        id = migration.id,
+        %source_email,
+        %target_email,
+        resume_after_uid = migration.last_uid,
+        "starting account migration"
+    );
+    let cancel = CancellationToken::new();
+    let storage = state.storage.clone();
+    let batch_size = options.batch_size();
+    let checkpoint = migration.clone();
+    let child_token = cancel.clone();
+    let handle = tokio::spawn(async move {
+        migration::run(
+            app,
+            storage,
+            source,
+            target,
+            checkpoint,
+            batch_size,
+            child_token,
+        )
+        .await;
+    });
+    jobs.insert(job_key, SyncHandle { cancel, handle });
+
+    Ok(migration)
+}
+
+/// Stops a running migration after its current batch; it can be resumed by
+/// calling `migrate_account` again with the same accounts and folders.
+#[tauri::command]
+async fn cancel_migration(state: State<'_, AppState>, migration_id: i64) -> Result<bool, String> {
+    let jobs = state.sync_jobs.read().await;
+    match jobs.get(&format!("migration:{migration_id}")) {
+        Some(job) if !job.handle.is_finished() => {
+            job.cancel.cancel();
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+#[tauri::command]
+async fn get_migration_status(
+    state: State<'_, AppState>,
+    migration_id: i64,
+) -> Result<Option<AccountMigration>, String> {
+    state
+        .storage
+        .account_migration(migration_id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_lite_sync(state: State<'_, AppState>, email: String) -> Result<bool, String> {
+    let normalized_email = normalize_email(&email);
+    Ok(lite_sync_enabled(&state.storage, &normalized_email).await)
+}
+
+/// Lite sync fetches envelopes and flags only, in small batches; snippets and
+/// bodies are pulled on demand with `hydrate_messages`.
+#[tauri::command]
+async fn set_lite_sync(
+    state: State<'_, AppState>,
+    email: String,
+    enabled: bool,
+) -> Result<(), String> {
+    let normalized_email = normalize_email(&email);
+    let value = enabled.then_some("true");
+    state
+        .storage
+        .set_setting(&lite_sync_setting_key(&normalized_email), value)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_headers_only(state: State<'_, AppState>, email: String) -> Result<bool, String> {
+    let normalized_email = normalize_email(&email);
+    state
+        .storage
+        .headers_only(&normalized_email)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Headers-only mode keeps an account's snippets and bodies out of the
+/// cache; views that need a body fetch it from the server each time.
+/// Enabling it purges what is already cached and returns how many messages
+/// were cleared.
+#[tauri::command]
+async fn set_headers_only(
+    state: State<'_, AppState>,
+    email: String,
+    enabled: bool,
+) -> Result<usize, String> {
+    let normalized_email = normalize_email(&email);
+    let purged = state
+        .storage
+        .set_headers_only(&normalized_email, enabled)
+        .await
+        .map_err(|err| err.to_string())?;
+    info!(account = %normalized_email, enabled, purged, "updated headers-only mode");
+    Ok(purged)
+}
+
+/// Downloads snippets and body text for messages synced without them.
+/// Returns how many messages were updated.
+#[tauri::command]
+async fn hydrate_messages(
+    state: State<'_, AppState>,
+    email: String,
+    uids: Vec<String>,
+) -> Result<usize, String> {
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::connected(&state, &email).await?;
+
+    let uids = uid_args(&uids)?;
+    let mut hydrated = 0;
+    for chunk in uids.chunks(HYDRATE_BATCH_SIZE) {
+        let envelopes = providers::fetch_envelopes(&credentials, chunk)
+            .await
+            .map_err(provider_error_to_message)?;
+        let inserts = envelopes
+            .iter()
+            .map(|envelope| {
+                let (insert, _) = build_records(
+                    &normalized_email,
+                    &envelope.summary,
+                    envelope.snippet.clone(),
+                    envelope.body.clone(),
+                    Some(envelope.flags.as_slice()),
+                );
+                insert
+            })
+            .collect::<Vec<_>>();
+        hydrated += inserts.len();
+        state
+            .storage
+            .upsert_messages(inserts)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    info!(%normalized_email, requested = uids.len(), hydrated, "hydrated messages");
+    Ok(hydrated)
+}
+
+#[derive(Serialize)]
+struct OcrRunReport {
+    processed: usize,
+    /// Messages whose images yielded any text.
+    recognized: usize,
+    failed: usize,
+}
+
+/// Fetches messages and stores the text recognized in their images, which
+/// later analyses include. Without `uids`, picks recent messages that have
+/// almost no text of their own.
+#[tauri::command]
+async fn run_ocr(
+    state: State<'_, AppState>,
+    email: String,
+    uids: Option<Vec<String>>,
+) -> Result<OcrRunReport, String> {
+    let available = tauri::async_runtime::spawn_blocking(ocr::engine_available)
+        .await
+        .map_err(|err| err.to_string())?;
+    if !available {
+        return Err("OCR needs the tesseract command-line tool; install it and try again".into());
+    }
+
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::connected(&state, &email).await?;
+
+    let uids = match uids {
+        Some(uids) => uid_args(&uids)?,
+        None => state
+            .storage
+            .ocr_candidates(
+                &normalized_email,
+                "INBOX",
+                OCR_MAX_SNIPPET_CHARS,
+                OCR_BATCH_LIMIT,
+            )
+            .await
+            .map_err(|err| err.to_string())?,
+    };
+
+    let mut report = OcrRunReport {
+        processed: 0,
+        recognized: 0,
+        failed: 0,
+    };
+    for chunk in uids.chunks(HYDRATE_BATCH_SIZE) {
+        let messages = providers::fetch_for_transfer(&credentials, "INBOX", chunk)
+            .await
+            .map_err(provider_error_to_message)?;
+        for message in messages {
+            let uid = message.uid;
+            let raw = message.raw;
+            let outcome =
+                tauri::async_runtime::spawn_blocking(move || ocr::recognize_message(&raw))
+                    .await
+                    .map_err(|err| err.to_string())?;
+            let (text, images) = match outcome {
+                Ok(Some(result)) => (result.text, result.images),
+                Ok(None) => (String::new(), 0),
+                Err(err) => {
+                    warn!(%uid, %err, "OCR failed");
+                    report.failed += 1;
+                    continue;
+                }
+            };
+            state
+                .storage
+                .save_message_ocr(&normalized_email, "INBOX", uid, &text, images, ocr::ENGINE)
+                .await
+                .map_err(|err| err.to_string())?;
+            report.processed += 1;
+            if !text.is_empty() {
+                report.recognized += 1;
+            }
+        }
+    }
+
+    info!(
+        %normalized_email,
+        processed = report.processed,
+        recognized = report.recognized,
+        "OCR run finished"
+    );
+    Ok(report)
+}
+
+#[derive(Serialize)]
+struct AttachmentIndexReport {
+    processed: usize,
+    /// Messages with at least one PDF or Office document that had text.
+    indexed: usize,
+}
+
+/// Fetches messages and stores the text of their PDF and Office
+/// attachments, which text search then matches. Without `uids`, picks the
+/// newest messages not indexed yet.
+#[tauri::command]
+async fn index_attachments(
+    state: State<'_, AppState>,
+    email: String,
+    uids: Option<Vec<String>>,
+) -> Result<AttachmentIndexReport, String> {
+    let CommandContext {
+        account_email: normalized_email,
+        credentials,
+    } = CommandContext::connected(&state, &email).await?;
+
+    let uids = match uids {
+        Some(uids) => uid_args(&uids)?,
+        None => state
+            .storage
+            .attachment_index_candidates(&normalized_email, "INBOX", ATTACHMENT_INDEX_BATCH_LIMIT)
+            .await
+            .map_err(|err| err.to_string())?,
+    };
+
+    let mut report = AttachmentIndexReport {
+        processed: 0,
+        indexed: 0,
+    };
+    for chunk in uids.chunks(HYDRATE_BATCH_SIZE) {
+        let messages = providers::fetch_for_transfer(&credentials, "INBOX", chunk)
+            .await
+            .map_err(provider_error_to_message)?;
+        for message in messages {
+            let uid = message.uid;
+            let raw = message.raw;
+            let (text, documents) =
+                tauri::async_runtime::spawn_blocking(move || attachment_text::message_text(&raw))
+                    .await
+                    .map_err(|err| err.to_string())?;
+            state
+                .storage
+                .save_attachment_text(&normalized_email, "INBOX", uid, &text, documents)
+                .await
+                .map_err(|err| err.to_string())?;
+            report.processed += 1;
+            if !text.is_empty() {
+                report.indexed += 1;
+            }
+        }
+    }
+
+    info!(
+        %normalized_email,
+        processed = report.processed,
+        indexed = report.indexed,
+        "attachment indexing finished"
+    );
+    Ok(report)
+}
+
+/// A message's attachments with their risk flags. The message is fetched
+/// from `folder` (INBOX when left out) and the result recorded; when that
+/// fails, the list from the last fetch is returned.
+#[tauri::command]
+async fn list_attachments(
+    state: State<'_, AppState>,
+    account: String,
+    uid: String,
+    folder: Option<String>,
+) -> Result<Vec<Attachment>, String> {
+    let normalized_email = normalize_email(&account);
+    let uid = uid_arg(&uid)?;
+    let folder = folder.unwrap_or_else(|| "INBOX".to_string());
+    let mut raw = fetch_raw_messages(&state, &normalized_email, &folder, &[uid]).await;
+    let Some(raw) = raw.remove(&uid) else {
+        return state
+            .storage
+            .message_attachments(&normalized_email, &folder, uid)
+            .await
+            .map_err(|err| err.to_string());
+    };
+    let found = tauri::async_runtime::spawn_blocking(move || attachments::list(&raw))
+        .await
+        .map_err(|err| err.to_string())?;
+    state
+        .storage
+        .save_message_attachments(&normalized_email, &folder, uid, &found)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let risky = found
+        .iter()
+        .filter(|attachment| attachment.is_risky())
+        .count();
+    if risky > 0 {
+        info!(%normalized_email, %uid, risky, "flagged risky attachments");
+        if let Err(err) = state
+            .storage
+            .refresh_quarantine(Some(&normalized_email))
+            .await
+        {
+            warn!(%normalized_email, ?err, "failed to refresh quarantine");
+        }
+    }
+    Ok(found)
+}
+
+/// Writes an attachment of the message in `folder` (INBOX when left out) to
+/// `dest`, or into the account's folder in the attachment store when none
+/// is given. A risky one is only written with `confirmed` set; otherwise
+/// the error is a JSON object with code `attachment_risky` and the risks
+/// found, so the UI can ask first.
+#[tauri::command]
+async fn save_attachment(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    account: String,
+    uid: String,
+    folder: Option<String>,
+    index: usize,
+    dest: Option<String>,
+    confirmed: Option<bool>,
+) -> Result<String, String> {
+    let normalized_email = normalize_email(&account);
+    let uid = uid_arg(&uid)?;
+    let folder = folder.unwrap_or_else(|| "INBOX".to_string());
+    let dest = dest.as_deref().map(expand_path).transpose()?;
+    let raw = fetch_raw_messages(&state, &normalized_email, &folder, &[uid])
+        .await
+        .remove(&uid)
+        .ok_or_else(|| "Could not fetch the message from the server".to_string())?;
+    let (attachment, bytes) =
+        tauri::async_runtime::spawn_blocking(move || attachments::extract(&raw, index))
+            .await
+            .map_err(|err| err.to_string())?
+            .ok_or_else(|| "Attachment not found".to_string())?;
+
+    if attachment.is_risky() && !confirmed.unwrap_or(false) {
+        warn!(
+            %normalized_email,
+            %uid,
+            risks = ?attachment.risks,
+            "refused to save risky attachment without confirmation"
+        );
+        return Err(risky_attachment_error(&attachment));
+    }
+
+    let target = match dest {
+        Some(dest) => dest,
+        None => data_dir::store_dir(&app, Store::Attachments)
+            .map_err(|err| err.to_string())?
+            .join(&normalized_email)
+            .join(format!("{uid}-{}", archive_file_stem(&attachment.filename))),
+    };
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    fs::write(&target, bytes)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(target.to_string_lossy().into_owned())
+}
+
+fn risky_attachment_error(attachment: &Attachment) -> String {
+    let risks = attachment
+        .risks
+        .iter()
+        .map(|risk| risk.as_str().replace('_', " "))
+        .collect::<Vec<_>>()
+        .join(", ");
+    json!({
+        "code": "attachment_risky",
+        "message": format!(
+            "{} may be harmful ({risks}). Confirm to save it anyway.",
+            attachment.filename
+        ),
+        "risks": attachment.risks,
+    })
+    .to_string()
+}
+
+/// Links found in a cached message body, flagged when the visible text
+/// names another domain or the domain is on the local blocklist.
+#[tauri::command]
+async fn get_message_links(
+    state: State<'_, AppState>,
+    account: String,
+    uid: String,
+) -> Result<Vec<MessageLink>, String> {
+    let normalized_email = normalize_email(&account);
+    state
+        .storage
+        .message_links(&normalized_email, "INBOX", uid_arg(&uid)?)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_link_blocklist(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    state
+        .storage
+        .link_blocklist()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Replaces the local link blocklist. Entries also match their subdomains;
+/// a leading `*.` is accepted and dropped.
+#[tauri::command]
+async fn set_link_blocklist(
+    state: State<'_, AppState>,
+    domains: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let mut normalized = Vec::new();
+    for domain in domains {
+        let domain = domain
+            .trim()
+            .trim_start_matches("*.")
+            .trim_matches('.')
+            .to_lowercase();
+        if domain.is_empty() {
+            continue;
+        }
+        if !domain.contains('.') || domain.contains(char::is_whitespace) {
+            return Err(format!("Not a domain: {domain}"));
+        }
+        normalized.push(domain);
+    }
+    normalized.sort();
+    normalized.dedup();
+    state
+        .storage
+        .set_link_blocklist(normalized.clone())
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(normalized)
+}
+
+/// Tracking pixels and click trackers found in a message, or `None` if its
+/// body has not been cached.
+#[tauri::command]
+async fn get_message_trackers(
+    state: State<'_, AppState>,
+    account: String,
+    uid: String,
+) -> Result<Option<Vec<Tracker>>, String> {
+    let normalized_email = normalize_email(&account);
+    state
+        .storage
+        .message_trackers(&normalized_email, "INBOX", uid_arg(&uid)?)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// HTML of a cached message for display, or `None` when the cached body has
+/// no HTML part. Headers-only accounts cache no bodies, so theirs is fetched
+/// from the server for each view. Remote images are blocked unless the
+/// sender is on the image allow list; with tracker stripping on, open pixels
+/// are removed and tracked links point at their destinations. Images the
+/// message carries itself (`cid:` URLs) are inlined as `data:` URIs and
+/// kept, so they show again offline.
+#[tauri::command]
+async fn get_message_html(
+    state: State<'_, AppState>,
+    account: String,
+    uid: String,
+) -> Result<Option<RenderedHtml>, String> {
+    let normalized_email = normalize_email(&account);
+    let uid = uid_arg(&uid)?;
+    let body = state
+        .storage
+        .message_body(&normalized_email, "INBOX", uid)
+        .await
+        .map_err(|err| err.to_string())?;
+    let (parts, raw) = match body {
+        Some(body) => (body_text::decode_body_parts(&body), None),
+        None => {
+            let Some(raw) = transient_raw_message(&state, &normalized_email, "INBOX", uid).await
+            else {
+                return Ok(None);
+            };
+            match body_text::decode_full_message(&raw) {
+                Some(message) => (message.parts, Some(raw)),
+                None => return Ok(None),
+            }
+        }
+    };
+    if parts.html.is_empty() {
+        return Ok(None);
+    }
+
+    let policy = render_policy(&state.storage, &normalized_email, "INBOX", uid).await?;
+    let mut rendered = html_render::render(&parts.html.join("\n"), &policy);
+    if html_render::has_inline_images(&rendered.html) {
+        let images = inline_images_for(&state, &normalized_email, "INBOX", uid, raw).await?;
+        html_render::resolve_inline_images(&mut rendered, &images);
+    }
+    Ok(Some(rendered))
+}
+
+/// The `cid:` images of a message. The cached body is cut short before its
+/// images, so they are read from the complete message the first time and
+/// stored, except for headers-only accounts; `raw` is that message when the
+/// caller already has it. Empty when offline with nothing stored.
+async fn inline_images_for(
+    state: &AppState,
+    account_email: &str,
+    folder: &str,
+    uid: u32,
+    raw: Option<Zeroizing<Vec<u8>>>,
+) -> Result<Vec<InlineImage>, String> {
+    let stored = state
+        .storage
+        .inline_images(account_email, folder, uid)
+        .await
+        .map_err(|err| err.to_string())?;
+    if !stored.is_empty() {
+        return Ok(stored);
+    }
+    let raw = match raw {
+        Some(raw) => raw,
+        None => match fetch_raw_messages(state, account_email, folder, &[uid])
+            .await
+            .remove(&uid)
+        {
+            Some(raw) => raw,
+            None => return Ok(Vec::new()),
+        },
+    };
+    let images = tauri::async_runtime::spawn_blocking(move || attachments::inline_images(&raw))
+        .await
+        .map_err(|err| err.to_string())?;
+    let headers_only = state
+        .storage
+        .headers_only(account_email)
+        .await
+        .map_err(|err| err.to_string())?;
+    if !images.is_empty() && !headers_only {
+        state
+            .storage
+            .save_inline_images(account_email, folder, uid, images.clone())
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(images)
+}
+
+/// Body parts fetched from the server for a headers-only account, which
+/// has nothing cached to read. `None` for other accounts, and when the
+/// account is offline. Nothing fetched here is stored.
+async fn transient_body_parts(
+    storage: &Storage,
+    credentials: &Credentials,
+    folder: &str,
+    uid: u32,
+) -> Option<BodyParts> {
+    let raw = transient_raw_with(storage, credentials, folder, uid).await?;
+    body_text::decode_full_message(&raw).map(|message| message.parts)
+}
+
+/// The complete message for a headers-only account, as
+/// [`transient_body_parts`] reads it.
+async fn transient_raw_message(
+    state: &AppState,
+    account_email: &str,
+    folder: &str,
+    uid: u32,
+) -> Option<Zeroizing<Vec<u8>>> {
+    let credentials = command_context::credentials(state, account_email).await?;
+    transient_raw_with(&state.storage, &credentials, folder, uid).await
+}
+
+async fn transient_raw_with(
+    storage: &Storage,
+    credentials: &Credentials,
+    folder: &str,
+    uid: u32,
+) -> Option<Zeroizing<Vec<u8>>> {
+    let account_email = &credentials.email;
+    match storage.headers_only(account_email).await {
+        Ok(true) => {}
+        Ok(false) => return None,
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to read headers-only setting");
+            return None;
+        }
+    }
+    fetch_raw_with(credentials, folder, &[uid])
+        .await
+        .remove(&uid)
+}
+
+/// Quarantined messages are shown with remote images and links disabled,
+/// whatever the sender's image setting.
+async fn render_policy(
+    storage: &Storage,
+    account_email: &str,
+    folder: &str,
+    uid: u32,
+) -> Result<RenderPolicy, String> {
+    let quarantined = storage
+        .is_quarantined(account_email, folder, uid)
+        .await
+        .map_err(|err| err.to_string())?;
+    let sender = storage
+        .cached_message(account_email, folder, uid)
+        .await
+        .map_err(|err| err.to_string())?
+        .map(|message| message.sender_email);
+    let images_allowed = match sender {
+        Some(sender) => storage
+            .images_allowed_for_sender(&sender)
+            .await
+            .map_err(|err| err.to_string())?,
+        None => false,
+    };
+    Ok(RenderPolicy {
+        strip_trackers: strip_trackers_enabled(storage).await,
+        block_remote_images: quarantined || !images_allowed,
+        disable_links: quarantined,
+    })
+}
+
+/// Prints a message to a PDF at `dest`, for keeping receipts and
+/// confirmations outside the encrypted cache. The complete message is
+/// fetched when the account is connected; otherwise the cached body, which
+/// may be truncated, is used. HTML-only mail is sanitized like
+/// [`get_message_html`], with remote content blocked, and printed as text.
+#[tauri::command]
+async fn export_message_pdf(
+    state: State<'_, AppState>,
+    account: String,
+    uid: String,
+    dest: String,
+) -> Result<String, String> {
+    let normalized_email = normalize_email(&account);
+    let uid = uid_arg(&uid)?;
+    let target = expand_path(&dest)?;
+    let mut raw = fetch_raw_messages(&state, &normalized_email, "INBOX", &[uid]).await;
+    let raw = raw.remove(&uid);
+    let document = message_pdf(&state.storage, &normalized_email, "INBOX", uid, raw).await?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    fs::write(&target, document)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(target.to_string_lossy().into_owned())
+}
+
+/// Complete messages from the server, keyed by UID. Empty when the account
+/// is not connected or the fetch fails; callers fall back to the cache.
+async fn fetch_raw_messages(
+    state: &AppState,
+    account_email: &str,
+    folder: &str,
+    uids: &[u32],
+) -> HashMap<u32, Zeroizing<Vec<u8>>> {
+    match command_context::credentials(state, account_email).await {
+        Some(credentials) => fetch_raw_with(&credentials, folder, uids).await,
+        None => HashMap::new(),
+    }
+}
+
+async fn fetch_raw_with(
+    credentials: &Credentials,
+    folder: &str,
+    uids: &[u32],
+) -> HashMap<u32, Zeroizing<Vec<u8>>> {
+    let mut raw = HashMap::new();
+    for chunk in uids.chunks(HYDRATE_BATCH_SIZE) {
+        match providers::fetch_for_transfer(credentials, folder, chunk).await {
+            Ok(messages) => {
+                for message in messages {
+                    raw.insert(message.uid, Zeroizing::new(message.raw));
+                }
+            }
+            Err(err) => {
+                warn!(
+                    account = %credentials.email,
+                    ?err,
+                    "failed to fetch full messages; using the cache"
+                );
+            }
+        }
+    }
+    raw
+}
+
+/// Prints one message, from its complete source when `raw` is given and
+/// from the cached summary and body otherwise.
+async fn message_pdf(
+    storage: &Storage,
+    account_email: &str,
+    folder: &str,
+    uid: u32,
+    raw: Option<Zeroizing<Vec<u8>>>,
+) -> Result<Vec<u8>, String> {
+    let full = raw.and_then(|raw| body_text::decode_full_message(&raw));
+    let (headers, parts, snippet) = match full {
+        Some(message) => (message.headers, message.parts, None),
+        None => {
+            let cached = storage
+                .cached_message(account_email, folder, uid)
+                .await
+                .map_err(|err| err.to_string())?
+                .ok_or_else(|| "Message not found".to_string())?;
+            let sender = match &cached.sender_display {
+                Some(display) => format!("{display} <{}>", cached.sender_email),
+                None => cached.sender_email.clone(),
+            };
+            let mut headers = vec![("From", sender)];
+            if let Some(date) = &cached.date {
+                headers.push(("Date", date.clone()));
+            }
+            headers.push(("Subject", cached.subject.clone()));
+            let body = storage
+                .message_body(account_email, folder, uid)
+                .await
+                .map_err(|err| err.to_string())?
+                .unwrap_or_default();
+            (headers, body_text::decode_body_parts(&body), cached.snippet)
+        }
+    };
+
+    let text = Zeroizing::new(if !parts.plain.is_empty() {
+        parts.plain.join("\n\n")
+    } else if !parts.html.is_empty() {
+        let policy = RenderPolicy {
+            strip_trackers: true,
+            block_remote_images: true,
+            disable_links: false,
+        };
+        let html = Zeroizing::new(parts.html.join("\n"));
+        let mut rendered = html_render::render(&html, &policy);
+        let text = body_text::html_to_text(&rendered.html);
+        rendered.html.zeroize();
+        text
+    } else {
+        snippet.unwrap_or_default()
+    });
+
+    let title = headers
+        .iter()
+        .find(|(name, _)| *name == "Subject")
+        .map(|(_, value)| value.trim())
+        .filter(|value| !value.is_empty())
+        .unwrap_or("(no subject)")
+        .to_string();
+    Ok(pdf::text_document(&title, &headers, &text))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageRef {
+    account_email: String,
+    /// INBOX when left out.
+    folder: Option<String>,
+    #[serde(with = "uid_string")]
+    uid: u32,
+}
+
+fn message_refs(messages: Vec<MessageRef>) -> Vec<(String, String, u32)> {
+    messages
+        .into_iter()
+        .map(|message| {
+            (
+                message.account_email.trim().to_lowercase(),
+                message.folder.unwrap_or_else(|| "INBOX".to_string()),
+                message.uid,
+            )
+        })
+        .collect()
+}
+
+#[tauri::command]
+async fn list_collections(state: State<'_, AppState>) -> Result<Vec<Collection>, String> {
+    state
+        .storage
+        .list_collections()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Creates a collection, or renames an existing one when `id` is given.
+#[tauri::command]
+async fn save_collection(
+    state: State<'_, AppState>,
+    id: Option<i64>,
+    name: String,
+) -> Result<Collection, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Collection name is required".into());
+    }
+    let existing = state
+        .storage
+        .list_collections()
+        .await
+        .map_err(|err| err.to_string())?;
+    if existing
+        .iter()
+        .any(|collection| collection.name.eq_ignore_ascii_case(name) && Some(collection.id) != id)
+    {
+        return Err(format!("A collection named \"{name}\" already exists"));
+    }
+
+    let saved_id = state
+        .storage
+        .save_collection(id, name)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Collection not found".to_string())?;
+    state
+        .storage
+        .list_collections()
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .find(|collection| collection.id == saved_id)
+        .ok_or_else(|| "Collection not found".to_string())
+}
+
+#[tauri::command]
+async fn delete_collection(state: State<'_, AppState>, id: i64) -> Result<bool, String> {
+    state
+        .storage
+        .delete_collection(id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Returns how many of `messages` were not already in the collection.
+#[tauri::command]
+async fn add_to_collection(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    messages: Vec<MessageRef>,
+) -> Result<usize, String> {
+    state
+        .storage
+        .add_to_collection(collection_id, message_refs(messages))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn remove_from_collection(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    messages: Vec<MessageRef>,
+) -> Result<usize, String> {
+    state
+        .storage
+        .remove_from_collection(collection_id, message_refs(messages))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_collection_items(
+    state: State<'_, AppState>,
+    collection_id: i64,
+) -> Result<Vec<CollectionItem>, String> {
+    state
+        .storage
+        .collection_items(collection_id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CollectionExportFormat {
+    Eml,
+    Pdf,
+    #[default]
+    Both,
+}
+
+#[derive(Serialize)]
+struct CollectionExportReport {
+    path: String,
+    eml_files: usize,
+    pdf_files: usize,
+    /// `account/uid` of messages that could not be exported at all.
+    skipped: Vec<String>,
+}
+
+/// Writes a collection to a zip at `dest`: each message as its original
+/// `.eml`, a printed `.pdf`, or both. EML files need the account to be
+/// connected, since the cache keeps only part of each message; PDFs fall
+/// back to the cached text.
+#[tauri::command]
+async fn export_collection(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    dest: String,
+    format: Option<CollectionExportFormat>,
+) -> Result<CollectionExportReport, String> {
+    let format = format.unwrap_or_default();
+    let target = expand_path(&dest)?;
+    let items = state
+        .storage
+        .collection_items(collection_id)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut by_folder: HashMap<(String, String), Vec<u32>> = HashMap::new();
+    for item in &items {
+        by_folder
+            .entry((item.account_email.clone(), item.folder.clone()))
+            .or_default()
+            .push(item.uid);
+    }
+    // Complete messages make better PDFs too, so fetch them for any format.
+    let mut raw_messages = HashMap::new();
+    for ((account, folder), uids) in &by_folder {
+        raw_messages.insert(
+            (account.clone(), folder.clone()),
+            fetch_raw_messages(&state, account, folder, uids).await,
+        );
+    }
+
+    let mut archive = archive::ZipWriter::new(Vec::new());
+    let mut report = CollectionExportReport {
+        path: target.to_string_lossy().into_owned(),
+        eml_files: 0,
+        pdf_files: 0,
+        skipped: Vec::new(),
+    };
+    for (index, item) in items.iter().enumerate() {
+        let raw = raw_messages
+            .get_mut(&(item.account_email.clone(), item.folder.clone()))
+            .and_then(|messages| messages.remove(&item.uid));
+        let stem = format!(
+            "{:03}-{}",
+            index + 1,
+            archive_file_stem(item.subject.as_deref().unwrap_or("message"))
+        );
+        let mut exported = false;
+
+        if format != CollectionExportFormat::Pdf {
+            if let Some(raw) = &raw {
+                archive
+                    .add(&format!("{stem}.eml"), raw)
+                    .map_err(|err| err.to_string())?;
+                report.eml_files += 1;
+                exported = true;
+            }
+        }
+        if format != CollectionExportFormat::Eml {
+            let account = &item.account_email;
+            match message_pdf(&state.storage, account, &item.folder, item.uid, raw).await {
+                Ok(document) => {
+                    archive
+                        .add(&format!("{stem}.pdf"), &document)
+                        .map_err(|err| err.to_string())?;
+                    report.pdf_files += 1;
+                    exported = true;
+                }
+                Err(err) => warn!(uid = %item.uid, %err, "skipping message in collection export"),
+            }
+        }
+        if !exported {
+            report
+                .skipped
+                .push(format!("{}/{}", item.account_email, item.uid));
+        }
+    }
+
+    let bytes = archive.finish().map_err(|err| err.to_string())?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    fs::write(&target, bytes)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(report)
+}
+
+/// A subject reduced to something safe as a file name on every platform.
+fn archive_file_stem(subject: &str) -> String {
+    let cleaned = subject
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    let trimmed = cleaned
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches('.')
+        .chars()
+        .take(60)
+        .collect::<String>();
+    if trimmed.is_empty() {
+        "message".to_string()
+    } else {
+        trimmed
+    }
+}
+
+/// Shows remote images in mail from `sender` from now on.
+#[tauri::command]
+async fn allow_images_for_sender(state: State<'_, AppState>, sender: String) -> Result<(), String> {
+    let sender = sender.trim().to_lowercase();
+    if sender.is_empty() {
+        return Err("Sender is required".into());
+    }
+    state
+        .storage
+        .set_images_allowed_for_sender(&sender, true)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn revoke_images_for_sender(
+    state: State<'_, AppState>,
+    sender: String,
+) -> Result<(), String> {
+    state
+        .storage
+        .set_images_allowed_for_sender(sender.trim(), false)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn get_image_allowlist(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    state
+        .storage
+        .image_allowlist()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+async fn strip_trackers_enabled(storage: &Storage) -> bool {
+    match storage.get_setting(STRIP_TRACKERS_SETTING_KEY).await {
+        Ok(value) => value.as_deref() == Some("true"),
+        Err(err) => {
+            warn!(?err, "failed to read tracker stripping setting");
+            false
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_strip_trackers(state: State<'_, AppState>) -> Result<bool, String> {
+    Ok(strip_trackers_enabled(&state.storage).await)
+}
+
+#[tauri::command]
+async fn set_strip_trackers(state: State<'_, AppState>, enabled: bool) -> Result<(), String> {
+    state
+        .storage
+        .set_setting(STRIP_TRACKERS_SETTING_KEY, enabled.then_some("true"))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+async fn tombstone_retention_days(storage: &Storage) -> u32 {
+    match storage.get_setting(TOMBSTONE_RETENTION_SETTING_KEY).await {
+        Ok(value) => value
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_TOMBSTONE_RETENTION_DAYS),
+        Err(err) => {
+            warn!(?err, "failed to read tombstone retention setting");
+            DEFAULT_TOMBSTONE_RETENTION_DAYS
+        }
+    }
+}
+
+#[tauri::command]
+async fn get_tombstone_retention_days(state: State<'_, AppState>) -> Result<u32, String> {
+    Ok(tombstone_retention_days(&state.storage).await)
+}
+
+#[tauri::command]
+async fn set_tombstone_retention_days(state: State<'_, AppState>, days: u32) -> Result<(), String> {
+    if days == 0 {
+        return Err("Retention must be at least one day".into());
+    }
+    state
+        .storage
+        .set_setting(TOMBSTONE_RETENTION_SETTING_KEY, Some(&days.to_string()))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Purges tombstoned messages past the retention period at startup and
+/// every few hours after.
+/// Restores the earlier status of senders whose mute ran out and tells the
+/// UI which ones.
+async fn expire_sender_mutes_periodically(app: tauri::AppHandle, storage: Storage) {
+    let mut ticker = time::interval(Duration::from_secs(SENDER_MUTE_CHECK_INTERVAL_SECS));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let rules = match storage.expire_sender_mutes(Utc::now().timestamp()).await {
+            Ok(rules) if rules.is_empty() => continue,
+            Ok(rules) => rules,
+            Err(err) => {
+                warn!(?err, "failed to expire sender mutes");
+                continue;
+            }
+        };
+        info!(count = rules.len(), "sender mutes expired");
+        events::emit(&app, &SenderMutesExpired { rules });
+    }
+}
+
+/// Reports the mail focus mode held back each time a review time passes.
+async fn focus_reviews_periodically(app: tauri::AppHandle, storage: Storage) {
+    let mut ticker = time::interval(Duration::from_secs(FOCUS_REVIEW_CHECK_INTERVAL_SECS));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let settings = match load_focus_settings(&storage).await {
+            Ok(settings) => settings,
+            Err(err) => {
+                warn!(%err, "failed to load focus mode settings");
+                continue;
+            }
+        };
+        let last_review_at = match storage.get_setting(focus::LAST_REVIEW_KEY).await {
+            Ok(value) => value
+                .and_then(|value| value.parse::<i64>().ok())
+                .unwrap_or_default(),
+            Err(err) => {
+                warn!(?err, "failed to read last focus review time");
+                continue;
+            }
+        };
+        let now = Local::now();
+        if !settings.review_due(last_review_at, now) {
+            continue;
+        }
+
+        let messages = match storage.take_focus_review(FOCUS_REVIEW_LIMIT).await {
+            Ok(messages) => messages,
+            Err(err) => {
+                warn!(?err, "failed to collect held focus mail");
+                continue;
+            }
+        };
+        let stamp = now.timestamp().to_string();
+        if let Err(err) = storage
+            .set_setting(focus::LAST_REVIEW_KEY, Some(&stamp))
+            .await
+        {
+            warn!(?err, "failed to record focus review time");
+        }
+        if messages.is_empty() {
+            continue;
+        }
+        info!(count = messages.len(), "focus review ready");
+        events::emit(&app, &FocusReview { messages });
+    }
+}
+
+async fn purge_tombstones_periodically(storage: Storage) {
+    let mut ticker = time::interval(Duration::from_secs(TOMBSTONE_PURGE_INTERVAL_SECS));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let days = tombstone_retention_days(&storage).await;
+        let cutoff = Utc::now().timestamp() - i64::from(days) * 24 * 60 * 60;
+        match storage.purge_tombstones(cutoff).await {
+            Ok(0) => {}
+            Ok(purged) => info!(purged, days, "purged expired message tombstones"),
+            Err(err) => warn!(?err, "failed to purge message tombstones"),
+        }
+    }
+}
+
+#[tauri::command]
+async fn disconnect_account(state: State<'_, AppState>, email: String) -> Result<(), String> {
+    let normalized_email = normalize_email(&email);
+    let mut accounts = state.accounts.write().await;
+    let Some(credentials) = accounts.remove(&normalized_email) else {
+        warn!(%normalized_email, "disconnect_account requested but account not found");
+        return Err("Account not found".into());
+    };
+    drop(accounts);
+    if let Some(job) = state
+        .sync_jobs
+        .write()
+        .await
+        .remove(&idle_job_key(&normalized_email))
+    {
+        job.cancel.cancel();
+        job.handle.abort();
+    }
+    session::discard_idle(&credentials);
+    oauth::forget(&credentials);
+    state.folder_cache.write().await.remove(&normalized_email);
+
+    if let Err(err) = state.storage.remove_account(&normalized_email).await {
+        error!(%normalized_email, ?err, "failed to remove persisted account metadata");
+    }
+    if let Err(err) = delete_password_from_keychain(&normalized_email) {
+        warn!(%normalized_email, ?err, "failed to delete keychain password during disconnect");
+    }
+    info!(%normalized_email, "account disconnected");
+    Ok(())
+}
+
+/// Runs OAuth consent in the browser and returns the access token.
+#[tauri::command]
+async fn oauth(client_id: String, provider: String) -> Result<String, String> {
+    let provider = Provider::from_key(&provider).ok_or("Unsupported provider")?;
+    let token = authorize_in_browser(provider, &client_id).await?;
+    Ok(token.access_token().secret().clone())
+}
+
+/// Opens the provider's consent page and waits for the browser to come
+/// back to `oauth::REDIRECT_URI` with a code, then trades it for tokens.
+async fn authorize_in_browser(
+    provider: Provider,
+    client_id: &str,
+) -> Result<BasicTokenResponse, String> {
+    let authorization = oauth::authorize(provider, client_id).map_err(|err| err.to_string())?;
+
+    // Open the URL
+    Command::new("open")
+        .arg(authorization.url.to_string())
+        .spawn()
+        .map_err(|e| format!("Failed to open browser: {}", e))?;
+
+    // Start server
+    let code_shared = Arc::new(Mutex::new(None::<String>));
     let code_clone = Arc::clone(&code_shared);
+    let expected_state = authorization.csrf.secret().clone();
+
+    let routes = warp::get()
+        .and(warp::query::<HashMap<String, String>>())
+        .and_then(move |query: HashMap<String, String>| {
+            let code = Arc::clone(&code_clone);
+            let state_matches = query.get("state") == Some(&expected_state);
+            async move {
+                match query.get("code") {
+                    Some(c) if state_matches => {
+                        *code.lock().unwrap() = Some(c.clone());
+                        Ok::<_, warp::Rejection>(warp::reply::html(
+                            "Authorization successful! You can close this window.",
+                        ))
+                    }
+                    _ => Ok::<_, warp::Rejection>(warp::reply::html("Authorization failed.")),
+                }
+            }
+        });
+
+    let (shutdown, stopped) = tokio::sync::oneshot::channel::<()>();
+    let (_, server) = warp::serve(routes)
+        .try_bind_with_graceful_shutdown(([127, 0, 0, 1], 8080), async {
+            let _ = stopped.await;
+        })
+        .map_err(|err| format!("Could not listen for the sign-in redirect: {err}"))?;
+
+    tokio::spawn(server);
+
+    // Wait for the code
+    let code = loop {
+        time::sleep(time::Duration::from_millis(100)).await;
+        if let Some(c) = code_shared.lock().unwrap().take() {
+            break c;
+        }
+    };
+    let _ = shutdown.send(());
+
+    oauth::exchange_code(provider, client_id, authorization, code)
+        .await
+        .map_err(|err| format!("Token exchange failed: {err}"))
+}
+
+/// Connects a Gmail, Outlook, or Yahoo account through OAuth consent in the
+/// browser instead of an app password. The refresh token goes to the
+/// keychain in the password's place, and the account signs in with
+/// `XOAUTH2` from then on.
+#[tauri::command]
+async fn connect_account_oauth(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    provider: Provider,
+    email: String,
+    client_id: String,
+) -> Result<ConnectAccountResponse, String> {
+    let normalized_email = require_email(&email)?;
+    let client_id = client_id.trim().to_string();
+    if client_id.is_empty() {
+        return Err("An OAuth client ID is required".into());
+    }
+
+    let token = authorize_in_browser(provider, &client_id).await?;
+    let refresh_token = token
+        .refresh_token()
+        .map(|token| SecretString::new(token.secret().clone()))
+        .ok_or_else(|| "The provider did not grant offline access".to_string())?;
+    let credentials = Credentials::new(
+        provider,
+        normalized_email.clone(),
+        refresh_token.clone(),
+        None,
+        None,
+    )
+    .with_auth(AuthMethod::OAuth2 { client_id });
+    oauth::remember(&credentials, &token);
+
+    let response = perform_connect(&app, state.inner(), credentials).await?;
+
+    if let Err(err) = store_password_in_keychain(&normalized_email, &refresh_token) {
+        warn!(%normalized_email, ?err, "failed to persist refresh token in keychain");
+    }
+
+    Ok(response)
+}
+
+/// Login failures travel to the UI as a JSON object (`code`, `message`,
+/// `help_url`) so it can branch on the code; the message alone is the fallback.
+fn login_issue_to_error(issue: &LoginIssue) -> String {
+    serde_json::to_string(issue).unwrap_or_else(|_| issue.message.clone())
+}
+
+fn idle_job_key(account_email: &str) -> String {
+    format!("idle:{account_email}")
+}
+
+/// Starts the account's IDLE listener, replacing one already running.
+async fn start_idle_listener(app: &tauri::AppHandle, state: &AppState, credentials: &Credentials) {
+    let cancel = CancellationToken::new();
+    let handle = tokio::spawn(listen_for_new_mail(
+        app.clone(),
+        state.storage.clone(),
+        credentials.clone(),
+        cancel.clone(),
+    ));
+    let mut jobs = state.sync_jobs.write().await;
+    if let Some(existing) = jobs.insert(
+        idle_job_key(&credentials.email),
+        SyncHandle { cancel, handle },
+    ) {
+        existing.cancel.cancel();
+        existing.handle.abort();
+    }
+}
+
+/// Keeps an IDLE listener on the account's INBOX until cancelled, reopening
+/// it with backoff when the connection drops. Each report of new mail runs
+/// an incremental sync and then sends `new-mail`. Servers without IDLE are
+/// left to `configure_periodic_sync`.
+async fn listen_for_new_mail(
+    app: tauri::AppHandle,
+    storage: Storage,
+    credentials: Credentials,
+    cancel: CancellationToken,
+) {
+    let account_email = credentials.email.clone();
+    let mut retry = Duration::from_secs(IDLE_RETRY_MIN_SECS);
+    loop {
+        let (mut arrivals, handle) = idle::listen(&credentials, "INBOX", cancel.clone());
+        while let Some(mut arrival) = arrivals.recv().await {
+            retry = Duration::from_secs(IDLE_RETRY_MIN_SECS);
+            // One sync covers every report that queued up behind it.
+            while let Ok(next) = arrivals.try_recv() {
+                arrival = next;
+            }
+            let synced = match perform_incremental_sync(
+                &app,
+                &storage,
+                &credentials,
+                &account_email,
+                IDLE_SYNC_LIMIT,
+            )
+            .await
+            {
+                Ok(()) => true,
+                Err(err) => {
+                    warn!(account = %account_email, ?err, "sync after IDLE report failed");
+                    false
+                }
+            };
+            events::emit(
+                &app,
+                &NewMail {
+                    account_email: account_email.clone(),
+                    exists: arrival.exists,
+                    synced,
+                },
+            );
+        }
+
+        match handle.await {
+            Ok(Ok(IdleExit::Cancelled)) => return,
+            Ok(Ok(IdleExit::Unsupported)) => {
+                info!(account = %account_email, "server has no IDLE, relying on periodic sync");
+                return;
+            }
+            Ok(Err(err)) => {
+                warn!(
+                    account = %account_email,
+                    ?err,
+                    retry_secs = retry.as_secs(),
+                    "IDLE connection failed"
+                );
+            }
+            Err(err) => {
+                warn!(account = %account_email, ?err, "IDLE listener task failed");
+            }
+        }
+        tokio::select! {
+            _ = cancel.cancelled() => return,
+            _ = time::sleep(retry) => {}
+        }
+        retry = (retry * 2).min(Duration::from_secs(IDLE_RETRY_MAX_SECS));
+    }
+}
+
+/// UIDs arrive from the frontend as strings; providers take them as integers.
+fn uid_arg(uid: &str) -> Result<u32, String> {
+    parse_uid(uid).ok_or_else(|| format!("Invalid message UID '{uid}'"))
+}
+
+fn uid_args(uids: &[String]) -> Result<Vec<u32>, String> {
+    uids.iter().map(|uid| uid_arg(uid)).collect()
+}
+
+async fn perform_incremental_sync(
+    app: &tauri::AppHandle,
+    storage: &Storage,
+    credentials: &Credentials,
+    account_email: &str,
+    limit: usize,
+) -> Result<(), ProviderError> {
+    let summaries = providers::fetch_recent(credentials, limit).await?;
+
+    let mut inserts = Vec::with_capacity(summaries.len());
+    let mut analyses = Vec::with_capacity(summaries.len());
+
+    for summary in &summaries {
+        let (insert, analysis) = build_records(account_email, summary, None, None, None);
+        inserts.push(insert);
+        analyses.push(analysis);
+    }
+
+    if !summaries.is_empty() {
+        if let Err(err) = storage.upsert_batch(inserts, analyses).await {
+            error!(
+                account = %account_email,
+                ?err,
+                "failed to persist messages during periodic sync"
+            );
+        }
+    }
+    process_synced_mail(app, storage, credentials, account_email, &summaries).await;
+
+    Ok(())
+}
+
+/// Runs after every sync, whichever brought the mail in: the hooks for
+/// newly cached `summaries`, thread mutes, reconciling messages deleted on
+/// the server, and pushing queued flag changes.
+async fn process_synced_mail(
+    app: &tauri::AppHandle,
+    storage: &Storage,
+    credentials: &Credentials,
+    account_email: &str,
+    summaries: &[EmailSummary],
+) {
+    surface_one_time_codes(app, storage, account_email, summaries).await;
+    enrich_cached_messages(storage, account_email).await;
+    process_autoreplies(storage, account_email, summaries).await;
+    detect_bounces(storage, credentials, account_email, summaries).await;
+    read_calendar_invites(storage, credentials, account_email, summaries).await;
+    apply_focus_mode(app, storage, account_email, summaries).await;
+    apply_thread_mutes(app, storage, account_email, summaries).await;
+    reconcile_server_deletions(app, storage, credentials, account_email).await;
+    if let Err(err) = push_flag_changes(app, storage, credentials, account_email).await {
+        warn!(account = %account_email, ?err, "queued flag changes were not pushed");
+    }
+}
+
+/// Sends flag edits queued while offline to the server and caches the flags
+/// it reports back. Edits the server refuses [`MAX_FLAG_PUSH_ATTEMPTS`]
+/// times are undone in the cache and reported with
+/// `local-update-rolled-back`. Returns how many edits were pushed.
+async fn push_flag_changes(
+    app: &tauri::AppHandle,
+    storage: &Storage,
+    credentials: &Credentials,
+    account_email: &str,
+) -> Result<usize, String> {
+    let pending = storage
+        .pending_flag_changes(account_email)
+        .await
+        .map_err(|err| err.to_string())?;
+    if pending.is_empty() {
+        return Ok(0);
+    }
+
+    // UIDs only name a message within their folder and UIDVALIDITY, so
+    // each store covers one of those.
+    let mut groups: HashMap<FlagPushKey, Vec<PendingFlagChange>> = HashMap::new();
+    for change in pending {
+        let key = (
+            change.folder.clone(),
+            change.uid_validity,
+            change.flag.clone(),
+            change.enabled,
+        );
+        groups.entry(key).or_default().push(change);
+    }
+
+    let mut pushed: HashMap<(String, Option<u32>), PushedFlags> = HashMap::new();
+    let mut failed = Vec::new();
+    for ((folder, uid_validity, flag, enabled), changes) in groups {
+        let uids = changes.iter().map(|change| change.uid).collect::<Vec<_>>();
+        let flags = [flag.clone()];
+        match providers::store_flags(credentials, &folder, &uids, uid_validity, &flags, enabled)
+            .await
+        {
+            Ok(updated) => {
+                let done = pushed.entry((folder, uid_validity)).or_default();
+                for (uid, flags) in updated {
+                    done.server_flags
+                        .insert(uid, (!flags.is_empty()).then(|| flags.join(" ")));
+                }
+                done.changes.extend(changes);
+            }
+            Err(err) => failed.push((flag, enabled, changes, provider_error_to_message(err))),
+        }
+    }
+
+    let mut count = 0;
+    for ((folder, uid_validity), done) in pushed {
+        count += done.changes.len();
+        storage
+            .complete_flag_changes(
+                account_email,
+                &folder,
+                uid_validity,
+                done.changes,
+                done.server_flags.into_iter().collect(),
+            )
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    let mut failure = None;
+    for (flag, enabled, changes, message) in failed {
+        let rolled_back = storage
+            .fail_flag_changes(account_email, changes, MAX_FLAG_PUSH_ATTEMPTS)
+            .await
+            .map_err(|err| err.to_string())?;
+        if !rolled_back.is_empty() {
+            warn!(
+                account = %account_email,
+                %flag,
+                count = rolled_back.len(),
+                %message,
+                "gave up pushing flag changes; restored the server's flags"
+            );
+            let payload = LocalUpdate {
+                account_email: account_email.to_string(),
+                action: if enabled { "set_flags" } else { "clear_flags" }.to_string(),
+                uids: rolled_back.into_iter().map(|change| change.uid).collect(),
+                target: Some(flag),
+                error: Some(message.clone()),
+            };
+            emit_local_update(app, payload, LocalUpdateRolledBack);
+        }
+        failure = Some(message);
+    }
+    match failure {
+        Some(message) => Err(message),
+        None => Ok(count),
+    }
+}
+
+/// The folder, UIDVALIDITY, flag, and direction one flag store covers.
+type FlagPushKey = (String, Option<u32>, String, bool);
+
+/// Flag edits that reached one folder, and the flags the server reported
+/// for those messages afterwards.
+#[derive(Default)]
+struct PushedFlags {
+    changes: Vec<PendingFlagChange>,
+    server_flags: HashMap<u32, Option<String>>,
+}
+
+/// Emits `update` as [`LocalUpdateApplied`] or [`LocalUpdateRolledBack`],
+/// unless it names no messages.
+fn emit_local_update<E: Event>(
+    app: &tauri::AppHandle,
+    update: LocalUpdate,
+    event: fn(LocalUpdate) -> E,
+) {
+    if !update.uids.is_empty() {
+        events::emit(app, &event(update));
+    }
+}
+
+fn emit_flag_conflicts(app: &tauri::AppHandle, account_email: &str, conflicts: Vec<FlagConflict>) {
+    if conflicts.is_empty() {
+        return;
+    }
+    info!(account = %account_email, count = conflicts.len(), "resolved flag conflicts");
+    let payload = FlagConflicts {
+        account_email: account_email.to_string(),
+        conflicts,
+    };
+    events::emit(app, &payload);
+}
+
+/// Tombstones cached INBOX messages the server no longer has, such as ones
+/// deleted from another client, and emits `messages-removed` for them.
+async fn reconcile_server_deletions(
+    app: &tauri::AppHandle,
+    storage: &Storage,
+    credentials: &Credentials,
+    account_email: &str,
+) {
+    let listing = match providers::inbox_uids(credentials).await {
+        Ok(listing) => listing,
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to list INBOX UIDs for reconciliation");
+            return;
+        }
+    };
+    if let Some(uid_validity) = listing.uid_validity {
+        // After a reset the cache was dropped; nothing is left to compare.
+        match storage
+            .record_uid_validity(account_email, "INBOX", uid_validity)
+            .await
+        {
+            Ok(0) => {}
+            Ok(purged) => {
+                warn!(account = %account_email, purged, "INBOX UIDVALIDITY changed; dropped cached messages");
+                return;
+            }
+            Err(err) => {
+                error!(account = %account_email, ?err, "failed to record INBOX UIDVALIDITY");
+                return;
+            }
+        }
+    }
+
+    let vanished = match storage
+        .tombstone_vanished(account_email, "INBOX", listing.uids, listing.uid_next)
+        .await
+    {
+        Ok(vanished) => vanished,
+        Err(err) => {
+            error!(account = %account_email, ?err, "failed to tombstone messages deleted on the server");
+            return;
+        }
+    };
+    if vanished.is_empty() {
+        return;
+    }
+
+    info!(account = %account_email, count = vanished.len(), "messages deleted on the server");
+    let (uids, message_keys) = vanished.into_iter().unzip();
+    let payload = MessagesRemoved {
+        account_email: account_email.to_string(),
+        uids,
+        message_keys,
+    };
+    events::emit(app, &payload);
+}
+
+/// Post-sync enrichment that needs no model: sender-profile pre-fill, spam
+/// scores, and quarantine.
+async fn enrich_cached_messages(storage: &Storage, account_email: &str) {
+    if let Err(err) = storage.refresh_spam_scores(Some(account_email), false).await {
+        warn!(account = %account_email, ?err, "failed to score new messages for spam");
+    }
+    match storage.refresh_quarantine(Some(account_email)).await {
+        Ok(0) => {}
+        Ok(count) => info!(account = %account_email, count, "quarantined high-risk messages"),
+        Err(err) => warn!(account = %account_email, ?err, "failed to refresh quarantine"),
+    }
+
+    match storage
+        .prefill_from_sender_profiles(
+            account_email,
+            SENDER_PROFILE_MIN_CONFIDENCE,
+            SENDER_PROFILE_MIN_SAMPLES as i64,
+        )
+        .await
+    {
+        Ok(0) => {}
+        Ok(count) => {
+            debug!(account = %account_email, count, "pre-filled analyses from sender profiles")
+        }
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to pre-fill analyses from sender profiles")
+        }
+    }
+}
+
+fn lite_sync_setting_key(account_email: &str) -> String {
+    format!("lite_sync:{account_email}")
+}
+
+async fn lite_sync_enabled(storage: &Storage, account_email: &str) -> bool {
+    let key = lite_sync_setting_key(account_email);
+    match storage.get_setting(&key).await {
+        Ok(value) => value.as_deref() == Some("true"),
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to read lite sync setting");
+            false
+        }
+    }
+}
+
+async fn load_autoreply_settings(
+    storage: &Storage,
+    account_email: &str,
+) -> Result<AutoReplySettings, String> {
+    let raw = storage
+        .get_setting(&AutoReplySettings::setting_key(account_email))
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(AutoReplySettings::default()),
+    }
+}
+
+/// Queues out-of-office replies for newly synced mail. Replies go through the
+/// outbox like any other outgoing message.
+/// Reads delivery failure reports among newly synced messages and links
+/// them to the outbox entries they answer. Likely bounces are fetched in
+/// full once; everything else is left alone.
+async fn detect_bounces(
+    storage: &Storage,
+    credentials: &Credentials,
+    account_email: &str,
+    summaries: &[EmailSummary],
+) {
+    let candidates = summaries
+        .iter()
+        .filter(|message| bounces::is_candidate(message))
+        .map(|message| message.uid)
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return;
+    }
+    let unscanned = match storage
+        .unscanned_bounce_uids(account_email, "INBOX", candidates)
+        .await
+    {
+        Ok(uids) => uids,
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to check bounce scans");
+            return;
+        }
+    };
+
+    for uid in unscanned {
+        let raw = match providers::fetch_raw_message(credentials, uid).await {
+            Ok(Some(raw)) => raw,
+            Ok(None) => continue,
+            Err(err) => {
+                warn!(account = %account_email, %uid, ?err, "failed to fetch possible bounce");
+                continue;
+            }
+        };
+        match storage
+            .record_bounce(account_email, "INBOX", uid, bounces::parse(&raw))
+            .await
+        {
+            Ok(added) if !added.is_empty() => {
+                info!(
+                    account = %account_email,
+                    %uid,
+                    recipients = added.len(),
+                    linked = added.iter().filter(|bounce| bounce.outbox_id.is_some()).count(),
+                    "delivery failure recorded"
+                );
+            }
+            Ok(_) => {}
+            Err(err) => warn!(account = %account_email, %uid, ?err, "failed to record bounce"),
+        }
+    }
+}
+
+/// Reads calendar invitations among newly synced messages into the event
+/// store. Messages whose body is not cached are fetched, each once.
+async fn read_calendar_invites(
+    storage: &Storage,
+    credentials: &Credentials,
+    account_email: &str,
+    summaries: &[EmailSummary],
+) {
+    let candidates = summaries
+        .iter()
+        .filter(|message| calendar::is_candidate(message))
+        .map(|message| message.uid)
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return;
+    }
+    let unscanned = match storage
+        .unscanned_for_calendar(account_email, "INBOX", candidates)
+        .await
+    {
+        Ok(uids) => uids,
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to check calendar scans");
+            return;
+        }
+    };
+
+    let mut stored_any = false;
+    for uid in unscanned {
+        let events = match storage.message_body(account_email, "INBOX", uid).await {
+            Ok(Some(body)) => calendar::extract(&body, account_email),
+            Ok(None) => match providers::fetch_raw_message(credentials, uid).await {
+                Ok(Some(raw)) => calendar::extract(&raw, account_email),
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!(account = %account_email, %uid, ?err, "failed to fetch invitation");
+                    continue;
+                }
+            },
+            Err(err) => {
+                warn!(account = %account_email, %uid, ?err, "failed to load cached body");
+                continue;
+            }
+        };
+        match storage
+            .record_calendar_scan(account_email, "INBOX", uid, events)
+            .await
+        {
+            Ok(0) => {}
+            Ok(count) => {
+                debug!(account = %account_email, %uid, count, "calendar events stored");
+                stored_any = true;
+            }
+            Err(err) => warn!(account = %account_email, %uid, ?err, "failed to store events"),
+        }
+    }
+
+    if !stored_any {
+        return;
+    }
+    match load_caldav_settings(storage).await {
+        Ok(settings) if settings.enabled => {
+            if let Err(err) = export_calendar_events(storage, &settings, Some(account_email)).await
+            {
+                warn!(account = %account_email, %err, "CalDAV export failed");
+            }
+        }
+        Ok(_) => {}
+        Err(err) => warn!(%err, "failed to load CalDAV settings"),
+    }
+}
+
+async fn load_caldav_settings(storage: &Storage) -> Result<CalDavSettings, String> {
+    let raw = storage
+        .get_setting(caldav::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(CalDavSettings::default()),
+    }
+}
+
+/// Pushes events the CalDAV calendar does not have yet, or has an older
+/// version of, and logs each attempt.
+async fn export_calendar_events(
+    storage: &Storage,
+    settings: &CalDavSettings,
+    account_email: Option<&str>,
+) -> Result<CalDavExportReport, String> {
+    let username = settings
+        .username
+        .as_deref()
+        .ok_or_else(|| "Set the CalDAV username first".to_string())?;
+    let password = fetch_password_from_keychain(&caldav::keychain_key(username))?
+        .ok_or_else(|| "Save the CalDAV password first".to_string())?;
+    let events = storage
+        .calendar_events_to_push(account_email, CALDAV_PUSH_BATCH)
+        .await
+        .map_err(|err| err.to_string())?;
+    let client = caldav::client()?;
+
+    let mut report = CalDavExportReport::default();
+    for (account, event) in events {
+        let outcome = caldav::put_event(&client, settings, &password, &event).await;
+        match outcome.status {
+            PushStatus::Pushed => report.pushed += 1,
+            PushStatus::AlreadyPresent => report.already_present += 1,
+            PushStatus::Failed => {
+                warn!(%account, detail = ?outcome.detail, "failed to push calendar event");
+                report.failed += 1;
+            }
+        }
+        storage
+            .record_caldav_push(&account, &event, outcome)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    info!(
+        pushed = report.pushed,
+        already_present = report.already_present,
+        failed = report.failed,
+        "CalDAV export finished"
+    );
+    Ok(report)
+}
+
+#[tauri::command]
+async fn get_caldav_settings(state: State<'_, AppState>) -> Result<CalDavSettings, String> {
+    load_caldav_settings(&state.storage).await
+}
+
+/// Saves where events are exported to. A `password` given here goes to the
+/// OS keychain, never to app settings.
+#[tauri::command]
+async fn set_caldav_settings(
+    state: State<'_, AppState>,
+    settings: CalDavSettings,
+    password: Option<String>,
+) -> Result<CalDavSettings, String> {
+    let normalized = settings.normalized()?;
+    if let Some(password) = password.filter(|value| !value.is_empty()) {
+        let username = normalized
+            .username
+            .as_deref()
+            .ok_or_else(|| "Set the CalDAV username to save a password".to_string())?;
+        let password = SecretString::new(password);
+        store_service_secret(&state.storage, &caldav::keychain_key(username), &password).await?;
+    }
+    let json = serde_json::to_string(&normalized).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(caldav::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(normalized)
+}
+
+/// Pushes pending events now, for one account or all.
+#[tauri::command]
+async fn export_to_caldav(
+    state: State<'_, AppState>,
+    account: Option<String>,
+) -> Result<CalDavExportReport, String> {
+    let settings = load_caldav_settings(&state.storage).await?;
+    if !settings.enabled {
+        return Err("CalDAV export is turned off".into());
+    }
+    let normalized_account = account.map(|value| normalize_email(&value));
+    export_calendar_events(&state.storage, &settings, normalized_account.as_deref()).await
+}
+
+#[tauri::command]
+async fn list_caldav_sync_log(
+    state: State<'_, AppState>,
+    limit: Option<usize>,
+) -> Result<Vec<CalDavLogEntry>, String> {
+    state
+        .storage
+        .caldav_sync_log(limit.unwrap_or(200).clamp(1, 1000))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+async fn load_carddav_settings(storage: &Storage) -> Result<CardDavSettings, String> {
+    let raw = storage
+        .get_setting(carddav::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(CardDavSettings::default()),
+    }
+}
+
+/// Pulls the address book, then pushes the opted-in contacts edited here.
+/// A card edited on both sides goes to the conflict policy; under
+/// newest-wins, a server card without `REV` counts as the older one.
+async fn sync_contacts_with_carddav(
+    storage: &Storage,
+    settings: &CardDavSettings,
+) -> Result<CardDavSyncReport, String> {
+    let username = settings
+        .username
+        .as_deref()
+        .ok_or_else(|| "Set the CardDAV username first".to_string())?;
+    let password = fetch_password_from_keychain(&carddav::keychain_key(username))?
+        .ok_or_else(|| "Save the CardDAV password first".to_string())?;
+    let client = carddav::Client::new(settings, password)?;
+    let remote = client.list().await?;
+    let local = storage
+        .carddav_contacts()
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut report = CardDavSyncReport::default();
+    let by_href = local
+        .iter()
+        .filter_map(|contact| Some((contact.href.clone()?, contact)))
+        .collect::<HashMap<_, _>>();
+    let by_uid = local
+        .iter()
+        .map(|contact| (contact.card.uid.clone(), contact))
+        .collect::<HashMap<_, _>>();
+
+    let changed = remote
+        .iter()
+        .filter(|card| {
+            by_href
+                .get(&card.href)
+                .is_none_or(|contact| contact.etag != card.etag)
+        })
+        .map(|card| card.href.clone())
+        .collect::<Vec<_>>();
+    for hrefs in changed.chunks(carddav::MULTIGET_BATCH) {
+        for fetched in client.fetch(hrefs).await? {
+            let Some(card) = fetched
+                .vcard
+                .as_deref()
+                .and_then(|text| vcard::parse_cards(text).into_iter().next())
+            else {
+                continue;
+            };
+            let existing = by_href.get(&fetched.href).or_else(|| by_uid.get(&card.uid));
+            let remote_wins = match existing {
+                Some(contact) if contact.modified => {
+                    report.conflicts += 1;
+                    match settings.conflict_policy {
+                        ConflictPolicy::ServerWins => true,
+                        ConflictPolicy::LocalWins => false,
+                        ConflictPolicy::NewestWins => card
+                            .revision
+                            .is_some_and(|revision| revision > contact.updated_at),
+                    }
+                }
+                _ => true,
+            };
+            let etag = fetched.etag.as_deref();
+            let result = match (existing, remote_wins) {
+                (Some(contact), false) => {
+                    // Adopting the server's ETag lets the next push overwrite it.
+                    storage
+                        .link_contact(contact.id, &fetched.href, etag, false)
+                        .await
+                }
+                (existing, _) => {
+                    report.pulled += 1;
+                    let id = existing.map(|contact| contact.id);
+                    storage
+                        .apply_remote_contact(id, card, &fetched.href, etag)
+                        .await
+                }
+            };
+            result.map_err(|err| err.to_string())?;
+        }
+    }
+
+    let remote_hrefs = remote
+        .iter()
+        .map(|card| card.href.as_str())
+        .collect::<Vec<_>>();
+    let gone = by_href
+        .iter()
+        .filter(|(href, _)| !remote_hrefs.contains(&href.as_str()))
+        .map(|(_, contact)| contact.id)
+        .collect::<Vec<_>>();
+    report.unlinked = storage
+        .unlink_contacts(gone)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let pending = storage
+        .carddav_contacts()
+        .await
+        .map_err(|err| err.to_string())?;
+    for contact in pending
+        .into_iter()
+        .filter(|contact| contact.sync_enabled && contact.modified)
+    {
+        let (href, result) = client
+            .put(
+                contact.href.as_deref(),
+                &contact.card.uid,
+                contact.card.to_vcard(),
+                contact.etag.as_deref(),
+            )
+            .await;
+        match result {
+            PutResult::Stored(etag) => {
+                report.pushed += 1;
+                storage
+                    .link_contact(contact.id, &href, etag.as_deref(), true)
+                    .await
+                    .map_err(|err| err.to_string())?;
+            }
+            // Settled by the conflict policy on the next pull.
+            PutResult::Conflict => report.conflicts += 1,
+            PutResult::Failed(detail) => {
+                warn!(id = contact.id, %detail, "failed to push contact");
+                report.failed += 1;
+            }
+        }
+    }
+    info!(
+        pulled = report.pulled,
+        pushed = report.pushed,
+        conflicts = report.conflicts,
+        failed = report.failed,
+        unlinked = report.unlinked,
+        "CardDAV sync finished"
+    );
+    Ok(report)
+}
+
+#[tauri::command]
+async fn get_carddav_settings(state: State<'_, AppState>) -> Result<CardDavSettings, String> {
+    load_carddav_settings(&state.storage).await
+}
+
+/// Saves the address book to sync with. A `password` given here goes to the
+/// OS keychain, never to app settings.
+#[tauri::command]
+async fn set_carddav_settings(
+    state: State<'_, AppState>,
+    settings: CardDavSettings,
+    password: Option<String>,
+) -> Result<CardDavSettings, String> {
+    let normalized = settings.normalized()?;
+    if let Some(password) = password.filter(|value| !value.is_empty()) {
+        let username = normalized
+            .username
+            .as_deref()
+            .ok_or_else(|| "Set the CardDAV username to save a password".to_string())?;
+        let password = SecretString::new(password);
+        store_service_secret(&state.storage, &carddav::keychain_key(username), &password).await?;
+    }
+    let json = serde_json::to_string(&normalized).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(carddav::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(normalized)
+}
+
+#[tauri::command]
+async fn sync_carddav(state: State<'_, AppState>) -> Result<CardDavSyncReport, String> {
+    let settings = load_carddav_settings(&state.storage).await?;
+    if !settings.enabled {
+        return Err("CardDAV sync is turned off".into());
+    }
+    sync_contacts_with_carddav(&state.storage, &settings).await
+}
+
+#[tauri::command]
+async fn list_contacts(
+    state: State<'_, AppState>,
+    filter: Option<String>,
+) -> Result<Vec<Contact>, String> {
+    state
+        .storage
+        .contacts(filter.as_deref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Adds the cached senders of an account to the contacts table. Returns
+/// how many were new.
+#[tauri::command]
+async fn extract_contacts_from_mail(
+    state: State<'_, AppState>,
+    email: String,
+) -> Result<usize, String> {
+    let normalized_email = normalize_email(&email);
+    let cards = state
+        .storage
+        .contact_candidates(&normalized_email, None)
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|(sender, display)| Card::from_sender(&sender, display.as_deref()))
+        .collect();
+    state
+        .storage
+        .add_contacts(cards, "mail")
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn update_contact(state: State<'_, AppState>, id: i64, edit: CardEdit) -> Result<(), String> {
+    let edit = edit.normalized()?;
+    let found = state
+        .storage
+        .update_contact(id, edit)
+        .await
+        .map_err(|err| err.to_string())?;
+    if !found {
+        return Err("Contact not found".into());
+    }
+    Ok(())
+}
+
+/// Adds the cards in a vCard file, such as an address book export, to the
+/// contacts table. Cards whose `UID` or first address is already there are
+/// skipped, so importing the same file twice adds nothing.
+#[tauri::command]
+async fn import_vcards(
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<VCardImportReport, String> {
+    let path = expand_path(path.trim())?;
+    let text = fs::read_to_string(&path)
+        .await
+        .map_err(|err| format!("failed to read vCard file: {err}"))?;
+    let cards = vcard::parse_cards(&text)
+        .into_iter()
+        .filter(|card| !card.full_name.is_empty())
+        .collect::<Vec<_>>();
+    if cards.is_empty() {
+        return Err("The file has no contacts".into());
+    }
+    let count = cards.len();
+    let imported = state
+        .storage
+        .add_contacts(cards, "import")
+        .await
+        .map_err(|err| err.to_string())?;
+    info!(path = %path.display(), cards = count, imported, "imported vCards");
+    Ok(VCardImportReport {
+        cards: count,
+        imported,
+    })
+}
+
+/// Writes the contacts matching `filter` (all when not given) to one vCard
+/// file, keeping every property read from their original cards.
+#[tauri::command]
+async fn export_vcards(
+    state: State<'_, AppState>,
+    path: String,
+    filter: Option<String>,
+) -> Result<VCardExportReport, String> {
+    let target = expand_path(path.trim())?;
+    let contacts = state
+        .storage
+        .contacts(filter.as_deref())
+        .await
+        .map_err(|err| err.to_string())?;
+    if contacts.is_empty() {
+        return Err("No contacts to export".into());
+    }
+    let document = contacts
+        .iter()
+        .map(|contact| contact.card.to_vcard())
+        .collect::<String>();
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    fs::write(&target, document)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(VCardExportReport {
+        path: target.to_string_lossy().into_owned(),
+        contacts: contacts.len(),
+    })
+}
+
+/// Opts a contact in to or out of CardDAV sync.
+#[tauri::command]
+async fn set_contact_sync(
+    state: State<'_, AppState>,
+    id: i64,
+    enabled: bool,
+) -> Result<(), String> {
+    let found = state
+        .storage
+        .set_contact_sync(id, enabled)
+        .await
+        .map_err(|err| err.to_string())?;
+    if !found {
+        return Err("Contact not found".into());
+    }
+    Ok(())
+}
+
+async fn load_avatar_settings(storage: &Storage) -> Result<AvatarSettings, String> {
+    let raw = storage
+        .get_setting(avatars::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(AvatarSettings::default()),
+    }
+}
+
+#[tauri::command]
+async fn get_avatar_settings(state: State<'_, AppState>) -> Result<AvatarSettings, String> {
+    load_avatar_settings(&state.storage).await
+}
+
+/// Chooses which sources sender pictures may come from. All are off by
+/// default, since each lookup reaches a third party.
+#[tauri::command]
+async fn set_avatar_settings(
+    state: State<'_, AppState>,
+    settings: AvatarSettings,
+) -> Result<AvatarSettings, String> {
+    let json = serde_json::to_string(&settings).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(avatars::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(settings)
+}
+
+async fn load_dns_settings(storage: &Storage) -> Result<DnsSettings, String> {
+    let raw = storage
+        .get_setting(dns::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(DnsSettings::default()),
+    }
+}
+
+#[tauri::command]
+async fn get_dns_settings(state: State<'_, AppState>) -> Result<DnsSettings, String> {
+    load_dns_settings(&state.storage).await
+}
+
+/// Chooses which lookups may send a domain to the DNS-over-HTTPS resolver.
+/// All are off by default.
+#[tauri::command]
+async fn set_dns_settings(
+    state: State<'_, AppState>,
+    settings: DnsSettings,
+) -> Result<DnsSettings, String> {
+    let json = serde_json::to_string(&settings).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(dns::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(settings)
+}
+
+/// The picture to show for `sender`, from the sources the user enabled.
+/// `None` when they are all off or none has one.
+#[tauri::command]
+async fn get_sender_avatar(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    sender: String,
+) -> Result<Option<SenderAvatar>, String> {
+    let settings = load_avatar_settings(&state.storage).await?;
+    if !settings.any_enabled() {
+        return Ok(None);
+    }
+    let cache_dir = data_dir::resolve(&app)
+        .map_err(|err| err.to_string())?
+        .join(avatars::CACHE_DIR);
+    let resolver = AvatarResolver::new(cache_dir, settings)?;
+    Ok(resolver.resolve(&sender).await)
+}
+
+#[derive(Serialize)]
+struct SenderBrand {
+    sender: String,
+    domain: String,
+    dmarc_policy: Option<DmarcPolicy>,
+    bimi_logo: Option<String>,
+    /// The verdict on the sender's newest message that has one.
+    dmarc_aligned: Option<bool>,
+    /// The domain enforces DMARC and publishes a BIMI logo, and the
+    /// sender's mail passed DMARC for it.
+    brand_verified: bool,
+}
+
+/// Brand verification for each sender, so genuine brand mail can be told
+/// from lookalikes. Domain records are read over DNS, when the DNS settings
+/// allow it, and kept for a day; senders whose records cannot be read come
+/// back unverified.
+#[tauri::command]
+async fn get_sender_brands(
+    state: State<'_, AppState>,
+    account: String,
+    senders: Vec<String>,
+) -> Result<Vec<SenderBrand>, String> {
+    let normalized_email = normalize_email(&account);
+    let lookups = load_dns_settings(&state.storage).await?.brand_checks;
+    let client = brand::client()?;
+    let mut domains: HashMap<String, Option<DomainBrand>> = HashMap::new();
+    let mut brands = Vec::new();
+    for sender in senders {
+        let sender = sender.trim().to_lowercase();
+        let Some((_, domain)) = sender.rsplit_once('@') else {
+            continue;
+        };
+        let domain = domain.to_string();
+        if !domains.contains_key(&domain) {
+            let found = domain_brand(&state.storage, &client, &domain, lookups).await?;
+            domains.insert(domain.clone(), found);
+        }
+        let dmarc_aligned = state
+            .storage
+            .sender_dmarc_alignment(&normalized_email, &sender)
+            .await
+            .map_err(|err| err.to_string())?;
+        let found = domains.get(&domain).cloned().flatten();
+        let brand_verified =
+            dmarc_aligned == Some(true) && found.as_ref().is_some_and(DomainBrand::verifiable);
+        brands.push(SenderBrand {
+            dmarc_policy: found.as_ref().and_then(|found| found.dmarc_policy),
+            bimi_logo: found.and_then(|found| found.bimi_logo),
+            sender,
+            domain,
+            dmarc_aligned,
+            brand_verified,
+        });
+    }
+    Ok(brands)
+}
+
+/// A domain's DMARC and BIMI records, read again once the stored ones are
+/// older than [`brand::CHECK_TTL_SECS`]. Stale records stand in when DNS
+/// cannot be reached or `lookups` are not allowed; `None` when there are
+/// none to fall back on.
+async fn domain_brand(
+    storage: &Storage,
+    client: &reqwest::Client,
+    domain: &str,
+    lookups: bool,
+) -> Result<Option<DomainBrand>, String> {
+    let stored = storage
+        .brand_check(domain)
+        .await
+        .map_err(|err| err.to_string())?;
+    if !lookups {
+        return Ok(stored.map(|check| check.brand));
+    }
+    if let Some(check) = &stored {
+        if Utc::now().timestamp() - check.checked_at < brand::CHECK_TTL_SECS {
+            return Ok(Some(check.brand.clone()));
+        }
+    }
+    match brand::check_domain(client, domain).await {
+        Ok(found) => {
+            storage
+                .save_brand_check(&found)
+                .await
+                .map_err(|err| err.to_string())?;
+            Ok(Some(found))
+        }
+        Err(err) => {
+            warn!(%domain, %err, "brand check failed");
+            Ok(stored.map(|check| check.brand))
+        }
+    }
+}
+
+async fn load_focus_settings(storage: &Storage) -> Result<FocusSettings, String> {
+    let raw = storage
+        .get_setting(focus::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(FocusSettings::default()),
+    }
+}
+
+/// Decides, once per message, whether newly arrived mail gets through focus
+/// mode. During focus hours urgent mail raises `focus-alert` and the rest is
+/// held for the next review. Old mail seen by a resync is left alone.
+async fn apply_focus_mode(
+    app: &tauri::AppHandle,
+    storage: &Storage,
+    account_email: &str,
+    summaries: &[EmailSummary],
+) {
+    let settings = match load_focus_settings(storage).await {
+        Ok(settings) if settings.enabled => settings,
+        Ok(_) => return,
+        Err(err) => {
+            warn!(account = %account_email, %err, "failed to load focus mode settings");
+            return;
+        }
+    };
+
+    let now = Local::now();
+    let fresh = summaries
+        .iter()
+        .filter(|message| {
+            message
+                .date
+                .as_deref()
+                .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+                .is_some_and(|date| now.timestamp() - date.timestamp() <= FOCUS_FRESH_SECS)
+        })
+        .collect::<Vec<_>>();
+    if fresh.is_empty() {
+        return;
+    }
+
+    let uids = fresh.iter().map(|message| message.uid).collect();
+    let priorities = match storage
+        .message_priorities(account_email, "INBOX", uids)
+        .await
+    {
+        Ok(priorities) => priorities,
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to load priorities for focus mode");
+            HashMap::new()
+        }
+    };
+    let noise = match storage.sender_noise(account_email).await {
+        Ok(noise) => noise,
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to load sender noise for focus mode");
+            HashMap::new()
+        }
+    };
+    let active = settings.is_active(now);
+    let decisions = fresh
+        .iter()
+        .map(|message| {
+            let priority = priorities.get(&message.uid).map(String::as_str);
+            let noisy = noise
+                .get(&message.sender.email.trim().to_lowercase())
+                .is_some_and(NoiseScore::is_noisy);
+            let reason = settings.urgency(account_email, message, priority, noisy);
+            FocusDecision {
+                uid: message.uid,
+                reason: reason.map(|reason| reason.as_str().to_string()),
+                held: active && reason.is_none(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let urgent = decisions
+        .iter()
+        .filter(|decision| decision.reason.is_some())
+        .map(|decision| (decision.uid, decision.reason.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let recorded = match storage
+        .record_focus_decisions(account_email, "INBOX", decisions)
+        .await
+    {
+        Ok(recorded) => recorded,
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to record focus decisions");
+            return;
+        }
+    };
+    if !active {
+        return;
+    }
+
+    let decided_at = now.timestamp();
+    let messages = fresh
+        .into_iter()
+        .filter(|message| recorded.contains(&message.uid))
+        .filter_map(|message| {
+            Some(FocusMessage {
+                account_email: account_email.to_string(),
+                folder: "INBOX".to_string(),
+                uid: message.uid,
+                sender_email: message.sender.email.clone(),
+                sender_display: message.sender.display_name.clone(),
+                subject: message.subject.clone(),
+                date: message.date.clone(),
+                reason: urgent.get(&message.uid)?.clone(),
+                decided_at,
+            })
+        })
+        .collect::<Vec<_>>();
+    if messages.is_empty() {
+        return;
+    }
+    let payload = FocusAlert {
+        account_email: account_email.to_string(),
+        messages,
+    };
+    events::emit(app, &payload);
+}
+
+/// Marks newly arrived mail in muted threads read and archives it, and
+/// sends `local-update-applied` for it. The flag changes go out with the
+/// next push.
+async fn apply_thread_mutes(
+    app: &tauri::AppHandle,
+    storage: &Storage,
+    account_email: &str,
+    summaries: &[EmailSummary],
+) {
+    let uids = mute_thread_arrivals(storage, account_email, summaries).await;
+    let payload = LocalUpdate {
+        account_email: account_email.to_string(),
+        action: "mute_thread".to_string(),
+        uids,
+        target: None,
+        error: None,
+    };
+    emit_local_update(app, payload, LocalUpdateApplied);
+}
+
+/// The cache side of [`apply_thread_mutes`]. Returns the UIDs it muted.
+async fn mute_thread_arrivals(
+    storage: &Storage,
+    account_email: &str,
+    summaries: &[EmailSummary],
+) -> Vec<u32> {
+    let muted = match storage.muted_thread_ids(account_email).await {
+        Ok(muted) if !muted.is_empty() => muted,
+        Ok(_) => return Vec::new(),
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to load muted threads");
+            return Vec::new();
+        }
+    };
+    let uids = summaries
+        .iter()
+        .filter(|message| {
+            message
+                .thread_id
+                .as_ref()
+                .is_some_and(|thread| muted.contains(thread))
+        })
+        .map(|message| message.uid)
+        .collect::<Vec<_>>();
+    if uids.is_empty() {
+        return uids;
+    }
+
+    if let Err(err) = storage
+        .queue_flag_changes(account_email, "INBOX", &uids, &["\\Seen".to_string()], true)
+        .await
+    {
+        warn!(account = %account_email, ?err, "failed to mark muted thread mail read");
+        return Vec::new();
+    }
+    for uid in &uids {
+        let write = PendingWrite::Lifecycle {
+            account_email: account_email.to_string(),
+            folder: "INBOX".to_string(),
+            uid: *uid,
+            lifecycle: threads::MUTED_LIFECYCLE.to_string(),
+        };
+        if let Err(err) = storage.queue_write(write).await {
+            warn!(account = %account_email, %uid, ?err, "failed to archive muted thread mail");
+        }
+    }
+    uids
+}
+
+/// Surfaces one-time codes and password-reset links in newly arrived mail.
+/// Each find is stored until it expires and announced with `otp-received`.
+/// With a model loaded, finds it does not confirm are dropped; if it cannot
+/// answer, the pattern match stands.
+async fn surface_one_time_codes(
+    app: &tauri::AppHandle,
+    storage: &Storage,
+    account_email: &str,
+    summaries: &[EmailSummary],
+) {
+    let settings = match load_otp_settings(storage).await {
+        Ok(settings) if settings.enabled => settings,
+        Ok(_) => return,
+        Err(err) => {
+            warn!(account = %account_email, %err, "failed to load one-time code settings");
+            return;
+        }
+    };
+
+    let now = Utc::now().timestamp();
+    let fresh_uids = summaries
+        .iter()
+        .filter(|message| {
+            message
+                .date
+                .as_deref()
+                .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+                .is_some_and(|date| now - date.timestamp() <= otp::FRESH_SECS)
+        })
+        .map(|message| message.uid)
+        .collect::<Vec<_>>();
+    if fresh_uids.is_empty() {
+        return;
+    }
+    let unscanned = match storage
+        .unscanned_for_one_time_codes(account_email, "INBOX", fresh_uids)
+        .await
+    {
+        Ok(unscanned) if unscanned.is_empty() => return,
+        Ok(unscanned) => unscanned,
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to check scanned messages");
+            return;
+        }
+    };
+
+    // Incremental syncs cache headers only, so most new mail has to be
+    // fetched to be read.
+    let state = app.state::<AppState>();
+    let mut texts = HashMap::new();
+    let mut missing = Vec::new();
+    for &uid in &unscanned {
+        match storage.message_body(account_email, "INBOX", uid).await {
+            Ok(Some(body)) => {
+                texts.insert(uid, body_text::decode_body_text(&body));
+            }
+            Ok(None) => missing.push(uid),
+            Err(err) => warn!(account = %account_email, %uid, ?err, "failed to load cached body"),
+        }
+    }
+    if !missing.is_empty() {
+        for (uid, raw) in fetch_raw_messages(&state, account_email, "INBOX", &missing).await {
+            if let Some(message) = body_text::decode_full_message(&raw) {
+                texts.insert(uid, body_text::parts_text(&message.parts));
+            }
+        }
+    }
+
+    let confirm = settings.confirm_with_llm
+        && state.llm.status().loaded
+        && state.llm.permits(OTP_CONFIRM_CLASSES);
+    let expires_at = now + settings.expire_after_secs as i64;
+    for message in summaries
+        .iter()
+        .filter(|message| unscanned.contains(&message.uid))
+    {
+        let uid = message.uid;
+        let text = texts.remove(&uid).unwrap_or_default();
+        let mut detection = otp::detect(&message.subject, &text);
+        if let (true, Some(found)) = (confirm, &detection) {
+            let prompt = otp::confirmation_prompt(&message.subject, &text, found);
+            // Background work, but someone is usually waiting on the code,
+            // so it goes ahead of bulk runs.
+            match state
+                .llm
+                .analyze_prompt_with_priority(
+                    prompt,
+                    Some(OTP_CONFIRM_MAX_TOKENS),
+                    RequestPriority::Validation,
+                    OTP_CONFIRM_CLASSES,
+                )
+                .await
+            {
+                Ok(reply) if !otp::confirmed(&reply) => {
+                    debug!(account = %account_email, %uid, "model did not confirm one-time code");
+                    detection = None;
+                }
+                Ok(_) => {}
+                Err(err) => debug!(account = %account_email, %uid, %err, "could not confirm code"),
+            }
+        }
+        match storage
+            .record_one_time_code(account_email, "INBOX", uid, detection.as_ref(), expires_at)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) => {
+                warn!(account = %account_email, %uid, ?err, "failed to record one-time code");
+                continue;
+            }
+        }
+        let Some(detection) = detection else {
+            continue;
+        };
+        info!(
+            account = %account_email,
+            %uid,
+            kind = detection.kind.as_str(),
+            "surfaced one-time code"
+        );
+        events::emit(
+            app,
+            &OtpReceived {
+                account_email: account_email.to_string(),
+                uid,
+                kind: detection.kind,
+                code: detection.code,
+                link: detection.link,
+                sender_email: message.sender.email.clone(),
+                subject: message.subject.clone(),
+                expires_at,
+            },
+        );
+    }
+}
+
+/// Erases surfaced one-time codes as they expire.
+async fn expire_one_time_codes_periodically(storage: Storage) {
+    let mut ticker = time::interval(Duration::from_secs(OTP_EXPIRY_CHECK_INTERVAL_SECS));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        match storage.expire_one_time_codes(Utc::now().timestamp()).await {
+            Ok(0) => {}
+            Ok(count) => debug!(count, "erased expired one-time codes"),
+            Err(err) => warn!(?err, "failed to expire one-time codes"),
+        }
+    }
+}
+
+async fn load_otp_settings(storage: &Storage) -> Result<OtpSettings, String> {
+    let raw = storage
+        .get_setting(otp::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(OtpSettings::default()),
+    }
+}
+
+#[tauri::command]
+async fn get_otp_settings(state: State<'_, AppState>) -> Result<OtpSettings, String> {
+    load_otp_settings(&state.storage).await
+}
+
+/// Sets whether one-time codes are surfaced, whether the model confirms
+/// them, and how long they are kept.
+#[tauri::command]
+async fn set_otp_settings(
+    state: State<'_, AppState>,
+    settings: OtpSettings,
+) -> Result<OtpSettings, String> {
+    let normalized = settings.normalized()?;
+    let json = serde_json::to_string(&normalized).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(otp::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(normalized)
+}
+
+/// One-time codes and reset links that have not expired, newest first, for
+/// one account or all.
+#[tauri::command]
+async fn list_one_time_codes(
+    state: State<'_, AppState>,
+    account: Option<String>,
+) -> Result<Vec<OneTimeCode>, String> {
+    let normalized_account = account.map(|value| normalize_email(&value));
+    state
+        .storage
+        .one_time_codes(normalized_account.as_deref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+async fn process_autoreplies(storage: &Storage, account_email: &str, summaries: &[EmailSummary]) {
+    let settings = match load_autoreply_settings(storage, account_email).await {
+        Ok(settings) if settings.enabled => settings,
+        Ok(_) => return,
+        Err(err) => {
+            warn!(account = %account_email, %err, "failed to load auto-reply settings");
+            return;
+        }
+    };
+
+    let now = Utc::now().timestamp();
+    let candidates = summaries
+        .iter()
+        .filter(|message| match autoreply::evaluate(&settings, account_email, message, now) {
+            Ok(()) => true,
+            Err(reason) => {
+                debug!(account = %account_email, uid = %message.uid, ?reason, "auto-reply skipped");
+                false
+            }
+        })
+        .collect::<Vec<_>>();
+    if candidates.is_empty() {
+        return;
+    }
+
+    let template = match settings.template_id {
+        Some(id) => storage.template(id).await,
+        None => return,
+    };
+    let template = match template {
+        Ok(Some(template)) => template,
+        Ok(None) => {
+            warn!(account = %account_email, "auto-reply template no longer exists");
+            return;
+        }
+        Err(err) => {
+            warn!(account = %account_email, ?err, "failed to load auto-reply template");
+            return;
+        }
+    };
+
+    let mut queued = Vec::new();
+    for message in candidates {
+        let sender = message.sender.email.trim().to_lowercase();
+        let mut fields =
+            mail_merge::contact_fields(&sender, message.sender.display_name.as_deref());
+        fields.insert("subject".into(), message.subject.clone());
+
+        let rendered =
+            mail_merge::render_placeholders(&template.subject, &fields).and_then(|subject| {
+                mail_merge::render_placeholders(&template.body, &fields).map(|body| (subject, body))
+            });
+        let (subject, body) = match rendered {
+            Ok(rendered) => rendered,
+            Err(err) => {
+                warn!(account = %account_email, %err, "failed to render auto-reply");
+                continue;
+            }
+        };
+
+        match storage
+            .claim_autoreply(account_email, &sender, settings.activated_at, message.uid)
+            .await
+        {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(err) => {
+                warn!(account = %account_email, ?err, "failed to record auto-reply");
+                continue;
+            }
+        }
+
+        let subject = if subject.trim().is_empty() {
+            format!("Re: {}", message.subject)
+        } else {
+            subject
+        };
+        // RFC 3834: mark the reply automatic so other responders leave it
+        // alone, and thread it under the message it answers.
+        let mut references = message.references.clone();
+        references.extend(message.message_id.clone());
+        queued.push(OutboxInsert {
+            account_email: account_email.to_string(),
+            merge_id: None,
+            recipient: sender,
+            subject,
+            body,
+            attachments: Vec::new(),
+            uploads: Vec::new(),
+            scheduled_at: now,
+            in_reply_to: message.message_id.clone(),
+            references,
+            auto_submitted: Some("auto-replied".into()),
+        });
+    }
+
+    match storage.enqueue_outbox(queued).await {
+        Ok(0) => {}
+        Ok(count) => info!(account = %account_email, count, "queued auto-replies"),
+        Err(err) => warn!(account = %account_email, ?err, "failed to queue auto-replies"),
+    }
+}
+
+fn build_records(
+    account_email: &str,
+    summary: &EmailSummary,
+    snippet: Option<String>,
+    body: Option<Vec<u8>>,
+    flags: Option<&[String]>,
+) -> (MessageInsert, AnalysisInsert) {
+    let flags_string = flags.map(|values| values.join(" "));
+
+    let display_name = summary
+        .sender
+        .display_name
+        .clone()
+        .unwrap_or_else(|| summary.sender.email.clone());
+
+    let snippet_clone = snippet.clone();
+    let (analysis_summary, analysis_sentiment, categories) =
+        analyze_message(&summary.subject, snippet_clone.as_deref());
+
+    let insert = MessageInsert {
+        account_email: account_email.to_string(),
+        folder: "INBOX".to_string(),
+        uid: summary.uid,
+        sender_display: display_name,
+        sender_email: summary.sender.email.clone(),
+        subject: summary.subject.clone(),
+        date: summary.date.clone(),
+        snippet,
+        body,
+        flags: flags_string,
+        size: summary.size,
+        dmarc_aligned: summary.dmarc_aligned,
+        thread_id: summary.thread_id.clone(),
+    };
+
+    let analysis = AnalysisInsert {
+        message_key: None,
+        account_email: account_email.to_string(),
+        folder: "INBOX".to_string(),
+        uid: summary.uid,
+        summary: analysis_summary,
+        sentiment: analysis_sentiment,
+        categories,
+        metadata_json: Value::Null,
+        model_id: None,
+        analyzed: false,
+        analyzed_at: None,
+        analysis_confidence: None,
+        validation: AnalysisValidation::default(),
+        fingerprint: None,
+    };
+
+    (insert, analysis)
+}
+
+fn analyze_message(
+    subject: &str,
+    snippet: Option<&str>,
+) -> (Option<String>, Option<String>, Vec<String>) {
+    const POSITIVE_WORDS: &[&str] = &[
+        "thanks",
+        "thank you",
+        "appreciate",
+        "great",
+        "success",
+        "approved",
+    ];
+    const NEGATIVE_WORDS: &[&str] = &["issue", "urgent", "problem", "failed", "declined", "error"];
+
+    let snippet_text = snippet.unwrap_or("");
+    let combined = format!("{} {}", subject, snippet_text).to_lowercase();
+
+    let base_text = if snippet_text.trim().is_empty() {
+        subject.trim()
+    } else {
+        snippet_text.trim()
+    };
+
+    let summary = if base_text.is_empty() {
+        None
+    } else {
+        let mut truncated = base_text.chars().take(200).collect::<String>();
+        if base_text.len() > truncated.len() {
+            truncated.push('…');
+        }
+        Some(truncated)
+    };
+
+    let mut score = 0i32;
+    for word in POSITIVE_WORDS {
+        if combined.contains(word) {
+            score += 1;
+        }
+    }
+    for word in NEGATIVE_WORDS {
+        if combined.contains(word) {
+            score -= 1;
+        }
+    }
+
+    let sentiment = if score > 1 {
+        Some("positive".to_string())
+    } else if score < -1 {
+        Some("negative".to_string())
+    } else if combined.trim().is_empty() {
+        None
+    } else {
+        Some("neutral".to_string())
+    };
+
+    let mut categories = Vec::new();
+    if combined.contains("invoice") || combined.contains("receipt") || combined.contains("payment")
+    {
+        categories.push("billing".to_string());
+    }
+    if combined.contains("alert") || combined.contains("warning") || combined.contains("security") {
+        categories.push("alert".to_string());
+    }
+    if combined.contains("newsletter")
+        || combined.contains("subscribe")
+        || combined.contains("update")
+    {
+        categories.push("newsletter".to_string());
+    }
+    if combined.contains("meeting")
+        || combined.contains("schedule")
+        || combined.contains("calendar")
+    {
+        categories.push("calendar".to_string());
+    }
+    categories.sort();
+    categories.dedup();
+
+    (summary, sentiment, categories)
+}
+
+#[tauri::command]
+async fn get_llm_status(state: State<'_, AppState>) -> Result<LlmStatus, String> {
+    Ok(state.llm.status())
+}
+
+#[tauri::command]
+async fn list_known_llm_models(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<KnownModelResponse>, String> {
+    let models_dir = models_directory(&app)?;
+    fs::create_dir_all(&models_dir)
+        .await
+        .map_err(|err| format!("failed to prepare models directory: {err}"))?;
+
+    let active_path = state.llm.configured_path();
+    let mut benchmarks = state
+        .storage
+        .llm_benchmarks()
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|benchmark| (benchmark.model_id.clone(), benchmark))
+        .collect::<HashMap<_, _>>();
+    let mut responses = Vec::new();
+
+    for model in KNOWN_MODELS {
+        let candidate = models_dir.join(model.filename);
+        let metadata = fs::metadata(&candidate).await.ok();
+        let downloaded = metadata.is_some();
+        let installed_size_bytes = metadata.as_ref().map(|entry| entry.len());
+        let active = active_path
+            .as_ref()
+            .map(|path| path == &candidate)
+            .unwrap_or(false);
+
+        responses.push(KnownModelResponse {
+            id: model.id,
+            name: model.display_name,
+            description: model.description,
+            filename: model.filename,
+            url: model.download_url,
+            size_bytes: model.estimated_size_bytes,
+            recommended_ram_gb: model.recommended_ram_gb,
+            context_length: model.context_length,
+            notes: model.notes,
+            is_default: model.is_default,
+            downloaded,
+            active,
+            installed_size_bytes,
+            benchmark: benchmarks.remove(model.id),
+        });
+    }
+
+    Ok(responses)
+}
+
+/// Runs the fixed benchmark prompts against a downloaded model and stores the
+/// measured speed and JSON validity for `list_known_llm_models`.
+#[tauri::command]
+async fn benchmark_llm_model(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    model_id: String,
+) -> Result<LlmBenchmark, String> {
+    let model =
+        known_model_by_id(model_id.trim()).ok_or_else(|| format!("Unknown model '{model_id}'"))?;
+    let path = models_directory(&app)?.join(model.filename);
+    if fs::metadata(&path).await.is_err() {
+        return Err(format!("{} is not downloaded", model.display_name));
+    }
+
+    // A throwaway instance, even for the active model, so the user's model
+    // stays untouched and the memory it adds can be measured on its own.
+    let rss_before = process_rss_bytes();
+    let service = LlmService::new();
+    let loader = service.clone();
+    tokio::task::spawn_blocking(move || loader.set_model_path(Some(path)))
+        .await
+        .map_err(|err| err.to_string())??;
+
+    let allowed_tags = DEFAULT_BULK_TAGS
+        .iter()
+        .map(|tag| tag.to_string())
+        .collect::<Vec<_>>();
+    let mut output_tokens = 0usize;
+    let mut decode_secs = 0f64;
+    let mut first_token_ms = 0f64;
+    let mut valid = 0usize;
+
+    for (index, (sender, subject, snippet)) in BENCHMARK_SAMPLES.iter().enumerate() {
+        let message = MessageForAnalysis {
+            message_id: index as i64,
+            message_key: String::new(),
+            account_email: "benchmark@localhost".to_string(),
+            folder: "INBOX".to_string(),
+            uid: index as u32,
+            subject: subject.to_string(),
+            snippet: Some(snippet.to_string()),
+            date: None,
+            sender_email: sender.to_string(),
+            sender_display: None,
+            existing_analysis: ExistingAnalysisRecord::default(),
+        };
+        let prompt = build_bulk_prompt(&allowed_tags, &message, snippet, &[]);
+        let profile = service
+            .profile_prompt(prompt, DEFAULT_BULK_COMPLETION_TOKENS)
+            .await?;
+
+        output_tokens += profile.output_tokens;
+        decode_secs += profile
+            .total
+            .saturating_sub(profile.time_to_first_token)
+            .as_secs_f64();
+        first_token_ms += profile.time_to_first_token.as_secs_f64() * 1000.0;
+        if parse_bulk_json(&profile.output)
+            .and_then(|value| normalize_bulk_output(value, &allowed_tags))
+            .is_ok()
+        {
+            valid += 1;
+        }
+    }
+
+    let rss_after = process_rss_bytes();
+    drop(service);
+
+    let samples = BENCHMARK_SAMPLES.len();
+    let benchmark = LlmBenchmark {
+        model_id: model.id.to_string(),
+        samples,
+        tokens_per_second: if decode_secs > 0.0 {
+            output_tokens as f64 / decode_secs
+        } else {
+            0.0
+        },
+        time_to_first_token_ms: first_token_ms / samples as f64,
+        json_valid_rate: valid as f64 / samples as f64,
+        process_rss_bytes: rss_after,
+        model_rss_bytes: rss_before
+            .zip(rss_after)
+            .map(|(before, after)| after.saturating_sub(before)),
+        completed_at: Utc::now().timestamp(),
+    };
+
+    state
+        .storage
+        .save_llm_benchmark(&benchmark)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(benchmark)
+}
+
+/// Resident memory of this process, including any loaded model weights.
+fn process_rss_bytes() -> Option<u64> {
+    if cfg!(windows) {
+        return None;
+    }
+    let output = Command::new("ps")
+        .args(["-o", "rss=", "-p", &std::process::id().to_string()])
+        .output()
+        .ok()?;
+    let kilobytes = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+async fn load_llm_privacy_policy(storage: &Storage) -> Result<LlmPrivacyPolicy, String> {
+    let raw = storage
+        .get_setting(llm_policy::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(LlmPrivacyPolicy::default()),
+    }
+}
+
+async fn load_mock_rules(storage: &Storage) -> Result<Vec<MockRule>, String> {
+    let raw = storage
+        .get_setting(llm_mock::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// The user's canned replies for the mock backend, without the built-in ones.
+#[tauri::command]
+async fn get_llm_mock_responses(state: State<'_, AppState>) -> Result<Vec<MockRule>, String> {
+    load_mock_rules(&state.storage).await
+}
+
+/// Picks what answers prompts. The mock backend replies from canned rules
+/// instead of a model, for a dry-run analysis; `mock_responses`, when
+/// given, replaces the user's rules.
+#[tauri::command]
+async fn set_llm_backend(
+    state: State<'_, AppState>,
+    backend: LlmBackend,
+    mock_responses: Option<Vec<MockRule>>,
+) -> Result<LlmStatus, String> {
+    if let Some(rules) = mock_responses {
+        let responses = MockResponses::new(&rules)?;
+        let json = serde_json::to_string(&rules).map_err(|err| err.to_string())?;
+        state
+            .storage
+            .set_setting(
+                llm_mock::SETTING_KEY,
+                (!rules.is_empty()).then_some(json.as_str()),
+            )
+            .await
+            .map_err(|err| err.to_string())?;
+        state.llm.set_mock_responses(responses);
+    }
+    state
+        .storage
+        .set_setting(LLM_BACKEND_SETTING_KEY, Some(backend.as_str()))
+        .await
+        .map_err(|err| err.to_string())?;
+    state.llm.set_backend(backend);
+    info!(backend = backend.as_str(), "LLM backend changed");
+    Ok(state.llm.status())
+}
+
+/// The reach of every data class, defaults filled in.
+#[tauri::command]
+async fn get_llm_privacy_policy(state: State<'_, AppState>) -> Result<LlmPrivacyPolicy, String> {
+    Ok(state.llm.privacy_policy().resolved())
+}
+
+/// Sets which data classes may reach which backend. Applies to the next
+/// prompt; requests already running are not affected.
+#[tauri::command]
+async fn set_llm_privacy_policy(
+    state: State<'_, AppState>,
+    policy: LlmPrivacyPolicy,
+) -> Result<LlmPrivacyPolicy, String> {
+    let json = serde_json::to_string(&policy).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(llm_policy::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    state.llm.set_privacy_policy(policy);
+    Ok(state.llm.privacy_policy().resolved())
+}
+
+/// Turns prompt redaction on or off; see [`LlmService::set_redact_prompts`].
+#[tauri::command]
+async fn set_llm_prompt_redaction(
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<LlmStatus, String> {
+    state
+        .storage
+        .set_setting(LLM_REDACT_PROMPTS_SETTING_KEY, enabled.then_some("true"))
+        .await
+        .map_err(|err| err.to_string())?;
+    state.llm.set_redact_prompts(enabled);
+    Ok(state.llm.status())
+}
+
+#[tauri::command]
+async fn set_llm_model_path(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: Option<String>,
+) -> Result<LlmStatus, String> {
+    let models_dir = models_directory(&app)?;
+    fs::create_dir_all(&models_dir)
+        .await
+        .map_err(|err| format!("failed to prepare models directory: {err}"))?;
+
+    match path {
+        Some(path_str) => {
+            let expanded = expand_path(&path_str)?;
+            let candidate = if expanded.is_absolute() {
+                expanded.clone()
+            } else {
+                models_dir.join(&expanded)
+            };
+
+            let metadata = fs::metadata(&candidate)
+                .await
+                .map_err(|_| format!("model file not found at {}", candidate.display()))?;
+            if !metadata.is_file() {
+                return Err(format!("model path is not a file: {}", candidate.display()));
+            }
+
+            state
+                .llm
+                .set_model_path(Some(candidate.clone()))
+                .map_err(|err| err)?;
+
+            let stored_value = if candidate.starts_with(&models_dir) {
+                candidate
+                    .strip_prefix(&models_dir)
+                    .ok()
+                    .map(|p| {
+                        p.to_string_lossy()
+                            .trim_start_matches(std::path::MAIN_SEPARATOR)
+                            .to_string()
+                    })
+                    .filter(|s| !s.is_empty())
+                    .unwrap_or_else(|| candidate.to_string_lossy().to_string())
+            } else if expanded.is_relative() {
+                expanded.to_string_lossy().to_string()
+            } else {
+                candidate.to_string_lossy().to_string()
+            };
+
+            state
+                .storage
+                .set_setting(LLM_MODEL_SETTING_KEY, Some(&stored_value))
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+        None => {
+            state.llm.set_model_path(None).map_err(|err| err)?;
+            state
+                .storage
+                .set_setting(LLM_MODEL_SETTING_KEY, None)
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+    }
+
+    let status = state.llm.status();
+    if let Some(active_model_id) = infer_model_id_from_status(&status) {
+        let filter = StaleAnalysisFilter {
+            exclude_model_id: Some(active_model_id.clone()),
+            ..Default::default()
+        };
+        match state.storage.mark_analyses_stale(filter).await {
+            Ok(0) => {}
+            Ok(marked) => {
+                info!(%active_model_id, marked, "marked analyses from previous models as stale")
+            }
+            Err(err) => warn!(%active_model_id, ?err, "failed to mark analyses stale after model change"),
+        }
+    }
+
+    Ok(status)
+}
+
+/// Runs the download disk checks without downloading, so the UI can warn
+/// before the user starts.
+#[tauri::command]
+async fn check_llm_model_download(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    model_id: String,
+) -> Result<DownloadSpaceCheck, String> {
+    let model =
+        known_model_by_id(&model_id).ok_or_else(|| format!("unknown model id: {model_id}"))?;
+    let quota_bytes = models_quota_bytes(&state.storage).await?;
+    check_model_download_space(
+        &models_directory(&app)?,
+        model,
+        model.estimated_size_bytes,
+        quota_bytes,
+    )
+    .await
+}
+
+#[tauri::command]
+async fn get_model_download_settings(
+    state: State<'_, AppState>,
+) -> Result<DownloadSettings, String> {
+    model_download_settings(&state.storage).await
+}
+
+/// Sets the connection count and bandwidth cap used by later downloads.
+#[tauri::command]
+async fn set_model_download_settings(
+    state: State<'_, AppState>,
+    settings: DownloadSettings,
+) -> Result<DownloadSettings, String> {
+    let settings = DownloadSettings {
+        connections: settings.connections(),
+        bandwidth_limit: settings.bandwidth_limit.filter(|limit| *limit > 0),
+    };
+    let json = serde_json::to_string(&settings).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(MODEL_DOWNLOAD_SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(settings)
+}
+
+#[tauri::command]
+async fn get_models_disk_quota(state: State<'_, AppState>) -> Result<Option<u64>, String> {
+    models_quota_bytes(&state.storage).await
+}
+
+/// Caps the total size of the models folder; `None` removes the cap.
+#[tauri::command]
+async fn set_models_disk_quota(
+    state: State<'_, AppState>,
+    quota_bytes: Option<u64>,
+) -> Result<Option<u64>, String> {
+    let quota_bytes = quota_bytes.filter(|quota| *quota > 0);
+    state
+        .storage
+        .set_setting(
+            MODELS_QUOTA_SETTING_KEY,
+            quota_bytes.map(|quota| quota.to_string()).as_deref(),
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(quota_bytes)
+}
+
+#[tauri::command]
+async fn download_default_llm_model(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+) -> Result<LlmStatus, String> {
+    let model = known_model_by_id(DEFAULT_LLM_MODEL_ID)
+        .ok_or_else(|| "default model metadata not available".to_string())?;
+    ensure_model_downloaded(&app, model, false).await?;
+    set_llm_model_path(app, state, Some(model.filename.to_string())).await
+}
+
+#[tauri::command]
+async fn download_llm_model(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    model_id: String,
+    activate: Option<bool>,
+    force: Option<bool>,
+) -> Result<LlmStatus, String> {
+    let model =
+        known_model_by_id(&model_id).ok_or_else(|| format!("unknown model id: {model_id}"))?;
+    ensure_model_downloaded(&app, model, force.unwrap_or(false)).await?;
+
+    if activate.unwrap_or(false) {
+        set_llm_model_path(app, state, Some(model.filename.to_string())).await
+    } else {
+        Ok(state.llm.status())
+    }
+}
+
+#[tauri::command]
+async fn analyze_with_llm(
+    state: State<'_, AppState>,
+    prompt: String,
+    max_tokens: Option<usize>,
+) -> Result<String, String> {
+    state
+        .llm
+        .analyze_prompt(prompt, max_tokens, &[DataClass::UserPrompt])
+        .await
+}
+
+#[tauri::command]
+async fn start_bulk_analysis(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    allowed_tags: Vec<String>,
+    max_tokens: Option<usize>,
+    snippet_limit: Option<usize>,
+    full_body: Option<bool>,
+    force: Option<bool>,
+    stale_only: Option<bool>,
+    fast_path: Option<bool>,
+    model_id: Option<String>,
+    validator_model_id: Option<String>,
+) -> Result<String, String> {
+    let run_id = Uuid::new_v4().to_string();
+    let run_id_clone = run_id.clone();
+    let max_tokens = max_tokens.unwrap_or(512);
+    let snippet_limit = snippet_limit.unwrap_or(2048);
+    let target = if force.unwrap_or(false) {
+        BulkTarget::All
+    } else if stale_only.unwrap_or(false) {
+        BulkTarget::Stale
+    } else {
+        BulkTarget::Unanalyzed
+    };
+
+    let storage = state.storage.clone();
+    let llm = state.llm.clone();
+    let accounts = state.accounts.read().await.clone();
+    let report: ProgressSink = Arc::new(move |progress| events::emit(&app, progress));
+
+    tauri::async_runtime::spawn(async move {
+        if let Err(err) = execute_bulk_analysis(
+            report,
+            accounts,
+            storage,
+            llm,
+            run_id_clone,
+            allowed_tags,
+            max_tokens,
+            snippet_limit,
+            full_body.unwrap_or(false),
+            target,
+            fast_path.unwrap_or(true),
+            None,
+            model_id,
+            validator_model_id,
+        )
+        .await
+        {
+            error!(?err, "bulk analysis failed");
+        }
+    });
+
+    Ok(run_id)
+}
+
+#[tauri::command]
+async fn analysis_coverage(
+    state: State<'_, AppState>,
+    email: String,
+) -> Result<AnalysisCoverage, String> {
+    let normalized_email = normalize_email(&email);
+    let active_model_id = infer_model_id_from_status(&state.llm.status());
+    state
+        .storage
+        .analysis_coverage(&normalized_email, active_model_id.as_deref())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn mark_analyses_stale(
+    state: State<'_, AppState>,
+    email: Option<String>,
+    sender_email: Option<String>,
+    model_id: Option<String>,
+    allowed_tags: Option<Vec<String>>,
+    only_mismatched: Option<bool>,
+) -> Result<usize, String> {
+    let account_email = email
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty());
+    let sender_email = sender_email
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty());
+
+    let exclude_fingerprint = if only_mismatched.unwrap_or(false) {
+        let active_model_id = infer_model_id_from_status(&state.llm.status());
+        let tags = allowed_tags
+            .filter(|tags| !tags.is_empty())
+            .unwrap_or_else(|| DEFAULT_BULK_TAGS.iter().map(|tag| tag.to_string()).collect());
+        Some(analysis_fingerprint(active_model_id.as_deref(), &tags))
+    } else {
+        None
+    };
+
+    let filter = StaleAnalysisFilter {
+        account_email,
+        sender_email,
+        model_id,
+        exclude_model_id: None,
+        exclude_fingerprint,
+    };
+
+    let marked = state
+        .storage
+        .mark_analyses_stale(filter)
+        .await
+        .map_err(|err| err.to_string())?;
+    info!(marked, "analyses marked stale");
+    Ok(marked)
+}
+
+#[tauri::command]
+async fn list_review_queue(
+    state: State<'_, AppState>,
+    email: String,
+    limit: Option<usize>,
+    confidence_threshold: Option<f64>,
+) -> Result<Vec<ReviewQueueItem>, String> {
+    let normalized_email = normalize_email(&email);
+    let threshold = confidence_threshold
+        .unwrap_or(REVIEW_CONFIDENCE_THRESHOLD)
+        .clamp(0.0, 1.0);
+
+    state
+        .storage
+        .refresh_review_queue(&normalized_email, threshold)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    state
+        .storage
+        .list_review_queue(&normalized_email, limit.unwrap_or(200))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Re-clusters an account's cached mail into topics and returns how many were found.
+/// Topics come from shared terms rather than model embeddings; see [`topics`].
+#[tauri::command]
+async fn cluster_topics(state: State<'_, AppState>, email: String) -> Result<usize, String> {
+    let normalized_email = normalize_email(&email);
+    let messages = state
+        .storage
+        .messages_for_analysis(&normalized_email)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let documents = messages
+        .into_iter()
+        .map(|message| TopicDocument {
+            message_id: message.message_id,
+            sender_email: message.sender_email,
+            text: format!("{} {}", message.subject, message.snippet.unwrap_or_default()),
+        })
+        .collect::<Vec<_>>();
+
+    let clusters =
+        tokio::task::spawn_blocking(move || topics::cluster(&documents, MAX_TOPICS_PER_ACCOUNT))
+            .await
+            .map_err(|err| err.to_string())?;
+
+    state
+        .storage
+        .replace_topics(&normalized_email, clusters)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn list_topics(
+    state: State<'_, AppState>,
+    email: String,
+) -> Result<Vec<TopicSummary>, String> {
+    let normalized_email = normalize_email(&email);
+    state
+        .storage
+        .list_topics(&normalized_email)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn list_topic_messages(
+    state: State<'_, AppState>,
+    topic_id: i64,
+    limit: Option<usize>,
+) -> Result<Vec<TopicMessage>, String> {
+    state
+        .storage
+        .topic_messages(topic_id, limit.unwrap_or(200))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[derive(Serialize)]
+struct MailMergeResponse {
+    merge_id: i64,
+    queued: usize,
+    errors: Vec<String>,
+}
+
+/// Renders a template for every recipient (CSV rows, or cached contacts matching
+/// `contact_filter`) and queues the results in the outbox, spaced out to
+/// `rate_per_minute`.
+#[tauri::command]
+async fn create_mail_merge(
+    state: State<'_, AppState>,
+    email: String,
+    subject_template: String,
+    body_template: String,
+    recipients_csv: Option<String>,
+    contact_filter: Option<String>,
+    rate_per_minute: Option<u32>,
+) -> Result<MailMergeResponse, String> {
+    let normalized_email = normalize_email(&email);
+    let rate_per_minute = rate_per_minute
+        .unwrap_or(DEFAULT_MERGE_RATE_PER_MINUTE)
+        .clamp(1, 600);
+
+    let recipients = match recipients_csv.as_deref() {
+        Some(content) => mail_merge::parse_recipients_csv(content)?,
+        None => state
+            .storage
+            .contact_candidates(&normalized_email, contact_filter.as_deref())
+            .await
+            .map_err(|err| err.to_string())?
+            .into_iter()
+            .map(|(contact, display)| mail_merge::contact_fields(&contact, display.as_deref()))
+            .collect(),
+    };
+    if recipients.is_empty() {
+        return Err("No recipients to merge".into());
+    }
+
+    let (rendered, errors) =
+        mail_merge::render_for_recipients(&subject_template, &body_template, &recipients);
+    if rendered.is_empty() {
+        return Err(errors
+            .first()
+            .cloned()
+            .unwrap_or_else(|| "No messages could be rendered".into()));
+    }
+
+    let merge_id = state
+        .storage
+        .create_mail_merge(
+            &normalized_email,
+            &subject_template,
+            &body_template,
+            rate_per_minute,
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let start = Utc::now().timestamp();
+    let spacing = 60.0 / rate_per_minute as f64;
+    let rows = rendered
+        .into_iter()
+        .enumerate()
+        .map(|(index, message)| OutboxInsert {
+            account_email: normalized_email.clone(),
+            merge_id: Some(merge_id),
+            recipient: message.recipient,
+            subject: message.subject,
+            body: message.body,
+            attachments: Vec::new(),
+            uploads: Vec::new(),
+            scheduled_at: start + (index as f64 * spacing) as i64,
+            in_reply_to: None,
+            references: Vec::new(),
+            auto_submitted: None,
+        })
+        .collect::<Vec<_>>();
+
+    let queued = state
+        .storage
+        .enqueue_outbox(rows)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(MailMergeResponse {
+        merge_id,
+        queued,
+        errors,
+    })
+}
+
+#[tauri::command]
+async fn get_mail_merge_status(
+    state: State<'_, AppState>,
+    merge_id: i64,
+) -> Result<MailMergeStatus, String> {
+    state
+        .storage
+        .mail_merge_status(merge_id)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Mail merge not found".to_string())
+}
+
+#[tauri::command]
+async fn cancel_mail_merge(state: State<'_, AppState>, merge_id: i64) -> Result<usize, String> {
+    state
+        .storage
+        .cancel_mail_merge(merge_id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[derive(Serialize)]
+struct RenderedTemplate {
+    subject: String,
+    body: String,
+}
+
+#[derive(Serialize)]
+struct TemplateSuggestion {
+    template_id: i64,
+    name: String,
+    reason: Option<String>,
+}
+
+#[tauri::command]
+async fn list_templates(state: State<'_, AppState>) -> Result<Vec<EmailTemplate>, String> {
+    state
+        .storage
+        .list_templates()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Creates a template, or replaces an existing one when `id` is given.
+#[tauri::command]
+async fn save_template(
+    state: State<'_, AppState>,
+    id: Option<i64>,
+    name: String,
+    subject: String,
+    body: String,
+) -> Result<EmailTemplate, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Template name is required".into());
+    }
+
+    let saved_id = state
+        .storage
+        .save_template(id, name, &subject, &body)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Template not found".to_string())?;
+
+    state
+        .storage
+        .template(saved_id)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Template not found".to_string())
+}
+
+#[tauri::command]
+async fn delete_template(state: State<'_, AppState>, id: i64) -> Result<bool, String> {
+    state
+        .storage
+        .delete_template(id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+async fn render_template(
+    state: State<'_, AppState>,
+    id: i64,
+    vars: HashMap<String, String>,
+) -> Result<RenderedTemplate, String> {
+    let template = state
+        .storage
+        .template(id)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Template not found".to_string())?;
+
+    let fields = vars
+        .into_iter()
+        .map(|(key, value)| (key.trim().to_lowercase(), value))
+        .collect::<mail_merge::RecipientFields>();
+
+    Ok(RenderedTemplate {
+        subject: mail_merge::render_placeholders(&template.subject, &fields)?,
+        body: mail_merge::render_placeholders(&template.body, &fields)?,
+    })
+}
+
+/// Asks the LLM which saved template best answers a cached message.
+/// Returns `None` when no template fits or none exist.
+#[tauri::command]
+async fn suggest_template(
+    state: State<'_, AppState>,
+    email: String,
+    uid: String,
+) -> Result<Option<TemplateSuggestion>, String> {
+    let normalized_email = normalize_email(&email);
+    let templates = state
+        .storage
+        .list_templates()
+        .await
+        .map_err(|err| err.to_string())?;
+    if templates.is_empty() {
+        return Ok(None);
+    }
+
+    let message = state
+        .storage
+        .cached_message(&normalized_email, "INBOX", uid_arg(&uid)?)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Message not found".to_string())?;
+
+    let catalog = templates
+        .iter()
+        .map(|template| {
+            format!(
+                "- id {}: {} (subject: {}) {}",
+                template.id,
+                template.name,
+                template.subject,
+                clip_text(&template.body, 160).replace('\n', " ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let prompt = format!(
+        "You pick a canned reply for an incoming email.\n\
+         Templates:\n{catalog}\n\n\
+         Email from {sender}\nSubject: {subject}\n{snippet}\n\n\
+         Respond with JSON only: {{\"templateId\": <id or null>, \"reason\": \"<short reason>\"}}",
+        sender = message.sender_email,
+        subject = message.subject,
+        snippet = clip_text(message.snippet.as_deref().unwrap_or_default(), 600),
+    );
+
+    let raw = state
+        .llm
+        .analyze_prompt_with_priority(
+            prompt,
+            Some(96),
+            RequestPriority::Validation,
+            MESSAGE_PREVIEW_CLASSES,
+        )
+        .await?;
+    let parsed = parse_bulk_json(&raw)?;
+    let Some(template_id) = parsed.get("templateId").and_then(Value::as_i64) else {
+        return Ok(None);
+    };
+
+    Ok(templates
+        .into_iter()
+        .find(|template| template.id == template_id)
+        .map(|template| TemplateSuggestion {
+            template_id,
+            name: template.name,
+            reason: value_to_string(parsed.get("reason")),
+        }))
+}
+
+/// Returns short acknowledge/accept/decline replies for a cached message.
+/// Results are cached per message and regenerated when the active model
+/// changes or `refresh` is set.
+/// With `include_availability`, the replies offer free times for the days
+/// the message asks about (or the next few weekdays) and are not cached.
+#[tauri::command]
+async fn suggest_replies(
+    state: State<'_, AppState>,
+    email: String,
+    uid: String,
+    refresh: Option<bool>,
+    include_availability: Option<bool>,
+) -> Result<Vec<ReplySuggestion>, String> {
+    let normalized_email = normalize_email(&email);
+    let message = state
+        .storage
+        .cached_message(&normalized_email, "INBOX", uid_arg(&uid)?)
+        .await
+        .map_err(|err| err.to_string())?
+        .ok_or_else(|| "Message not found".to_string())?;
+
+    let model_id = infer_model_id_from_status(&state.llm.status());
+    let include_availability = include_availability.unwrap_or(false);
+    if !refresh.unwrap_or(false) && !include_availability {
+        let cached = state
+            .storage
+            .reply_suggestions(message.message_id)
+            .await
+            .map_err(|err| err.to_string())?;
+        if let Some((cached_model, suggestions)) = cached {
+            if cached_model == model_id && !suggestions.is_empty() {
+                return Ok(suggestions);
+            }
+        }
+    }
+
+    let sender = message
+        .sender_display
+        .clone()
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| message.sender_email.clone());
+    let snippet = message.snippet.as_deref().unwrap_or_default();
+    let mut prompt = format!(
+        "Write three short replies (one or two sentences each) to this email.\n\
+         From: {sender}\nSubject: {subject}\n{snippet}\n\n",
+        subject = message.subject,
+        snippet = clip_text(snippet, 800),
+    );
+    let mut classes = MESSAGE_PREVIEW_CLASSES;
+    if include_availability {
+        let now = Local::now();
+        let windows = calendar::resolve_question(&format!("{}\n{snippet}", message.subject), now)
+            .unwrap_or_else(|| calendar::upcoming_workdays(now, AVAILABILITY_REPLY_DAYS));
+        let availability =
+            availability_for(&state.storage, Some(&normalized_email), windows).await?;
+        let free = calendar::describe_free(&availability);
+        if !free.is_empty() {
+            prompt.push_str(&format!(
+                "My free times:\n{free}\n\
+                 When accepting or proposing a time, offer only these.\n\n"
+            ));
+        }
+        classes = REPLY_WITH_AVAILABILITY_CLASSES;
+    }
+    prompt.push_str(
+        "Respond with JSON only: \
+         {\"acknowledge\": \"...\", \"accept\": \"...\", \"decline\": \"...\"}",
+    );
+
+    let raw = state
+        .llm
+        .analyze_prompt_with_priority(prompt, Some(256), RequestPriority::Validation, classes)
+        .await?;
+    let parsed = parse_bulk_json(&raw)?;
+    let suggestions = QUICK_REPLY_KINDS
+        .iter()
+        .filter_map(|kind| {
+            value_to_string(parsed.get(*kind)).map(|text| ReplySuggestion {
+                kind: kind.to_string(),
+                text: clip_text(&text, QUICK_REPLY_MAX_CHARS),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    if suggestions.is_empty() {
+        return Err("Model did not return any reply suggestions".into());
+    }
+
+    if !include_availability {
+        state
+            .storage
+            .save_reply_suggestions(message.message_id, model_id.as_deref(), &suggestions)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    Ok(suggestions)
+}
+
+/// Free and busy time from calendar invitations, for an explicit range or
+/// a question such as "am I free Thursday afternoon?". Covers one account's
+/// events, or every account's.
+#[tauri::command]
+async fn check_availability(
+    state: State<'_, AppState>,
+    range: AvailabilityRange,
+    account: Option<String>,
+) -> Result<Availability, String> {
+    let windows = match (range.question.as_deref(), range.start, range.end) {
+        (Some(question), _, _) if !question.trim().is_empty() => {
+            calendar::resolve_question(question, Local::now())
+                .ok_or_else(|| "Could not tell which days the question is about".to_string())?
+        }
+        (_, Some(start), Some(end)) if start < end => vec![(start, end)],
+        _ => return Err("Give a question or a start before the end".into()),
+    };
+    let normalized_account = account.map(|value| normalize_email(&value));
+    availability_for(&state.storage, normalized_account.as_deref(), windows).await
+}
+
+async fn availability_for(
+    storage: &Storage,
+    account_email: Option<&str>,
+    windows: Vec<(i64, i64)>,
+) -> Result<Availability, String> {
+    let (Some(start), Some(end)) = (
+        windows.iter().map(|window| window.0).min(),
+        windows.iter().map(|window| window.1).max(),
+    ) else {
+        return Ok(calendar::availability(&[], &[]));
+    };
+    let events = storage
+        .calendar_events_between(account_email, start, end)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(calendar::availability(&events, &windows))
+}
+
+#[tauri::command]
+async fn get_autoreply_settings(
+    state: State<'_, AppState>,
+    email: String,
+) -> Result<AutoReplySettings, String> {
+    let normalized_email = normalize_email(&email);
+    load_autoreply_settings(&state.storage, &normalized_email).await
+}
+
+/// Saves the responder settings. Switching the responder on starts a new
+/// activation, so senders answered last time get one reply again.
+#[tauri::command]
+async fn set_autoreply_settings(
+    state: State<'_, AppState>,
+    email: String,
+    settings: AutoReplySettings,
+) -> Result<AutoReplySettings, String> {
+    let normalized_email = normalize_email(&email);
+    let mut settings = settings;
+
+    if settings.enabled {
+        let template_id = settings
+            .template_id
+            .ok_or_else(|| "Choose a template for the auto-reply".to_string())?;
+        state
+            .storage
+            .template(template_id)
+            .await
+            .map_err(|err| err.to_string())?
+            .ok_or_else(|| "Template not found".to_string())?;
+        if let (Some(start), Some(end)) = (settings.starts_at, settings.ends_at) {
+            if end <= start {
+                return Err("Auto-reply end must be after its start".into());
+            }
+        }
+    }
+
+    let previous = load_autoreply_settings(&state.storage, &normalized_email).await?;
+    settings.activated_at = if settings.enabled && !previous.enabled {
+        Utc::now().timestamp()
+    } else {
+        previous.activated_at
+    };
+
+    let json = serde_json::to_string(&settings).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(
+            &AutoReplySettings::setting_key(&normalized_email),
+            Some(&json),
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(settings)
+}
+
+#[tauri::command]
+async fn list_autoreply_log(
+    state: State<'_, AppState>,
+    email: String,
+    limit: Option<usize>,
+) -> Result<Vec<AutoReplyLogEntry>, String> {
+    let normalized_email = normalize_email(&email);
+    state
+        .storage
+        .autoreply_log(&normalized_email, limit.unwrap_or(200).clamp(1, 1000))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Sends the original message, wrapped as a `message/rfc822` attachment
+/// with every header intact, to `recipients` right away. It is recorded in
+/// the outbox and sent like [`send_message`] sends.
+async fn send_forward_as_attachment(
+    state: &AppState,
+    account_email: &str,
+    uid: u32,
+    recipients: &[String],
+    note: Option<&str>,
+) -> Result<ForwardOutcome, String> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| recipient.trim().to_lowercase())
+        .filter(|recipient| !recipient.is_empty())
+        .collect::<Vec<_>>();
+    if recipients.is_empty() {
+        return Err("Provide at least one recipient".into());
+    }
+    if let Some(invalid) = recipients.iter().find(|recipient| !recipient.contains('@')) {
+        return Err(format!("'{invalid}' is not an email address"));
+    }
+
+    let credentials = CommandContext::connected(state, account_email)
+        .await?
+        .credentials;
+
+    let raw = providers::fetch_raw_message(&credentials, uid)
+        .await
+        .map_err(provider_error_to_message)?
+        .ok_or_else(|| "Message not found on the server".to_string())?;
+
+    let original_subject = state
+        .storage
+        .cached_message(account_email, "INBOX", uid)
+        .await
+        .map_err(|err| err.to_string())?
+        .map(|message| message.subject)
+        .unwrap_or_default();
+
+    let mut filename = original_subject
+        .chars()
+        .map(|ch| {
+            if ch.is_alphanumeric() || ch == ' ' || ch == '-' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .take(60)
+        .collect::<String>()
+        .trim()
+        .to_string();
+    if filename.is_empty() {
+        filename = format!("message-{uid}");
+    }
+
+    let attachment = OutboxAttachment {
+        filename: format!("{filename}.eml"),
+        content_type: "message/rfc822".into(),
+        data: raw.to_vec(),
+    };
+    let (attachments, uploads) = link_large_attachments(&state.storage, vec![attachment]).await?;
+    let mut draft = OutgoingMessage {
+        to: recipients,
+        subject: format!("Fwd: {original_subject}"),
+        body: note.unwrap_or_default().to_string(),
+        attachments: attachments
+            .into_iter()
+            .map(|attachment| AttachmentData {
+                filename: attachment.filename,
+                content_type: attachment.content_type,
+                data: attachment.data,
+            })
+            .collect(),
+        ..Default::default()
+    };
+    for upload in &uploads {
+        if !draft.body.is_empty() {
+            draft.body.push_str("\n\n");
+        }
+        draft.body.push_str(&uploads::link_line(upload));
+    }
+
+    let (outbox_id, message_id) = state
+        .storage
+        .record_send(OutboxInsert {
+            account_email: account_email.to_string(),
+            merge_id: None,
+            recipient: draft.to.join(", "),
+            subject: draft.subject.clone(),
+            body: draft.body.clone(),
+            attachments: Vec::new(),
+            uploads,
+            scheduled_at: Utc::now().timestamp(),
+            in_reply_to: None,
+            references: Vec::new(),
+            auto_submitted: None,
+        })
+        .await
+        .map_err(|err| err.to_string())?;
+    match outbox::dispatch(&state.storage, &credentials, &draft, outbox_id, &message_id).await {
+        Ok(dispatched) => Ok(ForwardOutcome {
+            message_id,
+            delivery_path: dispatched.path,
+            saved_to_sent: dispatched.saved_to_sent,
+        }),
+        Err(err) => {
+            let message = provider_error_to_message(err);
+            if let Err(err) = state.storage.mark_outbox_failed(outbox_id, &message).await {
+                warn!(account = %account_email, outbox_id, %err, "could not record the forward");
+            }
+            Err(message)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ForwardOutcome {
+    /// The Message-ID header the forward went out with, without brackets.
+    message_id: String,
+    delivery_path: DeliveryPath,
+    saved_to_sent: bool,
+}
+
+/// Forwards a message as an `.eml` attachment, e.g. to IT or an abuse desk,
+/// and sends it right away.
+#[tauri::command]
+async fn forward_as_attachment(
+    state: State<'_, AppState>,
+    email: String,
+    uid: String,
+    recipients: Vec<String>,
+    note: Option<String>,
+) -> Result<ForwardOutcome, String> {
+    let normalized_email = normalize_email(&email);
+    send_forward_as_attachment(
+        state.inner(),
+        &normalized_email,
+        uid_arg(&uid)?,
+        &recipients,
+        note.as_deref(),
+    )
+    .await
+}
+
+/// Checks a draft's recipients, attachments, and subject before it is
+/// sent. A reply-all to a large conversation, a message to a list address,
+/// a send policy warning, or an empty or placeholder subject comes back
+/// with `requires_confirmation`, and the UI should ask before sending;
+/// `blocked` means the policy forbids it.
+#[tauri::command]
+async fn review_recipients(
+    state: State<'_, AppState>,
+    account: String,
+    recipients: Recipients,
+    reply_all: Option<bool>,
+    attachments: Option<Vec<OutgoingAttachment>>,
+    subject: Option<String>,
+) -> Result<RecipientReview, String> {
+    let normalized_account = normalize_email(&account);
+    let mut review =
+        send_guard::review(&normalized_account, &recipients, reply_all.unwrap_or(false))?;
+    let policy = load_send_policy(&state.storage).await?;
+    send_guard::apply_policy(
+        &mut review,
+        &policy,
+        &normalized_account,
+        &attachments.unwrap_or_default(),
+    );
+    if let Some(subject) = subject {
+        review.subject_issue = subject_lines::check(&subject);
+        review.requires_confirmation |= review.subject_issue.is_some();
+    }
+    Ok(review)
+}
+
+/// Three candidate subjects for a draft, written by the configured LLM.
+#[tauri::command]
+async fn suggest_subject(
+    state: State<'_, AppState>,
+    draft_body: String,
+) -> Result<Vec<String>, String> {
+    if draft_body.trim().is_empty() {
+        return Err("Write the message first".into());
+    }
+    let raw = state
+        .llm
+        .analyze_prompt(
+            subject_lines::prompt(&draft_body),
+            Some(128),
+            DRAFT_REWRITE_CLASSES,
+        )
+        .await?;
+    let candidates = subject_lines::parse_candidates(&raw);
+    if candidates.is_empty() {
+        return Err("Model did not return any subject lines".into());
+    }
+    Ok(candidates)
+}
+
+#[derive(Serialize)]
+struct SendOutcome {
+    sent: bool,
+    review: RecipientReview,
+    /// The Message-ID header the message went out with, without brackets.
+    message_id: Option<String>,
+    delivery_path: Option<DeliveryPath>,
+    uploads: Vec<AttachmentUpload>,
+    /// Whether a copy was appended to the Sent folder. Gmail and Outlook
+    /// file SMTP submissions there themselves, so this stays false for them.
+    saved_to_sent: bool,
+}
+
+/// Sends a composed message now. It gets the same review as
+/// `review_recipients` first: a blocked message is never sent, and one that
+/// needs confirmation only with `confirmed`; either way the review comes
+/// back with `sent: false`. Oversized attachments are uploaded and linked,
+/// the message goes out over SMTP, or the provider's HTTP API when SMTP is
+/// blocked, and a copy is filed in Sent unless the server does that itself.
+#[tauri::command]
+async fn send_message(
+    state: State<'_, AppState>,
+    email: String,
+    message: OutgoingMessage,
+    reply_all: Option<bool>,
+    confirmed: Option<bool>,
+) -> Result<SendOutcome, String> {
+    let normalized_email = require_email(&email)?;
+    let mut draft = message;
+    let recipients = Recipients {
+        to: draft.to.clone(),
+        cc: draft.cc.clone(),
+        bcc: draft.bcc.clone(),
+    };
+    let mut review =
+        send_guard::review(&normalized_email, &recipients, reply_all.unwrap_or(false))?;
+    if review.recipients.is_empty() {
+        return Err("Add at least one recipient".into());
+    }
+    let attachment_sizes = draft
+        .attachments
+        .iter()
+        .map(|attachment| OutgoingAttachment {
+            filename: attachment.filename.clone(),
+            size: attachment.data.len() as u64,
+        })
+        .collect::<Vec<_>>();
+    let policy = load_send_policy(&state.storage).await?;
+    send_guard::apply_policy(&mut review, &policy, &normalized_email, &attachment_sizes);
+    review.subject_issue = subject_lines::check(&draft.subject);
+    review.requires_confirmation |= review.subject_issue.is_some();
+    if review.blocked || (review.requires_confirmation && !confirmed.unwrap_or(false)) {
+        return Ok(SendOutcome {
+            sent: false,
+            review,
+            message_id: None,
+            delivery_path: None,
+            uploads: Vec::new(),
+            saved_to_sent: false,
+        });
+    }
+
+    let credentials = CommandContext::connected(&state, &normalized_email)
+        .await?
+        .credentials;
+
+    let attachments = std::mem::take(&mut draft.attachments)
+        .into_iter()
+        .map(|attachment| OutboxAttachment {
+            filename: attachment.filename,
+            content_type: attachment.content_type,
+            data: attachment.data,
+        })
+        .collect();
+    let (kept, uploads) = link_large_attachments(&state.storage, attachments).await?;
+    draft.attachments = kept
+        .into_iter()
+        .map(|attachment| AttachmentData {
+            filename: attachment.filename,
+            content_type: attachment.content_type,
+            data: attachment.data,
+        })
+        .collect();
+    for upload in &uploads {
+        if !draft.body.is_empty() {
+            draft.body.push_str("\n\n");
+        }
+        draft.body.push_str(&uploads::link_line(upload));
+    }
+
+    let (outbox_id, message_id) = state
+        .storage
+        .record_send(OutboxInsert {
+            account_email: normalized_email.clone(),
+            merge_id: None,
+            recipient: review.recipients.join(", "),
+            subject: draft.subject.trim().to_string(),
+            body: draft.body.clone(),
+            attachments: Vec::new(),
+            uploads: uploads.clone(),
+            scheduled_at: Utc::now().timestamp(),
+            in_reply_to: draft.in_reply_to.clone(),
+            references: draft.references.clone(),
+            auto_submitted: None,
+        })
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let dispatched =
+        outbox::dispatch(&state.storage, &credentials, &draft, outbox_id, &message_id).await;
+    let dispatched = match dispatched {
+        Ok(dispatched) => dispatched,
+        Err(err) => {
+            let message = provider_error_to_message(err);
+            if let Err(err) = state.storage.mark_outbox_failed(outbox_id, &message).await {
+                warn!(%normalized_email, outbox_id, %err, "could not record the failed send");
+            }
+            return Err(message);
+        }
+    };
+
+    Ok(SendOutcome {
+        sent: true,
+        review,
+        message_id: Some(message_id),
+        delivery_path: Some(dispatched.path),
+        uploads,
+        saved_to_sent: dispatched.saved_to_sent,
+    })
+}
+
+/// Sends queued outbox entries as they come due, for accounts that are
+/// connected at the time; the rest wait until they are.
+async fn drain_outbox_periodically(app: tauri::AppHandle) {
+    let mut ticker = time::interval(Duration::from_secs(OUTBOX_DRAIN_INTERVAL_SECS));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        ticker.tick().await;
+        let state = app.state::<AppState>();
+        let accounts = state.accounts.read().await.clone();
+        if accounts.is_empty() {
+            continue;
+        }
+        match outbox::drain(&state.storage, &accounts, Utc::now().timestamp()).await {
+            Ok(0) => {}
+            Ok(count) => info!(count, "sent queued outbox entries"),
+            Err(err) => warn!(?err, "failed to drain the outbox"),
+        }
+    }
+}
+
+async fn load_send_policy(storage: &Storage) -> Result<SendPolicy, String> {
+    let raw = storage
+        .get_setting(send_guard::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(SendPolicy::default()),
+    }
+}
+
+#[tauri::command]
+async fn get_send_policy(state: State<'_, AppState>) -> Result<SendPolicy, String> {
+    load_send_policy(&state.storage).await
+}
+
+/// Sets which recipient domains and attachment sizes outgoing mail is
+/// warned about or blocked for.
+#[tauri::command]
+async fn set_send_policy(
+    state: State<'_, AppState>,
+    policy: SendPolicy,
+) -> Result<SendPolicy, String> {
+    let normalized = policy.normalized()?;
+    let json = serde_json::to_string(&normalized).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(send_guard::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(normalized)
+}
+
+/// Counts, reading time, readability, tone, and risky phrases for a draft.
+/// Cheap enough to call whenever typing pauses.
+#[tauri::command]
+async fn analyze_draft(state: State<'_, AppState>, text: String) -> Result<DraftStats, String> {
+    let settings = load_draft_check_settings(&state.storage).await?;
+    Ok(draft_stats::analyze(&text, &settings))
+}
+
+/// Rewrites a draft more formally, shorter, friendlier, or with grammar
+/// fixed, through whichever LLM backend is configured (the local model
+/// works offline). The response carries a word diff against the original.
+#[tauri::command]
+async fn rewrite_draft(
+    state: State<'_, AppState>,
+    text: String,
+    instruction: RewriteInstruction,
+) -> Result<DraftRewrite, String> {
+    let draft = draft_rewrite::validate(&text)?;
+    let max_tokens = instruction.max_tokens(draft.chars().count());
+    let raw = state
+        .llm
+        .analyze_prompt(
+            draft_rewrite::prompt(draft, instruction),
+            Some(max_tokens),
+            DRAFT_REWRITE_CLASSES,
+        )
+        .await?;
+    let rewritten = draft_rewrite::clean_output(&raw)
+        .ok_or_else(|| "Model did not return a rewritten draft".to_string())?;
+    Ok(draft_rewrite::rewrite(draft, rewritten, instruction))
+}
+
+async fn load_draft_check_settings(storage: &Storage) -> Result<DraftCheckSettings, String> {
+    let raw = storage
+        .get_setting(draft_stats::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(DraftCheckSettings::default()),
+    }
+}
+
+#[tauri::command]
+async fn get_draft_check_settings(
+    state: State<'_, AppState>,
+) -> Result<DraftCheckSettings, String> {
+    load_draft_check_settings(&state.storage).await
+}
+
+/// Sets the phrases drafts are flagged for.
+#[tauri::command]
+async fn set_draft_check_settings(
+    state: State<'_, AppState>,
+    settings: DraftCheckSettings,
+) -> Result<DraftCheckSettings, String> {
+    let normalized = settings.normalized()?;
+    let json = serde_json::to_string(&normalized).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(draft_stats::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(normalized)
+}
+
+async fn load_upload_settings(storage: &Storage) -> Result<UploadSettings, String> {
+    let raw = storage
+        .get_setting(uploads::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match raw {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(UploadSettings::default()),
+    }
+}
+
+#[tauri::command]
+async fn get_upload_settings(state: State<'_, AppState>) -> Result<UploadSettings, String> {
+    load_upload_settings(&state.storage).await
+}
+
+/// Saves where oversized attachments are uploaded. A `secret` (WebDAV
+/// password or S3 secret key) goes to the OS keychain, never to app
+/// settings.
+#[tauri::command]
+async fn set_upload_settings(
+    state: State<'_, AppState>,
+    settings: UploadSettings,
+    secret: Option<String>,
+) -> Result<UploadSettings, String> {
+    let normalized = settings.normalized()?;
+    if let Some(secret) = secret.filter(|value| !value.is_empty()) {
+        let backend = normalized
+            .backend
+            .as_ref()
+            .ok_or_else(|| "Choose an upload backend to save its secret".to_string())?;
+        let secret = SecretString::new(secret);
+        store_service_secret(&state.storage, &backend.keychain_key(), &secret).await?;
+    }
+    let json = serde_json::to_string(&normalized).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(uploads::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(normalized)
+}
+
+/// Uploads attachments over the configured threshold and returns the rest,
+/// plus the uploads to link from the body. Everything is kept attached
+/// when uploads are turned off.
+async fn link_large_attachments(
+    storage: &Storage,
+    attachments: Vec<OutboxAttachment>,
+) -> Result<(Vec<OutboxAttachment>, Vec<AttachmentUpload>), String> {
+    let settings = load_upload_settings(storage).await?;
+    let threshold = settings.threshold_bytes;
+    let config = match settings.backend {
+        Some(config) if settings.enabled => config,
+        _ => return Ok((attachments, Vec::new())),
+    };
+    let (large, kept): (Vec<_>, Vec<_>) = attachments
+        .into_iter()
+        .partition(|attachment| attachment.data.len() as u64 > threshold);
+    if large.is_empty() {
+        return Ok((kept, Vec::new()));
+    }
+
+    let secret = fetch_password_from_keychain(&config.keychain_key())?
+        .ok_or_else(|| "Save the upload backend's secret first".to_string())?;
+    let backend = config.backend(secret)?;
+    let mut linked = Vec::with_capacity(large.len());
+    for attachment in large {
+        let url = backend
+            .upload(UploadFile {
+                filename: &attachment.filename,
+                content_type: &attachment.content_type,
+                data: &attachment.data,
+            })
+            .await?;
+        info!(
+            backend = config.name(),
+            size = attachment.data.len(),
+            "uploaded large attachment"
+        );
+        linked.push(AttachmentUpload {
+            filename: attachment.filename,
+            size: attachment.data.len() as u64,
+            backend: config.name().to_string(),
+            url,
+            uploaded_at: Utc::now().timestamp(),
+        });
+    }
+    Ok((kept, linked))
+}
+
+#[derive(Serialize)]
+struct ReportOutcome {
+    moved: bool,
+    /// The abuse address the message was sent to; `None` unless it went out.
+    forwarded_to: Option<String>,
+    forward_delivery_path: Option<DeliveryPath>,
+    /// Why the forward was not sent, when one was asked for and failed.
+    forward_error: Option<String>,
+}
+
+/// Adds or removes flags (`seen`, `flagged`, ...). The cache changes at
+/// once and `local-update-applied` is emitted; the edit is then pushed to
+/// the server, or stays queued until the next sync when the account is
+/// offline. Returns how many cached messages changed.
+#[tauri::command]
+async fn set_message_flags(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    email: String,
+    uids: Vec<String>,
+    flags: Vec<String>,
+    enabled: bool,
+) -> Result<usize, String> {
+    let normalized_email = normalize_email(&email);
+    let flags = flags
+        .into_iter()
+        .map(|flag| flag.trim().to_string())
+        .filter(|flag| !flag.is_empty())
+        .collect::<Vec<_>>();
+    if let Some(flag) = flags.iter().find(|flag| !valid_flag(flag)) {
+        return Err(format!("Invalid flag '{flag}'"));
+    }
+    let uids = uid_args(&uids)?;
+
+    let changed = state
+        .storage
+        .queue_flag_changes(&normalized_email, "INBOX", &uids, &flags, enabled)
+        .await
+        .map_err(|err| err.to_string())?;
+    let payload = LocalUpdate {
+        account_email: normalized_email.clone(),
+        action: if enabled { "set_flags" } else { "clear_flags" }.to_string(),
+        uids,
+        target: Some(flags.join(" ")),
+        error: None,
+    };
+    emit_local_update(&app, payload, LocalUpdateApplied);
+
+    match command_context::credentials(&state, &normalized_email).await {
+        Some(credentials) => {
+            if let Err(err) =
+                push_flag_changes(&app, &state.storage, &credentials, &normalized_email).await
+            {
+                warn!(%normalized_email, %err, "flag changes queued until the next sync");
+            }
+        }
+        None => info!(%normalized_email, "account offline; flag changes queued"),
+    }
+    Ok(changed)
+}
+
+/// Whether `flag` can go into an IMAP STORE as a single atom.
+fn valid_flag(flag: &str) -> bool {
+    !flag.contains(|c: char| c.is_whitespace() || "()[]{}\"*%".contains(c))
+}
+
+#[tauri::command]
+async fn get_flag_conflict_policies(state: State<'_, AppState>) -> Result<FlagPolicies, String> {
+    let json = state
+        .storage
+        .get_setting(flag_sync::SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    match json {
+        Some(json) => serde_json::from_str(&json).map_err(|err| err.to_string()),
+        None => Ok(FlagPolicies::default()),
+    }
+}
+
+/// Sets how flags edited both here and on another client are resolved:
+/// `server-wins`, `local-wins`, or `newest-wins`, per flag with a default.
+#[tauri::command]
+async fn set_flag_conflict_policies(
+    state: State<'_, AppState>,
+    policies: FlagPolicies,
+) -> Result<FlagPolicies, String> {
+    let mut normalized = FlagPolicies {
+        default: policies.default,
+        flags: Default::default(),
+    };
+    for (flag, policy) in policies.flags {
+        let flag = flag.trim();
+        if flag.is_empty() || flag.contains(|c: char| c.is_whitespace()) {
+            return Err(format!("Invalid flag '{flag}'"));
+        }
+        normalized.flags.insert(flag_sync::flag_key(flag), policy);
+    }
+    let json = serde_json::to_string(&normalized).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(flag_sync::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(normalized)
+}
+
+#[tauri::command]
+async fn list_flag_conflicts(
+    state: State<'_, AppState>,
+    email: String,
+    limit: Option<usize>,
+) -> Result<Vec<FlagConflict>, String> {
+    let normalized_email = normalize_email(&email);
+    state
+        .storage
+        .flag_conflicts(&normalized_email, limit.unwrap_or(100))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Response rates and reply times for mail sent from `account`, by
+/// recipient and by hour of day. Unless `refresh` is false, the newest Sent
+/// folder headers are synced first; cached ones are used if that fails.
+#[tauri::command]
+async fn get_send_insights(
+    state: State<'_, AppState>,
+    account: String,
+    refresh: Option<bool>,
+) -> Result<SendInsights, String> {
+    let normalized_account = normalize_email(&account);
+    let credentials = command_context::credentials(&state, &normalized_account).await;
+
+    if let Some(credentials) = credentials.filter(|_| refresh.unwrap_or(true)) {
+        sync_sent_headers(&state.storage, &credentials).await;
+    }
+
+    let (sent, received) = state
+        .storage
+        .send_history(&normalized_account)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(send_insights::compute(
+        &normalized_account,
+        &sent,
+        &received,
+    ))
+}
+
+/// Caches the newest Sent folder headers of an account, one row per
+/// recipient. Failures are logged; callers carry on with what is cached.
+async fn sync_sent_headers(storage: &Storage, credentials: &Credentials) {
+    let account = &credentials.email;
+    let envelopes = match providers::fetch_sent(
+        credentials,
+        credentials.provider.sent_folder(),
+        SENT_SYNC_LIMIT,
+    )
+    .await
+    {
+        Ok(envelopes) => envelopes,
+        Err(err) => {
+            warn!(%account, ?err, "failed to sync sent mail headers");
+            return;
+        }
+    };
+    let rows = envelopes
+        .into_iter()
+        .filter_map(|envelope| {
+            let sent_at = envelope.sent_at?;
+            Some(envelope.recipients.into_iter().map(move |recipient| {
+                (
+                    envelope.uid,
+                    SentMessage {
+                        recipient,
+                        subject: envelope.subject.clone(),
+                        sent_at,
+                    },
+                )
+            }))
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    if let Err(err) = storage.record_sent_messages(account, rows).await {
+        warn!(%account, ?err, "failed to store sent mail headers");
+    }
+}
+
+/// When mail arrives for `account`: counts by local day of week and hour,
+/// overall and per tag, optionally limited to a date range.
+#[tauri::command]
+async fn get_inbox_heatmap(
+    state: State<'_, AppState>,
+    account: String,
+    range: Option<HeatmapRange>,
+) -> Result<InboxHeatmap, String> {
+    let normalized_account = normalize_email(&account);
+    let range = range.unwrap_or_default();
+    if let (Some(since), Some(until)) = (range.since, range.until) {
+        if until <= since {
+            return Err("Range end must be after its start".into());
+        }
+    }
+    let samples = state
+        .storage
+        .arrival_samples(&normalized_account, range.since, range.until)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(heatmap::build(&normalized_account, range, &samples))
+}
+
+#[tauri::command]
+async fn get_focus_settings(state: State<'_, AppState>) -> Result<FocusSettings, String> {
+    load_focus_settings(&state.storage).await
+}
+
+/// Sets focus hours, what counts as urgent, and when held mail is reviewed.
+#[tauri::command]
+async fn set_focus_settings(
+    state: State<'_, AppState>,
+    settings: FocusSettings,
+) -> Result<FocusSettings, String> {
+    let normalized = settings.normalized()?;
+    let json = serde_json::to_string(&normalized).map_err(|err| err.to_string())?;
+    state
+        .storage
+        .set_setting(focus::SETTING_KEY, Some(&json))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(normalized)
+}
+
+#[derive(Serialize)]
+struct FocusListing {
+    /// Whether focus hours are on right now.
+    active: bool,
+    messages: Vec<FocusMessage>,
+}
+
+/// The focused inbox: urgent mail focus mode let through, newest first.
+#[tauri::command]
+async fn list_focused_messages(
+    state: State<'_, AppState>,
+    account: String,
+    limit: Option<usize>,
+) -> Result<FocusListing, String> {
+    let normalized_account = normalize_email(&account);
+    let settings = load_focus_settings(&state.storage).await?;
+    let messages = state
+        .storage
+        .focused_messages(&normalized_account, limit.unwrap_or(100))
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(FocusListing {
+        active: settings.is_active(Local::now()),
+        messages,
+    })
+}
+
+/// Mail held back during focus hours that the next review will report.
+#[tauri::command]
+async fn list_focus_held(
+    state: State<'_, AppState>,
+    account: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<FocusMessage>, String> {
+    let normalized_account = account.map(|value| value.trim().to_lowercase());
+    state
+        .storage
+        .held_focus_messages(normalized_account.as_deref(), limit.unwrap_or(200))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Delivery failures reported for mail sent from `account`, newest first.
+#[tauri::command]
+async fn list_bounces(
+    state: State<'_, AppState>,
+    account: String,
+    limit: Option<usize>,
+) -> Result<Vec<Bounce>, String> {
+    let normalized_account = normalize_email(&account);
+    state
+        .storage
+        .list_bounces(&normalized_account, limit.unwrap_or(100))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Moves messages through the triage lifecycle (`snoozed`, `done`, ...).
+/// The updates are coalesced, so rapid clicks cost one write.
+#[tauri::command]
+async fn set_message_lifecycle(
+    state: State<'_, AppState>,
+    email: String,
+    uids: Vec<String>,
+    lifecycle: String,
+) -> Result<usize, String> {
+    let normalized_email = normalize_email(&email);
+    let lifecycle = lifecycle.trim().to_lowercase();
+    if !BULK_LIFECYCLE_VALUES.contains(&lifecycle.as_str()) {
+        return Err(format!(
+            "Unknown lifecycle '{lifecycle}'; expected one of {}",
+            BULK_LIFECYCLE_VALUES.join(", ")
+        ));
+    }
+
+    let uids = uid_args(&uids)?;
+    let count = uids.len();
+    for uid in uids {
+        let write = PendingWrite::Lifecycle {
+            account_email: normalized_email.clone(),
+            folder: "INBOX".to_string(),
+            uid,
+            lifecycle: lifecycle.clone(),
+        };
+        state
+            .storage
+            .queue_write(write)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(count)
+}
+
+#[derive(Serialize)]
+struct BulkItemResult {
+    uid: String,
+    ok: bool,
+    error: Option<String>,
+}
+
+impl BulkItemResult {
+    fn done(uid: &str) -> Self {
+        Self {
+            uid: uid.to_string(),
+            ok: true,
+            error: None,
+        }
+    }
 
-    let routes = warp::get()
-        .and(warp::query::<HashMap<String, String>>())
-        .and_then(move |query: HashMap<String, String>| {
-            let code = Arc::clone(&code_clone);
-            async move {
-                if let Some(c) = query.get("code") {
-                    *code.lock().unwrap() = Some(c.clone());
-                    Ok::<_, warp::Rejection>(warp::reply::html(
-                        "Authorization successful! You can close this window.",
-                    ))
-                } else {
-                    Ok::<_, warp::Rejection>(warp::reply::html("Authorization failed."))
+    fn failed(uid: &str, error: impl Into<String>) -> Self {
+        Self {
+            uid: uid.to_string(),
+            ok: false,
+            error: Some(error.into()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BulkActionReport {
+    action: String,
+    succeeded: usize,
+    failed: usize,
+    /// One entry per requested UID, in request order.
+    results: Vec<BulkItemResult>,
+}
+
+/// Applies one action to many messages at once: `mark_read`,
+/// `mark_unread`, `label` / `unlabel` (`target` is the keyword), `move`
+/// (`target` is the folder), `delete`, or `block_sender` (`target` limits
+/// the block to one account). The cache changes in one transaction and
+/// `local-update-applied` is emitted before the server is updated through
+/// the same queues the single-message commands use. Flag edits and moves
+/// the server still refuses after retries are undone with
+/// `local-update-rolled-back`; deletes keep their row in the deleted list
+/// with the remote error. UIDs that are invalid or not cached fail on their
+/// own without stopping the rest.
+#[tauri::command]
+async fn bulk_action(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    account: String,
+    uids: Vec<String>,
+    action: String,
+    target: Option<String>,
+) -> Result<BulkActionReport, String> {
+    let normalized_account = normalize_email(&account);
+    let action = action.trim().to_lowercase();
+    let target = target
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    if !BULK_ACTIONS.contains(&action.as_str()) {
+        return Err(format!(
+            "Unknown bulk action '{action}'; expected one of {}",
+            BULK_ACTIONS.join(", ")
+        ));
+    }
+    let target = match (action.as_str(), target) {
+        ("label" | "unlabel" | "move", None) => {
+            return Err(format!("The '{action}' action needs a target"));
+        }
+        ("label" | "unlabel", Some(flag)) if !valid_flag(&flag) => {
+            return Err(format!("Invalid flag '{flag}'"));
+        }
+        ("block_sender", target) => target.map(|value| value.to_lowercase()),
+        (_, target) => target,
+    };
+
+    let mut requested = Vec::with_capacity(uids.len());
+    for uid in uids {
+        let uid = uid.trim().to_string();
+        if !requested.contains(&uid) {
+            requested.push(uid);
+        }
+    }
+    let valid = requested
+        .iter()
+        .filter_map(|uid| parse_uid(uid))
+        .collect::<Vec<_>>();
+    let senders = state
+        .storage
+        .message_senders(&normalized_account, "INBOX", &valid)
+        .await
+        .map_err(|err| err.to_string())?;
+    let cached = valid
+        .into_iter()
+        .filter(|uid| senders.contains_key(uid))
+        .collect::<Vec<_>>();
+
+    let credentials = command_context::credentials(&state, &normalized_account).await;
+    let mut outcomes: HashMap<u32, Result<(), String>> = HashMap::new();
+    let mut flags_queued = false;
+    match action.as_str() {
+        "mark_read" | "mark_unread" | "label" | "unlabel" => {
+            let (flag, enabled) = match action.as_str() {
+                "mark_read" => ("seen".to_string(), true),
+                "mark_unread" => ("seen".to_string(), false),
+                _ => (target.clone().unwrap_or_default(), action == "label"),
+            };
+            state
+                .storage
+                .queue_flag_changes(&normalized_account, "INBOX", &cached, &[flag], enabled)
+                .await
+                .map_err(|err| err.to_string())?;
+            outcomes.extend(cached.iter().map(|&uid| (uid, Ok(()))));
+            flags_queued = true;
+        }
+        "move" => {
+            let credentials = credentials
+                .clone()
+                .ok_or_else(|| NOT_CONNECTED.to_string())?;
+            state
+                .storage
+                .tombstone_messages(&normalized_account, "INBOX", &cached, TOMBSTONE_MOVED)
+                .await
+                .map_err(|err| err.to_string())?;
+            outcomes.extend(cached.iter().map(|&uid| (uid, Ok(()))));
+            tauri::async_runtime::spawn(move_remotely(
+                app.clone(),
+                state.storage.clone(),
+                credentials,
+                normalized_account.clone(),
+                cached.clone(),
+                target.clone().unwrap_or_default(),
+            ));
+        }
+        "delete" => {
+            let credentials = credentials
+                .clone()
+                .ok_or_else(|| NOT_CONNECTED.to_string())?;
+            let archived = state
+                .storage
+                .archive_messages(&normalized_account, "INBOX", &cached)
+                .await
+                .map_err(|err| err.to_string())?;
+            for mut row in archived {
+                let outcome =
+                    delete_remotely(state.inner(), &credentials, &normalized_account, &mut row)
+                        .await;
+                outcomes.insert(row.uid, outcome);
+            }
+        }
+        _ => {
+            let scope = target.clone().unwrap_or_else(|| GLOBAL_SCOPE.to_string());
+            let mut blocked: HashMap<&str, Result<(), String>> = HashMap::new();
+            for sender in senders.values() {
+                if blocked.contains_key(sender.as_str()) {
+                    continue;
+                }
+                let outcome = state
+                    .storage
+                    .update_sender_status(sender, &scope, SenderStatus::Blocked)
+                    .await
+                    .map_err(|err| err.to_string());
+                if outcome.is_ok() && scope == GLOBAL_SCOPE {
+                    train_spam_for_sender_in_background(
+                        state.storage.clone(),
+                        sender.clone(),
+                        Some(SpamLabel::Spam),
+                    );
                 }
+                blocked.insert(sender, outcome);
             }
-        });
+            for uid in &cached {
+                outcomes.insert(*uid, blocked[senders[uid].as_str()].clone());
+            }
+        }
+    }
+
+    let results = requested
+        .iter()
+        .map(
+            |uid| match parse_uid(uid).map(|value| outcomes.remove(&value)) {
+                Some(Some(Ok(()))) => BulkItemResult::done(uid),
+                Some(Some(Err(message))) => BulkItemResult::failed(uid, message),
+                Some(None) => BulkItemResult::failed(uid, "Message not found in local cache"),
+                None => BulkItemResult::failed(uid, format!("Invalid message UID '{uid}'")),
+            },
+        )
+        .collect::<Vec<_>>();
+    let succeeded = results.iter().filter(|result| result.ok).count();
+    let failed = results.len() - succeeded;
+
+    let payload = LocalUpdate {
+        account_email: normalized_account.clone(),
+        action: action.clone(),
+        uids: results
+            .iter()
+            .filter(|result| result.ok)
+            .filter_map(|result| parse_uid(&result.uid))
+            .collect(),
+        target: target.clone(),
+        error: None,
+    };
+    emit_local_update(&app, payload, LocalUpdateApplied);
+    // Pushed after the event, so the UI hears of the change before any
+    // rollback of it.
+    if flags_queued {
+        match &credentials {
+            Some(credentials) => {
+                if let Err(err) =
+                    push_flag_changes(&app, &state.storage, credentials, &normalized_account).await
+                {
+                    warn!(%normalized_account, %err, "flag changes queued until the next sync");
+                }
+            }
+            None => info!(%normalized_account, "account offline; flag changes queued"),
+        }
+    }
+
+    state
+        .storage
+        .record_audit(
+            Some(&normalized_account),
+            "bulk_action",
+            target.as_deref(),
+            json!({
+                "action": action,
+                "succeeded": succeeded,
+                "failed": failed,
+            }),
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(BulkActionReport {
+        action,
+        succeeded,
+        failed,
+        results,
+    })
+}
+
+/// Moves messages on the server after the cache already shows them moved,
+/// retrying a few times. If every attempt fails the messages come back to
+/// the cache and `local-update-rolled-back` says why.
+async fn move_remotely(
+    app: tauri::AppHandle,
+    storage: Storage,
+    credentials: Credentials,
+    account_email: String,
+    uids: Vec<u32>,
+    folder: String,
+) {
+    let mut attempt = 1;
+    let message = loop {
+        match providers::move_messages(&credentials, &uids, &folder).await {
+            Ok(_) => return,
+            Err(err) if attempt < BULK_MOVE_ATTEMPTS => {
+                warn!(
+                    account = %account_email,
+                    %folder,
+                    attempt,
+                    ?err,
+                    "remote move failed; retrying"
+                );
+                let delay = BULK_MOVE_RETRY_SECS * u64::from(attempt);
+                time::sleep(Duration::from_secs(delay)).await;
+                attempt += 1;
+            }
+            Err(err) => break provider_error_to_message(err),
+        }
+    };
+
+    error!(account = %account_email, %folder, %message, "remote move failed; restoring messages");
+    if let Err(err) = storage
+        .untombstone_messages(&account_email, "INBOX", &uids, TOMBSTONE_MOVED)
+        .await
+    {
+        error!(account = %account_email, ?err, "failed to restore messages after a failed move");
+        return;
+    }
+    let payload = LocalUpdate {
+        account_email,
+        action: "move".to_string(),
+        uids,
+        target: Some(folder),
+        error: Some(message),
+    };
+    emit_local_update(&app, payload, LocalUpdateRolledBack);
+}
+
+/// Reports a message as spam or phishing: moves it to the provider's Junk
+/// folder (which trains the server-side filter), optionally forwards it as an
+/// attachment to the provider's abuse address, and trains the local spam model.
+#[tauri::command]
+async fn report_message(
+    state: State<'_, AppState>,
+    email: String,
+    uid: String,
+    kind: String,
+    forward: Option<bool>,
+) -> Result<ReportOutcome, String> {
+    let normalized_email = normalize_email(&email);
+    let phishing = match kind.trim().to_lowercase().as_str() {
+        "spam" => false,
+        "phishing" => true,
+        other => return Err(format!("Unknown report kind '{other}'")),
+    };
+    let uid = uid_arg(&uid)?;
+
+    let credentials = CommandContext::connected(&state, &normalized_email)
+        .await?
+        .credentials;
+
+    let cached = state
+        .storage
+        .cached_message(&normalized_email, "INBOX", uid)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    // Forward before moving: the raw message is read from the INBOX. A
+    // forward that fails is reported back but does not stop the report.
+    let mut forwarded = None;
+    let mut forward_error = None;
+    if forward.unwrap_or(false) {
+        if let Some(address) = credentials.provider.report_address(phishing) {
+            match send_forward_as_attachment(
+                state.inner(),
+                &normalized_email,
+                uid,
+                &[address.to_string()],
+                None,
+            )
+            .await
+            {
+                Ok(outcome) => forwarded = Some((address.to_string(), outcome)),
+                Err(err) => {
+                    warn!(account = %normalized_email, %uid, %err, "failed to forward report");
+                    forward_error = Some(err);
+                }
+            }
+        }
+    }
+    let forwarded_to = forwarded.as_ref().map(|(address, _)| address.clone());
+    let forward_message_id = forwarded
+        .as_ref()
+        .map(|(_, outcome)| outcome.message_id.clone());
+
+    let moved = providers::move_messages(&credentials, &[uid], credentials.provider.junk_folder())
+        .await
+        .map_err(provider_error_to_message)?
+        > 0;
+
+    if moved {
+        state
+            .storage
+            .tombstone_messages(&normalized_email, "INBOX", &[uid], TOMBSTONE_MOVED)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    if let Some(message) = cached.as_ref() {
+        let tokens = spam::tokenize(
+            &message.sender_email,
+            &message.subject,
+            message.snippet.as_deref(),
+        );
+        let document = (format!("report:{normalized_email}:{uid}"), tokens);
+        if let Err(err) = state
+            .storage
+            .train_spam_documents(vec![document], SpamLabel::Spam)
+            .await
+        {
+            warn!(account = %normalized_email, %uid, ?err, "failed to train spam model from report");
+        }
+    }
+
+    state
+        .storage
+        .record_audit(
+            Some(&normalized_email),
+            "report_message",
+            Some(&uid.to_string()),
+            json!({
+                "kind": if phishing { "phishing" } else { "spam" },
+                "sender": cached.as_ref().map(|message| message.sender_email.clone()),
+                "movedToJunk": moved,
+                "forwardedTo": forwarded_to,
+                "forwardMessageId": forward_message_id,
+                "forwardError": forward_error,
+            }),
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(ReportOutcome {
+        moved,
+        forwarded_to,
+        forward_delivery_path: forwarded.map(|(_, outcome)| outcome.delivery_path),
+        forward_error,
+    })
+}
+
+#[tauri::command]
+async fn list_audit_log(
+    state: State<'_, AppState>,
+    email: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<AuditEntry>, String> {
+    let normalized_email = email.map(|value| value.trim().to_lowercase());
+    state
+        .storage
+        .audit_log(
+            normalized_email.as_deref(),
+            limit.unwrap_or(200).clamp(1, 1000),
+        )
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Startup integrity check. The report is emitted as `storage-health` and
+/// also saved, since the window may not be listening yet at launch.
+async fn check_storage_health(app: &tauri::AppHandle, storage: &Storage) {
+    let report = match storage.verify_and_recover().await {
+        Ok(report) => report,
+        Err(err) => {
+            error!(?err, "storage integrity check could not run");
+            StorageHealthReport {
+                status: "failed".into(),
+                problems: vec![err.to_string()],
+                actions: Vec::new(),
+                restored_from: None,
+                quarantined: None,
+                checked_at: Utc::now().timestamp(),
+            }
+        }
+    };
+
+    match report.status.as_str() {
+        "ok" => info!("storage integrity check passed"),
+        "failed" => error!(problems = ?report.problems, "storage could not be recovered"),
+        status => warn!(status, actions = ?report.actions, "storage recovered from corruption"),
+    }
+
+    // Only snapshot a database known to be good.
+    if report.status != "failed" {
+        if let Err(err) = storage.create_backup(STORAGE_BACKUP_INTERVAL_SECS).await {
+            warn!(?err, "failed to write storage backup");
+        }
+    }
+
+    if let Ok(json) = serde_json::to_string(&report) {
+        if let Err(err) = storage
+            .set_setting(STORAGE_HEALTH_SETTING_KEY, Some(&json))
+            .await
+        {
+            warn!(?err, "failed to save storage health report");
+        }
+    }
+    events::emit(app, &StorageHealth { report });
+}
+
+/// Last startup integrity report, or `None` before the first check finishes.
+#[tauri::command]
+async fn get_storage_health(
+    state: State<'_, AppState>,
+) -> Result<Option<StorageHealthReport>, String> {
+    let raw = state
+        .storage
+        .get_setting(STORAGE_HEALTH_SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    raw.map(|json| serde_json::from_str(&json).map_err(|err| err.to_string()))
+        .transpose()
+}
+
+fn emit_wipe_progress(app: &tauri::AppHandle, step: &str, detail: Value) {
+    let step = step.to_string();
+    events::emit(app, &WipeProgress { step, detail });
+}
+
+/// First half of the wipe handshake: returns a token that
+/// [`wipe_local_data`] accepts for the next two minutes.
+#[tauri::command]
+async fn prepare_wipe_local_data(state: State<'_, AppState>) -> Result<String, String> {
+    let token = Uuid::new_v4().to_string();
+    let expires_at = Utc::now().timestamp() + WIPE_TOKEN_TTL_SECS;
+    state
+        .storage
+        .set_setting(
+            WIPE_TOKEN_SETTING_KEY,
+            Some(&format!("{token}:{expires_at}")),
+        )
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(token)
+}
+
+/// Saves a service's secret to the keychain under `key` and records the key
+/// for [`wipe_local_data`].
+async fn store_service_secret(
+    storage: &Storage,
+    key: &str,
+    secret: &SecretString,
+) -> Result<(), String> {
+    store_password_in_keychain(key, secret)?;
+    let mut keys = recorded_service_secrets(storage).await?;
+    if !keys.iter().any(|known| known == key) {
+        keys.push(key.to_string());
+        let json = serde_json::to_string(&keys).map_err(|err| err.to_string())?;
+        storage
+            .set_setting(SERVICE_SECRETS_SETTING_KEY, Some(&json))
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+async fn recorded_service_secrets(storage: &Storage) -> Result<Vec<String>, String> {
+    let raw = storage
+        .get_setting(SERVICE_SECRETS_SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(raw
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default())
+}
+
+/// The service keychain entries a wipe removes: those recorded by
+/// [`store_service_secret`], and those the current settings name, which
+/// may have been saved before it kept a record.
+async fn service_secret_keys(storage: &Storage) -> Result<Vec<String>, String> {
+    let mut keys = recorded_service_secrets(storage).await?;
+    if let Ok(CalDavSettings {
+        username: Some(username),
+        ..
+    }) = load_caldav_settings(storage).await
+    {
+        keys.push(caldav::keychain_key(&username));
+    }
+    if let Ok(CardDavSettings {
+        username: Some(username),
+        ..
+    }) = load_carddav_settings(storage).await
+    {
+        keys.push(carddav::keychain_key(&username));
+    }
+    if let Ok(UploadSettings {
+        backend: Some(backend),
+        ..
+    }) = load_upload_settings(storage).await
+    {
+        keys.push(backend.keychain_key());
+    }
+    keys.sort();
+    keys.dedup();
+    Ok(keys)
+}
+
+#[derive(Serialize)]
+struct WipeReport {
+    removed_files: Vec<String>,
+    keychain_entries_removed: usize,
+    /// CalDAV, CardDAV, and upload backend secrets.
+    service_secrets_removed: usize,
+    models_removed: bool,
+}
+
+/// Erases everything this machine holds for the app: sync jobs are stopped,
+/// account and service secrets removed from the keychain, then the
+/// database, WAL, backups, cached avatars, saved attachments, and master
+/// key are shredded, and optionally the downloaded models. The app should be restarted afterwards.
+#[tauri::command]
+async fn wipe_local_data(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    confirm_token: String,
+    include_models: Option<bool>,
+) -> Result<WipeReport, String> {
+    let stored = state
+        .storage
+        .get_setting(WIPE_TOKEN_SETTING_KEY)
+        .await
+        .map_err(|err| err.to_string())?;
+    let valid = stored
+        .as_deref()
+        .and_then(|value| value.rsplit_once(':'))
+        .map(|(token, expires_at)| {
+            token == confirm_token.trim()
+                && expires_at
+                    .parse::<i64>()
+                    .map(|expires_at| expires_at >= Utc::now().timestamp())
+                    .unwrap_or(false)
+        })
+        .unwrap_or(false);
+    if !valid {
+        return Err("Wipe confirmation token is invalid or expired".into());
+    }
+
+    emit_wipe_progress(&app, "stopping-sync", Value::Null);
+    {
+        let mut jobs = state.sync_jobs.write().await;
+        for (_, job) in jobs.drain() {
+            job.cancel.cancel();
+            job.handle.abort();
+        }
+    }
+
+    // Keychain entries are keyed by account and by service settings, so
+    // collect them before the database disappears.
+    emit_wipe_progress(&app, "keychain", Value::Null);
+    let mut emails = state
+        .storage
+        .list_accounts()
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|record| record.email)
+        .collect::<Vec<_>>();
+    emails.extend(state.accounts.write().await.drain().map(|(email, _)| email));
+    emails.sort();
+    emails.dedup();
+
+    let mut keychain_entries_removed = 0usize;
+    for email in &emails {
+        match delete_password_from_keychain(email) {
+            Ok(()) => keychain_entries_removed += 1,
+            Err(err) => warn!(%email, ?err, "failed to delete keychain password during wipe"),
+        }
+    }
+    let mut service_secrets_removed = 0usize;
+    for key in service_secret_keys(&state.storage).await? {
+        match delete_password_from_keychain(&key) {
+            Ok(()) => service_secrets_removed += 1,
+            Err(err) => warn!(%key, ?err, "failed to delete service secret during wipe"),
+        }
+    }
+
+    emit_wipe_progress(&app, "database", Value::Null);
+    let data_path = data_dir::resolve(&app).map_err(|err| err.to_string())?;
+    let attachments =
+        data_dir::store_dir(&app, Store::Attachments).map_err(|err| err.to_string())?;
+    let stores = vec![data_path.join(avatars::CACHE_DIR), attachments];
+    let removed = state
+        .storage
+        .wipe_files(stores)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut models_removed = false;
+    if include_models.unwrap_or(false) {
+        emit_wipe_progress(&app, "models", Value::Null);
+        state.llm.set_model_path(None)?;
+        let models_dir = models_directory(&app)?;
+        match fs::remove_dir_all(&models_dir).await {
+            Ok(()) => models_removed = true,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(format!("Failed to remove models: {err}")),
+        }
+    }
+
+    let removed_files = removed
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>();
+    emit_wipe_progress(&app, "done", json!({ "removedFiles": removed_files.len() }));
+    info!(
+        files = removed_files.len(),
+        keychain_entries_removed, service_secrets_removed, "local data wiped"
+    );
+
+    Ok(WipeReport {
+        removed_files,
+        keychain_entries_removed,
+        service_secrets_removed,
+        models_removed,
+    })
+}
+
+#[tauri::command]
+async fn get_data_directory(app: tauri::AppHandle) -> Result<DataDirectoryInfo, String> {
+    data_dir::info(&app).map_err(|err| err.to_string())
+}
 
-    let server = warp::serve(routes).bind(([127, 0, 0, 1], 8080));
+/// Moves the database, key, models, and backups to `path`, an empty folder,
+/// and restarts into it. The old copy is only removed once the new one has
+/// opened.
+#[tauri::command]
+async fn set_data_directory(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<DataDirectoryInfo, String> {
+    let info = data_dir::info(&app).map_err(|err| err.to_string())?;
+    if info.portable {
+        return Err("Portable mode is on; turn it off to choose a data folder".into());
+    }
+    let target = expand_path(path.trim())?;
+    relocate_data(&state, &info, &target).await?;
+    data_dir::set_location(&app, &target, &info.path).map_err(|err| err.to_string())?;
+    finish_data_move(app)
+}
 
-    tokio::spawn(async move {
-        let _ = server.await;
-    });
+/// Portable mode keeps all data in a `data` folder next to the executable,
+/// so the app can run from a USB stick. Turning it off moves data back to
+/// the folder used before.
+#[tauri::command]
+async fn set_portable_mode(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<DataDirectoryInfo, String> {
+    let info = data_dir::info(&app).map_err(|err| err.to_string())?;
+    if info.portable == enabled {
+        return Ok(info);
+    }
+    let target = if enabled {
+        info.portable_path
+            .clone()
+            .ok_or_else(|| "Executable folder not found".to_string())?
+    } else {
+        data_dir::configured_dir(&app).map_err(|err| err.to_string())?
+    };
+    relocate_data(&state, &info, &target).await?;
+    data_dir::set_portable(&app, enabled, &info.path).map_err(|err| err.to_string())?;
+    finish_data_move(app)
+}
 
-    // Wait for the code
-    let code = loop {
-        time::sleep(time::Duration::from_millis(100)).await;
-        if let Some(c) = code_shared.lock().unwrap().take() {
-            break c;
+/// Stops background jobs and copies everything to `target`. Nothing points
+/// at the copy yet, so a failure here leaves the app as it was.
+async fn relocate_data(
+    state: &State<'_, AppState>,
+    info: &DataDirectoryInfo,
+    target: &Path,
+) -> Result<(), String> {
+    let current = info.path.as_path();
+    data_dir::validate_target(current, target, &info.default_path)?;
+    {
+        let mut jobs = state.sync_jobs.write().await;
+        for (_, job) in jobs.drain() {
+            job.cancel.cancel();
+            job.handle.abort();
         }
-    };
+    }
 
-    let token = client
-        .exchange_code(AuthorizationCode::new(code))
-        .set_pkce_verifier(pkce_verifier)
-        .request_async(oauth2::reqwest::async_http_client)
+    fs::create_dir_all(target)
         .await
-        .map_err(|e| format!("Token exchange failed: {}", e))?;
-
-    Ok(token.access_token().secret().clone())
-}
+        .map_err(|err| format!("Cannot create {}: {err}", target.display()))?;
+    let database = target.join(data_dir::DATABASE_FILE);
+    state
+        .storage
+        .snapshot_to(database.clone())
+        .await
+        .map_err(|err| err.to_string())?;
 
-fn provider_error_to_message(error: ProviderError) -> String {
-    match error {
-        ProviderError::Authentication(message) => message,
-        ProviderError::Network(message) => format!("Network error: {message}"),
-        ProviderError::Imap(message) => format!("IMAP error: {message}"),
-        ProviderError::Other(message) => message,
+    let from = current.to_path_buf();
+    let to = target.to_path_buf();
+    let copied =
+        tauri::async_runtime::spawn_blocking(move || data_dir::copy_managed_entries(&from, &to))
+            .await
+            .map_err(|err| err.to_string())?;
+    match copied {
+        Ok(bytes) => {
+            info!(target = %target.display(), bytes, "data directory copied");
+            Ok(())
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&database).await;
+            Err(format!("Failed to copy data: {err}"))
+        }
     }
 }
 
-async fn perform_incremental_sync(
-    storage: &Storage,
-    credentials: &Credentials,
-    account_email: &str,
-    limit: usize,
-) -> Result<(), ProviderError> {
-    let summaries = providers::fetch_recent(credentials, limit).await?;
+#[tauri::command]
+async fn get_storage_locations(app: tauri::AppHandle) -> Result<Vec<StoreInfo>, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        [Store::Models, Store::Attachments]
+            .into_iter()
+            .map(|store| data_dir::store_info(&app, store))
+            .collect::<std::io::Result<Vec<_>>>()
+    })
+    .await
+    .map_err(|err| err.to_string())?
+    .map_err(|err| err.to_string())
+}
 
-    if summaries.is_empty() {
-        return Ok(());
+/// Moves models or attachments to `path`, or back into the data folder when
+/// `path` is empty. The active model is unloaded during the move and
+/// reloaded from its new location.
+#[tauri::command]
+async fn set_storage_location(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    store: Store,
+    path: Option<String>,
+) -> Result<StoreInfo, String> {
+    let current = data_dir::store_dir(&app, store).map_err(|err| err.to_string())?;
+    let requested = path
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty());
+    let target = match requested {
+        Some(value) => expand_path(value)?,
+        None => data_dir::resolve(&app)
+            .map_err(|err| err.to_string())?
+            .join(store.dir_name()),
+    };
+    if target == current {
+        return data_dir::store_info(&app, store).map_err(|err| err.to_string());
     }
 
-    let mut inserts = Vec::with_capacity(summaries.len());
-    let mut analyses = Vec::with_capacity(summaries.len());
+    let needed = data_dir::dir_size(&current);
+    data_dir::validate_store_target(&current, &target, needed)?;
 
-    for summary in &summaries {
-        let (insert, analysis) = build_records(account_email, summary, None, None, None);
-        inserts.push(insert);
-        analyses.push(analysis);
+    let active_model = state
+        .llm
+        .configured_path()
+        .filter(|path| store == Store::Models && path.starts_with(&current));
+    if active_model.is_some() {
+        state.llm.set_model_path(None)?;
     }
 
-    if let Err(err) = storage.upsert_messages(inserts).await {
-        error!(account = %account_email, ?err, "failed to persist messages during periodic sync");
-    }
+    let from = current.clone();
+    let to = target.clone();
+    let moved = tauri::async_runtime::spawn_blocking(move || data_dir::move_store(&from, &to))
+        .await
+        .map_err(|err| err.to_string())?;
+    let result = moved
+        .map_err(|err| format!("Failed to move {}: {err}", store.dir_name()))
+        .and_then(|bytes| {
+            let custom = requested.is_some().then_some(target.as_path());
+            data_dir::set_store_location(&app, store, custom).map_err(|err| err.to_string())?;
+            info!(store = store.dir_name(), bytes, target = %target.display(), "store moved");
+            Ok(())
+        });
 
-    if let Err(err) = storage.upsert_analysis(analyses).await {
-        error!(account = %account_email, ?err, "failed to persist analysis during periodic sync");
+    // Reload from wherever the files ended up, even if the move failed.
+    if let Some(previous) = active_model {
+        let location = data_dir::store_dir(&app, store).map_err(|err| err.to_string())?;
+        let reloaded = previous
+            .strip_prefix(&current)
+            .map(|relative| location.join(relative))
+            .unwrap_or(previous);
+        if let Err(err) = state.llm.set_model_path(Some(reloaded)) {
+            warn!(?err, "failed to reload model after moving models folder");
+        }
     }
 
-    Ok(())
+    result?;
+    data_dir::store_info(&app, store).map_err(|err| err.to_string())
 }
 
-fn build_records(
-    account_email: &str,
-    summary: &EmailSummary,
-    snippet: Option<String>,
-    body: Option<Vec<u8>>,
-    flags: Option<&[String]>,
-) -> (MessageInsert, AnalysisInsert) {
-    let flags_string = flags.and_then(|values| {
-        if values.is_empty() {
-            None
-        } else {
-            Some(values.join(" "))
-        }
+/// Restarts shortly after returning so the UI receives the new location.
+fn finish_data_move(app: tauri::AppHandle) -> Result<DataDirectoryInfo, String> {
+    let info = data_dir::info(&app).map_err(|err| err.to_string())?;
+    tauri::async_runtime::spawn(async move {
+        time::sleep(Duration::from_millis(500)).await;
+        app.restart();
     });
+    Ok(info)
+}
 
-    let display_name = summary
-        .sender
-        .display_name
-        .clone()
-        .unwrap_or_else(|| summary.sender.email.clone());
-
-    let snippet_clone = snippet.clone();
-    let (analysis_summary, analysis_sentiment, categories) =
-        analyze_message(&summary.subject, snippet_clone.as_deref());
-
-    let insert = MessageInsert {
-        account_email: account_email.to_string(),
-        uid: summary.uid.clone(),
-        sender_display: display_name,
-        sender_email: summary.sender.email.clone(),
-        subject: summary.subject.clone(),
-        date: summary.date.clone(),
-        snippet,
-        body,
-        flags: flags_string,
-    };
+/// Preferences a settings bundle carries. Anything not listed stays on the
+/// machine that wrote it: model paths and quotas, health and review
+/// timestamps, tokens, demo data, and auto-reply state.
+const PORTABLE_SETTING_KEYS: &[&str] = &[
+    LLM_REDACT_PROMPTS_SETTING_KEY,
+    LLM_BACKEND_SETTING_KEY,
+    MODEL_DOWNLOAD_SETTING_KEY,
+    STRIP_TRACKERS_SETTING_KEY,
+    TOMBSTONE_RETENTION_SETTING_KEY,
+    send_guard::SETTING_KEY,
+    focus::SETTING_KEY,
+    draft_stats::SETTING_KEY,
+    avatars::SETTING_KEY,
+    caldav::SETTING_KEY,
+    carddav::SETTING_KEY,
+    flag_sync::SETTING_KEY,
+    llm_policy::SETTING_KEY,
+    llm_mock::SETTING_KEY,
+    uploads::SETTING_KEY,
+    otp::SETTING_KEY,
+    breach::SETTING_KEY,
+];
 
-    let analysis = AnalysisInsert {
-        account_email: account_email.to_string(),
-        uid: summary.uid.clone(),
-        summary: analysis_summary,
-        sentiment: analysis_sentiment,
-        categories,
-        metadata_json: Value::Null,
-        model_id: None,
-        analyzed: false,
-        analyzed_at: None,
-        analysis_confidence: None,
-        validation: AnalysisValidation::default(),
-    };
+fn is_portable_setting(key: &str) -> bool {
+    PORTABLE_SETTING_KEYS.contains(&key) || key.starts_with("lite_sync:")
+}
 
-    (insert, analysis)
+#[derive(Serialize)]
+struct SettingsTransferSummary {
+    accounts: usize,
+    sender_rules: usize,
+    templates: usize,
+    preferences: usize,
 }
 
-fn analyze_message(
-    subject: &str,
-    snippet: Option<&str>,
-) -> (Option<String>, Option<String>, Vec<String>) {
-    const POSITIVE_WORDS: &[&str] = &[
-        "thanks",
-        "thank you",
-        "appreciate",
-        "great",
-        "success",
-        "approved",
-    ];
-    const NEGATIVE_WORDS: &[&str] = &["issue", "urgent", "problem", "failed", "declined", "error"];
+/// Writes accounts (without passwords), sender rules, templates, and
+/// preferences to a signed JSON file. A passphrase, if given, is needed to
+/// import the file again.
+#[tauri::command]
+async fn export_settings(
+    state: State<'_, AppState>,
+    path: String,
+    passphrase: Option<String>,
+) -> Result<SettingsTransferSummary, String> {
+    let target = expand_path(&path)?;
+    let storage = &state.storage;
 
-    let snippet_text = snippet.unwrap_or("");
-    let combined = format!("{} {}", subject, snippet_text).to_lowercase();
+    let accounts = storage
+        .list_accounts()
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|record| BundleAccount {
+            email: record.email,
+            provider: record.provider,
+            display_name: None,
+            custom_host: record.custom_host,
+            custom_port: record.custom_port,
+            oauth_client_id: record.oauth_client_id,
+        })
+        .collect::<Vec<_>>();
+    let sender_rules = storage
+        .list_statuses()
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        // Imported rules are re-imported from their list, not carried along.
+        .filter(|rule| {
+            rule.status != SenderStatus::Neutral.as_str() && rule.origin == MANUAL_ORIGIN
+        })
+        .map(|rule| BundleSenderRule {
+            sender_email: rule.sender_email,
+            status: rule.status,
+            scope: (rule.scope != GLOBAL_SCOPE).then_some(rule.scope),
+        })
+        .collect::<Vec<_>>();
+    let templates = storage
+        .list_templates()
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|template| BundleTemplate {
+            name: template.name,
+            subject: template.subject,
+            body: template.body,
+        })
+        .collect::<Vec<_>>();
+    let preferences = storage
+        .list_settings()
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .filter(|(key, _)| is_portable_setting(key))
+        .collect();
 
-    let base_text = if snippet_text.trim().is_empty() {
-        subject.trim()
-    } else {
-        snippet_text.trim()
+    let payload = SettingsPayload {
+        exported_at: Utc::now().timestamp(),
+        accounts,
+        sender_rules,
+        templates,
+        preferences,
     };
-
-    let summary = if base_text.is_empty() {
-        None
-    } else {
-        let mut truncated = base_text.chars().take(200).collect::<String>();
-        if base_text.len() > truncated.len() {
-            truncated.push('…');
-        }
-        Some(truncated)
+    let summary = SettingsTransferSummary {
+        accounts: payload.accounts.len(),
+        sender_rules: payload.sender_rules.len(),
+        templates: payload.templates.len(),
+        preferences: payload.preferences.len(),
     };
 
-    let mut score = 0i32;
-    for word in POSITIVE_WORDS {
-        if combined.contains(word) {
-            score += 1;
-        }
+    let bundle = settings_bundle::sign(payload, passphrase.as_deref())?;
+    let json = serde_json::to_vec_pretty(&bundle).map_err(|err| err.to_string())?;
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|err| err.to_string())?;
     }
-    for word in NEGATIVE_WORDS {
-        if combined.contains(word) {
-            score -= 1;
-        }
+    fs::write(&target, json)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(summary)
+}
+
+/// Applies a bundle written by [`export_settings`]. Accounts come back
+/// without passwords, so each one must be reconnected; templates with the
+/// same name are overwritten.
+#[tauri::command]
+async fn import_settings(
+    state: State<'_, AppState>,
+    path: String,
+    passphrase: Option<String>,
+) -> Result<SettingsTransferSummary, String> {
+    let source = expand_path(&path)?;
+    let raw = fs::read(&source).await.map_err(|err| err.to_string())?;
+    let bundle: SignedBundle =
+        serde_json::from_slice(&raw).map_err(|err| format!("Invalid settings file: {err}"))?;
+    let payload = settings_bundle::verify(bundle, passphrase.as_deref())?;
+    let storage = &state.storage;
+
+    for account in &payload.accounts {
+        storage
+            .upsert_account(&Account {
+                provider: account.provider,
+                email: account.email.trim().to_lowercase(),
+                display_name: account.display_name.clone(),
+                custom_host: account.custom_host.clone(),
+                custom_port: account.custom_port,
+                oauth_client_id: account.oauth_client_id.clone(),
+            })
+            .await
+            .map_err(|err| err.to_string())?;
     }
 
-    let sentiment = if score > 1 {
-        Some("positive".to_string())
-    } else if score < -1 {
-        Some("negative".to_string())
-    } else if combined.trim().is_empty() {
-        None
-    } else {
-        Some("neutral".to_string())
-    };
+    for rule in &payload.sender_rules {
+        storage
+            .update_sender_status(
+                &rule.sender_email.trim().to_lowercase(),
+                rule.scope.as_deref().unwrap_or(GLOBAL_SCOPE),
+                SenderStatus::from_str(&rule.status),
+            )
+            .await
+            .map_err(|err| err.to_string())?;
+    }
 
-    let mut categories = Vec::new();
-    if combined.contains("invoice") || combined.contains("receipt") || combined.contains("payment")
-    {
-        categories.push("billing".to_string());
+    let existing = storage
+        .list_templates()
+        .await
+        .map_err(|err| err.to_string())?
+        .into_iter()
+        .map(|template| (template.name.to_lowercase(), template.id))
+        .collect::<HashMap<_, _>>();
+    for template in &payload.templates {
+        storage
+            .save_template(
+                existing.get(&template.name.to_lowercase()).copied(),
+                &template.name,
+                &template.subject,
+                &template.body,
+            )
+            .await
+            .map_err(|err| err.to_string())?;
     }
-    if combined.contains("alert") || combined.contains("warning") || combined.contains("security") {
-        categories.push("alert".to_string());
+
+    let mut preferences = 0usize;
+    for (key, value) in &payload.preferences {
+        if !is_portable_setting(key) {
+            continue;
+        }
+        storage
+            .set_setting(key, Some(value))
+            .await
+            .map_err(|err| err.to_string())?;
+        preferences += 1;
     }
-    if combined.contains("newsletter")
-        || combined.contains("subscribe")
-        || combined.contains("update")
+
+    let summary = SettingsTransferSummary {
+        accounts: payload.accounts.len(),
+        sender_rules: payload.sender_rules.len(),
+        templates: payload.templates.len(),
+        preferences,
+    };
+    if let Err(err) = storage
+        .record_audit(
+            None,
+            "import_settings",
+            Some(&source.display().to_string()),
+            json!({
+                "accounts": summary.accounts,
+                "senderRules": summary.sender_rules,
+                "templates": summary.templates,
+                "preferences": summary.preferences,
+            }),
+        )
+        .await
     {
-        categories.push("newsletter".to_string());
+        warn!(?err, "failed to record settings import in audit log");
     }
-    if combined.contains("meeting")
-        || combined.contains("schedule")
-        || combined.contains("calendar")
-    {
-        categories.push("calendar".to_string());
+
+    Ok(summary)
+}
+
+/// Recomputes and stores interaction stats for one contact. Unless
+/// `refresh` is false, the Sent folders of connected accounts are synced
+/// first so the user's replies are counted.
+#[tauri::command]
+async fn get_contact_insights(
+    state: State<'_, AppState>,
+    email: String,
+    refresh: Option<bool>,
+) -> Result<RelationshipStats, String> {
+    let contact = email.trim().to_lowercase();
+    if refresh.unwrap_or(true) {
+        let connected = state
+            .accounts
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        for credentials in &connected {
+            sync_sent_headers(&state.storage, credentials).await;
+        }
     }
-    categories.sort();
-    categories.dedup();
+    let messages = state
+        .storage
+        .contact_messages(&contact)
+        .await
+        .map_err(|err| err.to_string())?;
+    let sent = state
+        .storage
+        .sent_to_contact(&contact)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let stats = relationships::summarize(&contact, &messages, &sent);
+    state
+        .storage
+        .save_relationship_stats(&stats)
+        .await
+        .map_err(|err| err.to_string())?;
 
-    (summary, sentiment, categories)
+    Ok(stats)
 }
 
 #[tauri::command]
-async fn get_llm_status(state: State<'_, AppState>) -> Result<LlmStatus, String> {
-    Ok(state.llm.status())
+async fn get_sender_profile(
+    state: State<'_, AppState>,
+    sender_email: String,
+) -> Result<Option<SenderProfile>, String> {
+    state
+        .storage
+        .sender_profile(sender_email.trim())
+        .await
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
-async fn list_known_llm_models(
-    app: tauri::AppHandle,
-    state: State<'_, AppState>,
-) -> Result<Vec<KnownModelResponse>, String> {
-    let models_dir = models_directory(&app)?;
-    fs::create_dir_all(&models_dir)
+async fn refresh_sender_profiles(state: State<'_, AppState>) -> Result<usize, String> {
+    state
+        .storage
+        .refresh_sender_profiles(SENDER_PROFILE_MIN_SAMPLES)
         .await
-        .map_err(|err| format!("failed to prepare models directory: {err}"))?;
-
-    let active_path = state.llm.configured_path();
-    let mut responses = Vec::new();
-
-    for model in KNOWN_MODELS {
-        let candidate = models_dir.join(model.filename);
-        let metadata = fs::metadata(&candidate).await.ok();
-        let downloaded = metadata.is_some();
-        let installed_size_bytes = metadata.as_ref().map(|entry| entry.len());
-        let active = active_path
-            .as_ref()
-            .map(|path| path == &candidate)
-            .unwrap_or(false);
-
-        responses.push(KnownModelResponse {
-            id: model.id,
-            name: model.display_name,
-            description: model.description,
-            filename: model.filename,
-            url: model.download_url,
-            size_bytes: model.estimated_size_bytes,
-            recommended_ram_gb: model.recommended_ram_gb,
-            context_length: model.context_length,
-            notes: model.notes,
-            is_default: model.is_default,
-            downloaded,
-            active,
-            installed_size_bytes,
-        });
-    }
-
-    Ok(responses)
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
-async fn set_llm_model_path(
-    app: tauri::AppHandle,
+async fn accept_review_item(
     state: State<'_, AppState>,
-    path: Option<String>,
-) -> Result<LlmStatus, String> {
-    let models_dir = models_directory(&app)?;
-    fs::create_dir_all(&models_dir)
+    email: String,
+    uid: String,
+) -> Result<bool, String> {
+    let normalized_email = normalize_email(&email);
+    state
+        .storage
+        .resolve_analysis_review(&normalized_email, "INBOX", uid_arg(&uid)?, None)
         .await
-        .map_err(|err| format!("failed to prepare models directory: {err}"))?;
-
-    match path {
-        Some(path_str) => {
-            let expanded = expand_path(&path_str)?;
-            let candidate = if expanded.is_absolute() {
-                expanded.clone()
-            } else {
-                models_dir.join(&expanded)
-            };
-
-            let metadata = fs::metadata(&candidate)
-                .await
-                .map_err(|_| format!("model file not found at {}", candidate.display()))?;
-            if !metadata.is_file() {
-                return Err(format!("model path is not a file: {}", candidate.display()));
-            }
-
-            state
-                .llm
-                .set_model_path(Some(candidate.clone()))
-                .map_err(|err| err)?;
-
-            let stored_value = if candidate.starts_with(&models_dir) {
-                candidate
-                    .strip_prefix(&models_dir)
-                    .ok()
-                    .map(|p| {
-                        p.to_string_lossy()
-                            .trim_start_matches(std::path::MAIN_SEPARATOR)
-                            .to_string()
-                    })
-                    .filter(|s| !s.is_empty())
-                    .unwrap_or_else(|| candidate.to_string_lossy().to_string())
-            } else if expanded.is_relative() {
-                expanded.to_string_lossy().to_string()
-            } else {
-                candidate.to_string_lossy().to_string()
-            };
-
-            state
-                .storage
-                .set_setting(LLM_MODEL_SETTING_KEY, Some(&stored_value))
-                .await
-                .map_err(|err| err.to_string())?;
-        }
-        None => {
-            state.llm.set_model_path(None).map_err(|err| err)?;
-            state
-                .storage
-                .set_setting(LLM_MODEL_SETTING_KEY, None)
-                .await
-                .map_err(|err| err.to_string())?;
-        }
-    }
-
-    Ok(state.llm.status())
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
-async fn download_default_llm_model(
-    app: tauri::AppHandle,
+async fn correct_review_item(
     state: State<'_, AppState>,
-) -> Result<LlmStatus, String> {
-    let model = known_model_by_id(DEFAULT_LLM_MODEL_ID)
-        .ok_or_else(|| "default model metadata not available".to_string())?;
-    ensure_model_downloaded(&app, model, false).await?;
-    set_llm_model_path(app, state, Some(model.filename.to_string())).await
+    email: String,
+    uid: String,
+    tags: Vec<String>,
+    priority: Option<String>,
+    sentiment: Option<String>,
+) -> Result<bool, String> {
+    apply_analysis_correction(state.inner(), &email, &uid, Some(tags), priority, sentiment).await
 }
 
+/// Applies a user fix to a tag, priority, or sentiment from anywhere in the UI.
+/// Every correction becomes a few-shot example for later bulk runs.
 #[tauri::command]
-async fn download_llm_model(
-    app: tauri::AppHandle,
+async fn correct_analysis(
     state: State<'_, AppState>,
-    model_id: String,
-    activate: Option<bool>,
-    force: Option<bool>,
-) -> Result<LlmStatus, String> {
-    let model =
-        known_model_by_id(&model_id).ok_or_else(|| format!("unknown model id: {model_id}"))?;
-    ensure_model_downloaded(&app, model, force.unwrap_or(false)).await?;
-
-    if activate.unwrap_or(false) {
-        set_llm_model_path(app, state, Some(model.filename.to_string())).await
-    } else {
-        Ok(state.llm.status())
+    email: String,
+    uid: String,
+    tags: Option<Vec<String>>,
+    priority: Option<String>,
+    sentiment: Option<String>,
+) -> Result<bool, String> {
+    if tags.is_none() && priority.is_none() && sentiment.is_none() {
+        return Err("Provide at least one of tags, priority, or sentiment".into());
     }
+    apply_analysis_correction(state.inner(), &email, &uid, tags, priority, sentiment).await
 }
 
-#[tauri::command]
-async fn analyze_with_llm(
-    state: State<'_, AppState>,
-    prompt: String,
-    max_tokens: Option<usize>,
-) -> Result<String, String> {
-    state.llm.analyze_prompt(prompt, max_tokens).await
+async fn apply_analysis_correction(
+    state: &AppState,
+    email: &str,
+    uid: &str,
+    tags: Option<Vec<String>>,
+    priority: Option<String>,
+    sentiment: Option<String>,
+) -> Result<bool, String> {
+    let normalized_email = normalize_email(email);
+
+    let categories = tags.map(|tags| {
+        let mut categories = tags
+            .iter()
+            .map(|tag| tag.trim().to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect::<Vec<_>>();
+        categories.sort();
+        categories.dedup();
+        categories
+    });
+
+    let priority = match priority.as_deref() {
+        Some(value) => Some(
+            sanitize_enum_value(Some(value), BULK_PRIORITY_VALUES)
+                .ok_or_else(|| format!("Unsupported priority '{value}'"))?,
+        ),
+        None => None,
+    };
+    let sentiment = match sentiment.as_deref() {
+        Some(value) => Some(
+            sanitize_sentiment(Some(value))
+                .ok_or_else(|| format!("Unsupported sentiment '{value}'"))?,
+        ),
+        None => None,
+    };
+
+    let correction = AnalysisCorrection {
+        categories,
+        priority,
+        sentiment,
+    };
+
+    state
+        .storage
+        .resolve_analysis_review(&normalized_email, "INBOX", uid_arg(uid)?, Some(correction))
+        .await
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
-async fn start_bulk_analysis(
+async fn analyze_missing(
     app: tauri::AppHandle,
     state: State<'_, AppState>,
-    allowed_tags: Vec<String>,
+    email: String,
+    allowed_tags: Option<Vec<String>>,
     max_tokens: Option<usize>,
     snippet_limit: Option<usize>,
-    force: Option<bool>,
-    model_id: Option<String>,
+    full_body: Option<bool>,
     validator_model_id: Option<String>,
 ) -> Result<String, String> {
+    let normalized_email = normalize_email(&email);
+    if normalized_email.is_empty() {
+        return Err("Account email is required".into());
+    }
+
     let run_id = Uuid::new_v4().to_string();
     let run_id_clone = run_id.clone();
-    let max_tokens = max_tokens.unwrap_or(512);
-    let snippet_limit = snippet_limit.unwrap_or(2048);
-    let force = force.unwrap_or(false);
+    let allowed_tags = allowed_tags
+        .filter(|tags| !tags.is_empty())
+        .unwrap_or_else(|| DEFAULT_BULK_TAGS.iter().map(|tag| tag.to_string()).collect());
+    let max_tokens = max_tokens.unwrap_or(DEFAULT_BULK_COMPLETION_TOKENS);
+    let snippet_limit = snippet_limit.unwrap_or(DEFAULT_BULK_SNIPPET_CHARS);
+    let model_id = infer_model_id_from_status(&state.llm.status());
 
     let storage = state.storage.clone();
     let llm = state.llm.clone();
+    let accounts = state.accounts.read().await.clone();
+    let report: ProgressSink = Arc::new(move |progress| events::emit(&app, progress));
 
     tauri::async_runtime::spawn(async move {
         if let Err(err) = execute_bulk_analysis(
-            app,
+            report,
+            accounts,
             storage,
             llm,
             run_id_clone,
             allowed_tags,
             max_tokens,
             snippet_limit,
-            force,
+            full_body.unwrap_or(false),
+            BulkTarget::Uncovered,
+            true,
+            Some(normalized_email),
             model_id,
             validator_model_id,
         )
         .await
         {
-            error!(?err, "bulk analysis failed");
+            error!(?err, "scoped bulk analysis for missing coverage failed");
         }
     });
 
@@ -2874,15 +10747,13 @@ fn main() {
             let storage = Storage::initialize(&app.app_handle())
                 .map_err(|err| -> Box<dyn std::error::Error> { Box::new(err) })?;
 
-            let data_dir = app.path_resolver().app_data_dir().ok_or_else(
-                || -> Box<dyn std::error::Error> {
-                    Box::new(std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        "App data directory not available",
-                    ))
-                },
-            )?;
-            let models_dir = data_dir.join("models");
+            match data_dir::finish_relocation(&app.app_handle()) {
+                Ok(Some(previous)) => info!(?previous, "removed data left by relocation"),
+                Ok(None) => {}
+                Err(err) => warn!(?err, "failed to clean up previous data directory"),
+            }
+
+            let models_dir = data_dir::store_dir(&app.app_handle(), Store::Models)?;
             std::fs::create_dir_all(&models_dir)?;
 
             let llm_service = LlmService::new();
@@ -2907,17 +10778,112 @@ fn main() {
                 }
             }
 
+            let redact_prompts = tauri::async_runtime::block_on(async {
+                storage
+                    .get_setting(LLM_REDACT_PROMPTS_SETTING_KEY)
+                    .await
+                    .map_err(|err| -> Box<dyn std::error::Error> { Box::new(err) })
+            })?;
+            llm_service.set_redact_prompts(redact_prompts.as_deref() == Some("true"));
+            let stored_backend = tauri::async_runtime::block_on(async {
+                storage
+                    .get_setting(LLM_BACKEND_SETTING_KEY)
+                    .await
+                    .map_err(|err| -> Box<dyn std::error::Error> { Box::new(err) })
+            })?;
+            llm_service.set_backend(
+                LlmBackend::from_env()
+                    .or_else(|| stored_backend.as_deref().and_then(LlmBackend::parse))
+                    .unwrap_or_default(),
+            );
+            match tauri::async_runtime::block_on(load_mock_rules(&storage))
+                .and_then(|rules| MockResponses::new(&rules))
+            {
+                Ok(responses) => llm_service.set_mock_responses(responses),
+                Err(err) => warn!(%err, "failed to load mock LLM responses; using built-in ones"),
+            }
+            match tauri::async_runtime::block_on(load_llm_privacy_policy(&storage)) {
+                Ok(policy) => llm_service.set_privacy_policy(policy),
+                Err(err) => warn!(%err, "failed to load LLM privacy policy; using defaults"),
+            }
+
             app.manage(AppState::new(
                 app.app_handle(),
                 storage.clone(),
                 llm_service,
             ));
+
+            tauri::async_runtime::spawn(purge_tombstones_periodically(storage.clone()));
+            tauri::async_runtime::spawn(expire_sender_mutes_periodically(
+                app.app_handle(),
+                storage.clone(),
+            ));
+            tauri::async_runtime::spawn(focus_reviews_periodically(
+                app.app_handle(),
+                storage.clone(),
+            ));
+            tauri::async_runtime::spawn(expire_one_time_codes_periodically(storage.clone()));
+            tauri::async_runtime::spawn(drain_outbox_periodically(app.app_handle()));
+
+            let app_handle = app.app_handle();
+            tauri::async_runtime::spawn(async move {
+                check_storage_health(&app_handle, &storage).await;
+            });
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             connect_account,
             connect_account_saved,
+            connect_demo_account,
             test_account_connection,
+            preflight_account,
+            diagnose_connection,
+            get_imap_health,
+            get_folder_tree,
+            create_folder,
+            rename_folder,
+            delete_folder,
+            set_folder_subscribed,
+            copy_message,
+            migrate_account,
+            cancel_migration,
+            get_migration_status,
+            get_lite_sync,
+            set_lite_sync,
+            get_headers_only,
+            set_headers_only,
+            hydrate_messages,
+            run_ocr,
+            index_attachments,
+            get_message_links,
+            get_link_blocklist,
+            set_link_blocklist,
+            get_message_trackers,
+            get_message_html,
+            allow_images_for_sender,
+            revoke_images_for_sender,
+            get_image_allowlist,
+            export_message_pdf,
+            list_attachments,
+            save_attachment,
+            list_collections,
+            save_collection,
+            delete_collection,
+            add_to_collection,
+            remove_from_collection,
+            get_collection_items,
+            export_collection,
+            get_strip_trackers,
+            set_strip_trackers,
+            get_tombstone_retention_days,
+            set_tombstone_retention_days,
+            get_storage_health,
+            get_data_directory,
+            set_data_directory,
+            set_portable_mode,
+            get_storage_locations,
+            set_storage_location,
+            autodiscover_account,
             list_saved_accounts,
             list_connected_accounts,
             get_saved_password,
@@ -2926,28 +10892,332 @@ fn main() {
             sync_account_window,
             sync_account_incremental,
             list_sender_groups,
+            list_sender_group_headers,
+            list_noisy_senders,
+            query_messages,
+            find_messages_by_size,
+            list_quarantined,
+            release_from_quarantine,
             set_sender_status,
+            mute_sender,
+            mute_thread,
+            unmute_thread,
+            list_muted_threads,
+            list_sender_rules,
+            import_blocklist,
+            remove_imported_blocklist,
+            export_blocklist,
+            import_breach_data,
+            get_breach_check_settings,
+            set_breach_check_settings,
+            get_security_alerts,
+            get_otp_settings,
+            set_otp_settings,
+            list_one_time_codes,
+            check_availability,
+            get_caldav_settings,
+            set_caldav_settings,
+            export_to_caldav,
+            list_caldav_sync_log,
+            get_carddav_settings,
+            set_carddav_settings,
+            sync_carddav,
+            list_contacts,
+            extract_contacts_from_mail,
+            update_contact,
+            set_contact_sync,
+            import_vcards,
+            export_vcards,
+            get_avatar_settings,
+            set_avatar_settings,
+            get_dns_settings,
+            set_dns_settings,
+            get_sender_avatar,
+            get_sender_brands,
             list_recent_messages,
             cached_message_count,
             delete_message,
             purge_sender_messages,
             list_deleted_messages,
+            resolve_message_key,
             restore_deleted_message,
             purge_deleted_message,
             get_remote_delete_metrics,
             set_remote_delete_mode,
             configure_periodic_sync,
             apply_block_filter,
+            simulate_policies,
             disconnect_account,
             oauth,
+            connect_account_oauth,
             get_llm_status,
             list_known_llm_models,
             set_llm_model_path,
+            set_llm_prompt_redaction,
+            get_llm_mock_responses,
+            set_llm_backend,
+            get_llm_privacy_policy,
+            set_llm_privacy_policy,
             download_llm_model,
+            check_llm_model_download,
+            get_models_disk_quota,
+            set_models_disk_quota,
+            get_model_download_settings,
+            set_model_download_settings,
             download_default_llm_model,
             analyze_with_llm,
-            start_bulk_analysis
+            start_bulk_analysis,
+            analysis_coverage,
+            analyze_missing,
+            mark_analyses_stale,
+            list_review_queue,
+            accept_review_item,
+            correct_review_item,
+            correct_analysis,
+            get_sender_profile,
+            refresh_sender_profiles,
+            train_spam_from_junk,
+            benchmark_llm_model,
+            cluster_topics,
+            list_topics,
+            list_topic_messages,
+            get_contact_insights,
+            create_mail_merge,
+            get_mail_merge_status,
+            cancel_mail_merge,
+            list_templates,
+            save_template,
+            delete_template,
+            render_template,
+            suggest_template,
+            suggest_replies,
+            get_autoreply_settings,
+            set_autoreply_settings,
+            list_autoreply_log,
+            forward_as_attachment,
+            review_recipients,
+            suggest_subject,
+            send_message,
+            get_send_policy,
+            set_send_policy,
+            get_upload_settings,
+            set_upload_settings,
+            analyze_draft,
+            rewrite_draft,
+            get_draft_check_settings,
+            set_draft_check_settings,
+            set_message_flags,
+            get_flag_conflict_policies,
+            set_flag_conflict_policies,
+            list_flag_conflicts,
+            list_bounces,
+            bulk_action,
+            get_send_insights,
+            get_inbox_heatmap,
+            get_focus_settings,
+            set_focus_settings,
+            list_focused_messages,
+            list_focus_held,
+            set_message_lifecycle,
+            report_message,
+            list_audit_log,
+            prepare_wipe_local_data,
+            wipe_local_data,
+            export_settings,
+            import_settings
         ])
         .run(tauri::generate_context!())
         .expect("error while running personal mail client application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_llm(rules: &[MockRule]) -> LlmService {
+        let llm = LlmService::new();
+        llm.set_backend(LlmBackend::Mock);
+        llm.set_mock_responses(MockResponses::new(rules).unwrap());
+        llm
+    }
+
+    #[tokio::test]
+    async fn bulk_analysis_runs_the_real_prompts_through_the_mock() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path()).unwrap();
+        let account = "dry-run@example.test";
+        storage
+            .upsert_account(&Account {
+                provider: Provider::Custom,
+                email: account.into(),
+                display_name: None,
+                custom_host: None,
+                custom_port: None,
+                oauth_client_id: None,
+            })
+            .await
+            .unwrap();
+        let message = |uid: u32, subject: &str| MessageInsert {
+            account_email: account.into(),
+            folder: "INBOX".into(),
+            uid,
+            sender_display: "Ann Lee".into(),
+            sender_email: "ann@example.org".into(),
+            subject: subject.into(),
+            date: None,
+            snippet: Some("Numbers attached, let me know what you think.".into()),
+            body: None,
+            flags: None,
+            size: None,
+            dmarc_aligned: None,
+            thread_id: None,
+        };
+        storage
+            .upsert_messages(vec![message(1, "Quarterly numbers"), message(2, "Lunch?")])
+            .await
+            .unwrap();
+        // Keyed on the triage prompt as built, so it only matches if the
+        // subject made it in; its reply needs normalizing.
+        let llm = mock_llm(&[MockRule {
+            pattern: r"(?s)^You are an email triage system.*- Subject: Quarterly numbers\n".into(),
+            response: concat!(
+                r#"Sure! {"summary": "Q3 numbers for review", "sentiment": "POSITIVE", "#,
+                r#""tags": ["Work", "made-up"], "priority": "urgent", "confidence": 3}"#,
+            )
+            .into(),
+        }]);
+
+        let reported = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let log = reported.clone();
+        let report: ProgressSink = Arc::new(move |progress: &BulkAnalysisProgress| {
+            log.lock().push(progress.clone());
+        });
+        execute_bulk_analysis(
+            report,
+            HashMap::new(),
+            storage.clone(),
+            llm,
+            "run".into(),
+            vec!["work".into(), "personal".into()],
+            DEFAULT_BULK_COMPLETION_TOKENS,
+            DEFAULT_BULK_SNIPPET_CHARS,
+            false,
+            BulkTarget::Unanalyzed,
+            false,
+            None,
+            Some(llm_mock::MODEL_ID.into()),
+            Some(llm_mock::MODEL_ID.into()),
+        )
+        .await
+        .unwrap();
+
+        let last = reported.lock().last().cloned().unwrap();
+        assert_eq!(last.status, BulkAnalysisStatus::Completed);
+        assert_eq!((last.total, last.completed, last.failed), (2, 2, 0));
+
+        let analyses = storage
+            .messages_for_analysis(account)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|message| (message.subject, message.existing_analysis))
+            .collect::<HashMap<_, _>>();
+        let numbers = &analyses["Quarterly numbers"];
+        assert_eq!(numbers.categories, ["work"]);
+        assert_eq!(numbers.validation_status.as_deref(), Some("passed"));
+        let metadata = numbers.metadata.as_ref().unwrap();
+        assert_eq!(metadata["summary"], "Q3 numbers for review");
+        assert_eq!(metadata["sentiment"], "positive");
+        assert!(metadata["priority"].is_null());
+        assert_eq!(metadata["confidence"], 1.0);
+
+        // The built-in canned triage reply for everything else.
+        let lunch = &analyses["Lunch?"];
+        assert!(lunch.analyzed);
+        assert!(lunch.categories.is_empty());
+        let metadata = lunch.metadata.as_ref().unwrap();
+        assert_eq!(metadata["priority"], "normal");
+        assert_eq!(metadata["model_id"], llm_mock::MODEL_ID);
+    }
+
+    #[tokio::test]
+    async fn muted_threads_stay_read_and_archived_after_a_manual_sync() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path()).unwrap();
+        let account = "mute@example.test";
+        storage.mute_thread(account, "reply-all").await.unwrap();
+        let reply = EmailSummary {
+            uid: 9,
+            subject: "Re: Re: Offsite".into(),
+            sender: MailAddress {
+                display_name: None,
+                email: "ann@example.org".into(),
+            },
+            date: None,
+            auto_submitted: false,
+            to: Vec::new(),
+            size: None,
+            dmarc_aligned: None,
+            thread_id: Some("reply-all".into()),
+            message_id: None,
+            references: Vec::new(),
+        };
+        // What `run_provider_fetch` stores and then processes. The server
+        // keeps reporting the reply unread until the queued flag is pushed.
+        let sync = || async {
+            let (insert, analysis) = build_records(account, &reply, None, None, Some(&[]));
+            storage
+                .upsert_batch(vec![insert], vec![analysis])
+                .await
+                .unwrap();
+            let muted = mute_thread_arrivals(&storage, account, &[reply.clone()]).await;
+            storage.flush_pending_writes().await.unwrap();
+            muted
+        };
+
+        assert_eq!(sync().await, [9]);
+        assert_eq!(sync().await, [9]);
+        let message = storage
+            .messages_for_analysis(account)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|message| message.uid == 9)
+            .unwrap();
+        let metadata = message.existing_analysis.metadata.unwrap();
+        assert_eq!(metadata["lifecycle"], threads::MUTED_LIFECYCLE);
+        let groups = storage
+            .grouped_messages_for_account(account, &FieldMask::new(None))
+            .await
+            .unwrap();
+        let flags = groups[0].messages[0].flags.as_deref();
+        assert!(flag_sync::same_flags(flags, Some("seen")), "{flags:?}");
+        let queued = storage.pending_flag_changes(account).await.unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(
+            (queued[0].uid, queued[0].flag.as_str(), queued[0].enabled),
+            (9, "seen", true)
+        );
+    }
+
+    #[tokio::test]
+    async fn subject_suggestions_parse_from_the_mock() {
+        let llm = mock_llm(&[]);
+        let raw = llm
+            .analyze_prompt(
+                subject_lines::prompt("Can we move Thursday's review to Friday?"),
+                Some(128),
+                DRAFT_REWRITE_CLASSES,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            subject_lines::parse_candidates(&raw),
+            [
+                "Quick update",
+                "Following up on our conversation",
+                "Next steps"
+            ]
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! Structured queries over cached messages: a composable filter tree, a
+//! sort order, and opaque cursors for keyset pagination. Filters compile to
+//! SQL over `messages m`, `analysis_results ar`, and the sender rule joins
+//! (`ss` global, `sa` account, `sd` domain) the other listings use.
+
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, NaiveDate};
+use rusqlite::types::Value as SqlValue;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+/// SQL function the storage layer registers to match text against the
+/// encrypted subject and snippet, or attachment text.
+pub const TEXT_MATCH_FUNCTION: &str = "message_text_contains";
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+const EFFECTIVE_SENDER_STATUS: &str =
+    "COALESCE(sa.status, NULLIF(ss.status, 'neutral'), sd.status, 'neutral')";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageFilter {
+    All(Vec<MessageFilter>),
+    Any(Vec<MessageFilter>),
+    Not(Box<MessageFilter>),
+    /// An address, or `@example.com` for everyone at a domain.
+    Sender(String),
+    Folder(String),
+    /// An IMAP flag such as `\Seen` or `\Flagged`.
+    Flag(String),
+    /// An analysis category.
+    Tag(String),
+    /// Sent at or after this instant (RFC 3339 or `YYYY-MM-DD`).
+    After(String),
+    /// Sent before this instant.
+    Before(String),
+    /// Case-insensitive match in the subject, the snippet, or the text of
+    /// indexed PDF and Office attachments.
+    Text(String),
+    Sentiment(String),
+    /// Effective block/allow status: `allowed`, `blocked`, or `neutral`.
+    SenderStatus(String),
+    Analyzed(bool),
+    ValidationStatus(String),
+    MinConfidence(f64),
+    MinSpamScore(f64),
+    MaxSpamScore(f64),
+    BodyCached(bool),
+    AttachmentsIndexed(bool),
+    /// `RFC822.SIZE` above this many bytes, as IMAP `LARGER`. Messages
+    /// whose size is not yet recorded never match.
+    LargerThan(u64),
+    /// `RFC822.SIZE` below this many bytes, as IMAP `SMALLER`.
+    SmallerThan(u64),
+}
+
+/// Every order ends with the row id, so ties keep a stable position
+/// between pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MessageSort {
+    #[default]
+    DateDesc,
+    DateAsc,
+    Sender,
+    SpamScoreDesc,
+    SizeDesc,
+}
+
+impl MessageSort {
+    fn key(self) -> &'static str {
+        match self {
+            MessageSort::DateDesc | MessageSort::DateAsc => "COALESCE(m.date_ts, 0)",
+            MessageSort::Sender => "m.sender_email",
+            MessageSort::SpamScoreDesc => "COALESCE(m.spam_score, -1.0)",
+            MessageSort::SizeDesc => "COALESCE(m.size_bytes, -1)",
+        }
+    }
+
+    fn descending(self) -> bool {
+        matches!(
+            self,
+            MessageSort::DateDesc | MessageSort::SpamScoreDesc | MessageSort::SizeDesc
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MessageQuery {
+    pub filter: Option<MessageFilter>,
+    pub sort: MessageSort,
+}
+
+/// Fields a caller wants back from a message listing, named as in the
+/// response (`snippet`, `analysis_metadata`, ...). Without a mask every
+/// field is returned; with one, storage skips decrypting and parsing the
+/// columns behind fields that are left out.
+#[derive(Debug, Clone, Default)]
+pub struct FieldMask(Option<HashSet<String>>);
+
+impl FieldMask {
+    pub fn new(fields: Option<Vec<String>>) -> Self {
+        Self(fields.map(|fields| {
+            fields
+                .into_iter()
+                .map(|field| field.trim().to_string())
+                .collect()
+        }))
+    }
+
+    pub fn includes(&self, field: &str) -> bool {
+        match &self.0 {
+            Some(fields) => fields.contains(field),
+            None => true,
+        }
+    }
+}
+
+/// A query ready to run: the condition with its parameters, the order, and
+/// the page size.
+#[derive(Debug, Clone)]
+pub struct QueryPlan {
+    pub sort: MessageSort,
+    pub condition: String,
+    pub params: Vec<SqlValue>,
+    pub page_size: usize,
+}
+
+impl QueryPlan {
+    pub fn new(
+        query: &MessageQuery,
+        cursor: Option<&str>,
+        page_size: Option<usize>,
+    ) -> Result<Self, String> {
+        let mut params = Vec::new();
+        let mut conditions = Vec::new();
+        if let Some(filter) = &query.filter {
+            conditions.push(compile(filter, &mut params)?);
+        }
+        if let Some(cursor) = cursor.filter(|value| !value.is_empty()) {
+            let (key, id) = decode_cursor(cursor, query.sort)?;
+            let op = if query.sort.descending() { "<" } else { ">" };
+            let key_expr = query.sort.key();
+            conditions.push(format!(
+                "({key_expr} {op} ? OR ({key_expr} = ? AND m.id {op} ?))"
+            ));
+            params.extend([key.clone(), key, SqlValue::Integer(id)]);
+        }
+        let condition = if conditions.is_empty() {
+            "1".to_string()
+        } else {
+            conditions.join(" AND ")
+        };
+        Ok(Self {
+            sort: query.sort,
+            condition,
+            params,
+            page_size: page_size
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+
+    /// Selected with each row so the next cursor can be built from the last.
+    pub fn sort_key(&self) -> &'static str {
+        self.sort.key()
+    }
+
+    pub fn order_by(&self) -> String {
+        let direction = if self.sort.descending() {
+            "DESC"
+        } else {
+            "ASC"
+        };
+        format!("{} {direction}, m.id {direction}", self.sort.key())
+    }
+
+    /// Cursor for the page that follows the row with this sort key and id.
+    pub fn cursor_after(&self, key: SqlValue, id: i64) -> String {
+        let key = match key {
+            SqlValue::Integer(value) => json!(value),
+            SqlValue::Real(value) => json!(value),
+            SqlValue::Text(value) => json!(value),
+            _ => Value::Null,
+        };
+        let payload = json!({ "sort": self.sort, "key": key, "id": id });
+        general_purpose::URL_SAFE_NO_PAD.encode(payload.to_string())
+    }
+}
+
+fn decode_cursor(cursor: &str, sort: MessageSort) -> Result<(SqlValue, i64), String> {
+    let invalid = || "Invalid query cursor".to_string();
+    let bytes = general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .map_err(|_| invalid())?;
+    let payload: Value = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+    let cursor_sort: MessageSort =
+        serde_json::from_value(payload["sort"].clone()).map_err(|_| invalid())?;
+    if cursor_sort != sort {
+        return Err("The cursor belongs to a query with a different sort order".into());
+    }
+    let id = payload["id"].as_i64().ok_or_else(invalid)?;
+    let key = match &payload["key"] {
+        Value::String(text) => SqlValue::Text(text.clone()),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => SqlValue::Integer(value),
+            None => SqlValue::Real(number.as_f64().ok_or_else(invalid)?),
+        },
+        _ => return Err(invalid()),
+    };
+    Ok((key, id))
+}
+
+fn compile(filter: &MessageFilter, params: &mut Vec<SqlValue>) -> Result<String, String> {
+    let text = |value: &str| SqlValue::Text(value.trim().to_lowercase());
+    let sql = match filter {
+        MessageFilter::All(filters) | MessageFilter::Any(filters) => {
+            if filters.is_empty() {
+                // An empty `all` matches everything, an empty `any` nothing.
+                let all = matches!(filter, MessageFilter::All(_));
+                return Ok(if all { "1" } else { "0" }.to_string());
+            }
+            let joiner = if matches!(filter, MessageFilter::All(_)) {
+                " AND "
+            } else {
+                " OR "
+            };
+            let parts = filters
+                .iter()
+                .map(|child| compile(child, params).map(|sql| format!("({sql})")))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(parts.join(joiner));
+        }
+        // Missing analysis makes comparisons NULL; `not` counts that as no
+        // match, so the negation holds.
+        MessageFilter::Not(inner) => {
+            return Ok(format!("NOT COALESCE(({}), 0)", compile(inner, params)?));
+        }
+        MessageFilter::Sender(value) => {
+            params.push(text(value));
+            if value.trim().starts_with('@') {
+                "substr(m.sender_email, instr(m.sender_email, '@')) = ?"
+            } else {
+                "m.sender_email = ?"
+            }
+        }
+        // Only INBOX is cached for now.
+        MessageFilter::Folder(name) => {
+            return Ok(if name.trim().eq_ignore_ascii_case("INBOX") {
+                "1"
+            } else {
+                "0"
+            }
+            .to_string());
+        }
+        MessageFilter::Flag(flag) => {
+            params.push(SqlValue::Text(format!(" {} ", flag.trim().to_lowercase())));
+            "instr(' ' || lower(COALESCE(m.flags, '')) || ' ', ?) > 0"
+        }
+        MessageFilter::Tag(tag) => {
+            params.push(text(tag));
+            "EXISTS (SELECT 1 FROM json_each(ar.categories) WHERE lower(json_each.value) = ?)"
+        }
+        MessageFilter::After(value) => {
+            params.push(SqlValue::Integer(parse_instant(value)?));
+            "m.date_ts >= ?"
+        }
+        MessageFilter::Before(value) => {
+            params.push(SqlValue::Integer(parse_instant(value)?));
+            "m.date_ts < ?"
+        }
+        MessageFilter::Text(value) => {
+            params.push(text(value));
+            params.push(text(value));
+            return Ok(format!(
+                "{TEXT_MATCH_FUNCTION}(m.subject_encrypted, m.snippet_encrypted, ?) \
+                 OR EXISTS (SELECT 1 FROM attachment_text att \
+                 WHERE att.account_email = m.account_email AND att.uid = m.uid \
+                 AND {TEXT_MATCH_FUNCTION}(att.text_encrypted, NULL, ?))"
+            ));
+        }
+        MessageFilter::Sentiment(value) => {
+            params.push(text(value));
+            "lower(ar.sentiment) = ?"
+        }
+        MessageFilter::SenderStatus(value) => {
+            params.push(text(value));
+            return Ok(format!("{EFFECTIVE_SENDER_STATUS} = ?"));
+        }
+        MessageFilter::Analyzed(analyzed) => {
+            params.push(SqlValue::Integer(*analyzed as i64));
+            "COALESCE(ar.analyzed, 0) = ?"
+        }
+        MessageFilter::ValidationStatus(value) => {
+            params.push(text(value));
+            "lower(ar.validation_status) = ?"
+        }
+        MessageFilter::MinConfidence(value) => {
+            params.push(SqlValue::Real(*value));
+            "ar.analysis_confidence >= ?"
+        }
+        MessageFilter::MinSpamScore(value) => {
+            params.push(SqlValue::Real(*value));
+            "m.spam_score >= ?"
+        }
+        MessageFilter::MaxSpamScore(value) => {
+            params.push(SqlValue::Real(*value));
+            "m.spam_score <= ?"
+        }
+        MessageFilter::BodyCached(cached) => {
+            params.push(SqlValue::Integer(*cached as i64));
+            "(m.body_encrypted IS NOT NULL) = ?"
+        }
+        MessageFilter::AttachmentsIndexed(indexed) => {
+            params.push(SqlValue::Integer(*indexed as i64));
+            "m.attachments_indexed = ?"
+        }
+        MessageFilter::LargerThan(bytes) => {
+            params.push(SqlValue::Integer(size_param(*bytes)));
+            "m.size_bytes > ?"
+        }
+        MessageFilter::SmallerThan(bytes) => {
+            params.push(SqlValue::Integer(size_param(*bytes)));
+            "m.size_bytes < ?"
+        }
+    };
+    Ok(sql.to_string())
+}
+
+fn size_param(bytes: u64) -> i64 {
+    i64::try_from(bytes).unwrap_or(i64::MAX)
+}
+
+/// Unix seconds for an RFC 3339 instant or a `YYYY-MM-DD` date (midnight
+/// UTC).
+fn parse_instant(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    if let Ok(instant) = DateTime::parse_from_rfc3339(value) {
+        return Ok(instant.timestamp());
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|midnight| midnight.and_utc().timestamp())
+        .ok_or_else(|| format!("Invalid date in query: {value}"))
+}
@@ -0,0 +1,179 @@
+//! Copies a folder from one connected account to another. Messages are
+//! streamed in UID order and APPENDed with their original flags and
+//! INTERNALDATE; a checkpoint in `account_migrations` is saved after every
+//! batch, so an interrupted run resumes where it stopped. A crash between
+//! an APPEND and its checkpoint can duplicate at most one batch. Messages
+//! the target rejected are kept in the checkpoint and retried on resume.
+
+use crate::events::{self, MigrationProgress};
+use crate::models::Credentials;
+use crate::providers;
+use crate::storage::{AccountMigration, Storage};
+use serde::Deserialize;
+use tauri::AppHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+const DEFAULT_BATCH_SIZE: usize = 25;
+const MAX_BATCH_SIZE: usize = 200;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MigrationOptions {
+    /// Defaults to INBOX.
+    pub source_folder: Option<String>,
+    /// Defaults to the source folder name.
+    pub target_folder: Option<String>,
+    pub batch_size: Option<usize>,
+}
+
+impl MigrationOptions {
+    pub fn folders(&self) -> (String, String) {
+        let source = self
+            .source_folder
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .unwrap_or("INBOX")
+            .to_string();
+        let target = self
+            .target_folder
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+            .unwrap_or_else(|| source.clone());
+        (source, target)
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+            .unwrap_or(DEFAULT_BATCH_SIZE)
+            .clamp(1, MAX_BATCH_SIZE)
+    }
+}
+
+/// Runs (or resumes) `migration` until it completes, fails, or `cancel`
+/// fires, which leaves it "paused". Every checkpoint is also emitted as a
+/// [`MigrationProgress`] event.
+pub async fn run(
+    app: AppHandle,
+    storage: Storage,
+    source: Credentials,
+    target: Credentials,
+    mut migration: AccountMigration,
+    batch_size: usize,
+    cancel: CancellationToken,
+) -> AccountMigration {
+    let outcome = copy_batches(
+        &app,
+        &storage,
+        &source,
+        &target,
+        &mut migration,
+        batch_size,
+        &cancel,
+    )
+    .await;
+
+    match outcome {
+        Ok(()) if cancel.is_cancelled() => migration.status = "paused".into(),
+        Ok(()) => migration.status = "completed".into(),
+        Err(err) => {
+            warn!(id = migration.id, %err, "account migration failed");
+            migration.status = "failed".into();
+            migration.error = Some(err);
+        }
+    }
+    checkpoint(&app, &storage, &migration).await;
+    info!(
+        id = migration.id,
+        status = %migration.status,
+        copied = migration.copied,
+        "account migration stopped"
+    );
+    migration
+}
+
+async fn copy_batches(
+    app: &AppHandle,
+    storage: &Storage,
+    source: &Credentials,
+    target: &Credentials,
+    migration: &mut AccountMigration,
+    batch_size: usize,
+    cancel: &CancellationToken,
+) -> Result<(), String> {
+    let remaining =
+        providers::list_uids_after(source, &migration.source_folder, migration.last_uid)
+            .await
+            .map_err(|err| err.to_string())?;
+    migration.total = migration.copied + migration.failed + remaining.len();
+    checkpoint(app, storage, migration).await;
+
+    // Earlier failures first; ones still failing stay on the list.
+    let retry = std::mem::take(&mut migration.failed_uids);
+    for chunk in retry.chunks(batch_size) {
+        if cancel.is_cancelled() {
+            migration.failed_uids.extend_from_slice(chunk);
+            continue;
+        }
+        let messages = providers::fetch_for_transfer(source, &migration.source_folder, chunk)
+            .await
+            .map_err(|err| err.to_string())?;
+        let fetched = messages
+            .iter()
+            .map(|message| message.uid)
+            .collect::<Vec<_>>();
+        let appended = providers::append_messages(target, &migration.target_folder, messages)
+            .await
+            .map_err(|err| err.to_string())?;
+        migration.copied += appended.len();
+        migration.failed -= appended.len().min(migration.failed);
+        // A UID no longer on the source was expunged; it stays counted as
+        // failed but there is nothing left to retry.
+        migration
+            .failed_uids
+            .extend(fetched.into_iter().filter(|uid| !appended.contains(uid)));
+        checkpoint(app, storage, migration).await;
+    }
+
+    for chunk in remaining.chunks(batch_size) {
+        if cancel.is_cancelled() {
+            return Ok(());
+        }
+
+        let messages = providers::fetch_for_transfer(source, &migration.source_folder, chunk)
+            .await
+            .map_err(|err| err.to_string())?;
+        let appended = providers::append_messages(target, &migration.target_folder, messages)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        // UIDs expunged mid-run or rejected by the target count as failed,
+        // and are retried when the migration resumes.
+        migration.copied += appended.len();
+        migration.failed += chunk.len().saturating_sub(appended.len());
+        migration
+            .failed_uids
+            .extend(chunk.iter().filter(|uid| !appended.contains(uid)));
+        if let Some(last) = chunk.last() {
+            migration.last_uid = *last;
+        }
+        checkpoint(app, storage, migration).await;
+    }
+
+    Ok(())
+}
+
+async fn checkpoint(app: &AppHandle, storage: &Storage, migration: &AccountMigration) {
+    if let Err(err) = storage.save_migration(migration).await {
+        warn!(
+            id = migration.id,
+            ?err,
+            "failed to save migration checkpoint"
+        );
+    }
+    let migration = migration.clone();
+    events::emit(app, &MigrationProgress { migration });
+}
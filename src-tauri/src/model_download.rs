@@ -0,0 +1,279 @@
+//! Model file downloads. When the server accepts range requests the file is
+//! split into chunks fetched over several connections; otherwise it streams
+//! over one. An optional bandwidth cap is shared by all connections, and a
+//! chunk that fails mid-way is retried from the byte where it stopped.
+
+use futures_util::{stream, StreamExt, TryStreamExt};
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::io::SeekFrom;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tracing::warn;
+
+const CHUNK_SIZE: u64 = 32 * 1024 * 1024;
+const MAX_CONNECTIONS: usize = 8;
+const MAX_CHUNK_ATTEMPTS: u32 = 4;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadSettings {
+    /// Parallel connections; 1 downloads in a single stream.
+    pub connections: usize,
+    /// Cap shared by all connections, in bytes per second.
+    pub bandwidth_limit: Option<u64>,
+}
+
+impl Default for DownloadSettings {
+    fn default() -> Self {
+        Self {
+            connections: 4,
+            bandwidth_limit: None,
+        }
+    }
+}
+
+impl DownloadSettings {
+    pub fn connections(&self) -> usize {
+        self.connections.clamp(1, MAX_CONNECTIONS)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadProgress {
+    pub downloaded: u64,
+    /// Zero when the server did not report a size.
+    pub total: u64,
+    pub bytes_per_sec: u64,
+    pub eta_secs: Option<u64>,
+}
+
+enum ChunkError {
+    /// The connection dropped or the server had a transient failure.
+    Retryable(String),
+    /// A client error status, a full disk, or an unwritable file.
+    Fatal(String),
+}
+
+struct Transfer<'a> {
+    client: &'a reqwest::Client,
+    url: &'a str,
+    path: &'a Path,
+    total: u64,
+    ranged: bool,
+    limit: Option<u64>,
+    downloaded: AtomicU64,
+    started: Instant,
+    last_report: parking_lot::Mutex<Instant>,
+    on_progress: &'a (dyn Fn(DownloadProgress) + Send + Sync),
+}
+
+impl Transfer<'_> {
+    /// Counts `bytes` and, when over the bandwidth cap, sleeps until the
+    /// average rate is back under it.
+    async fn record(&self, bytes: u64) {
+        let downloaded = self.downloaded.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        if let Some(limit) = self.limit.filter(|limit| *limit > 0) {
+            let expected = Duration::from_secs_f64(downloaded as f64 / limit as f64);
+            let elapsed = self.started.elapsed();
+            if expected > elapsed {
+                tokio::time::sleep(expected - elapsed).await;
+            }
+        }
+        self.report(false);
+    }
+
+    fn rewind(&self, bytes: u64) {
+        self.downloaded.fetch_sub(bytes, Ordering::SeqCst);
+    }
+
+    fn report(&self, force: bool) {
+        {
+            let mut last = self.last_report.lock();
+            if !force && last.elapsed() < PROGRESS_INTERVAL {
+                return;
+            }
+            *last = Instant::now();
+        }
+        let downloaded = self.downloaded.load(Ordering::SeqCst);
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let bytes_per_sec = if elapsed > 0.0 {
+            (downloaded as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        let eta_secs = (self.total > 0 && bytes_per_sec > 0)
+            .then(|| self.total.saturating_sub(downloaded) / bytes_per_sec);
+        (self.on_progress)(DownloadProgress {
+            downloaded,
+            total: self.total,
+            bytes_per_sec,
+            eta_secs,
+        });
+    }
+
+    /// Fetches `start..end` with retries. Ranged chunks resume from the last
+    /// byte written; an unranged stream starts over.
+    async fn fetch_chunk(&self, start: u64, end: u64) -> Result<(), String> {
+        let mut written = 0u64;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self.stream_chunk(start, end, &mut written).await {
+                Ok(()) => return Ok(()),
+                Err(ChunkError::Retryable(err)) if attempt < MAX_CHUNK_ATTEMPTS => {
+                    warn!(start, attempt, %err, "model download chunk failed; retrying");
+                    if !self.ranged {
+                        self.rewind(written);
+                        written = 0;
+                    }
+                    tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+                }
+                Err(ChunkError::Retryable(err)) | Err(ChunkError::Fatal(err)) => return Err(err),
+            }
+        }
+    }
+
+    async fn stream_chunk(
+        &self,
+        start: u64,
+        end: u64,
+        written: &mut u64,
+    ) -> Result<(), ChunkError> {
+        let offset = start + *written;
+        let mut request = self.client.get(self.url);
+        if self.ranged {
+            request = request.header(RANGE, format!("bytes={offset}-{}", end - 1));
+        }
+        let response = request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|err| {
+                let message = format!("failed to download model: {err}");
+                match err.status() {
+                    Some(status) if status.is_client_error() => ChunkError::Fatal(message),
+                    _ => ChunkError::Retryable(message),
+                }
+            })?;
+        if self.ranged && response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(ChunkError::Fatal(format!(
+                "server answered a range request with {}",
+                response.status()
+            )));
+        }
+
+        let disk_error =
+            |err: std::io::Error| ChunkError::Fatal(format!("failed writing model data: {err}"));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(!self.ranged)
+            .open(self.path)
+            .await
+            .map_err(disk_error)?;
+        file.seek(SeekFrom::Start(offset))
+            .await
+            .map_err(disk_error)?;
+
+        let mut body = response.bytes_stream();
+        while let Some(piece) = body.next().await {
+            let piece = piece.map_err(|err| {
+                ChunkError::Retryable(format!("error while downloading model: {err}"))
+            })?;
+            file.write_all(&piece).await.map_err(disk_error)?;
+            *written += piece.len() as u64;
+            self.record(piece.len() as u64).await;
+        }
+        file.flush().await.map_err(disk_error)?;
+
+        if self.ranged && start + *written < end {
+            return Err(ChunkError::Retryable(
+                "connection closed before the chunk finished".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Size and range support from a HEAD request; `(0, false)` if unknown.
+async fn probe(client: &reqwest::Client, url: &str) -> (u64, bool) {
+    let Ok(response) = client.head(url).send().await else {
+        return (0, false);
+    };
+    if !response.status().is_success() {
+        return (0, false);
+    }
+    let headers = response.headers();
+    let total = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(0);
+    let ranged = headers
+        .get(ACCEPT_RANGES)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("bytes"));
+    (total, ranged && total > 0)
+}
+
+/// The size `url` reports, if it answers a HEAD request with one.
+pub async fn remote_size(client: &reqwest::Client, url: &str) -> Option<u64> {
+    let (total, _) = probe(client, url).await;
+    (total > 0).then_some(total)
+}
+
+/// Downloads `url` to `path`, replacing any existing file, and returns the
+/// number of bytes written.
+pub async fn download(
+    client: &reqwest::Client,
+    url: &str,
+    path: &Path,
+    settings: &DownloadSettings,
+    on_progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+) -> Result<u64, String> {
+    let (total, ranged) = probe(client, url).await;
+    let file = File::create(path)
+        .await
+        .map_err(|err| format!("failed to create temporary model file: {err}"))?;
+    if ranged {
+        file.set_len(total)
+            .await
+            .map_err(|err| format!("failed to allocate model file: {err}"))?;
+    }
+    drop(file);
+
+    let transfer = Transfer {
+        client,
+        url,
+        path,
+        total,
+        ranged,
+        limit: settings.bandwidth_limit,
+        downloaded: AtomicU64::new(0),
+        started: Instant::now(),
+        last_report: parking_lot::Mutex::new(Instant::now()),
+        on_progress,
+    };
+
+    if ranged {
+        let chunks = (0..total)
+            .step_by(CHUNK_SIZE as usize)
+            .map(|start| (start, (start + CHUNK_SIZE).min(total)))
+            .collect::<Vec<_>>();
+        stream::iter(chunks)
+            .map(|(start, end)| transfer.fetch_chunk(start, end))
+            .buffer_unordered(settings.connections())
+            .try_collect::<Vec<()>>()
+            .await?;
+    } else {
+        transfer.fetch_chunk(0, 0).await?;
+    }
+
+    transfer.report(true);
+    Ok(transfer.downloaded.load(Ordering::SeqCst))
+}
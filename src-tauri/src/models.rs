@@ -1,12 +1,17 @@
+use secrecy::SecretString;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::{self, Display};
+use std::time::Instant;
 use tauri::AppHandle;
 use tokio::sync::RwLock;
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
-use crate::{llm::LlmService, remote_delete::RemoteDeleteManager, storage::Storage};
+use crate::{
+    llm::LlmService, providers::folders::FolderStatus, remote_delete::RemoteDeleteManager,
+    storage::Storage,
+};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
@@ -15,6 +20,9 @@ pub enum Provider {
     Outlook,
     Yahoo,
     Custom,
+    /// A generated mailbox that never leaves the machine (see
+    /// `providers::demo`).
+    Demo,
 }
 
 impl Provider {
@@ -24,6 +32,7 @@ impl Provider {
             Provider::Outlook => "outlook",
             Provider::Yahoo => "yahoo",
             Provider::Custom => "custom",
+            Provider::Demo => "demo",
         }
     }
 
@@ -33,6 +42,7 @@ impl Provider {
             "outlook" => Some(Provider::Outlook),
             "yahoo" => Some(Provider::Yahoo),
             "custom" => Some(Provider::Custom),
+            "demo" => Some(Provider::Demo),
             _ => None,
         }
     }
@@ -43,6 +53,16 @@ impl Provider {
             Provider::Outlook => "outlook.office365.com",
             Provider::Yahoo => "imap.mail.yahoo.com",
             Provider::Custom => "localhost", // Default for custom, but will be overridden
+            Provider::Demo => "localhost",
+        }
+    }
+
+    pub fn smtp_host(&self) -> &'static str {
+        match self {
+            Provider::Gmail => "smtp.gmail.com",
+            Provider::Outlook => "smtp.office365.com",
+            Provider::Yahoo => "smtp.mail.yahoo.com",
+            Provider::Custom | Provider::Demo => "localhost",
         }
     }
 
@@ -52,6 +72,35 @@ impl Provider {
             Provider::Outlook => "Outlook / Live",
             Provider::Yahoo => "Yahoo Mail",
             Provider::Custom => "Custom IMAP",
+            Provider::Demo => "Demo mailbox",
+        }
+    }
+
+    /// Where users create an app password when the provider rejects their
+    /// regular one over IMAP.
+    pub fn app_password_url(&self) -> Option<&'static str> {
+        match self {
+            Provider::Gmail => Some("https://myaccount.google.com/apppasswords"),
+            Provider::Yahoo => Some("https://login.yahoo.com/myaccount/security/app-password"),
+            Provider::Outlook | Provider::Custom | Provider::Demo => None,
+        }
+    }
+
+    /// Page that clears a "sign in through your browser" IMAP block.
+    pub fn web_login_url(&self) -> Option<&'static str> {
+        match self {
+            Provider::Gmail => Some("https://accounts.google.com/DisplayUnlockCaptcha"),
+            Provider::Yahoo => Some("https://login.yahoo.com"),
+            Provider::Outlook => Some("https://outlook.live.com"),
+            Provider::Custom | Provider::Demo => None,
+        }
+    }
+
+    pub fn imap_settings_url(&self) -> Option<&'static str> {
+        match self {
+            Provider::Gmail => Some("https://mail.google.com/mail/u/0/#settings/fwdandpop"),
+            Provider::Outlook => Some("https://outlook.live.com/mail/0/options/mail/accounts"),
+            Provider::Yahoo | Provider::Custom | Provider::Demo => None,
         }
     }
 
@@ -60,7 +109,43 @@ impl Provider {
             Provider::Gmail => "[Gmail]/Trash",
             Provider::Outlook => "Deleted Items",
             Provider::Yahoo => "Trash",
-            Provider::Custom => "Trash",
+            Provider::Custom | Provider::Demo => "Trash",
+        }
+    }
+
+    /// Where forwarded spam or phishing reports go. Gmail only takes spam
+    /// reports through its own UI, so moving to Spam is the whole report there.
+    pub fn report_address(&self, phishing: bool) -> Option<&'static str> {
+        match (self, phishing) {
+            (Provider::Outlook, false) => Some("junk@office365.microsoft.com"),
+            (Provider::Outlook, true) => Some("phish@office365.microsoft.com"),
+            (Provider::Demo, _) => None,
+            (_, true) => Some("reportphishing@apwg.org"),
+            (_, false) => None,
+        }
+    }
+
+    pub fn sent_folder(&self) -> &'static str {
+        match self {
+            Provider::Gmail => "[Gmail]/Sent Mail",
+            Provider::Outlook => "Sent Items",
+            Provider::Yahoo => "Sent",
+            Provider::Custom | Provider::Demo => "Sent",
+        }
+    }
+
+    /// Whether the server files mail submitted over SMTP in Sent itself,
+    /// so the client must not append a second copy.
+    pub fn files_sent_mail(&self) -> bool {
+        matches!(self, Provider::Gmail | Provider::Outlook)
+    }
+
+    pub fn junk_folder(&self) -> &'static str {
+        match self {
+            Provider::Gmail => "[Gmail]/Spam",
+            Provider::Outlook => "Junk",
+            Provider::Yahoo => "Bulk",
+            Provider::Custom | Provider::Demo => "Junk",
         }
     }
 }
@@ -78,14 +163,122 @@ pub struct Account {
     pub display_name: Option<String>,
     pub custom_host: Option<String>,
     pub custom_port: Option<u16>,
+    /// Set for accounts that sign in with OAuth: the client the refresh
+    /// token was issued to.
+    #[serde(default)]
+    pub oauth_client_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailSummary {
-    pub uid: String,
+    #[serde(with = "uid_string")]
+    pub uid: u32,
     pub subject: String,
     pub sender: MailAddress,
     pub date: Option<String>,
+    /// Set when headers mark the message as machine-generated
+    /// (Auto-Submitted, bulk Precedence, or mailing-list headers).
+    #[serde(default)]
+    pub auto_submitted: bool,
+    /// `To` addresses, lowercase. Empty for messages read from the cache.
+    #[serde(default)]
+    pub to: Vec<String>,
+    /// `RFC822.SIZE` in bytes, when the server reported it.
+    #[serde(default)]
+    pub size: Option<u32>,
+    /// Whether the receiving server found the message DMARC-aligned with
+    /// its `From` domain. `None` when it recorded no verdict.
+    #[serde(default)]
+    pub dmarc_aligned: Option<bool>,
+    /// The conversation the message belongs to (see `threads`).
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    /// The message's own Message-ID, without brackets. `None` for messages
+    /// read from the cache.
+    #[serde(default)]
+    pub message_id: Option<String>,
+    /// Message-IDs in its `References` header, oldest first.
+    #[serde(default)]
+    pub references: Vec<String>,
+}
+
+/// Parses a message UID from the string form the frontend and the message
+/// cache pass around. IMAP UIDs are non-zero 32-bit integers.
+pub fn parse_uid(value: &str) -> Option<u32> {
+    value.trim().parse::<u32>().ok().filter(|uid| *uid > 0)
+}
+
+/// Serde for a `u32` UID in the string form the frontend holds:
+/// `#[serde(with = "uid_string")]`. Numbers are accepted on the way in.
+pub mod uid_string {
+    use super::parse_uid;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Wire {
+        Number(u32),
+        Text(String),
+    }
+
+    pub fn serialize<S: Serializer>(uid: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(uid)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+        match Wire::deserialize(deserializer)? {
+            Wire::Number(uid) => Ok(uid),
+            Wire::Text(text) => {
+                parse_uid(&text).ok_or_else(|| D::Error::custom(format!("invalid UID '{text}'")))
+            }
+        }
+    }
+}
+
+/// [`uid_string`] for an optional UID.
+pub mod uid_option {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    struct Uid(#[serde(with = "super::uid_string")] u32);
+
+    pub fn serialize<S: Serializer>(uid: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error> {
+        match uid {
+            Some(uid) => serializer.collect_str(uid),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u32>, D::Error> {
+        Ok(Option::<Uid>::deserialize(deserializer)?.map(|Uid(uid)| uid))
+    }
+}
+
+/// [`uid_string`] for a list of UIDs.
+pub mod uid_strings {
+    use serde::ser::SerializeSeq;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    struct Uid(#[serde(with = "super::uid_string")] u32);
+
+    pub fn serialize<S: Serializer>(uids: &[u32], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(uids.len()))?;
+        for uid in uids {
+            seq.serialize_element(&uid.to_string())?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u32>, D::Error> {
+        Ok(Vec::<Uid>::deserialize(deserializer)?
+            .into_iter()
+            .map(|Uid(uid)| uid)
+            .collect())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -94,20 +287,35 @@ pub struct MailAddress {
     pub email: String,
 }
 
+/// How an account signs in to its servers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// `LOGIN` with the account or app password.
+    Password,
+    /// `AUTHENTICATE XOAUTH2`. The credentials' password is then a refresh
+    /// token issued to `client_id`, traded for access tokens by
+    /// `providers::oauth`.
+    OAuth2 { client_id: String },
+}
+
+/// Login details for one account. The password is a [`SecretString`]: it is
+/// zeroed when the last copy drops, prints as `[REDACTED]` in `Debug`
+/// output, and has to be read explicitly with `expose_secret`.
 #[derive(Debug, Clone)]
 pub struct Credentials {
     pub provider: Provider,
     pub email: String,
-    pub password: String,
+    pub password: SecretString,
     pub custom_host: Option<String>,
     pub custom_port: Option<u16>,
+    pub auth: AuthMethod,
 }
 
 impl Credentials {
     pub fn new(
         provider: Provider,
         email: String,
-        password: String,
+        password: SecretString,
         custom_host: Option<String>,
         custom_port: Option<u16>,
     ) -> Self {
@@ -117,9 +325,15 @@ impl Credentials {
             password,
             custom_host,
             custom_port,
+            auth: AuthMethod::Password,
         }
     }
 
+    pub fn with_auth(mut self, auth: AuthMethod) -> Self {
+        self.auth = auth;
+        self
+    }
+
     pub fn key(&self) -> String {
         format!("{}::{}", self.provider.display_name(), self.email)
     }
@@ -131,6 +345,10 @@ impl Credentials {
             display_name: None,
             custom_host: self.custom_host.clone(),
             custom_port: self.custom_port,
+            oauth_client_id: match &self.auth {
+                AuthMethod::Password => None,
+                AuthMethod::OAuth2 { client_id } => Some(client_id.clone()),
+            },
         }
     }
 }
@@ -150,6 +368,8 @@ pub struct AppState {
     pub sync_jobs: RwLock<HashMap<String, SyncHandle>>,
     pub llm: LlmService,
     pub remote_delete: RemoteDeleteManager,
+    /// Last folder listing per account and when it was fetched.
+    pub folder_cache: RwLock<HashMap<String, (Instant, Vec<FolderStatus>)>>,
 }
 
 impl AppState {
@@ -161,6 +381,7 @@ impl AppState {
             sync_jobs: RwLock::new(HashMap::new()),
             llm,
             remote_delete,
+            folder_cache: RwLock::new(HashMap::new()),
         }
     }
 }
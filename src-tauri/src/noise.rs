@@ -0,0 +1,142 @@
+//! How ignorable a sender is. The noise score runs from 0 (mail the user
+//! reads and answers) to 1 (mail left unread and never answered), from
+//! open and reply behavior plus what analysis says the sender sends. Sender
+//! groups carry it so the UI can sort by it, cleanup lists the noisiest
+//! senders first, and focus mode lets only VIPs through from noisy ones.
+
+use crate::storage::SenderProfile;
+use serde::Serialize;
+
+/// Scores from here up count as noisy.
+pub const NOISY_THRESHOLD: f64 = 0.7;
+/// Messages after which the score is fully trusted.
+const FULL_CONFIDENCE_MESSAGES: f64 = 20.0;
+
+/// Analysis tags of mail that rarely needs attention.
+const NOISY_TAGS: &[&str] = &[
+    "newsletter",
+    "promotions",
+    "marketing",
+    "social",
+    "updates",
+    "notification",
+];
+
+/// What the user did with a sender's cached mail.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SenderEngagement {
+    pub message_count: i64,
+    pub read_count: i64,
+    pub answered_count: i64,
+    pub flagged_count: i64,
+    /// Sent messages addressed to the sender.
+    pub sent_to_count: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NoiseScore {
+    pub score: f64,
+    /// How far the score can be trusted, from the number of messages.
+    pub confidence: f64,
+    pub open_rate: f64,
+    pub reply_rate: f64,
+    /// Agreement of the sender's analysed messages with its typical tags
+    /// and priority, i.e. how safely new mail can be auto-triaged.
+    pub triage_confidence: Option<f64>,
+}
+
+impl NoiseScore {
+    pub fn is_noisy(&self) -> bool {
+        self.score >= NOISY_THRESHOLD
+    }
+}
+
+pub fn score(engagement: &SenderEngagement, profile: Option<&SenderProfile>) -> NoiseScore {
+    let count = engagement.message_count.max(1) as f64;
+    let open_rate = (engagement.read_count as f64 / count).clamp(0.0, 1.0);
+    let replies = engagement.answered_count.max(engagement.sent_to_count) as f64;
+    let reply_rate = (replies / count).clamp(0.0, 1.0);
+    let flagged_rate = (engagement.flagged_count as f64 / count).clamp(0.0, 1.0);
+
+    // Ignoring mail weighs most; never replying adds to it, fading out
+    // once a quarter of the mail gets an answer.
+    let mut score = 0.5 * (1.0 - open_rate) + 0.25 * (1.0 - (reply_rate * 4.0).min(1.0));
+    if let Some(profile) = profile {
+        if profile.kind == "automated" {
+            score += 0.15;
+        }
+        let noisy_tags = profile
+            .typical_tags
+            .iter()
+            .filter(|tag| NOISY_TAGS.contains(&tag.as_str()))
+            .count();
+        if noisy_tags > 0 {
+            score += 0.1;
+        }
+        score += match profile.typical_priority.as_deref() {
+            Some("low") => 0.05,
+            Some("high") => -0.2,
+            Some("critical") => -0.3,
+            _ => 0.0,
+        };
+    }
+    score -= 0.3 * (flagged_rate * 4.0).min(1.0);
+
+    NoiseScore {
+        score: round(score.clamp(0.0, 1.0)),
+        confidence: round((engagement.message_count as f64 / FULL_CONFIDENCE_MESSAGES).min(1.0)),
+        open_rate: round(open_rate),
+        reply_rate: round(reply_rate),
+        triage_confidence: profile
+            .filter(|profile| profile.sample_count > 0)
+            .map(|profile| round(profile.confidence)),
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(kind: &str, tags: &[&str], priority: Option<&str>) -> SenderProfile {
+        SenderProfile {
+            kind: kind.into(),
+            typical_tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            typical_priority: priority.map(str::to_string),
+            sample_count: 10,
+            confidence: 0.8,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn unread_automated_mail_is_noisy_and_answered_mail_is_not() {
+        let ignored = SenderEngagement {
+            message_count: 40,
+            read_count: 2,
+            ..Default::default()
+        };
+        let newsletter = score(
+            &ignored,
+            Some(&profile("automated", &["newsletter"], Some("low"))),
+        );
+        assert!(newsletter.is_noisy());
+        assert_eq!(newsletter.confidence, 1.0);
+        assert_eq!(newsletter.triage_confidence, Some(0.8));
+
+        let colleague = score(
+            &SenderEngagement {
+                message_count: 10,
+                read_count: 10,
+                answered_count: 6,
+                ..Default::default()
+            },
+            Some(&profile("human", &["work"], Some("high"))),
+        );
+        assert_eq!(colleague.score, 0.0);
+        assert_eq!(colleague.reply_rate, 0.6);
+    }
+}
@@ -0,0 +1,92 @@
+//! Text recognition for image-only mail. Images are pulled out of the raw
+//! message and passed to the `tesseract` command-line tool, which has to be
+//! installed separately; without it OCR is simply unavailable.
+
+use mailparse::{parse_mail, ParsedMail};
+use std::process::Command;
+use uuid::Uuid;
+
+pub const ENGINE: &str = "tesseract";
+/// Smaller images are tracking pixels, spacers, and logos.
+const MIN_IMAGE_BYTES: usize = 4 * 1024;
+const MAX_IMAGES_PER_MESSAGE: usize = 6;
+
+pub struct OcrResult {
+    pub text: String,
+    pub images: usize,
+}
+
+pub fn engine_available() -> bool {
+    Command::new(ENGINE)
+        .arg("--version")
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Image parts (attachments and inline) large enough to carry text.
+fn image_parts(raw: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let Ok(parsed) = parse_mail(raw) else {
+        return Vec::new();
+    };
+    let mut images = Vec::new();
+    collect_images(&parsed, &mut images);
+    images.truncate(MAX_IMAGES_PER_MESSAGE);
+    images
+}
+
+fn collect_images(part: &ParsedMail, images: &mut Vec<(String, Vec<u8>)>) {
+    for subpart in &part.subparts {
+        collect_images(subpart, images);
+    }
+    let mimetype = part.ctype.mimetype.to_lowercase();
+    if !mimetype.starts_with("image/") || mimetype == "image/svg+xml" {
+        return;
+    }
+    if let Ok(bytes) = part.get_body_raw() {
+        if bytes.len() >= MIN_IMAGE_BYTES {
+            images.push((mimetype, bytes));
+        }
+    }
+}
+
+fn recognize(mimetype: &str, bytes: &[u8]) -> Result<String, String> {
+    let extension = mimetype.trim_start_matches("image/");
+    let path = std::env::temp_dir().join(format!("pmc-ocr-{}.{extension}", Uuid::new_v4()));
+    std::fs::write(&path, bytes).map_err(|err| format!("Failed to stage image: {err}"))?;
+    let output = Command::new(ENGINE).arg(&path).arg("stdout").output();
+    let _ = std::fs::remove_file(&path);
+
+    let output = output.map_err(|err| format!("Failed to run {ENGINE}: {err}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Recognizes text in every usable image of a raw message. Blocking; run it
+/// on a worker thread. Returns `None` when the message has no such images.
+pub fn recognize_message(raw: &[u8]) -> Result<Option<OcrResult>, String> {
+    let images = image_parts(raw);
+    if images.is_empty() {
+        return Ok(None);
+    }
+
+    let mut texts = Vec::new();
+    for (mimetype, bytes) in &images {
+        let text = recognize(mimetype, bytes)?;
+        let cleaned = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !cleaned.is_empty() {
+            texts.push(cleaned);
+        }
+    }
+    Ok(Some(OcrResult {
+        text: texts.join("\n\n"),
+        images: images.len(),
+    }))
+}
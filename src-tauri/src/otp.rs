@@ -0,0 +1,257 @@
+//! One-time codes and password-reset links in newly arrived mail. Patterns
+//! find the candidates; when a model is loaded it confirms each one, so an
+//! order number next to the word "code" is not offered as a login code.
+//! Extracted codes are kept only until they expire, then erased along with
+//! the code in the cached snippet.
+
+use crate::models::uid_string;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+/// App setting holding the [`OtpSettings`] as JSON.
+pub const SETTING_KEY: &str = "otp_surfacing";
+/// Mail older than this when it is synced is not surfaced.
+pub const FRESH_SECS: i64 = 15 * 60;
+
+const MIN_EXPIRY_SECS: u64 = 60;
+const MAX_EXPIRY_SECS: u64 = 24 * 60 * 60;
+/// Text searched for a code, from the start of the message.
+const SCAN_CHARS: usize = 4000;
+const RESET_LINK_HINTS: &[&str] = &["reset", "recover", "password", "forgot"];
+
+/// A code named as one: "code: 123456", "OTP 4821", "login code 123-456".
+static CODE_AFTER_KEYWORD: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"(?i)\b(?:code|otp|passcode|pin|verification|one-time password)\b",
+        r"[^\d\n]{0,30}?\b(\d{3}[ -]\d{3}|\d{4,8})\b",
+    ))
+    .expect("code pattern is valid")
+});
+/// "482913 is your verification code".
+static CODE_BEFORE_KEYWORD: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b(\d{3}[ -]\d{3}|\d{4,8})\s+is\s+your\b").expect("code pattern is valid")
+});
+static RESET_CONTEXT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"(?i)\b(?:reset|forgot|recover|change)\b[^.\n]{0,40}\bpassword\b",
+        r"|\bpassword\b[^.\n]{0,20}\b(?:reset|recovery)\b",
+    ))
+    .expect("reset pattern is valid")
+});
+static URL: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"https?://[^\s<>"')\]]+"#).expect("url pattern is valid"));
+static DIGIT_RUN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\d(?:[ -]?\d)+").expect("digit run pattern is valid"));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OtpSettings {
+    pub enabled: bool,
+    /// Seconds a surfaced code is kept before it is erased.
+    pub expire_after_secs: u64,
+    /// Ask the loaded model to confirm each find.
+    pub confirm_with_llm: bool,
+}
+
+impl Default for OtpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            expire_after_secs: 10 * 60,
+            confirm_with_llm: true,
+        }
+    }
+}
+
+impl OtpSettings {
+    pub fn normalized(self) -> Result<Self, String> {
+        if !(MIN_EXPIRY_SECS..=MAX_EXPIRY_SECS).contains(&self.expire_after_secs) {
+            return Err(format!(
+                "Codes must expire after {MIN_EXPIRY_SECS} to {MAX_EXPIRY_SECS} seconds"
+            ));
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OtpKind {
+    Code,
+    ResetLink,
+}
+
+impl OtpKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OtpKind::Code => "code",
+            OtpKind::ResetLink => "reset_link",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "code" => Some(OtpKind::Code),
+            "reset_link" => Some(OtpKind::ResetLink),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Detection {
+    pub kind: OtpKind,
+    /// Digits only, ready to paste.
+    pub code: Option<String>,
+    pub link: Option<String>,
+}
+
+/// A surfaced code or link while it lasts. After `expires_at` both are gone.
+#[derive(Debug, Clone, Serialize)]
+pub struct OneTimeCode {
+    pub account_email: String,
+    pub folder: String,
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub kind: OtpKind,
+    pub code: Option<String>,
+    pub link: Option<String>,
+    pub sender_email: String,
+    pub subject: String,
+    pub received_at: i64,
+    pub expires_at: i64,
+}
+
+/// The code or reset link in a message, if it has one. A code wins when a
+/// message has both.
+pub fn detect(subject: &str, text: &str) -> Option<Detection> {
+    let text = text
+        .char_indices()
+        .nth(SCAN_CHARS)
+        .map_or(text, |(end, _)| &text[..end]);
+    let haystack = format!("{subject}\n{text}");
+    let code = CODE_AFTER_KEYWORD
+        .captures(&haystack)
+        .or_else(|| CODE_BEFORE_KEYWORD.captures(&haystack))
+        .map(|caps| digits(&caps[1]));
+    if let Some(code) = code {
+        return Some(Detection {
+            kind: OtpKind::Code,
+            code: Some(code),
+            link: None,
+        });
+    }
+    if !RESET_CONTEXT.is_match(&haystack) {
+        return None;
+    }
+    let link = URL
+        .find_iter(text)
+        .map(|found| found.as_str().trim_end_matches(['.', ',', ';']))
+        .find(|url| {
+            let lowered = url.to_lowercase();
+            RESET_LINK_HINTS.iter().any(|hint| lowered.contains(hint))
+        })?;
+    Some(Detection {
+        kind: OtpKind::ResetLink,
+        code: None,
+        link: Some(link.to_string()),
+    })
+}
+
+/// Asks for a one-word verdict on a find.
+pub fn confirmation_prompt(subject: &str, text: &str, detection: &Detection) -> String {
+    let excerpt = text.chars().take(1200).collect::<String>();
+    let claim = match detection.kind {
+        OtpKind::Code => format!(
+            "contains the one-time login or verification code {}",
+            detection.code.as_deref().unwrap_or_default()
+        ),
+        OtpKind::ResetLink => "contains a link to reset an account password".to_string(),
+    };
+    format!(
+        "Answer with one word, YES or NO. Does this email {claim}?\n\n\
+         Subject: {subject}\n\n{excerpt}\n\nAnswer:"
+    )
+}
+
+/// Whether a model reply to [`confirmation_prompt`] agrees.
+pub fn confirmed(reply: &str) -> bool {
+    reply
+        .trim_start()
+        .get(..3)
+        .is_some_and(|start| start.eq_ignore_ascii_case("yes"))
+}
+
+/// `text` with every spelling of `code` ("123456", "123 456") masked, or
+/// `None` when it does not contain the code.
+pub fn redact_code(text: &str, code: &str) -> Option<String> {
+    let mut found = false;
+    let redacted = DIGIT_RUN.replace_all(text, |caps: &Captures<'_>| {
+        if digits(&caps[0]) == code {
+            found = true;
+            "[code]".to_string()
+        } else {
+            caps[0].to_string()
+        }
+    });
+    found.then(|| redacted.into_owned())
+}
+
+fn digits(value: &str) -> String {
+    value.chars().filter(char::is_ascii_digit).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_codes_near_their_keyword() {
+        let found = detect("Your sign-in code", "Use code 482 913 to sign in.").unwrap();
+        assert_eq!(found.kind, OtpKind::Code);
+        assert_eq!(found.code.as_deref(), Some("482913"));
+
+        let found = detect("Verify", "0042 is your one-time password").unwrap();
+        assert_eq!(found.code.as_deref(), Some("0042"));
+
+        assert_eq!(
+            detect("Order shipped", "Order 12345678 is on its way"),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_reset_links_only_in_reset_mail() {
+        let text = "We got a request to reset your password.\n\
+                    Open https://example.com/account/reset?token=abc. It expires soon.\n\
+                    https://example.com/help";
+        let found = detect("Password help", text).unwrap();
+        assert_eq!(found.kind, OtpKind::ResetLink);
+        assert_eq!(
+            found.link.as_deref(),
+            Some("https://example.com/account/reset?token=abc")
+        );
+
+        assert_eq!(
+            detect("News", "Read more at https://example.com/reset-your-garden"),
+            None
+        );
+    }
+
+    #[test]
+    fn redacts_every_spelling_of_the_code() {
+        assert_eq!(
+            redact_code("Code 482 913 (or 482913). Ref 12", "482913").as_deref(),
+            Some("Code [code] (or [code]). Ref 12")
+        );
+        assert_eq!(redact_code("nothing here 1234", "482913"), None);
+    }
+
+    #[test]
+    fn reads_model_verdicts() {
+        assert!(confirmed(" Yes."));
+        assert!(!confirmed("No"));
+        assert!(!confirmed(""));
+    }
+}
@@ -0,0 +1,217 @@
+//! Sending what the outbox holds. Mail merges, auto-replies, and forwards
+//! queue entries that wait until their `scheduled_at`; [`drain`] claims the
+//! due ones for connected accounts and sends each with [`dispatch`], which
+//! also sends the messages composed and sent right away.
+
+use crate::command_context::provider_error_to_message;
+use crate::models::Credentials;
+use crate::providers::api_send::{self, DeliveryPath};
+use crate::providers::smtp::{self, AttachmentData, OutgoingMessage};
+use crate::providers::{self, ProviderError, TransferMessage};
+use crate::storage::{OutboxMessage, Storage, StorageError};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// Entries claimed per drain; the rest wait for the next one.
+pub const DRAIN_BATCH: usize = 20;
+/// Sends tried before an entry the network keeps refusing is left failed.
+pub const MAX_ATTEMPTS: i64 = 5;
+/// Wait before retrying an entry, multiplied by the attempts so far.
+const RETRY_BACKOFF_SECS: i64 = 5 * 60;
+
+/// How a message went out.
+#[derive(Debug, Clone, Copy)]
+pub struct Dispatched {
+    pub path: DeliveryPath,
+    /// Whether a copy was appended to the Sent folder.
+    pub saved_to_sent: bool,
+}
+
+/// Sends `draft` as outbox entry `outbox_id` over SMTP, or the provider's
+/// HTTP API when SMTP is blocked, marks the entry sent, and files a copy in
+/// Sent unless the server does that itself. A failed send is returned for
+/// the caller to record, since only it knows whether to retry.
+pub async fn dispatch(
+    storage: &Storage,
+    credentials: &Credentials,
+    draft: &OutgoingMessage,
+    outbox_id: i64,
+    message_id: &str,
+) -> Result<Dispatched, ProviderError> {
+    // SMTP takes the recipients from the envelope, so the copy it sends
+    // (and the one filed in Sent) leaves Bcc out; the HTTP APIs read the
+    // recipients from the headers and get a copy that keeps it.
+    let message = smtp::build(credentials, draft, message_id, false)?;
+    let with_bcc = smtp::build(credentials, draft, message_id, true)?.formatted();
+    let raw = message.formatted();
+    let path =
+        api_send::deliver(credentials, &with_bcc, || smtp::send(credentials, message)).await?;
+
+    let account = &credentials.email;
+    info!(%account, outbox_id, path = path.as_key(), "sent message");
+    // The message is out; bookkeeping failures from here on are only logged
+    // so it is not sent again.
+    if let Err(err) = storage.mark_outbox_sent(outbox_id, path).await {
+        warn!(%account, outbox_id, %err, "could not record the sent message");
+    }
+
+    let provider = credentials.provider;
+    let mut saved_to_sent = false;
+    if path == DeliveryPath::Smtp && !provider.files_sent_mail() {
+        let copy = TransferMessage {
+            uid: 0,
+            flags: vec!["seen".into()],
+            internal_date: None,
+            raw,
+        };
+        match providers::append_messages(credentials, provider.sent_folder(), vec![copy]).await {
+            Ok(_) => saved_to_sent = true,
+            Err(err) => warn!(%account, %err, "could not file the message in Sent"),
+        }
+    }
+    Ok(Dispatched {
+        path,
+        saved_to_sent,
+    })
+}
+
+/// Sends the queued entries due by `now` for the connected `accounts`,
+/// keyed by normalized address. Entries the network refused go back in the
+/// queue until [`MAX_ATTEMPTS`]; other failures are final. Returns how
+/// many were sent.
+pub async fn drain(
+    storage: &Storage,
+    accounts: &HashMap<String, Credentials>,
+    now: i64,
+) -> Result<usize, StorageError> {
+    let due = storage
+        .claim_due_outbox(accounts.keys().cloned().collect(), now, DRAIN_BATCH)
+        .await?;
+    let mut sent = 0;
+    for entry in due {
+        let Some(credentials) = accounts.get(&entry.account_email) else {
+            continue;
+        };
+        let (id, attempts) = (entry.id, entry.attempts);
+        let message_id = entry.message_id.clone();
+        match dispatch(storage, credentials, &draft_for(entry), id, &message_id).await {
+            Ok(_) => sent += 1,
+            Err(err) => {
+                let retry = api_send::is_blocked(&err) && attempts < MAX_ATTEMPTS;
+                let message = provider_error_to_message(err);
+                warn!(
+                    account = %credentials.email,
+                    outbox_id = id,
+                    attempts,
+                    retry,
+                    %message,
+                    "outbox send failed"
+                );
+                if retry {
+                    let retry_at = now + RETRY_BACKOFF_SECS * attempts;
+                    storage.retry_outbox(id, &message, retry_at).await?;
+                } else {
+                    storage.mark_outbox_failed(id, &message).await?;
+                }
+            }
+        }
+    }
+    Ok(sent)
+}
+
+fn draft_for(entry: OutboxMessage) -> OutgoingMessage {
+    OutgoingMessage {
+        to: entry
+            .recipient
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .map(str::to_string)
+            .collect(),
+        subject: entry.subject,
+        body: entry.body,
+        in_reply_to: entry.in_reply_to,
+        references: entry.references,
+        auto_submitted: entry.auto_submitted,
+        attachments: entry
+            .attachments
+            .into_iter()
+            .map(|attachment| AttachmentData {
+                filename: attachment.filename,
+                content_type: attachment.content_type,
+                data: attachment.data,
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Provider;
+    use crate::providers::demo;
+    use crate::storage::OutboxInsert;
+    use secrecy::SecretString;
+
+    #[tokio::test]
+    async fn due_entries_are_sent_and_filed() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path()).unwrap();
+        let account = "drain@demo.local";
+        let credentials = Credentials::new(
+            Provider::Demo,
+            account.into(),
+            SecretString::new(String::new()),
+            None,
+            None,
+        );
+        let merge_id = storage
+            .create_mail_merge(account, "Hi", "Hello", 60)
+            .await
+            .unwrap();
+        let entry = |recipient: &str, scheduled_at| OutboxInsert {
+            account_email: account.into(),
+            merge_id: Some(merge_id),
+            recipient: recipient.into(),
+            subject: format!("Hi {recipient}"),
+            body: "Hello".into(),
+            attachments: Vec::new(),
+            uploads: Vec::new(),
+            scheduled_at,
+            in_reply_to: None,
+            references: Vec::new(),
+            auto_submitted: None,
+        };
+        storage
+            .enqueue_outbox(vec![
+                entry("ann@example.org", 100),
+                entry("bob@example.org", 200),
+            ])
+            .await
+            .unwrap();
+        let accounts = HashMap::from([(account.to_string(), credentials.clone())]);
+
+        assert_eq!(drain(&storage, &accounts, 150).await.unwrap(), 1);
+        assert_eq!(drain(&storage, &accounts, 150).await.unwrap(), 0);
+        let status = storage.mail_merge_status(merge_id).await.unwrap().unwrap();
+        let statuses = status
+            .items
+            .iter()
+            .map(|item| (item.recipient.as_str(), item.status.as_str(), item.attempts))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            [
+                ("ann@example.org", "sent", 1),
+                ("bob@example.org", "queued", 0)
+            ]
+        );
+        assert_eq!(status.items[0].delivery_path.as_deref(), Some("smtp"));
+
+        let sent = demo::fetch_recent(&credentials, "Sent", 10).unwrap();
+        assert!(sent
+            .iter()
+            .any(|summary| summary.subject == "Hi ann@example.org"));
+    }
+}
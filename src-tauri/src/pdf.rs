@@ -0,0 +1,252 @@
+//! A small PDF writer for printing messages: a bold title, a block of
+//! header fields, and wrapped body text on A4 pages. It uses the standard
+//! Helvetica fonts, which every reader has built in, so nothing is embedded
+//! and characters outside Latin-1 are printed as `?`.
+
+use std::fmt::Write as _;
+
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 56.0;
+const TITLE_SIZE: f32 = 14.0;
+const TEXT_SIZE: f32 = 10.0;
+const LEADING: f32 = 1.35;
+/// Helvetica-Bold runs about this much wider than the regular widths below.
+const BOLD_SCALE: f32 = 1.06;
+
+/// Helvetica advance widths for ASCII 32..=126, in 1/1000 em.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Font {
+    Regular,
+    Bold,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+        }
+    }
+}
+
+enum Item {
+    Text { font: Font, size: f32, text: String },
+    Gap(f32),
+    Rule,
+}
+
+/// Lays out a message and returns the PDF file bytes.
+pub fn text_document(title: &str, headers: &[(&str, String)], body: &str) -> Vec<u8> {
+    let width = PAGE_WIDTH - 2.0 * MARGIN;
+    let mut items = Vec::new();
+    for line in wrap(title, Font::Bold, TITLE_SIZE, width) {
+        items.push(Item::Text {
+            font: Font::Bold,
+            size: TITLE_SIZE,
+            text: line,
+        });
+    }
+    items.push(Item::Gap(TEXT_SIZE * 0.5));
+    for (name, value) in headers {
+        for line in wrap(&format!("{name}: {value}"), Font::Regular, TEXT_SIZE, width) {
+            items.push(Item::Text {
+                font: Font::Regular,
+                size: TEXT_SIZE,
+                text: line,
+            });
+        }
+    }
+    items.push(Item::Gap(TEXT_SIZE * 0.5));
+    items.push(Item::Rule);
+    items.push(Item::Gap(TEXT_SIZE));
+    for paragraph in body.lines() {
+        if paragraph.trim().is_empty() {
+            items.push(Item::Gap(TEXT_SIZE * LEADING));
+            continue;
+        }
+        for line in wrap(paragraph, Font::Regular, TEXT_SIZE, width) {
+            items.push(Item::Text {
+                font: Font::Regular,
+                size: TEXT_SIZE,
+                text: line,
+            });
+        }
+    }
+
+    write_document(title, &paginate(&items))
+}
+
+fn text_width(text: &str, font: Font, size: f32) -> f32 {
+    let units: u32 = text
+        .chars()
+        .map(|c| match c as u32 {
+            code @ 32..=126 => HELVETICA_WIDTHS[(code - 32) as usize] as u32,
+            _ => 556,
+        })
+        .sum();
+    let scale = if font == Font::Bold { BOLD_SCALE } else { 1.0 };
+    units as f32 * size / 1000.0 * scale
+}
+
+/// Breaks `text` into lines no wider than `width`, splitting words that do
+/// not fit on a line of their own.
+fn wrap(text: &str, font: Font, size: f32, width: f32) -> Vec<String> {
+    let text = text.replace('\t', "    ");
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if current.is_empty() {
+            word.to_string()
+        } else {
+            format!("{current} {word}")
+        };
+        if text_width(&candidate, font, size) <= width {
+            current = candidate;
+            continue;
+        }
+        if !current.is_empty() {
+            lines.push(std::mem::take(&mut current));
+        }
+        for c in word.chars() {
+            current.push(c);
+            if text_width(&current, font, size) > width {
+                current.pop();
+                lines.push(std::mem::take(&mut current));
+                current.push(c);
+            }
+        }
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+/// Turns the items into one content stream per page.
+fn paginate(items: &[Item]) -> Vec<String> {
+    let top = PAGE_HEIGHT - MARGIN;
+    let mut pages = Vec::new();
+    let mut content = String::new();
+    let mut y = top;
+    for item in items {
+        let height = match item {
+            Item::Text { size, .. } => size * LEADING,
+            Item::Gap(height) => *height,
+            Item::Rule => 1.0,
+        };
+        if y - height < MARGIN && y < top {
+            pages.push(std::mem::take(&mut content));
+            y = top;
+            if matches!(item, Item::Gap(_)) {
+                continue;
+            }
+        }
+        y -= height;
+        match item {
+            Item::Text { font, size, text } => {
+                let _ = writeln!(
+                    content,
+                    "BT /{} {size} Tf 1 0 0 1 {MARGIN} {y:.2} Tm ({}) Tj ET",
+                    font.resource(),
+                    escape(text)
+                );
+            }
+            Item::Rule => {
+                let _ = writeln!(
+                    content,
+                    "0.5 w {MARGIN} {y:.2} m {:.2} {y:.2} l S",
+                    PAGE_WIDTH - MARGIN
+                );
+            }
+            Item::Gap(_) => {}
+        }
+    }
+    pages.push(content);
+    pages
+}
+
+/// A PDF string literal in WinAnsi encoding, with the delimiters escaped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            '\u{a0}'..='\u{ff}' => {
+                let _ = write!(escaped, "\\{:03o}", c as u32);
+            }
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+fn write_document(title: &str, pages: &[String]) -> Vec<u8> {
+    // Objects 1-5 are fixed: catalog, page tree, the two fonts, and info.
+    // Each page then takes two: the page and its content stream.
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        String::new(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+        format!(
+            "<< /Title ({}) /Producer (PersonalMailClient) >>",
+            escape(title)
+        ),
+    ];
+    let mut kids = Vec::new();
+    for content in pages {
+        let page_id = objects.len() + 1;
+        kids.push(format!("{page_id} 0 R"));
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            page_id + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{content}endstream",
+            content.len()
+        ));
+    }
+    objects[1] = format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        kids.join(" "),
+        pages.len()
+    );
+
+    let mut output = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(output.len());
+        let _ = write!(output, "{} 0 obj\n{object}\nendobj\n", index + 1);
+    }
+    let xref = output.len();
+    let _ = write!(
+        output,
+        "xref\n0 {}\n0000000000 65535 f \n",
+        objects.len() + 1
+    );
+    for offset in offsets {
+        let _ = writeln!(output, "{offset:010} 00000 n ");
+    }
+    let _ = write!(
+        output,
+        "trailer\n<< /Size {} /Root 1 0 R /Info 5 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    );
+    output.into_bytes()
+}
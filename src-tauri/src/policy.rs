@@ -0,0 +1,235 @@
+//! Which sender rule acts on a message, and why. Actions taken without the
+//! user picking the message, such as the block filter, go through this
+//! module so the precedence lives in one place:
+//!
+//! 1. Rules for the exact address come before rules for its domain, and at
+//!    each level the account's rule comes before the global one. The first
+//!    rule that is not neutral is the sender's status.
+//! 2. The allow-list wins over blocks when acting on mail: an allow for the
+//!    address protects it from every block, and an allow for the domain
+//!    protects it from domain blocks. A more specific block still beats a
+//!    broader allow.
+
+use crate::storage::{SenderRule, GLOBAL_SCOPE};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleLevel {
+    AccountAddress,
+    GlobalAddress,
+    AccountDomain,
+    GlobalDomain,
+}
+
+impl RuleLevel {
+    fn is_address(self) -> bool {
+        matches!(self, RuleLevel::AccountAddress | RuleLevel::GlobalAddress)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleOutcome {
+    /// Sets the sender's status.
+    Decides,
+    /// Blocks the sender, but an allow rule keeps its mail in place.
+    OverriddenByAllow,
+    /// A rule earlier in the order already decided.
+    Shadowed,
+    Neutral,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleEvaluation {
+    pub entry: String,
+    pub scope: String,
+    pub status: String,
+    pub origin: String,
+    pub origin_ref: Option<String>,
+    pub level: RuleLevel,
+    pub outcome: RuleOutcome,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyDecision {
+    pub sender_email: String,
+    /// `allowed`, `blocked`, or `neutral`, as the inbox shows it.
+    pub status: String,
+    /// Whether automated actions (the block filter) would act on the mail.
+    pub blocked: bool,
+    /// Whether the allow-list stopped a block rule.
+    pub protected: bool,
+    pub explanation: String,
+    /// Every rule that matches the sender, in precedence order.
+    pub rules: Vec<RuleEvaluation>,
+}
+
+/// A `FROM` pattern the block filter moves, minus allowed addresses inside
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockTarget {
+    pub sender: String,
+    pub except: Vec<String>,
+}
+
+/// Where `rule` sits in the order for `sender_email`, which may itself be a
+/// domain (`@example.com`); `None` when it does not match.
+fn rule_level(rule: &SenderRule, account_email: &str, sender_email: &str) -> Option<RuleLevel> {
+    let account = rule.scope == account_email;
+    if !account && rule.scope != GLOBAL_SCOPE {
+        return None;
+    }
+    let domain = sender_email.find('@').map(|at| &sender_email[at..]);
+    if !sender_email.starts_with('@') && rule.sender_email == sender_email {
+        Some(if account {
+            RuleLevel::AccountAddress
+        } else {
+            RuleLevel::GlobalAddress
+        })
+    } else if domain == Some(rule.sender_email.as_str()) {
+        Some(if account {
+            RuleLevel::AccountDomain
+        } else {
+            RuleLevel::GlobalDomain
+        })
+    } else {
+        None
+    }
+}
+
+/// Decides how the rules treat mail from `sender_email` in `account_email`.
+pub fn evaluate(account_email: &str, sender_email: &str, rules: &[SenderRule]) -> PolicyDecision {
+    let sender_email = sender_email.trim().to_lowercase();
+    let mut matched = rules
+        .iter()
+        .filter_map(|rule| Some((rule_level(rule, account_email, &sender_email)?, rule)))
+        .collect::<Vec<_>>();
+    matched.sort_by_key(|(level, _)| *level);
+
+    let levels = |status: &str| {
+        matched
+            .iter()
+            .filter(|(_, rule)| rule.status == status)
+            .map(|(level, _)| *level)
+            .collect::<Vec<_>>()
+    };
+    let deciding = matched
+        .iter()
+        .position(|(_, rule)| rule.status != "neutral");
+    let first_block = levels("blocked").first().copied();
+    // An address allow covers every block; a domain allow only domain blocks.
+    let shield = first_block.and_then(|block| {
+        levels("allowed")
+            .into_iter()
+            .find(|allow| allow.is_address() || !block.is_address())
+    });
+
+    let status = deciding.map_or("neutral", |index| matched[index].1.status.as_str());
+    let blocked = first_block.is_some() && shield.is_none();
+    let explanation = match (first_block, shield, deciding) {
+        (Some(block), Some(allow), _) => format!(
+            "A {} allow rule takes precedence over the {} block; automated actions leave this mail alone.",
+            describe(allow),
+            describe(block)
+        ),
+        (Some(block), None, _) => format!(
+            "The {} block applies; the block filter moves this mail.",
+            describe(block)
+        ),
+        (None, _, Some(index)) => format!(
+            "The {} rule marks the sender {}; nothing acts on this mail automatically.",
+            describe(matched[index].0),
+            status
+        ),
+        (None, _, None) => "No rule matches this sender.".to_string(),
+    };
+
+    let rules = matched
+        .iter()
+        .enumerate()
+        .map(|(index, (level, rule))| {
+            let outcome = if rule.status == "neutral" {
+                RuleOutcome::Neutral
+            } else if rule.status == "blocked" && shield.is_some() {
+                RuleOutcome::OverriddenByAllow
+            } else if Some(index) == deciding {
+                RuleOutcome::Decides
+            } else {
+                RuleOutcome::Shadowed
+            };
+            RuleEvaluation {
+                entry: rule.sender_email.clone(),
+                scope: rule.scope.clone(),
+                status: rule.status.clone(),
+                origin: rule.origin.clone(),
+                origin_ref: rule.origin_ref.clone(),
+                level: *level,
+                outcome,
+            }
+        })
+        .collect();
+
+    PolicyDecision {
+        sender_email,
+        status: status.to_string(),
+        blocked,
+        protected: shield.is_some(),
+        explanation,
+        rules,
+    }
+}
+
+fn describe(level: RuleLevel) -> &'static str {
+    match level {
+        RuleLevel::AccountAddress => "account address",
+        RuleLevel::GlobalAddress => "global address",
+        RuleLevel::AccountDomain => "account domain",
+        RuleLevel::GlobalDomain => "global domain",
+    }
+}
+
+/// What the block filter should move in `account_email`: every blocked
+/// address and domain that the allow-list does not protect, with allowed
+/// addresses carved out of blocked domains.
+pub fn block_targets(account_email: &str, rules: &[SenderRule]) -> Vec<BlockTarget> {
+    let applies = |rule: &&SenderRule| rule.scope == account_email || rule.scope == GLOBAL_SCOPE;
+    let mut entries = rules
+        .iter()
+        .filter(applies)
+        .filter(|rule| rule.status == "blocked")
+        .map(|rule| rule.sender_email.as_str())
+        .collect::<Vec<_>>();
+    entries.sort_unstable();
+    entries.dedup();
+
+    let allowed_addresses = rules
+        .iter()
+        .filter(applies)
+        .filter(|rule| rule.status == "allowed" && !rule.sender_email.starts_with('@'))
+        .map(|rule| rule.sender_email.as_str())
+        .collect::<Vec<_>>();
+
+    entries
+        .into_iter()
+        .filter_map(|entry| {
+            if !evaluate(account_email, entry, rules).blocked {
+                return None;
+            }
+            let mut except = Vec::new();
+            if entry.starts_with('@') {
+                except = allowed_addresses
+                    .iter()
+                    .filter(|address| address.ends_with(entry))
+                    .map(|address| address.to_string())
+                    .collect();
+                except.sort_unstable();
+                except.dedup();
+            }
+            Some(BlockTarget {
+                sender: entry.to_string(),
+                except,
+            })
+        })
+        .collect()
+}
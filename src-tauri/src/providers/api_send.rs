@@ -0,0 +1,167 @@
+//! Sending through the provider's HTTP API, for networks that block the
+//! SMTP submission ports (587 and 465). Gmail accounts use the Gmail API's
+//! `messages.send` and Outlook accounts Microsoft Graph's `sendMail`; both
+//! take the finished RFC 5322 message and an OAuth access token, and file
+//! the message in Sent themselves.
+
+use super::{oauth, ProviderError};
+use crate::models::{AuthMethod, Credentials, Provider};
+use base64::{engine::general_purpose, Engine as _};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Serialize;
+use std::future::Future;
+use std::time::Duration;
+use tracing::{info, warn};
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(120);
+const GMAIL_SEND_URL: &str = "https://gmail.googleapis.com/gmail/v1/users/me/messages/send";
+const GRAPH_SEND_URL: &str = "https://graph.microsoft.com/v1.0/me/sendMail";
+
+/// How a message left the client, as recorded on its outbox entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryPath {
+    Smtp,
+    GmailApi,
+    Graph,
+}
+
+impl DeliveryPath {
+    pub fn as_key(&self) -> &'static str {
+        match self {
+            DeliveryPath::Smtp => "smtp",
+            DeliveryPath::GmailApi => "gmail_api",
+            DeliveryPath::Graph => "graph",
+        }
+    }
+
+    pub fn from_key(value: &str) -> Option<Self> {
+        match value {
+            "smtp" => Some(DeliveryPath::Smtp),
+            "gmail_api" => Some(DeliveryPath::GmailApi),
+            "graph" => Some(DeliveryPath::Graph),
+            _ => None,
+        }
+    }
+
+    /// The HTTP API a provider's mail can be sent through, if it has one.
+    pub fn api_for(provider: Provider) -> Option<Self> {
+        match provider {
+            Provider::Gmail => Some(DeliveryPath::GmailApi),
+            Provider::Outlook => Some(DeliveryPath::Graph),
+            Provider::Yahoo | Provider::Custom | Provider::Demo => None,
+        }
+    }
+}
+
+/// Whether an SMTP failure looks like the network refusing the connection,
+/// rather than the server rejecting the login or the message.
+pub fn is_blocked(error: &ProviderError) -> bool {
+    match error {
+        ProviderError::Network(_) => true,
+        ProviderError::Authentication(_) | ProviderError::Imap(_) | ProviderError::Other(_) => {
+            false
+        }
+    }
+}
+
+/// Sends `raw` through an HTTP API path.
+pub async fn send(
+    path: DeliveryPath,
+    access_token: &SecretString,
+    raw: &[u8],
+) -> Result<(), ProviderError> {
+    let client = reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .map_err(|err| ProviderError::Other(err.to_string()))?;
+    let request = match path {
+        DeliveryPath::GmailApi => client.post(GMAIL_SEND_URL).json(&serde_json::json!({
+            "raw": general_purpose::URL_SAFE_NO_PAD.encode(raw),
+        })),
+        DeliveryPath::Graph => client
+            .post(GRAPH_SEND_URL)
+            .header("Content-Type", "text/plain")
+            .body(general_purpose::STANDARD.encode(raw)),
+        DeliveryPath::Smtp => {
+            return Err(ProviderError::Other("SMTP is not an HTTP send path".into()))
+        }
+    };
+    let response = request
+        .bearer_auth(access_token.expose_secret())
+        .send()
+        .await
+        .map_err(|err| ProviderError::Network(err.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let detail = response.text().await.unwrap_or_default();
+    let message = format!("{} refused the message ({status}): {detail}", path.as_key());
+    Err(match status.as_u16() {
+        401 | 403 => ProviderError::Authentication(message),
+        _ => ProviderError::Other(message),
+    })
+}
+
+/// Sends over SMTP, falling back to the provider's HTTP API when the
+/// connection is blocked and the account signs in with OAuth, whose access
+/// token the API takes. Returns the path the message went out on.
+pub async fn deliver<F, Fut>(
+    credentials: &Credentials,
+    raw: &[u8],
+    smtp: F,
+) -> Result<DeliveryPath, ProviderError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<(), ProviderError>>,
+{
+    let smtp_error = match smtp().await {
+        Ok(()) => return Ok(DeliveryPath::Smtp),
+        Err(err) => err,
+    };
+    let fallback = DeliveryPath::api_for(credentials.provider);
+    let Some(path) = fallback.filter(|_| is_blocked(&smtp_error)) else {
+        return Err(smtp_error);
+    };
+    if !matches!(credentials.auth, AuthMethod::OAuth2 { .. }) {
+        return Err(smtp_error);
+    }
+    let owned = credentials.clone();
+    let token = match tokio::task::spawn_blocking(move || oauth::access_token(&owned)).await {
+        Ok(Ok(token)) => token,
+        Ok(Err(err)) => {
+            warn!(email = %credentials.email, %err, "could not get an access token for API send");
+            return Err(smtp_error);
+        }
+        Err(err) => {
+            warn!(email = %credentials.email, %err, "access token task failed");
+            return Err(smtp_error);
+        }
+    };
+    warn!(
+        email = %credentials.email,
+        path = path.as_key(),
+        error = %smtp_error,
+        "SMTP unreachable; sending through the provider API"
+    );
+    send(path, &token, raw).await?;
+    info!(email = %credentials.email, path = path.as_key(), "sent through the provider API");
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_only_for_api_providers_and_network_failures() {
+        assert_eq!(
+            DeliveryPath::api_for(Provider::Gmail),
+            Some(DeliveryPath::GmailApi)
+        );
+        assert_eq!(DeliveryPath::api_for(Provider::Custom), None);
+        assert!(is_blocked(&ProviderError::Network("refused".into())));
+        assert!(!is_blocked(&ProviderError::Authentication("bad".into())));
+    }
+}
@@ -0,0 +1,299 @@
+//! Server-settings discovery for custom domains. Sources are tried from most
+//! to least authoritative: the domain's own autoconfig file, Thunderbird's
+//! ISPDB, MX-record heuristics (only when the caller allows the DNS lookup),
+//! and finally common host names that accept a TLS port.
+
+use crate::dns::{self, RecordType};
+use crate::models::Provider;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(6);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+const ISPDB_URL: &str = "https://autoconfig.thunderbird.net/v1.1";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServerSettings {
+    pub host: String,
+    pub port: u16,
+    /// "ssl" (implicit TLS), "starttls", or "plain".
+    pub security: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredSettings {
+    /// "known", "autoconfig", "ispdb", "mx", or "probe".
+    pub source: String,
+    pub provider: Provider,
+    pub imap: Option<ServerSettings>,
+    pub smtp: Option<ServerSettings>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AutodiscoverResult {
+    pub email: String,
+    pub domain: String,
+    /// Best candidate first.
+    pub candidates: Vec<DiscoveredSettings>,
+}
+
+static SERVER_BLOCK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r#"(?is)<(incomingServer|outgoingServer)\s+type="(\w+)"\s*>"#,
+        r"(.*?)</(?:incomingServer|outgoingServer)>"
+    ))
+    .expect("server block pattern is valid")
+});
+static XML_FIELD: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?is)<(hostname|port|socketType)>\s*([^<]*?)\s*</")
+        .expect("xml field pattern is valid")
+});
+
+pub async fn autodiscover(email: &str, mx_lookup: bool) -> Result<AutodiscoverResult, String> {
+    let email = email.trim().to_lowercase();
+    let domain = email
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_string())
+        .filter(|domain| domain.contains('.'))
+        .ok_or_else(|| "Enter a full email address".to_string())?;
+
+    let client = reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()
+        .map_err(|err| err.to_string())?;
+
+    let mut candidates = Vec::new();
+
+    if let Some(provider) = known_provider_for_domain(&domain) {
+        candidates.push(settings_for_provider(provider, "known"));
+    }
+
+    let autoconfig_urls = [
+        format!("https://autoconfig.{domain}/mail/config-v1.1.xml?emailaddress={email}"),
+        format!("https://{domain}/.well-known/autoconfig/mail/config-v1.1.xml"),
+    ];
+    for url in &autoconfig_urls {
+        if let Some(found) = fetch_autoconfig(&client, url, &email, "autoconfig").await {
+            candidates.push(found);
+            break;
+        }
+    }
+
+    let ispdb_url = format!("{ISPDB_URL}/{domain}");
+    if let Some(found) = fetch_autoconfig(&client, &ispdb_url, &email, "ispdb").await {
+        candidates.push(found);
+    }
+
+    if candidates.is_empty() && mx_lookup {
+        for exchanger in mx_hosts(&client, &domain).await {
+            if let Some(provider) = known_provider_for_mx(&exchanger) {
+                candidates.push(settings_for_provider(provider, "mx"));
+                break;
+            }
+            // Hosted mail often publishes its ISPDB entry under the MX domain.
+            if let Some(base) = registrable_domain(&exchanger) {
+                if base != domain {
+                    let url = format!("{ISPDB_URL}/{base}");
+                    if let Some(found) = fetch_autoconfig(&client, &url, &email, "mx").await {
+                        candidates.push(found);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    if candidates.is_empty() {
+        if let Some(found) = probe_common_hosts(&domain).await {
+            candidates.push(found);
+        }
+    }
+
+    candidates.dedup_by(|a, b| a.imap == b.imap && a.smtp == b.smtp);
+
+    Ok(AutodiscoverResult {
+        email,
+        domain,
+        candidates,
+    })
+}
+
+fn settings_for_provider(provider: Provider, source: &str) -> DiscoveredSettings {
+    DiscoveredSettings {
+        source: source.to_string(),
+        provider,
+        imap: Some(ServerSettings {
+            host: provider.imap_host().to_string(),
+            port: 993,
+            security: "ssl".into(),
+        }),
+        smtp: Some(ServerSettings {
+            host: provider.smtp_host().to_string(),
+            port: 587,
+            security: "starttls".into(),
+        }),
+    }
+}
+
+fn known_provider_for_domain(domain: &str) -> Option<Provider> {
+    match domain {
+        "gmail.com" | "googlemail.com" => Some(Provider::Gmail),
+        "outlook.com" | "hotmail.com" | "live.com" | "msn.com" => Some(Provider::Outlook),
+        "yahoo.com" | "ymail.com" | "rocketmail.com" => Some(Provider::Yahoo),
+        _ => None,
+    }
+}
+
+fn known_provider_for_mx(exchanger: &str) -> Option<Provider> {
+    let exchanger = exchanger.trim_end_matches('.').to_lowercase();
+    if exchanger.ends_with(".google.com") || exchanger.ends_with(".googlemail.com") {
+        Some(Provider::Gmail)
+    } else if exchanger.ends_with(".outlook.com") || exchanger.ends_with(".office365.com") {
+        Some(Provider::Outlook)
+    } else if exchanger.ends_with(".yahoodns.net") {
+        Some(Provider::Yahoo)
+    } else {
+        None
+    }
+}
+
+async fn fetch_autoconfig(
+    client: &reqwest::Client,
+    url: &str,
+    email: &str,
+    source: &str,
+) -> Option<DiscoveredSettings> {
+    let response = client.get(url).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let xml = response.text().await.ok()?;
+    parse_autoconfig(&xml, email, source)
+}
+
+/// Reads the first IMAP and SMTP servers from a Thunderbird `config-v1.1.xml`.
+fn parse_autoconfig(xml: &str, email: &str, source: &str) -> Option<DiscoveredSettings> {
+    let (local_part, domain) = email.rsplit_once('@')?;
+    let mut imap = None;
+    let mut smtp = None;
+
+    for block in SERVER_BLOCK.captures_iter(xml) {
+        let kind = block[2].to_lowercase();
+        let slot = match kind.as_str() {
+            "imap" if imap.is_none() => &mut imap,
+            "smtp" if smtp.is_none() => &mut smtp,
+            _ => continue,
+        };
+
+        let mut host = None;
+        let mut port = None;
+        let mut security = None;
+        for field in XML_FIELD.captures_iter(&block[3]) {
+            let value = field[2].trim().to_string();
+            match &field[1] {
+                "hostname" => host = Some(value),
+                "port" => port = value.parse::<u16>().ok(),
+                _ => security = Some(value.to_lowercase()),
+            }
+        }
+
+        if let (Some(host), Some(port)) = (host, port) {
+            *slot = Some(ServerSettings {
+                host: host
+                    .replace("%EMAILLOCALPART%", local_part)
+                    .replace("%EMAILDOMAIN%", domain),
+                port,
+                security: match security.as_deref() {
+                    Some("ssl") | Some("tls") => "ssl".into(),
+                    Some("starttls") => "starttls".into(),
+                    _ => "plain".into(),
+                },
+            });
+        }
+    }
+
+    imap.as_ref()?;
+    Some(DiscoveredSettings {
+        source: source.to_string(),
+        provider: Provider::Custom,
+        imap,
+        smtp,
+    })
+}
+
+/// MX exchangers by preference.
+async fn mx_hosts(client: &reqwest::Client, domain: &str) -> Vec<String> {
+    let mut records = dns::lookup(client, domain, RecordType::Mx)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|data| {
+            let (preference, host) = data.split_once(' ')?;
+            Some((
+                preference.parse::<u16>().ok()?,
+                host.trim_end_matches('.').to_string(),
+            ))
+        })
+        .collect::<Vec<_>>();
+    records.sort();
+    records.into_iter().map(|(_, host)| host).collect()
+}
+
+fn registrable_domain(host: &str) -> Option<String> {
+    let labels = host
+        .trim_end_matches('.')
+        .rsplit('.')
+        .take(2)
+        .collect::<Vec<_>>();
+    (labels.len() == 2).then(|| format!("{}.{}", labels[1], labels[0]))
+}
+
+async fn probe_common_hosts(domain: &str) -> Option<DiscoveredSettings> {
+    let mut imap = None;
+    for host in [format!("imap.{domain}"), format!("mail.{domain}")] {
+        if port_open(&host, 993).await {
+            imap = Some(ServerSettings {
+                host,
+                port: 993,
+                security: "ssl".into(),
+            });
+            break;
+        }
+    }
+    let imap = imap?;
+
+    let mut smtp = None;
+    for host in [format!("smtp.{domain}"), format!("mail.{domain}")] {
+        for (port, security) in [(587, "starttls"), (465, "ssl")] {
+            if port_open(&host, port).await {
+                smtp = Some(ServerSettings {
+                    host: host.clone(),
+                    port,
+                    security: security.into(),
+                });
+                break;
+            }
+        }
+        if smtp.is_some() {
+            break;
+        }
+    }
+
+    Some(DiscoveredSettings {
+        source: "probe".into(),
+        provider: Provider::Custom,
+        imap: Some(imap),
+        smtp,
+    })
+}
+
+async fn port_open(host: &str, port: u16) -> bool {
+    matches!(
+        timeout(PROBE_TIMEOUT, TcpStream::connect((host, port))).await,
+        Ok(Ok(_))
+    )
+}
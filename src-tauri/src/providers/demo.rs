@@ -0,0 +1,1219 @@
+//! A synthetic mailbox for frontend work and demos. A demo account's mail
+//! is generated on the machine from a seed: colleagues writing and replying
+//! to each other, newsletters, notification robots, and a share of spam,
+//! spread over the last few months. Every provider operation works on it
+//! the way it would on a server, so sync, storage, and analysis run
+//! unchanged. Deletes, moves, flag changes, and sent mail last until the
+//! app quits; the same configuration always generates the same mail.
+
+use super::folders::{FolderOperation, FolderStatus};
+use super::imap::{body_snippet, check_uid_validity, headers_mark_automated};
+use super::{
+    BatchResult, MailboxUids, MessageEnvelope, ProviderError, SentEnvelope, SizeRange, SyncWindow,
+    TransferMessage,
+};
+use crate::brand;
+use crate::flag_sync::flag_key;
+use crate::models::{Credentials, EmailSummary, MailAddress};
+use crate::policy::BlockTarget;
+use crate::threads;
+use chrono::{DateTime, Duration, FixedOffset, NaiveTime, Utc};
+use mailparse::{MailAddr, MailHeaderMap};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::seq::{index, SliceRandom};
+use rand::{Rng, SeedableRng};
+use secrecy::zeroize::Zeroizing;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::task::{self, JoinHandle};
+
+/// App setting holding the last [`DemoConfig`] as JSON.
+pub const SETTING_KEY: &str = "demo_mailbox";
+
+/// The address the demo account signs in as.
+pub const ACCOUNT_EMAIL: &str = "demo@example.test";
+
+const MAX_SIZE: usize = 50_000;
+const MAX_SENDERS: usize = 1_000;
+/// Generated mail goes back this many days from today.
+const SPAN_DAYS: i64 = 120;
+/// How much of a body sync reads, as `BODY.PEEK[TEXT]<0.4096>` does.
+const SYNC_BODY_BYTES: usize = 4096;
+const FOLDERS: &[&str] = &["INBOX", "Archive", "Junk", "Sent", "Trash"];
+
+const FIRST_NAMES: &[&str] = &[
+    "Ann", "Bob", "Carol", "Dev", "Elena", "Farid", "Grace", "Hiro", "Ines", "Jonas", "Kemi",
+    "Luca", "Maya", "Nikhil", "Olga", "Pedro", "Quinn", "Rosa", "Sami", "Tara",
+];
+const LAST_NAMES: &[&str] = &[
+    "Lee", "Stone", "Diaz", "Patel", "Novak", "Haddad", "Kim", "Tanaka", "Silva", "Berg", "Okafor",
+    "Rossi", "Cohen", "Iyer", "Petrova", "Costa",
+];
+const PEOPLE_DOMAINS: &[&str] = &["example.org", "example.net", "example.com"];
+
+/// Subject and topic of each conversation people start.
+const CONVERSATIONS: &[(&str, &str)] = &[
+    ("Quarterly planning", "quarterly planning"),
+    ("Lunch on Friday?", "lunch on Friday"),
+    ("Design review notes", "the design review"),
+    ("Trip photos", "the trip photos"),
+    ("Can you take a look at this?", "the contract draft"),
+    ("Notes from today's call", "today's call"),
+    ("Budget for next year", "next year's budget"),
+    ("Weekend plans", "the weekend"),
+    ("Draft proposal", "the proposal"),
+    ("Quick question about the launch", "the launch"),
+    ("Offsite agenda", "the offsite agenda"),
+    ("Book recommendation", "that book"),
+];
+/// `{topic}` is the conversation's topic, `{name}` the sender's first name.
+const PERSONAL_BODIES: &[&str] = &[
+    "Hi,\n\nFollowing up on {topic}. Could you send me your thoughts by Thursday?\n\n\
+     Thanks,\n{name}",
+    "Hey,\n\nAre you free to talk about {topic} on Tuesday at 3pm? Happy to move it if \
+     not.\n\n{name}",
+    "Hi there,\n\nI put together some notes on {topic}. Let me know what you think, no \
+     rush.\n\nBest,\n{name}",
+    "Thanks for yesterday! Sharing what we agreed on {topic} so it doesn't get lost.\n\n{name}",
+];
+
+/// Name, address, and topics for each newsletter.
+const NEWSLETTERS: &[(&str, &str, &[&str])] = &[
+    (
+        "Weekly Digest",
+        "digest@news.example.com",
+        &[
+            "Ten tools worth a look",
+            "The week in review",
+            "Reader questions answered",
+        ],
+    ),
+    (
+        "Rust Roundup",
+        "hello@roundup.example.org",
+        &[
+            "Async traits land",
+            "Faster builds with caching",
+            "Crates of the week",
+        ],
+    ),
+    (
+        "Design Notes",
+        "notes@design.example.net",
+        &[
+            "Color systems that scale",
+            "Typography basics",
+            "Designing empty states",
+        ],
+    ),
+    (
+        "Market Brief",
+        "brief@markets.example.com",
+        &[
+            "Rates hold steady",
+            "Earnings season preview",
+            "What moved this week",
+        ],
+    ),
+    (
+        "Travel Deals",
+        "deals@travel.example.com",
+        &[
+            "Weekend getaways from $99",
+            "Last-minute flights",
+            "Hotels with free cancellation",
+        ],
+    ),
+];
+
+/// Name, address, subject, and body of each notification robot. `{n}` is
+/// replaced with a number, which makes the security code a one-time code.
+const ROBOTS: &[(&str, &str, &str, &str)] = &[
+    (
+        "Build Bot",
+        "noreply@ci.example.com",
+        "Build #{n} failed on main",
+        "Build #{n} failed at the test step.\n\nView the log: https://ci.example.com/builds/{n}",
+    ),
+    (
+        "Calendar",
+        "calendar-noreply@example.com",
+        "Reminder: Team sync at 10:00",
+        "Team sync starts at 10:00 in Room {n}.\n\nJoin online: https://meet.example.com/{n}",
+    ),
+    (
+        "Example Bank",
+        "alerts@bank.example.com",
+        "Your statement is ready",
+        "Your statement ending {n} is ready to view in online banking.",
+    ),
+    (
+        "Parcel Tracker",
+        "tracking@shipping.example.net",
+        "Your parcel is out for delivery",
+        "Parcel {n} is out for delivery and should arrive today.\n\n\
+         Track it: https://shipping.example.net/track/{n}",
+    ),
+    (
+        "Account Security",
+        "security@accounts.example.com",
+        "Your verification code",
+        "Your verification code is {n}. It expires in 10 minutes.\n\n\
+         If you did not ask for it, you can ignore this message.",
+    ),
+];
+
+/// Name, address, subject, and body of each spammer. The domains imitate
+/// real brands, and none passes DMARC.
+const SPAMMERS: &[(&str, &str, &str, &str)] = &[
+    (
+        "PayPal Support",
+        "service@paypa1-support.example",
+        "Your account has been limited",
+        "We noticed unusual activity. Confirm your details within 24 hours or your account \
+         will be closed: http://paypa1-support.example/verify?id={n}",
+    ),
+    (
+        "Amazon Billing",
+        "billing@amaz0n-orders.example",
+        "Unpaid invoice #{n}",
+        "Your order #{n} could not be charged. Update your card now: \
+         http://amaz0n-orders.example/pay/{n}",
+    ),
+    (
+        "Prize Center",
+        "winner@lucky-draw.example",
+        "Congratulations, you have won!",
+        "You were selected to receive a $1000 gift card. Claim it here before it expires: \
+         http://lucky-draw.example/claim/{n}",
+    ),
+    (
+        "IT Helpdesk",
+        "helpdesk@mailbox-upgrade.example",
+        "Mailbox storage full: verify now",
+        "Your mailbox is over its limit and will stop receiving mail. Sign in to upgrade: \
+         http://mailbox-upgrade.example/login?u={n}",
+    ),
+];
+
+/// What a demo mailbox is generated from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DemoConfig {
+    /// Messages in INBOX.
+    pub size: usize,
+    /// People, newsletters, and robots writing in, not counting spammers.
+    pub senders: usize,
+    /// Share of INBOX that is spam, from 0 to 1.
+    pub spam_ratio: f64,
+    pub seed: u64,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        Self {
+            size: 500,
+            senders: 40,
+            spam_ratio: 0.1,
+            seed: 1,
+        }
+    }
+}
+
+impl DemoConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_SIZE).contains(&self.size) {
+            return Err(format!(
+                "Demo mailbox size must be between 1 and {MAX_SIZE}"
+            ));
+        }
+        if !(1..=MAX_SENDERS).contains(&self.senders) {
+            return Err(format!("Demo senders must be between 1 and {MAX_SENDERS}"));
+        }
+        if !(0.0..=1.0).contains(&self.spam_ratio) {
+            return Err("Demo spam ratio must be between 0 and 1".into());
+        }
+        Ok(())
+    }
+
+    /// Differs between configurations, so the cache of a mailbox generated
+    /// differently is discarded as it would be after a server reset.
+    fn uid_validity(&self) -> u32 {
+        let mixed = self.seed
+            ^ (self.size as u64).rotate_left(17)
+            ^ (self.senders as u64).rotate_left(37)
+            ^ self.spam_ratio.to_bits().rotate_left(51);
+        ((mixed ^ (mixed >> 32)) as u32).max(1)
+    }
+}
+
+#[derive(Clone)]
+struct StoredMessage {
+    raw: Vec<u8>,
+    /// In the form the client stores them (`seen`, `flagged`, ...).
+    flags: Vec<String>,
+    internal_date: DateTime<FixedOffset>,
+}
+
+impl StoredMessage {
+    /// The summary the IMAP provider would build from this message's
+    /// envelope and header fields.
+    fn summary(&self, uid: u32) -> Option<EmailSummary> {
+        let (headers, body_start) = mailparse::parse_headers(&self.raw).ok()?;
+        let header_text = String::from_utf8_lossy(&self.raw[..body_start]);
+        let sender = addresses(headers.get_first_value("From"))
+            .into_iter()
+            .next()
+            .unwrap_or(MailAddress {
+                display_name: None,
+                email: String::new(),
+            });
+        let dmarc_aligned = sender
+            .email
+            .rsplit_once('@')
+            .and_then(|(_, domain)| brand::header_alignment(&header_text, domain));
+        let message_id = headers.get_first_value("Message-ID");
+        let thread_id = threads::thread_id(
+            Some(&header_text),
+            headers.get_first_value("In-Reply-To").as_deref(),
+            message_id.as_deref(),
+        );
+        Some(EmailSummary {
+            uid,
+            subject: headers.get_first_value("Subject").unwrap_or_default(),
+            sender,
+            date: Some(self.internal_date.to_rfc2822()),
+            auto_submitted: headers_mark_automated(&header_text),
+            to: recipients(headers.get_first_value("To")),
+            size: Some(self.size()),
+            dmarc_aligned,
+            thread_id,
+            message_id: message_id
+                .as_deref()
+                .and_then(|value| threads::message_ids(value).into_iter().next()),
+            references: threads::references(&header_text),
+        })
+    }
+
+    /// Lite sync leaves out the body text, as it does over IMAP.
+    fn envelope(&self, uid: u32, lite: bool) -> Option<MessageEnvelope> {
+        let summary = self.summary(uid)?;
+        let body = (!lite).then(|| {
+            let text = self.text();
+            text[..text.len().min(SYNC_BODY_BYTES)].to_vec()
+        });
+        Some(MessageEnvelope {
+            summary,
+            snippet: body.as_deref().and_then(body_snippet),
+            body,
+            flags: self.flags.clone(),
+        })
+    }
+
+    fn text(&self) -> &[u8] {
+        let start = mailparse::parse_headers(&self.raw)
+            .map(|(_, start)| start)
+            .unwrap_or(self.raw.len());
+        &self.raw[start..]
+    }
+
+    fn size(&self) -> u32 {
+        self.raw.len() as u32
+    }
+
+    fn in_window(&self, window: SyncWindow) -> bool {
+        let date = self.internal_date.date_naive();
+        date >= window.since && window.before.is_none_or(|before| date < before)
+    }
+}
+
+/// Every address in a header value, groups flattened.
+fn addresses(value: Option<String>) -> Vec<MailAddress> {
+    let Some(list) = value.and_then(|value| mailparse::addrparse(&value).ok()) else {
+        return Vec::new();
+    };
+    list.iter()
+        .flat_map(|address| match address {
+            MailAddr::Single(info) => vec![info.clone()],
+            MailAddr::Group(group) => group.addrs.clone(),
+        })
+        .map(|info| MailAddress {
+            display_name: info.display_name,
+            email: info.addr,
+        })
+        .collect()
+}
+
+fn recipients(value: Option<String>) -> Vec<String> {
+    addresses(value)
+        .into_iter()
+        .map(|address| address.email.to_lowercase())
+        .filter(|email| email.contains('@'))
+        .collect()
+}
+
+struct Folder {
+    uid_next: u32,
+    messages: BTreeMap<u32, StoredMessage>,
+    subscribed: bool,
+}
+
+impl Folder {
+    fn new() -> Self {
+        Self {
+            uid_next: 1,
+            messages: BTreeMap::new(),
+            subscribed: true,
+        }
+    }
+
+    fn add(&mut self, message: StoredMessage) -> u32 {
+        let uid = self.uid_next;
+        self.uid_next += 1;
+        self.messages.insert(uid, message);
+        uid
+    }
+}
+
+struct Mailbox {
+    config: DemoConfig,
+    uid_validity: u32,
+    folders: BTreeMap<String, Folder>,
+}
+
+impl Mailbox {
+    fn generate(email: &str, config: &DemoConfig) -> Self {
+        let mut folders = FOLDERS
+            .iter()
+            .map(|name| (name.to_string(), Folder::new()))
+            .collect::<BTreeMap<_, _>>();
+        let (inbox, sent) = generate(email, config);
+        for (folder, messages) in [("INBOX", inbox), ("Sent", sent)] {
+            let folder = folders.get_mut(folder).expect("standard folder");
+            for message in messages {
+                folder.add(message);
+            }
+        }
+        Self {
+            config: *config,
+            uid_validity: config.uid_validity(),
+            folders,
+        }
+    }
+
+    fn folder(&self, name: &str) -> Result<&Folder, ProviderError> {
+        self.folders.get(name).ok_or_else(|| no_such_folder(name))
+    }
+
+    fn folder_mut(&mut self, name: &str) -> Result<&mut Folder, ProviderError> {
+        self.folders
+            .get_mut(name)
+            .ok_or_else(|| no_such_folder(name))
+    }
+
+    /// Moves the messages of `uids` that exist; returns how many did.
+    fn move_uids(&mut self, from: &str, uids: &[u32], to: &str) -> Result<usize, ProviderError> {
+        let source = self.folder_mut(from)?;
+        let moved = uids
+            .iter()
+            .filter_map(|uid| source.messages.remove(uid))
+            .collect::<Vec<_>>();
+        let target = self
+            .folders
+            .entry(to.to_string())
+            .or_insert_with(Folder::new);
+        let count = moved.len();
+        for message in moved {
+            target.add(message);
+        }
+        Ok(count)
+    }
+}
+
+fn no_such_folder(name: &str) -> ProviderError {
+    ProviderError::Imap(format!("No such mailbox: {name}"))
+}
+
+static MAILBOXES: Lazy<Mutex<HashMap<String, Mailbox>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Sets up `email`'s mailbox from `config`. A mailbox already generated
+/// from the same config keeps the changes made to it.
+pub fn configure(email: &str, config: &DemoConfig) -> Result<(), String> {
+    config.validate()?;
+    let mut mailboxes = MAILBOXES.lock();
+    if mailboxes.get(email).map(|mailbox| mailbox.config) != Some(*config) {
+        mailboxes.insert(email.to_string(), Mailbox::generate(email, config));
+    }
+    Ok(())
+}
+
+/// Runs `run` on the account's mailbox, generating a default one if the
+/// account was never configured.
+fn with_mailbox<T>(
+    credentials: &Credentials,
+    run: impl FnOnce(&mut Mailbox) -> Result<T, ProviderError>,
+) -> Result<T, ProviderError> {
+    let mut mailboxes = MAILBOXES.lock();
+    let mailbox = mailboxes
+        .entry(credentials.email.clone())
+        .or_insert_with(|| Mailbox::generate(&credentials.email, &DemoConfig::default()));
+    run(mailbox)
+}
+
+pub fn verify_credentials(credentials: &Credentials) -> Result<(), ProviderError> {
+    with_mailbox(credentials, |_| Ok(()))
+}
+
+pub fn fetch_recent(
+    credentials: &Credentials,
+    folder: &str,
+    limit: usize,
+) -> Result<Vec<EmailSummary>, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        Ok(mailbox
+            .folder(folder)?
+            .messages
+            .iter()
+            .rev()
+            .take(limit.min(200))
+            .filter_map(|(uid, message)| message.summary(*uid))
+            .collect())
+    })
+}
+
+pub fn fetch_all(
+    credentials: &Credentials,
+    since_uid: Option<u32>,
+    chunk_size: usize,
+    window: Option<SyncWindow>,
+    lite: bool,
+) -> (
+    UnboundedReceiver<BatchResult>,
+    JoinHandle<Result<(), ProviderError>>,
+) {
+    let credentials = credentials.clone();
+    let chunk_size = chunk_size.max(1);
+    let (tx, rx) = unbounded_channel();
+    let handle = task::spawn_blocking(move || {
+        let (uid_validity, uids) = with_mailbox(&credentials, |mailbox| {
+            let uids = mailbox
+                .folder("INBOX")?
+                .messages
+                .iter()
+                .filter(|(uid, _)| since_uid.is_none_or(|since| **uid > since))
+                .filter(|(_, message)| window.is_none_or(|window| message.in_window(window)))
+                .map(|(uid, _)| *uid)
+                .collect::<Vec<_>>();
+            Ok((mailbox.uid_validity, uids))
+        })?;
+
+        let total = uids.len().div_ceil(chunk_size);
+        for (index, chunk) in uids.chunks(chunk_size).enumerate() {
+            let messages = with_mailbox(&credentials, |mailbox| {
+                let inbox = mailbox.folder("INBOX")?;
+                Ok(chunk
+                    .iter()
+                    .filter_map(|uid| inbox.messages.get(uid)?.envelope(*uid, lite))
+                    .collect::<Vec<_>>())
+            })?;
+            let result = BatchResult {
+                index: index + 1,
+                total,
+                requested: chunk.len(),
+                fetched: messages.len(),
+                messages,
+                uid_validity: Some(uid_validity),
+            };
+            tx.send(result)
+                .map_err(|_| ProviderError::Other("progress channel closed".into()))?;
+        }
+        Ok(())
+    });
+    (rx, handle)
+}
+
+pub fn fetch_raw_message(
+    credentials: &Credentials,
+    uid: u32,
+) -> Result<Option<Zeroizing<Vec<u8>>>, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        let inbox = mailbox.folder("INBOX")?;
+        let raw = inbox.messages.get(&uid).map(|message| message.raw.clone());
+        Ok(raw.map(Zeroizing::new))
+    })
+}
+
+/// Moves a folder's messages to Trash.
+pub fn delete_messages(
+    credentials: &Credentials,
+    folder: &str,
+    uids: &[u32],
+    expected_uid_validity: Option<u32>,
+) -> Result<(), ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        check_uid_validity(folder, expected_uid_validity, Some(mailbox.uid_validity))?;
+        mailbox.move_uids(folder, uids, credentials.provider.trash_folder())?;
+        Ok(())
+    })
+}
+
+pub fn move_messages(
+    credentials: &Credentials,
+    uids: &[u32],
+    target_folder: &str,
+) -> Result<usize, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        mailbox.move_uids("INBOX", uids, target_folder)
+    })
+}
+
+pub fn store_flags(
+    credentials: &Credentials,
+    folder: &str,
+    uids: &[u32],
+    expected_uid_validity: Option<u32>,
+    flags: &[String],
+    add: bool,
+) -> Result<Vec<(u32, Vec<String>)>, ProviderError> {
+    let flags = flags.iter().map(|flag| flag_key(flag)).collect::<Vec<_>>();
+    with_mailbox(credentials, |mailbox| {
+        check_uid_validity(folder, expected_uid_validity, Some(mailbox.uid_validity))?;
+        let messages = &mut mailbox.folder_mut(folder)?.messages;
+        Ok(uids
+            .iter()
+            .filter_map(|uid| {
+                let message = messages.get_mut(uid)?;
+                if add {
+                    for flag in &flags {
+                        if !message.flags.contains(flag) {
+                            message.flags.push(flag.clone());
+                        }
+                    }
+                    message.flags.sort();
+                } else {
+                    message.flags.retain(|flag| !flags.contains(flag));
+                }
+                Some((*uid, message.flags.clone()))
+            })
+            .collect())
+    })
+}
+
+pub fn inbox_uids(credentials: &Credentials) -> Result<MailboxUids, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        let inbox = mailbox.folder("INBOX")?;
+        Ok(MailboxUids {
+            uid_validity: Some(mailbox.uid_validity),
+            uid_next: Some(inbox.uid_next),
+            uids: inbox.messages.keys().copied().collect(),
+        })
+    })
+}
+
+pub fn fetch_sent(
+    credentials: &Credentials,
+    folder: &str,
+    limit: usize,
+) -> Result<Vec<SentEnvelope>, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        let folder = mailbox.folder(folder)?;
+        let skip = folder.messages.len().saturating_sub(limit);
+        Ok(folder
+            .messages
+            .iter()
+            .skip(skip)
+            .filter_map(|(uid, message)| {
+                let (headers, _) = mailparse::parse_headers(&message.raw).ok()?;
+                let mut sent_to = recipients(headers.get_first_value("To"));
+                sent_to.extend(recipients(headers.get_first_value("Cc")));
+                Some(SentEnvelope {
+                    uid: *uid,
+                    subject: headers.get_first_value("Subject").unwrap_or_default(),
+                    recipients: sent_to,
+                    sent_at: Some(message.internal_date.timestamp()),
+                })
+            })
+            .collect())
+    })
+}
+
+pub fn list_folders(credentials: &Credentials) -> Result<Vec<FolderStatus>, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        Ok(mailbox
+            .folders
+            .iter()
+            .map(|(path, folder)| FolderStatus {
+                path: path.clone(),
+                delimiter: Some("/".to_string()),
+                selectable: true,
+                subscribed: folder.subscribed,
+                exists: folder.messages.len() as u32,
+                unseen: folder
+                    .messages
+                    .values()
+                    .filter(|message| !message.flags.iter().any(|flag| flag == "seen"))
+                    .count() as u32,
+            })
+            .collect())
+    })
+}
+
+pub fn fetch_envelopes(
+    credentials: &Credentials,
+    uids: &[u32],
+) -> Result<Vec<MessageEnvelope>, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        let inbox = mailbox.folder("INBOX")?;
+        Ok(uids
+            .iter()
+            .filter_map(|uid| inbox.messages.get(uid)?.envelope(*uid, false))
+            .collect())
+    })
+}
+
+pub fn list_uids_after(
+    credentials: &Credentials,
+    folder: &str,
+    after_uid: u32,
+) -> Result<Vec<u32>, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        Ok(mailbox
+            .folder(folder)?
+            .messages
+            .range(after_uid.saturating_add(1)..)
+            .map(|(uid, _)| *uid)
+            .collect())
+    })
+}
+
+pub fn search_by_size(
+    credentials: &Credentials,
+    folder: &str,
+    range: SizeRange,
+    limit: usize,
+) -> Result<Vec<EmailSummary>, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        let mut sized = mailbox
+            .folder(folder)?
+            .messages
+            .iter()
+            .filter(|(_, message)| {
+                let size = message.size();
+                range.larger_than.is_none_or(|larger| size > larger)
+                    && range.smaller_than.is_none_or(|smaller| size < smaller)
+            })
+            .collect::<Vec<_>>();
+        sized.sort_unstable_by(|a, b| b.1.size().cmp(&a.1.size()).then(b.0.cmp(a.0)));
+        Ok(sized
+            .into_iter()
+            .take(limit)
+            .filter_map(|(uid, message)| message.summary(*uid))
+            .collect())
+    })
+}
+
+pub fn fetch_for_transfer(
+    credentials: &Credentials,
+    folder: &str,
+    uids: &[u32],
+) -> Result<Vec<TransferMessage>, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        let folder = mailbox.folder(folder)?;
+        let mut messages = uids
+            .iter()
+            .filter_map(|uid| {
+                let message = folder.messages.get(uid)?;
+                Some(TransferMessage {
+                    uid: *uid,
+                    flags: message.flags.clone(),
+                    internal_date: Some(message.internal_date),
+                    raw: message.raw.clone(),
+                })
+            })
+            .filter(|message| !message.flags.iter().any(|flag| flag == "deleted"))
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|message| message.uid);
+        Ok(messages)
+    })
+}
+
+/// Files messages in `folder`, creating it if needed, with their flags
+/// and dates.
+pub fn append_messages(
+    credentials: &Credentials,
+    folder: &str,
+    messages: Vec<TransferMessage>,
+) -> Result<Vec<u32>, ProviderError> {
+    let now = Utc::now().fixed_offset();
+    with_mailbox(credentials, |mailbox| {
+        let folder = mailbox
+            .folders
+            .entry(folder.to_string())
+            .or_insert_with(Folder::new);
+        let mut appended = Vec::with_capacity(messages.len());
+        for message in messages {
+            appended.push(message.uid);
+            let flags = message
+                .flags
+                .iter()
+                .map(|flag| flag_key(flag))
+                // Server-managed flags cannot be set by a client.
+                .filter(|flag| !matches!(flag.as_str(), "recent" | "may-create" | "deleted"))
+                .collect();
+            folder.add(StoredMessage {
+                raw: message.raw,
+                flags,
+                internal_date: message.internal_date.unwrap_or(now),
+            });
+        }
+        Ok(appended)
+    })
+}
+
+pub fn copy_message(
+    credentials: &Credentials,
+    uid: u32,
+    source_folder: &str,
+    target_folder: &str,
+) -> Result<(), ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        let message = mailbox
+            .folder(source_folder)?
+            .messages
+            .get(&uid)
+            .cloned()
+            .ok_or_else(|| {
+                ProviderError::Other(format!("Message {uid} not found in {source_folder}"))
+            })?;
+        mailbox.folder_mut(target_folder)?.add(message);
+        Ok(())
+    })
+}
+
+pub fn manage_folder(
+    credentials: &Credentials,
+    operation: FolderOperation,
+) -> Result<(), ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        match operation {
+            FolderOperation::Create { path } => {
+                if mailbox.folders.contains_key(&path) {
+                    return Err(ProviderError::Imap(format!(
+                        "Mailbox already exists: {path}"
+                    )));
+                }
+                mailbox.folders.insert(path, Folder::new());
+            }
+            FolderOperation::Rename { from, to } => {
+                if mailbox.folders.contains_key(&to) {
+                    return Err(ProviderError::Imap(format!("Mailbox already exists: {to}")));
+                }
+                let folder = mailbox
+                    .folders
+                    .remove(&from)
+                    .ok_or_else(|| no_such_folder(&from))?;
+                mailbox.folders.insert(to, folder);
+            }
+            FolderOperation::Delete { path } => {
+                mailbox
+                    .folders
+                    .remove(&path)
+                    .ok_or_else(|| no_such_folder(&path))?;
+            }
+            FolderOperation::SetSubscribed { path, subscribed } => {
+                mailbox.folder_mut(&path)?.subscribed = subscribed;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Moves INBOX mail from each target's sender to `target_folder`, matching
+/// `From` by substring as IMAP SEARCH does.
+pub fn move_blocked(
+    credentials: &Credentials,
+    targets: &[BlockTarget],
+    target_folder: &str,
+) -> Result<usize, ProviderError> {
+    with_mailbox(credentials, |mailbox| {
+        let mut moved = 0;
+        for target in targets.iter().filter(|target| !target.sender.is_empty()) {
+            let sender = target.sender.to_lowercase();
+            let uids = mailbox
+                .folder("INBOX")?
+                .messages
+                .iter()
+                .filter(|(_, message)| {
+                    let from = mailparse::parse_headers(&message.raw)
+                        .ok()
+                        .and_then(|(headers, _)| headers.get_first_value("From"))
+                        .unwrap_or_default()
+                        .to_lowercase();
+                    from.contains(&sender)
+                        && !target
+                            .except
+                            .iter()
+                            .any(|allowed| from.contains(&allowed.to_lowercase()))
+                })
+                .map(|(uid, _)| *uid)
+                .collect::<Vec<_>>();
+            moved += mailbox.move_uids("INBOX", &uids, target_folder)?;
+        }
+        Ok(moved)
+    })
+}
+
+enum SenderKind {
+    Person,
+    Newsletter(usize),
+    Robot(usize),
+}
+
+struct Sender {
+    name: String,
+    address: String,
+    kind: SenderKind,
+}
+
+/// `count` senders: about a sixth each newsletters and robots, as far as
+/// the tables go, and people for the rest.
+fn senders(count: usize) -> Vec<Sender> {
+    let newsletters = (count / 6).min(NEWSLETTERS.len());
+    let robots = (count / 6).min(ROBOTS.len());
+    let people = (0..count - newsletters - robots).map(|index| {
+        let first = FIRST_NAMES[index % FIRST_NAMES.len()];
+        let last = LAST_NAMES[(index / FIRST_NAMES.len()) % LAST_NAMES.len()];
+        let round = index / (FIRST_NAMES.len() * LAST_NAMES.len());
+        let suffix = if round == 0 {
+            String::new()
+        } else {
+            round.to_string()
+        };
+        Sender {
+            name: format!("{first} {last}"),
+            address: format!(
+                "{}.{}{suffix}@{}",
+                first.to_lowercase(),
+                last.to_lowercase(),
+                PEOPLE_DOMAINS[index % PEOPLE_DOMAINS.len()]
+            ),
+            kind: SenderKind::Person,
+        }
+    });
+    let newsletters = (0..newsletters).map(|index| Sender {
+        name: NEWSLETTERS[index].0.to_string(),
+        address: NEWSLETTERS[index].1.to_string(),
+        kind: SenderKind::Newsletter(index),
+    });
+    let robots = (0..robots).map(|index| Sender {
+        name: ROBOTS[index].0.to_string(),
+        address: ROBOTS[index].1.to_string(),
+        kind: SenderKind::Robot(index),
+    });
+    people.chain(newsletters).chain(robots).collect()
+}
+
+/// A message before it is given a date.
+struct Draft {
+    from: String,
+    to: String,
+    subject: String,
+    body: String,
+    message_id: String,
+    /// The conversation so far, oldest first; the last is replied to.
+    references: Vec<String>,
+    headers: Vec<(&'static str, String)>,
+}
+
+impl Draft {
+    fn raw(&self, date: DateTime<FixedOffset>) -> Vec<u8> {
+        let mut headers = vec![
+            ("Date", date.to_rfc2822()),
+            ("From", self.from.clone()),
+            ("To", self.to.clone()),
+            ("Subject", self.subject.clone()),
+            ("Message-ID", self.message_id.clone()),
+        ];
+        if let Some(parent) = self.references.last() {
+            headers.push(("In-Reply-To", parent.clone()));
+            headers.push(("References", self.references.join(" ")));
+        }
+        headers.push(("MIME-Version", "1.0".to_string()));
+        headers.push(("Content-Type", "text/plain; charset=utf-8".to_string()));
+        headers.extend(self.headers.iter().cloned());
+
+        let mut raw = String::new();
+        for (name, value) in headers {
+            raw.push_str(&format!("{name}: {value}\r\n"));
+        }
+        raw.push_str("\r\n");
+        raw.push_str(&self.body.replace('\n', "\r\n"));
+        raw.push_str("\r\n");
+        raw.into_bytes()
+    }
+}
+
+fn domain(address: &str) -> &str {
+    address
+        .rsplit_once('@')
+        .map_or(address, |(_, domain)| domain)
+}
+
+fn authentication_results(address: &str, pass: bool) -> (&'static str, String) {
+    let verdict = if pass { "pass" } else { "fail" };
+    (
+        "Authentication-Results",
+        format!(
+            "mx.example.test; dmarc={verdict} header.from={}",
+            domain(address)
+        ),
+    )
+}
+
+/// The INBOX and Sent folder contents for `config`, each oldest first.
+fn generate(email: &str, config: &DemoConfig) -> (Vec<StoredMessage>, Vec<StoredMessage>) {
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut senders = senders(config.senders);
+    senders.shuffle(&mut rng);
+    let me = format!("Demo User <{email}>");
+    let today = Utc::now()
+        .date_naive()
+        .and_time(NaiveTime::MIN)
+        .and_utc()
+        .fixed_offset();
+    let mut dates = (0..config.size)
+        .map(|_| today - Duration::minutes(rng.gen_range(1..=SPAN_DAYS * 24 * 60)))
+        .collect::<Vec<_>>();
+    dates.sort_unstable();
+    let spam_count = (config.size as f64 * config.spam_ratio).round() as usize;
+    let spam = index::sample(&mut rng, config.size, spam_count)
+        .into_iter()
+        .collect::<HashSet<_>>();
+
+    let mut inbox = Vec::with_capacity(config.size);
+    let mut sent = Vec::new();
+    // Personal mail so far, for replies to pick from: the references a
+    // reply would carry, and the conversation it belongs to.
+    let mut personal: Vec<(Vec<String>, usize)> = Vec::new();
+    for (index, date) in dates.into_iter().enumerate() {
+        let n = rng.gen_range(100_000..1_000_000).to_string();
+        let message_id = |domain: &str| format!("<demo-{index}.{}@{domain}>", config.seed);
+        let mut person = None;
+        let draft = if spam.contains(&index) {
+            let (name, address, subject, body) = *SPAMMERS.choose(&mut rng).expect("spammers");
+            Draft {
+                from: format!("{name} <{address}>"),
+                to: me.clone(),
+                subject: subject.replace("{n}", &n),
+                body: body.replace("{n}", &n),
+                message_id: message_id(domain(address)),
+                references: Vec::new(),
+                headers: vec![authentication_results(address, false)],
+            }
+        } else {
+            // Squaring skews the pick, so a few senders write most of the
+            // mail, as in a real inbox.
+            let pick = (rng.gen::<f64>().powi(2) * senders.len() as f64) as usize;
+            let sender = &senders[pick.min(senders.len() - 1)];
+            let from = format!("{} <{}>", sender.name, sender.address);
+            let mut headers = vec![authentication_results(&sender.address, true)];
+            match sender.kind {
+                SenderKind::Person => {
+                    let (references, conversation) = if !personal.is_empty() && rng.gen_bool(0.3) {
+                        personal[rng.gen_range(0..personal.len())].clone()
+                    } else {
+                        (Vec::new(), rng.gen_range(0..CONVERSATIONS.len()))
+                    };
+                    let (subject, topic) = CONVERSATIONS[conversation];
+                    let first_name = sender.name.split(' ').next().unwrap_or_default();
+                    let draft = Draft {
+                        from,
+                        to: me.clone(),
+                        subject: if references.is_empty() {
+                            subject.to_string()
+                        } else {
+                            format!("Re: {subject}")
+                        },
+                        body: PERSONAL_BODIES
+                            .choose(&mut rng)
+                            .expect("bodies")
+                            .replace("{topic}", topic)
+                            .replace("{name}", first_name),
+                        message_id: message_id(domain(&sender.address)),
+                        references,
+                        headers,
+                    };
+                    let mut thread = draft.references.clone();
+                    thread.push(draft.message_id.clone());
+                    personal.push((thread, conversation));
+                    person = Some(sender);
+                    draft
+                }
+                SenderKind::Newsletter(which) => {
+                    let (name, address, topics) = NEWSLETTERS[which];
+                    let topic = topics.choose(&mut rng).expect("topics");
+                    let issue = 100 + index;
+                    let site = domain(address);
+                    headers.push(("List-Id", format!("{name} <{}>", address.replace('@', "."))));
+                    headers.push(("List-Unsubscribe", format!("<https://{site}/unsubscribe>")));
+                    headers.push(("Precedence", "bulk".to_string()));
+                    Draft {
+                        from,
+                        to: me.clone(),
+                        subject: format!("{name} #{issue}: {topic}"),
+                        body: format!(
+                            "This week in {name}: {topic}.\n\n\
+                             Read online: https://{site}/issues/{issue}?utm_source=newsletter\n\n\
+                             Unsubscribe: https://{site}/unsubscribe"
+                        ),
+                        message_id: message_id(site),
+                        references: Vec::new(),
+                        headers,
+                    }
+                }
+                SenderKind::Robot(which) => {
+                    let (_, address, subject, body) = ROBOTS[which];
+                    headers.push(("Auto-Submitted", "auto-generated".to_string()));
+                    Draft {
+                        from,
+                        to: me.clone(),
+                        subject: subject.replace("{n}", &n),
+                        body: body.replace("{n}", &n),
+                        message_id: message_id(domain(address)),
+                        references: Vec::new(),
+                        headers,
+                    }
+                }
+            }
+        };
+
+        let mut flags = Vec::new();
+        let old = today - date > Duration::days(2);
+        if rng.gen_bool(if old { 0.85 } else { 0.3 }) {
+            flags.push("seen".to_string());
+        }
+        if person.is_some() && rng.gen_bool(0.06) {
+            flags.insert(0, "flagged".to_string());
+        }
+
+        // Some personal mail got an answer a few hours later.
+        if let Some(sender) = person.filter(|_| rng.gen_bool(0.15)) {
+            let reply_date = date + Duration::minutes(rng.gen_range(10..6 * 60));
+            let reply = Draft {
+                from: me.clone(),
+                to: format!("{} <{}>", sender.name, sender.address),
+                subject: format!("Re: {}", draft.subject.trim_start_matches("Re: ")),
+                body: "Thanks, sounds good to me.\n\nDemo".to_string(),
+                message_id: format!("<demo-sent-{index}.{}@{}>", config.seed, domain(email)),
+                references: personal
+                    .last()
+                    .map(|(thread, _)| thread.clone())
+                    .unwrap_or_default(),
+                headers: Vec::new(),
+            };
+            sent.push(StoredMessage {
+                raw: reply.raw(reply_date),
+                flags: vec!["seen".to_string()],
+                internal_date: reply_date,
+            });
+        }
+
+        inbox.push(StoredMessage {
+            raw: draft.raw(date),
+            flags,
+            internal_date: date,
+        });
+    }
+    sent.sort_by_key(|message| message.internal_date);
+    (inbox, sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Provider;
+    use secrecy::SecretString;
+
+    fn credentials(email: &str) -> Credentials {
+        Credentials::new(
+            Provider::Demo,
+            email.to_string(),
+            SecretString::new(String::new()),
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn generation_follows_the_config() {
+        let config = DemoConfig {
+            size: 200,
+            senders: 12,
+            spam_ratio: 0.25,
+            seed: 7,
+        };
+        let (inbox, _) = generate(ACCOUNT_EMAIL, &config);
+        let summaries = inbox
+            .iter()
+            .enumerate()
+            .map(|(index, message)| message.summary(index as u32 + 1).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(summaries.len(), 200);
+        let spam = summaries
+            .iter()
+            .filter(|summary| summary.dmarc_aligned == Some(false))
+            .count();
+        assert_eq!(spam, 50);
+        let senders = summaries
+            .iter()
+            .filter(|summary| summary.dmarc_aligned == Some(true))
+            .map(|summary| summary.sender.email.as_str())
+            .collect::<HashSet<_>>();
+        assert!(senders.len() <= 12);
+        assert!(summaries.iter().any(|summary| summary.auto_submitted));
+        assert!(summaries
+            .iter()
+            .all(|summary| summary.to == [ACCOUNT_EMAIL]));
+
+        let (again, _) = generate(ACCOUNT_EMAIL, &config);
+        assert!(inbox.iter().zip(&again).all(|(a, b)| a.raw == b.raw));
+    }
+
+    #[test]
+    fn changes_stay_until_the_mailbox_is_reconfigured() {
+        let email = "changes@example.test";
+        let credentials = credentials(email);
+        let config = DemoConfig {
+            size: 20,
+            ..DemoConfig::default()
+        };
+        configure(email, &config).unwrap();
+        let uids = inbox_uids(&credentials).unwrap();
+        assert_eq!(uids.uids, (1..=20).collect::<Vec<_>>());
+
+        delete_messages(&credentials, "INBOX", &[3, 4], uids.uid_validity).unwrap();
+        let flagged = ["\\Flagged".to_string()];
+        let updated = store_flags(
+            &credentials,
+            "INBOX",
+            &[5],
+            uids.uid_validity,
+            &flagged,
+            true,
+        )
+        .unwrap();
+        assert!(updated[0].1.contains(&"flagged".to_string()));
+        assert_eq!(fetch_recent(&credentials, "Trash", 10).unwrap().len(), 2);
+
+        configure(email, &config).unwrap();
+        assert_eq!(inbox_uids(&credentials).unwrap().uids.len(), 18);
+        configure(email, &DemoConfig { seed: 2, ..config }).unwrap();
+        let reset = inbox_uids(&credentials).unwrap();
+        assert_eq!(reset.uids.len(), 20);
+        assert_ne!(reset.uid_validity, uids.uid_validity);
+        assert!(delete_messages(&credentials, "INBOX", &[1], uids.uid_validity).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+//! Step-by-step connection check for the account setup screen. Each stage
+//! runs only if the previous one passed, so the first failure pinpoints
+//! where a connection breaks (DNS, firewall, TLS interception, login, ...).
+
+use crate::models::Credentials;
+use crate::providers::preflight::{self, LoginIssue};
+use crate::providers::session;
+use ::imap_proto::types::Capability;
+use native_tls::TlsConnector;
+use secrecy::ExposeSecret;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use tokio::task;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticStage {
+    /// "dns", "tcp", "tls", "greeting", "login", "capabilities", or "select".
+    pub name: &'static str,
+    /// "pass", "fail", or "skipped".
+    pub status: &'static str,
+    pub duration_ms: u64,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionDiagnostics {
+    pub host: String,
+    pub port: u16,
+    pub ok: bool,
+    pub stages: Vec<DiagnosticStage>,
+    /// Set when the login stage failed with a recognizable provider error.
+    pub login_issue: Option<LoginIssue>,
+}
+
+#[derive(Default)]
+struct StageLog {
+    stages: Vec<DiagnosticStage>,
+    login_issue: Option<LoginIssue>,
+}
+
+impl StageLog {
+    fn record<T>(
+        &mut self,
+        name: &'static str,
+        started: Instant,
+        result: Result<(T, String), String>,
+    ) -> Option<T> {
+        let duration_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok((value, detail)) => {
+                self.stages.push(DiagnosticStage {
+                    name,
+                    status: "pass",
+                    duration_ms,
+                    detail,
+                });
+                Some(value)
+            }
+            Err(detail) => {
+                self.stages.push(DiagnosticStage {
+                    name,
+                    status: "fail",
+                    duration_ms,
+                    detail,
+                });
+                None
+            }
+        }
+    }
+
+    fn skip_remaining(&mut self, names: &[&'static str]) {
+        let done = self.stages.len();
+        for &name in names.iter().skip(done) {
+            self.stages.push(DiagnosticStage {
+                name,
+                status: "skipped",
+                duration_ms: 0,
+                detail: String::new(),
+            });
+        }
+    }
+}
+
+const STAGES: [&str; 7] = [
+    "dns",
+    "tcp",
+    "tls",
+    "greeting",
+    "login",
+    "capabilities",
+    "select",
+];
+
+/// Runs every stage against the account's IMAP server. Login, capability,
+/// and select stages are skipped when no password is available.
+pub async fn diagnose(credentials: &Credentials) -> ConnectionDiagnostics {
+    let credentials = credentials.clone();
+    let host = credentials
+        .custom_host
+        .clone()
+        .unwrap_or_else(|| credentials.provider.imap_host().to_string());
+    let port = credentials.custom_port.unwrap_or(993);
+
+    let blocking_host = host.clone();
+    let outcome =
+        task::spawn_blocking(move || diagnose_blocking(&credentials, &blocking_host, port)).await;
+
+    let log = outcome.unwrap_or_else(|err| {
+        let mut log = StageLog::default();
+        log.record::<()>(
+            "dns",
+            Instant::now(),
+            Err(format!("Background task failure: {err}")),
+        );
+        log.skip_remaining(&STAGES);
+        log
+    });
+
+    ConnectionDiagnostics {
+        ok: !log.stages.iter().any(|stage| stage.status == "fail"),
+        host,
+        port,
+        stages: log.stages,
+        login_issue: log.login_issue,
+    }
+}
+
+fn diagnose_blocking(credentials: &Credentials, host: &str, port: u16) -> StageLog {
+    let mut log = StageLog::default();
+    run_stages(&mut log, credentials, host, port);
+    log.skip_remaining(&STAGES);
+    log
+}
+
+/// Records stages in order and returns at the first failure.
+fn run_stages(log: &mut StageLog, credentials: &Credentials, host: &str, port: u16) {
+    let started = Instant::now();
+    let addresses = (host, port)
+        .to_socket_addrs()
+        .map(|addresses| addresses.collect::<Vec<SocketAddr>>())
+        .map_err(|err| format!("Could not resolve {host}: {err}"))
+        .and_then(|addresses| {
+            if addresses.is_empty() {
+                return Err(format!("{host} has no addresses"));
+            }
+            let listed = addresses
+                .iter()
+                .map(|address| address.ip().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            Ok((addresses, listed))
+        });
+    let Some(addresses) = log.record("dns", started, addresses) else {
+        return;
+    };
+
+    let started = Instant::now();
+    let tcp = connect_any(&addresses).map(|(stream, address)| {
+        let detail = format!("Connected to {address}");
+        (stream, detail)
+    });
+    let Some(tcp) = log.record("tcp", started, tcp) else {
+        return;
+    };
+    let _ = tcp.set_read_timeout(Some(READ_TIMEOUT));
+    let _ = tcp.set_write_timeout(Some(READ_TIMEOUT));
+
+    let started = Instant::now();
+    let tls = TlsConnector::builder()
+        .build()
+        .map_err(|err| err.to_string())
+        .and_then(|connector| {
+            connector
+                .connect(host, tcp)
+                .map_err(|err| format!("TLS handshake failed: {err}"))
+        })
+        .map(|stream| {
+            let detail = match stream.peer_certificate() {
+                Ok(Some(certificate)) => match certificate.to_der() {
+                    Ok(der) => format!(
+                        "Certificate valid for {host}; SHA-256 {}",
+                        hex::encode(Sha256::digest(&der))
+                    ),
+                    Err(err) => format!("Certificate unreadable: {err}"),
+                },
+                Ok(None) => "Server sent no certificate".to_string(),
+                Err(err) => format!("Certificate unavailable: {err}"),
+            };
+            (stream, detail)
+        });
+    let Some(tls) = log.record("tls", started, tls) else {
+        return;
+    };
+
+    let started = Instant::now();
+    let mut client = ::imap::Client::new(tls);
+    let greeting = client
+        .read_greeting()
+        .map(|line| {
+            let detail = String::from_utf8_lossy(&line).trim().to_string();
+            ((), detail)
+        })
+        .map_err(|err| format!("No IMAP greeting: {err}"));
+    if log.record("greeting", started, greeting).is_none() {
+        return;
+    }
+
+    if credentials.password.expose_secret().is_empty() {
+        return;
+    }
+
+    let started = Instant::now();
+    let login = match session::sign_in(client, credentials) {
+        Ok(session) => Ok((session, format!("Signed in as {}", credentials.email))),
+        Err(error) => {
+            let issue = preflight::classify(credentials.provider, &error);
+            let detail = format!("{} ({error})", issue.message);
+            log.login_issue = Some(issue);
+            Err(detail)
+        }
+    };
+    let Some(mut session) = log.record("login", started, login) else {
+        return;
+    };
+
+    // The imap crate only exposes CAPABILITY on authenticated sessions.
+    let started = Instant::now();
+    let capabilities = session
+        .capabilities()
+        .map(|capabilities| {
+            let mut names = capabilities
+                .iter()
+                .map(|capability| match capability {
+                    Capability::Imap4rev1 => "IMAP4rev1".to_string(),
+                    Capability::Auth(mechanism) => format!("AUTH={mechanism}"),
+                    Capability::Atom(name) => name.to_string(),
+                })
+                .collect::<Vec<_>>();
+            names.sort();
+            ((), names.join(" "))
+        })
+        .map_err(|err| format!("CAPABILITY failed: {err}"));
+    if log.record("capabilities", started, capabilities).is_none() {
+        return;
+    }
+
+    let started = Instant::now();
+    let select = session
+        .select("INBOX")
+        .map(|mailbox| ((), format!("INBOX has {} messages", mailbox.exists)))
+        .map_err(|err| format!("Could not open INBOX: {err}"));
+    log.record("select", started, select);
+    let _ = session.logout();
+}
+
+fn connect_any(addresses: &[SocketAddr]) -> Result<(TcpStream, SocketAddr), String> {
+    let mut last_error = None;
+    for address in addresses {
+        match TcpStream::connect_timeout(address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok((stream, *address)),
+            Err(err) => last_error = Some(format!("{address}: {err}")),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| "No addresses to connect to".into()))
+}
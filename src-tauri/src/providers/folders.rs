@@ -0,0 +1,195 @@
+//! Server folder listing: flat LIST/STATUS results and the tree the sidebar
+//! renders from them.
+
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use serde::Serialize;
+
+/// One mailbox as reported by LIST, LSUB, and STATUS.
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderStatus {
+    /// Raw (modified UTF-7) mailbox name; pass this back to IMAP commands.
+    pub path: String,
+    pub delimiter: Option<String>,
+    /// False for `\Noselect` containers, which have no messages of their own.
+    pub selectable: bool,
+    pub subscribed: bool,
+    pub exists: u32,
+    pub unseen: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderNode {
+    /// Last path segment, decoded for display.
+    pub name: String,
+    pub path: String,
+    pub selectable: bool,
+    pub subscribed: bool,
+    pub exists: u32,
+    pub unseen: u32,
+    pub children: Vec<FolderNode>,
+}
+
+/// Nests folders by their hierarchy delimiter. Parents missing from the
+/// listing (servers may omit them) are synthesized as non-selectable nodes.
+pub fn build_tree(folders: &[FolderStatus]) -> Vec<FolderNode> {
+    let mut sorted = folders.to_vec();
+    sorted.sort_by(|a, b| {
+        let not_inbox = |folder: &FolderStatus| !folder.path.eq_ignore_ascii_case("INBOX");
+        not_inbox(a)
+            .cmp(&not_inbox(b))
+            .then_with(|| a.path.to_lowercase().cmp(&b.path.to_lowercase()))
+    });
+
+    let mut roots: Vec<FolderNode> = Vec::new();
+    for folder in &sorted {
+        let segments = match folder
+            .delimiter
+            .as_deref()
+            .filter(|value| !value.is_empty())
+        {
+            Some(delimiter) => folder.path.split(delimiter).collect::<Vec<_>>(),
+            None => vec![folder.path.as_str()],
+        };
+        let delimiter = folder.delimiter.as_deref().unwrap_or_default();
+
+        let mut level = &mut roots;
+        for (depth, segment) in segments.iter().enumerate() {
+            let path = segments[..=depth].join(delimiter);
+            let position = match level.iter().position(|node| node.path == path) {
+                Some(position) => position,
+                None => {
+                    level.push(FolderNode {
+                        name: decode_mailbox_name(segment),
+                        path,
+                        selectable: false,
+                        subscribed: false,
+                        exists: 0,
+                        unseen: 0,
+                        children: Vec::new(),
+                    });
+                    level.len() - 1
+                }
+            };
+            let node = &mut level[position];
+            if depth + 1 == segments.len() {
+                node.selectable = folder.selectable;
+                node.subscribed = folder.subscribed;
+                node.exists = folder.exists;
+                node.unseen = folder.unseen;
+            }
+            level = &mut node.children;
+        }
+    }
+    roots
+}
+
+/// Decodes an IMAP modified UTF-7 mailbox name (RFC 3501 section 5.1.3).
+/// Malformed input is returned as-is.
+pub fn decode_mailbox_name(raw: &str) -> String {
+    let mut output = String::with_capacity(raw.len());
+    let mut rest = raw;
+
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('-') else {
+            return raw.to_string();
+        };
+        let encoded = &after[..end];
+        if encoded.is_empty() {
+            output.push('&');
+        } else {
+            let Ok(bytes) = STANDARD_NO_PAD.decode(encoded.replace(',', "/")) else {
+                return raw.to_string();
+            };
+            let units = bytes
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>();
+            match String::from_utf16(&units) {
+                Ok(decoded) => output.push_str(&decoded),
+                Err(_) => return raw.to_string(),
+            }
+        }
+        rest = &after[end + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// A change to the server's folder list.
+#[derive(Debug, Clone)]
+pub enum FolderOperation {
+    Create { path: String },
+    Rename { from: String, to: String },
+    Delete { path: String },
+    SetSubscribed { path: String, subscribed: bool },
+}
+
+impl FolderOperation {
+    /// Rejects names the server would refuse or that would break the app,
+    /// such as renaming or deleting INBOX.
+    pub fn validate(&self) -> Result<(), String> {
+        let is_inbox = |path: &str| path.eq_ignore_ascii_case("INBOX");
+        match self {
+            FolderOperation::Create { path } if path.trim().is_empty() => {
+                Err("Folder name is required".into())
+            }
+            FolderOperation::Rename { from, .. } if is_inbox(from) => {
+                Err("INBOX cannot be renamed".into())
+            }
+            FolderOperation::Rename { to, .. } if to.trim().is_empty() => {
+                Err("New folder name is required".into())
+            }
+            FolderOperation::Delete { path } if is_inbox(path) => {
+                Err("INBOX cannot be deleted".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Mirrors a successful server change in a cached listing so the next
+    /// `get_folder_tree` call does not need a round trip.
+    pub fn apply_to(&self, folders: &mut Vec<FolderStatus>) {
+        match self {
+            FolderOperation::Create { path } => {
+                if folders.iter().any(|folder| &folder.path == path) {
+                    return;
+                }
+                let delimiter = folders.iter().find_map(|folder| folder.delimiter.clone());
+                folders.push(FolderStatus {
+                    path: path.clone(),
+                    delimiter,
+                    selectable: true,
+                    subscribed: true,
+                    exists: 0,
+                    unseen: 0,
+                });
+            }
+            FolderOperation::Rename { from, to } => {
+                for folder in folders.iter_mut() {
+                    if &folder.path == from {
+                        folder.path = to.clone();
+                        continue;
+                    }
+                    // IMAP RENAME moves inferior folders along with the parent.
+                    let Some(delimiter) = folder.delimiter.clone() else {
+                        continue;
+                    };
+                    let prefix = format!("{from}{delimiter}");
+                    if let Some(child) = folder.path.strip_prefix(&prefix).map(str::to_string) {
+                        folder.path = format!("{to}{delimiter}{child}");
+                    }
+                }
+            }
+            FolderOperation::Delete { path } => folders.retain(|folder| &folder.path != path),
+            FolderOperation::SetSubscribed { path, subscribed } => {
+                if let Some(folder) = folders.iter_mut().find(|folder| &folder.path == path) {
+                    folder.subscribed = *subscribed;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Push notification of new mail with IMAP IDLE (RFC 2177). A listener
+//! holds its own connection, outside the session pool, with a mailbox
+//! examined and an IDLE command outstanding. When the server reports a
+//! change the listener re-examines the mailbox and passes on the new counts
+//! if mail arrived; flag changes and expunges are not reported.
+
+use super::session;
+use super::ProviderError;
+use crate::models::{Credentials, Provider};
+use ::imap::extensions::idle::WaitOutcome;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::{self, JoinHandle};
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// IDLE is reissued this often. RFC 2177 has servers drop it after 30
+/// minutes, and NAT routers often forget quiet connections after five, so
+/// this also keeps the connection alive. A cancelled listener stops at the
+/// end of its current round.
+const RENEW_AFTER: Duration = Duration::from_secs(4 * 60);
+
+/// The mailbox gained messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NewMail {
+    pub exists: u32,
+    pub uid_next: Option<u32>,
+}
+
+/// Why a listener stopped without an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleExit {
+    Cancelled,
+    /// The server does not offer IDLE; polling has to do.
+    Unsupported,
+}
+
+/// Starts listening on `folder`. Reports arrive on the receiver until
+/// `cancel` fires or the connection fails; the handle then says which.
+pub fn listen(
+    credentials: &Credentials,
+    folder: &str,
+    cancel: CancellationToken,
+) -> (
+    UnboundedReceiver<NewMail>,
+    JoinHandle<Result<IdleExit, ProviderError>>,
+) {
+    let (tx, rx) = unbounded_channel();
+    // Demo mail only changes when the app changes it.
+    if credentials.provider == Provider::Demo {
+        return (rx, task::spawn(async { Ok(IdleExit::Unsupported) }));
+    }
+    let credentials = credentials.clone();
+    let folder = folder.to_string();
+    let handle = task::spawn_blocking(move || listen_blocking(&credentials, &folder, cancel, tx));
+    (rx, handle)
+}
+
+fn listen_blocking(
+    credentials: &Credentials,
+    folder: &str,
+    cancel: CancellationToken,
+    tx: UnboundedSender<NewMail>,
+) -> Result<IdleExit, ProviderError> {
+    let mut session = session::connect(credentials)?;
+    if !session.capabilities()?.has_str("IDLE") {
+        let _ = session.logout();
+        return Ok(IdleExit::Unsupported);
+    }
+
+    let mailbox = session.examine(folder)?;
+    let mut last = NewMail {
+        exists: mailbox.exists,
+        uid_next: mailbox.uid_next,
+    };
+    debug!(account = %credentials.email, folder, exists = last.exists, "IDLE started");
+
+    while !cancel.is_cancelled() && !tx.is_closed() {
+        let outcome = session.idle()?.wait_with_timeout(RENEW_AFTER)?;
+        // Responses the imap crate parsed along the way are not needed and
+        // would otherwise pile up for as long as the connection lives.
+        while session.unsolicited_responses.try_recv().is_ok() {}
+        if matches!(outcome, WaitOutcome::TimedOut) || cancel.is_cancelled() {
+            continue;
+        }
+
+        let mailbox = session.examine(folder)?;
+        let current = NewMail {
+            exists: mailbox.exists,
+            uid_next: mailbox.uid_next,
+        };
+        let arrived = match (current.uid_next, last.uid_next) {
+            (Some(next), Some(previous)) => next > previous,
+            _ => current.exists > last.exists,
+        };
+        if arrived && tx.send(current).is_err() {
+            break;
+        }
+        last = current;
+    }
+
+    let _ = session.logout();
+    Ok(IdleExit::Cancelled)
+}
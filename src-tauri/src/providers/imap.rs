@@ -1,16 +1,34 @@
+use crate::brand;
+use crate::flag_sync::flag_key;
 use crate::models::{Credentials, EmailSummary, MailAddress};
-use crate::providers::{BatchResult, MessageEnvelope, ProviderError, SyncWindow};
+use crate::policy::BlockTarget;
+use crate::providers::folders::{FolderOperation, FolderStatus};
+use crate::providers::session::{self, ImapSession};
+use crate::providers::{
+    BatchResult, MailboxUids, MessageEnvelope, ProviderError, SentEnvelope, SizeRange, SyncWindow,
+    TransferMessage,
+};
+use crate::threads;
 use chrono::{Duration, NaiveDate};
-use ::imap::types::{Fetch, Flag};
+use ::imap::types::{Fetch, Flag, NameAttribute};
 use ::imap_proto::types::Address;
-use native_tls::{TlsConnector, TlsStream};
-use std::net::TcpStream;
+use secrecy::zeroize::Zeroizing;
+use std::cmp::Reverse;
 use std::time::Instant;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::task::{self, JoinHandle};
-use tracing::info;
+use tracing::{info, warn};
 
 const MAX_UIDS_PER_SEARCH: usize = 900; // stay safely below Yahoo's 1k cap
+/// Header fields read with every summary: those marking automated mail,
+/// the receiving server's authentication verdict, and the thread's
+/// references.
+const SUMMARY_HEADERS: &str = concat!(
+    "BODY.PEEK[HEADER.FIELDS (AUTO-SUBMITTED PRECEDENCE LIST-ID X-AUTO-RESPONSE-SUPPRESS ",
+    "AUTHENTICATION-RESULTS REFERENCES)]"
+);
+/// Lite sync keeps batches small so a slow link shows progress quickly.
+const LITE_SYNC_MAX_CHUNK: usize = 100;
 
 pub async fn verify_credentials(credentials: &Credentials) -> Result<(), ProviderError> {
     let credentials = credentials.clone();
@@ -22,14 +40,16 @@ pub async fn verify_credentials(credentials: &Credentials) -> Result<(), Provide
 
 pub async fn fetch_recent(
     credentials: &Credentials,
+    folder: &str,
     limit: usize,
 ) -> Result<Vec<EmailSummary>, ProviderError> {
-    let credentials = credentials.clone();
+    let folder = folder.to_string();
     let limit = limit.min(200);
 
-    task::spawn_blocking(move || fetch_recent_blocking(credentials, limit))
-        .await
-        .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
+    session::retry_once(credentials, "fetch_recent", move |credentials| {
+        fetch_recent_blocking(credentials, folder.clone(), limit)
+    })
+    .await
 }
 
 pub async fn fetch_all(
@@ -37,6 +57,7 @@ pub async fn fetch_all(
     since_uid: Option<u32>,
     chunk_size: usize,
     window: Option<SyncWindow>,
+    lite: bool,
 ) -> Result<
     (
         UnboundedReceiver<BatchResult>,
@@ -45,85 +66,86 @@ pub async fn fetch_all(
     ProviderError,
 > {
     let credentials = credentials.clone();
-    let chunk = chunk_size.clamp(50, 1000);
+    let chunk = if lite {
+        chunk_size.clamp(10, LITE_SYNC_MAX_CHUNK)
+    } else {
+        chunk_size.clamp(50, 1000)
+    };
     let window = window.clone();
     let (tx, rx) = unbounded_channel();
 
     let handle = task::spawn_blocking(move || {
-        fetch_all_blocking(credentials, since_uid, chunk, window, tx)
+        fetch_all_blocking(credentials, since_uid, chunk, window, lite, tx)
     });
 
     Ok((rx, handle))
 }
 
-pub async fn delete_message(credentials: &Credentials, uid: &str) -> Result<(), ProviderError> {
+pub async fn delete_message(
+    credentials: &Credentials,
+    folder: &str,
+    uid: u32,
+    expected_uid_validity: Option<u32>,
+) -> Result<(), ProviderError> {
     let credentials = credentials.clone();
-    let uid = uid.to_string();
+    let folder = folder.to_string();
 
-    task::spawn_blocking(move || delete_message_blocking(credentials, uid))
-        .await
-        .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
+    task::spawn_blocking(move || {
+        delete_message_blocking(credentials, folder, uid, expected_uid_validity)
+    })
+    .await
+    .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
 }
 
 pub async fn delete_messages(
     credentials: &Credentials,
-    uids: &[String],
+    folder: &str,
+    uids: &[u32],
+    expected_uid_validity: Option<u32>,
 ) -> Result<(), ProviderError> {
     if uids.is_empty() {
         return Ok(());
     }
 
     let credentials = credentials.clone();
+    let folder = folder.to_string();
     let items = uids.to_vec();
 
-    task::spawn_blocking(move || delete_messages_blocking(credentials, items))
-        .await
-        .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
+    task::spawn_blocking(move || {
+        delete_messages_blocking(credentials, folder, items, expected_uid_validity)
+    })
+    .await
+    .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
 }
 
 pub async fn move_blocked(
     credentials: &Credentials,
-    senders: &[String],
+    targets: &[BlockTarget],
     target_folder: &str,
 ) -> Result<usize, ProviderError> {
     let credentials = credentials.clone();
-    let senders = senders.to_vec();
+    let targets = targets.to_vec();
     let folder = target_folder.to_string();
 
-    task::spawn_blocking(move || move_blocked_blocking(credentials, senders, folder))
+    task::spawn_blocking(move || move_blocked_blocking(credentials, targets, folder))
         .await
         .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
 }
 
 fn fetch_recent_blocking(
     credentials: Credentials,
+    folder: String,
     limit: usize,
 ) -> Result<Vec<EmailSummary>, ProviderError> {
-    let domain = credentials
-        .custom_host
-        .as_deref()
-        .unwrap_or_else(|| credentials.provider.imap_host());
-    let port = credentials.custom_port.unwrap_or(993);
-    let tls = TlsConnector::builder()
-        .build()
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-    let client = ::imap::connect((domain, port), domain, &tls)
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-
-    let mut session = match client.login(&credentials.email, &credentials.password) {
-        Ok(session) => session,
-        Err((err, _client)) => {
-            return Err(ProviderError::Authentication(err.to_string()));
-        }
-    };
+    let mut session = open_session(&credentials)?;
 
-    let mailbox = session.select("INBOX")?;
+    let mailbox = session.select(&folder)?;
 
     // For fetch_recent, we can use a simpler approach: get the last N messages by sequence number
     // This is more efficient than fetching all UIDs first
     let exists = mailbox.exists;
     if exists == 0 {
-        session.logout()?;
+        session::release(&credentials, session);
         return Ok(Vec::new());
     }
 
@@ -139,7 +161,7 @@ fn fetch_recent_blocking(
         .collect();
 
     if uids.is_empty() {
-        session.logout()?;
+        session::release(&credentials, session);
         return Ok(Vec::new());
     }
 
@@ -151,7 +173,10 @@ fn fetch_recent_blocking(
         .collect::<Vec<_>>()
         .join(",");
 
-    let fetches = session.uid_fetch(&query, "(ENVELOPE INTERNALDATE)")?;
+    let fetches = session.uid_fetch(
+        &query,
+        format!("(ENVELOPE INTERNALDATE RFC822.SIZE {SUMMARY_HEADERS})"),
+    )?;
     let mut emails: Vec<EmailSummary> = fetches
         .iter()
         .filter_map(|item| summarize_fetch(item))
@@ -159,28 +184,12 @@ fn fetch_recent_blocking(
 
     emails.sort_by(|a, b| b.date.cmp(&a.date));
 
-    session.logout()?;
+    session::release(&credentials, session);
     Ok(emails)
 }
 
 fn verify_credentials_blocking(credentials: Credentials) -> Result<(), ProviderError> {
-    let domain = credentials
-        .custom_host
-        .as_deref()
-        .unwrap_or_else(|| credentials.provider.imap_host());
-    let port = credentials.custom_port.unwrap_or(993);
-    let tls = TlsConnector::builder()
-        .build()
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-    let client = ::imap::connect((domain, port), domain, &tls)
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-
-    let mut session = match client.login(&credentials.email, &credentials.password) {
-        Ok(session) => session,
-        Err((err, _client)) => {
-            return Err(ProviderError::Authentication(err.to_string()));
-        }
-    };
+    let mut session = session::connect(&credentials)?;
 
     // Ensure the inbox can be selected to validate permissions.
     session.select("INBOX")?;
@@ -193,25 +202,10 @@ fn fetch_all_blocking(
     since_uid: Option<u32>,
     chunk_size: usize,
     window: Option<SyncWindow>,
+    lite: bool,
     tx: UnboundedSender<BatchResult>,
 ) -> Result<(), ProviderError> {
-    let domain = credentials
-        .custom_host
-        .as_deref()
-        .unwrap_or_else(|| credentials.provider.imap_host());
-    let port = credentials.custom_port.unwrap_or(993);
-    let tls = TlsConnector::builder()
-        .build()
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-    let client = ::imap::connect((domain, port), domain, &tls)
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-
-    let mut session = match client.login(&credentials.email, &credentials.password) {
-        Ok(session) => session,
-        Err((err, _client)) => {
-            return Err(ProviderError::Authentication(err.to_string()));
-        }
-    };
+    let mut session = open_session(&credentials)?;
 
     let mailbox = session.select("INBOX")?;
 
@@ -222,7 +216,7 @@ fn fetch_all_blocking(
     };
 
     if uids.is_empty() {
-        session.logout()?;
+        session::release(&credentials, session);
         return Ok(());
     }
 
@@ -234,7 +228,7 @@ fn fetch_all_blocking(
     };
 
     if filtered.is_empty() {
-        session.logout()?;
+        session::release(&credentials, session);
         return Ok(());
     }
 
@@ -243,6 +237,7 @@ fn fetch_all_blocking(
         total_uids = filtered.len(),
         chunk_size,
         since_uid,
+        lite,
         "full sync message set ready"
     );
 
@@ -255,25 +250,8 @@ fn fetch_all_blocking(
             .collect::<Vec<_>>()
             .join(",");
 
-        let fetches = session.uid_fetch(
-            &query,
-            "(ENVELOPE INTERNALDATE BODY.PEEK[TEXT]<0.4096> FLAGS)",
-        )?;
-
-        let mut batch_envelopes: Vec<MessageEnvelope> = Vec::with_capacity(fetches.len());
-        for item in fetches.iter() {
-            if let Some(summary) = summarize_fetch(item) {
-                let snippet = extract_body_snippet(item);
-                let body = item.body().map(|bytes| bytes.to_vec());
-                let flags = extract_flags(item);
-                batch_envelopes.push(MessageEnvelope {
-                    summary,
-                    snippet,
-                    body,
-                    flags,
-                });
-            }
-        }
+        let fetches = session.uid_fetch(&query, sync_fetch_items(lite))?;
+        let batch_envelopes = envelopes_from_fetches(fetches.iter());
 
         let batch_duration = batch_start.elapsed().as_millis() as u64;
         let processed = batch_envelopes.len();
@@ -283,6 +261,7 @@ fn fetch_all_blocking(
             requested: chunk.len(),
             fetched: processed,
             messages: batch_envelopes,
+            uid_validity: mailbox.uid_validity,
         };
         tx.send(result)
             .map_err(|_| ProviderError::Other("progress channel closed".into()))?;
@@ -298,12 +277,38 @@ fn fetch_all_blocking(
         );
     }
 
-    session.logout()?;
+    session::release(&credentials, session);
     Ok(())
 }
 
+/// FETCH items for sync. Lite mode skips the body text; the few summary
+/// header fields stay because auto-replies must never answer bulk mail.
+fn sync_fetch_items(lite: bool) -> String {
+    if lite {
+        format!("(ENVELOPE INTERNALDATE RFC822.SIZE FLAGS {SUMMARY_HEADERS})")
+    } else {
+        format!(
+            "(ENVELOPE INTERNALDATE RFC822.SIZE BODY.PEEK[TEXT]<0.4096> FLAGS {SUMMARY_HEADERS})"
+        )
+    }
+}
+
+fn envelopes_from_fetches<'a>(fetches: impl Iterator<Item = &'a Fetch>) -> Vec<MessageEnvelope> {
+    fetches
+        .filter_map(|item| {
+            let summary = summarize_fetch(item)?;
+            Some(MessageEnvelope {
+                summary,
+                snippet: extract_body_snippet(item),
+                body: item.body().map(|bytes| bytes.to_vec()),
+                flags: extract_flags(item),
+            })
+        })
+        .collect()
+}
+
 fn collect_all_uids(
-    session: &mut ::imap::Session<TlsStream<TcpStream>>,
+    session: &mut ImapSession,
     account_email: &str,
     uid_next: u32,
 ) -> Result<Vec<u32>, ProviderError> {
@@ -341,7 +346,7 @@ fn collect_all_uids(
 }
 
 fn collect_uids_for_window(
-    session: &mut ::imap::Session<TlsStream<TcpStream>>,
+    session: &mut ImapSession,
     account_email: &str,
     window: SyncWindow,
 ) -> Result<Vec<u32>, ProviderError> {
@@ -394,104 +399,645 @@ fn collect_uids_for_window(
     collected.dedup();
     Ok(collected)
 }
+pub async fn move_messages(
+    credentials: &Credentials,
+    uids: &[u32],
+    target_folder: &str,
+) -> Result<usize, ProviderError> {
+    if uids.is_empty() {
+        return Ok(0);
+    }
+
+    let credentials = credentials.clone();
+    let uids = uids.to_vec();
+    let folder = target_folder.to_string();
+
+    task::spawn_blocking(move || move_messages_blocking(credentials, uids, folder))
+        .await
+        .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
+}
+
+pub async fn fetch_raw_message(
+    credentials: &Credentials,
+    uid: u32,
+) -> Result<Option<Zeroizing<Vec<u8>>>, ProviderError> {
+    session::retry_once(credentials, "fetch_raw_message", move |credentials| {
+        fetch_raw_message_blocking(credentials, uid)
+    })
+    .await
+}
+
+/// Lists every mailbox with its message and unseen counts.
+pub async fn list_folders(credentials: &Credentials) -> Result<Vec<FolderStatus>, ProviderError> {
+    session::retry_once(credentials, "list_folders", list_folders_blocking).await
+}
+
+pub async fn manage_folder(
+    credentials: &Credentials,
+    operation: FolderOperation,
+) -> Result<(), ProviderError> {
+    let credentials = credentials.clone();
+
+    task::spawn_blocking(move || manage_folder_blocking(credentials, operation))
+        .await
+        .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
+}
+
+/// UID COPY from `source_folder` into `target_folder`; the original stays put.
+pub async fn copy_message(
+    credentials: &Credentials,
+    uid: u32,
+    source_folder: &str,
+    target_folder: &str,
+) -> Result<(), ProviderError> {
+    let credentials = credentials.clone();
+    let source = source_folder.to_string();
+    let target = target_folder.to_string();
+
+    task::spawn_blocking(move || copy_message_blocking(credentials, uid, source, target))
+        .await
+        .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
+}
+
+/// Fetches the full sync payload (including body text) for specific INBOX
+/// UIDs, e.g. messages first synced in lite mode.
+pub async fn fetch_envelopes(
+    credentials: &Credentials,
+    uids: &[u32],
+) -> Result<Vec<MessageEnvelope>, ProviderError> {
+    if uids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let uids = uids.to_vec();
+
+    session::retry_once(credentials, "fetch_envelopes", move |credentials| {
+        fetch_envelopes_blocking(credentials, uids.clone())
+    })
+    .await
+}
+
+pub async fn list_uids_after(
+    credentials: &Credentials,
+    folder: &str,
+    after_uid: u32,
+) -> Result<Vec<u32>, ProviderError> {
+    let folder = folder.to_string();
+
+    session::retry_once(credentials, "list_uids_after", move |credentials| {
+        list_uids_after_blocking(credentials, folder.clone(), after_uid)
+    })
+    .await
+}
+
+pub async fn search_by_size(
+    credentials: &Credentials,
+    folder: &str,
+    range: SizeRange,
+    limit: usize,
+) -> Result<Vec<EmailSummary>, ProviderError> {
+    let folder = folder.to_string();
+
+    session::retry_once(credentials, "search_by_size", move |credentials| {
+        search_by_size_blocking(credentials, folder.clone(), range, limit)
+    })
+    .await
+}
+
+pub async fn fetch_for_transfer(
+    credentials: &Credentials,
+    folder: &str,
+    uids: &[u32],
+) -> Result<Vec<TransferMessage>, ProviderError> {
+    let folder = folder.to_string();
+    let uids = uids.to_vec();
+
+    session::retry_once(credentials, "fetch_for_transfer", move |credentials| {
+        fetch_for_transfer_blocking(credentials, folder.clone(), uids.clone())
+    })
+    .await
+}
+
+pub async fn append_messages(
+    credentials: &Credentials,
+    folder: &str,
+    messages: Vec<TransferMessage>,
+) -> Result<Vec<u32>, ProviderError> {
+    let credentials = credentials.clone();
+    let folder = folder.to_string();
+
+    task::spawn_blocking(move || append_messages_blocking(credentials, folder, messages))
+        .await
+        .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
+}
+
+pub async fn inbox_uids(credentials: &Credentials) -> Result<MailboxUids, ProviderError> {
+    session::retry_once(credentials, "inbox_uids", inbox_uids_blocking).await
+}
+
+pub async fn fetch_sent(
+    credentials: &Credentials,
+    folder: &str,
+    limit: usize,
+) -> Result<Vec<SentEnvelope>, ProviderError> {
+    let folder = folder.to_string();
+    let limit = limit.min(1000);
+
+    session::retry_once(credentials, "fetch_sent", move |credentials| {
+        fetch_sent_blocking(credentials, folder.clone(), limit)
+    })
+    .await
+}
+
+pub async fn store_flags(
+    credentials: &Credentials,
+    folder: &str,
+    uids: &[u32],
+    expected_uid_validity: Option<u32>,
+    flags: &[String],
+    add: bool,
+) -> Result<Vec<(u32, Vec<String>)>, ProviderError> {
+    if uids.is_empty() || flags.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let folder = folder.to_string();
+    let uids = uids.to_vec();
+    let flags = flags.to_vec();
+
+    // Adding or removing a flag twice leaves the same result.
+    session::retry_once(credentials, "store_flags", move |credentials| {
+        store_flags_blocking(
+            credentials,
+            &folder,
+            uids.clone(),
+            expected_uid_validity,
+            flags.clone(),
+            add,
+        )
+    })
+    .await
+}
+
+/// Fails when a folder's UIDVALIDITY is not the one its UIDs were cached
+/// under: the same UID may now name a different message.
+pub(super) fn check_uid_validity(
+    folder: &str,
+    expected: Option<u32>,
+    actual: Option<u32>,
+) -> Result<(), ProviderError> {
+    match (expected, actual) {
+        (Some(expected), Some(actual)) if expected != actual => Err(ProviderError::Other(format!(
+            "{folder} UIDVALIDITY changed from {expected} to {actual}; sync before changing it"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// A UID set such as `4,8,15` for UID commands.
+fn uid_set(uids: &[u32]) -> String {
+    uids.iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
 
 fn format_imap_date(date: NaiveDate) -> String {
     date.format("%d-%b-%Y").to_string()
 }
 
-fn delete_message_blocking(credentials: Credentials, uid: String) -> Result<(), ProviderError> {
-    let domain = credentials
-        .custom_host
-        .as_deref()
-        .unwrap_or_else(|| credentials.provider.imap_host());
-    let port = credentials.custom_port.unwrap_or(993);
-    let tls = TlsConnector::builder()
-        .build()
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-    let client = ::imap::connect((domain, port), domain, &tls)
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-
-    let mut session = match client.login(&credentials.email, &credentials.password) {
-        Ok(session) => session,
-        Err((err, _client)) => return Err(ProviderError::Authentication(err.to_string())),
+fn open_session(credentials: &Credentials) -> Result<ImapSession, ProviderError> {
+    session::checkout(credentials)
+}
+
+fn list_folders_blocking(credentials: Credentials) -> Result<Vec<FolderStatus>, ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    let subscribed = session
+        .lsub(Some(""), Some("*"))?
+        .iter()
+        .map(|name| name.name().to_string())
+        .collect::<std::collections::HashSet<_>>();
+    let names = session.list(Some(""), Some("*"))?;
+
+    let mut folders = Vec::with_capacity(names.len());
+    for name in names.iter() {
+        let path = name.name().to_string();
+        let selectable = !name
+            .attributes()
+            .iter()
+            .any(|attribute| matches!(attribute, NameAttribute::NoSelect));
+        // STATUS on a \Noselect container is an error, and servers can refuse
+        // it for individual folders; neither should fail the whole listing.
+        let status = if selectable {
+            session.status(&path, "(MESSAGES UNSEEN)").ok()
+        } else {
+            None
+        };
+        folders.push(FolderStatus {
+            subscribed: subscribed.contains(&path),
+            delimiter: name.delimiter().map(str::to_string),
+            selectable,
+            exists: status.as_ref().map_or(0, |mailbox| mailbox.exists),
+            unseen: status.and_then(|mailbox| mailbox.unseen).unwrap_or(0),
+            path,
+        });
+    }
+
+    session::release(&credentials, session);
+    Ok(folders)
+}
+
+fn manage_folder_blocking(
+    credentials: Credentials,
+    operation: FolderOperation,
+) -> Result<(), ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    match &operation {
+        FolderOperation::Create { path } => {
+            session.create(path)?;
+            // Most clients hide unsubscribed folders, so subscribe right away.
+            let _ = session.subscribe(path);
+        }
+        FolderOperation::Rename { from, to } => {
+            session.rename(from, to)?;
+            let _ = session.unsubscribe(from);
+            let _ = session.subscribe(to);
+        }
+        FolderOperation::Delete { path } => {
+            let _ = session.unsubscribe(path);
+            session.delete(path)?;
+        }
+        FolderOperation::SetSubscribed { path, subscribed } => {
+            if *subscribed {
+                session.subscribe(path)?;
+            } else {
+                session.unsubscribe(path)?;
+            }
+        }
+    }
+
+    session.logout()?;
+    Ok(())
+}
+
+fn copy_message_blocking(
+    credentials: Credentials,
+    uid: u32,
+    source_folder: String,
+    target_folder: String,
+) -> Result<(), ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    session.select(&source_folder)?;
+    // UID COPY succeeds silently for unknown UIDs, so confirm the message first.
+    if session.uid_fetch(uid.to_string(), "UID")?.is_empty() {
+        session::release(&credentials, session);
+        return Err(ProviderError::Other(format!(
+            "Message {uid} not found in {source_folder}"
+        )));
+    }
+    session.uid_copy(uid.to_string(), &target_folder)?;
+    session::release(&credentials, session);
+    Ok(())
+}
+
+fn inbox_uids_blocking(credentials: Credentials) -> Result<MailboxUids, ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    let mailbox = session.select("INBOX")?;
+    let mut uids: Vec<u32> = if mailbox.exists == 0 {
+        Vec::new()
+    } else {
+        session.uid_search("ALL")?.into_iter().collect()
     };
+    uids.sort_unstable();
+    session::release(&credentials, session);
+    Ok(MailboxUids {
+        uid_validity: mailbox.uid_validity,
+        uid_next: mailbox.uid_next,
+        uids,
+    })
+}
+
+fn fetch_sent_blocking(
+    credentials: Credentials,
+    folder: String,
+    limit: usize,
+) -> Result<Vec<SentEnvelope>, ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    let mailbox = session.select(&folder)?;
+    if mailbox.exists == 0 || limit == 0 {
+        session::release(&credentials, session);
+        return Ok(Vec::new());
+    }
+    let start_seq = mailbox.exists.saturating_sub(limit as u32 - 1).max(1);
+    let fetches = session.fetch(
+        format!("{start_seq}:{}", mailbox.exists),
+        "(UID ENVELOPE INTERNALDATE)",
+    )?;
+    let sent = fetches
+        .iter()
+        .filter_map(|fetch| {
+            let envelope = fetch.envelope()?;
+            let mut recipients = address_list(envelope.to.as_deref());
+            recipients.extend(address_list(envelope.cc.as_deref()));
+            Some(SentEnvelope {
+                uid: fetch.uid?,
+                subject: decode_bytes(envelope.subject.as_ref().map(|cow| cow.as_ref())),
+                recipients,
+                sent_at: fetch.internal_date().map(|date| date.timestamp()),
+            })
+        })
+        .collect();
+    session::release(&credentials, session);
+    Ok(sent)
+}
+
+fn store_flags_blocking(
+    credentials: Credentials,
+    folder: &str,
+    uids: Vec<u32>,
+    expected_uid_validity: Option<u32>,
+    flags: Vec<String>,
+    add: bool,
+) -> Result<Vec<(u32, Vec<String>)>, ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    let mailbox = session.select(folder)?;
+    if let Err(err) = check_uid_validity(folder, expected_uid_validity, mailbox.uid_validity) {
+        session::release(&credentials, session);
+        return Err(err);
+    }
+    let names = flags
+        .iter()
+        .map(|flag| imap_flag_name(flag))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let op = if add { "+FLAGS" } else { "-FLAGS" };
+    // The untagged FETCH replies carry each message's flags after the change.
+    let fetches = session.uid_store(uid_set(&uids), format!("{op} ({names})"))?;
+    let updated = fetches
+        .iter()
+        .filter_map(|fetch| Some((fetch.uid?, extract_flags(fetch))))
+        .collect();
+    session::release(&credentials, session);
+    Ok(updated)
+}
+
+fn fetch_envelopes_blocking(
+    credentials: Credentials,
+    uids: Vec<u32>,
+) -> Result<Vec<MessageEnvelope>, ProviderError> {
+    let mut session = open_session(&credentials)?;
 
     session.select("INBOX")?;
+    let fetches = session.uid_fetch(uid_set(&uids), sync_fetch_items(false))?;
+    let envelopes = envelopes_from_fetches(fetches.iter());
+    session::release(&credentials, session);
+    Ok(envelopes)
+}
+
+fn list_uids_after_blocking(
+    credentials: Credentials,
+    folder: String,
+    after_uid: u32,
+) -> Result<Vec<u32>, ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    session.select(&folder)?;
+    // "n:*" always matches the highest UID, even when it is below n.
+    let mut uids = session
+        .uid_search(format!("UID {}:*", after_uid.saturating_add(1)))?
+        .into_iter()
+        .filter(|uid| *uid > after_uid)
+        .collect::<Vec<_>>();
+    uids.sort_unstable();
+    session::release(&credentials, session);
+    Ok(uids)
+}
+
+fn search_by_size_blocking(
+    credentials: Credentials,
+    folder: String,
+    range: SizeRange,
+    limit: usize,
+) -> Result<Vec<EmailSummary>, ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    session.select(&folder)?;
+    let uids = session.uid_search(range.search_criteria())?;
+    if uids.is_empty() || limit == 0 {
+        session::release(&credentials, session);
+        return Ok(Vec::new());
+    }
+    // SEARCH has no order, so sizes are fetched first to keep the largest.
+    let uids = uids.into_iter().collect::<Vec<_>>();
+    let mut sized = Vec::with_capacity(uids.len());
+    for chunk in uids.chunks(MAX_UIDS_PER_SEARCH) {
+        let fetches = session.uid_fetch(uid_set(chunk), "(UID RFC822.SIZE)")?;
+        sized.extend(
+            fetches
+                .iter()
+                .filter_map(|fetch| Some((fetch.uid?, fetch.size.unwrap_or(0)))),
+        );
+    }
+    sized.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+    sized.truncate(limit);
+    let selected = sized.iter().map(|(uid, _)| *uid).collect::<Vec<_>>();
+
+    let fetches = session.uid_fetch(
+        uid_set(&selected),
+        format!("(ENVELOPE INTERNALDATE RFC822.SIZE {SUMMARY_HEADERS})"),
+    )?;
+    let mut emails = fetches
+        .iter()
+        .filter_map(summarize_fetch)
+        .collect::<Vec<_>>();
+    emails.sort_by_key(|email| Reverse(email.size));
+    session::release(&credentials, session);
+    Ok(emails)
+}
+
+fn fetch_for_transfer_blocking(
+    credentials: Credentials,
+    folder: String,
+    uids: Vec<u32>,
+) -> Result<Vec<TransferMessage>, ProviderError> {
+    if uids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut session = open_session(&credentials)?;
+
+    session.select(&folder)?;
+    let sequence = uids
+        .iter()
+        .map(|uid| uid.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let fetches = session.uid_fetch(&sequence, "(UID FLAGS INTERNALDATE BODY.PEEK[])")?;
+    let mut messages = fetches
+        .iter()
+        .filter(|fetch| !fetch.flags().contains(&Flag::Deleted))
+        .filter_map(|fetch| {
+            Some(TransferMessage {
+                uid: fetch.uid?,
+                flags: extract_flags(fetch),
+                internal_date: fetch.internal_date(),
+                raw: fetch.body()?.to_vec(),
+            })
+        })
+        .collect::<Vec<_>>();
+    messages.sort_by_key(|message| message.uid);
+    session::release(&credentials, session);
+    Ok(messages)
+}
+
+/// APPENDs each message with its original flags and INTERNALDATE. A message
+/// the server rejects (e.g. over its size limit) is logged and skipped.
+fn append_messages_blocking(
+    credentials: Credentials,
+    folder: String,
+    messages: Vec<TransferMessage>,
+) -> Result<Vec<u32>, ProviderError> {
+    let mut session = open_session(&credentials)?;
+    let _ = session.create(&folder);
+
+    let mut appended = Vec::new();
+    for message in &messages {
+        let flags = message
+            .flags
+            .iter()
+            .filter_map(|flag| match flag.as_str() {
+                "seen" => Some(Flag::Seen),
+                "answered" => Some(Flag::Answered),
+                "flagged" => Some(Flag::Flagged),
+                "draft" => Some(Flag::Draft),
+                // Server-managed flags cannot be set by a client.
+                "recent" | "may-create" | "deleted" => None,
+                custom => Some(Flag::Custom(custom.into())),
+            })
+            .collect::<Vec<_>>();
+        match session.append_with_flags_and_date(
+            &folder,
+            &message.raw,
+            &flags,
+            message.internal_date,
+        ) {
+            Ok(()) => appended.push(message.uid),
+            Err(err) => {
+                warn!(account = %credentials.email, uid = message.uid, ?err, "failed to append message");
+            }
+        }
+    }
+
+    session::release(&credentials, session);
+    Ok(appended)
+}
+
+fn delete_message_blocking(
+    credentials: Credentials,
+    folder: String,
+    uid: u32,
+    expected_uid_validity: Option<u32>,
+) -> Result<(), ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    let mailbox = session.select(&folder)?;
+    if let Err(err) = check_uid_validity(&folder, expected_uid_validity, mailbox.uid_validity) {
+        session::release(&credentials, session);
+        return Err(err);
+    }
     let trash_folder = credentials.provider.trash_folder();
     let _ = session.create(trash_folder);
+    let uid = uid.to_string();
     session.uid_copy(&uid, trash_folder)?;
     session.uid_store(&uid, "+FLAGS (\\Deleted)")?;
     session.expunge()?;
-    session.logout()?;
+    session::release(&credentials, session);
     Ok(())
 }
 
+fn move_messages_blocking(
+    credentials: Credentials,
+    uids: Vec<u32>,
+    target_folder: String,
+) -> Result<usize, ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    session.select("INBOX")?;
+    let _ = session.create(&target_folder);
+    let sequence = uid_set(&uids);
+    session.uid_copy(&sequence, &target_folder)?;
+    session.uid_store(&sequence, "+FLAGS (\\Deleted)")?;
+    session.expunge()?;
+    session::release(&credentials, session);
+    Ok(uids.len())
+}
+
+fn fetch_raw_message_blocking(
+    credentials: Credentials,
+    uid: u32,
+) -> Result<Option<Zeroizing<Vec<u8>>>, ProviderError> {
+    let mut session = open_session(&credentials)?;
+
+    session.select("INBOX")?;
+    let fetches = session.uid_fetch(uid.to_string(), "BODY.PEEK[]")?;
+    let raw = fetches
+        .iter()
+        .find_map(|item| item.body().map(|bytes| Zeroizing::new(bytes.to_vec())));
+    session::release(&credentials, session);
+    Ok(raw)
+}
+
 fn delete_messages_blocking(
     credentials: Credentials,
-    uids: Vec<String>,
+    folder: String,
+    uids: Vec<u32>,
+    expected_uid_validity: Option<u32>,
 ) -> Result<(), ProviderError> {
-    let domain = credentials
-        .custom_host
-        .as_deref()
-        .unwrap_or_else(|| credentials.provider.imap_host());
-    let port = credentials.custom_port.unwrap_or(993);
-    let tls = TlsConnector::builder()
-        .build()
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-    let client = ::imap::connect((domain, port), domain, &tls)
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-
-    let mut session = match client.login(&credentials.email, &credentials.password) {
-        Ok(session) => session,
-        Err((err, _client)) => return Err(ProviderError::Authentication(err.to_string())),
-    };
+    let mut session = open_session(&credentials)?;
 
-    session.select("INBOX")?;
+    let mailbox = session.select(&folder)?;
+    if let Err(err) = check_uid_validity(&folder, expected_uid_validity, mailbox.uid_validity) {
+        session::release(&credentials, session);
+        return Err(err);
+    }
     let trash_folder = credentials.provider.trash_folder();
     let _ = session.create(trash_folder);
-    let sequence = uids.join(",");
+    let sequence = uid_set(&uids);
     session.uid_copy(&sequence, trash_folder)?;
     session.uid_store(&sequence, "+FLAGS (\\Deleted)")?;
     session.expunge()?;
-    session.logout()?;
+    session::release(&credentials, session);
     Ok(())
 }
 
 fn move_blocked_blocking(
     credentials: Credentials,
-    senders: Vec<String>,
+    targets: Vec<BlockTarget>,
     target_folder: String,
 ) -> Result<usize, ProviderError> {
-    if senders.is_empty() {
+    if targets.is_empty() {
         return Ok(0);
     }
 
-    let domain = credentials
-        .custom_host
-        .as_deref()
-        .unwrap_or_else(|| credentials.provider.imap_host());
-    let port = credentials.custom_port.unwrap_or(993);
-    let tls = TlsConnector::builder()
-        .build()
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-    let client = ::imap::connect((domain, port), domain, &tls)
-        .map_err(|err| ProviderError::Network(err.to_string()))?;
-
-    let mut session = match client.login(&credentials.email, &credentials.password) {
-        Ok(session) => session,
-        Err((err, _client)) => return Err(ProviderError::Authentication(err.to_string())),
-    };
+    let mut session = open_session(&credentials)?;
 
     session.select("INBOX")?;
     let _ = session.create(&target_folder);
 
     let mut moved = 0usize;
 
-    for sender in senders {
-        if sender.is_empty() {
+    for target in targets {
+        if target.sender.is_empty() {
             continue;
         }
-        let query = format!("FROM \"{}\"", sender);
+        let mut query = format!("FROM \"{}\"", target.sender);
+        for allowed in &target.except {
+            query.push_str(&format!(" NOT FROM \"{allowed}\""));
+        }
         let uids = session.uid_search(query)?;
         if uids.is_empty() {
             continue;
@@ -510,7 +1056,7 @@ fn move_blocked_blocking(
         let _ = session.expunge();
     }
 
-    session.logout()?;
+    session::release(&credentials, session);
     Ok(moved)
 }
 
@@ -530,11 +1076,69 @@ fn summarize_fetch(fetch: &Fetch) -> Option<EmailSummary> {
         })
     });
 
+    let headers = fetch
+        .header()
+        .map(|bytes| String::from_utf8_lossy(bytes).into_owned());
+    let auto_submitted = headers
+        .as_deref()
+        .map(headers_mark_automated)
+        .unwrap_or(false);
+    let dmarc_aligned = match (headers.as_deref(), sender.email.rsplit_once('@')) {
+        (Some(headers), Some((_, domain))) => brand::header_alignment(headers, domain),
+        _ => None,
+    };
+    let in_reply_to = decode_bytes(envelope.in_reply_to.as_ref().map(|cow| cow.as_ref()));
+    let message_id = decode_bytes(envelope.message_id.as_ref().map(|cow| cow.as_ref()));
+    let thread_id = threads::thread_id(headers.as_deref(), Some(&in_reply_to), Some(&message_id));
+
     Some(EmailSummary {
-        uid: uid.to_string(),
+        uid,
         subject,
         sender,
         date,
+        auto_submitted,
+        to: address_list(envelope.to.as_deref()),
+        size: fetch.size,
+        dmarc_aligned,
+        thread_id,
+        message_id: threads::message_ids(&message_id).into_iter().next(),
+        references: headers
+            .as_deref()
+            .map(threads::references)
+            .unwrap_or_default(),
+    })
+}
+
+/// Every address in an envelope list, lowercase, skipping group markers.
+fn address_list(addresses: Option<&[Address]>) -> Vec<String> {
+    addresses
+        .unwrap_or_default()
+        .iter()
+        .map(|address| {
+            primary_address(Some(std::slice::from_ref(address)))
+                .email
+                .to_lowercase()
+        })
+        .filter(|email| email.contains('@'))
+        .collect()
+}
+
+/// RFC 3834 `Auto-Submitted`, bulk `Precedence`, and list headers all mean
+/// nobody is waiting for a personal reply.
+pub(super) fn headers_mark_automated(raw_headers: &str) -> bool {
+    let unfolded = raw_headers.replace("\r\n ", " ").replace("\r\n\t", " ");
+    unfolded.lines().any(|line| {
+        let Some((name, value)) = line.split_once(':') else {
+            return false;
+        };
+        let value = value.trim().to_ascii_lowercase();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "auto-submitted" => !value.is_empty() && value != "no",
+            "precedence" => matches!(value.as_str(), "bulk" | "junk" | "list" | "auto_reply"),
+            "list-id" => true,
+            "x-auto-response-suppress" => value.contains("all") || value.contains("oof"),
+            _ => false,
+        }
     })
 }
 
@@ -576,24 +1180,37 @@ fn decode_bytes(data: Option<&[u8]>) -> String {
 }
 
 fn extract_body_snippet(fetch: &Fetch) -> Option<String> {
-    fetch
-        .body()
-        .map(|bytes| {
-            let raw = String::from_utf8_lossy(bytes);
-            let collapsed = raw
-                .replace(['\r', '\n'], " ")
-                .split_whitespace()
-                .take(80)
-                .collect::<Vec<_>>()
-                .join(" ");
-            let trimmed = collapsed.trim();
-            if trimmed.len() > 280 {
-                format!("{}…", &trimmed[..280])
-            } else {
-                trimmed.to_string()
-            }
-        })
-        .filter(|snippet| !snippet.is_empty())
+    fetch.body().and_then(body_snippet)
+}
+
+/// The first words of a message's text, collapsed onto one line.
+pub(super) fn body_snippet(bytes: &[u8]) -> Option<String> {
+    let raw = String::from_utf8_lossy(bytes);
+    let collapsed = raw
+        .replace(['\r', '\n'], " ")
+        .split_whitespace()
+        .take(80)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let trimmed = collapsed.trim();
+    let snippet = if trimmed.len() > 280 {
+        format!("{}…", &trimmed[..280])
+    } else {
+        trimmed.to_string()
+    };
+    (!snippet.is_empty()).then_some(snippet)
+}
+
+/// The IMAP spelling of a flag in the form [`extract_flags`] stores.
+fn imap_flag_name(flag: &str) -> String {
+    match flag_key(flag).as_str() {
+        "seen" => "\\Seen".to_string(),
+        "answered" => "\\Answered".to_string(),
+        "flagged" => "\\Flagged".to_string(),
+        "deleted" => "\\Deleted".to_string(),
+        "draft" => "\\Draft".to_string(),
+        _ => flag.trim().to_string(),
+    }
 }
 
 fn extract_flags(fetch: &Fetch) -> Vec<String> {
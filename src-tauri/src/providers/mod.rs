@@ -1,12 +1,31 @@
-use crate::models::{Credentials, EmailSummary};
+//! Mail server access. The functions here each open or borrow an IMAP
+//! session for `credentials`, do their work on a blocking thread, and
+//! report failures as [`ProviderError`]; submodules cover sending, folders,
+//! push notification, OAuth sign-in, and connection setup. Demo accounts
+//! are answered from [`demo`]'s generated mailbox instead.
+
+use crate::models::{Credentials, EmailSummary, Provider};
+use crate::policy::BlockTarget;
 use ::imap::Error as ImapError;
-use chrono::NaiveDate;
+use chrono::{DateTime, FixedOffset, NaiveDate};
 use native_tls::Error as TlsError;
+use secrecy::zeroize::Zeroizing;
+use std::fmt;
 use thiserror::Error;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::task::JoinHandle;
 
+pub mod api_send;
+pub mod autodiscover;
+pub mod demo;
+pub mod diagnostics;
+pub mod folders;
+pub mod idle;
 pub mod imap;
+pub mod oauth;
+pub mod preflight;
+pub mod session;
+pub mod smtp;
 
 #[derive(Debug, Error)]
 pub enum ProviderError {
@@ -48,14 +67,38 @@ pub async fn fetch_recent(
         ));
     }
 
-    imap::fetch_recent(credentials, limit).await
+    if credentials.provider == Provider::Demo {
+        return demo::fetch_recent(credentials, "INBOX", limit);
+    }
+    imap::fetch_recent(credentials, "INBOX", limit).await
+}
+
+/// Like [`fetch_recent`] but reads another mailbox, e.g. the provider's Junk folder.
+pub async fn fetch_recent_in_folder(
+    credentials: &Credentials,
+    folder: &str,
+    limit: usize,
+) -> Result<Vec<EmailSummary>, ProviderError> {
+    if limit == 0 {
+        return Err(ProviderError::Other(
+            "limit must be greater than zero".into(),
+        ));
+    }
+
+    if credentials.provider == Provider::Demo {
+        return demo::fetch_recent(credentials, folder, limit);
+    }
+    imap::fetch_recent(credentials, folder, limit).await
 }
 
 pub async fn verify_credentials(credentials: &Credentials) -> Result<(), ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::verify_credentials(credentials);
+    }
     imap::verify_credentials(credentials).await
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct MessageEnvelope {
     pub summary: EmailSummary,
     pub snippet: Option<String>,
@@ -63,6 +106,38 @@ pub struct MessageEnvelope {
     pub flags: Vec<String>,
 }
 
+/// Message content stays out of `Debug` output, and so out of logs.
+impl fmt::Debug for MessageEnvelope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageEnvelope")
+            .field("uid", &self.summary.uid)
+            .field("snippet_len", &self.snippet.as_ref().map(String::len))
+            .field("body_len", &self.body.as_ref().map(Vec::len))
+            .field("flags", &self.flags)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A full message plus the metadata needed to recreate it in another mailbox.
+#[derive(Clone)]
+pub struct TransferMessage {
+    pub uid: u32,
+    pub flags: Vec<String>,
+    pub internal_date: Option<DateTime<FixedOffset>>,
+    pub raw: Vec<u8>,
+}
+
+impl fmt::Debug for TransferMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TransferMessage")
+            .field("uid", &self.uid)
+            .field("flags", &self.flags)
+            .field("internal_date", &self.internal_date)
+            .field("raw_len", &self.raw.len())
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 pub struct BatchResult {
     pub index: usize,
@@ -70,6 +145,53 @@ pub struct BatchResult {
     pub requested: usize,
     pub fetched: usize,
     pub messages: Vec<MessageEnvelope>,
+    /// INBOX's UIDVALIDITY when the batch was fetched.
+    pub uid_validity: Option<u32>,
+}
+
+/// Every UID in INBOX at one moment, for finding messages deleted
+/// elsewhere. UIDs at or above `uid_next` were assigned after it.
+#[derive(Debug, Clone)]
+pub struct MailboxUids {
+    pub uid_validity: Option<u32>,
+    pub uid_next: Option<u32>,
+    pub uids: Vec<u32>,
+}
+
+/// Message size bounds in bytes. Both are exclusive, like IMAP `LARGER`
+/// and `SMALLER`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SizeRange {
+    pub larger_than: Option<u32>,
+    pub smaller_than: Option<u32>,
+}
+
+impl SizeRange {
+    /// The range as SEARCH criteria; `ALL` when it has no bounds.
+    pub fn search_criteria(&self) -> String {
+        let mut criteria = Vec::new();
+        if let Some(larger) = self.larger_than {
+            criteria.push(format!("LARGER {larger}"));
+        }
+        if let Some(smaller) = self.smaller_than {
+            criteria.push(format!("SMALLER {smaller}"));
+        }
+        if criteria.is_empty() {
+            "ALL".to_string()
+        } else {
+            criteria.join(" ")
+        }
+    }
+}
+
+/// Headers of a message the user sent, from the Sent folder.
+#[derive(Debug, Clone)]
+pub struct SentEnvelope {
+    pub uid: u32,
+    pub subject: String,
+    /// Every To and Cc address, lowercase.
+    pub recipients: Vec<String>,
+    pub sent_at: Option<i64>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -83,6 +205,7 @@ pub async fn fetch_all(
     since_uid: Option<u32>,
     chunk_size: usize,
     window: Option<SyncWindow>,
+    lite: bool,
 ) -> Result<
     (
         UnboundedReceiver<BatchResult>,
@@ -90,43 +213,238 @@ pub async fn fetch_all(
     ),
     ProviderError,
 > {
-    imap::fetch_all(credentials, since_uid, chunk_size, window).await
+    if credentials.provider == Provider::Demo {
+        return Ok(demo::fetch_all(
+            credentials,
+            since_uid,
+            chunk_size,
+            window,
+            lite,
+        ));
+    }
+    imap::fetch_all(credentials, since_uid, chunk_size, window, lite).await
+}
+
+/// Downloads the complete RFC 822 source of an INBOX message.
+pub async fn fetch_raw_message(
+    credentials: &Credentials,
+    uid: u32,
+) -> Result<Option<Zeroizing<Vec<u8>>>, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::fetch_raw_message(credentials, uid);
+    }
+    imap::fetch_raw_message(credentials, uid).await
 }
 
-pub async fn delete_message(credentials: &Credentials, uid: &str) -> Result<(), ProviderError> {
-    imap::delete_message(credentials, uid).await
+/// Deletes a folder's messages by UID. With `expected_uid_validity` set,
+/// nothing is deleted if the folder has been reset since the UIDs were
+/// cached.
+pub async fn delete_message(
+    credentials: &Credentials,
+    folder: &str,
+    uid: u32,
+    expected_uid_validity: Option<u32>,
+) -> Result<(), ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::delete_messages(credentials, folder, &[uid], expected_uid_validity);
+    }
+    imap::delete_message(credentials, folder, uid, expected_uid_validity).await
 }
 
 pub async fn delete_messages(
     credentials: &Credentials,
-    uids: &[String],
+    folder: &str,
+    uids: &[u32],
+    expected_uid_validity: Option<u32>,
+) -> Result<(), ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::delete_messages(credentials, folder, uids, expected_uid_validity);
+    }
+    imap::delete_messages(credentials, folder, uids, expected_uid_validity).await
+}
+
+/// Moves INBOX messages to another folder, e.g. the provider's Junk folder.
+pub async fn move_messages(
+    credentials: &Credentials,
+    uids: &[u32],
+    target_folder: &str,
+) -> Result<usize, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::move_messages(credentials, uids, target_folder);
+    }
+    imap::move_messages(credentials, uids, target_folder).await
+}
+
+/// Adds or removes flags on a folder's messages. Returns each message's
+/// flags as the server reports them after the change. Like
+/// [`delete_messages`], nothing changes if the folder's UIDVALIDITY is not
+/// `expected_uid_validity`.
+pub async fn store_flags(
+    credentials: &Credentials,
+    folder: &str,
+    uids: &[u32],
+    expected_uid_validity: Option<u32>,
+    flags: &[String],
+    add: bool,
+) -> Result<Vec<(u32, Vec<String>)>, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::store_flags(credentials, folder, uids, expected_uid_validity, flags, add);
+    }
+    imap::store_flags(credentials, folder, uids, expected_uid_validity, flags, add).await
+}
+
+pub async fn inbox_uids(credentials: &Credentials) -> Result<MailboxUids, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::inbox_uids(credentials);
+    }
+    imap::inbox_uids(credentials).await
+}
+
+/// Headers of the newest `limit` messages in the Sent folder.
+pub async fn fetch_sent(
+    credentials: &Credentials,
+    folder: &str,
+    limit: usize,
+) -> Result<Vec<SentEnvelope>, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::fetch_sent(credentials, folder, limit);
+    }
+    imap::fetch_sent(credentials, folder, limit).await
+}
+
+pub async fn list_folders(
+    credentials: &Credentials,
+) -> Result<Vec<folders::FolderStatus>, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::list_folders(credentials);
+    }
+    imap::list_folders(credentials).await
+}
+
+/// Full sync payload (snippet and body text) for specific INBOX UIDs.
+pub async fn fetch_envelopes(
+    credentials: &Credentials,
+    uids: &[u32],
+) -> Result<Vec<MessageEnvelope>, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::fetch_envelopes(credentials, uids);
+    }
+    imap::fetch_envelopes(credentials, uids).await
+}
+
+/// UIDs in `folder` above `after_uid`, ascending.
+pub async fn list_uids_after(
+    credentials: &Credentials,
+    folder: &str,
+    after_uid: u32,
+) -> Result<Vec<u32>, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::list_uids_after(credentials, folder, after_uid);
+    }
+    imap::list_uids_after(credentials, folder, after_uid).await
+}
+
+/// Headers of the largest messages in `folder` within `range`, largest
+/// first, found by the server rather than the cache.
+pub async fn search_by_size(
+    credentials: &Credentials,
+    folder: &str,
+    range: SizeRange,
+    limit: usize,
+) -> Result<Vec<EmailSummary>, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::search_by_size(credentials, folder, range, limit);
+    }
+    imap::search_by_size(credentials, folder, range, limit).await
+}
+
+pub async fn fetch_for_transfer(
+    credentials: &Credentials,
+    folder: &str,
+    uids: &[u32],
+) -> Result<Vec<TransferMessage>, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::fetch_for_transfer(credentials, folder, uids);
+    }
+    imap::fetch_for_transfer(credentials, folder, uids).await
+}
+
+/// Appends messages to `folder`, creating it if needed. Returns the `uid`s
+/// of the messages the server accepted; rejected ones are logged and left
+/// out.
+pub async fn append_messages(
+    credentials: &Credentials,
+    folder: &str,
+    messages: Vec<TransferMessage>,
+) -> Result<Vec<u32>, ProviderError> {
+    if credentials.provider == Provider::Demo {
+        return demo::append_messages(credentials, folder, messages);
+    }
+    imap::append_messages(credentials, folder, messages).await
+}
+
+/// Copies one message between folders, leaving the original in place.
+pub async fn copy_message(
+    credentials: &Credentials,
+    uid: u32,
+    source_folder: &str,
+    target_folder: &str,
 ) -> Result<(), ProviderError> {
-    imap::delete_messages(credentials, uids).await
+    if credentials.provider == Provider::Demo {
+        return demo::copy_message(credentials, uid, source_folder, target_folder);
+    }
+    imap::copy_message(credentials, uid, source_folder, target_folder).await
+}
+
+/// Creates, renames, deletes, or (un)subscribes a server folder.
+pub async fn manage_folder(
+    credentials: &Credentials,
+    operation: folders::FolderOperation,
+) -> Result<(), ProviderError> {
+    operation.validate().map_err(ProviderError::Other)?;
+    if credentials.provider == Provider::Demo {
+        return demo::manage_folder(credentials, operation);
+    }
+    imap::manage_folder(credentials, operation).await
 }
 
 pub async fn move_blocked_to_folder(
     credentials: &Credentials,
-    senders: &[String],
+    targets: &[BlockTarget],
     target_folder: &str,
 ) -> Result<usize, ProviderError> {
-    imap::move_blocked(credentials, senders, target_folder).await
+    if credentials.provider == Provider::Demo {
+        return demo::move_blocked(credentials, targets, target_folder);
+    }
+    imap::move_blocked(credentials, targets, target_folder).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::models::{Credentials, Provider};
+    use secrecy::SecretString;
 
     #[tokio::test]
     async fn clamp_limit_to_bounds() {
         let credentials = Credentials::new(
             Provider::Gmail,
             "user@example.com".to_string(),
-            "secret".to_string(),
+            SecretString::new("secret".to_string()),
             None,
             None,
         );
         let result = fetch_recent(&credentials, 0).await;
         assert!(matches!(result, Err(ProviderError::Other(_))));
     }
+
+    #[test]
+    fn size_ranges_become_search_criteria() {
+        let range = SizeRange {
+            larger_than: Some(5_000_000),
+            smaller_than: Some(20_000_000),
+        };
+        assert_eq!(range.search_criteria(), "LARGER 5000000 SMALLER 20000000");
+        assert_eq!(SizeRange::default().search_criteria(), "ALL");
+    }
 }
@@ -0,0 +1,289 @@
+//! OAuth sign-in for Gmail, Outlook, and Yahoo. An OAuth account keeps a
+//! refresh token where a password account keeps its password, in the
+//! keychain and in [`Credentials::password`]. Each IMAP or SMTP connection
+//! signs in with `XOAUTH2` and an access token from [`access_token`], which
+//! reuses the last one until it is about to expire and then refreshes it.
+
+use super::ProviderError;
+use crate::keychain::store_password_in_keychain;
+use crate::models::{AuthMethod, Credentials, Provider};
+use oauth2::basic::{BasicClient, BasicErrorResponse, BasicTokenResponse};
+use oauth2::reqwest::{async_http_client, http_client};
+use oauth2::url::Url;
+use oauth2::{
+    AuthUrl, AuthorizationCode, ClientId, CsrfToken, PkceCodeChallenge, PkceCodeVerifier,
+    RedirectUrl, RefreshToken, RequestTokenError, Scope, TokenResponse, TokenUrl,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use secrecy::{ExposeSecret, SecretString};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Where the provider sends the browser back with the authorization code.
+pub const REDIRECT_URI: &str = "http://localhost:8080";
+/// Access tokens this close to expiring are refreshed before use.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// Lifetime assumed when a token response leaves it out.
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+struct Endpoints {
+    auth_url: &'static str,
+    token_url: &'static str,
+    scopes: &'static [&'static str],
+    params: &'static [(&'static str, &'static str)],
+}
+
+fn endpoints(provider: Provider) -> Option<Endpoints> {
+    match provider {
+        Provider::Gmail => Some(Endpoints {
+            auth_url: "https://accounts.google.com/o/oauth2/v2/auth",
+            token_url: "https://oauth2.googleapis.com/token",
+            scopes: &["https://mail.google.com/"],
+            // Google only issues a refresh token for offline access, and
+            // only on first consent unless asked to show the prompt again.
+            params: &[("access_type", "offline"), ("prompt", "consent")],
+        }),
+        Provider::Outlook => Some(Endpoints {
+            auth_url: "https://login.microsoftonline.com/common/oauth2/v2.0/authorize",
+            token_url: "https://login.microsoftonline.com/common/oauth2/v2.0/token",
+            scopes: &[
+                "https://outlook.office.com/IMAP.AccessAsUser.All",
+                "https://outlook.office.com/SMTP.Send",
+                "offline_access",
+            ],
+            params: &[],
+        }),
+        Provider::Yahoo => Some(Endpoints {
+            auth_url: "https://api.login.yahoo.com/oauth2/request_auth",
+            token_url: "https://api.login.yahoo.com/oauth2/get_token",
+            scopes: &["mail-w"],
+            params: &[],
+        }),
+        Provider::Custom | Provider::Demo => None,
+    }
+}
+
+/// A public client: desktop apps cannot keep a secret, so PKCE stands in.
+fn client(provider: Provider, client_id: &str) -> Result<(BasicClient, Endpoints), ProviderError> {
+    let endpoints = endpoints(provider)
+        .ok_or_else(|| ProviderError::Other(format!("{provider} does not offer OAuth sign-in")))?;
+    let invalid = |err: oauth2::url::ParseError| ProviderError::Other(err.to_string());
+    let client = BasicClient::new(
+        ClientId::new(client_id.to_string()),
+        None,
+        AuthUrl::new(endpoints.auth_url.to_string()).map_err(invalid)?,
+        Some(TokenUrl::new(endpoints.token_url.to_string()).map_err(invalid)?),
+    )
+    .set_redirect_uri(RedirectUrl::new(REDIRECT_URI.to_string()).map_err(invalid)?);
+    Ok((client, endpoints))
+}
+
+/// A consent request in progress: the page to open, and the state the
+/// provider must send back along with the code.
+pub struct Authorization {
+    pub url: Url,
+    pub csrf: CsrfToken,
+    verifier: PkceCodeVerifier,
+}
+
+/// Starts consent for mail access on `provider`, asking for a refresh
+/// token so the account can reconnect without the browser.
+pub fn authorize(provider: Provider, client_id: &str) -> Result<Authorization, ProviderError> {
+    let (client, endpoints) = client(provider, client_id)?;
+    let (challenge, verifier) = PkceCodeChallenge::new_random_sha256();
+    let scopes = endpoints
+        .scopes
+        .iter()
+        .map(|scope| Scope::new(scope.to_string()));
+    let mut request = client
+        .authorize_url(CsrfToken::new_random)
+        .add_scopes(scopes)
+        .set_pkce_challenge(challenge);
+    for (name, value) in endpoints.params {
+        request = request.add_extra_param(*name, *value);
+    }
+    let (url, csrf) = request.url();
+    Ok(Authorization {
+        url,
+        csrf,
+        verifier,
+    })
+}
+
+/// Trades the code the browser came back with for tokens.
+pub async fn exchange_code(
+    provider: Provider,
+    client_id: &str,
+    authorization: Authorization,
+    code: String,
+) -> Result<BasicTokenResponse, ProviderError> {
+    let (client, _) = client(provider, client_id)?;
+    client
+        .exchange_code(AuthorizationCode::new(code))
+        .set_pkce_verifier(authorization.verifier)
+        .request_async(async_http_client)
+        .await
+        .map_err(token_error)
+}
+
+/// The latest tokens per account. `refresh` is set once the provider
+/// rotates the refresh token the account was connected with.
+#[derive(Default)]
+struct CachedTokens {
+    access: Option<(SecretString, Instant)>,
+    refresh: Option<SecretString>,
+}
+
+static TOKENS: Lazy<Mutex<HashMap<String, CachedTokens>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Caches the access token from `response` for `credentials` and returns
+/// it, so a fresh sign-in does not refresh straight away.
+pub fn remember(credentials: &Credentials, response: &BasicTokenResponse) -> SecretString {
+    let access_token = SecretString::new(response.access_token().secret().clone());
+    let lifetime = response.expires_in().unwrap_or(DEFAULT_LIFETIME);
+    let mut tokens = TOKENS.lock();
+    let cached = tokens.entry(credentials.key()).or_default();
+    cached.access = Some((access_token.clone(), Instant::now() + lifetime));
+    if let Some(refresh) = response.refresh_token() {
+        cached.refresh = Some(SecretString::new(refresh.secret().clone()));
+    }
+    access_token
+}
+
+/// Drops the cached access token, as after the server refused it, so the
+/// next connection refreshes it.
+pub fn forget(credentials: &Credentials) {
+    if let Some(cached) = TOKENS.lock().get_mut(&credentials.key()) {
+        cached.access = None;
+    }
+}
+
+/// A current access token for an OAuth account. Blocks while refreshing,
+/// so call it from a blocking thread. A refresh the provider refuses, as
+/// when access was revoked, is an [`ProviderError::Authentication`] error.
+pub fn access_token(credentials: &Credentials) -> Result<SecretString, ProviderError> {
+    let AuthMethod::OAuth2 { client_id } = &credentials.auth else {
+        return Err(ProviderError::Other(format!(
+            "{} does not sign in with OAuth",
+            credentials.email
+        )));
+    };
+    let refresh_token = {
+        let tokens = TOKENS.lock();
+        let cached = tokens.get(&credentials.key());
+        let current = cached
+            .and_then(|cached| cached.access.as_ref())
+            .filter(|(_, expires_at)| Instant::now() + REFRESH_MARGIN < *expires_at);
+        if let Some((access_token, _)) = current {
+            return Ok(access_token.clone());
+        }
+        cached
+            .and_then(|cached| cached.refresh.clone())
+            .unwrap_or_else(|| credentials.password.clone())
+    };
+
+    let (client, _) = client(credentials.provider, client_id)?;
+    let response = client
+        .exchange_refresh_token(&RefreshToken::new(refresh_token.expose_secret().clone()))
+        .request(http_client)
+        .map_err(token_error)?;
+    debug!(account = %credentials.email, "refreshed OAuth access token");
+    if let Some(rotated) = response.refresh_token() {
+        if rotated.secret() != refresh_token.expose_secret() {
+            let rotated = SecretString::new(rotated.secret().clone());
+            if let Err(err) = store_password_in_keychain(&credentials.email, &rotated) {
+                warn!(account = %credentials.email, %err, "failed to save rotated refresh token");
+            }
+        }
+    }
+    Ok(remember(credentials, &response))
+}
+
+fn token_error<RE: std::error::Error + 'static>(
+    err: RequestTokenError<RE, BasicErrorResponse>,
+) -> ProviderError {
+    match err {
+        RequestTokenError::ServerResponse(response) => ProviderError::Authentication(format!(
+            "the provider refused the OAuth token ({response}); connect the account again"
+        )),
+        other => ProviderError::Network(format!("OAuth token request failed: {other}")),
+    }
+}
+
+/// The `XOAUTH2` initial response, before the base64 the protocol adds.
+fn xoauth2_response(user: &str, access_token: &str) -> String {
+    format!("user={user}\x01auth=Bearer {access_token}\x01\x01")
+}
+
+/// `AUTHENTICATE XOAUTH2` for the imap crate.
+pub(crate) struct XOAuth2<'a> {
+    pub user: &'a str,
+    pub access_token: &'a SecretString,
+}
+
+impl ::imap::Authenticator for XOAuth2<'_> {
+    type Response = String;
+
+    fn process(&self, challenge: &[u8]) -> String {
+        // A challenge after the first is the server's error, as JSON; an
+        // empty reply lets it finish with NO.
+        if challenge.is_empty() {
+            xoauth2_response(self.user, self.access_token.expose_secret())
+        } else {
+            String::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::imap::Authenticator;
+    use oauth2::basic::BasicTokenType;
+    use oauth2::{AccessToken, EmptyExtraTokenFields, StandardTokenResponse};
+
+    #[test]
+    fn xoauth2_answers_the_first_challenge_only() {
+        let token = SecretString::new("ya29.token".into());
+        let authenticator = XOAuth2 {
+            user: "me@gmail.com",
+            access_token: &token,
+        };
+        assert_eq!(
+            authenticator.process(b""),
+            "user=me@gmail.com\x01auth=Bearer ya29.token\x01\x01"
+        );
+        assert_eq!(authenticator.process(br#"{"status":"400"}"#), "");
+    }
+
+    #[test]
+    fn cached_tokens_are_used_until_forgotten() {
+        let credentials = Credentials::new(
+            Provider::Gmail,
+            "cache@gmail.com".into(),
+            SecretString::new("refresh".into()),
+            None,
+            None,
+        );
+        let mut response = StandardTokenResponse::new(
+            AccessToken::new("access".into()),
+            BasicTokenType::Bearer,
+            EmptyExtraTokenFields {},
+        );
+        response.set_expires_in(Some(&Duration::from_secs(3600)));
+        remember(&credentials, &response);
+        assert!(access_token(&credentials).is_err(), "password accounts");
+
+        let oauth = credentials.with_auth(AuthMethod::OAuth2 {
+            client_id: "client".into(),
+        });
+        assert_eq!(access_token(&oauth).unwrap().expose_secret(), "access");
+
+        forget(&oauth);
+        let tokens = TOKENS.lock();
+        assert!(tokens[&oauth.key()].access.is_none());
+    }
+}
@@ -0,0 +1,108 @@
+//! Connect preflight: turns the provider-specific login failures users hit
+//! most often (app password required, web login required, IMAP disabled)
+//! into stable codes with a link to the page that fixes them.
+
+use crate::models::{Credentials, Provider};
+use crate::providers::{self, ProviderError};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoginIssue {
+    /// Stable identifier for the UI, e.g. `app_password_required`.
+    pub code: &'static str,
+    pub message: String,
+    pub help_url: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PreflightReport {
+    pub ok: bool,
+    pub issue: Option<LoginIssue>,
+}
+
+/// Logs in and selects INBOX, classifying any failure.
+pub async fn preflight(credentials: &Credentials) -> PreflightReport {
+    match providers::verify_credentials(credentials).await {
+        Ok(()) => PreflightReport {
+            ok: true,
+            issue: None,
+        },
+        Err(err) => PreflightReport {
+            ok: false,
+            issue: Some(classify(credentials.provider, &err)),
+        },
+    }
+}
+
+pub fn classify(provider: Provider, error: &ProviderError) -> LoginIssue {
+    let raw = error.to_string();
+    let lowered = raw.to_lowercase();
+    let app_password_url = provider.app_password_url();
+
+    if lowered.contains("application-specific password") || lowered.contains("app password") {
+        return LoginIssue {
+            code: "app_password_required",
+            message: format!(
+                "{} requires an app password instead of your normal password.",
+                provider.display_name()
+            ),
+            help_url: app_password_url,
+        };
+    }
+    if lowered.contains("webalert") || lowered.contains("log in via your web browser") {
+        return LoginIssue {
+            code: "web_login_required",
+            message: format!(
+                "{} wants you to sign in through the browser once before IMAP access works.",
+                provider.display_name()
+            ),
+            help_url: provider.web_login_url(),
+        };
+    }
+    if lowered.contains("not enabled for imap") || lowered.contains("imap access is disabled") {
+        return LoginIssue {
+            code: "imap_disabled",
+            message: format!(
+                "IMAP access is turned off for this {} account.",
+                provider.display_name()
+            ),
+            help_url: provider.imap_settings_url(),
+        };
+    }
+    if lowered.contains("basicauthblocked") || lowered.contains("basic authentication is disabled")
+    {
+        return LoginIssue {
+            code: "basic_auth_blocked",
+            message: "Password sign-in is blocked for this account; use OAuth instead.".into(),
+            help_url: None,
+        };
+    }
+
+    match error {
+        // Gmail and Yahoo reject regular passwords with a generic
+        // "invalid credentials", so point at app passwords there too.
+        ProviderError::Authentication(_) if app_password_url.is_some() => LoginIssue {
+            code: "invalid_credentials",
+            message: format!(
+                "{} rejected the password. Accounts with two-step verification need an app password.",
+                provider.display_name()
+            ),
+            help_url: app_password_url,
+        },
+        ProviderError::Authentication(_) => LoginIssue {
+            code: "invalid_credentials",
+            message: "The server rejected the email address or password.".into(),
+            help_url: None,
+        },
+        ProviderError::Network(_) => LoginIssue {
+            code: "network",
+            message: raw,
+            help_url: None,
+        },
+        ProviderError::Imap(_) | ProviderError::Other(_) => LoginIssue {
+            code: "server_error",
+            message: raw,
+            help_url: None,
+        },
+    }
+}
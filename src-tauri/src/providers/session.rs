@@ -0,0 +1,371 @@
+//! Pooled IMAP sessions. A finished operation hands its session back instead
+//! of logging out, and the next operation on the account reuses it after a
+//! NOOP keep-alive check. Dropped connections are replaced transparently:
+//! connecting retries a few times with backoff, and operations that are safe
+//! to repeat run once more on a fresh session after a BYE or timeout. Each
+//! replacement is counted per account, and a burst of them is logged as a
+//! reconnect storm.
+
+use crate::models::{AuthMethod, Credentials};
+use crate::providers::oauth::{self, XOAuth2};
+use crate::providers::ProviderError;
+use chrono::Utc;
+use native_tls::{TlsConnector, TlsStream};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use secrecy::ExposeSecret;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task;
+use tracing::{debug, warn};
+
+pub(crate) type ImapSession = ::imap::Session<TlsStream<TcpStream>>;
+
+/// Idle sessions kept per account.
+const MAX_IDLE_PER_ACCOUNT: usize = 2;
+/// Servers may drop a connection idle for 30 minutes; retire ours well before.
+const MAX_IDLE: Duration = Duration::from_secs(10 * 60);
+/// Sessions idle for less than this are reused without a NOOP.
+const NOOP_AFTER: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// Generous, since one FETCH of a large sync batch can take a while.
+const IO_TIMEOUT: Duration = Duration::from_secs(120);
+const CONNECT_ATTEMPTS: u32 = 3;
+const CONNECT_BACKOFF: Duration = Duration::from_millis(500);
+const STORM_WINDOW: Duration = Duration::from_secs(60);
+const STORM_THRESHOLD: usize = 5;
+
+struct IdleSession {
+    session: ImapSession,
+    since: Instant,
+}
+
+static IDLE: Lazy<Mutex<HashMap<String, Vec<IdleSession>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static RECONNECTS: Lazy<Mutex<HashMap<String, ReconnectLog>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Reconnects for one account.
+#[derive(Debug, Default)]
+struct ReconnectLog {
+    total: u64,
+    recent: VecDeque<Instant>,
+    storming: bool,
+    last_error: Option<String>,
+    last_at: Option<i64>,
+}
+
+impl ReconnectLog {
+    /// Counts a reconnect. Returns how many fell inside the storm window when
+    /// this one starts a storm.
+    fn record(&mut self, now: Instant, error: String) -> Option<usize> {
+        self.total += 1;
+        self.last_error = Some(error);
+        self.last_at = Some(Utc::now().timestamp());
+        self.recent.push_back(now);
+        while self
+            .recent
+            .front()
+            .is_some_and(|first| now.duration_since(*first) > STORM_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        if self.recent.len() < STORM_THRESHOLD {
+            self.storming = false;
+            return None;
+        }
+        if self.storming {
+            return None;
+        }
+        self.storming = true;
+        Some(self.recent.len())
+    }
+}
+
+/// Reconnect counts and pool size for an account, for diagnostics.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionHealth {
+    pub account: String,
+    pub idle_sessions: usize,
+    pub reconnects: u64,
+    /// Reconnects in the last minute.
+    pub recent_reconnects: usize,
+    pub storming: bool,
+    pub last_error: Option<String>,
+    pub last_reconnect_at: Option<i64>,
+}
+
+pub fn health() -> Vec<SessionHealth> {
+    let idle = IDLE.lock();
+    let reconnects = RECONNECTS.lock();
+    let now = Instant::now();
+    let mut accounts = reconnects
+        .iter()
+        .map(|(account, log)| SessionHealth {
+            account: account.clone(),
+            idle_sessions: idle_count(&idle, account),
+            reconnects: log.total,
+            recent_reconnects: log
+                .recent
+                .iter()
+                .filter(|at| now.duration_since(**at) <= STORM_WINDOW)
+                .count(),
+            storming: log.storming,
+            last_error: log.last_error.clone(),
+            last_reconnect_at: log.last_at,
+        })
+        .collect::<Vec<_>>();
+    accounts.sort_by(|a, b| a.account.cmp(&b.account));
+    accounts
+}
+
+fn idle_count(idle: &HashMap<String, Vec<IdleSession>>, account: &str) -> usize {
+    idle.iter()
+        .filter(|(key, _)| key.ends_with(&format!("::{account}")))
+        .map(|(_, sessions)| sessions.len())
+        .sum()
+}
+
+/// Logs in without touching the pool. Reads and writes time out, so a
+/// stalled server fails the operation, which can then be retried, instead
+/// of hanging it.
+pub(crate) fn connect(credentials: &Credentials) -> Result<ImapSession, ProviderError> {
+    let domain = credentials
+        .custom_host
+        .as_deref()
+        .unwrap_or_else(|| credentials.provider.imap_host());
+    let port = credentials.custom_port.unwrap_or(993);
+    let mut last_error = None;
+    let mut tcp = None;
+    for address in (domain, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                tcp = Some(stream);
+                break;
+            }
+            Err(err) => last_error = Some(err),
+        }
+    }
+    let tcp = match (tcp, last_error) {
+        (Some(tcp), _) => tcp,
+        (None, Some(err)) => return Err(err.into()),
+        (None, None) => {
+            return Err(ProviderError::Network(format!("{domain} has no addresses")));
+        }
+    };
+    tcp.set_read_timeout(Some(IO_TIMEOUT))?;
+    tcp.set_write_timeout(Some(IO_TIMEOUT))?;
+    let tls = tls_connector()?
+        .connect(domain, tcp)
+        .map_err(|err| ProviderError::Network(err.to_string()))?;
+    let mut client = ::imap::Client::new(tls);
+    client
+        .read_greeting()
+        .map_err(|err| ProviderError::Network(err.to_string()))?;
+    sign_in(client, credentials)
+}
+
+/// `LOGIN` with the password, or `AUTHENTICATE XOAUTH2` with a current
+/// access token for OAuth accounts.
+pub(crate) fn sign_in(
+    client: ::imap::Client<TlsStream<TcpStream>>,
+    credentials: &Credentials,
+) -> Result<ImapSession, ProviderError> {
+    match credentials.auth {
+        AuthMethod::Password => client
+            .login(&credentials.email, credentials.password.expose_secret())
+            .map_err(|(err, _client)| ProviderError::Authentication(err.to_string())),
+        AuthMethod::OAuth2 { .. } => {
+            let access_token = oauth::access_token(credentials)?;
+            let authenticator = XOAuth2 {
+                user: &credentials.email,
+                access_token: &access_token,
+            };
+            client
+                .authenticate("XOAUTH2", &authenticator)
+                .map_err(|(err, _client)| {
+                    // Revoked early, perhaps; the next attempt refreshes.
+                    oauth::forget(credentials);
+                    ProviderError::Authentication(err.to_string())
+                })
+        }
+    }
+}
+
+/// `test-support` builds also trust the in-process test server's authority.
+fn tls_connector() -> Result<TlsConnector, ProviderError> {
+    let builder = &mut TlsConnector::builder();
+    #[cfg(feature = "test-support")]
+    if let Some(root) = crate::test_support::trusted_root() {
+        builder.add_root_certificate(root);
+    }
+    Ok(builder.build()?)
+}
+
+/// An idle session that still answers NOOP, or a new one.
+pub(crate) fn checkout(credentials: &Credentials) -> Result<ImapSession, ProviderError> {
+    let key = credentials.key();
+    loop {
+        let Some(idle) = IDLE.lock().get_mut(&key).and_then(Vec::pop) else {
+            break;
+        };
+        let idle_for = idle.since.elapsed();
+        if idle_for > MAX_IDLE {
+            continue;
+        }
+        let mut session = idle.session;
+        if idle_for < NOOP_AFTER {
+            return Ok(session);
+        }
+        match session.noop() {
+            Ok(()) => return Ok(session),
+            Err(err) => {
+                debug!(account = %credentials.email, %err, "idle IMAP session failed keep-alive");
+                record_reconnect(credentials, &ProviderError::from(err));
+            }
+        }
+    }
+    connect_with_retries(credentials)
+}
+
+/// Hands a healthy session back for reuse, or logs it out when the account
+/// already has enough idle ones.
+pub(crate) fn release(credentials: &Credentials, mut session: ImapSession) {
+    {
+        let mut idle = IDLE.lock();
+        let sessions = idle.entry(credentials.key()).or_default();
+        if sessions.len() < MAX_IDLE_PER_ACCOUNT {
+            sessions.push(IdleSession {
+                session,
+                since: Instant::now(),
+            });
+            return;
+        }
+    }
+    let _ = session.logout();
+}
+
+/// Drops the account's idle sessions: after one failed the rest likely
+/// share its fate, and a disconnected account should not keep any open.
+pub fn discard_idle(credentials: &Credentials) {
+    IDLE.lock().remove(&credentials.key());
+}
+
+fn connect_with_retries(credentials: &Credentials) -> Result<ImapSession, ProviderError> {
+    let mut attempt = 1;
+    loop {
+        match connect(credentials) {
+            Ok(session) => return Ok(session),
+            Err(err @ ProviderError::Network(_)) if attempt < CONNECT_ATTEMPTS => {
+                record_reconnect(credentials, &err);
+                std::thread::sleep(CONNECT_BACKOFF * attempt);
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Whether `error` looks like a dropped connection rather than a refusal.
+fn is_transient(error: &ProviderError) -> bool {
+    match error {
+        ProviderError::Network(_) => true,
+        ProviderError::Imap(message) => {
+            let message = message.to_lowercase();
+            ["bye", "connection", "timed out", "broken pipe", "eof"]
+                .iter()
+                .any(|needle| message.contains(needle))
+        }
+        ProviderError::Authentication(_) | ProviderError::Other(_) => false,
+    }
+}
+
+fn record_reconnect(credentials: &Credentials, error: &ProviderError) {
+    let storm = RECONNECTS
+        .lock()
+        .entry(credentials.email.clone())
+        .or_default()
+        .record(Instant::now(), error.to_string());
+    if let Some(reconnects) = storm {
+        warn!(
+            account = %credentials.email,
+            reconnects,
+            window_secs = STORM_WINDOW.as_secs(),
+            %error,
+            "IMAP reconnect storm"
+        );
+    }
+}
+
+/// Runs an operation that is safe to repeat, such as a fetch or a flag
+/// store, on a blocking thread. If the connection drops under it, the
+/// operation runs once more on a fresh session.
+pub(crate) async fn retry_once<T, F>(
+    credentials: &Credentials,
+    operation: &'static str,
+    run: F,
+) -> Result<T, ProviderError>
+where
+    T: Send + 'static,
+    F: Fn(Credentials) -> Result<T, ProviderError> + Send + Sync + 'static,
+{
+    let run = Arc::new(run);
+    match run_blocking(credentials, run.clone()).await {
+        Err(err) if is_transient(&err) => {
+            warn!(
+                account = %credentials.email,
+                operation,
+                %err,
+                "retrying on a fresh IMAP session"
+            );
+            record_reconnect(credentials, &err);
+            discard_idle(credentials);
+            run_blocking(credentials, run).await
+        }
+        result => result,
+    }
+}
+
+async fn run_blocking<T, F>(credentials: &Credentials, run: Arc<F>) -> Result<T, ProviderError>
+where
+    T: Send + 'static,
+    F: Fn(Credentials) -> Result<T, ProviderError> + Send + Sync + 'static,
+{
+    let credentials = credentials.clone();
+    task::spawn_blocking(move || run(credentials))
+        .await
+        .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn storm_is_reported_once_per_burst() {
+        let mut log = ReconnectLog::default();
+        let start = Instant::now();
+        let reports = (0..STORM_THRESHOLD + 2)
+            .filter_map(|i| log.record(start + Duration::from_secs(i as u64), "bye".into()))
+            .collect::<Vec<_>>();
+        assert_eq!(reports, vec![STORM_THRESHOLD]);
+        assert!(log.storming);
+
+        let later = start + STORM_WINDOW * 3;
+        assert_eq!(log.record(later, "bye".into()), None);
+        assert!(!log.storming);
+        assert_eq!(log.total, STORM_THRESHOLD as u64 + 3);
+    }
+
+    #[test]
+    fn only_connection_failures_are_retried() {
+        let imap = |message: &str| ProviderError::Imap(message.into());
+        assert!(is_transient(&ProviderError::Network("timed out".into())));
+        assert!(is_transient(&imap("Connection Lost: BYE")));
+        assert!(!is_transient(&imap("NO [TRYCREATE] no such mailbox")));
+        assert!(!is_transient(&ProviderError::Authentication("bad".into())));
+    }
+}
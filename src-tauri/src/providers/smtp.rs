@@ -0,0 +1,302 @@
+//! Sending mail over SMTP submission (port 587 with STARTTLS, or 465 with
+//! implicit TLS when 587 cannot be reached), logging in with the account's
+//! stored password or, for OAuth accounts, `XOAUTH2` and an access token.
+//! Messages are built here from a compose draft; the finished bytes are
+//! also what gets filed in Sent.
+
+use super::{oauth, ProviderError};
+use crate::models::{AuthMethod, Credentials, Provider};
+use lettre::message::header::{ContentType, Header, HeaderName, HeaderValue};
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::{Credentials as SmtpCredentials, Mechanism};
+use lettre::{Message, SmtpTransport, Transport};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::task;
+use tracing::warn;
+
+pub const SUBMISSION_PORT: u16 = 587;
+/// Submission over implicit TLS (RFC 8314), for networks that block 587.
+pub const IMPLICIT_TLS_PORT: u16 = 465;
+const SMTP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A message as composed, before it is built.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OutgoingMessage {
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub subject: String,
+    /// Plain text body.
+    pub body: String,
+    /// Message-ID of the message replied to, with or without brackets.
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+    /// The `Auto-Submitted` value (RFC 3834) for mail the client sends on
+    /// its own, such as `auto-replied`. Never taken from the frontend.
+    #[serde(skip)]
+    pub auto_submitted: Option<String>,
+    pub attachments: Vec<AttachmentData>,
+}
+
+/// RFC 3834 `Auto-Submitted`, which keeps other responders from answering.
+#[derive(Debug, Clone)]
+struct AutoSubmitted(String);
+
+impl Header for AutoSubmitted {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Auto-Submitted")
+    }
+
+    fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self(value.trim().to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct AttachmentData {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+impl std::fmt::Debug for AttachmentData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AttachmentData")
+            .field("filename", &self.filename)
+            .field("content_type", &self.content_type)
+            .field("size", &self.data.len())
+            .finish()
+    }
+}
+
+/// The submission server for an account. Custom accounts send through the
+/// IMAP host with `imap.` swapped for `smtp.`, or the IMAP host itself.
+pub fn server(credentials: &Credentials) -> String {
+    match credentials.provider {
+        Provider::Custom => credentials
+            .custom_host
+            .as_deref()
+            .map(|host| match host.strip_prefix("imap.") {
+                Some(domain) => format!("smtp.{domain}"),
+                None => host.to_string(),
+            })
+            .unwrap_or_else(|| Provider::Custom.smtp_host().to_string()),
+        provider => provider.smtp_host().to_string(),
+    }
+}
+
+/// Builds `draft` as sent from the account. `Bcc` recipients get the
+/// message either way but are only listed in the headers when `keep_bcc`
+/// is set, which HTTP send APIs need to find them.
+pub fn build(
+    credentials: &Credentials,
+    draft: &OutgoingMessage,
+    message_id: &str,
+    keep_bcc: bool,
+) -> Result<Message, ProviderError> {
+    let mailbox = |address: &str| -> Result<Mailbox, ProviderError> {
+        address.trim().parse::<Mailbox>().map_err(|err| {
+            ProviderError::Other(format!("'{}' is not valid: {err}", address.trim()))
+        })
+    };
+    let bracketed = |id: &str| format!("<{}>", id.trim().trim_matches(['<', '>']));
+
+    let mut builder = Message::builder()
+        .from(mailbox(&credentials.email)?)
+        .subject(draft.subject.trim())
+        .message_id(Some(bracketed(message_id)))
+        .date_now();
+    for address in &draft.to {
+        builder = builder.to(mailbox(address)?);
+    }
+    for address in &draft.cc {
+        builder = builder.cc(mailbox(address)?);
+    }
+    for address in &draft.bcc {
+        builder = builder.bcc(mailbox(address)?);
+    }
+    if keep_bcc {
+        builder = builder.keep_bcc();
+    }
+    if let Some(parent) = draft
+        .in_reply_to
+        .as_deref()
+        .filter(|id| !id.trim().is_empty())
+    {
+        builder = builder.in_reply_to(bracketed(parent));
+    }
+    if !draft.references.is_empty() {
+        let references = draft
+            .references
+            .iter()
+            .map(|id| bracketed(id))
+            .collect::<Vec<_>>()
+            .join(" ");
+        builder = builder.references(references);
+    }
+    if let Some(value) = draft
+        .auto_submitted
+        .as_deref()
+        .filter(|value| !value.trim().is_empty())
+    {
+        builder = builder.header(AutoSubmitted(value.trim().to_string()));
+    }
+
+    let text = SinglePart::plain(draft.body.clone());
+    let message = if draft.attachments.is_empty() {
+        builder.singlepart(text)
+    } else {
+        let mut parts = MultiPart::mixed().singlepart(text);
+        for attachment in &draft.attachments {
+            let content_type = ContentType::parse(&attachment.content_type)
+                .unwrap_or_else(|_| ContentType::parse("application/octet-stream").unwrap());
+            parts = parts.singlepart(
+                Attachment::new(attachment.filename.clone())
+                    .body(attachment.data.clone(), content_type),
+            );
+        }
+        builder.multipart(parts)
+    };
+    message.map_err(|err| ProviderError::Other(format!("could not build the message: {err}")))
+}
+
+/// Submits a built message. The connection failing outright comes back as
+/// [`ProviderError::Network`], a rejected login as `Authentication`.
+pub async fn send(credentials: &Credentials, message: Message) -> Result<(), ProviderError> {
+    // Demo mail goes nowhere; the copy filed in Sent is all there is of it.
+    if credentials.provider == Provider::Demo {
+        return Ok(());
+    }
+    let credentials = credentials.clone();
+    task::spawn_blocking(move || send_blocking(&credentials, &message))
+        .await
+        .map_err(|err| ProviderError::Other(format!("Background task failure: {err}")))?
+}
+
+fn send_blocking(credentials: &Credentials, message: &Message) -> Result<(), ProviderError> {
+    match send_on_port(credentials, message, SUBMISSION_PORT) {
+        Err(ProviderError::Network(first)) => {
+            warn!(email = %credentials.email, error = %first, "trying SMTP over implicit TLS");
+            send_on_port(credentials, message, IMPLICIT_TLS_PORT).map_err(|err| match err {
+                ProviderError::Network(second) => {
+                    ProviderError::Network(format!("{first}; {second}"))
+                }
+                other => other,
+            })
+        }
+        result => result,
+    }
+}
+
+fn send_on_port(
+    credentials: &Credentials,
+    message: &Message,
+    port: u16,
+) -> Result<(), ProviderError> {
+    let host = server(credentials);
+    let builder = if port == IMPLICIT_TLS_PORT {
+        SmtpTransport::relay(&host)
+    } else {
+        SmtpTransport::starttls_relay(&host)
+    };
+    let builder = builder
+        .map_err(|err| ProviderError::Network(format!("{host}: {err}")))?
+        .port(port)
+        .timeout(Some(SMTP_TIMEOUT));
+    let builder = match credentials.auth {
+        AuthMethod::Password => builder.credentials(SmtpCredentials::new(
+            credentials.email.clone(),
+            credentials.password.expose_secret().to_string(),
+        )),
+        AuthMethod::OAuth2 { .. } => {
+            let access_token = oauth::access_token(credentials)?;
+            builder
+                .credentials(SmtpCredentials::new(
+                    credentials.email.clone(),
+                    access_token.expose_secret().to_string(),
+                ))
+                .authentication(vec![Mechanism::Xoauth2])
+        }
+    };
+    let transport = builder.build();
+    transport.send(message).map(|_| ()).map_err(|err| {
+        let code = err.status().map(|code| code.to_string());
+        match code.as_deref() {
+            // 530/534/535: authentication required, too weak, or rejected.
+            Some("530" | "534" | "535") => {
+                oauth::forget(credentials);
+                ProviderError::Authentication(err.to_string())
+            }
+            Some(_) => ProviderError::Other(err.to_string()),
+            None if err.is_timeout() || err.is_tls() || !err.is_client() => {
+                ProviderError::Network(format!("{host}:{port}: {err}"))
+            }
+            None => ProviderError::Other(err.to_string()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::SecretString;
+
+    fn credentials(provider: Provider, host: Option<&str>) -> Credentials {
+        Credentials::new(
+            provider,
+            "me@example.com".into(),
+            SecretString::new("secret".into()),
+            host.map(str::to_string),
+            None,
+        )
+    }
+
+    #[test]
+    fn picks_the_submission_server() {
+        assert_eq!(
+            server(&credentials(Provider::Gmail, None)),
+            "smtp.gmail.com"
+        );
+        assert_eq!(
+            server(&credentials(Provider::Custom, Some("imap.example.net"))),
+            "smtp.example.net"
+        );
+        assert_eq!(
+            server(&credentials(Provider::Custom, Some("mail.example.net"))),
+            "mail.example.net"
+        );
+    }
+
+    #[test]
+    fn builds_replies_and_hides_bcc_unless_asked() {
+        let draft = OutgoingMessage {
+            to: vec!["Ann <ann@example.org>".into()],
+            bcc: vec!["boss@example.org".into()],
+            subject: "Re: Plans".into(),
+            body: "Sounds good.".into(),
+            in_reply_to: Some("parent@example.org".into()),
+            references: vec!["root@example.org".into(), "parent@example.org".into()],
+            auto_submitted: Some("auto-replied".into()),
+            ..Default::default()
+        };
+        let creds = credentials(Provider::Gmail, None);
+        let formatted = |keep_bcc| {
+            let message = build(&creds, &draft, "id@example.com", keep_bcc).unwrap();
+            String::from_utf8(message.formatted()).unwrap()
+        };
+        let sent = formatted(false);
+        assert!(sent.contains("Message-ID: <id@example.com>"));
+        assert!(sent.contains("In-Reply-To: <parent@example.org>"));
+        assert!(sent.contains("References: <root@example.org> <parent@example.org>"));
+        assert!(sent.contains("Auto-Submitted: auto-replied"));
+        assert!(!sent.contains("boss@example.org"));
+        assert!(formatted(true).contains("Bcc: boss@example.org"));
+    }
+}
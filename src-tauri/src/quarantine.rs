@@ -0,0 +1,136 @@
+//! Local quarantine for mail that looks dangerous. Each cached message is
+//! scored from its spam probability, links that disguise their target or
+//! point at a blocklisted domain, and risky attachments. At or above
+//! [`THRESHOLD`] it is hidden from listings and rendered with links and
+//! remote images disabled until the user releases it. Nothing changes on
+//! the server.
+
+use serde::{Deserialize, Serialize};
+
+/// Risk at which a message is quarantined.
+pub const THRESHOLD: f64 = 0.8;
+/// Spam scores from here on are named as a reason.
+const SPAM_REASON_MIN: f64 = 0.5;
+const DECEPTIVE_LINK_RISK: f64 = 0.6;
+const BLOCKED_LINK_RISK: f64 = 0.8;
+const RISKY_ATTACHMENT_RISK: f64 = 0.7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuarantineReason {
+    Spam,
+    /// A link whose text shows a different domain than it opens.
+    DeceptiveLink,
+    /// A link to a blocklisted domain.
+    BlockedLink,
+    RiskyAttachment,
+}
+
+/// What is known about a message's risk.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Signals {
+    pub spam_score: Option<f64>,
+    pub deceptive_links: usize,
+    pub blocked_links: usize,
+    pub risky_attachments: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assessment {
+    /// Between 0 and 1.
+    pub score: f64,
+    pub reasons: Vec<QuarantineReason>,
+}
+
+impl Assessment {
+    pub fn quarantined(&self) -> bool {
+        self.score >= THRESHOLD
+    }
+}
+
+/// Combines the signals as independent evidence: the score is the chance
+/// that at least one of them is right. One deceptive link or one risky
+/// attachment is not enough on its own; together, or alongside a spammy
+/// message, they are.
+pub fn assess(signals: &Signals) -> Assessment {
+    let spam_score = signals.spam_score.unwrap_or(0.0).clamp(0.0, 1.0);
+    let mut reasons = Vec::new();
+    if spam_score >= SPAM_REASON_MIN {
+        reasons.push(QuarantineReason::Spam);
+    }
+    let mut clean = 1.0 - spam_score;
+    for (reason, risk, count) in [
+        (
+            QuarantineReason::DeceptiveLink,
+            DECEPTIVE_LINK_RISK,
+            signals.deceptive_links,
+        ),
+        (
+            QuarantineReason::BlockedLink,
+            BLOCKED_LINK_RISK,
+            signals.blocked_links,
+        ),
+        (
+            QuarantineReason::RiskyAttachment,
+            RISKY_ATTACHMENT_RISK,
+            signals.risky_attachments,
+        ),
+    ] {
+        if count > 0 {
+            clean *= 1.0 - risk;
+            reasons.push(reason);
+        }
+    }
+    Assessment {
+        score: 1.0 - clean,
+        reasons,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_weak_signals_are_not_quarantined() {
+        let deceptive = Signals {
+            deceptive_links: 2,
+            ..Signals::default()
+        };
+        assert!(!assess(&deceptive).quarantined());
+        let attachment = Signals {
+            risky_attachments: 1,
+            spam_score: Some(0.1),
+            ..Signals::default()
+        };
+        assert!(!assess(&attachment).quarantined());
+        assert!(!assess(&Signals::default()).quarantined());
+    }
+
+    #[test]
+    fn strong_or_combined_signals_are_quarantined() {
+        let blocked = assess(&Signals {
+            blocked_links: 1,
+            ..Signals::default()
+        });
+        assert!(blocked.quarantined());
+        assert_eq!(blocked.reasons, vec![QuarantineReason::BlockedLink]);
+
+        let combined = assess(&Signals {
+            spam_score: Some(0.6),
+            deceptive_links: 1,
+            ..Signals::default()
+        });
+        assert!(combined.quarantined());
+        assert_eq!(
+            combined.reasons,
+            vec![QuarantineReason::Spam, QuarantineReason::DeceptiveLink]
+        );
+
+        assert!(assess(&Signals {
+            spam_score: Some(0.95),
+            ..Signals::default()
+        })
+        .quarantined());
+    }
+}
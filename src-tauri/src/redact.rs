@@ -0,0 +1,159 @@
+//! Masks personal data in text that leaves the app's control: log lines,
+//! and prompts when prompt redaction is on. Email addresses keep their
+//! first character and domain, phone numbers become `[phone]`, and digits
+//! that read as a one-time code become `[code]`. Uids, counts, dates, and
+//! addresses of servers are left alone so logs stay useful.
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::io;
+use tracing_subscriber::fmt::MakeWriter;
+
+static EMAIL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)\b([a-z0-9])[a-z0-9._%+-]*@([a-z0-9-]+(?:\.[a-z0-9-]+)*\.[a-z]{2,})\b")
+        .expect("email pattern is valid")
+});
+/// Digit groups joined by spaces, dots, or dashes, optionally with a
+/// country code or a bracketed area code. Matches with too few digits for
+/// a phone number are kept.
+static PHONE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{2,4}\)[\s.-]?)?\b\d{2,5}(?:[\s.-]\d{2,5}){1,3}\b")
+        .expect("phone pattern is valid")
+});
+/// A code named as one: "code: 123456", "OTP 4821", "your PIN is 0000".
+static CODE_AFTER_KEYWORD: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)(\b(?:code|otp|passcode|pin|verification|one-time password)\b[^\d\n]{0,20})\d{4,8}\b",
+    )
+    .expect("code pattern is valid")
+});
+/// "482913 is your verification code".
+static CODE_BEFORE_KEYWORD: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b\d{4,8}(\s+is\s+your\b)").expect("code pattern is valid"));
+
+const PHONE_MIN_DIGITS: usize = 9;
+const PHONE_MAX_DIGITS: usize = 15;
+
+/// `text` with addresses, phone numbers, and codes masked. Borrows when
+/// nothing needed masking.
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let text = replace(Cow::Borrowed(text), &CODE_AFTER_KEYWORD, |caps| {
+        format!("{}[code]", &caps[1])
+    });
+    let text = replace(text, &CODE_BEFORE_KEYWORD, |caps| {
+        format!("[code]{}", &caps[1])
+    });
+    let text = replace(text, &EMAIL, |caps| format!("{}***@{}", &caps[1], &caps[2]));
+    replace(text, &PHONE, |caps| {
+        let digits = caps[0].chars().filter(char::is_ascii_digit).count();
+        if (PHONE_MIN_DIGITS..=PHONE_MAX_DIGITS).contains(&digits) && !looks_like_date(&caps[0]) {
+            "[phone]".to_string()
+        } else {
+            caps[0].to_string()
+        }
+    })
+}
+
+fn replace<'a>(
+    text: Cow<'a, str>,
+    pattern: &Regex,
+    mask: impl Fn(&Captures<'_>) -> String,
+) -> Cow<'a, str> {
+    let replaced = match pattern.replace_all(&text, |caps: &Captures<'_>| mask(caps)) {
+        Cow::Owned(replaced) if replaced != *text => replaced,
+        _ => return text,
+    };
+    Cow::Owned(replaced)
+}
+
+/// `2024-10-16 12 30`-style runs are timestamps, not phone numbers.
+fn looks_like_date(candidate: &str) -> bool {
+    let mut groups = candidate.split(|c: char| !c.is_ascii_digit());
+    groups
+        .next()
+        .is_some_and(|year| year.len() == 4 && (year.starts_with("19") || year.starts_with("20")))
+}
+
+/// Wraps a log writer factory so every line written through it is
+/// [`redact`]ed first.
+pub struct Redacted<M>(pub M);
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for Redacted<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter(self.0.make_writer())
+    }
+}
+
+/// Redacts each write before passing it on. The formatter writes one whole
+/// event at a time, so a pattern is never split across writes.
+pub struct RedactingWriter<W>(W);
+
+impl<W: io::Write> io::Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.0.write_all(redact(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn masks_email_addresses() {
+        assert_eq!(
+            redact("login failed for Jane.Doe+mail@Example.co.uk, retrying"),
+            "login failed for J***@Example.co.uk, retrying"
+        );
+        assert_eq!(redact("a@b.io and c@d.io"), "a***@b.io and c***@d.io");
+    }
+
+    #[test]
+    fn masks_phone_numbers() {
+        assert_eq!(redact("call +1 (555) 123-4567 today"), "call [phone] today");
+        assert_eq!(redact("mobile 07700 900 123"), "mobile [phone]");
+        assert_eq!(redact("+44 20 7946 0958"), "[phone]");
+    }
+
+    #[test]
+    fn masks_one_time_codes() {
+        assert_eq!(redact("Your code: 482913"), "Your code: [code]");
+        assert_eq!(redact("OTP is 0042"), "OTP is [code]");
+        assert_eq!(
+            redact("482913 is your verification code"),
+            "[code] is your verification code"
+        );
+    }
+
+    #[test]
+    fn leaves_diagnostic_numbers_alone() {
+        for text in [
+            "fetched 250 messages in 1834 ms",
+            "uid=48291 uid_validity=1700000000",
+            "connecting to 192.168.1.20:993",
+            "sent at 2024-10-16 12:30:05",
+            "batch 3/12",
+        ] {
+            assert!(matches!(redact(text), Cow::Borrowed(_)), "{text}");
+        }
+    }
+
+    #[test]
+    fn writer_redacts_each_line() {
+        let mut output = Vec::new();
+        let mut writer = RedactingWriter(&mut output);
+        writer
+            .write_all(b"INFO connected account=me@example.com\n")
+            .unwrap();
+        assert_eq!(output, b"INFO connected account=m***@example.com\n");
+    }
+}
@@ -0,0 +1,233 @@
+//! Per-contact interaction statistics for CRM-style contact views.
+//!
+//! Mail from the contact comes from the cache and mail to them from the
+//! Sent folder headers kept for [`crate::send_insights`]. Within a thread
+//! (grouped by subject), a message answered by one from the other side
+//! gives one reply latency for whoever answered.
+
+use crate::send_insights::SentMessage;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+const RECENT_WINDOW_DAYS: i64 = 30;
+const TREND_THRESHOLD: f64 = 0.25;
+
+/// One cached message from the contact, as loaded by storage.
+#[derive(Debug, Clone)]
+pub struct ContactMessage {
+    pub date: Option<String>,
+    pub subject: String,
+    pub flags: Option<String>,
+    pub sentiment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RelationshipStats {
+    pub contact_email: String,
+    pub message_count: usize,
+    pub thread_count: usize,
+    pub answered_count: usize,
+    pub first_contact_at: Option<i64>,
+    pub last_contact_at: Option<i64>,
+    /// Median time the user took to answer the contact.
+    pub user_reply_secs: Option<i64>,
+    /// Median time the contact took to answer the user.
+    pub contact_reply_secs: Option<i64>,
+    pub average_sentiment: Option<f64>,
+    pub recent_sentiment: Option<f64>,
+    pub sentiment_trend: String,
+    pub computed_at: i64,
+}
+
+/// Who wrote a message in a thread with the contact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    FromContact,
+    FromUser,
+}
+
+/// Stats for `contact_email` from the messages they sent and the `sent`
+/// headers of mail the user wrote to them.
+pub fn summarize(
+    contact_email: &str,
+    messages: &[ContactMessage],
+    sent: &[SentMessage],
+) -> RelationshipStats {
+    let now = Utc::now();
+    let mut threads: HashMap<String, Vec<(i64, Direction)>> = HashMap::new();
+    let mut answered_count = 0usize;
+    let mut sentiments = Vec::new();
+    let mut recent_sentiments = Vec::new();
+    let mut first_contact_at: Option<i64> = None;
+    let mut last_contact_at: Option<i64> = None;
+
+    for message in messages {
+        let answered = message
+            .flags
+            .as_deref()
+            .map(|flags| flags.split_whitespace().any(|flag| flag == "answered"))
+            .unwrap_or(false);
+        if answered {
+            answered_count += 1;
+        }
+
+        let timestamp = message.date.as_deref().and_then(parse_timestamp);
+        if let Some(ts) = timestamp {
+            first_contact_at = Some(first_contact_at.map_or(ts, |current| current.min(ts)));
+            last_contact_at = Some(last_contact_at.map_or(ts, |current| current.max(ts)));
+            threads
+                .entry(normalize_subject(&message.subject))
+                .or_default()
+                .push((ts, Direction::FromContact));
+        }
+
+        if let Some(score) = message.sentiment.as_deref().and_then(sentiment_score) {
+            sentiments.push(score);
+            let recent = timestamp
+                .map(|ts| now.timestamp() - ts <= RECENT_WINDOW_DAYS * 86_400)
+                .unwrap_or(false);
+            if recent {
+                recent_sentiments.push(score);
+            }
+        }
+    }
+
+    for message in sent {
+        threads
+            .entry(normalize_subject(&message.subject))
+            .or_default()
+            .push((message.sent_at, Direction::FromUser));
+    }
+
+    // The latest message from one side before the other side writes is the
+    // one being answered.
+    let mut user_replies = Vec::new();
+    let mut contact_replies = Vec::new();
+    for entries in threads.values_mut() {
+        entries.sort_by_key(|(ts, _)| *ts);
+        for pair in entries.windows(2) {
+            let (earlier, from) = pair[0];
+            let (later, to) = pair[1];
+            if from == to || later <= earlier {
+                continue;
+            }
+            match to {
+                Direction::FromUser => user_replies.push(later - earlier),
+                Direction::FromContact => contact_replies.push(later - earlier),
+            }
+        }
+    }
+
+    let average_sentiment = mean(&sentiments);
+    let recent_sentiment = mean(&recent_sentiments);
+    let sentiment_trend = match (average_sentiment, recent_sentiment) {
+        (Some(overall), Some(recent)) if recent - overall >= TREND_THRESHOLD => "improving",
+        (Some(overall), Some(recent)) if overall - recent >= TREND_THRESHOLD => "declining",
+        (Some(_), Some(_)) => "steady",
+        _ => "unknown",
+    };
+
+    RelationshipStats {
+        contact_email: contact_email.to_string(),
+        message_count: messages.len(),
+        thread_count: threads.len(),
+        answered_count,
+        first_contact_at,
+        last_contact_at,
+        user_reply_secs: median(&mut user_replies),
+        contact_reply_secs: median(&mut contact_replies),
+        average_sentiment,
+        recent_sentiment,
+        sentiment_trend: sentiment_trend.to_string(),
+        computed_at: now.timestamp(),
+    }
+}
+
+fn parse_timestamp(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc2822(value)
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .ok()
+        .map(|date| date.timestamp())
+}
+
+/// Strips reply/forward prefixes so replies group with the original thread.
+pub fn normalize_subject(subject: &str) -> String {
+    let mut current = subject.trim();
+    loop {
+        let lowered = current.to_ascii_lowercase();
+        let stripped = ["re:", "fw:", "fwd:", "aw:", "sv:"]
+            .iter()
+            .find(|prefix| lowered.starts_with(*prefix))
+            .map(|prefix| current[prefix.len()..].trim_start());
+        match stripped {
+            Some(rest) => current = rest,
+            None => break,
+        }
+    }
+    current.to_lowercase()
+}
+
+fn sentiment_score(value: &str) -> Option<f64> {
+    match value {
+        "positive" => Some(1.0),
+        "neutral" => Some(0.0),
+        "negative" => Some(-1.0),
+        _ => None,
+    }
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
+
+fn median(values: &mut [i64]) -> Option<i64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    Some(values[values.len() / 2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn received(date: &str, subject: &str) -> ContactMessage {
+        ContactMessage {
+            date: Some(date.to_string()),
+            subject: subject.to_string(),
+            flags: Some("seen answered".to_string()),
+            sentiment: None,
+        }
+    }
+
+    fn sent(sent_at: &str, subject: &str) -> SentMessage {
+        SentMessage {
+            recipient: "ann@example.org".to_string(),
+            subject: subject.to_string(),
+            sent_at: parse_timestamp(sent_at).unwrap(),
+        }
+    }
+
+    #[test]
+    fn reply_latency_is_measured_each_way() {
+        let messages = [
+            received("Mon, 4 Mar 2024 09:00:00 +0000", "Lunch?"),
+            received("Mon, 4 Mar 2024 15:00:00 +0000", "Re: Lunch?"),
+            received("Tue, 5 Mar 2024 08:00:00 +0000", "Invoice"),
+        ];
+        // The user answers after two hours; the contact answers that four
+        // hours later.
+        let sent = [sent("Mon, 4 Mar 2024 11:00:00 +0000", "Re: Lunch?")];
+
+        let stats = summarize("ann@example.org", &messages, &sent);
+        assert_eq!(stats.thread_count, 2);
+        assert_eq!(stats.user_reply_secs, Some(2 * 3_600));
+        assert_eq!(stats.contact_reply_secs, Some(4 * 3_600));
+    }
+}
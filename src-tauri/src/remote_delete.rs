@@ -1,11 +1,14 @@
+use crate::events::{
+    self, RemoteDeleteMetrics, RemoteDeleteMetricsHistoryEntry, RemoteDeleteMetricsSnapshot,
+    RemoteDeleteQueued, RemoteDeleteStatus, RemoteDeleteUpdate,
+};
 use crate::models::Credentials;
 use crate::providers::{self, ProviderError};
-use crate::storage::Storage;
+use crate::storage::{PendingRemoteDelete, Storage};
 use chrono::Utc;
-use serde::Serialize;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use tauri::{AppHandle, Manager};
+use tauri::AppHandle;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
@@ -20,9 +23,6 @@ const BACKOFF_BASE_SECS: u64 = 1;
 const BACKOFF_MAX_SECS: u64 = 120;
 const SINGLE_DELETE_DELAY_MS: u64 = 200;
 const RECONCILE_INTERVAL_SECS: u64 = 45;
-const REMOTE_DELETE_EVENT: &str = "remote-delete-status";
-const REMOTE_DELETE_ENQUEUED_EVENT: &str = "remote-delete-queued";
-const REMOTE_DELETE_METRICS_EVENT: &str = "remote-delete-metrics";
 const METRICS_HISTORY_LIMIT: usize = 360;
 const METRIC_WINDOW_SECS: i64 = 60;
 
@@ -60,30 +60,42 @@ struct RemoteDeleteInner {
 #[derive(Clone)]
 struct DeleteJob {
     credentials: Credentials,
-    uid: String,
+    target: PendingRemoteDelete,
+}
+
+impl DeleteJob {
+    /// Whether both jobs delete from the same folder under the same
+    /// UIDVALIDITY, and so can share one batch.
+    fn same_folder(&self, other: &DeleteJob) -> bool {
+        self.target.folder == other.target.folder
+            && self.target.uid_validity == other.target.uid_validity
+    }
 }
 
 impl RemoteDeleteInner {
-    async fn register_pending(&self, account_email: &str, uids: &[String]) -> Vec<String> {
+    /// Marks `targets` as queued by message key; returns those that were
+    /// not already.
+    async fn register_pending(
+        &self,
+        account_email: &str,
+        targets: Vec<PendingRemoteDelete>,
+    ) -> Vec<PendingRemoteDelete> {
         let mut pending = self.pending.lock().await;
         let set = pending
             .entry(account_email.to_string())
             .or_insert_with(HashSet::new);
 
-        let mut newly_added = Vec::new();
-        for uid in uids {
-            if set.insert(uid.clone()) {
-                newly_added.push(uid.clone());
-            }
-        }
-        newly_added
+        targets
+            .into_iter()
+            .filter(|target| set.insert(target.message_key.clone()))
+            .collect()
     }
 
-    async fn clear_pending_many(&self, account_email: &str, uids: &[String]) {
+    async fn clear_pending_many(&self, account_email: &str, message_keys: &[String]) {
         let mut pending = self.pending.lock().await;
         if let Some(set) = pending.get_mut(account_email) {
-            for uid in uids {
-                set.remove(uid);
+            for key in message_keys {
+                set.remove(key);
             }
             if set.is_empty() {
                 pending.remove(account_email);
@@ -206,18 +218,15 @@ impl RemoteDeleteInner {
             .collect::<Vec<_>>();
         drop(metrics_map);
 
-        let response = RemoteDeleteMetricsResponse {
+        let response = RemoteDeleteMetrics {
             account_email: account_email.to_string(),
             latest: snapshot,
             history: history_export,
         };
-
-        if let Err(err) = self.app.emit_all(REMOTE_DELETE_METRICS_EVENT, response) {
-            warn!(account = %account_email, ?err, "failed to emit remote delete metrics event");
-        }
+        events::emit(&self.app, &response);
     }
 
-    async fn metrics_response(&self, account_email: &str) -> RemoteDeleteMetricsResponse {
+    async fn metrics_response(&self, account_email: &str) -> RemoteDeleteMetrics {
         let (latest, history) = {
             let metrics_map = self.metrics.lock().await;
             if let Some(state) = metrics_map.get(account_email) {
@@ -241,7 +250,7 @@ impl RemoteDeleteInner {
             }
         };
 
-        RemoteDeleteMetricsResponse {
+        RemoteDeleteMetrics {
             account_email: account_email.to_string(),
             latest,
             history,
@@ -264,25 +273,6 @@ impl RemoteDeleteInner {
     }
 }
 
-#[derive(Serialize, Clone)]
-struct RemoteDeleteEventPayload {
-    account_email: String,
-    updates: Vec<RemoteDeleteUpdate>,
-}
-
-#[derive(Serialize, Clone)]
-struct RemoteDeleteUpdate {
-    uid: String,
-    remote_deleted_at: Option<i64>,
-    remote_error: Option<String>,
-}
-
-#[derive(Serialize, Clone)]
-struct RemoteDeleteQueuedPayload {
-    account_email: String,
-    uids: Vec<String>,
-}
-
 #[derive(Clone)]
 struct MetricsEntry {
     timestamp: i64,
@@ -297,35 +287,6 @@ struct MetricsState {
     last: Option<RemoteDeleteMetricsSnapshot>,
 }
 
-#[derive(Serialize, Clone)]
-pub struct RemoteDeleteMetricsSnapshot {
-    pub account_email: String,
-    pub timestamp: i64,
-    pub mode: String,
-    pub batch_size: usize,
-    pub processed: usize,
-    pub failed: usize,
-    pub pending: usize,
-    pub total_pending: usize,
-    pub rate_per_minute: f64,
-    pub override_mode: String,
-}
-
-#[derive(Serialize, Clone)]
-pub struct RemoteDeleteMetricsHistoryEntry {
-    pub timestamp: i64,
-    pub processed: usize,
-    pub mode: String,
-    pub pending: usize,
-}
-
-#[derive(Serialize, Clone)]
-pub struct RemoteDeleteMetricsResponse {
-    pub account_email: String,
-    pub latest: RemoteDeleteMetricsSnapshot,
-    pub history: Vec<RemoteDeleteMetricsHistoryEntry>,
-}
-
 impl RemoteDeleteManager {
     pub fn new(storage: Storage, app: AppHandle) -> Self {
         let inner = RemoteDeleteInner {
@@ -347,11 +308,11 @@ impl RemoteDeleteManager {
         &self,
         account_email: &str,
         credentials: Credentials,
-        uid: String,
+        target: PendingRemoteDelete,
     ) -> Result<(), String> {
         let normalized = account_email.trim().to_lowercase();
         let new_items = self
-            .enqueue_many_internal(&normalized, &credentials, vec![uid])
+            .enqueue_many_internal(&normalized, &credentials, vec![target])
             .await?;
 
         if !new_items.is_empty() {
@@ -376,27 +337,27 @@ impl RemoteDeleteManager {
             .await
             .map_err(|err| err.to_string())?;
 
-        let mut retry_uids = Vec::new();
-        for (uid, remote_error) in pending {
-            if should_retry_remote_error(&remote_error) {
-                if remote_error.is_some() {
+        let mut retry = Vec::new();
+        for target in pending {
+            if should_retry_remote_error(&target.remote_error) {
+                if target.remote_error.is_some() {
                     if let Err(err) = self
                         .inner
                         .storage
-                        .mark_deleted_remote(&account_email, &uid, None, None)
+                        .mark_deleted_remote(&target.message_key, None, None)
                         .await
                     {
-                        warn!(account = %account_email, %uid, ?err, "failed to clear remote delete error before retry");
+                        warn!(account = %account_email, uid = %target.uid, ?err, "failed to clear remote delete error before retry");
                         continue;
                     }
                 }
-                retry_uids.push(uid);
+                retry.push(target);
             }
         }
 
-        if !retry_uids.is_empty() {
+        if !retry.is_empty() {
             let new_items = self
-                .enqueue_many_internal(&account_email, &credentials, retry_uids)
+                .enqueue_many_internal(&account_email, &credentials, retry)
                 .await?;
             if !new_items.is_empty() {
                 self.emit_enqueued(&account_email, &new_items);
@@ -407,7 +368,7 @@ impl RemoteDeleteManager {
         Ok(())
     }
 
-    pub async fn get_metrics(&self, account_email: &str) -> RemoteDeleteMetricsResponse {
+    pub async fn get_metrics(&self, account_email: &str) -> RemoteDeleteMetrics {
         let normalized = account_email.trim().to_lowercase();
         let mut response = self.inner.metrics_response(&normalized).await;
         response.latest.pending = self.inner.pending_count(&normalized).await;
@@ -445,14 +406,9 @@ impl RemoteDeleteManager {
         &self,
         account_email: &str,
         credentials: &Credentials,
-        mut uids: Vec<String>,
-    ) -> Result<Vec<String>, String> {
-        if uids.is_empty() {
-            return Ok(Vec::new());
-        }
-
-        uids.retain(|uid| !uid.trim().is_empty());
-        if uids.is_empty() {
+        targets: Vec<PendingRemoteDelete>,
+    ) -> Result<Vec<PendingRemoteDelete>, String> {
+        if targets.is_empty() {
             return Ok(Vec::new());
         }
 
@@ -461,19 +417,19 @@ impl RemoteDeleteManager {
             .await;
 
         let sender = self.ensure_worker(account_email).await;
-        let new_items = self.inner.register_pending(account_email, &uids).await;
+        let new_items = self.inner.register_pending(account_email, targets).await;
 
         if new_items.is_empty() {
             return Ok(Vec::new());
         }
 
-        for uid in &new_items {
+        for target in &new_items {
             if let Err(err) = sender.send(DeleteJob {
                 credentials: credentials.clone(),
-                uid: uid.clone(),
+                target: target.clone(),
             }) {
                 self.inner
-                    .clear_pending_many(account_email, &[uid.clone()])
+                    .clear_pending_many(account_email, std::slice::from_ref(&target.message_key))
                     .await;
                 return Err(format!("queue closed: {err}"));
             }
@@ -482,20 +438,20 @@ impl RemoteDeleteManager {
         Ok(new_items)
     }
 
-    fn emit_enqueued(&self, account_email: &str, uids: &[String]) {
-        if uids.is_empty() {
+    fn emit_enqueued(&self, account_email: &str, targets: &[PendingRemoteDelete]) {
+        if targets.is_empty() {
             return;
         }
 
-        if let Err(err) = self.inner.app.emit_all(
-            REMOTE_DELETE_ENQUEUED_EVENT,
-            RemoteDeleteQueuedPayload {
-                account_email: account_email.to_string(),
-                uids: uids.to_vec(),
-            },
-        ) {
-            warn!(account = %account_email, ?err, "failed to emit remote delete queue event");
-        }
+        let event = RemoteDeleteQueued {
+            account_email: account_email.to_string(),
+            uids: targets.iter().map(|target| target.uid).collect(),
+            message_keys: targets
+                .iter()
+                .map(|target| target.message_key.clone())
+                .collect(),
+        };
+        events::emit(&self.inner.app, &event);
     }
 
     async fn ensure_reconciler(&self, account_email: &str) {
@@ -557,30 +513,30 @@ impl RemoteDeleteManager {
                 continue;
             }
 
-            let mut retry_uids = Vec::new();
-            for (uid, remote_error) in pending {
-                if should_retry_remote_error(&remote_error) {
-                    if remote_error.is_some() {
+            let mut retry = Vec::new();
+            for target in pending {
+                if should_retry_remote_error(&target.remote_error) {
+                    if target.remote_error.is_some() {
                         if let Err(err) = manager
                             .inner
                             .storage
-                            .mark_deleted_remote(&account_email, &uid, None, None)
+                            .mark_deleted_remote(&target.message_key, None, None)
                             .await
                         {
-                            warn!(account = %account_email, %uid, ?err, "failed to clear remote delete error before reconciliation");
+                            warn!(account = %account_email, uid = %target.uid, ?err, "failed to clear remote delete error before reconciliation");
                             continue;
                         }
                     }
-                    retry_uids.push(uid);
+                    retry.push(target);
                 }
             }
 
-            if retry_uids.is_empty() {
+            if retry.is_empty() {
                 continue;
             }
 
             match manager
-                .enqueue_many_internal(&account_email, &credentials, retry_uids)
+                .enqueue_many_internal(&account_email, &credentials, retry)
                 .await
             {
                 Ok(new_items) => {
@@ -620,7 +576,15 @@ async fn run_account_worker(
 
         while batch.len() < current_batch_size {
             match timeout(Duration::from_millis(BATCH_DEBOUNCE_MS), rx.recv()).await {
-                Ok(Some(next_job)) => batch.push(next_job),
+                Ok(Some(next_job)) if next_job.same_folder(&batch[0]) => batch.push(next_job),
+                Ok(Some(next_job)) => {
+                    // Another folder, or an older UIDVALIDITY, gets a batch
+                    // of its own.
+                    if let Err(send_err) = sender.send(next_job) {
+                        error!(account = %account_email, ?send_err, "failed to requeue delete job for another folder");
+                    }
+                    break;
+                }
                 Ok(None) => break,
                 Err(_) => break,
             }
@@ -630,7 +594,13 @@ async fn run_account_worker(
             .last()
             .map(|job| job.credentials.clone())
             .unwrap_or_else(|| batch[0].credentials.clone());
-        let uids: Vec<String> = batch.iter().map(|job| job.uid.clone()).collect();
+        let folder = batch[0].target.folder.clone();
+        let uid_validity = batch[0].target.uid_validity;
+        let uids: Vec<u32> = batch.iter().map(|job| job.target.uid).collect();
+        let message_keys: Vec<String> = batch
+            .iter()
+            .map(|job| job.target.message_key.clone())
+            .collect();
         let batch_size_executed = batch.len();
         let mut used_single_fallback = false;
         let mut encountered_rate_limit = false;
@@ -642,7 +612,7 @@ async fn run_account_worker(
             "processing remote delete batch"
         );
 
-        let batch_result = execute_batch(&credentials, &uids).await;
+        let batch_result = execute_batch(&credentials, &folder, &uids, uid_validity).await;
 
         let mut updates: Vec<RemoteDeleteUpdate> = Vec::with_capacity(uids.len());
 
@@ -653,14 +623,16 @@ async fn run_account_worker(
                 current_batch_size = (current_batch_size + BATCH_GROWTH_STEP).min(MAX_BATCH_SIZE);
 
                 let timestamp = Utc::now().timestamp();
-                for uid in &uids {
+                for job in &batch {
+                    let (uid, message_key) = (job.target.uid, &job.target.message_key);
                     match inner
                         .storage
-                        .mark_deleted_remote(&account_email, uid, Some(timestamp), None)
+                        .mark_deleted_remote(message_key, Some(timestamp), None)
                         .await
                     {
                         Ok(_) => updates.push(RemoteDeleteUpdate {
-                            uid: uid.clone(),
+                            uid,
+                            message_key: message_key.clone(),
                             remote_deleted_at: Some(timestamp),
                             remote_error: None,
                         }),
@@ -733,7 +705,14 @@ async fn run_account_worker(
                         cooldown_until = None;
                     }
 
-                    let result = providers::delete_message(&job.credentials, &job.uid).await;
+                    let target = &job.target;
+                    let result = providers::delete_message(
+                        &job.credentials,
+                        &target.folder,
+                        target.uid,
+                        target.uid_validity,
+                    )
+                    .await;
 
                     if rate_limited {
                         sleep(Duration::from_millis(SINGLE_DELETE_DELAY_MS)).await;
@@ -744,14 +723,15 @@ async fn run_account_worker(
                             let timestamp = Utc::now().timestamp();
                             if let Err(err) = inner
                                 .storage
-                                .mark_deleted_remote(&account_email, &job.uid, Some(timestamp), None)
+                                .mark_deleted_remote(&target.message_key, Some(timestamp), None)
                                 .await
                             {
-                                error!(account = %account_email, uid = %job.uid, ?err, "failed to mark remote delete success (fallback)");
+                                error!(account = %account_email, uid = %target.uid, ?err, "failed to mark remote delete success (fallback)");
                                 continue;
                             }
                             updates.push(RemoteDeleteUpdate {
-                                uid: job.uid.clone(),
+                                uid: target.uid,
+                                message_key: target.message_key.clone(),
                                 remote_deleted_at: Some(timestamp),
                                 remote_error: None,
                             });
@@ -760,14 +740,19 @@ async fn run_account_worker(
                             let message = provider_error_to_message(single_err);
                             if let Err(storage_err) = inner
                                 .storage
-                                .mark_deleted_remote(&account_email, &job.uid, None, Some(message.clone()))
+                                .mark_deleted_remote(
+                                    &target.message_key,
+                                    None,
+                                    Some(message.clone()),
+                                )
                                 .await
                             {
-                                error!(account = %account_email, uid = %job.uid, ?storage_err, "failed to mark remote delete error");
+                                error!(account = %account_email, uid = %target.uid, ?storage_err, "failed to mark remote delete error");
                                 continue;
                             }
                             updates.push(RemoteDeleteUpdate {
-                                uid: job.uid.clone(),
+                                uid: target.uid,
+                                message_key: target.message_key.clone(),
                                 remote_deleted_at: None,
                                 remote_error: Some(message),
                             });
@@ -790,18 +775,16 @@ async fn run_account_worker(
         let failed_count = updates.len().saturating_sub(success_count);
 
         if !updates.is_empty() {
-            if let Err(err) = inner.app.emit_all(
-                REMOTE_DELETE_EVENT,
-                RemoteDeleteEventPayload {
-                    account_email: account_email.clone(),
-                    updates,
-                },
-            ) {
-                error!(account = %account_email, ?err, "failed to emit remote delete event");
-            }
+            let event = RemoteDeleteStatus {
+                account_email: account_email.clone(),
+                updates,
+            };
+            events::emit(&inner.app, &event);
         }
 
-        inner.clear_pending_many(&account_email, &uids).await;
+        inner
+            .clear_pending_many(&account_email, &message_keys)
+            .await;
         let pending = inner.pending_count(&account_email).await;
 
         let (mode_label, metrics_batch_size) = if used_single_fallback {
@@ -842,12 +825,17 @@ async fn run_account_worker(
     }
 }
 
-async fn execute_batch(credentials: &Credentials, uids: &[String]) -> Result<(), ProviderError> {
+async fn execute_batch(
+    credentials: &Credentials,
+    folder: &str,
+    uids: &[u32],
+    uid_validity: Option<u32>,
+) -> Result<(), ProviderError> {
     if uids.is_empty() {
         return Ok(());
     }
 
-    match providers::delete_messages(credentials, uids).await {
+    match providers::delete_messages(credentials, folder, uids, uid_validity).await {
         Ok(_) => Ok(()),
         Err(err) => {
             warn!(
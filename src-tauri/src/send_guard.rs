@@ -0,0 +1,491 @@
+//! Recipient checks before a message goes out. Replying to all of a large
+//! conversation, or writing to a list address that fans out to many
+//! people, needs the user's confirmation; the review also names the
+//! recipient domains outside the account's own and which addresses would
+//! be better off in `Bcc`. On top of that the user's [`SendPolicy`] can warn
+//! about or block mail to domains off an allow list, mail mixing internal
+//! and external recipients, and oversized attachments.
+
+use crate::brand::organizational_domain;
+use crate::subject_lines::SubjectIssue;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// App setting holding the [`SendPolicy`] as JSON.
+pub const SETTING_KEY: &str = "send_policy";
+/// Visible recipients from this many on count as a large list.
+pub const LARGE_RECIPIENT_COUNT: usize = 10;
+
+/// Local parts that usually name a distribution list.
+const LIST_LOCAL_PARTS: &[&str] = &[
+    "all",
+    "all-staff",
+    "allstaff",
+    "announce",
+    "announcements",
+    "company",
+    "employees",
+    "everyone",
+    "list",
+    "members",
+    "staff",
+    "team",
+];
+const LIST_LOCAL_SUFFIXES: &[&str] = &["-all", "-announce", "-l", "-list", "-team"];
+/// Domains that only host lists, and subdomain prefixes lists live under.
+const LIST_DOMAINS: &[&str] = &["googlegroups.com", "groups.io"];
+const LIST_SUBDOMAINS: &[&str] = &["groups.", "list.", "lists."];
+
+/// An outgoing message's recipients as typed; entries may be bare
+/// addresses or `Name <address>`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Recipients {
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+}
+
+/// What a send policy rule does when it matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyAction {
+    #[default]
+    Off,
+    /// Ask before sending.
+    Warn,
+    /// Refuse to send.
+    Block,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SendPolicy {
+    /// Domains mail may go to besides the account's own. Subdomains match.
+    pub allowed_domains: Vec<String>,
+    /// For recipients outside the account's domain and `allowed_domains`.
+    pub outside_allow_list: PolicyAction,
+    /// For messages to both the account's domain and others.
+    pub mixed_recipients: PolicyAction,
+    pub max_attachment_bytes: Option<u64>,
+    /// For attachments over `max_attachment_bytes`.
+    pub oversized_attachments: PolicyAction,
+}
+
+impl SendPolicy {
+    /// Lowercases the allowed domains, dropping `@` and blanks; rejects a
+    /// size rule without a size.
+    pub fn normalized(mut self) -> Result<Self, String> {
+        self.allowed_domains = self
+            .allowed_domains
+            .iter()
+            .map(|domain| {
+                domain
+                    .trim()
+                    .trim_start_matches('@')
+                    .trim_end_matches('.')
+                    .to_lowercase()
+            })
+            .filter(|domain| !domain.is_empty())
+            .collect();
+        self.allowed_domains.sort();
+        self.allowed_domains.dedup();
+        if let Some(invalid) = self
+            .allowed_domains
+            .iter()
+            .find(|domain| !domain.contains('.') || domain.contains(char::is_whitespace))
+        {
+            return Err(format!("'{invalid}' is not a domain"));
+        }
+        if self.oversized_attachments != PolicyAction::Off
+            && self.max_attachment_bytes.unwrap_or(0) == 0
+        {
+            return Err("Set a size limit for attachments".into());
+        }
+        Ok(self)
+    }
+}
+
+/// An attachment of the message under review.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutgoingAttachment {
+    pub filename: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyRule {
+    OutsideAllowList,
+    MixedRecipients,
+    OversizedAttachment,
+}
+
+/// A send policy rule the message breaks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PolicyResult {
+    pub rule: PolicyRule,
+    /// `warn` or `block`.
+    pub action: PolicyAction,
+    pub message: String,
+    /// The recipients or attachment names behind the result.
+    pub subjects: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecipientWarning {
+    /// A reply to everyone on a large conversation.
+    LargeReplyAll { count: usize },
+    /// A new message to many visible recipients, who all see each other.
+    ManyVisibleRecipients { count: usize },
+    /// Addresses that look like distribution lists.
+    ListAddresses { addresses: Vec<String> },
+    /// Addresses given more than once; the message goes to each once.
+    Duplicates { addresses: Vec<String> },
+}
+
+impl RecipientWarning {
+    fn needs_confirmation(&self) -> bool {
+        !matches!(self, RecipientWarning::Duplicates { .. })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecipientReview {
+    /// Distinct recipient addresses across `To`, `Cc`, and `Bcc`, lowercase.
+    pub recipients: Vec<String>,
+    pub recipient_count: usize,
+    /// Recipient domains outside the account's own, sorted.
+    pub external_domains: Vec<String>,
+    /// Visible recipients that could move to `Bcc`.
+    pub suggest_bcc: Vec<String>,
+    pub warnings: Vec<RecipientWarning>,
+    /// Send policy rules the message breaks; see [`apply_policy`].
+    pub policy: Vec<PolicyResult>,
+    /// Set when the subject checked with the recipients is empty or a
+    /// placeholder; see [`crate::subject_lines::check`].
+    pub subject_issue: Option<SubjectIssue>,
+    /// The message must not go out until the user confirms it.
+    pub requires_confirmation: bool,
+    /// A send policy forbids the message.
+    pub blocked: bool,
+}
+
+/// Reviews `recipients` for a message sent from `account_email`. Fails on
+/// an entry that is not an address, or when there is no recipient at all.
+pub fn review(
+    account_email: &str,
+    recipients: &Recipients,
+    reply_all: bool,
+) -> Result<RecipientReview, String> {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    let mut visible = Vec::new();
+    let mut hidden = Vec::new();
+    for (entry, is_visible) in recipients
+        .to
+        .iter()
+        .chain(&recipients.cc)
+        .map(|entry| (entry, true))
+        .chain(recipients.bcc.iter().map(|entry| (entry, false)))
+    {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let address = address_of(entry)
+            .ok_or_else(|| format!("'{}' is not an email address", entry.trim()))?;
+        if !seen.insert(address.clone()) {
+            if !duplicates.contains(&address) {
+                duplicates.push(address);
+            }
+            continue;
+        }
+        if is_visible {
+            visible.push(address);
+        } else {
+            hidden.push(address);
+        }
+    }
+    if seen.is_empty() {
+        return Err("Provide at least one recipient".into());
+    }
+
+    let own_domain = account_domain(account_email);
+    let mut external_domains = seen
+        .iter()
+        .filter_map(|address| address.rsplit_once('@'))
+        .map(|(_, domain)| organizational_domain(domain))
+        .filter(|domain| *domain != own_domain)
+        .collect::<Vec<_>>();
+    external_domains.sort();
+    external_domains.dedup();
+
+    let lists = visible
+        .iter()
+        .chain(&hidden)
+        .filter(|address| is_list_address(address))
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut warnings = Vec::new();
+    let mut suggest_bcc = Vec::new();
+    let large = visible.len() >= LARGE_RECIPIENT_COUNT;
+    if large && reply_all {
+        warnings.push(RecipientWarning::LargeReplyAll { count: seen.len() });
+    } else if large {
+        warnings.push(RecipientWarning::ManyVisibleRecipients {
+            count: visible.len(),
+        });
+    }
+    if large {
+        // The first `To` is who the message is for (on a reply, the
+        // sender); everyone else can be told without being shown.
+        suggest_bcc.extend(visible.iter().skip(1).cloned());
+    }
+    if !lists.is_empty() {
+        for list in &lists {
+            if visible.contains(list) && !suggest_bcc.contains(list) {
+                suggest_bcc.push(list.clone());
+            }
+        }
+        warnings.push(RecipientWarning::ListAddresses { addresses: lists });
+    }
+    if !duplicates.is_empty() {
+        warnings.push(RecipientWarning::Duplicates {
+            addresses: duplicates,
+        });
+    }
+
+    Ok(RecipientReview {
+        recipient_count: seen.len(),
+        recipients: visible.into_iter().chain(hidden).collect(),
+        external_domains,
+        suggest_bcc,
+        requires_confirmation: warnings.iter().any(RecipientWarning::needs_confirmation),
+        warnings,
+        policy: Vec::new(),
+        subject_issue: None,
+        blocked: false,
+    })
+}
+
+/// Adds the rules of `policy` that the reviewed message breaks. Warnings
+/// need confirmation; a block cannot be confirmed away.
+pub fn apply_policy(
+    review: &mut RecipientReview,
+    policy: &SendPolicy,
+    account_email: &str,
+    attachments: &[OutgoingAttachment],
+) {
+    let own_domain = account_domain(account_email);
+    let (internal, external): (Vec<&String>, Vec<&String>) =
+        review.recipients.iter().partition(|address| {
+            address
+                .rsplit_once('@')
+                .is_some_and(|(_, domain)| organizational_domain(domain) == own_domain)
+        });
+    let outside = external
+        .iter()
+        .filter(|address| {
+            let domain = address.rsplit_once('@').map_or("", |(_, domain)| domain);
+            !policy
+                .allowed_domains
+                .iter()
+                .any(|allowed| domain == allowed || domain.ends_with(&format!(".{allowed}")))
+        })
+        .map(|address| address.to_string())
+        .collect::<Vec<_>>();
+
+    let mut results = Vec::new();
+    if policy.outside_allow_list != PolicyAction::Off && !outside.is_empty() {
+        results.push(PolicyResult {
+            rule: PolicyRule::OutsideAllowList,
+            action: policy.outside_allow_list,
+            message: format!("{} recipient(s) outside the allowed domains", outside.len()),
+            subjects: outside,
+        });
+    }
+    if policy.mixed_recipients != PolicyAction::Off && !internal.is_empty() && !external.is_empty()
+    {
+        results.push(PolicyResult {
+            rule: PolicyRule::MixedRecipients,
+            action: policy.mixed_recipients,
+            message: format!(
+                "Mixes {} internal and {} external recipient(s)",
+                internal.len(),
+                external.len()
+            ),
+            subjects: external.iter().map(|address| address.to_string()).collect(),
+        });
+    }
+    if let (PolicyAction::Warn | PolicyAction::Block, Some(limit)) =
+        (policy.oversized_attachments, policy.max_attachment_bytes)
+    {
+        let oversized = attachments
+            .iter()
+            .filter(|attachment| attachment.size > limit)
+            .map(|attachment| attachment.filename.clone())
+            .collect::<Vec<_>>();
+        if !oversized.is_empty() {
+            results.push(PolicyResult {
+                rule: PolicyRule::OversizedAttachment,
+                action: policy.oversized_attachments,
+                message: format!("{} attachment(s) over {limit} bytes", oversized.len()),
+                subjects: oversized,
+            });
+        }
+    }
+
+    review.blocked |= results
+        .iter()
+        .any(|result| result.action == PolicyAction::Block);
+    review.requires_confirmation |= results
+        .iter()
+        .any(|result| result.action == PolicyAction::Warn);
+    review.policy.extend(results);
+}
+
+fn account_domain(account_email: &str) -> String {
+    account_email
+        .rsplit_once('@')
+        .map(|(_, domain)| organizational_domain(&domain.to_lowercase()))
+        .unwrap_or_default()
+}
+
+/// Whether `address` looks like a distribution list rather than a person.
+pub fn is_list_address(address: &str) -> bool {
+    let Some((local, domain)) = address.rsplit_once('@') else {
+        return false;
+    };
+    LIST_LOCAL_PARTS.contains(&local)
+        || LIST_LOCAL_SUFFIXES
+            .iter()
+            .any(|suffix| local.len() > suffix.len() && local.ends_with(suffix))
+        || LIST_DOMAINS.contains(&domain)
+        || LIST_SUBDOMAINS
+            .iter()
+            .any(|prefix| domain.starts_with(prefix))
+}
+
+/// The lowercase address in a recipient entry, or `None` if it has none.
+fn address_of(entry: &str) -> Option<String> {
+    let entry = entry.trim();
+    let address = match (entry.rfind('<'), entry.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &entry[start + 1..end],
+        _ => entry,
+    };
+    let address = address.trim().to_lowercase();
+    let (local, domain) = address.split_once('@')?;
+    let valid = !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !domain.contains('@')
+        && !address.contains(|ch: char| ch.is_whitespace() || "<>,;".contains(ch));
+    valid.then_some(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipients(to: &[&str], cc: &[&str], bcc: &[&str]) -> Recipients {
+        let owned = |list: &[&str]| list.iter().map(|entry| entry.to_string()).collect();
+        Recipients {
+            to: owned(to),
+            cc: owned(cc),
+            bcc: owned(bcc),
+        }
+    }
+
+    #[test]
+    fn flags_large_reply_all_and_lists() {
+        let cc = (1..12)
+            .map(|n| format!("person{n}@partner.example"))
+            .collect::<Vec<_>>();
+        let cc = cc.iter().map(String::as_str).collect::<Vec<_>>();
+        let reply_all = review(
+            "me@corp.example",
+            &recipients(&["Boss <boss@corp.example>"], &cc, &[]),
+            true,
+        )
+        .unwrap();
+        assert!(reply_all.requires_confirmation);
+        assert_eq!(reply_all.recipient_count, 12);
+        assert_eq!(
+            reply_all.warnings,
+            [RecipientWarning::LargeReplyAll { count: 12 }]
+        );
+        assert_eq!(reply_all.external_domains, ["partner.example"]);
+        assert_eq!(reply_all.suggest_bcc.len(), 11);
+
+        let to_list = review(
+            "me@corp.example",
+            &recipients(
+                &["everyone@corp.example", "a@corp.example"],
+                &[],
+                &["A@corp.example"],
+            ),
+            false,
+        )
+        .unwrap();
+        assert!(to_list.requires_confirmation);
+        assert_eq!(to_list.suggest_bcc, ["everyone@corp.example"]);
+        assert!(to_list.external_domains.is_empty());
+        assert_eq!(to_list.warnings.len(), 2);
+    }
+
+    #[test]
+    fn small_messages_pass_and_bad_addresses_fail() {
+        let small = review(
+            "me@corp.example",
+            &recipients(&["a@x.example"], &[], &[]),
+            true,
+        );
+        let small = small.unwrap();
+        assert!(!small.requires_confirmation);
+        assert!(small.warnings.is_empty());
+        let invalid = recipients(&["not an address"], &[], &[]);
+        assert!(review("me@corp.example", &invalid, false).is_err());
+        assert!(review("me@corp.example", &Recipients::default(), false).is_err());
+    }
+
+    #[test]
+    fn applies_send_policy() {
+        let policy = SendPolicy {
+            allowed_domains: vec!["@Partner.example".into()],
+            outside_allow_list: PolicyAction::Block,
+            mixed_recipients: PolicyAction::Warn,
+            max_attachment_bytes: Some(1_000),
+            oversized_attachments: PolicyAction::Warn,
+        }
+        .normalized()
+        .unwrap();
+        let to = [
+            "a@corp.example",
+            "b@mail.partner.example",
+            "c@other.example",
+        ];
+        let mut checked = review("me@corp.example", &recipients(&to, &[], &[]), false).unwrap();
+        let attachments = [OutgoingAttachment {
+            filename: "big.zip".into(),
+            size: 5_000,
+        }];
+        apply_policy(&mut checked, &policy, "me@corp.example", &attachments);
+        assert!(checked.blocked);
+        assert!(checked.requires_confirmation);
+        let rules = checked
+            .policy
+            .iter()
+            .map(|result| (result.rule, result.subjects.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules,
+            [
+                (PolicyRule::OutsideAllowList, 1),
+                (PolicyRule::MixedRecipients, 2),
+                (PolicyRule::OversizedAttachment, 1),
+            ]
+        );
+    }
+}
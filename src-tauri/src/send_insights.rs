@@ -0,0 +1,223 @@
+//! Response rates for mail the user sent. Headers from the Sent folder are
+//! matched against cached INBOX mail: a sent message counts as answered when
+//! the recipient writes back in the same thread (grouped by subject, as in
+//! [`crate::relationships`]) before the reply window closes or the user
+//! writes to them again there.
+
+use crate::relationships::normalize_subject;
+use chrono::{Local, TimeZone, Timelike, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+const REPLY_WINDOW_DAYS: i64 = 14;
+/// Hours with fewer sends than this are not recommended.
+const MIN_HOUR_SAMPLES: usize = 5;
+const BEST_HOURS: usize = 3;
+
+/// One recipient of a message in the Sent folder.
+#[derive(Debug, Clone)]
+pub struct SentMessage {
+    pub recipient: String,
+    pub subject: String,
+    pub sent_at: i64,
+}
+
+/// A cached INBOX message from someone the user wrote to.
+#[derive(Debug, Clone)]
+pub struct ReceivedMessage {
+    pub sender: String,
+    pub subject: String,
+    pub received_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecipientInsight {
+    pub recipient: String,
+    pub sent: usize,
+    pub replied: usize,
+    pub response_rate: f64,
+    pub median_reply_secs: Option<i64>,
+    pub last_sent_at: i64,
+}
+
+/// Replies to mail sent during one local hour of the day.
+#[derive(Debug, Clone, Serialize)]
+pub struct HourInsight {
+    pub hour: u32,
+    pub sent: usize,
+    pub replied: usize,
+    pub response_rate: f64,
+    pub median_reply_secs: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SendInsights {
+    pub account_email: String,
+    pub sent: usize,
+    pub replied: usize,
+    pub response_rate: f64,
+    pub median_reply_secs: Option<i64>,
+    /// Most-written-to recipients first.
+    pub recipients: Vec<RecipientInsight>,
+    /// All 24 local hours, in order.
+    pub by_hour: Vec<HourInsight>,
+    /// Hours with the best response rate, given enough sends to judge.
+    pub best_hours: Vec<u32>,
+    pub computed_at: i64,
+}
+
+#[derive(Default)]
+struct Tally {
+    sent: usize,
+    latencies: Vec<i64>,
+    last_sent_at: i64,
+}
+
+impl Tally {
+    fn add(&mut self, sent_at: i64, latency: Option<i64>) {
+        self.sent += 1;
+        self.latencies.extend(latency);
+        self.last_sent_at = self.last_sent_at.max(sent_at);
+    }
+
+    fn rate(&self) -> f64 {
+        if self.sent == 0 {
+            0.0
+        } else {
+            self.latencies.len() as f64 / self.sent as f64
+        }
+    }
+}
+
+pub fn compute(
+    account_email: &str,
+    sent: &[SentMessage],
+    received: &[ReceivedMessage],
+) -> SendInsights {
+    let mut replies: HashMap<(String, String), Vec<i64>> = HashMap::new();
+    for message in received {
+        replies
+            .entry((
+                message.sender.to_lowercase(),
+                normalize_subject(&message.subject),
+            ))
+            .or_default()
+            .push(message.received_at);
+    }
+    for times in replies.values_mut() {
+        times.sort_unstable();
+    }
+
+    let mut threads: HashMap<(String, String), Vec<i64>> = HashMap::new();
+    for message in sent {
+        let recipient = message.recipient.to_lowercase();
+        if recipient == account_email {
+            continue;
+        }
+        threads
+            .entry((recipient, normalize_subject(&message.subject)))
+            .or_default()
+            .push(message.sent_at);
+    }
+
+    let mut overall = Tally::default();
+    let mut recipients: HashMap<String, Tally> = HashMap::new();
+    let mut hours: Vec<Tally> = (0..24).map(|_| Tally::default()).collect();
+    for (key, times) in threads.iter_mut() {
+        times.sort_unstable();
+        let answers = replies.get(key).map(Vec::as_slice).unwrap_or_default();
+        for (index, &sent_at) in times.iter().enumerate() {
+            let mut closes = sent_at + REPLY_WINDOW_DAYS * 86_400;
+            if let Some(&next) = times.get(index + 1) {
+                closes = closes.min(next);
+            }
+            let first = answers.partition_point(|&at| at <= sent_at);
+            let latency = answers
+                .get(first)
+                .filter(|&&at| at <= closes)
+                .map(|&at| at - sent_at);
+
+            overall.add(sent_at, latency);
+            recipients
+                .entry(key.0.clone())
+                .or_default()
+                .add(sent_at, latency);
+            if let Some(hour) = local_hour(sent_at) {
+                hours[hour as usize].add(sent_at, latency);
+            }
+        }
+    }
+
+    let by_hour = hours
+        .iter_mut()
+        .enumerate()
+        .map(|(hour, tally)| HourInsight {
+            hour: hour as u32,
+            sent: tally.sent,
+            replied: tally.latencies.len(),
+            response_rate: tally.rate(),
+            median_reply_secs: median(&mut tally.latencies),
+        })
+        .collect::<Vec<_>>();
+    let mut ranked = by_hour
+        .iter()
+        .filter(|hour| hour.sent >= MIN_HOUR_SAMPLES)
+        .collect::<Vec<_>>();
+    ranked.sort_by(|a, b| {
+        b.response_rate.total_cmp(&a.response_rate).then_with(|| {
+            a.median_reply_secs
+                .unwrap_or(i64::MAX)
+                .cmp(&b.median_reply_secs.unwrap_or(i64::MAX))
+        })
+    });
+    let best_hours = ranked
+        .into_iter()
+        .filter(|hour| hour.replied > 0)
+        .take(BEST_HOURS)
+        .map(|hour| hour.hour)
+        .collect();
+
+    let mut recipients = recipients
+        .into_iter()
+        .map(|(recipient, mut tally)| RecipientInsight {
+            recipient,
+            sent: tally.sent,
+            replied: tally.latencies.len(),
+            response_rate: tally.rate(),
+            median_reply_secs: median(&mut tally.latencies),
+            last_sent_at: tally.last_sent_at,
+        })
+        .collect::<Vec<_>>();
+    recipients.sort_by(|a, b| {
+        b.sent
+            .cmp(&a.sent)
+            .then_with(|| b.last_sent_at.cmp(&a.last_sent_at))
+    });
+
+    SendInsights {
+        account_email: account_email.to_string(),
+        sent: overall.sent,
+        replied: overall.latencies.len(),
+        response_rate: overall.rate(),
+        median_reply_secs: median(&mut overall.latencies),
+        recipients,
+        by_hour,
+        best_hours,
+        computed_at: Utc::now().timestamp(),
+    }
+}
+
+fn local_hour(timestamp: i64) -> Option<u32> {
+    Local
+        .timestamp_opt(timestamp, 0)
+        .single()
+        .map(|date| date.hour())
+}
+
+fn median(values: &mut [i64]) -> Option<i64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    Some(values[values.len() / 2])
+}
@@ -0,0 +1,145 @@
+//! Portable settings export. A bundle carries account metadata (never
+//! passwords), sender rules, compose templates, and preferences, plus an
+//! HMAC-SHA256 signature so edits or corruption are caught on import.
+
+use crate::models::Provider;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+
+pub const BUNDLE_FORMAT: &str = "personal-mail-client/settings";
+pub const BUNDLE_VERSION: u32 = 1;
+/// Used when the user does not pick a passphrase. It only detects damage;
+/// anyone can re-sign a bundle keyed this way.
+const DEFAULT_SIGNING_KEY: &[u8] = b"personal-mail-client settings bundle";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleAccount {
+    pub email: String,
+    pub provider: Provider,
+    pub display_name: Option<String>,
+    pub custom_host: Option<String>,
+    pub custom_port: Option<u16>,
+    #[serde(default)]
+    pub oauth_client_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleSenderRule {
+    pub sender_email: String,
+    pub status: String,
+    /// Account the rule is limited to; absent for global rules and in
+    /// bundles written before rules had a scope.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleTemplate {
+    pub name: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsPayload {
+    pub exported_at: i64,
+    pub accounts: Vec<BundleAccount>,
+    pub sender_rules: Vec<BundleSenderRule>,
+    pub templates: Vec<BundleTemplate>,
+    pub preferences: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedBundle {
+    pub format: String,
+    pub version: u32,
+    /// True when the signature is keyed by a user passphrase.
+    pub passphrase_protected: bool,
+    pub signature: String,
+    pub payload: SettingsPayload,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub fn sign(payload: SettingsPayload, passphrase: Option<&str>) -> Result<SignedBundle, String> {
+    let signature = hex::encode(mac_for(&payload, passphrase)?.finalize().into_bytes());
+    Ok(SignedBundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        passphrase_protected: passphrase.is_some_and(|value| !value.is_empty()),
+        signature,
+        payload,
+    })
+}
+
+/// Checks format, version, and signature, returning the payload on success.
+pub fn verify(bundle: SignedBundle, passphrase: Option<&str>) -> Result<SettingsPayload, String> {
+    if bundle.format != BUNDLE_FORMAT {
+        return Err("File is not a settings bundle".into());
+    }
+    if bundle.version > BUNDLE_VERSION {
+        return Err(format!(
+            "Settings bundle version {} is newer than this app supports",
+            bundle.version
+        ));
+    }
+    let passphrase = passphrase.filter(|value| !value.is_empty());
+    if bundle.passphrase_protected && passphrase.is_none() {
+        return Err("This settings bundle needs its passphrase".into());
+    }
+
+    let passphrase = passphrase.filter(|_| bundle.passphrase_protected);
+    let mismatch = || {
+        "Settings bundle signature does not match (file changed or wrong passphrase)".to_string()
+    };
+    let signature = hex::decode(bundle.signature.trim()).map_err(|_| mismatch())?;
+    // `verify_slice` compares in constant time.
+    mac_for(&bundle.payload, passphrase)?
+        .verify_slice(&signature)
+        .map_err(|_| mismatch())?;
+    Ok(bundle.payload)
+}
+
+fn mac_for(payload: &SettingsPayload, passphrase: Option<&str>) -> Result<HmacSha256, String> {
+    let message = serde_json::to_vec(payload).map_err(|err| err.to_string())?;
+    let key = passphrase
+        .filter(|value| !value.is_empty())
+        .map(str::as_bytes)
+        .unwrap_or(DEFAULT_SIGNING_KEY);
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).map_err(|err| err.to_string())?;
+    mac.update(&message);
+    Ok(mac)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_catch_edits_and_wrong_passphrases() {
+        let payload = SettingsPayload {
+            exported_at: 1_700_000_000,
+            preferences: BTreeMap::from([("strip_trackers".into(), "true".into())]),
+            ..Default::default()
+        };
+        let bundle = sign(payload.clone(), Some("hunter2")).unwrap();
+        assert!(verify(bundle.clone(), Some("hunter2")).is_ok());
+        assert!(verify(bundle.clone(), Some("wrong")).is_err());
+        assert!(verify(bundle.clone(), None).is_err());
+
+        let mut edited = bundle;
+        edited.payload.exported_at += 1;
+        assert!(verify(edited, Some("hunter2")).is_err());
+
+        let unkeyed = sign(payload, None).unwrap();
+        assert!(!unkeyed.passphrase_protected);
+        assert!(verify(unkeyed, None).is_ok());
+    }
+}
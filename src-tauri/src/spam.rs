@@ -0,0 +1,104 @@
+//! Local Naive Bayes spam model trained from block/allow decisions and the
+//! provider's Junk folder. Token counts live in the database (see
+//! `Storage::train_spam_documents`); this module only tokenizes and scores.
+
+use std::collections::{HashMap, HashSet};
+
+/// Each class needs at least this many training documents before scores are produced.
+pub const MIN_TRAINING_DOCS: u64 = 5;
+const MAX_TOKENS_PER_MESSAGE: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpamLabel {
+    Spam,
+    Ham,
+}
+
+impl SpamLabel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpamLabel::Spam => "spam",
+            SpamLabel::Ham => "ham",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "spam" => Some(SpamLabel::Spam),
+            "ham" => Some(SpamLabel::Ham),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SpamModel {
+    pub spam_docs: u64,
+    pub ham_docs: u64,
+    /// Token digest -> (spam document count, ham document count). Callers
+    /// score with tokens digested the same way.
+    pub tokens: HashMap<String, (u64, u64)>,
+}
+
+impl SpamModel {
+    pub fn is_trained(&self) -> bool {
+        self.spam_docs >= MIN_TRAINING_DOCS && self.ham_docs >= MIN_TRAINING_DOCS
+    }
+
+    /// Probability in `[0, 1]` that a message with these tokens is spam, or
+    /// `None` while the model does not have enough examples of both classes.
+    pub fn score(&self, tokens: &[String]) -> Option<f64> {
+        if !self.is_trained() {
+            return None;
+        }
+
+        let spam_docs = self.spam_docs as f64;
+        let ham_docs = self.ham_docs as f64;
+        let mut log_spam = (spam_docs / (spam_docs + ham_docs)).ln();
+        let mut log_ham = (ham_docs / (spam_docs + ham_docs)).ln();
+
+        for token in tokens {
+            // Tokens never seen in training carry no evidence either way.
+            let Some((spam_count, ham_count)) = self.tokens.get(token) else {
+                continue;
+            };
+            log_spam += ((*spam_count as f64 + 1.0) / (spam_docs + 2.0)).ln();
+            log_ham += ((*ham_count as f64 + 1.0) / (ham_docs + 2.0)).ln();
+        }
+
+        Some(1.0 / (1.0 + (log_ham - log_spam).exp()))
+    }
+}
+
+/// Splits sender, subject, and snippet into the deduplicated feature set used
+/// for both training and scoring.
+pub fn tokenize(sender_email: &str, subject: &str, snippet: Option<&str>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut tokens = Vec::new();
+
+    let sender = sender_email.trim().to_lowercase();
+    if let Some((_, domain)) = sender.rsplit_once('@') {
+        if !domain.is_empty() {
+            let token = format!("from:{domain}");
+            seen.insert(token.clone());
+            tokens.push(token);
+        }
+    }
+
+    let text = format!("{subject} {}", snippet.unwrap_or_default()).to_lowercase();
+    for word in text.split(|ch: char| !ch.is_alphanumeric() && ch != '$' && ch != '\'') {
+        let word = word.trim_matches('\'');
+        let length = word.chars().count();
+        if !(3..=24).contains(&length) || word.chars().all(|ch| ch.is_ascii_digit()) {
+            continue;
+        }
+        if seen.insert(word.to_string()) {
+            tokens.push(word.to_string());
+            if tokens.len() >= MAX_TOKENS_PER_MESSAGE {
+                break;
+            }
+        }
+    }
+
+    tokens
+}
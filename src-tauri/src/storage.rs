@@ -1,26 +1,63 @@
+//! The local cache: messages, analyses, and everything the app learns
+//! about them, in one SQLite database. Subjects, snippets, and bodies are
+//! encrypted with AES-256-GCM under a master key kept beside the database.
+//! The schema is brought up to date each time the database is opened, so
+//! opening an existing one is always safe.
+
 use std::{
-    fs,
+    collections::{HashMap, HashSet},
+    fmt, fs,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
-use crate::models::{Account, Provider};
+use crate::attachments::{Attachment, AttachmentRisk, InlineImage};
+use crate::bounces::{self, DeliveryReport};
+use crate::brand::{DmarcPolicy, DomainBrand};
+use crate::breach::{Breach, SecurityMessage};
+use crate::caldav::{PushOutcome, PushStatus};
+use crate::calendar::{CalendarEvent, EventStatus};
+use crate::data_dir;
+use crate::decrypt_cache::{self, DecryptCache, Decrypted};
+use crate::flag_sync::{self, ConflictPolicy, FlagPolicies, Winner};
+use crate::links;
+use crate::lookalike;
+use crate::mail_merge;
+use crate::message_query::{FieldMask, QueryPlan, TEXT_MATCH_FUNCTION};
+use crate::models::{uid_option, uid_string, Account, AuthMethod, Provider};
+use crate::noise::{self, NoiseScore, SenderEngagement};
+use crate::otp::{self, Detection, OneTimeCode, OtpKind};
+use crate::providers::api_send::DeliveryPath;
+use crate::quarantine::{self, QuarantineReason, Signals};
+use crate::relationships::{ContactMessage, RelationshipStats};
+use crate::send_insights::{ReceivedMessage, SentMessage};
+use crate::spam::{self, SpamLabel, SpamModel};
+use crate::threads::MutedThread;
+use crate::topics::TopicCluster;
+use crate::trackers::{self, Tracker};
+use crate::uploads::AttachmentUpload;
+use crate::vcard::{self, Card, CardEdit};
 use aes_gcm::{
     aead::{generic_array::typenum::Unsigned, Aead, AeadCore, KeyInit, OsRng},
     Aes256Gcm, Nonce,
 };
 use base64::{engine::general_purpose, Engine as _};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
 use once_cell::sync::OnceCell;
 use rand::RngCore;
-use rusqlite::{params, Connection, OptionalExtension};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use secrecy::zeroize::Zeroizing;
 use secrecy::{ExposeSecret, SecretVec};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{self, Value};
 use sha2::{Digest, Sha256};
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use tauri::AppHandle;
+use tracing::warn;
 type Result<T> = std::result::Result<T, StorageError>;
 
 #[derive(thiserror::Error, Debug)]
@@ -64,17 +101,84 @@ impl SenderStatus {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Scope of sender rules that apply to every account. Any other scope is
+/// an account email, and a rule there overrides the global one for mail in
+/// that account.
+pub const GLOBAL_SCOPE: &str = "global";
+
+/// Origin of rules the user set themselves. Rules merged from a blocklist
+/// carry [`IMPORTED_ORIGIN`] and the list's path or URL in `origin_ref`.
+pub const MANUAL_ORIGIN: &str = "manual";
+pub const IMPORTED_ORIGIN: &str = "imported";
+
+/// Why a cached message was tombstoned: deleted here, moved out of the
+/// folder, or no longer on the server.
+pub const TOMBSTONE_LOCAL: &str = "local";
+pub const TOMBSTONE_MOVED: &str = "moved";
+pub const TOMBSTONE_REMOTE: &str = "remote";
+
+/// App setting marking an account as headers-only: snippets and bodies
+/// are never written to the cache for it.
+pub fn headers_only_setting_key(account_email: &str) -> String {
+    format!("headers_only:{account_email}")
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SenderRule {
+    pub sender_email: String,
+    pub scope: String,
+    pub status: String,
+    pub updated_at: i64,
+    pub origin: String,
+    pub origin_ref: Option<String>,
+    /// When a mute ends and the rule goes back to its earlier status.
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BlocklistMerge {
+    /// Entries that are now blocked because of the list.
+    pub applied: usize,
+    /// Entries left alone because the user has their own rule for them.
+    pub kept_manual: usize,
+    /// Entries from an earlier import of the same list that are gone now.
+    pub removed: usize,
+}
+
+#[derive(Clone)]
 pub struct MessageInsert {
     pub account_email: String,
-    pub uid: String,
+    pub folder: String,
+    pub uid: u32,
     pub sender_display: String,
     pub sender_email: String,
     pub subject: String,
     pub date: Option<String>,
     pub snippet: Option<String>,
     pub body: Option<Vec<u8>>,
+    /// Space-separated, empty when the message has none. `None` when the
+    /// fetch did not read flags, which leaves the cached ones as they are.
     pub flags: Option<String>,
+    /// `RFC822.SIZE`; `None` keeps the recorded size.
+    pub size: Option<u32>,
+    /// The receiving server's DMARC verdict; `None` keeps the recorded one.
+    pub dmarc_aligned: Option<bool>,
+    /// `None` keeps the recorded thread.
+    pub thread_id: Option<String>,
+}
+
+/// Prints sizes in place of the snippet and body.
+impl fmt::Debug for MessageInsert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MessageInsert")
+            .field("account_email", &self.account_email)
+            .field("folder", &self.folder)
+            .field("uid", &self.uid)
+            .field("snippet_len", &self.snippet.as_ref().map(String::len))
+            .field("body_len", &self.body.as_ref().map(Vec::len))
+            .field("flags", &self.flags)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -88,8 +192,13 @@ pub struct AnalysisValidation {
 
 #[derive(Debug, Clone)]
 pub struct AnalysisInsert {
+    /// The analyzed message's [`MessageRow::message_key`]. Without one the
+    /// analysis goes to whatever is cached at `uid` in `folder`, which only
+    /// suits a message written in the same batch.
+    pub message_key: Option<String>,
     pub account_email: String,
-    pub uid: String,
+    pub folder: String,
+    pub uid: u32,
     pub summary: Option<String>,
     pub sentiment: Option<String>,
     pub categories: Vec<String>,
@@ -99,12 +208,15 @@ pub struct AnalysisInsert {
     pub analyzed_at: Option<i64>,
     pub analysis_confidence: Option<f64>,
     pub validation: AnalysisValidation,
+    pub fingerprint: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct MessageRow {
     pub id: i64,
-    pub uid: String,
+    pub folder: String,
+    pub uid: u32,
+    pub message_key: String,
     pub subject: String,
     pub sender_display: String,
     pub sender_email: String,
@@ -125,12 +237,45 @@ pub struct MessageRow {
     pub analysis_validation_confidence: Option<f64>,
     pub analysis_validation_notes: Option<String>,
     pub analysis_validated_at: Option<i64>,
+    pub spam_score: Option<f64>,
     pub body_cached: bool,
+    /// `RFC822.SIZE`, unknown for messages cached before sizes were kept.
+    pub size_bytes: Option<i64>,
+    pub thread_id: Option<String>,
+}
+
+/// A sender domain's DMARC and BIMI records as last read.
+#[derive(Debug, Clone)]
+pub struct BrandCheck {
+    pub brand: DomainBrand,
+    pub checked_at: i64,
+}
+
+/// A message held in quarantine, with the risk that put it there.
+#[derive(Debug, Clone)]
+pub struct QuarantinedMessage {
+    pub message: MessageRow,
+    pub score: f64,
+    pub reasons: Vec<QuarantineReason>,
+    pub quarantined_at: i64,
+}
+
+/// One page of [`Storage::query_messages`]; `next_cursor` is `None` on the
+/// last page.
+#[derive(Debug, Clone)]
+pub struct MessagePage {
+    pub messages: Vec<MessageRow>,
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct DeletedMessageRow {
-    pub uid: String,
+    pub folder: String,
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub message_key: String,
+    #[serde(skip)]
+    pub uid_validity: Option<u32>,
     pub sender_email: String,
     pub sender_display: Option<String>,
     pub subject: String,
@@ -144,20 +289,102 @@ pub struct DeletedMessageRow {
     pub remote_error: Option<String>,
 }
 
+impl DeletedMessageRow {
+    /// The server-side delete this row is waiting for.
+    pub fn remote_delete(&self) -> PendingRemoteDelete {
+        PendingRemoteDelete {
+            message_key: self.message_key.clone(),
+            folder: self.folder.clone(),
+            uid_validity: self.uid_validity,
+            uid: self.uid,
+            remote_error: self.remote_error.clone(),
+        }
+    }
+}
+
+/// A deleted message not yet removed from the server, by the identity it
+/// was cached under.
+#[derive(Debug, Clone)]
+pub struct PendingRemoteDelete {
+    pub message_key: String,
+    pub folder: String,
+    pub uid_validity: Option<u32>,
+    pub uid: u32,
+    pub remote_error: Option<String>,
+}
+
+/// What a message key points at: a cached message, or one in the deleted
+/// list waiting to be removed from the server.
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageIdentity {
+    pub message_key: String,
+    pub account_email: String,
+    pub folder: String,
+    pub uid_validity: Option<u32>,
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub deleted: bool,
+}
+
+/// A flag edited here that has not reached the server yet.
 #[derive(Debug, Clone)]
+pub struct PendingFlagChange {
+    pub folder: String,
+    /// The UIDVALIDITY the message was cached under, which the server must
+    /// still report for the edit to be pushed.
+    pub uid_validity: Option<u32>,
+    pub uid: u32,
+    pub flag: String,
+    pub enabled: bool,
+    pub changed_at: i64,
+}
+
+/// A flag edited here while another client changed the same message, and
+/// the side whose value was kept.
+#[derive(Debug, Clone, Serialize)]
+pub struct FlagConflict {
+    pub id: i64,
+    pub account_email: String,
+    pub folder: String,
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub flag: String,
+    pub local_enabled: bool,
+    pub server_enabled: bool,
+    pub policy: ConflictPolicy,
+    pub winner: Winner,
+    pub local_changed_at: i64,
+    pub detected_at: i64,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct ExistingAnalysisRecord {
     pub analyzed: bool,
     pub analyzed_at: Option<i64>,
     pub model_id: Option<String>,
     pub categories: Vec<String>,
     pub metadata: Option<Value>,
+    pub validation_status: Option<String>,
+    pub fingerprint: Option<String>,
+    pub stale: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StaleAnalysisFilter {
+    pub account_email: Option<String>,
+    pub sender_email: Option<String>,
+    pub model_id: Option<String>,
+    pub exclude_model_id: Option<String>,
+    pub exclude_fingerprint: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct MessageForAnalysis {
     pub message_id: i64,
+    pub message_key: String,
     pub account_email: String,
-    pub uid: String,
+    pub folder: String,
+    pub uid: u32,
     pub subject: String,
     pub snippet: Option<String>,
     pub date: Option<String>,
@@ -166,17 +393,407 @@ pub struct MessageForAnalysis {
     pub existing_analysis: ExistingAnalysisRecord,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisCoverage {
+    pub account_email: String,
+    pub total: usize,
+    pub analyzed: usize,
+    pub unanalyzed: usize,
+    pub failed: usize,
+    pub stale: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TopicSummary {
+    pub id: i64,
+    pub account_email: String,
+    pub label: String,
+    pub keywords: Vec<String>,
+    pub message_count: usize,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TopicMessage {
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub subject: String,
+    pub sender_email: String,
+    pub sender_display: Option<String>,
+    pub date: Option<String>,
+    pub similarity: f64,
+}
+
+/// A reusable compose template (canned response). `variables` lists the
+/// `{{placeholder}}` names found in the subject and body.
+#[derive(Debug, Clone, Serialize)]
+pub struct EmailTemplate {
+    pub id: i64,
+    pub name: String,
+    pub subject: String,
+    pub body: String,
+    pub variables: Vec<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// A named set of messages gathered from any account, e.g. "Tax 2024".
+#[derive(Debug, Clone, Serialize)]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    pub item_count: usize,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectionItem {
+    pub account_email: String,
+    pub folder: String,
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    /// Cached details; `None` once the message has left the local cache.
+    pub subject: Option<String>,
+    pub sender_email: Option<String>,
+    pub date: Option<String>,
+    pub added_at: i64,
+}
+
+/// One canned quick reply; `kind` is "acknowledge", "accept", or "decline".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplySuggestion {
+    pub kind: String,
+    pub text: String,
+}
+
+/// Decrypted headline fields of one cached message.
+#[derive(Debug, Clone)]
+pub struct CachedMessage {
+    pub message_id: i64,
+    pub uid: u32,
+    pub subject: String,
+    pub snippet: Option<String>,
+    pub date: Option<String>,
+    pub sender_email: String,
+    pub sender_display: Option<String>,
+}
+
+/// A user action worth keeping a record of (reports, wipes, imports).
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub account_email: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub detail: Value,
+    pub created_at: i64,
+}
+
+/// A contact in the local address book.
+#[derive(Debug, Clone, Serialize)]
+pub struct Contact {
+    pub id: i64,
+    #[serde(flatten)]
+    pub card: Card,
+    /// Where the contact came from: `mail`, `carddav`, or `import`.
+    pub source: String,
+    /// The user opted this contact in to CardDAV sync.
+    pub sync_enabled: bool,
+    /// Linked to a card on the CardDAV server and unchanged since the last
+    /// sync.
+    pub synced: bool,
+    pub updated_at: i64,
+}
+
+/// A contact as CardDAV sync sees it.
+#[derive(Debug, Clone)]
+pub struct ContactSyncState {
+    pub id: i64,
+    pub card: Card,
+    pub href: Option<String>,
+    pub etag: Option<String>,
+    pub sync_enabled: bool,
+    /// Edited here since the last sync, or never synced.
+    pub modified: bool,
+    pub updated_at: i64,
+}
+
+/// One attempt to push an event to the CalDAV calendar.
+#[derive(Debug, Clone, Serialize)]
+pub struct CalDavLogEntry {
+    pub account_email: String,
+    pub event_uid: String,
+    pub sequence: i64,
+    pub status: PushStatus,
+    pub detail: Option<String>,
+    pub logged_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AutoReplyLogEntry {
+    pub sender_email: String,
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub activation: i64,
+    pub replied_at: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboxInsert {
+    pub account_email: String,
+    pub merge_id: Option<i64>,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub attachments: Vec<OutboxAttachment>,
+    /// Attachments uploaded and linked from the body instead.
+    pub uploads: Vec<AttachmentUpload>,
+    pub scheduled_at: i64,
+    /// Message-ID of the message replied to, without brackets.
+    pub in_reply_to: Option<String>,
+    /// Message-IDs of the conversation so far, oldest first.
+    pub references: Vec<String>,
+    /// The `Auto-Submitted` value (RFC 3834) for mail the client sends on
+    /// its own, such as `auto-replied`.
+    pub auto_submitted: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutboxItem {
+    pub id: i64,
+    pub account_email: String,
+    pub merge_id: Option<i64>,
+    pub recipient: String,
+    pub subject: String,
+    pub status: String,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub scheduled_at: i64,
+    pub updated_at: i64,
+    /// The Message-ID header the entry is sent with, without brackets.
+    pub message_id: Option<String>,
+    /// `sent` or `bounced` once the entry has gone out; `None` before.
+    pub delivery_status: Option<String>,
+    pub uploads: Vec<AttachmentUpload>,
+    /// `smtp`, `gmail_api`, or `graph` once sent.
+    pub delivery_path: Option<String>,
+}
+
+/// An outbox entry claimed for sending, with what it is sent with.
+#[derive(Debug, Clone)]
+pub struct OutboxMessage {
+    pub id: i64,
+    pub account_email: String,
+    /// One address, or several separated by commas.
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    pub message_id: String,
+    /// Sends tried so far, counting this one.
+    pub attempts: i64,
+    pub attachments: Vec<OutboxAttachment>,
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+    pub auto_submitted: Option<String>,
+}
+
+/// What focus mode decided for a newly synced message.
+#[derive(Debug, Clone)]
+pub struct FocusDecision {
+    pub uid: u32,
+    /// Why the message got through; `None` when it did not qualify.
+    pub reason: Option<String>,
+    /// Arrived during focus hours without qualifying, so it waits for review.
+    pub held: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FocusMessage {
+    pub account_email: String,
+    pub folder: String,
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub sender_email: String,
+    pub sender_display: Option<String>,
+    pub subject: String,
+    pub date: Option<String>,
+    pub reason: Option<String>,
+    pub decided_at: i64,
+}
+
+/// A recipient a delivery failure report says could not be reached,
+/// linked to the outbox entry it answers when one matches.
+#[derive(Debug, Clone, Serialize)]
+pub struct Bounce {
+    pub id: i64,
+    pub account_email: String,
+    /// The report's own UID in INBOX.
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub recipient: String,
+    pub status_code: Option<String>,
+    pub diagnostic: Option<String>,
+    pub original_subject: Option<String>,
+    pub outbox_id: Option<i64>,
+    pub detected_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MailMergeStatus {
+    pub id: i64,
+    pub account_email: String,
+    pub rate_per_minute: u32,
+    pub created_at: i64,
+    pub total: usize,
+    pub queued: usize,
+    pub sent: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    pub items: Vec<OutboxItem>,
+}
+
+/// Checkpoint for copying one folder between accounts; `last_uid` is the
+/// highest source UID already appended to the target.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountMigration {
+    pub id: i64,
+    pub source_email: String,
+    pub target_email: String,
+    pub source_folder: String,
+    pub target_folder: String,
+    pub last_uid: u32,
+    pub total: usize,
+    pub copied: usize,
+    pub failed: usize,
+    /// Source UIDs at or below `last_uid` that were not copied; a resumed
+    /// run tries them again first.
+    pub failed_uids: Vec<u32>,
+    /// "running", "paused", "failed", or "completed".
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LlmBenchmark {
+    pub model_id: String,
+    pub samples: usize,
+    pub tokens_per_second: f64,
+    pub time_to_first_token_ms: f64,
+    pub json_valid_rate: f64,
+    /// Resident memory of the whole app at the end of the run.
+    pub process_rss_bytes: Option<u64>,
+    /// How much the benchmarked model added to resident memory.
+    pub model_rss_bytes: Option<u64>,
+    pub completed_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReviewQueueItem {
+    pub account_email: String,
+    pub folder: String,
+    #[serde(with = "uid_string")]
+    pub uid: u32,
+    pub sender_email: String,
+    pub sender_display: Option<String>,
+    pub subject: String,
+    pub reason: String,
+    pub confidence: Option<f64>,
+    pub summary: Option<String>,
+    pub categories: Vec<String>,
+    pub metadata: Option<Value>,
+    pub queued_at: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalysisCorrection {
+    pub categories: Option<Vec<String>>,
+    pub priority: Option<String>,
+    pub sentiment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisExample {
+    pub id: i64,
+    pub account_email: String,
+    pub sender_email: String,
+    pub sender_domain: Option<String>,
+    pub subject: String,
+    pub snippet: Option<String>,
+    pub categories: Vec<String>,
+    pub priority: Option<String>,
+    pub sentiment: Option<String>,
+    pub created_at: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct SenderGroup {
     pub sender_email: String,
     pub sender_display: String,
     pub status: SenderStatus,
+    pub profile: Option<SenderProfile>,
+    /// Set while the sender is blocked in this account.
+    pub block_note: Option<BlockNote>,
     pub messages: Vec<MessageRow>,
 }
 
+/// A sender group without its messages, read from `sender_aggregates`.
+#[derive(Debug, Clone)]
+pub struct SenderGroupHeader {
+    pub sender_email: String,
+    pub sender_display: String,
+    pub status: SenderStatus,
+    pub profile: Option<SenderProfile>,
+    pub block_note: Option<BlockNote>,
+    pub message_count: i64,
+    pub unread_count: i64,
+    pub latest_date: Option<String>,
+}
+
+/// Why a sender was blocked in an account, as noted when blocking it.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockNote {
+    pub reason: Option<String>,
+    /// The message that prompted the block.
+    #[serde(with = "uid_option")]
+    pub origin_uid: Option<u32>,
+    pub created_at: i64,
+}
+
+/// Aggregated analysis outcomes for a sender, used to classify new mail
+/// from that sender without a model round-trip.
+#[derive(Debug, Clone, Serialize)]
+pub struct SenderProfile {
+    pub kind: String,
+    pub typical_tags: Vec<String>,
+    pub typical_priority: Option<String>,
+    pub sample_count: i64,
+    pub confidence: f64,
+    pub updated_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageLink {
+    pub url: String,
+    pub domain: String,
+    pub display_text: Option<String>,
+    pub mismatch: bool,
+    /// The domain, or a parent of it, is on the local blocklist.
+    pub blocked: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct CachedMessageSummary {
-    pub uid: String,
+    pub uid: u32,
     pub subject: String,
     pub sender_email: String,
     pub sender_display: Option<String>,
@@ -188,7 +805,7 @@ pub struct AccountSyncState {
     pub account_email: String,
     pub last_full_sync: Option<i64>,
     pub last_incremental_sync: Option<i64>,
-    pub last_uid: Option<String>,
+    pub last_uid: Option<u32>,
     pub total_messages: i64,
 }
 
@@ -198,12 +815,75 @@ pub struct AccountRecord {
     pub email: String,
     pub custom_host: Option<String>,
     pub custom_port: Option<u16>,
+    pub oauth_client_id: Option<String>,
+}
+
+impl AccountRecord {
+    /// How the account signs in; its keychain entry holds the password or,
+    /// for OAuth, the refresh token.
+    pub fn auth(&self) -> AuthMethod {
+        match &self.oauth_client_id {
+            Some(client_id) => AuthMethod::OAuth2 {
+                client_id: client_id.clone(),
+            },
+            None => AuthMethod::Password,
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Storage {
     conn: Arc<parking_lot::Mutex<Connection>>,
     cipher: Arc<Cipher>,
+    decrypted: Arc<DecryptCache>,
+    pending_writes: Arc<parking_lot::Mutex<HashMap<PendingKey, PendingWrite>>>,
+}
+
+/// How long a queued write waits for others to join it.
+const WRITE_DEBOUNCE: Duration = Duration::from_millis(250);
+/// Queued writes that trigger a flush without waiting for the debounce.
+const WRITE_QUEUE_FLUSH_AT: usize = 256;
+
+/// A small per-message update that can wait briefly and be written together
+/// with others. A newer write for the same message and kind replaces the
+/// queued one.
+#[derive(Debug, Clone)]
+pub enum PendingWrite {
+    /// Replaces the cached IMAP flags of a message.
+    Flags {
+        account_email: String,
+        folder: String,
+        uid: u32,
+        flags: Option<String>,
+    },
+    /// Sets the analysis lifecycle (`snoozed`, `done`, ...) of a message.
+    Lifecycle {
+        account_email: String,
+        folder: String,
+        uid: u32,
+        lifecycle: String,
+    },
+}
+
+type PendingKey = (&'static str, String, String, u32);
+
+impl PendingWrite {
+    fn key(&self) -> PendingKey {
+        match self {
+            PendingWrite::Flags {
+                account_email,
+                folder,
+                uid,
+                ..
+            } => ("flags", account_email.clone(), folder.clone(), *uid),
+            PendingWrite::Lifecycle {
+                account_email,
+                folder,
+                uid,
+                ..
+            } => ("lifecycle", account_email.clone(), folder.clone(), *uid),
+        }
+    }
 }
 
 struct Cipher {
@@ -211,6 +891,76 @@ struct Cipher {
 }
 
 static DB_PATH: OnceCell<PathBuf> = OnceCell::new();
+/// Snapshots kept in `<data dir>/backups`, newest first.
+const BACKUP_KEEP: usize = 3;
+const BACKUP_DIR: &str = "backups";
+const BACKUP_PREFIX: &str = "mail_cache-";
+
+/// Outcome of the startup integrity check, sent to the UI as `storage-health`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageHealthReport {
+    /// "ok", "recovered" (WAL checkpoint or rollback fixed it), "restored"
+    /// (replaced by a backup), or "failed".
+    pub status: String,
+    /// What `PRAGMA integrity_check` reported before any recovery.
+    pub problems: Vec<String>,
+    /// Recovery steps attempted, in order.
+    pub actions: Vec<String>,
+    pub restored_from: Option<PathBuf>,
+    /// Where the damaged database was moved before a restore.
+    pub quarantined: Option<PathBuf>,
+    pub checked_at: i64,
+}
+
+fn open_database(path: &Path) -> Result<Connection> {
+    let mut connection = Connection::open(path)?;
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.pragma_update(None, "synchronous", "NORMAL")?;
+    Storage::apply_migrations(&mut connection)?;
+    Ok(connection)
+}
+
+/// `PRAGMA integrity_check` output, empty when the database is healthy. A
+/// database too damaged to run the check reports the error instead.
+fn integrity_problems(conn: &Connection) -> Vec<String> {
+    let result = conn.prepare("PRAGMA integrity_check").and_then(|mut stmt| {
+        let mut rows = stmt.query([])?;
+        let mut lines = Vec::new();
+        while let Some(row) = rows.next()? {
+            lines.push(row.get::<_, String>(0)?);
+        }
+        Ok(lines)
+    });
+    match result {
+        Ok(lines) if lines.len() == 1 && lines[0] == "ok" => Vec::new(),
+        Ok(lines) => lines,
+        Err(err) => vec![err.to_string()],
+    }
+}
+
+fn sidecar_path(db_path: &Path, suffix: &str) -> PathBuf {
+    let mut name = db_path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Backup snapshots, newest first. Names embed a sortable timestamp.
+fn list_backups(data_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(data_dir.join(BACKUP_DIR)) else {
+        return Vec::new();
+    };
+    let mut backups = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(BACKUP_PREFIX) && name.ends_with(".db"))
+        })
+        .collect::<Vec<_>>();
+    backups.sort();
+    backups.reverse();
+    backups
+}
 
 fn load_or_create_master_key(dir: &Path) -> Result<Vec<u8>> {
     let key_path = dir.join("master.key");
@@ -234,6 +984,57 @@ fn load_or_create_master_key(dir: &Path) -> Result<Vec<u8>> {
     }
 }
 
+/// Overwrites a file with zeros before unlinking it. Flash storage may keep
+/// old blocks around, but this defeats simple undelete tools.
+fn shred_file(path: &Path) -> Result<bool> {
+    let length = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+
+    {
+        use std::io::Write;
+        let mut file = fs::OpenOptions::new().write(true).open(path)?;
+        let zeros = vec![0u8; 64 * 1024];
+        let mut remaining = length;
+        while remaining > 0 {
+            let chunk = remaining.min(zeros.len() as u64) as usize;
+            file.write_all(&zeros[..chunk])?;
+            remaining -= chunk as u64;
+        }
+        file.sync_all()?;
+    }
+
+    fs::remove_file(path)?;
+    Ok(true)
+}
+
+/// Shreds every file below `dir` and removes the emptied folders. Links are
+/// removed without touching what they point to. A missing `dir` is fine.
+fn shred_dir(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut removed = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            removed.extend(shred_dir(&path)?);
+        } else if file_type.is_symlink() {
+            fs::remove_file(&path)?;
+        } else if shred_file(&path)? {
+            removed.push(path);
+        }
+    }
+    fs::remove_dir(dir)?;
+    Ok(removed)
+}
+
 fn map_join_error(err: tokio::task::JoinError) -> StorageError {
     StorageError::Io(std::io::Error::new(
         std::io::ErrorKind::Interrupted,
@@ -268,39 +1069,1028 @@ fn add_column_if_missing(
     Ok(())
 }
 
-impl Storage {
-    pub fn initialize(handle: &AppHandle) -> Result<Self> {
-        let data_dir = handle.path_resolver().app_data_dir().ok_or_else(|| {
-            StorageError::Io(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "App data directory not available",
-            ))
-        })?;
-        fs::create_dir_all(&data_dir)?;
-        let db_path = data_dir.join("mail_cache.db");
-        DB_PATH.set(db_path.clone()).ok();
+/// Drops imported rules matching `condition`, limited to one list when
+/// `origin_ref` is set. Rows that also hold a sender profile are reset to a
+/// neutral manual row instead, so the profile survives.
+fn remove_imported(conn: &Connection, origin_ref: Option<&str>, condition: &str) -> Result<usize> {
+    let scope = format!("origin = 'imported' AND (?1 IS NULL OR origin_ref = ?1) AND {condition}");
+    let reset = conn.execute(
+        &format!(
+            "UPDATE sender_status SET status = 'neutral', origin = 'manual', origin_ref = NULL \
+             WHERE {scope} AND profile_samples > 0"
+        ),
+        params![origin_ref],
+    )?;
+    let deleted = conn.execute(
+        &format!("DELETE FROM sender_status WHERE {scope}"),
+        params![origin_ref],
+    )?;
+    Ok(reset + deleted)
+}
 
-        let mut connection = Connection::open(&db_path)?;
-        connection.pragma_update(None, "journal_mode", "WAL")?;
-        connection.pragma_update(None, "synchronous", "NORMAL")?;
-        Self::apply_migrations(&mut connection)?;
+/// Unix seconds for a message `Date` header, used to sort and filter by
+/// date; the header text itself is kept for display.
+fn message_timestamp(date: &str) -> Option<i64> {
+    DateTime::parse_from_rfc2822(date)
+        .or_else(|_| DateTime::parse_from_rfc3339(date))
+        .ok()
+        .map(|date| date.timestamp())
+}
 
-        let master_key = load_or_create_master_key(&data_dir)?;
-        let cipher = Cipher::from_bytes(master_key)?;
+fn backfill_message_timestamps(conn: &Connection) -> Result<()> {
+    let mut select =
+        conn.prepare("SELECT id, date FROM messages WHERE date_ts IS NULL AND date IS NOT NULL")?;
+    let mut update = conn.prepare("UPDATE messages SET date_ts = ? WHERE id = ?")?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let date: String = row.get(1)?;
+        if let Some(timestamp) = message_timestamp(&date) {
+            update.execute(params![timestamp, id])?;
+        }
+    }
+    Ok(())
+}
 
-        Ok(Self {
-            conn: Arc::new(parking_lot::Mutex::new(connection)),
-            cipher: Arc::new(cipher),
-        })
+/// Rebuilds `messages` and `deleted_messages` from before UIDs were stored
+/// as integers. Rows are keyed by `(account_email, folder, uid)`; everything
+/// cached so far came from INBOX. Rows whose UID is not a number cannot
+/// name a server message and are dropped.
+fn integer_message_uids(conn: &Connection) -> Result<()> {
+    if column_exists(conn, "messages", "folder")? {
+        return Ok(());
     }
+    const NUMERIC_UID: &str = "uid GLOB '[0-9]*' AND uid NOT GLOB '*[^0-9]*'";
+    conn.execute_batch(&format!(
+        r#"
+        BEGIN;
+        CREATE TABLE messages_by_folder (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL DEFAULT 'INBOX',
+            uid INTEGER NOT NULL,
+            sender_email TEXT NOT NULL,
+            sender_display TEXT,
+            subject_encrypted TEXT,
+            date TEXT,
+            snippet_encrypted TEXT,
+            body_encrypted TEXT,
+            flags TEXT,
+            created_at INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            spam_score REAL,
+            trackers_detected TEXT,
+            date_ts INTEGER,
+            UNIQUE(account_email, folder, uid)
+        );
+        INSERT INTO messages_by_folder (
+            id, account_email, folder, uid, sender_email, sender_display, subject_encrypted,
+            date, snippet_encrypted, body_encrypted, flags, created_at, updated_at, spam_score,
+            trackers_detected, date_ts
+        )
+        SELECT id, account_email, 'INBOX', CAST(uid AS INTEGER), sender_email, sender_display,
+            subject_encrypted, date, snippet_encrypted, body_encrypted, flags, created_at,
+            updated_at, spam_score, trackers_detected, date_ts
+        FROM messages
+        WHERE {NUMERIC_UID};
+        DROP TABLE messages;
+        ALTER TABLE messages_by_folder RENAME TO messages;
+        CREATE INDEX idx_messages_account_sender ON messages(account_email, sender_email);
+        DELETE FROM analysis_results WHERE message_id NOT IN (SELECT id FROM messages);
+
+        CREATE TABLE deleted_messages_by_folder (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL DEFAULT 'INBOX',
+            uid INTEGER NOT NULL,
+            sender_email TEXT NOT NULL,
+            sender_display TEXT,
+            subject_encrypted TEXT,
+            date TEXT,
+            snippet_encrypted TEXT,
+            flags TEXT,
+            analysis_summary TEXT,
+            analysis_sentiment TEXT,
+            analysis_categories TEXT,
+            deleted_at INTEGER NOT NULL,
+            remote_deleted_at INTEGER,
+            remote_error TEXT,
+            UNIQUE(account_email, folder, uid)
+        );
+        INSERT INTO deleted_messages_by_folder (
+            id, account_email, folder, uid, sender_email, sender_display, subject_encrypted,
+            date, snippet_encrypted, flags, analysis_summary, analysis_sentiment,
+            analysis_categories, deleted_at, remote_deleted_at, remote_error
+        )
+        SELECT id, account_email, 'INBOX', CAST(uid AS INTEGER), sender_email, sender_display,
+            subject_encrypted, date, snippet_encrypted, flags, analysis_summary,
+            analysis_sentiment, analysis_categories, deleted_at, remote_deleted_at, remote_error
+        FROM deleted_messages
+        WHERE {NUMERIC_UID};
+        DROP TABLE deleted_messages;
+        ALTER TABLE deleted_messages_by_folder RENAME TO deleted_messages;
+        CREATE INDEX idx_deleted_messages_account
+            ON deleted_messages(account_email, deleted_at DESC);
+        COMMIT;
+        "#
+    ))?;
+    // Dropping `messages` took the aggregate triggers with it. Dropping the
+    // counts too has them rebuilt along with the triggers.
+    conn.execute("DROP TABLE IF EXISTS sender_aggregates", ())?;
+    Ok(())
+}
 
-    fn apply_migrations(conn: &mut Connection) -> Result<()> {
-        conn.execute_batch(
+/// Suffix of a message side table from before it had a `folder` column,
+/// while the table is made again with one.
+const UID_KEYED_LEGACY: &str = "_by_uid";
+
+/// Moves the [`UID_KEYED_TABLES`] from before they were keyed by folder
+/// out of the way, so they are created again with a `folder` column and an
+/// INTEGER `uid`. [`restore_uid_keyed_tables`] copies their rows back once
+/// the schema is current. Their indexes are dropped so they are made again
+/// on the new tables.
+fn set_aside_uid_keyed_tables(conn: &Connection) -> Result<()> {
+    for table in UID_KEYED_TABLES {
+        let exists = conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?",
+                params![table],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        let folder_column = if table == "message_folders" {
+            "target_folder"
+        } else {
+            "folder"
+        };
+        if !exists || column_exists(conn, table, folder_column)? {
+            continue;
+        }
+        let indexes = {
+            let mut stmt = conn.prepare(
+                "SELECT name FROM sqlite_master \
+                 WHERE type = 'index' AND tbl_name = ? AND sql IS NOT NULL",
+            )?;
+            let names = stmt.query_map(params![table], |row| row.get::<_, String>(0))?;
+            names.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        for index in indexes {
+            conn.execute(&format!("DROP INDEX {index}"), ())?;
+        }
+        conn.execute(
+            &format!("ALTER TABLE {table} RENAME TO {table}{UID_KEYED_LEGACY}"),
+            (),
+        )?;
+    }
+    Ok(())
+}
+
+/// Copies the rows [`set_aside_uid_keyed_tables`] moved away into the new
+/// tables. Everything cached before then came from INBOX. Rows whose UID is
+/// not a number are dropped, as they were from `messages`.
+fn restore_uid_keyed_tables(conn: &Connection) -> Result<()> {
+    const NUMERIC_UID: &str = "CAST(uid AS TEXT) GLOB '[0-9]*' \
+         AND CAST(uid AS TEXT) NOT GLOB '*[^0-9]*'";
+    for table in UID_KEYED_TABLES {
+        let legacy = format!("{table}{UID_KEYED_LEGACY}");
+        let columns = {
+            let mut stmt = conn.prepare(&format!("PRAGMA table_info({legacy})"))?;
+            let names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+            names.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        if columns.is_empty() {
+            continue;
+        }
+        let mut targets = vec!["folder".to_string()];
+        let mut values = vec!["'INBOX'".to_string()];
+        for column in columns {
+            let target = if table == "message_folders" && column == "folder" {
+                // It named the folder the message was copied to.
+                "target_folder".to_string()
+            } else if column_exists(conn, table, &column)? {
+                column.clone()
+            } else {
+                continue;
+            };
+            values.push(if column == "uid" {
+                "CAST(uid AS INTEGER)".to_string()
+            } else {
+                column
+            });
+            targets.push(target);
+        }
+        conn.execute_batch(&format!(
+            "INSERT OR IGNORE INTO {table} ({}) SELECT {} FROM {legacy} WHERE {NUMERIC_UID}; \
+             DROP TABLE {legacy};",
+            targets.join(", "),
+            values.join(", ")
+        ))?;
+    }
+    Ok(())
+}
+
+/// Drops topics stored before their labels and keywords were encrypted.
+/// They are derived from the messages, so the next clustering run brings
+/// them back.
+fn encrypted_topics(conn: &Connection) -> Result<()> {
+    if !column_exists(conn, "topics", "label")? {
+        return Ok(());
+    }
+    conn.execute_batch(
+        r#"
+        BEGIN;
+        DELETE FROM topic_assignments;
+        DROP TABLE topics;
+        CREATE TABLE topics (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account_email TEXT NOT NULL,
+            label_encrypted TEXT NOT NULL,
+            keywords_encrypted TEXT NOT NULL,
+            message_count INTEGER NOT NULL,
+            created_at INTEGER NOT NULL
+        );
+        CREATE INDEX idx_topics_account ON topics(account_email);
+        COMMIT;
+        "#,
+    )?;
+    Ok(())
+}
+
+/// A stored `uidvalidity`, which the server sends as a 32-bit value.
+fn uid_validity_from(value: Option<i64>) -> Option<u32> {
+    value.and_then(|value| u32::try_from(value).ok())
+}
+
+/// The key the frontend holds for a message: a digest of its
+/// `(account, folder, uidvalidity, uid)` identity. It is computed once, when
+/// the message is first cached, and stays with the row from then on.
+fn message_key(account_email: &str, folder: &str, uid_validity: Option<i64>, uid: i64) -> String {
+    let uid_validity = uid_validity
+        .map(|value| value.to_string())
+        .unwrap_or_default();
+    let identity = format!("{account_email}\n{folder}\n{uid_validity}\n{uid}");
+    let digest = Sha256::digest(identity.as_bytes());
+    general_purpose::URL_SAFE_NO_PAD.encode(&digest[..12])
+}
+
+/// Adds `folder_state`, which remembers the UIDVALIDITY each folder was
+/// cached under, and the `uidvalidity` and `message_key` columns of
+/// `messages` and `deleted_messages`. Rows cached before then get their
+/// key here; their UIDVALIDITY is filled in by the next sync.
+fn track_message_identity(conn: &Connection) -> Result<()> {
+    conn.execute(
+        r#"
+        CREATE TABLE IF NOT EXISTS folder_state (
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL,
+            uid_validity INTEGER NOT NULL,
+            updated_at INTEGER NOT NULL,
+            PRIMARY KEY(account_email, folder)
+        )
+        "#,
+        (),
+    )?;
+    for table in ["messages", "deleted_messages"] {
+        add_column_if_missing(conn, table, "uidvalidity", "uidvalidity INTEGER")?;
+        add_column_if_missing(conn, table, "message_key", "message_key TEXT")?;
+        let mut select = conn.prepare(&format!(
+            "SELECT id, account_email, folder, uidvalidity, uid FROM {table} \
+             WHERE message_key IS NULL"
+        ))?;
+        let mut update =
+            conn.prepare(&format!("UPDATE {table} SET message_key = ? WHERE id = ?"))?;
+        let mut rows = select.query([])?;
+        while let Some(row) = rows.next()? {
+            let key = message_key(
+                &row.get::<_, String>(1)?,
+                &row.get::<_, String>(2)?,
+                row.get(3)?,
+                row.get(4)?,
+            );
+            update.execute(params![key, row.get::<_, i64>(0)?])?;
+        }
+    }
+    conn.execute_batch(
+        r#"
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_key ON messages(message_key);
+        CREATE INDEX IF NOT EXISTS idx_deleted_messages_key ON deleted_messages(message_key);
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Adds tombstones to `messages`. A message deleted here, moved away, or
+/// gone from the server keeps its row and analysis, hidden from listings,
+/// until [`Storage::purge_tombstones`] removes it.
+fn track_tombstones(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "messages", "tombstoned_at", "tombstoned_at INTEGER")?;
+    add_column_if_missing(
+        conn,
+        "messages",
+        "tombstone_reason",
+        "tombstone_reason TEXT",
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_messages_tombstoned \
+         ON messages(tombstoned_at) WHERE tombstoned_at IS NOT NULL",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Queues flag edits made here until they reach the server. `base_flags`
+/// is the server's flag list the edit was made against, and
+/// `base_refreshed_at` when it was read; a sync that finds different flags
+/// knows another client changed the message too.
+fn track_flag_changes(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS pending_flag_changes (
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL DEFAULT 'INBOX',
+            uid INTEGER NOT NULL,
+            flag TEXT NOT NULL,
+            enabled INTEGER NOT NULL,
+            base_flags TEXT,
+            base_refreshed_at INTEGER NOT NULL,
+            changed_at INTEGER NOT NULL,
+            PRIMARY KEY (account_email, folder, uid, flag)
+        );
+        CREATE TABLE IF NOT EXISTS flag_conflicts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account_email TEXT NOT NULL,
+            uid INTEGER NOT NULL,
+            flag TEXT NOT NULL,
+            local_enabled INTEGER NOT NULL,
+            server_enabled INTEGER NOT NULL,
+            policy TEXT NOT NULL,
+            winner TEXT NOT NULL,
+            local_changed_at INTEGER NOT NULL,
+            detected_at INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_flag_conflicts_account
+            ON flag_conflicts(account_email, detected_at);
+        "#,
+    )?;
+    add_column_if_missing(
+        conn,
+        "pending_flag_changes",
+        "attempts",
+        "attempts INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column_if_missing(
+        conn,
+        "flag_conflicts",
+        "folder",
+        "folder TEXT NOT NULL DEFAULT 'INBOX'",
+    )?;
+    Ok(())
+}
+
+/// Notes on why a sender was blocked, one per sender and account. The
+/// reason is encrypted like message text.
+fn track_block_notes(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS sender_block_notes (
+            sender_email TEXT NOT NULL,
+            account_email TEXT NOT NULL,
+            reason_encrypted TEXT,
+            origin_uid INTEGER,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (sender_email, account_email)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Delivery failures found in INBOX. `bounce_scans` remembers which likely
+/// bounces were already fetched and read, so each is downloaded once; the
+/// outbox gets a Message-ID that reports can be matched on.
+fn track_bounces(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "outbox", "message_id", "message_id TEXT")?;
+    conn.execute_batch(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_outbox_message_id
+            ON outbox(account_email, message_id);
+        CREATE TABLE IF NOT EXISTS bounces (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account_email TEXT NOT NULL,
+            uid INTEGER NOT NULL,
+            recipient TEXT NOT NULL,
+            status_code TEXT,
+            diagnostic TEXT,
+            original_subject_encrypted TEXT,
+            outbox_id INTEGER,
+            detected_at INTEGER NOT NULL,
+            UNIQUE (account_email, uid, recipient)
+        );
+        CREATE INDEX IF NOT EXISTS idx_bounces_outbox ON bounces(outbox_id);
+        CREATE TABLE IF NOT EXISTS bounce_scans (
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL DEFAULT 'INBOX',
+            uid INTEGER NOT NULL,
+            checked_at INTEGER NOT NULL,
+            PRIMARY KEY (account_email, folder, uid)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Headers of sent mail, one row per recipient, for response-rate insights.
+/// UIDs are the Sent folder's, not INBOX's.
+fn track_sent_messages(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS sent_messages (
+            account_email TEXT NOT NULL,
+            uid INTEGER NOT NULL,
+            recipient TEXT NOT NULL,
+            subject_encrypted TEXT NOT NULL,
+            sent_at INTEGER NOT NULL,
+            PRIMARY KEY (account_email, uid, recipient)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Focus mode's verdict on each new message: `reason` is set for urgent
+/// mail, `held` for mail kept back until `reviewed_at`.
+fn track_focus_decisions(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS focus_decisions (
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL DEFAULT 'INBOX',
+            uid INTEGER NOT NULL,
+            reason TEXT,
+            held INTEGER NOT NULL DEFAULT 0,
+            decided_at INTEGER NOT NULL,
+            reviewed_at INTEGER,
+            PRIMARY KEY (account_email, folder, uid)
+        );
+        CREATE INDEX IF NOT EXISTS idx_focus_decisions_held
+            ON focus_decisions(held, reviewed_at);
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Text of a message's PDF and Office attachments, which text search also
+/// matches. `messages.attachments_indexed` marks messages already read, so
+/// one without documents is not fetched again.
+fn track_attachment_text(conn: &Connection) -> Result<()> {
+    add_column_if_missing(
+        conn,
+        "messages",
+        "attachments_indexed",
+        "attachments_indexed INTEGER NOT NULL DEFAULT 0",
+    )?;
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS attachment_text (
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL DEFAULT 'INBOX',
+            uid INTEGER NOT NULL,
+            text_encrypted TEXT NOT NULL,
+            documents INTEGER NOT NULL,
+            indexed_at INTEGER NOT NULL,
+            PRIMARY KEY (account_email, folder, uid)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Images HTML bodies refer to by `cid:` URL, kept so a message fetched
+/// once renders with them offline.
+fn track_inline_images(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS inline_images (
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL DEFAULT 'INBOX',
+            uid INTEGER NOT NULL,
+            content_id TEXT NOT NULL,
+            content_type TEXT NOT NULL,
+            data_encrypted TEXT NOT NULL,
+            PRIMARY KEY (account_email, folder, uid, content_id)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+/// `RFC822.SIZE` of each message, recorded as messages are synced. Rows
+/// cached before have none until they are fetched again.
+fn track_message_size(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "messages", "size_bytes", "size_bytes INTEGER")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_messages_account_size \
+         ON messages(account_email, size_bytes)",
+        (),
+    )?;
+    Ok(())
+}
+
+/// Each message's DMARC verdict from the receiving server, and the DMARC
+/// and BIMI records last read for each sender domain.
+fn track_brand_checks(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "messages", "dmarc_aligned", "dmarc_aligned INTEGER")?;
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS brand_checks (
+            domain TEXT PRIMARY KEY,
+            dmarc_policy TEXT,
+            bimi_logo TEXT,
+            bimi_authority TEXT,
+            checked_at INTEGER NOT NULL
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Each message's conversation, and the threads muted per account.
+fn track_threads(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "messages", "thread_id", "thread_id TEXT")?;
+    conn.execute_batch(
+        r#"
+        CREATE INDEX IF NOT EXISTS idx_messages_account_thread
+            ON messages(account_email, thread_id);
+        CREATE TABLE IF NOT EXISTS muted_threads (
+            account_email TEXT NOT NULL,
+            thread_id TEXT NOT NULL,
+            muted_at INTEGER NOT NULL,
+            PRIMARY KEY (account_email, thread_id)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Attachments an outbox entry links to instead of carrying, as a JSON
+/// array of uploads.
+fn track_outbox_uploads(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "outbox", "uploads_json", "uploads_json TEXT")
+}
+
+/// Which route each sent outbox entry left by: SMTP, or a provider's HTTP
+/// API when SMTP was blocked.
+fn track_delivery_paths(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "outbox", "delivery_path", "delivery_path TEXT")
+}
+
+/// Reply headers an outbox entry is sent with: `In-Reply-To`, the
+/// space-separated `References`, and `Auto-Submitted` for mail the client
+/// sends on its own.
+fn track_outbox_headers(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "outbox", "in_reply_to", "in_reply_to TEXT")?;
+    add_column_if_missing(conn, "outbox", "reference_ids", "reference_ids TEXT")?;
+    add_column_if_missing(conn, "outbox", "auto_submitted", "auto_submitted TEXT")
+}
+
+/// The local address book. The full vCard is kept encrypted so properties
+/// this client does not edit survive; name and first address are copied
+/// out for search. `href` and `etag` link a contact to its CardDAV card,
+/// and it counts as edited here while `updated_at` is past `synced_at`.
+fn track_contacts(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS contacts (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            uid TEXT NOT NULL UNIQUE,
+            full_name TEXT NOT NULL,
+            primary_email TEXT,
+            card_encrypted TEXT NOT NULL,
+            source TEXT NOT NULL,
+            sync_enabled INTEGER NOT NULL DEFAULT 0,
+            href TEXT,
+            etag TEXT,
+            updated_at INTEGER NOT NULL,
+            synced_at INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS idx_contacts_email ON contacts(primary_email);
+        CREATE INDEX IF NOT EXISTS idx_contacts_href ON contacts(href);
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Events from invitations in mail, one row per event (the latest version
+/// wins), and which candidate messages were already read for them. Events
+/// outlive the message that carried them.
+fn track_calendar_events(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS calendar_events (
+            account_email TEXT NOT NULL,
+            event_uid TEXT NOT NULL,
+            uid INTEGER NOT NULL,
+            sequence INTEGER NOT NULL DEFAULT 0,
+            summary_encrypted TEXT,
+            location_encrypted TEXT,
+            starts_at INTEGER NOT NULL,
+            ends_at INTEGER NOT NULL,
+            all_day INTEGER NOT NULL DEFAULT 0,
+            status TEXT NOT NULL,
+            busy INTEGER NOT NULL DEFAULT 1,
+            PRIMARY KEY (account_email, event_uid)
+        );
+        CREATE INDEX IF NOT EXISTS idx_calendar_events_time
+            ON calendar_events(account_email, starts_at);
+        CREATE TABLE IF NOT EXISTS calendar_scans (
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL DEFAULT 'INBOX',
+            uid INTEGER NOT NULL,
+            checked_at INTEGER NOT NULL,
+            PRIMARY KEY (account_email, folder, uid)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Which version of each event the CalDAV calendar holds, and a log of
+/// every push attempt.
+fn track_caldav_pushes(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS caldav_pushes (
+            account_email TEXT NOT NULL,
+            event_uid TEXT NOT NULL,
+            sequence INTEGER NOT NULL,
+            etag TEXT,
+            pushed_at INTEGER NOT NULL,
+            PRIMARY KEY (account_email, event_uid)
+        );
+        CREATE TABLE IF NOT EXISTS caldav_sync_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            account_email TEXT NOT NULL,
+            event_uid TEXT NOT NULL,
+            sequence INTEGER NOT NULL,
+            status TEXT NOT NULL,
+            detail TEXT,
+            logged_at INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_caldav_sync_log_time ON caldav_sync_log(logged_at);
+        "#,
+    )?;
+    Ok(())
+}
+
+/// New mail scanned for one-time codes, with the code or reset link found
+/// (`kind` is NULL when there was none). Once expired, the code and link
+/// are erased but the row stays, so a resync does not scan the message
+/// again.
+fn track_one_time_codes(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS one_time_codes (
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL DEFAULT 'INBOX',
+            uid INTEGER NOT NULL,
+            kind TEXT,
+            code_encrypted TEXT,
+            link_encrypted TEXT,
+            received_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            PRIMARY KEY (account_email, folder, uid)
+        );
+        CREATE INDEX IF NOT EXISTS idx_one_time_codes_expiry ON one_time_codes(expires_at);
+        "#,
+    )?;
+    Ok(())
+}
+
+/// The imported breach list, one row per breached domain.
+fn track_breaches(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS breaches (
+            domain TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            breach_date TEXT,
+            added_date TEXT,
+            data_classes TEXT NOT NULL DEFAULT '[]'
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Messages scored at or above the quarantine threshold. A released row is
+/// kept so the next refresh does not quarantine the message again.
+fn track_quarantine(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        r#"
+        CREATE TABLE IF NOT EXISTS quarantine (
+            account_email TEXT NOT NULL,
+            folder TEXT NOT NULL DEFAULT 'INBOX',
+            uid INTEGER NOT NULL,
+            score REAL NOT NULL,
+            reasons TEXT NOT NULL DEFAULT '[]',
+            quarantined_at INTEGER NOT NULL,
+            released_at INTEGER,
+            PRIMARY KEY (account_email, folder, uid)
+        );
+        "#,
+    )?;
+    Ok(())
+}
+
+/// Tables of data derived from a cached message, keyed by account, folder,
+/// and UID rather than the message row.
+const UID_KEYED_TABLES: [&str; 14] = [
+    "message_links",
+    "message_ocr",
+    "message_attachments",
+    "message_folders",
+    "collection_items",
+    "review_queue",
+    "pending_flag_changes",
+    "bounce_scans",
+    "focus_decisions",
+    "quarantine",
+    "one_time_codes",
+    "calendar_scans",
+    "attachment_text",
+    "inline_images",
+];
+
+/// The card stored for a contact, or `None` if it no longer parses.
+fn decrypt_card(cipher: &Cipher, encrypted: &str) -> Result<Option<Card>> {
+    let text = cipher.decrypt_string(encrypted)?;
+    Ok(vcard::parse_cards(&text).into_iter().next())
+}
+
+/// Matches `deleted_messages` rows still valid on the server: those from a
+/// folder whose UIDVALIDITY is unknown or unchanged since they were cached.
+const CURRENT_UID_VALIDITY: &str = r#"
+    NOT EXISTS (
+        SELECT 1 FROM folder_state fs
+        WHERE fs.account_email = deleted_messages.account_email
+          AND fs.folder = deleted_messages.folder
+          AND fs.uid_validity IS NOT deleted_messages.uidvalidity
+    )"#;
+
+/// Matches messages (`m`) that are not held in quarantine.
+const NOT_QUARANTINED: &str = r#"
+    NOT EXISTS (
+        SELECT 1 FROM quarantine q
+        WHERE q.account_email = m.account_email AND q.folder = m.folder AND q.uid = m.uid
+          AND q.released_at IS NULL
+    )"#;
+
+/// SQL for whether the message in `row` (`NEW` or `OLD`) is unread. Flags
+/// are stored space-separated, e.g. `seen flagged`.
+fn unread_expr(row: &str) -> String {
+    format!(
+        "(instr(' ' || replace(lower(COALESCE({row}.flags, '')), '\\', '') || ' ', ' seen ') = 0)"
+    )
+}
+
+/// 1 when the row's flags include `flag` (lowercase, without the
+/// backslash), else 0.
+fn flag_set_expr(row: &str, flag: &str) -> String {
+    format!(
+        "(instr(' ' || replace(lower(COALESCE({row}.flags, '')), '\\', '') || ' ', ' {flag} ') > 0)"
+    )
+}
+
+/// Creates `sender_aggregates`, the per-sender counts behind the sender group
+/// headers, and the triggers that keep it in step with `messages`. A new
+/// table is filled from the cached messages once. Tombstoned messages are
+/// not counted. The triggers are recreated on every start so they follow
+/// changes here.
+fn track_sender_aggregates(conn: &Connection) -> Result<()> {
+    let existed = column_exists(conn, "sender_aggregates", "account_email")?;
+    let (new_unread, old_unread) = (unread_expr("NEW"), unread_expr("OLD"));
+    // Removing a message recounts the sender's latest message from the
+    // account/sender index rather than the whole mailbox.
+    let remove_old = format!(
+        r#"
+            UPDATE sender_aggregates SET
+                message_count = message_count - 1,
+                unread_count = unread_count - {old_unread},
+                (latest_date_ts, latest_date, sender_display) = (
+                    SELECT date_ts, date, sender_display FROM messages
+                    WHERE account_email = OLD.account_email AND sender_email = OLD.sender_email
+                        AND tombstoned_at IS NULL
+                    ORDER BY COALESCE(date_ts, 0) DESC, id DESC
+                    LIMIT 1
+                )
+            WHERE account_email = OLD.account_email AND sender_email = OLD.sender_email;
+            DELETE FROM sender_aggregates
+            WHERE account_email = OLD.account_email AND sender_email = OLD.sender_email
+                AND message_count <= 0;"#
+    );
+    let add_new = format!(
+        r#"
+            INSERT INTO sender_aggregates (
+                account_email, sender_email, sender_display, message_count, unread_count,
+                latest_date_ts, latest_date
+            )
+            VALUES (
+                NEW.account_email, NEW.sender_email, NEW.sender_display, 1, {new_unread},
+                NEW.date_ts, NEW.date
+            )
+            ON CONFLICT(account_email, sender_email) DO UPDATE SET
+                message_count = message_count + 1,
+                unread_count = unread_count + excluded.unread_count,
+                sender_display = CASE
+                    WHEN COALESCE(excluded.latest_date_ts, 0) >= COALESCE(latest_date_ts, 0)
+                    THEN COALESCE(excluded.sender_display, sender_display)
+                    ELSE sender_display END,
+                latest_date = CASE
+                    WHEN COALESCE(excluded.latest_date_ts, 0) >= COALESCE(latest_date_ts, 0)
+                    THEN excluded.latest_date
+                    ELSE latest_date END,
+                latest_date_ts = CASE
+                    WHEN COALESCE(excluded.latest_date_ts, 0) >= COALESCE(latest_date_ts, 0)
+                    THEN excluded.latest_date_ts
+                    ELSE latest_date_ts END;"#
+    );
+    conn.execute_batch(&format!(
+        r#"
+        CREATE TABLE IF NOT EXISTS sender_aggregates (
+            account_email TEXT NOT NULL,
+            sender_email TEXT NOT NULL,
+            sender_display TEXT,
+            message_count INTEGER NOT NULL DEFAULT 0,
+            unread_count INTEGER NOT NULL DEFAULT 0,
+            latest_date_ts INTEGER,
+            latest_date TEXT,
+            PRIMARY KEY(account_email, sender_email)
+        );
+
+        DROP TRIGGER IF EXISTS sender_aggregates_insert;
+        CREATE TRIGGER sender_aggregates_insert
+        AFTER INSERT ON messages
+        WHEN NEW.tombstoned_at IS NULL
+        BEGIN {add_new}
+        END;
+
+        DROP TRIGGER IF EXISTS sender_aggregates_delete;
+        CREATE TRIGGER sender_aggregates_delete
+        AFTER DELETE ON messages
+        WHEN OLD.tombstoned_at IS NULL
+        BEGIN {remove_old}
+        END;
+
+        DROP TRIGGER IF EXISTS sender_aggregates_tombstone;
+        CREATE TRIGGER sender_aggregates_tombstone
+        AFTER UPDATE OF tombstoned_at ON messages
+        WHEN OLD.tombstoned_at IS NULL AND NEW.tombstoned_at IS NOT NULL
+        BEGIN {remove_old}
+        END;
+
+        DROP TRIGGER IF EXISTS sender_aggregates_revive;
+        CREATE TRIGGER sender_aggregates_revive
+        AFTER UPDATE OF tombstoned_at ON messages
+        WHEN OLD.tombstoned_at IS NOT NULL AND NEW.tombstoned_at IS NULL
+        BEGIN {add_new}
+        END;
+
+        DROP TRIGGER IF EXISTS sender_aggregates_move;
+        CREATE TRIGGER sender_aggregates_move
+        AFTER UPDATE OF account_email, sender_email, sender_display, date_ts ON messages
+        WHEN (OLD.account_email IS NOT NEW.account_email
+            OR OLD.sender_email IS NOT NEW.sender_email
+            OR OLD.sender_display IS NOT NEW.sender_display
+            OR OLD.date_ts IS NOT NEW.date_ts)
+            AND OLD.tombstoned_at IS NULL AND NEW.tombstoned_at IS NULL
+        BEGIN {remove_old} {add_new}
+        END;
+
+        -- Flag changes only move the unread count.
+        DROP TRIGGER IF EXISTS sender_aggregates_flags;
+        CREATE TRIGGER sender_aggregates_flags
+        AFTER UPDATE OF flags ON messages
+        WHEN OLD.account_email IS NEW.account_email
+            AND OLD.sender_email IS NEW.sender_email
+            AND OLD.sender_display IS NEW.sender_display
+            AND OLD.date_ts IS NEW.date_ts
+            AND OLD.tombstoned_at IS NULL AND NEW.tombstoned_at IS NULL
+            AND {old_unread} != {new_unread}
+        BEGIN
+            UPDATE sender_aggregates
+            SET unread_count = unread_count + {new_unread} - {old_unread}
+            WHERE account_email = NEW.account_email AND sender_email = NEW.sender_email;
+        END;
+        "#
+    ))?;
+    if !existed {
+        rebuild_sender_aggregates(conn)?;
+    }
+    Ok(())
+}
+
+fn rebuild_sender_aggregates(conn: &Connection) -> Result<()> {
+    let unread = unread_expr("m");
+    conn.execute_batch(&format!(
+        r#"
+        DELETE FROM sender_aggregates;
+        INSERT INTO sender_aggregates (
+            account_email, sender_email, sender_display, message_count, unread_count,
+            latest_date_ts, latest_date
+        )
+        SELECT m.account_email, m.sender_email, latest.sender_display, COUNT(*),
+            SUM({unread}), latest.date_ts, latest.date
+        FROM messages m
+        JOIN messages latest ON latest.id = (
+            SELECT id FROM messages
+            WHERE account_email = m.account_email AND sender_email = m.sender_email
+                AND tombstoned_at IS NULL
+            ORDER BY COALESCE(date_ts, 0) DESC, id DESC
+            LIMIT 1
+        )
+        WHERE m.tombstoned_at IS NULL
+        GROUP BY m.account_email, m.sender_email;
+        "#
+    ))?;
+    Ok(())
+}
+
+/// Rebuilds a `sender_status` table from before rules had a scope. The key
+/// changes to `(sender_email, scope)`, which SQLite cannot alter in place;
+/// existing rules all become global.
+fn scope_sender_status(conn: &Connection) -> Result<()> {
+    if column_exists(conn, "sender_status", "scope")? {
+        return Ok(());
+    }
+    conn.execute_batch(
+        r#"
+        BEGIN;
+        CREATE TABLE sender_status_scoped (
+            sender_email TEXT NOT NULL,
+            scope TEXT NOT NULL DEFAULT 'global',
+            status TEXT NOT NULL,
+            updated_at INTEGER NOT NULL,
+            sender_kind TEXT,
+            typical_tags TEXT,
+            typical_priority TEXT,
+            profile_samples INTEGER NOT NULL DEFAULT 0,
+            profile_confidence REAL,
+            profile_updated_at INTEGER,
+            PRIMARY KEY(sender_email, scope)
+        );
+        INSERT INTO sender_status_scoped (
+            sender_email, scope, status, updated_at, sender_kind, typical_tags,
+            typical_priority, profile_samples, profile_confidence, profile_updated_at
+        )
+        SELECT sender_email, 'global', status, updated_at, sender_kind, typical_tags,
+               typical_priority, profile_samples, profile_confidence, profile_updated_at
+        FROM sender_status;
+        DROP TABLE sender_status;
+        ALTER TABLE sender_status_scoped RENAME TO sender_status;
+        COMMIT;
+        "#,
+    )?;
+    Ok(())
+}
+
+impl Storage {
+    pub fn initialize(handle: &AppHandle) -> Result<Self> {
+        Self::open(&data_dir::resolve(handle)?)
+    }
+
+    /// Opens the database and master key in `data_dir`, creating both on
+    /// first use and bringing the schema up to date. This is how tools
+    /// outside the app get at the store. Backups and integrity checks use
+    /// the first directory opened in the process.
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        fs::create_dir_all(data_dir)?;
+        let db_path = data_dir.join(data_dir::DATABASE_FILE);
+        DB_PATH.set(db_path.clone()).ok();
+
+        let connection = open_database(&db_path)?;
+
+        let master_key = load_or_create_master_key(data_dir)?;
+        let cipher = Cipher::from_bytes(master_key)?;
+        rekey_spam_tokens(&connection, &cipher)?;
+
+        Ok(Self {
+            conn: Arc::new(parking_lot::Mutex::new(connection)),
+            cipher: Arc::new(cipher),
+            decrypted: Arc::new(DecryptCache::new(decrypt_cache::DEFAULT_CAPACITY_BYTES)),
+            pending_writes: Arc::new(parking_lot::Mutex::new(HashMap::new())),
+        })
+    }
+
+    fn apply_migrations(conn: &mut Connection) -> Result<()> {
+        set_aside_uid_keyed_tables(conn)?;
+        conn.execute_batch(
             r#"
             CREATE TABLE IF NOT EXISTS messages (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 account_email TEXT NOT NULL,
-                uid TEXT NOT NULL,
+                folder TEXT NOT NULL DEFAULT 'INBOX',
+                uid INTEGER NOT NULL,
                 sender_email TEXT NOT NULL,
                 sender_display TEXT,
                 subject_encrypted TEXT,
@@ -310,7 +2100,7 @@ impl Storage {
                 flags TEXT,
                 created_at INTEGER NOT NULL,
                 updated_at INTEGER NOT NULL,
-                UNIQUE(account_email, uid)
+                UNIQUE(account_email, folder, uid)
             );
 
             CREATE INDEX IF NOT EXISTS idx_messages_account_sender
@@ -319,7 +2109,8 @@ impl Storage {
             CREATE TABLE IF NOT EXISTS deleted_messages (
                 id INTEGER PRIMARY KEY AUTOINCREMENT,
                 account_email TEXT NOT NULL,
-                uid TEXT NOT NULL,
+                folder TEXT NOT NULL DEFAULT 'INBOX',
+                uid INTEGER NOT NULL,
                 sender_email TEXT NOT NULL,
                 sender_display TEXT,
                 subject_encrypted TEXT,
@@ -332,16 +2123,18 @@ impl Storage {
                 deleted_at INTEGER NOT NULL,
                 remote_deleted_at INTEGER,
                 remote_error TEXT,
-                UNIQUE(account_email, uid)
+                UNIQUE(account_email, folder, uid)
             );
 
             CREATE INDEX IF NOT EXISTS idx_deleted_messages_account
                 ON deleted_messages(account_email, deleted_at DESC);
 
             CREATE TABLE IF NOT EXISTS sender_status (
-                sender_email TEXT PRIMARY KEY,
+                sender_email TEXT NOT NULL,
+                scope TEXT NOT NULL DEFAULT 'global',
                 status TEXT NOT NULL,
-                updated_at INTEGER NOT NULL
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY(sender_email, scope)
             );
 
             CREATE TABLE IF NOT EXISTS analysis_results (
@@ -383,6 +2176,260 @@ impl Storage {
                 key TEXT PRIMARY KEY,
                 value TEXT
             );
+
+            CREATE TABLE IF NOT EXISTS review_queue (
+                message_id INTEGER PRIMARY KEY,
+                account_email TEXT NOT NULL,
+                folder TEXT NOT NULL DEFAULT 'INBOX',
+                uid INTEGER NOT NULL,
+                reason TEXT NOT NULL,
+                confidence REAL,
+                queued_at INTEGER NOT NULL,
+                FOREIGN KEY(message_id) REFERENCES messages(id) ON DELETE CASCADE
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_review_queue_account
+                ON review_queue(account_email, queued_at);
+
+            CREATE TABLE IF NOT EXISTS analysis_examples (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                account_email TEXT NOT NULL,
+                sender_email TEXT NOT NULL,
+                sender_domain TEXT,
+                subject_encrypted TEXT,
+                snippet_encrypted TEXT,
+                categories TEXT,
+                priority TEXT,
+                sentiment TEXT,
+                created_at INTEGER NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_analysis_examples_sender
+                ON analysis_examples(sender_email, created_at DESC);
+
+            CREATE TABLE IF NOT EXISTS topics (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                account_email TEXT NOT NULL,
+                label_encrypted TEXT NOT NULL,
+                keywords_encrypted TEXT NOT NULL,
+                message_count INTEGER NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_topics_account
+                ON topics(account_email);
+
+            CREATE TABLE IF NOT EXISTS topic_assignments (
+                message_id INTEGER PRIMARY KEY,
+                topic_id INTEGER NOT NULL,
+                similarity REAL NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_topic_assignments_topic
+                ON topic_assignments(topic_id, similarity DESC);
+
+            CREATE TABLE IF NOT EXISTS relationship_stats (
+                contact_email TEXT PRIMARY KEY,
+                message_count INTEGER NOT NULL,
+                thread_count INTEGER NOT NULL,
+                answered_count INTEGER NOT NULL,
+                first_contact_at INTEGER,
+                last_contact_at INTEGER,
+                user_reply_secs INTEGER,
+                contact_reply_secs INTEGER,
+                average_sentiment REAL,
+                recent_sentiment REAL,
+                sentiment_trend TEXT NOT NULL,
+                computed_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS reply_suggestions (
+                message_id INTEGER PRIMARY KEY,
+                model_id TEXT,
+                suggestions_encrypted TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY(message_id) REFERENCES messages(id) ON DELETE CASCADE
+            );
+
+            CREATE TABLE IF NOT EXISTS templates (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                subject_encrypted TEXT NOT NULL,
+                body_encrypted TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                account_email TEXT,
+                action TEXT NOT NULL,
+                target TEXT,
+                detail_json TEXT,
+                created_at INTEGER NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_audit_log_account
+                ON audit_log(account_email, created_at DESC);
+
+            CREATE TABLE IF NOT EXISTS autoreply_log (
+                account_email TEXT NOT NULL,
+                sender_email TEXT NOT NULL,
+                activation INTEGER NOT NULL,
+                uid TEXT NOT NULL,
+                replied_at INTEGER NOT NULL,
+                PRIMARY KEY(account_email, sender_email, activation)
+            );
+
+            CREATE TABLE IF NOT EXISTS mail_merges (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                account_email TEXT NOT NULL,
+                subject_template_encrypted TEXT NOT NULL,
+                body_template_encrypted TEXT NOT NULL,
+                rate_per_minute INTEGER NOT NULL,
+                created_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                account_email TEXT NOT NULL,
+                merge_id INTEGER,
+                recipient TEXT NOT NULL,
+                subject_encrypted TEXT NOT NULL,
+                body_encrypted TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'queued',
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                scheduled_at INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS account_migrations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                source_email TEXT NOT NULL,
+                target_email TEXT NOT NULL,
+                source_folder TEXT NOT NULL,
+                target_folder TEXT NOT NULL,
+                last_uid INTEGER NOT NULL DEFAULT 0,
+                total INTEGER NOT NULL DEFAULT 0,
+                copied INTEGER NOT NULL DEFAULT 0,
+                failed INTEGER NOT NULL DEFAULT 0,
+                status TEXT NOT NULL,
+                error TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS message_ocr (
+                account_email TEXT NOT NULL,
+                folder TEXT NOT NULL DEFAULT 'INBOX',
+                uid INTEGER NOT NULL,
+                text_encrypted TEXT NOT NULL,
+                image_count INTEGER NOT NULL,
+                engine TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY(account_email, folder, uid)
+            );
+
+            CREATE TABLE IF NOT EXISTS message_attachments (
+                account_email TEXT NOT NULL,
+                folder TEXT NOT NULL DEFAULT 'INBOX',
+                uid INTEGER NOT NULL,
+                position INTEGER NOT NULL,
+                filename_encrypted TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                risks TEXT NOT NULL DEFAULT '[]',
+                PRIMARY KEY(account_email, folder, uid, position)
+            );
+
+            CREATE TABLE IF NOT EXISTS message_links (
+                account_email TEXT NOT NULL,
+                folder TEXT NOT NULL DEFAULT 'INBOX',
+                uid INTEGER NOT NULL,
+                position INTEGER NOT NULL,
+                url_encrypted TEXT NOT NULL,
+                domain TEXT NOT NULL,
+                display_text_encrypted TEXT,
+                mismatch INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY(account_email, folder, uid, position)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_message_links_domain ON message_links(domain);
+
+            CREATE TABLE IF NOT EXISTS link_blocklist (
+                domain TEXT PRIMARY KEY,
+                added_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS image_allowlist (
+                sender_email TEXT PRIMARY KEY,
+                added_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS collections (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS collection_items (
+                collection_id INTEGER NOT NULL,
+                account_email TEXT NOT NULL,
+                folder TEXT NOT NULL DEFAULT 'INBOX',
+                uid INTEGER NOT NULL,
+                added_at INTEGER NOT NULL,
+                PRIMARY KEY(collection_id, account_email, folder, uid)
+            );
+
+            CREATE TABLE IF NOT EXISTS message_folders (
+                account_email TEXT NOT NULL,
+                folder TEXT NOT NULL DEFAULT 'INBOX',
+                uid INTEGER NOT NULL,
+                target_folder TEXT NOT NULL,
+                copied_at INTEGER NOT NULL,
+                PRIMARY KEY(account_email, folder, uid, target_folder)
+            );
+
+            CREATE TABLE IF NOT EXISTS outbox_attachments (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                outbox_id INTEGER NOT NULL,
+                filename TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                data_encrypted TEXT NOT NULL,
+                FOREIGN KEY(outbox_id) REFERENCES outbox(id) ON DELETE CASCADE
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_outbox_status
+                ON outbox(status, scheduled_at);
+
+            CREATE INDEX IF NOT EXISTS idx_outbox_merge
+                ON outbox(merge_id);
+
+            CREATE TABLE IF NOT EXISTS llm_benchmarks (
+                model_id TEXT PRIMARY KEY,
+                samples INTEGER NOT NULL,
+                tokens_per_second REAL NOT NULL,
+                time_to_first_token_ms REAL NOT NULL,
+                json_valid_rate REAL NOT NULL,
+                process_rss_bytes INTEGER,
+                completed_at INTEGER NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS spam_tokens (
+                token_hmac TEXT PRIMARY KEY,
+                spam_count INTEGER NOT NULL DEFAULT 0,
+                ham_count INTEGER NOT NULL DEFAULT 0
+            );
+
+            CREATE TABLE IF NOT EXISTS spam_training (
+                document_key TEXT PRIMARY KEY,
+                label TEXT NOT NULL,
+                tokens_encrypted TEXT NOT NULL,
+                trained_at INTEGER NOT NULL
+            );
             "#,
         )?;
 
@@ -397,257 +2444,279 @@ impl Storage {
             ("validation_confidence", "validation_confidence REAL"),
             ("validation_notes", "validation_notes TEXT"),
             ("validated_at", "validated_at INTEGER"),
+            ("analysis_fingerprint", "analysis_fingerprint TEXT"),
+            ("stale", "stale INTEGER NOT NULL DEFAULT 0"),
         ];
 
         for (column, declaration) in analysis_columns {
             add_column_if_missing(conn, "analysis_results", column, declaration)?;
         }
 
+        let sender_columns = [
+            ("sender_kind", "sender_kind TEXT"),
+            ("typical_tags", "typical_tags TEXT"),
+            ("typical_priority", "typical_priority TEXT"),
+            ("profile_samples", "profile_samples INTEGER NOT NULL DEFAULT 0"),
+            ("profile_confidence", "profile_confidence REAL"),
+            ("profile_updated_at", "profile_updated_at INTEGER"),
+        ];
+
+        for (column, declaration) in sender_columns {
+            add_column_if_missing(conn, "sender_status", column, declaration)?;
+        }
+        scope_sender_status(conn)?;
+        add_column_if_missing(
+            conn,
+            "sender_status",
+            "origin",
+            "origin TEXT NOT NULL DEFAULT 'manual'",
+        )?;
+        add_column_if_missing(conn, "sender_status", "origin_ref", "origin_ref TEXT")?;
+        add_column_if_missing(conn, "sender_status", "expires_at", "expires_at INTEGER")?;
+        add_column_if_missing(
+            conn,
+            "sender_status",
+            "previous_status",
+            "previous_status TEXT",
+        )?;
+
+        add_column_if_missing(conn, "messages", "spam_score", "spam_score REAL")?;
+        add_column_if_missing(
+            conn,
+            "messages",
+            "trackers_detected",
+            "trackers_detected TEXT",
+        )?;
+        if !column_exists(conn, "messages", "date_ts")? {
+            conn.execute("ALTER TABLE messages ADD COLUMN date_ts INTEGER", ())?;
+            backfill_message_timestamps(conn)?;
+        }
+        integer_message_uids(conn)?;
+        encrypted_topics(conn)?;
+        // The stats are recomputed on every lookup, so rows from before reply
+        // latency was split by direction are simply dropped.
+        if column_exists(conn, "relationship_stats", "reply_round_trip_secs")? {
+            conn.execute_batch(
+                r#"
+                DROP TABLE relationship_stats;
+                CREATE TABLE relationship_stats (
+                    contact_email TEXT PRIMARY KEY,
+                    message_count INTEGER NOT NULL,
+                    thread_count INTEGER NOT NULL,
+                    answered_count INTEGER NOT NULL,
+                    first_contact_at INTEGER,
+                    last_contact_at INTEGER,
+                    user_reply_secs INTEGER,
+                    contact_reply_secs INTEGER,
+                    average_sentiment REAL,
+                    recent_sentiment REAL,
+                    sentiment_trend TEXT NOT NULL,
+                    computed_at INTEGER NOT NULL
+                );
+                "#,
+            )?;
+        }
+        track_message_identity(conn)?;
+        track_tombstones(conn)?;
+        track_flag_changes(conn)?;
+        track_block_notes(conn)?;
+        track_bounces(conn)?;
+        track_sent_messages(conn)?;
+        track_focus_decisions(conn)?;
+        track_quarantine(conn)?;
+        track_breaches(conn)?;
+        track_one_time_codes(conn)?;
+        track_calendar_events(conn)?;
+        track_caldav_pushes(conn)?;
+        track_contacts(conn)?;
+        track_attachment_text(conn)?;
+        track_inline_images(conn)?;
+        track_message_size(conn)?;
+        track_brand_checks(conn)?;
+        track_threads(conn)?;
+        track_outbox_uploads(conn)?;
+        track_delivery_paths(conn)?;
+        track_outbox_headers(conn)?;
+        add_column_if_missing(
+            conn,
+            "account_migrations",
+            "failed_uids",
+            "failed_uids TEXT NOT NULL DEFAULT ''",
+        )?;
+        // Set for accounts that sign in with OAuth rather than a password.
+        add_column_if_missing(conn, "accounts", "oauth_client_id", "oauth_client_id TEXT")?;
+        add_column_if_missing(
+            conn,
+            "llm_benchmarks",
+            "model_rss_bytes",
+            "model_rss_bytes INTEGER",
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_messages_account_date \
+             ON messages(account_email, date_ts, id)",
+            (),
+        )?;
+        restore_uid_keyed_tables(conn)?;
+        track_sender_aggregates(conn)?;
+
         Ok(())
     }
 
-    pub async fn upsert_messages(&self, rows: Vec<MessageInsert>) -> Result<()> {
+    /// Stores messages as the server reports them. Returns the flag
+    /// conflicts found against edits still waiting to reach the server.
+    pub async fn upsert_messages(&self, rows: Vec<MessageInsert>) -> Result<Vec<FlagConflict>> {
         if rows.is_empty() {
-            return Ok(());
+            return Ok(Vec::new());
         }
 
         let conn = self.conn.clone();
         let cipher = self.cipher.clone();
-        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
-            let now = Utc::now().timestamp();
+        let decrypted = self.decrypted.clone();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<FlagConflict>> {
             let mut conn = conn.lock();
             let tx = conn.transaction()?;
-            {
-                let mut stmt = tx.prepare(
-                    r#"
-                    INSERT INTO messages (
-                        account_email,
-                        uid,
-                        sender_email,
-                        sender_display,
-                        subject_encrypted,
-                        date,
-                        snippet_encrypted,
-                        body_encrypted,
-                        flags,
-                        created_at,
-                        updated_at
-                    ) VALUES (?,?,?,?,?,?,?,?,?,?,?)
-                    ON CONFLICT(account_email, uid) DO UPDATE SET
-                        sender_email=excluded.sender_email,
-                        sender_display=excluded.sender_display,
-                        subject_encrypted=excluded.subject_encrypted,
-                        date=excluded.date,
-                        snippet_encrypted=excluded.snippet_encrypted,
-                        body_encrypted=excluded.body_encrypted,
-                        flags=excluded.flags,
-                        updated_at=excluded.updated_at
-                    "#,
-                )?;
+            let conflicts = write_messages(&tx, &cipher, &decrypted, rows)?;
+            tx.commit()?;
+            Ok(conflicts)
+        })
+        .await
+        .map_err(map_join_error)?;
 
-                for row in rows {
-                    let subject_enc = cipher.encrypt_string(&row.subject)?;
-                    let snippet_enc = row
-                        .snippet
-                        .as_ref()
-                        .map(|value| cipher.encrypt_string(value))
-                        .transpose()?;
-                    let body_enc = row
-                        .body
-                        .as_ref()
-                        .map(|value| cipher.encrypt_bytes(value))
-                        .transpose()?;
+        join_result
+    }
 
-                    stmt.execute(params![
-                        row.account_email,
-                        row.uid,
-                        row.sender_email.to_lowercase(),
-                        row.sender_display,
-                        subject_enc,
-                        row.date,
-                        snippet_enc,
-                        body_enc,
-                        row.flags,
-                        now,
-                        now,
-                    ])?;
-                }
-            }
+    /// Stores a sync batch's messages and their analyses in one transaction,
+    /// so the batch costs a single commit. Returns the flag conflicts found
+    /// against edits still waiting to reach the server.
+    pub async fn upsert_batch(
+        &self,
+        messages: Vec<MessageInsert>,
+        analyses: Vec<AnalysisInsert>,
+    ) -> Result<Vec<FlagConflict>> {
+        if messages.is_empty() && analyses.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let decrypted = self.decrypted.clone();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<FlagConflict>> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let conflicts = write_messages(&tx, &cipher, &decrypted, messages)?;
+            write_analysis(&tx, analyses)?;
             tx.commit()?;
-            Ok(())
+            Ok(conflicts)
         })
         .await
         .map_err(map_join_error)?;
 
-        join_result?;
+        join_result
+    }
 
+    /// Queues a small update. Queued writes are flushed together after
+    /// [`WRITE_DEBOUNCE`], or at once when the queue fills up.
+    pub async fn queue_write(&self, write: PendingWrite) -> Result<()> {
+        let (first, full) = {
+            let mut pending = self.pending_writes.lock();
+            pending.insert(write.key(), write);
+            (pending.len() == 1, pending.len() >= WRITE_QUEUE_FLUSH_AT)
+        };
+        if full {
+            self.flush_pending_writes().await?;
+        } else if first {
+            let storage = self.clone();
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(WRITE_DEBOUNCE).await;
+                if let Err(err) = storage.flush_pending_writes().await {
+                    warn!(?err, "failed to flush queued writes");
+                }
+            });
+        }
         Ok(())
     }
 
+    /// Writes everything queued in one transaction and returns how many
+    /// writes it applied. On failure the writes are queued again unless a
+    /// newer write for the same message replaced them meanwhile.
+    pub async fn flush_pending_writes(&self) -> Result<usize> {
+        let writes = std::mem::take(&mut *self.pending_writes.lock());
+        if writes.is_empty() {
+            return Ok(0);
+        }
+
+        let conn = self.conn.clone();
+        let batch = writes.values().cloned().collect::<Vec<_>>();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            {
+                let mut set_flags = tx.prepare(
+                    "UPDATE messages SET flags = ? \
+                     WHERE account_email = ? AND folder = ? AND uid = ?",
+                )?;
+                let mut set_lifecycle = tx.prepare(
+                    r#"
+                    UPDATE analysis_results
+                    SET metadata_json = json_set(COALESCE(metadata_json, '{}'), '$.lifecycle', ?)
+                    WHERE message_id = (
+                        SELECT id FROM messages WHERE account_email = ? AND folder = ? AND uid = ?
+                    )
+                    "#,
+                )?;
+                for write in &batch {
+                    match write {
+                        PendingWrite::Flags {
+                            account_email,
+                            folder,
+                            uid,
+                            flags,
+                        } => set_flags.execute(params![flags, account_email, folder, uid])?,
+                        PendingWrite::Lifecycle {
+                            account_email,
+                            folder,
+                            uid,
+                            lifecycle,
+                        } => {
+                            set_lifecycle.execute(params![lifecycle, account_email, folder, uid])?
+                        }
+                    };
+                }
+            }
+            tx.commit()?;
+            Ok(batch.len())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        if join_result.is_err() {
+            let mut pending = self.pending_writes.lock();
+            for (key, write) in writes {
+                pending.entry(key).or_insert(write);
+            }
+        }
+        join_result
+    }
+
     pub async fn archive_message(
         &self,
         account_email: &str,
-        uid: &str,
+        folder: &str,
+        uid: u32,
     ) -> Result<Option<DeletedMessageRow>> {
         let conn = self.conn.clone();
         let cipher = self.cipher.clone();
         let account = account_email.to_owned();
-        let uid_value = uid.to_owned();
+        let folder = folder.to_owned();
 
         let join_result =
             tokio::task::spawn_blocking(move || -> Result<Option<DeletedMessageRow>> {
                 let now = Utc::now().timestamp();
                 let mut conn = conn.lock();
                 let tx = conn.transaction()?;
-
-                let source = {
-                    let mut stmt = tx.prepare(
-                        r#"
-                        SELECT 
-                            m.uid,
-                            m.sender_email,
-                            m.sender_display,
-                            m.subject_encrypted,
-                            m.snippet_encrypted,
-                            m.date,
-                            m.flags,
-                            ar.summary,
-                            ar.sentiment,
-                            ar.categories
-                        FROM messages m
-                        LEFT JOIN analysis_results ar ON ar.message_id = m.id
-                        WHERE m.account_email = ? AND m.uid = ?
-                        "#,
-                    )?;
-
-                    stmt.query_row(params![account, uid_value], |row| {
-                        Ok((
-                            row.get::<_, String>(0)?,
-                            row.get::<_, String>(1)?,
-                            row.get::<_, Option<String>>(2)?,
-                            row.get::<_, String>(3)?,
-                            row.get::<_, Option<String>>(4)?,
-                            row.get::<_, Option<String>>(5)?,
-                            row.get::<_, Option<String>>(6)?,
-                            row.get::<_, Option<String>>(7)?,
-                            row.get::<_, Option<String>>(8)?,
-                            row.get::<_, Option<String>>(9)?,
-                        ))
-                    })
-                    .optional()?
-                };
-
-                let Some((
-                    uid,
-                    sender_email,
-                    sender_display,
-                    subject_encrypted,
-                    snippet_encrypted,
-                    date,
-                    _flags,
-                    analysis_summary,
-                    analysis_sentiment,
-                    analysis_categories,
-                )) = source
-                else {
-                    return Ok(None);
-                };
-
-                let categories_json = analysis_categories;
-
-                let account_key = account.clone();
-                let uid_insert = uid.clone();
-                let sender_email_insert = sender_email.clone();
-                let sender_display_insert = sender_display.clone();
-                let subject_insert = subject_encrypted.clone();
-                let date_insert = date.clone();
-                let snippet_insert = snippet_encrypted.clone();
-                let summary_insert = analysis_summary.clone();
-                let sentiment_insert = analysis_sentiment.clone();
-                let categories_insert = categories_json.clone();
-
-                tx.execute(
-                    r#"
-                    INSERT INTO deleted_messages (
-                        account_email,
-                        uid,
-                        sender_email,
-                        sender_display,
-                        subject_encrypted,
-                        date,
-                        snippet_encrypted,
-                        flags,
-                        analysis_summary,
-                        analysis_sentiment,
-                        analysis_categories,
-                        deleted_at,
-                        remote_deleted_at,
-                        remote_error
-                    ) VALUES (?, ?, ?, ?, ?, ?, ?, NULL, ?, ?, ?, ?, NULL, NULL)
-                    ON CONFLICT(account_email, uid) DO UPDATE SET
-                        sender_email = excluded.sender_email,
-                        sender_display = excluded.sender_display,
-                        subject_encrypted = excluded.subject_encrypted,
-                        date = excluded.date,
-                        snippet_encrypted = excluded.snippet_encrypted,
-                        flags = excluded.flags,
-                        analysis_summary = excluded.analysis_summary,
-                        analysis_sentiment = excluded.analysis_sentiment,
-                        analysis_categories = excluded.analysis_categories,
-                        deleted_at = excluded.deleted_at,
-                        remote_deleted_at = NULL,
-                        remote_error = NULL
-                    "#,
-                    params![
-                        account_key,
-                        uid_insert,
-                        sender_email_insert,
-                        sender_display_insert,
-                        subject_insert,
-                        date_insert,
-                        snippet_insert,
-                        summary_insert,
-                        sentiment_insert,
-                        categories_insert,
-                        now,
-                    ],
-                )?;
-
-                tx.execute(
-                    "DELETE FROM messages WHERE account_email = ? AND uid = ?",
-                    params![account, uid],
-                )?;
-
+                let archived = archive_cached(&tx, &cipher, &account, &folder, uid, now)?;
                 tx.commit()?;
-
-                let subject = cipher.decrypt_string(&subject_encrypted)?;
-                let snippet = snippet_encrypted
-                    .as_ref()
-                    .map(|value| cipher.decrypt_string(value))
-                    .transpose()?;
-                let categories: Vec<String> = categories_json
-                    .as_ref()
-                    .map(|value| {
-                        serde_json::from_str(value)
-                            .map_err(|err| StorageError::Serialization(err.to_string()))
-                    })
-                    .transpose()?
-                    .unwrap_or_default();
-
-                Ok(Some(DeletedMessageRow {
-                    uid,
-                    sender_email,
-                    sender_display,
-                    subject,
-                    snippet,
-                    date,
-                    analysis_summary,
-                    analysis_sentiment,
-                    analysis_categories: categories,
-                    deleted_at: now,
-                    remote_deleted_at: None,
-                    remote_error: None,
-                }))
+                Ok(archived)
             })
             .await
             .map_err(map_join_error)?;
@@ -655,32 +2724,66 @@ impl Storage {
         join_result
     }
 
+    /// Archives several cached messages in one transaction: either all of
+    /// them move to the deleted list or none do. UIDs not in the cache are
+    /// skipped.
+    pub async fn archive_messages(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uids: &[u32],
+    ) -> Result<Vec<DeletedMessageRow>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let uids = uids.to_vec();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<DeletedMessageRow>> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut archived = Vec::with_capacity(uids.len());
+            for &uid in &uids {
+                archived.extend(archive_cached(&tx, &cipher, &account, &folder, uid, now)?);
+            }
+            tx.commit()?;
+            Ok(archived)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
     pub async fn message_uids_for_sender(
         &self,
         account_email: &str,
+        folder: &str,
         sender_email: &str,
-    ) -> Result<Vec<String>> {
+    ) -> Result<Vec<u32>> {
         let conn = self.conn.clone();
         let account = account_email.to_owned();
+        let folder = folder.to_owned();
         let sender = sender_email.to_owned();
 
-        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<String>> {
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<u32>> {
             let conn = conn.lock();
             let mut stmt = conn.prepare(
                 r#"
                 SELECT uid
                 FROM messages
-                WHERE account_email = ? AND sender_email = ?
+                WHERE account_email = ? AND folder = ? AND sender_email = ?
+                  AND tombstoned_at IS NULL
                 ORDER BY date DESC, id DESC
                 "#,
             )?;
 
-            let mut rows = stmt.query(params![account, sender])?;
+            let mut rows = stmt.query(params![account, folder, sender])?;
             let mut uids = Vec::new();
 
             while let Some(row) = rows.next()? {
-                let uid: String = row.get(0)?;
-                uids.push(uid);
+                uids.push(row.get(0)?);
             }
 
             Ok(uids)
@@ -691,16 +2794,54 @@ impl Storage {
         join_result
     }
 
-    pub async fn mark_deleted_remote(
+    /// Senders of the cached messages among `uids`, keyed by UID. UIDs that
+    /// are not cached (or were removed) are missing from the map.
+    pub async fn message_senders(
         &self,
         account_email: &str,
-        uid: &str,
+        folder: &str,
+        uids: &[u32],
+    ) -> Result<HashMap<u32, String>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let uids = uids.to_vec();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<HashMap<u32, String>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                    SELECT sender_email FROM messages
+                    WHERE account_email = ? AND folder = ? AND uid = ? AND tombstoned_at IS NULL
+                    "#,
+            )?;
+            let mut senders = HashMap::new();
+            for uid in uids {
+                let sender = stmt
+                    .query_row(params![account, folder, uid], |row| row.get::<_, String>(0))
+                    .optional()?;
+                if let Some(sender) = sender {
+                    senders.insert(uid, sender);
+                }
+            }
+            Ok(senders)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Records the outcome of the server-side delete of the deleted message
+    /// with key `message_key`.
+    pub async fn mark_deleted_remote(
+        &self,
+        message_key: &str,
         remote_deleted_at: Option<i64>,
         remote_error: Option<String>,
     ) -> Result<()> {
         let conn = self.conn.clone();
-        let account = account_email.to_owned();
-        let message_uid = uid.to_owned();
+        let key = message_key.to_owned();
 
         let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
             let conn = conn.lock();
@@ -708,14 +2849,9 @@ impl Storage {
                 r#"
                     UPDATE deleted_messages
                     SET remote_deleted_at = ?, remote_error = ?
-                    WHERE account_email = ? AND uid = ?
+                    WHERE message_key = ?
                     "#,
-                params![
-                    remote_deleted_at,
-                    remote_error.as_deref(),
-                    account,
-                    message_uid,
-                ],
+                params![remote_deleted_at, remote_error.as_deref(), key],
             )?;
             Ok(())
         })
@@ -725,35 +2861,178 @@ impl Storage {
         join_result
     }
 
+    /// Records the UIDVALIDITY the server reported for a folder. The first
+    /// value seen is adopted by the rows already cached. When it changes,
+    /// the cached UIDs no longer name the same messages: those rows and what
+    /// was derived from them are dropped, and their pending remote deletes
+    /// are abandoned. Returns how many cached messages were dropped.
+    pub async fn record_uid_validity(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid_validity: u32,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+        let decrypted = self.decrypted.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let current = i64::from(uid_validity);
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let known: Option<i64> = tx
+                .query_row(
+                    "SELECT uid_validity FROM folder_state WHERE account_email = ? AND folder = ?",
+                    params![account, folder],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if known == Some(current) {
+                return Ok(0);
+            }
+
+            let mut purged = 0;
+            if known.is_none() {
+                for table in ["messages", "deleted_messages"] {
+                    tx.execute(
+                        &format!(
+                            "UPDATE {table} SET uidvalidity = ?3 \
+                             WHERE account_email = ?1 AND folder = ?2 AND uidvalidity IS NULL"
+                        ),
+                        params![account, folder, current],
+                    )?;
+                }
+            } else {
+                const STALE: &str = "SELECT id FROM messages \
+                     WHERE account_email = ?1 AND folder = ?2 AND uidvalidity IS NOT ?3";
+                tx.execute(
+                    &format!("DELETE FROM analysis_results WHERE message_id IN ({STALE})"),
+                    params![account, folder, current],
+                )?;
+                for table in UID_KEYED_TABLES {
+                    tx.execute(
+                        &format!(
+                            "DELETE FROM {table} WHERE account_email = ?1 AND folder = ?2 \
+                             AND uid IN (SELECT uid FROM messages \
+                             WHERE account_email = ?1 AND folder = ?2 AND uidvalidity IS NOT ?3)"
+                        ),
+                        params![account, folder, current],
+                    )?;
+                }
+                purged = tx.execute(
+                    "DELETE FROM messages \
+                     WHERE account_email = ?1 AND folder = ?2 AND uidvalidity IS NOT ?3",
+                    params![account, folder, current],
+                )?;
+                tx.execute(
+                    r#"
+                    UPDATE deleted_messages
+                    SET remote_error = 'The folder was reset on the server (UIDVALIDITY changed)'
+                    WHERE account_email = ?1 AND folder = ?2 AND uidvalidity IS NOT ?3
+                      AND remote_deleted_at IS NULL
+                    "#,
+                    params![account, folder, current],
+                )?;
+            }
+
+            tx.execute(
+                r#"
+                INSERT INTO folder_state (account_email, folder, uid_validity, updated_at)
+                VALUES (?, ?, ?, ?)
+                ON CONFLICT(account_email, folder) DO UPDATE SET
+                    uid_validity = excluded.uid_validity,
+                    updated_at = excluded.updated_at
+                "#,
+                params![account, folder, current, Utc::now().timestamp()],
+            )?;
+            tx.commit()?;
+            if purged > 0 {
+                decrypted.clear();
+            }
+            Ok(purged)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Resolves a key from [`MessageRow::message_key`] or
+    /// [`DeletedMessageRow::message_key`].
+    pub async fn message_by_key(&self, message_key: &str) -> Result<Option<MessageIdentity>> {
+        let conn = self.conn.clone();
+        let key = message_key.to_owned();
+
+        let join_result =
+            tokio::task::spawn_blocking(move || -> Result<Option<MessageIdentity>> {
+                let conn = conn.lock();
+                let identity = conn
+                    .query_row(
+                        r#"
+                    SELECT message_key, account_email, folder, uidvalidity, uid, 0 AS deleted
+                    FROM messages WHERE message_key = ?1
+                    UNION ALL
+                    SELECT message_key, account_email, folder, uidvalidity, uid, 1 AS deleted
+                    FROM deleted_messages WHERE message_key = ?1
+                    ORDER BY deleted
+                    LIMIT 1
+                    "#,
+                        params![key],
+                        |row| {
+                            Ok(MessageIdentity {
+                                message_key: row.get(0)?,
+                                account_email: row.get(1)?,
+                                folder: row.get(2)?,
+                                uid_validity: uid_validity_from(row.get(3)?),
+                                uid: row.get(4)?,
+                                deleted: row.get::<_, i64>(5)? != 0,
+                            })
+                        },
+                    )
+                    .optional()?;
+                Ok(identity)
+            })
+            .await
+            .map_err(map_join_error)?;
+
+        join_result
+    }
+
     pub async fn pending_remote_deletes(
         &self,
         account_email: &str,
         limit: usize,
-    ) -> Result<Vec<(String, Option<String>)>> {
+    ) -> Result<Vec<PendingRemoteDelete>> {
         let conn = self.conn.clone();
         let account = account_email.to_owned();
         let capped_limit = limit.min(500);
 
-        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<(String, Option<String>)>> {
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<PendingRemoteDelete>> {
             let conn = conn.lock();
-            let mut stmt = conn.prepare(
+            let mut stmt = conn.prepare(&format!(
                 r#"
-                SELECT uid, remote_error
+                SELECT message_key, folder, uidvalidity, uid, remote_error
                 FROM deleted_messages
                 WHERE account_email = ?
                   AND remote_deleted_at IS NULL
+                  AND {CURRENT_UID_VALIDITY}
                 ORDER BY deleted_at ASC
                 LIMIT ?
-                "#,
-            )?;
+                "#
+            ))?;
 
             let mut rows = stmt.query(params![account, capped_limit as i64])?;
             let mut pending = Vec::new();
 
             while let Some(row) = rows.next()? {
-                let uid: String = row.get(0)?;
-                let remote_error: Option<String> = row.get(1)?;
-                pending.push((uid, remote_error));
+                pending.push(PendingRemoteDelete {
+                    message_key: row.get(0)?,
+                    folder: row.get(1)?,
+                    uid_validity: uid_validity_from(row.get(2)?),
+                    uid: row.get(3)?,
+                    remote_error: row.get(4)?,
+                });
             }
 
             Ok(pending)
@@ -770,14 +3049,15 @@ impl Storage {
 
         let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
             let conn = conn.lock();
-            let mut stmt = conn.prepare(
+            let mut stmt = conn.prepare(&format!(
                 r#"
                 SELECT COUNT(*)
                 FROM deleted_messages
                 WHERE account_email = ?
                   AND remote_deleted_at IS NULL
-                "#,
-            )?;
+                  AND {CURRENT_UID_VALIDITY}
+                "#
+            ))?;
 
             let count: i64 = stmt.query_row(params![account], |row| row.get(0))?;
             Ok(count as usize)
@@ -816,7 +3096,10 @@ impl Storage {
                         analysis_categories,
                         deleted_at,
                         remote_deleted_at,
-                        remote_error
+                        remote_error,
+                        message_key,
+                        folder,
+                        uidvalidity
                     FROM deleted_messages
                     WHERE account_email = ?
                     ORDER BY deleted_at DESC
@@ -847,7 +3130,10 @@ impl Storage {
                     .unwrap_or_default();
 
                 results.push(DeletedMessageRow {
+                    folder: row.get(13)?,
                     uid: row.get(0)?,
+                    message_key: row.get(12)?,
+                    uid_validity: uid_validity_from(row.get(14)?),
                     sender_email: row.get(1)?,
                     sender_display: row.get(2)?,
                     subject,
@@ -873,12 +3159,13 @@ impl Storage {
     pub async fn restore_deleted_message(
         &self,
         account_email: &str,
-        uid: &str,
+        folder: &str,
+        uid: u32,
     ) -> Result<Option<DeletedMessageRow>> {
         let conn = self.conn.clone();
         let cipher = self.cipher.clone();
         let account = account_email.to_owned();
-        let uid_value = uid.to_owned();
+        let folder = folder.to_owned();
 
         let join_result =
             tokio::task::spawn_blocking(move || -> Result<Option<DeletedMessageRow>> {
@@ -900,13 +3187,14 @@ impl Storage {
                         analysis_categories,
                         deleted_at,
                         remote_deleted_at,
-                        remote_error
+                        remote_error,
+                        uidvalidity
                     FROM deleted_messages
-                    WHERE account_email = ? AND uid = ?
+                    WHERE account_email = ? AND folder = ? AND uid = ?
                     "#,
                     )?;
 
-                    stmt.query_row(params![account, uid_value.clone()], |row| {
+                    stmt.query_row(params![account, folder, uid], |row| {
                         Ok((
                             row.get::<_, String>(0)?,
                             row.get::<_, Option<String>>(1)?,
@@ -920,6 +3208,7 @@ impl Storage {
                             row.get::<_, i64>(9)?,
                             row.get::<_, Option<i64>>(10)?,
                             row.get::<_, Option<String>>(11)?,
+                            row.get::<_, Option<i64>>(12)?,
                         ))
                     })
                     .optional()?
@@ -938,6 +3227,7 @@ impl Storage {
                     deleted_at,
                     remote_deleted_at,
                     remote_error,
+                    uid_validity,
                 )) = source
                 else {
                     return Ok(None);
@@ -949,6 +3239,7 @@ impl Storage {
                     r#"
                 INSERT INTO messages (
                     account_email,
+                    folder,
                     uid,
                     sender_email,
                     sender_display,
@@ -959,20 +3250,23 @@ impl Storage {
                     flags,
                     created_at,
                     updated_at
-                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                ON CONFLICT(account_email, uid) DO UPDATE SET
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(account_email, folder, uid) DO UPDATE SET
                     sender_email = excluded.sender_email,
                     sender_display = excluded.sender_display,
                     subject_encrypted = excluded.subject_encrypted,
                     date = excluded.date,
                     snippet_encrypted = excluded.snippet_encrypted,
-                    body_encrypted = excluded.body_encrypted,
+                    body_encrypted = COALESCE(excluded.body_encrypted, messages.body_encrypted),
                     flags = excluded.flags,
-                    updated_at = excluded.updated_at
+                    updated_at = excluded.updated_at,
+                    tombstoned_at = NULL,
+                    tombstone_reason = NULL
                 "#,
                     params![
                         account.clone(),
-                        uid_value.clone(),
+                        folder.clone(),
+                        uid,
                         sender_email.clone(),
                         sender_display.clone(),
                         subject_encrypted.clone(),
@@ -985,10 +3279,25 @@ impl Storage {
                     ],
                 )?;
 
-                let message_id: i64 = tx.query_row(
-                    "SELECT id FROM messages WHERE account_email = ? AND uid = ?",
-                    params![account.clone(), uid_value.clone()],
-                    |row| row.get(0),
+                tx.execute(
+                    r#"
+                UPDATE messages SET (uidvalidity, message_key) = (
+                    SELECT d.uidvalidity, d.message_key
+                    FROM deleted_messages d
+                    WHERE d.account_email = messages.account_email
+                      AND d.folder = messages.folder
+                      AND d.uid = messages.uid
+                )
+                WHERE account_email = ? AND folder = ? AND uid = ?
+                "#,
+                    params![account.clone(), folder.clone(), uid],
+                )?;
+
+                let (message_id, message_key): (i64, String) = tx.query_row(
+                    "SELECT id, message_key FROM messages \
+                     WHERE account_email = ? AND folder = ? AND uid = ?",
+                    params![account.clone(), folder.clone(), uid],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
                 )?;
 
                 let categories_text = analysis_categories
@@ -1013,20 +3322,7 @@ impl Storage {
                     validation_notes,
                     validated_at
                 ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                ON CONFLICT(message_id) DO UPDATE SET
-                    summary = excluded.summary,
-                    sentiment = excluded.sentiment,
-                    categories = excluded.categories,
-                    metadata_json = excluded.metadata_json,
-                    model_id = excluded.model_id,
-                    analyzed = excluded.analyzed,
-                    analyzed_at = excluded.analyzed_at,
-                    analysis_confidence = excluded.analysis_confidence,
-                    validator_model_id = excluded.validator_model_id,
-                    validation_status = excluded.validation_status,
-                    validation_confidence = excluded.validation_confidence,
-                    validation_notes = excluded.validation_notes,
-                    validated_at = excluded.validated_at
+                ON CONFLICT(message_id) DO NOTHING
                 "#,
                     params![
                         message_id,
@@ -1047,8 +3343,9 @@ impl Storage {
                 )?;
 
                 tx.execute(
-                    "DELETE FROM deleted_messages WHERE account_email = ? AND uid = ?",
-                    params![account.clone(), uid_value],
+                    "DELETE FROM deleted_messages \
+                     WHERE account_email = ? AND folder = ? AND uid = ?",
+                    params![account.clone(), folder.clone(), uid],
                 )?;
 
                 tx.commit()?;
@@ -1063,7 +3360,10 @@ impl Storage {
                     .unwrap_or_default();
 
                 Ok(Some(DeletedMessageRow {
-                    uid: uid_value,
+                    folder,
+                    uid,
+                    message_key,
+                    uid_validity: uid_validity_from(uid_validity),
                     sender_email,
                     sender_display,
                     subject,
@@ -1083,17 +3383,45 @@ impl Storage {
         join_result
     }
 
-    pub async fn purge_deleted_message(&self, account_email: &str, uid: &str) -> Result<bool> {
+    pub async fn purge_deleted_message(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<bool> {
         let conn = self.conn.clone();
         let account = account_email.to_owned();
-        let uid_value = uid.to_owned();
+        let folder = folder.to_owned();
 
         let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
-            let conn = conn.lock();
-            let changes = conn.execute(
-                "DELETE FROM deleted_messages WHERE account_email = ? AND uid = ?",
-                params![account, uid_value],
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            // The tombstone archiving left in `messages` goes with it.
+            const TOMBSTONE: &str = "SELECT id FROM messages \
+                 WHERE account_email = ?1 AND folder = ?2 AND uid = ?3 \
+                 AND tombstone_reason = 'local'";
+            tx.execute(
+                &format!("DELETE FROM analysis_results WHERE message_id IN ({TOMBSTONE})"),
+                params![account, folder, uid],
+            )?;
+            for table in UID_KEYED_TABLES {
+                tx.execute(
+                    &format!(
+                        "DELETE FROM {table} WHERE account_email = ?1 AND folder = ?2 \
+                         AND uid = ?3 AND EXISTS ({TOMBSTONE})"
+                    ),
+                    params![account, folder, uid],
+                )?;
+            }
+            tx.execute(
+                &format!("DELETE FROM messages WHERE id IN ({TOMBSTONE})"),
+                params![account, folder, uid],
+            )?;
+            let changes = tx.execute(
+                "DELETE FROM deleted_messages WHERE account_email = ? AND folder = ? AND uid = ?",
+                params![account, folder, uid],
             )?;
+            tx.commit()?;
             Ok(changes > 0)
         })
         .await
@@ -1102,31 +3430,104 @@ impl Storage {
         join_result
     }
 
+    /// Remembers why `sender_email` was blocked in an account, replacing an
+    /// earlier note.
+    pub async fn set_block_note(
+        &self,
+        sender_email: &str,
+        account_email: &str,
+        reason: Option<&str>,
+        origin_uid: Option<u32>,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let sender = sender_email.to_lowercase();
+        let account = account_email.to_lowercase();
+        let reason = reason.map(str::to_owned);
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let reason_encrypted = reason
+                .as_ref()
+                .map(|value| cipher.encrypt_string(value))
+                .transpose()?;
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                INSERT INTO sender_block_notes (
+                    sender_email, account_email, reason_encrypted, origin_uid, created_at
+                ) VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT(sender_email, account_email) DO UPDATE SET
+                    reason_encrypted = excluded.reason_encrypted,
+                    origin_uid = excluded.origin_uid,
+                    created_at = excluded.created_at
+                "#,
+                params![
+                    sender,
+                    account,
+                    reason_encrypted,
+                    origin_uid,
+                    Utc::now().timestamp()
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Forgets the block notes for a sender in one account, or in every
+    /// account when `account_email` is `None`.
+    pub async fn clear_block_notes(
+        &self,
+        sender_email: &str,
+        account_email: Option<&str>,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+        let sender = sender_email.to_lowercase();
+        let account = account_email.map(str::to_lowercase);
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = conn.lock();
+            let removed = conn.execute(
+                "DELETE FROM sender_block_notes \
+                 WHERE sender_email = ?1 AND (?2 IS NULL OR account_email = ?2)",
+                params![sender, account],
+            )?;
+            Ok(removed)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
     pub async fn grouped_messages_for_account(
         &self,
         account_email: &str,
+        fields: &FieldMask,
     ) -> Result<Vec<SenderGroup>> {
         let conn = self.conn.clone();
         let cipher = self.cipher.clone();
+        let decrypted = self.decrypted.clone();
         let account = account_email.to_owned();
+        let fields = fields.clone();
         let result = tokio::task::spawn_blocking(move || -> Result<Vec<SenderGroup>> {
             let conn = conn.lock();
-            let mut stmt = conn.prepare(
+            let mut stmt = conn.prepare(&format!(
                 r#"
-          SELECT m.id, m.uid, m.sender_email, m.sender_display, m.subject_encrypted, m.date,
-              m.snippet_encrypted, m.body_encrypted IS NOT NULL AS body_cached, m.flags,
-              COALESCE(ss.status, 'neutral'),
-              ar.summary, ar.sentiment, ar.categories,
-              ar.metadata_json, ar.model_id, COALESCE(ar.analyzed, 0), ar.analyzed_at,
-              ar.analysis_confidence, ar.validator_model_id, ar.validation_status,
-              ar.validation_confidence, ar.validation_notes, ar.validated_at
-          FROM messages m
-          LEFT JOIN sender_status ss ON ss.sender_email = m.sender_email
-          LEFT JOIN analysis_results ar ON ar.message_id = m.id
-                WHERE m.account_email = ?
+                SELECT {MESSAGE_ROW_COLUMNS},
+                    ss.sender_kind, ss.typical_tags, ss.typical_priority,
+                    COALESCE(ss.profile_samples, 0), ss.profile_confidence, ss.profile_updated_at,
+                    bn.reason_encrypted, bn.origin_uid, bn.created_at
+                {MESSAGE_ROW_JOINS}
+                LEFT JOIN sender_block_notes bn
+                    ON bn.sender_email = m.sender_email AND bn.account_email = m.account_email
+                WHERE m.account_email = ? AND m.tombstoned_at IS NULL AND {NOT_QUARANTINED}
                 ORDER BY m.sender_email, m.date DESC, m.id DESC
-                "#,
-            )?;
+                "#
+            ))?;
             let mut rows_iter = stmt.query(params![account])?;
 
             let mut groups: Vec<SenderGroup> = Vec::new();
@@ -1141,87 +3542,226 @@ impl Storage {
                     current_sender = Some(sender_email.clone());
                     let status_value: String = row.get(9)?;
                     let status = SenderStatus::from_str(&status_value);
+                    let profile = sender_profile_from_row(row, MESSAGE_ROW_WIDTH)?;
+                    let block_note = match status {
+                        SenderStatus::Blocked => {
+                            block_note_from_row(row, MESSAGE_ROW_WIDTH + 6, &cipher)?
+                        }
+                        _ => None,
+                    };
                     groups.push(SenderGroup {
                         sender_email: sender_email.clone(),
                         sender_display: display.clone(),
                         status,
+                        profile,
+                        block_note,
                         messages: Vec::new(),
                     });
                 }
 
                 let group = groups.last_mut().expect("group should exist after push");
+                group
+                    .messages
+                    .push(message_row_from_row(row, &cipher, &decrypted, &fields)?);
+            }
 
-                let subject_enc: String = row.get(4)?;
-                let subject = cipher.decrypt_string(&subject_enc)?;
-                let snippet_enc: Option<String> = row.get(6)?;
-                let snippet = snippet_enc
-                    .as_ref()
-                    .map(|value| cipher.decrypt_string(value))
-                    .transpose()?;
+            Ok(groups)
+        })
+        .await
+        .map_err(map_join_error)?;
 
-                let body_cached: bool = row.get::<_, i64>(7)? != 0;
-                let flags: Option<String> = row.get(8)?;
+        result
+    }
 
-                let categories_json: Option<String> = row.get(12)?;
-                let analysis_categories = categories_json
-                    .as_ref()
-                    .map(|value| {
-                        serde_json::from_str::<Vec<String>>(value)
-                            .map_err(|err| StorageError::Serialization(err.to_string()))
-                    })
-                    .transpose()?
-                    .unwrap_or_default();
+    /// Sender group headers for the account, in the order
+    /// [`Storage::grouped_messages_for_account`] lists the groups. Reads one
+    /// row per sender instead of every message.
+    pub async fn sender_group_headers(
+        &self,
+        account_email: &str,
+    ) -> Result<Vec<SenderGroupHeader>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let result = tokio::task::spawn_blocking(move || -> Result<Vec<SenderGroupHeader>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT g.sender_email, g.sender_display, g.message_count, g.unread_count,
+                    g.latest_date,
+                    COALESCE(sa.status, NULLIF(ss.status, 'neutral'), sd.status, 'neutral'),
+                    ss.sender_kind, ss.typical_tags, ss.typical_priority,
+                    COALESCE(ss.profile_samples, 0), ss.profile_confidence, ss.profile_updated_at,
+                    bn.reason_encrypted, bn.origin_uid, bn.created_at
+                FROM sender_aggregates g
+                LEFT JOIN sender_status ss
+                    ON ss.sender_email = g.sender_email AND ss.scope = 'global'
+                LEFT JOIN sender_status sa
+                    ON sa.sender_email = g.sender_email AND sa.scope = g.account_email
+                LEFT JOIN sender_status sd
+                    ON sd.sender_email = substr(g.sender_email, instr(g.sender_email, '@'))
+                    AND sd.scope = 'global'
+                LEFT JOIN sender_block_notes bn
+                    ON bn.sender_email = g.sender_email AND bn.account_email = g.account_email
+                WHERE g.account_email = ?
+                ORDER BY g.sender_email
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account])?;
+            let mut headers = Vec::new();
+            while let Some(row) = rows.next()? {
+                let sender_email: String = row.get(0)?;
+                let status = SenderStatus::from_str(&row.get::<_, String>(5)?);
+                let block_note = match status {
+                    SenderStatus::Blocked => block_note_from_row(row, 12, &cipher)?,
+                    _ => None,
+                };
+                headers.push(SenderGroupHeader {
+                    sender_display: row
+                        .get::<_, Option<String>>(1)?
+                        .unwrap_or_else(|| sender_email.clone()),
+                    sender_email,
+                    status,
+                    profile: sender_profile_from_row(row, 6)?,
+                    block_note,
+                    message_count: row.get(2)?,
+                    unread_count: row.get(3)?,
+                    latest_date: row.get(4)?,
+                });
+            }
+            Ok(headers)
+        })
+        .await
+        .map_err(map_join_error)?;
 
-                let metadata_json: Option<String> = row.get(13)?;
-                let analysis_metadata = metadata_json
-                    .as_ref()
-                    .map(|value| {
-                        serde_json::from_str::<Value>(value)
-                            .map_err(|err| StorageError::Serialization(err.to_string()))
-                    })
-                    .transpose()?;
+        result
+    }
 
-                let analysis_model_id: Option<String> = row.get(14)?;
-                let analysis_analyzed_raw: i64 = row.get(15)?;
-                let analysis_analyzed = analysis_analyzed_raw != 0;
-                let analysis_analyzed_at: Option<i64> = row.get(16)?;
-                let analysis_confidence: Option<f64> = row.get(17)?;
-                let analysis_validator_model_id: Option<String> = row.get(18)?;
-                let analysis_validation_status: Option<String> = row.get(19)?;
-                let analysis_validation_confidence: Option<f64> = row.get(20)?;
-                let analysis_validation_notes: Option<String> = row.get(21)?;
-                let analysis_validated_at: Option<i64> = row.get(22)?;
-
-                let message = MessageRow {
-                    id: row.get(0)?,
-                    uid: row.get(1)?,
-                    sender_email: sender_email.clone(),
-                    sender_display: display.clone(),
-                    subject,
-                    date: row.get(5)?,
-                    snippet,
-                    flags,
-                    status: group.status.clone(),
-                    analysis_summary: row.get(10)?,
-                    analysis_sentiment: row.get(11)?,
-                    analysis_categories,
-                    analysis_metadata,
-                    analysis_model_id,
-                    analysis_analyzed,
-                    analysis_analyzed_at,
-                    analysis_confidence,
-                    analysis_validator_model_id,
-                    analysis_validation_status,
-                    analysis_validation_confidence,
-                    analysis_validation_notes,
-                    analysis_validated_at,
-                    body_cached,
+    /// Noise scores for the account's senders, from what the user did with
+    /// their cached mail and with the sender profile from analysis.
+    pub async fn sender_noise(&self, account_email: &str) -> Result<HashMap<String, NoiseScore>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let result = tokio::task::spawn_blocking(move || -> Result<HashMap<String, NoiseScore>> {
+            let conn = conn.lock();
+            let (seen, answered, flagged) = (
+                flag_set_expr("m", "seen"),
+                flag_set_expr("m", "answered"),
+                flag_set_expr("m", "flagged"),
+            );
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT m.sender_email, COUNT(*), SUM({seen}), SUM({answered}), SUM({flagged}),
+                    COALESCE(MAX(sent.sent_count), 0),
+                    ss.sender_kind, ss.typical_tags, ss.typical_priority,
+                    COALESCE(ss.profile_samples, 0), ss.profile_confidence, ss.profile_updated_at
+                FROM messages m
+                LEFT JOIN (
+                    SELECT lower(recipient) AS recipient, COUNT(*) AS sent_count
+                    FROM sent_messages
+                    WHERE account_email = ?1
+                    GROUP BY lower(recipient)
+                ) sent ON sent.recipient = m.sender_email
+                LEFT JOIN sender_status ss
+                    ON ss.sender_email = m.sender_email AND ss.scope = 'global'
+                WHERE m.account_email = ?1 AND m.tombstoned_at IS NULL
+                GROUP BY m.sender_email
+                "#
+            ))?;
+            let mut rows = stmt.query(params![account])?;
+            let mut scores = HashMap::new();
+            while let Some(row) = rows.next()? {
+                let engagement = SenderEngagement {
+                    message_count: row.get(1)?,
+                    read_count: row.get(2)?,
+                    answered_count: row.get(3)?,
+                    flagged_count: row.get(4)?,
+                    sent_to_count: row.get(5)?,
                 };
-
-                group.messages.push(message);
+                let profile = sender_profile_from_row(row, 6)?;
+                scores.insert(row.get(0)?, noise::score(&engagement, profile.as_ref()));
             }
+            Ok(scores)
+        })
+        .await
+        .map_err(map_join_error)?;
 
-            Ok(groups)
+        result
+    }
+
+    /// Runs a structured query against the account's cached messages and
+    /// returns one page in the plan's order.
+    pub async fn query_messages(
+        &self,
+        account_email: &str,
+        plan: QueryPlan,
+        fields: &FieldMask,
+    ) -> Result<MessagePage> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let decrypted = self.decrypted.clone();
+        let account = account_email.to_owned();
+        let fields = fields.clone();
+        let result = tokio::task::spawn_blocking(move || -> Result<MessagePage> {
+            let conn = conn.lock();
+            let text_cipher = cipher.clone();
+            conn.create_scalar_function(
+                TEXT_MATCH_FUNCTION,
+                3,
+                FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+                move |ctx| {
+                    let needle: String = ctx.get(2)?;
+                    for index in 0..2 {
+                        let encrypted: Option<String> = ctx.get(index)?;
+                        let matched = encrypted
+                            .and_then(|value| text_cipher.decrypt_string(&value).ok())
+                            .is_some_and(|text| text.to_lowercase().contains(&needle));
+                        if matched {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                },
+            )?;
+
+            let sql = format!(
+                r#"
+                SELECT {MESSAGE_ROW_COLUMNS}, {sort_key}
+                {MESSAGE_ROW_JOINS}
+                WHERE m.account_email = ? AND m.tombstoned_at IS NULL AND ({condition})
+                    AND {NOT_QUARANTINED}
+                ORDER BY {order_by}
+                LIMIT ?
+                "#,
+                sort_key = plan.sort_key(),
+                condition = plan.condition,
+                order_by = plan.order_by(),
+            );
+            let mut values = Vec::with_capacity(plan.params.len() + 2);
+            values.push(rusqlite::types::Value::Text(account));
+            values.extend(plan.params.iter().cloned());
+            // One extra row tells whether another page follows.
+            values.push(rusqlite::types::Value::Integer(plan.page_size as i64 + 1));
+
+            let mut stmt = conn.prepare(&sql)?;
+            let mut rows = stmt.query(params_from_iter(values))?;
+            let mut messages = Vec::new();
+            let mut last_key = None;
+            while let Some(row) = rows.next()? {
+                if messages.len() == plan.page_size {
+                    let last_id = messages.last().map_or(0, |message: &MessageRow| message.id);
+                    return Ok(MessagePage {
+                        messages,
+                        next_cursor: last_key.map(|key| plan.cursor_after(key, last_id)),
+                    });
+                }
+                last_key = Some(row.get::<_, rusqlite::types::Value>(MESSAGE_ROW_WIDTH)?);
+                messages.push(message_row_from_row(row, &cipher, &decrypted, &fields)?);
+            }
+            Ok(MessagePage {
+                messages,
+                next_cursor: None,
+            })
         })
         .await
         .map_err(map_join_error)?;
@@ -1242,10 +3782,12 @@ impl Storage {
                 r#"
                 SELECT m.id, m.uid, m.subject_encrypted, m.snippet_encrypted, m.date,
                        m.sender_email, m.sender_display,
-                       ar.analyzed, ar.analyzed_at, ar.model_id, ar.categories, ar.metadata_json
+                       ar.analyzed, ar.analyzed_at, ar.model_id, ar.categories, ar.metadata_json,
+                       ar.validation_status, ar.analysis_fingerprint, COALESCE(ar.stale, 0),
+                       m.folder, m.message_key
                 FROM messages m
                 LEFT JOIN analysis_results ar ON ar.message_id = m.id
-                WHERE m.account_email = ?
+                WHERE m.account_email = ? AND m.tombstoned_at IS NULL
                 ORDER BY m.updated_at DESC, m.id DESC
                 "#,
             )?;
@@ -1255,7 +3797,7 @@ impl Storage {
 
             while let Some(row) = rows.next()? {
                 let message_id: i64 = row.get(0)?;
-                let uid: String = row.get(1)?;
+                let uid: u32 = row.get(1)?;
                 let subject_enc: String = row.get(2)?;
                 let snippet_enc: Option<String> = row.get(3)?;
                 let date: Option<String> = row.get(4)?;
@@ -1267,6 +3809,9 @@ impl Storage {
                 let model_id: Option<String> = row.get(9)?;
                 let categories_json: Option<String> = row.get(10)?;
                 let metadata_json: Option<String> = row.get(11)?;
+                let validation_status: Option<String> = row.get(12)?;
+                let fingerprint: Option<String> = row.get(13)?;
+                let stale: bool = row.get::<_, i64>(14)? != 0;
 
                 let subject = cipher.decrypt_string(&subject_enc)?;
                 let snippet = snippet_enc
@@ -1297,11 +3842,16 @@ impl Storage {
                     model_id,
                     categories,
                     metadata,
+                    validation_status,
+                    fingerprint,
+                    stale,
                 };
 
                 messages.push(MessageForAnalysis {
                     message_id,
+                    message_key: row.get(16)?,
                     account_email: account.clone(),
+                    folder: row.get(15)?,
                     uid,
                     subject,
                     snippet,
@@ -1320,20 +3870,55 @@ impl Storage {
         result
     }
 
-    pub async fn message_count_for_account(&self, account_email: &str) -> Result<usize> {
+    pub async fn analysis_coverage(
+        &self,
+        account_email: &str,
+        active_model_id: Option<&str>,
+    ) -> Result<AnalysisCoverage> {
         let conn = self.conn.clone();
         let account = account_email.to_owned();
-        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+        let active_model = active_model_id.map(|value| value.to_owned());
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<AnalysisCoverage> {
             let conn = conn.lock();
             let mut stmt = conn.prepare(
                 r#"
-                SELECT COUNT(*) FROM messages
-                WHERE account_email = ?
+                SELECT
+                    COUNT(*),
+                    COALESCE(SUM(CASE WHEN COALESCE(ar.analyzed, 0) != 0 THEN 1 ELSE 0 END), 0),
+                    COALESCE(SUM(CASE WHEN ar.validation_status = 'failed' THEN 1 ELSE 0 END), 0),
+                    COALESCE(SUM(CASE
+                        WHEN COALESCE(ar.analyzed, 0) != 0
+                         AND (COALESCE(ar.stale, 0) != 0
+                              OR (?2 IS NOT NULL AND COALESCE(ar.model_id, '') NOT IN (?2, ?3)))
+                        THEN 1 ELSE 0 END), 0)
+                FROM messages m
+                LEFT JOIN analysis_results ar ON ar.message_id = m.id
+                WHERE m.account_email = ?1 AND m.tombstoned_at IS NULL
                 "#,
             )?;
 
-            let count: i64 = stmt.query_row(params![account], |row| row.get(0))?;
-            Ok(count.max(0) as usize)
+            let params = params![account, active_model, SENDER_PROFILE_MODEL_ID];
+            let (total, analyzed, failed, stale) = stmt.query_row(params, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, i64>(3)?,
+                ))
+            })?;
+
+            let total = total.max(0) as usize;
+            let analyzed = analyzed.max(0) as usize;
+
+            Ok(AnalysisCoverage {
+                account_email: account,
+                total,
+                analyzed,
+                unanalyzed: total.saturating_sub(analyzed),
+                failed: failed.max(0) as usize,
+                stale: stale.max(0) as usize,
+            })
         })
         .await
         .map_err(map_join_error)?;
@@ -1341,50 +3926,156 @@ impl Storage {
         join_result
     }
 
-    pub async fn recent_message_summaries(
+    pub async fn mark_analyses_stale(&self, filter: StaleAnalysisFilter) -> Result<usize> {
+        let conn = self.conn.clone();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = conn.lock();
+            let changes = conn.execute(
+                r#"
+                UPDATE analysis_results
+                SET stale = 1
+                WHERE analyzed != 0
+                  AND stale = 0
+                  AND message_id IN (
+                      SELECT id FROM messages
+                      WHERE (?1 IS NULL OR account_email = ?1)
+                        AND (?2 IS NULL OR sender_email = ?2)
+                  )
+                  AND (?3 IS NULL OR model_id = ?3)
+                  AND (?4 IS NULL OR COALESCE(model_id, '') NOT IN (?4, ?6))
+                  AND (?5 IS NULL OR (COALESCE(analysis_fingerprint, '') != ?5
+                                      AND COALESCE(model_id, '') != ?6))
+                "#,
+                params![
+                    filter.account_email,
+                    filter.sender_email.map(|value| value.to_lowercase()),
+                    filter.model_id,
+                    filter.exclude_model_id,
+                    filter.exclude_fingerprint,
+                    SENDER_PROFILE_MODEL_ID,
+                ],
+            )?;
+            Ok(changes)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn refresh_review_queue(
         &self,
         account_email: &str,
-        limit: usize,
-    ) -> Result<Vec<CachedMessageSummary>> {
-        if limit == 0 {
-            return Ok(Vec::new());
-        }
+        confidence_threshold: f64,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let now = Utc::now().timestamp();
+            let conn = conn.lock();
+            let changes = conn.execute(
+                r#"
+                INSERT INTO review_queue (
+                    message_id, account_email, folder, uid, reason, confidence, queued_at
+                )
+                SELECT
+                    m.id,
+                    m.account_email,
+                    m.folder,
+                    m.uid,
+                    CASE WHEN ar.validation_status = 'failed'
+                         THEN 'validation-failed'
+                         ELSE 'low-confidence' END,
+                    ar.analysis_confidence,
+                    ?3
+                FROM messages m
+                JOIN analysis_results ar ON ar.message_id = m.id
+                WHERE m.account_email = ?1
+                  AND m.tombstoned_at IS NULL
+                  AND ar.analyzed != 0
+                  AND COALESCE(ar.validation_status, '') != 'human-verified'
+                  AND (
+                      ar.validation_status = 'failed'
+                      OR (ar.analysis_confidence IS NOT NULL AND ar.analysis_confidence < ?2)
+                  )
+                ON CONFLICT(message_id) DO UPDATE SET
+                    reason = excluded.reason,
+                    confidence = excluded.confidence
+                "#,
+                params![account, confidence_threshold, now],
+            )?;
+            Ok(changes)
+        })
+        .await
+        .map_err(map_join_error)?;
 
+        join_result
+    }
+
+    pub async fn list_review_queue(
+        &self,
+        account_email: &str,
+        limit: usize,
+    ) -> Result<Vec<ReviewQueueItem>> {
         let conn = self.conn.clone();
         let cipher = self.cipher.clone();
         let account = account_email.to_owned();
-        let limit = limit.min(100_000);
+        let limit = limit.clamp(1, 1000) as i64;
 
-        let result = tokio::task::spawn_blocking(move || -> Result<Vec<CachedMessageSummary>> {
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<ReviewQueueItem>> {
             let conn = conn.lock();
             let mut stmt = conn.prepare(
                 r#"
-                SELECT uid, sender_email, sender_display, subject_encrypted, date
-                FROM messages
-                WHERE account_email = ?
-                ORDER BY updated_at DESC, id DESC
+                SELECT rq.uid, m.sender_email, m.sender_display, m.subject_encrypted,
+                       rq.reason, rq.confidence, ar.summary, ar.categories, ar.metadata_json,
+                       rq.queued_at, rq.folder
+                FROM review_queue rq
+                JOIN messages m ON m.id = rq.message_id
+                LEFT JOIN analysis_results ar ON ar.message_id = rq.message_id
+                WHERE rq.account_email = ?
+                ORDER BY rq.confidence IS NULL, rq.confidence ASC, rq.queued_at ASC
                 LIMIT ?
                 "#,
             )?;
 
-            let mut rows = stmt.query(params![account, limit as i64])?;
+            let mut rows = stmt.query(params![account, limit])?;
             let mut items = Vec::new();
 
             while let Some(row) = rows.next()? {
-                let uid: String = row.get(0)?;
-                let sender_email: String = row.get(1)?;
-                let sender_display: Option<String> = row.get(2)?;
                 let subject_enc: String = row.get(3)?;
-                let date: Option<String> = row.get(4)?;
+                let categories_json: Option<String> = row.get(7)?;
+                let metadata_json: Option<String> = row.get(8)?;
 
-                let subject = cipher.decrypt_string(&subject_enc)?;
+                let categories = categories_json
+                    .as_ref()
+                    .map(|value| {
+                        serde_json::from_str::<Vec<String>>(value)
+                            .map_err(|err| StorageError::Serialization(err.to_string()))
+                    })
+                    .transpose()?
+                    .unwrap_or_default();
+                let metadata = metadata_json
+                    .as_ref()
+                    .map(|value| {
+                        serde_json::from_str::<Value>(value)
+                            .map_err(|err| StorageError::Serialization(err.to_string()))
+                    })
+                    .transpose()?;
 
-                items.push(CachedMessageSummary {
-                    uid,
-                    subject,
-                    sender_email,
-                    sender_display,
-                    date,
+                items.push(ReviewQueueItem {
+                    account_email: account.clone(),
+                    folder: row.get(10)?,
+                    uid: row.get(0)?,
+                    sender_email: row.get(1)?,
+                    sender_display: row.get(2)?,
+                    subject: cipher.decrypt_string(&subject_enc)?,
+                    reason: row.get(4)?,
+                    confidence: row.get(5)?,
+                    summary: row.get(6)?,
+                    categories,
+                    metadata,
+                    queued_at: row.get(9)?,
                 });
             }
 
@@ -1393,25 +4084,316 @@ impl Storage {
         .await
         .map_err(map_join_error)?;
 
-        result
+        join_result
     }
 
-    pub async fn latest_uid_for_account(&self, account_email: &str) -> Result<Option<String>> {
+    /// Marks an analysis as human-verified, optionally applying a correction and
+    /// recording it as a few-shot example for future prompts.
+    pub async fn resolve_analysis_review(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+        correction: Option<AnalysisCorrection>,
+    ) -> Result<bool> {
         let conn = self.conn.clone();
         let account = account_email.to_owned();
-        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<String>> {
-            let conn = conn.lock();
-            let mut stmt = conn.prepare(
-                r#"
-                SELECT uid FROM messages
-                WHERE account_email = ?
-                ORDER BY CAST(uid AS INTEGER) DESC, id DESC
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+
+            let message_id: Option<i64> = tx
+                .query_row(
+                    "SELECT id FROM messages WHERE account_email = ? AND folder = ? AND uid = ?",
+                    params![account, folder, uid],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            let Some(message_id) = message_id else {
+                return Ok(false);
+            };
+
+            if let Some(correction) = &correction {
+                let categories_json = correction
+                    .categories
+                    .as_ref()
+                    .map(|values| {
+                        serde_json::to_string(values)
+                            .map_err(|err| StorageError::Serialization(err.to_string()))
+                    })
+                    .transpose()?;
+
+                tx.execute(
+                    r#"
+                    UPDATE analysis_results
+                    SET categories = COALESCE(?1, categories),
+                        sentiment = COALESCE(?2, sentiment),
+                        metadata_json = CASE
+                            WHEN ?3 IS NULL THEN metadata_json
+                            ELSE json_set(COALESCE(metadata_json, '{}'), '$.priority', ?3)
+                        END
+                    WHERE message_id = ?4
+                    "#,
+                    params![
+                        categories_json,
+                        correction.sentiment,
+                        correction.priority,
+                        message_id
+                    ],
+                )?;
+
+                tx.execute(
+                    r#"
+                    INSERT INTO analysis_examples (
+                        account_email,
+                        sender_email,
+                        sender_domain,
+                        subject_encrypted,
+                        snippet_encrypted,
+                        categories,
+                        priority,
+                        sentiment,
+                        created_at
+                    )
+                    SELECT m.account_email,
+                           m.sender_email,
+                           substr(m.sender_email, instr(m.sender_email, '@') + 1),
+                           m.subject_encrypted,
+                           m.snippet_encrypted,
+                           ar.categories,
+                           json_extract(ar.metadata_json, '$.priority'),
+                           ar.sentiment,
+                           ?
+                    FROM messages m
+                    LEFT JOIN analysis_results ar ON ar.message_id = m.id
+                    WHERE m.id = ?
+                    "#,
+                    params![now, message_id],
+                )?;
+            }
+
+            tx.execute(
+                r#"
+                UPDATE analysis_results
+                SET validation_status = 'human-verified',
+                    validator_model_id = 'human',
+                    validation_confidence = 1.0,
+                    validated_at = ?
+                WHERE message_id = ?
+                "#,
+                params![now, message_id],
+            )?;
+
+            tx.execute(
+                "DELETE FROM review_queue WHERE message_id = ?",
+                params![message_id],
+            )?;
+
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn recent_analysis_examples(&self, limit: usize) -> Result<Vec<AnalysisExample>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let limit = limit.min(1000) as i64;
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<AnalysisExample>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, account_email, sender_email, sender_domain, subject_encrypted,
+                       snippet_encrypted, categories, priority, sentiment, created_at
+                FROM analysis_examples
+                ORDER BY created_at DESC, id DESC
+                LIMIT ?
+                "#,
+            )?;
+
+            let mut rows = stmt.query(params![limit])?;
+            let mut examples = Vec::new();
+            while let Some(row) = rows.next()? {
+                examples.push(analysis_example_from_row(row, &cipher)?);
+            }
+            Ok(examples)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn message_count_for_account(&self, account_email: &str) -> Result<usize> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT COUNT(*) FROM messages
+                WHERE account_email = ? AND tombstoned_at IS NULL
+                "#,
+            )?;
+
+            let count: i64 = stmt.query_row(params![account], |row| row.get(0))?;
+            Ok(count.max(0) as usize)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn recent_message_summaries(
+        &self,
+        account_email: &str,
+        limit: usize,
+    ) -> Result<Vec<CachedMessageSummary>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let decrypted = self.decrypted.clone();
+        let account = account_email.to_owned();
+        let limit = limit.min(100_000);
+
+        let result = tokio::task::spawn_blocking(move || -> Result<Vec<CachedMessageSummary>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT uid, sender_email, sender_display, subject_encrypted, date, updated_at,
+                       folder
+                FROM messages
+                WHERE account_email = ? AND tombstoned_at IS NULL
+                ORDER BY updated_at DESC, id DESC
+                LIMIT ?
+                "#,
+            )?;
+
+            let mut rows = stmt.query(params![account, limit as i64])?;
+            let mut items = Vec::new();
+
+            while let Some(row) = rows.next()? {
+                let uid: u32 = row.get(0)?;
+                let sender_email: String = row.get(1)?;
+                let sender_display: Option<String> = row.get(2)?;
+                let subject_enc: String = row.get(3)?;
+                let date: Option<String> = row.get(4)?;
+                let folder: String = row.get(6)?;
+
+                let (subject, _) = decrypt_listing_text(
+                    &cipher,
+                    &decrypted,
+                    (&account, &folder, uid, row.get(5)?),
+                    &subject_enc,
+                    None,
+                    false,
+                )?;
+
+                items.push(CachedMessageSummary {
+                    uid,
+                    subject,
+                    sender_email,
+                    sender_display,
+                    date,
+                });
+            }
+
+            Ok(items)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        result
+    }
+
+    /// Decrypts the subjects and snippets of the account's newest messages
+    /// into the decrypt cache ahead of the next listing. Returns how many
+    /// rows were read.
+    pub async fn prewarm_decrypt_cache(&self, account_email: &str, limit: usize) -> Result<usize> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let decrypted = self.decrypted.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            // Read the rows first so the connection is not held while
+            // decrypting.
+            let rows = {
+                let conn = conn.lock();
+                let mut stmt = conn.prepare(
+                    r#"
+                    SELECT uid, updated_at, subject_encrypted, snippet_encrypted, folder
+                    FROM messages
+                    WHERE account_email = ? AND tombstoned_at IS NULL
+                    ORDER BY COALESCE(date_ts, 0) DESC, id DESC
+                    LIMIT ?
+                    "#,
+                )?;
+                let mut rows = stmt.query(params![account, limit as i64])?;
+                let mut items = Vec::new();
+                while let Some(row) = rows.next()? {
+                    items.push((
+                        row.get::<_, u32>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, String>(4)?,
+                    ));
+                }
+                items
+            };
+            let count = rows.len();
+            for (uid, updated_at, subject_enc, snippet_enc, folder) in rows {
+                decrypt_listing_text(
+                    &cipher,
+                    &decrypted,
+                    (&account, &folder, uid, updated_at),
+                    &subject_enc,
+                    snippet_enc.as_deref(),
+                    true,
+                )?;
+            }
+            Ok(count)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn latest_uid_for_account(
+        &self,
+        account_email: &str,
+        folder: &str,
+    ) -> Result<Option<u32>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<u32>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT uid FROM messages
+                WHERE account_email = ? AND folder = ?
+                ORDER BY uid DESC, id DESC
                 LIMIT 1
                 "#,
             )?;
 
             let uid = stmt
-                .query_row(params![account], |row| row.get::<_, String>(0))
+                .query_row(params![account, folder], |row| row.get(0))
                 .optional()?;
             Ok(uid)
         })
@@ -1424,13 +4406,12 @@ impl Storage {
     pub async fn update_sync_state(
         &self,
         account_email: &str,
-        last_uid: Option<&str>,
+        last_uid: Option<u32>,
         is_full: bool,
         total_messages: usize,
     ) -> Result<()> {
         let conn = self.conn.clone();
         let account = account_email.to_lowercase();
-        let last_uid_owned = last_uid.map(|value| value.to_string());
         let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
             let conn = conn.lock();
             let now = Utc::now().timestamp();
@@ -1456,7 +4437,7 @@ impl Storage {
                     account,
                     last_full,
                     last_incremental,
-                    last_uid_owned,
+                    last_uid,
                     total_messages as i64
                 ],
             )?;
@@ -1474,28 +4455,60 @@ impl Storage {
     ) -> Result<Option<AccountSyncState>> {
         let conn = self.conn.clone();
         let account = account_email.to_lowercase();
-        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<AccountSyncState>> {
-            let conn = conn.lock();
-            let mut stmt = conn.prepare(
-                r#"
-                SELECT account_email, last_full_sync, last_incremental_sync, last_uid, total_messages
+        let join_result =
+            tokio::task::spawn_blocking(move || -> Result<Option<AccountSyncState>> {
+                let conn = conn.lock();
+                let mut stmt = conn.prepare(
+                    r#"
+                SELECT account_email, last_full_sync, last_incremental_sync,
+                       CAST(last_uid AS INTEGER), total_messages
                 FROM account_sync_state
                 WHERE account_email = ?
                 "#,
-            )?;
+                )?;
 
-            let state = stmt
-                .query_row(params![account], |row| {
-                    Ok(AccountSyncState {
-                        account_email: row.get(0)?,
-                        last_full_sync: row.get(1)?,
-                        last_incremental_sync: row.get(2)?,
-                        last_uid: row.get(3)?,
-                        total_messages: row.get::<_, i64>(4)?,
+                let state = stmt
+                    .query_row(params![account], |row| {
+                        Ok(AccountSyncState {
+                            account_email: row.get(0)?,
+                            last_full_sync: row.get(1)?,
+                            last_incremental_sync: row.get(2)?,
+                            last_uid: row.get(3)?,
+                            total_messages: row.get::<_, i64>(4)?,
+                        })
                     })
-                })
-                .optional()?;
-            Ok(state)
+                    .optional()?;
+                Ok(state)
+            })
+            .await
+            .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Whether size filters over the cache see the whole mailbox: a full
+    /// sync has finished and every cached message has its size recorded.
+    pub async fn sizes_known(&self, account_email: &str) -> Result<bool> {
+        let conn = self.conn.clone();
+        let account = account_email.to_lowercase();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let conn = conn.lock();
+            let known = conn.query_row(
+                r#"
+                SELECT EXISTS (
+                        SELECT 1 FROM account_sync_state
+                        WHERE account_email = ?1 AND last_full_sync IS NOT NULL
+                    )
+                    AND NOT EXISTS (
+                        SELECT 1 FROM messages
+                        WHERE account_email = ?1 AND size_bytes IS NULL
+                            AND tombstoned_at IS NULL
+                    )
+                "#,
+                params![account],
+                |row| row.get(0),
+            )?;
+            Ok(known)
         })
         .await
         .map_err(map_join_error)?;
@@ -1503,86 +4516,101 @@ impl Storage {
         join_result
     }
 
-    pub async fn delete_message(&self, account_email: &str, uid: &str) -> Result<()> {
+    /// The DNS records last read for `domain`.
+    pub async fn brand_check(&self, domain: &str) -> Result<Option<BrandCheck>> {
         let conn = self.conn.clone();
-        let account = account_email.to_owned();
-        let uid = uid.to_owned();
-        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+        let domain = domain.to_lowercase();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<BrandCheck>> {
             let conn = conn.lock();
-            conn.execute(
-                "DELETE FROM messages WHERE account_email = ? AND uid = ?",
-                params![account, uid],
-            )?;
-            Ok(())
+            let check = conn
+                .query_row(
+                    r#"
+                    SELECT domain, dmarc_policy, bimi_logo, bimi_authority, checked_at
+                    FROM brand_checks
+                    WHERE domain = ?
+                    "#,
+                    params![domain],
+                    |row| {
+                        let policy: Option<String> = row.get(1)?;
+                        Ok(BrandCheck {
+                            brand: DomainBrand {
+                                domain: row.get(0)?,
+                                dmarc_policy: policy.as_deref().and_then(DmarcPolicy::parse),
+                                bimi_logo: row.get(2)?,
+                                bimi_authority: row.get(3)?,
+                            },
+                            checked_at: row.get(4)?,
+                        })
+                    },
+                )
+                .optional()?;
+            Ok(check)
         })
         .await
         .map_err(map_join_error)?;
 
-        join_result?;
-
-        Ok(())
+        join_result
     }
 
-    pub async fn update_message_body(
-        &self,
-        account_email: &str,
-        uid: &str,
-        body: &[u8],
-    ) -> Result<()> {
+    pub async fn save_brand_check(&self, brand: &DomainBrand) -> Result<()> {
         let conn = self.conn.clone();
-        let cipher = self.cipher.clone();
-        let account = account_email.to_owned();
-        let uid = uid.to_owned();
-        let payload = body.to_vec();
-
+        let brand = brand.clone();
         let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
-            let encrypted = cipher.encrypt_bytes(&payload)?;
-            let now = Utc::now().timestamp();
             let conn = conn.lock();
             conn.execute(
                 r#"
-                UPDATE messages
-                SET body_encrypted = ?, updated_at = ?
-                WHERE account_email = ? AND uid = ?
+                INSERT INTO brand_checks (
+                    domain, dmarc_policy, bimi_logo, bimi_authority, checked_at
+                )
+                VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT(domain) DO UPDATE SET
+                    dmarc_policy = excluded.dmarc_policy,
+                    bimi_logo = excluded.bimi_logo,
+                    bimi_authority = excluded.bimi_authority,
+                    checked_at = excluded.checked_at
                 "#,
-                params![encrypted, now, account, uid],
+                params![
+                    brand.domain.to_lowercase(),
+                    brand.dmarc_policy.map(DmarcPolicy::as_str),
+                    brand.bimi_logo,
+                    brand.bimi_authority,
+                    Utc::now().timestamp()
+                ],
             )?;
             Ok(())
         })
         .await
         .map_err(map_join_error)?;
 
-        join_result?;
-
-        Ok(())
+        join_result
     }
 
-    pub async fn message_body(&self, account_email: &str, uid: &str) -> Result<Option<Vec<u8>>> {
+    /// The DMARC verdict on the newest message from `sender` that has one.
+    pub async fn sender_dmarc_alignment(
+        &self,
+        account_email: &str,
+        sender_email: &str,
+    ) -> Result<Option<bool>> {
         let conn = self.conn.clone();
-        let cipher = self.cipher.clone();
         let account = account_email.to_owned();
-        let uid = uid.to_owned();
-
-        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<Vec<u8>>> {
+        let sender = sender_email.to_lowercase();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<bool>> {
             let conn = conn.lock();
-            let mut stmt = conn.prepare(
-                r#"
-                SELECT body_encrypted
-                FROM messages
-                WHERE account_email = ? AND uid = ?
-                "#,
-            )?;
-
-            let encrypted: Option<String> = stmt
-                .query_row(params![account, uid], |row| row.get(0))
+            let aligned = conn
+                .query_row(
+                    r#"
+                    SELECT dmarc_aligned
+                    FROM messages
+                    WHERE account_email = ? AND sender_email = ? AND dmarc_aligned IS NOT NULL
+                        AND tombstoned_at IS NULL
+                    ORDER BY COALESCE(date_ts, 0) DESC, id DESC
+                    LIMIT 1
+                    "#,
+                    params![account, sender],
+                    |row| row.get::<_, bool>(0),
+                )
                 .optional()?;
-
-            if let Some(payload) = encrypted {
-                let body = cipher.decrypt_bytes(&payload)?;
-                Ok(Some(body))
-            } else {
-                Ok(None)
-            }
+            Ok(aligned)
         })
         .await
         .map_err(map_join_error)?;
@@ -1590,71 +4618,5340 @@ impl Storage {
         join_result
     }
 
-    pub async fn update_sender_status(
-        &self,
-        sender_email: &str,
-        status: SenderStatus,
-    ) -> Result<()> {
+    /// Mutes a thread in the account. Muting it again keeps the original
+    /// time.
+    pub async fn mute_thread(&self, account_email: &str, thread_id: &str) -> Result<()> {
         let conn = self.conn.clone();
-        let email = sender_email.to_lowercase();
-        let status_str = status.as_str().to_string();
-
+        let account = account_email.to_owned();
+        let thread_id = thread_id.to_owned();
         let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
-            let now = Utc::now().timestamp();
             let conn = conn.lock();
             conn.execute(
                 r#"
-                INSERT INTO sender_status(sender_email, status, updated_at)
-                VALUES(?, ?, ?)
-                ON CONFLICT(sender_email) DO UPDATE SET
-                    status = excluded.status,
-                    updated_at = excluded.updated_at
+                INSERT INTO muted_threads (account_email, thread_id, muted_at)
+                VALUES (?, ?, ?)
+                ON CONFLICT(account_email, thread_id) DO NOTHING
                 "#,
-                params![email, status_str, now],
+                params![account, thread_id, Utc::now().timestamp()],
             )?;
             Ok(())
         })
         .await
         .map_err(map_join_error)?;
 
-        join_result?;
+        join_result
+    }
+
+    /// Returns whether the thread was muted.
+    pub async fn unmute_thread(&self, account_email: &str, thread_id: &str) -> Result<bool> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let thread_id = thread_id.to_owned();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let conn = conn.lock();
+            let removed = conn.execute(
+                "DELETE FROM muted_threads WHERE account_email = ? AND thread_id = ?",
+                params![account, thread_id],
+            )?;
+            Ok(removed > 0)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// The account's muted threads, most recently muted first.
+    pub async fn muted_threads(&self, account_email: &str) -> Result<Vec<MutedThread>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<MutedThread>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT mt.thread_id, mt.muted_at,
+                       (SELECT m.subject_encrypted FROM messages m
+                        WHERE m.account_email = mt.account_email AND m.thread_id = mt.thread_id
+                            AND m.tombstoned_at IS NULL
+                        ORDER BY COALESCE(m.date_ts, 0) DESC, m.id DESC
+                        LIMIT 1),
+                       (SELECT COUNT(*) FROM messages m
+                        WHERE m.account_email = mt.account_email AND m.thread_id = mt.thread_id
+                            AND m.tombstoned_at IS NULL)
+                FROM muted_threads mt
+                WHERE mt.account_email = ?
+                ORDER BY mt.muted_at DESC, mt.thread_id
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account])?;
+            let mut threads = Vec::new();
+            while let Some(row) = rows.next()? {
+                let subject = row
+                    .get::<_, Option<String>>(2)?
+                    .map(|value| cipher.decrypt_string(&value))
+                    .transpose()?;
+                threads.push(MutedThread {
+                    thread_id: row.get(0)?,
+                    subject,
+                    message_count: row.get::<_, i64>(3)?.max(0) as usize,
+                    muted_at: row.get(1)?,
+                });
+            }
+            Ok(threads)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn muted_thread_ids(&self, account_email: &str) -> Result<HashSet<String>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<HashSet<String>> {
+            let conn = conn.lock();
+            let mut stmt =
+                conn.prepare("SELECT thread_id FROM muted_threads WHERE account_email = ?")?;
+            let ids = stmt
+                .query_map(params![account], |row| row.get(0))?
+                .collect::<rusqlite::Result<HashSet<String>>>()?;
+            Ok(ids)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Hides cached messages from listings, keeping their rows and analysis
+    /// until the tombstones are purged. `reason` is one of the `TOMBSTONE_*`
+    /// values. Returns how many messages were tombstoned.
+    pub async fn tombstone_messages(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uids: &[u32],
+        reason: &str,
+    ) -> Result<usize> {
+        if uids.is_empty() {
+            return Ok(0);
+        }
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let uids = uids.to_vec();
+        let reason = reason.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let now = Utc::now().timestamp();
+            let mut tombstoned = 0;
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    UPDATE messages SET tombstoned_at = ?, tombstone_reason = ?
+                    WHERE account_email = ? AND folder = ? AND uid = ? AND tombstoned_at IS NULL
+                    "#,
+                )?;
+                for uid in &uids {
+                    tombstoned += stmt.execute(params![now, reason, account, folder, uid])?;
+                }
+            }
+            tx.commit()?;
+            Ok(tombstoned)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Brings back messages tombstoned for `reason`, as when a move the
+    /// cache already showed fails on the server. Returns how many returned.
+    pub async fn untombstone_messages(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uids: &[u32],
+        reason: &str,
+    ) -> Result<usize> {
+        if uids.is_empty() {
+            return Ok(0);
+        }
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let uids = uids.to_vec();
+        let reason = reason.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut restored = 0;
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    UPDATE messages SET tombstoned_at = NULL, tombstone_reason = NULL
+                    WHERE account_email = ? AND folder = ? AND uid = ? AND tombstone_reason = ?
+                    "#,
+                )?;
+                for uid in &uids {
+                    restored += stmt.execute(params![account, folder, uid, reason])?;
+                }
+            }
+            tx.commit()?;
+            Ok(restored)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Applies a flag edit to the cached messages and queues it for the
+    /// server. An edit that undoes one still queued cancels it. Returns how
+    /// many cached messages changed.
+    pub async fn queue_flag_changes(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uids: &[u32],
+        flags: &[String],
+        enabled: bool,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let uids = uids.to_vec();
+        let flags = flags
+            .iter()
+            .map(|flag| flag_sync::flag_key(flag))
+            .collect::<Vec<_>>();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let now = Utc::now().timestamp();
+            let mut changed = 0;
+            for &uid in &uids {
+                let Some((current, refreshed_at)) = tx
+                    .query_row(
+                        r#"
+                        SELECT flags, updated_at FROM messages
+                        WHERE account_email = ? AND folder = ? AND uid = ? AND tombstoned_at IS NULL
+                        "#,
+                        params![account, folder, uid],
+                        |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, i64>(1)?)),
+                    )
+                    .optional()?
+                else {
+                    continue;
+                };
+                // Every queued edit of a message shares the server state
+                // the first one was made against.
+                let (base_flags, base_refreshed_at) = tx
+                    .query_row(
+                        r#"
+                        SELECT base_flags, base_refreshed_at FROM pending_flag_changes
+                        WHERE account_email = ? AND folder = ? AND uid = ? LIMIT 1
+                        "#,
+                        params![account, folder, uid],
+                        |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, i64>(1)?)),
+                    )
+                    .optional()?
+                    .unwrap_or((current.clone(), refreshed_at));
+
+                let mut updated = current.clone();
+                for flag in &flags {
+                    updated = flag_sync::with_flag(updated.as_deref(), flag, enabled);
+                    if flag_sync::has_flag(base_flags.as_deref(), flag) == enabled {
+                        tx.execute(
+                            r#"
+                            DELETE FROM pending_flag_changes
+                            WHERE account_email = ? AND folder = ? AND uid = ? AND flag = ?
+                            "#,
+                            params![account, folder, uid, flag],
+                        )?;
+                    } else {
+                        tx.execute(
+                            r#"
+                            INSERT INTO pending_flag_changes (
+                                account_email, folder, uid, flag, enabled, base_flags,
+                                base_refreshed_at, changed_at
+                            ) VALUES (?,?,?,?,?,?,?,?)
+                            ON CONFLICT(account_email, folder, uid, flag) DO UPDATE SET
+                                enabled = excluded.enabled,
+                                changed_at = excluded.changed_at,
+                                attempts = 0
+                            "#,
+                            params![
+                                account,
+                                folder,
+                                uid,
+                                flag,
+                                enabled,
+                                base_flags,
+                                base_refreshed_at,
+                                now
+                            ],
+                        )?;
+                    }
+                }
+                if !flag_sync::same_flags(updated.as_deref(), current.as_deref()) {
+                    tx.execute(
+                        "UPDATE messages SET flags = ? \
+                         WHERE account_email = ? AND folder = ? AND uid = ?",
+                        params![updated, account, folder, uid],
+                    )?;
+                    changed += 1;
+                }
+            }
+            tx.commit()?;
+            Ok(changed)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn pending_flag_changes(
+        &self,
+        account_email: &str,
+    ) -> Result<Vec<PendingFlagChange>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<PendingFlagChange>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT p.uid, p.flag, p.enabled, p.changed_at, p.folder, m.uidvalidity
+                FROM pending_flag_changes p
+                LEFT JOIN messages m
+                    ON m.account_email = p.account_email AND m.folder = p.folder AND m.uid = p.uid
+                WHERE p.account_email = ?
+                ORDER BY p.changed_at
+                "#,
+            )?;
+            let rows = stmt.query_map(params![account], |row| {
+                Ok(PendingFlagChange {
+                    folder: row.get(4)?,
+                    uid_validity: uid_validity_from(row.get(5)?),
+                    uid: row.get(0)?,
+                    flag: row.get(1)?,
+                    enabled: row.get(2)?,
+                    changed_at: row.get(3)?,
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Counts a failed push of `failed`. Edits that have now failed
+    /// `max_attempts` times are dropped and undone in the cache; those are
+    /// returned. Edits changed again since they were read are left alone.
+    pub async fn fail_flag_changes(
+        &self,
+        account_email: &str,
+        failed: Vec<PendingFlagChange>,
+        max_attempts: u32,
+    ) -> Result<Vec<PendingFlagChange>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<PendingFlagChange>> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut rolled_back = Vec::new();
+            for change in failed {
+                let (folder, uid) = (&change.folder, change.uid);
+                let counted = tx.execute(
+                    r#"
+                    UPDATE pending_flag_changes SET attempts = attempts + 1
+                    WHERE account_email = ? AND folder = ? AND uid = ? AND flag = ?
+                      AND changed_at = ?
+                    "#,
+                    params![account, folder, uid, change.flag, change.changed_at],
+                )?;
+                if counted == 0 {
+                    continue;
+                }
+                let attempts: u32 = tx.query_row(
+                    r#"
+                    SELECT attempts FROM pending_flag_changes
+                    WHERE account_email = ? AND folder = ? AND uid = ? AND flag = ?
+                    "#,
+                    params![account, folder, uid, change.flag],
+                    |row| row.get(0),
+                )?;
+                if attempts < max_attempts {
+                    continue;
+                }
+                tx.execute(
+                    r#"
+                    DELETE FROM pending_flag_changes
+                    WHERE account_email = ? AND folder = ? AND uid = ? AND flag = ?
+                    "#,
+                    params![account, folder, uid, change.flag],
+                )?;
+                let uid_validity = change.uid_validity;
+                let current = tx
+                    .query_row(
+                        "SELECT flags FROM messages \
+                         WHERE account_email = ? AND folder = ? AND uid = ? AND uidvalidity IS ?",
+                        params![account, folder, uid, uid_validity],
+                        |row| row.get::<_, Option<String>>(0),
+                    )
+                    .optional()?
+                    .flatten();
+                let restored =
+                    flag_sync::with_flag(current.as_deref(), &change.flag, !change.enabled);
+                tx.execute(
+                    "UPDATE messages SET flags = ? \
+                     WHERE account_email = ? AND folder = ? AND uid = ? AND uidvalidity IS ?",
+                    params![restored, account, folder, uid, uid_validity],
+                )?;
+                rolled_back.push(change);
+            }
+            tx.commit()?;
+            Ok(rolled_back)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Records that `pushed` edits reached `folder` under `uid_validity`,
+    /// where the server now reports `server_flags` for those messages. Edits
+    /// made while they were being pushed stay queued and are laid over the
+    /// server's flags.
+    pub async fn complete_flag_changes(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid_validity: Option<u32>,
+        pushed: Vec<PendingFlagChange>,
+        server_flags: Vec<(u32, Option<String>)>,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let now = Utc::now().timestamp();
+            for change in &pushed {
+                tx.execute(
+                    r#"
+                    DELETE FROM pending_flag_changes
+                    WHERE account_email = ? AND folder = ? AND uid = ? AND flag = ?
+                      AND changed_at = ?
+                    "#,
+                    params![
+                        account,
+                        change.folder,
+                        change.uid,
+                        change.flag,
+                        change.changed_at
+                    ],
+                )?;
+            }
+            let policies = FlagPolicies::default();
+            let mut conflicts = Vec::new();
+            for &(uid, ref flags) in &server_flags {
+                tx.execute(
+                    r#"
+                    UPDATE pending_flag_changes SET base_flags = ?, base_refreshed_at = ?
+                    WHERE account_email = ? AND folder = ? AND uid = ?
+                    "#,
+                    params![flags, now, account, folder, uid],
+                )?;
+                // Rebased on the server's flags, the remaining edits cannot
+                // conflict; this only lays them over.
+                let cached = reconcile_flags(
+                    &tx,
+                    &policies,
+                    (&account, &folder, uid),
+                    flags.as_deref(),
+                    now,
+                    &mut conflicts,
+                )?;
+                tx.execute(
+                    "UPDATE messages SET flags = ? \
+                     WHERE account_email = ? AND folder = ? AND uid = ? AND uidvalidity IS ?",
+                    params![cached, account, folder, uid, uid_validity],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// The most recent flag conflicts recorded for an account, newest first.
+    pub async fn flag_conflicts(
+        &self,
+        account_email: &str,
+        limit: usize,
+    ) -> Result<Vec<FlagConflict>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<FlagConflict>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, account_email, uid, flag, local_enabled, server_enabled, policy,
+                       winner, local_changed_at, detected_at, folder
+                FROM flag_conflicts
+                WHERE account_email = ?
+                ORDER BY detected_at DESC, id DESC
+                LIMIT ?
+                "#,
+            )?;
+            let rows = stmt.query_map(params![account, limit as i64], |row| {
+                let policy: String = row.get(6)?;
+                let winner: String = row.get(7)?;
+                Ok(FlagConflict {
+                    id: row.get(0)?,
+                    account_email: row.get(1)?,
+                    folder: row.get(10)?,
+                    uid: row.get(2)?,
+                    flag: row.get(3)?,
+                    local_enabled: row.get(4)?,
+                    server_enabled: row.get(5)?,
+                    policy: ConflictPolicy::parse(&policy).unwrap_or_default(),
+                    winner: if winner == Winner::Local.as_str() {
+                        Winner::Local
+                    } else {
+                        Winner::Server
+                    },
+                    local_changed_at: row.get(8)?,
+                    detected_at: row.get(9)?,
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Tombstones live messages cached for a folder that the server no
+    /// longer lists, e.g. because another client deleted them. `server_uids`
+    /// is the folder's full UID list; cached UIDs at or above `uid_next` are
+    /// newer than that list and left alone. Returns the UIDs and message keys
+    /// of the tombstoned messages.
+    pub async fn tombstone_vanished(
+        &self,
+        account_email: &str,
+        folder: &str,
+        server_uids: Vec<u32>,
+        uid_next: Option<u32>,
+    ) -> Result<Vec<(u32, String)>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<(u32, String)>> {
+            let present: HashSet<u32> = server_uids.into_iter().collect();
+            let limit = uid_next.map_or(i64::MAX, i64::from);
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let cached = {
+                let mut stmt = tx.prepare(
+                    r#"
+                    SELECT uid, message_key FROM messages
+                    WHERE account_email = ? AND folder = ? AND uid < ? AND tombstoned_at IS NULL
+                    "#,
+                )?;
+                let rows = stmt.query_map(params![account, folder, limit], |row| {
+                    Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+                })?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            let vanished: Vec<(u32, String)> = cached
+                .into_iter()
+                .filter(|(uid, _)| !present.contains(uid))
+                .collect();
+            if !vanished.is_empty() {
+                let now = Utc::now().timestamp();
+                let mut stmt = tx.prepare(
+                    r#"
+                    UPDATE messages SET tombstoned_at = ?, tombstone_reason = ?
+                    WHERE account_email = ? AND folder = ? AND uid = ?
+                    "#,
+                )?;
+                for (uid, _) in &vanished {
+                    stmt.execute(params![now, TOMBSTONE_REMOTE, account, folder, uid])?;
+                }
+            }
+            tx.commit()?;
+            Ok(vanished)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Removes messages tombstoned before `cutoff` (Unix seconds), with
+    /// their analysis and the data derived from them. Returns how many
+    /// messages were removed.
+    pub async fn purge_tombstones(&self, cutoff: i64) -> Result<usize> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            tx.execute(
+                r#"
+                DELETE FROM analysis_results WHERE message_id IN (
+                    SELECT id FROM messages WHERE tombstoned_at < ?
+                )
+                "#,
+                params![cutoff],
+            )?;
+            for table in UID_KEYED_TABLES {
+                tx.execute(
+                    &format!(
+                        r#"
+                        DELETE FROM {table} WHERE EXISTS (
+                            SELECT 1 FROM messages m
+                            WHERE m.account_email = {table}.account_email
+                              AND m.folder = {table}.folder
+                              AND m.uid = {table}.uid
+                              AND m.tombstoned_at < ?
+                        )
+                        "#
+                    ),
+                    params![cutoff],
+                )?;
+            }
+            let purged = tx.execute(
+                "DELETE FROM messages WHERE tombstoned_at < ?",
+                params![cutoff],
+            )?;
+            tx.commit()?;
+            Ok(purged)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn update_message_body(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+        body: &[u8],
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let payload = Zeroizing::new(body.to_vec());
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            if headers_only(&conn, &account)? {
+                return Ok(());
+            }
+            let encrypted = cipher.encrypt_bytes(&payload)?;
+            let now = Utc::now().timestamp();
+            conn.execute(
+                r#"
+                UPDATE messages
+                SET body_encrypted = ?, updated_at = ?
+                WHERE account_email = ? AND folder = ? AND uid = ?
+                "#,
+                params![encrypted, now, account, folder, uid],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result?;
+
+        Ok(())
+    }
+
+    /// The cached body, decrypted. The buffer is zeroed when dropped.
+    pub async fn message_body(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<Option<Zeroizing<Vec<u8>>>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result =
+            tokio::task::spawn_blocking(move || -> Result<Option<Zeroizing<Vec<u8>>>> {
+                let conn = conn.lock();
+                let mut stmt = conn.prepare(
+                    r#"
+                    SELECT body_encrypted
+                    FROM messages
+                    WHERE account_email = ? AND folder = ? AND uid = ?
+                    "#,
+                )?;
+
+                let encrypted: Option<String> = stmt
+                    .query_row(params![account, folder, uid], |row| row.get(0))
+                    .optional()?;
+
+                if let Some(payload) = encrypted {
+                    let body = cipher.decrypt_bytes(&payload)?;
+                    Ok(Some(Zeroizing::new(body)))
+                } else {
+                    Ok(None)
+                }
+            })
+            .await
+            .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn cached_message(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<Option<CachedMessage>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<CachedMessage>> {
+            let conn = conn.lock();
+            let row = conn
+                .query_row(
+                    r#"
+                    SELECT id, uid, subject_encrypted, snippet_encrypted, date,
+                           sender_email, sender_display
+                    FROM messages
+                    WHERE account_email = ? AND folder = ? AND uid = ?
+                    "#,
+                    params![account, folder, uid],
+                    |row| {
+                        Ok((
+                            row.get::<_, i64>(0)?,
+                            row.get::<_, u32>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, Option<String>>(3)?,
+                            row.get::<_, Option<String>>(4)?,
+                            row.get::<_, String>(5)?,
+                            row.get::<_, Option<String>>(6)?,
+                        ))
+                    },
+                )
+                .optional()?;
+
+            let Some((message_id, uid, subject_enc, snippet_enc, date, sender_email, display)) =
+                row
+            else {
+                return Ok(None);
+            };
+
+            Ok(Some(CachedMessage {
+                message_id,
+                uid,
+                subject: cipher.decrypt_string(&subject_enc)?,
+                snippet: snippet_enc
+                    .as_ref()
+                    .map(|value| cipher.decrypt_string(value))
+                    .transpose()?,
+                date,
+                sender_email,
+                sender_display: display,
+            }))
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Sets a sender's status in `scope` ([`GLOBAL_SCOPE`] or an account
+    /// email). Setting an account-scoped rule to neutral removes it, so the
+    /// global rule applies to that account again.
+    pub async fn update_sender_status(
+        &self,
+        sender_email: &str,
+        scope: &str,
+        status: SenderStatus,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let email = sender_email.to_lowercase();
+        let scope = scope.to_lowercase();
+        let status_str = status.as_str().to_string();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let now = Utc::now().timestamp();
+            let conn = conn.lock();
+            if scope != GLOBAL_SCOPE && matches!(status, SenderStatus::Neutral) {
+                conn.execute(
+                    "DELETE FROM sender_status WHERE sender_email = ? AND scope = ?",
+                    params![email, scope],
+                )?;
+                return Ok(());
+            }
+            conn.execute(
+                r#"
+                INSERT INTO sender_status(sender_email, scope, status, updated_at)
+                VALUES(?, ?, ?, ?)
+                ON CONFLICT(sender_email, scope) DO UPDATE SET
+                    status = excluded.status,
+                    updated_at = excluded.updated_at,
+                    origin = 'manual',
+                    origin_ref = NULL,
+                    expires_at = NULL,
+                    previous_status = NULL
+                "#,
+                params![email, scope, status_str, now],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result?;
 
         Ok(())
     }
 
-    pub async fn sender_status(&self, sender_email: &str) -> Result<SenderStatus> {
+    /// Blocks a sender until `until` (Unix seconds). The rule keeps the
+    /// status it had before, which [`Storage::expire_sender_mutes`] restores.
+    pub async fn mute_sender(&self, sender_email: &str, scope: &str, until: i64) -> Result<()> {
+        let conn = self.conn.clone();
+        let email = sender_email.to_lowercase();
+        let scope = scope.to_lowercase();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            // Muting again only moves the expiry; the status to restore stays.
+            conn.execute(
+                r#"
+                INSERT INTO sender_status(sender_email, scope, status, updated_at, expires_at)
+                VALUES(?, ?, 'blocked', ?, ?)
+                ON CONFLICT(sender_email, scope) DO UPDATE SET
+                    previous_status = CASE
+                        WHEN sender_status.expires_at IS NULL THEN sender_status.status
+                        ELSE sender_status.previous_status
+                    END,
+                    status = 'blocked',
+                    updated_at = excluded.updated_at,
+                    expires_at = excluded.expires_at,
+                    origin = 'manual',
+                    origin_ref = NULL
+                "#,
+                params![email, scope, Utc::now().timestamp(), until],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Ends mutes that expired by `now`, putting each rule back to its
+    /// earlier status. Account rules that had none are removed. Returns the
+    /// rules as restored.
+    pub async fn expire_sender_mutes(&self, now: i64) -> Result<Vec<SenderRule>> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<SenderRule>> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let expired = {
+                let mut stmt = tx.prepare(
+                    r#"
+                    SELECT sender_email, scope, COALESCE(previous_status, 'neutral'), origin,
+                           origin_ref
+                    FROM sender_status
+                    WHERE expires_at <= ?
+                    "#,
+                )?;
+                let rows = stmt.query_map(params![now], |row| {
+                    Ok(SenderRule {
+                        sender_email: row.get(0)?,
+                        scope: row.get(1)?,
+                        status: row.get(2)?,
+                        updated_at: now,
+                        origin: row.get(3)?,
+                        origin_ref: row.get(4)?,
+                        expires_at: None,
+                    })
+                })?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            if !expired.is_empty() {
+                tx.execute(
+                    r#"
+                    DELETE FROM sender_status
+                    WHERE expires_at <= ?1 AND scope != 'global'
+                      AND COALESCE(previous_status, 'neutral') = 'neutral'
+                    "#,
+                    params![now],
+                )?;
+                tx.execute(
+                    r#"
+                    UPDATE sender_status
+                    SET status = COALESCE(previous_status, 'neutral'), updated_at = ?1,
+                        expires_at = NULL, previous_status = NULL
+                    WHERE expires_at <= ?1
+                    "#,
+                    params![now],
+                )?;
+            }
+            tx.commit()?;
+            Ok(expired)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// The status that applies to mail from `sender_email` in `account_email`:
+    /// the account's own rule if it has one, otherwise the global rule, and
+    /// failing both a global rule for the sender's domain (`@example.com`).
+    pub async fn sender_status(
+        &self,
+        sender_email: &str,
+        account_email: &str,
+    ) -> Result<SenderStatus> {
+        let conn = self.conn.clone();
+        let email = sender_email.to_lowercase();
+        let account = account_email.to_lowercase();
+        let result = tokio::task::spawn_blocking(move || -> Result<SenderStatus> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT status FROM sender_status
+                WHERE (sender_email = ?1 AND scope IN (?2, 'global') AND status != 'neutral')
+                   OR (sender_email = ?3 AND scope = 'global')
+                ORDER BY sender_email != ?1, scope = 'global'
+                LIMIT 1
+                "#,
+            )?;
+            let domain = email.find('@').map(|at| email[at..].to_string());
+            let status: Option<String> = stmt
+                .query_row(params![email, account, domain], |row| row.get(0))
+                .optional()?;
+            Ok(status
+                .map(|value| SenderStatus::from_str(&value))
+                .unwrap_or(SenderStatus::Neutral))
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        result
+    }
+
+    pub async fn sender_profile(&self, sender_email: &str) -> Result<Option<SenderProfile>> {
+        let conn = self.conn.clone();
+        let email = sender_email.to_lowercase();
+        let result = tokio::task::spawn_blocking(move || -> Result<Option<SenderProfile>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT sender_kind, typical_tags, typical_priority,
+                       profile_samples, profile_confidence, profile_updated_at
+                FROM sender_status
+                WHERE sender_email = ? AND scope = 'global'
+                "#,
+            )?;
+            let mut rows = stmt.query(params![email])?;
+            match rows.next()? {
+                Some(row) => sender_profile_from_row(row, 0),
+                None => Ok(None),
+            }
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        result
+    }
+
+    /// Rebuilds sender profiles from analyzed mail. Profiles are keyed by
+    /// sender like the block/allow status, so all accounts contribute.
+    pub async fn refresh_sender_profiles(&self, min_samples: usize) -> Result<usize> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+
+            let mut aggregates: HashMap<String, SenderAggregate> = HashMap::new();
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    SELECT m.sender_email,
+                           ar.categories,
+                           json_extract(ar.metadata_json, '$.priority')
+                    FROM messages m
+                    JOIN analysis_results ar ON ar.message_id = m.id
+                    WHERE ar.analyzed = 1
+                      AND COALESCE(ar.model_id, '') != ?
+                    "#,
+                )?;
+                let mut rows = stmt.query(params![SENDER_PROFILE_MODEL_ID])?;
+                while let Some(row) = rows.next()? {
+                    let sender: String = row.get(0)?;
+                    let categories_json: Option<String> = row.get(1)?;
+                    let priority: Option<String> = row.get(2)?;
+
+                    let mut categories = categories_json
+                        .as_deref()
+                        .and_then(|value| serde_json::from_str::<Vec<String>>(value).ok())
+                        .unwrap_or_default();
+                    categories.sort();
+                    categories.dedup();
+
+                    let aggregate = aggregates.entry(sender).or_default();
+                    aggregate.samples += 1;
+                    *aggregate
+                        .tag_sets
+                        .entry(categories.join(","))
+                        .or_insert(0) += 1;
+                    for tag in categories {
+                        *aggregate.tag_counts.entry(tag).or_insert(0) += 1;
+                    }
+                    if let Some(priority) = priority {
+                        *aggregate.priorities.entry(priority).or_insert(0) += 1;
+                    }
+                }
+            }
+
+            let mut updated = 0usize;
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    INSERT INTO sender_status (
+                        sender_email, status, updated_at,
+                        sender_kind, typical_tags, typical_priority,
+                        profile_samples, profile_confidence, profile_updated_at
+                    )
+                    VALUES (?1, 'neutral', ?7, ?2, ?3, ?4, ?5, ?6, ?7)
+                    ON CONFLICT(sender_email, scope) DO UPDATE SET
+                        sender_kind = excluded.sender_kind,
+                        typical_tags = excluded.typical_tags,
+                        typical_priority = excluded.typical_priority,
+                        profile_samples = excluded.profile_samples,
+                        profile_confidence = excluded.profile_confidence,
+                        profile_updated_at = excluded.profile_updated_at
+                    "#,
+                )?;
+
+                for (sender, aggregate) in aggregates {
+                    if aggregate.samples < min_samples {
+                        continue;
+                    }
+                    let profile = aggregate.into_profile(&sender, now);
+                    let tags_json = serde_json::to_string(&profile.typical_tags)
+                        .map_err(|err| StorageError::Serialization(err.to_string()))?;
+                    stmt.execute(params![
+                        sender,
+                        profile.kind,
+                        tags_json,
+                        profile.typical_priority,
+                        profile.sample_count,
+                        profile.confidence,
+                        now
+                    ])?;
+                    updated += 1;
+                }
+            }
+
+            tx.commit()?;
+            Ok(updated)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Fills pending heuristic analyses from confident sender profiles so known
+    /// senders are classified without invoking the model.
+    pub async fn prefill_from_sender_profiles(
+        &self,
+        account_email: &str,
+        min_confidence: f64,
+        min_samples: i64,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let now = Utc::now().timestamp();
+            let conn = conn.lock();
+            let updated = conn.execute(
+                r#"
+                UPDATE analysis_results
+                SET categories = (
+                        SELECT ss.typical_tags FROM sender_status ss
+                        JOIN messages m ON m.sender_email = ss.sender_email
+                        WHERE m.id = analysis_results.message_id AND ss.scope = 'global'
+                    ),
+                    metadata_json = (
+                        SELECT json_object(
+                            'priority', ss.typical_priority,
+                            'senderKind', ss.sender_kind,
+                            'source', 'sender-profile'
+                        )
+                        FROM sender_status ss
+                        JOIN messages m ON m.sender_email = ss.sender_email
+                        WHERE m.id = analysis_results.message_id AND ss.scope = 'global'
+                    ),
+                    analysis_confidence = (
+                        SELECT ss.profile_confidence FROM sender_status ss
+                        JOIN messages m ON m.sender_email = ss.sender_email
+                        WHERE m.id = analysis_results.message_id AND ss.scope = 'global'
+                    ),
+                    model_id = ?1,
+                    analyzed = 1,
+                    analyzed_at = ?2,
+                    stale = 0
+                WHERE COALESCE(analyzed, 0) = 0
+                  AND message_id IN (
+                      SELECT m.id
+                      FROM messages m
+                      JOIN sender_status ss
+                          ON ss.sender_email = m.sender_email AND ss.scope = 'global'
+                      WHERE m.account_email = ?3
+                        AND ss.profile_confidence >= ?4
+                        AND ss.profile_samples >= ?5
+                  )
+                "#,
+                params![
+                    SENDER_PROFILE_MODEL_ID,
+                    now,
+                    account,
+                    min_confidence,
+                    min_samples
+                ],
+            )?;
+            Ok(updated)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Trains (or, with `None`, untrains) the spam model on every cached
+    /// message from a sender. Returns how many documents changed label.
+    pub async fn train_spam_for_sender(
+        &self,
+        sender_email: &str,
+        label: Option<SpamLabel>,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let sender = sender_email.to_lowercase();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+
+            let mut documents = Vec::new();
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    SELECT id, sender_email, subject_encrypted, snippet_encrypted
+                    FROM messages
+                    WHERE sender_email = ?
+                    "#,
+                )?;
+                let mut rows = stmt.query(params![sender])?;
+                while let Some(row) = rows.next()? {
+                    let id: i64 = row.get(0)?;
+                    let sender_email: String = row.get(1)?;
+                    let subject = row
+                        .get::<_, Option<String>>(2)?
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()?
+                        .unwrap_or_default();
+                    let snippet = row
+                        .get::<_, Option<String>>(3)?
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()?;
+                    documents.push((
+                        format!("message:{id}"),
+                        spam::tokenize(&sender_email, &subject, snippet.as_deref()),
+                    ));
+                }
+            }
+
+            let mut changed = 0usize;
+            for (key, tokens) in documents {
+                if apply_spam_training(&tx, &cipher, &key, &tokens, label)? {
+                    changed += 1;
+                }
+            }
+
+            tx.commit()?;
+            Ok(changed)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Trains the spam model on documents that are not necessarily cached,
+    /// such as Junk folder headers. Keys must be stable so retraining is idempotent.
+    pub async fn train_spam_documents(
+        &self,
+        documents: Vec<(String, Vec<String>)>,
+        label: SpamLabel,
+    ) -> Result<usize> {
+        if documents.is_empty() {
+            return Ok(0);
+        }
+
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut changed = 0usize;
+            for (key, tokens) in documents {
+                if apply_spam_training(&tx, &cipher, &key, &tokens, Some(label))? {
+                    changed += 1;
+                }
+            }
+            tx.commit()?;
+            Ok(changed)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Scores cached messages with the current spam model. Only messages without
+    /// a score are touched unless `rescore_all` is set. Returns the number scored.
+    pub async fn refresh_spam_scores(
+        &self,
+        account_email: Option<&str>,
+        rescore_all: bool,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.map(|value| value.to_owned());
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let model = load_spam_model(&conn)?;
+            if !model.is_trained() {
+                return Ok(0);
+            }
+
+            let tx = conn.transaction()?;
+            let mut scores = Vec::new();
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    SELECT id, sender_email, subject_encrypted, snippet_encrypted
+                    FROM messages
+                    WHERE (?1 IS NULL OR account_email = ?1)
+                      AND (?2 != 0 OR spam_score IS NULL)
+                    "#,
+                )?;
+                let mut rows = stmt.query(params![account, rescore_all as i64])?;
+                while let Some(row) = rows.next()? {
+                    let id: i64 = row.get(0)?;
+                    let sender_email: String = row.get(1)?;
+                    let subject = row
+                        .get::<_, Option<String>>(2)?
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()?
+                        .unwrap_or_default();
+                    let snippet = row
+                        .get::<_, Option<String>>(3)?
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()?;
+                    let tokens = spam::tokenize(&sender_email, &subject, snippet.as_deref())
+                        .iter()
+                        .map(|token| cipher.digest(token))
+                        .collect::<Vec<_>>();
+                    if let Some(score) = model.score(&tokens) {
+                        scores.push((id, score));
+                    }
+                }
+            }
+
+            {
+                let mut stmt = tx.prepare("UPDATE messages SET spam_score = ? WHERE id = ?")?;
+                for (id, score) in &scores {
+                    stmt.execute(params![score, id])?;
+                }
+            }
+
+            tx.commit()?;
+            Ok(scores.len())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Replaces an account's topics and message assignments with a new clustering.
+    pub async fn replace_topics(
+        &self,
+        account_email: &str,
+        clusters: Vec<TopicCluster>,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+
+            tx.execute(
+                r#"
+                DELETE FROM topic_assignments
+                WHERE topic_id IN (SELECT id FROM topics WHERE account_email = ?)
+                "#,
+                params![account],
+            )?;
+            tx.execute("DELETE FROM topics WHERE account_email = ?", params![account])?;
+
+            let count = clusters.len();
+            for cluster in clusters {
+                let keywords = serde_json::to_string(&cluster.keywords)
+                    .map_err(|err| StorageError::Serialization(err.to_string()))?;
+                tx.execute(
+                    r#"
+                    INSERT INTO topics(
+                        account_email, label_encrypted, keywords_encrypted, message_count,
+                        created_at
+                    )
+                    VALUES(?, ?, ?, ?, ?)
+                    "#,
+                    params![
+                        account,
+                        cipher.encrypt_string(&cluster.label)?,
+                        cipher.encrypt_string(&keywords)?,
+                        cluster.members.len() as i64,
+                        now
+                    ],
+                )?;
+                let topic_id = tx.last_insert_rowid();
+
+                let mut stmt = tx.prepare_cached(
+                    r#"
+                    INSERT INTO topic_assignments(message_id, topic_id, similarity)
+                    VALUES(?, ?, ?)
+                    ON CONFLICT(message_id) DO UPDATE SET
+                        topic_id = excluded.topic_id,
+                        similarity = excluded.similarity
+                    "#,
+                )?;
+                for (message_id, similarity) in cluster.members {
+                    stmt.execute(params![message_id, topic_id, similarity as f64])?;
+                }
+            }
+
+            tx.commit()?;
+            Ok(count)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn list_topics(&self, account_email: &str) -> Result<Vec<TopicSummary>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<TopicSummary>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, account_email, label_encrypted, keywords_encrypted, message_count,
+                       created_at
+                FROM topics
+                WHERE account_email = ?
+                ORDER BY message_count DESC, id
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account])?;
+            let mut topics = Vec::new();
+            while let Some(row) = rows.next()? {
+                let label_enc: String = row.get(2)?;
+                let keywords_enc: String = row.get(3)?;
+                let keywords_json = cipher.decrypt_string(&keywords_enc)?;
+                let keywords = serde_json::from_str::<Vec<String>>(&keywords_json)
+                    .map_err(|err| StorageError::Serialization(err.to_string()))?;
+                topics.push(TopicSummary {
+                    id: row.get(0)?,
+                    account_email: row.get(1)?,
+                    label: cipher.decrypt_string(&label_enc)?,
+                    keywords,
+                    message_count: row.get::<_, i64>(4)?.max(0) as usize,
+                    created_at: row.get(5)?,
+                });
+            }
+            Ok(topics)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn topic_messages(&self, topic_id: i64, limit: usize) -> Result<Vec<TopicMessage>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let limit = limit.min(1000) as i64;
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<TopicMessage>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT m.uid, m.subject_encrypted, m.sender_email, m.sender_display, m.date,
+                       ta.similarity
+                FROM topic_assignments ta
+                JOIN messages m ON m.id = ta.message_id
+                WHERE ta.topic_id = ? AND m.tombstoned_at IS NULL
+                ORDER BY ta.similarity DESC, m.date DESC
+                LIMIT ?
+                "#,
+            )?;
+            let mut rows = stmt.query(params![topic_id, limit])?;
+            let mut messages = Vec::new();
+            while let Some(row) = rows.next()? {
+                let subject_enc: String = row.get(1)?;
+                messages.push(TopicMessage {
+                    uid: row.get(0)?,
+                    subject: cipher.decrypt_string(&subject_enc)?,
+                    sender_email: row.get(2)?,
+                    sender_display: row.get(3)?,
+                    date: row.get(4)?,
+                    similarity: row.get(5)?,
+                });
+            }
+            Ok(messages)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Every cached message from a contact across accounts, for relationship stats.
+    pub async fn contact_messages(&self, contact_email: &str) -> Result<Vec<ContactMessage>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let contact = contact_email.to_lowercase();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<ContactMessage>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT m.date, m.subject_encrypted, m.flags, ar.sentiment
+                FROM messages m
+                LEFT JOIN analysis_results ar ON ar.message_id = m.id
+                WHERE m.sender_email = ?
+                "#,
+            )?;
+            let mut rows = stmt.query(params![contact])?;
+            let mut messages = Vec::new();
+            while let Some(row) = rows.next()? {
+                let subject = row
+                    .get::<_, Option<String>>(1)?
+                    .map(|value| cipher.decrypt_string(&value))
+                    .transpose()?
+                    .unwrap_or_default();
+                messages.push(ContactMessage {
+                    date: row.get(0)?,
+                    subject,
+                    flags: row.get(2)?,
+                    sentiment: row.get(3)?,
+                });
+            }
+            Ok(messages)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Sent folder headers of mail to a contact, from every account.
+    pub async fn sent_to_contact(&self, contact_email: &str) -> Result<Vec<SentMessage>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let contact = contact_email.to_lowercase();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<SentMessage>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                "SELECT recipient, subject_encrypted, sent_at FROM sent_messages \
+                 WHERE lower(recipient) = ?",
+            )?;
+            let mut rows = stmt.query(params![contact])?;
+            let mut sent = Vec::new();
+            while let Some(row) = rows.next()? {
+                sent.push(SentMessage {
+                    recipient: row.get(0)?,
+                    subject: cipher.decrypt_string(&row.get::<_, String>(1)?)?,
+                    sent_at: row.get(2)?,
+                });
+            }
+            Ok(sent)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn save_relationship_stats(&self, stats: &RelationshipStats) -> Result<()> {
+        let conn = self.conn.clone();
+        let stats = stats.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                INSERT INTO relationship_stats (
+                    contact_email, message_count, thread_count, answered_count,
+                    first_contact_at, last_contact_at, user_reply_secs, contact_reply_secs,
+                    average_sentiment, recent_sentiment, sentiment_trend, computed_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(contact_email) DO UPDATE SET
+                    message_count = excluded.message_count,
+                    thread_count = excluded.thread_count,
+                    answered_count = excluded.answered_count,
+                    first_contact_at = excluded.first_contact_at,
+                    last_contact_at = excluded.last_contact_at,
+                    user_reply_secs = excluded.user_reply_secs,
+                    contact_reply_secs = excluded.contact_reply_secs,
+                    average_sentiment = excluded.average_sentiment,
+                    recent_sentiment = excluded.recent_sentiment,
+                    sentiment_trend = excluded.sentiment_trend,
+                    computed_at = excluded.computed_at
+                "#,
+                params![
+                    stats.contact_email,
+                    stats.message_count as i64,
+                    stats.thread_count as i64,
+                    stats.answered_count as i64,
+                    stats.first_contact_at,
+                    stats.last_contact_at,
+                    stats.user_reply_secs,
+                    stats.contact_reply_secs,
+                    stats.average_sentiment,
+                    stats.recent_sentiment,
+                    stats.sentiment_trend,
+                    stats.computed_at
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Cached quick replies for a message, with the model that generated them.
+    pub async fn reply_suggestions(
+        &self,
+        message_id: i64,
+    ) -> Result<Option<(Option<String>, Vec<ReplySuggestion>)>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(
+            move || -> Result<Option<(Option<String>, Vec<ReplySuggestion>)>> {
+                let conn = conn.lock();
+                let row: Option<(Option<String>, String)> = conn
+                    .query_row(
+                        r#"
+                        SELECT model_id, suggestions_encrypted
+                        FROM reply_suggestions
+                        WHERE message_id = ?
+                        "#,
+                        params![message_id],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .optional()?;
+
+                let Some((model_id, encrypted)) = row else {
+                    return Ok(None);
+                };
+                let json = cipher.decrypt_string(&encrypted)?;
+                let suggestions = serde_json::from_str::<Vec<ReplySuggestion>>(&json)
+                    .map_err(|err| StorageError::Serialization(err.to_string()))?;
+                Ok(Some((model_id, suggestions)))
+            },
+        )
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn save_reply_suggestions(
+        &self,
+        message_id: i64,
+        model_id: Option<&str>,
+        suggestions: &[ReplySuggestion],
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let model_id = model_id.map(str::to_owned);
+        let json = serde_json::to_string(suggestions)
+            .map_err(|err| StorageError::Serialization(err.to_string()))?;
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                INSERT INTO reply_suggestions (
+                    message_id, model_id, suggestions_encrypted, created_at
+                )
+                VALUES (?, ?, ?, ?)
+                ON CONFLICT(message_id) DO UPDATE SET
+                    model_id = excluded.model_id,
+                    suggestions_encrypted = excluded.suggestions_encrypted,
+                    created_at = excluded.created_at
+                "#,
+                params![
+                    message_id,
+                    model_id,
+                    cipher.encrypt_string(&json)?,
+                    Utc::now().timestamp()
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn list_templates(&self) -> Result<Vec<EmailTemplate>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<EmailTemplate>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, name, subject_encrypted, body_encrypted, created_at, updated_at
+                FROM templates
+                ORDER BY name COLLATE NOCASE, id
+                "#,
+            )?;
+            let mut rows = stmt.query([])?;
+            let mut templates = Vec::new();
+            while let Some(row) = rows.next()? {
+                templates.push(template_from_row(row, &cipher)?);
+            }
+            Ok(templates)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn template(&self, id: i64) -> Result<Option<EmailTemplate>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<EmailTemplate>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, name, subject_encrypted, body_encrypted, created_at, updated_at
+                FROM templates
+                WHERE id = ?
+                "#,
+            )?;
+            let mut rows = stmt.query(params![id])?;
+            match rows.next()? {
+                Some(row) => Ok(Some(template_from_row(row, &cipher)?)),
+                None => Ok(None),
+            }
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Inserts a template when `id` is `None`, otherwise updates it in place.
+    /// Returns the template id, or `None` when `id` does not exist.
+    pub async fn save_template(
+        &self,
+        id: Option<i64>,
+        name: &str,
+        subject: &str,
+        body: &str,
+    ) -> Result<Option<i64>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let name = name.to_owned();
+        let subject = subject.to_owned();
+        let body = body.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<i64>> {
+            let conn = conn.lock();
+            let now = Utc::now().timestamp();
+            let subject_enc = cipher.encrypt_string(&subject)?;
+            let body_enc = cipher.encrypt_string(&body)?;
+
+            match id {
+                Some(id) => {
+                    let updated = conn.execute(
+                        r#"
+                        UPDATE templates
+                        SET name = ?, subject_encrypted = ?, body_encrypted = ?, updated_at = ?
+                        WHERE id = ?
+                        "#,
+                        params![name, subject_enc, body_enc, now, id],
+                    )?;
+                    Ok((updated > 0).then_some(id))
+                }
+                None => {
+                    conn.execute(
+                        r#"
+                        INSERT INTO templates (
+                            name, subject_encrypted, body_encrypted, created_at, updated_at
+                        )
+                        VALUES (?, ?, ?, ?, ?)
+                        "#,
+                        params![name, subject_enc, body_enc, now, now],
+                    )?;
+                    Ok(Some(conn.last_insert_rowid()))
+                }
+            }
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn delete_template(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let conn = conn.lock();
+            let deleted = conn.execute("DELETE FROM templates WHERE id = ?", params![id])?;
+            Ok(deleted > 0)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn list_collections(&self) -> Result<Vec<Collection>> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<Collection>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT c.id, c.name, COUNT(i.uid), c.created_at, c.updated_at
+                FROM collections c
+                LEFT JOIN collection_items i ON i.collection_id = c.id
+                GROUP BY c.id
+                ORDER BY c.name COLLATE NOCASE, c.id
+                "#,
+            )?;
+            let mut rows = stmt.query([])?;
+            let mut collections = Vec::new();
+            while let Some(row) = rows.next()? {
+                collections.push(Collection {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    item_count: row.get::<_, i64>(2)? as usize,
+                    created_at: row.get(3)?,
+                    updated_at: row.get(4)?,
+                });
+            }
+            Ok(collections)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Creates a collection, or renames an existing one when `id` is given.
+    /// Returns `None` if `id` does not exist.
+    pub async fn save_collection(&self, id: Option<i64>, name: &str) -> Result<Option<i64>> {
+        let conn = self.conn.clone();
+        let name = name.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<i64>> {
+            let conn = conn.lock();
+            let now = Utc::now().timestamp();
+            match id {
+                Some(id) => {
+                    let updated = conn.execute(
+                        "UPDATE collections SET name = ?, updated_at = ? WHERE id = ?",
+                        params![name, now, id],
+                    )?;
+                    Ok((updated > 0).then_some(id))
+                }
+                None => {
+                    conn.execute(
+                        "INSERT INTO collections (name, created_at, updated_at) VALUES (?, ?, ?)",
+                        params![name, now, now],
+                    )?;
+                    Ok(Some(conn.last_insert_rowid()))
+                }
+            }
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn delete_collection(&self, id: i64) -> Result<bool> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM collection_items WHERE collection_id = ?",
+                params![id],
+            )?;
+            let deleted = tx.execute("DELETE FROM collections WHERE id = ?", params![id])?;
+            tx.commit()?;
+            Ok(deleted > 0)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Adds `(account_email, folder, uid)` messages to a collection, ignoring
+    /// ones it already holds, and returns how many were new.
+    pub async fn add_to_collection(
+        &self,
+        id: i64,
+        items: Vec<(String, String, u32)>,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut added = 0;
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    INSERT OR IGNORE INTO collection_items (
+                        collection_id, account_email, folder, uid, added_at
+                    ) VALUES (?, ?, ?, ?, ?)
+                    "#,
+                )?;
+                for (account, folder, uid) in items {
+                    added += stmt.execute(params![id, account, folder, uid, now])?;
+                }
+            }
+            tx.execute(
+                "UPDATE collections SET updated_at = ? WHERE id = ?",
+                params![now, id],
+            )?;
+            tx.commit()?;
+            Ok(added)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn remove_from_collection(
+        &self,
+        id: i64,
+        items: Vec<(String, String, u32)>,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut removed = 0;
+            for (account, folder, uid) in items {
+                removed += tx.execute(
+                    r#"
+                    DELETE FROM collection_items
+                    WHERE collection_id = ? AND account_email = ? AND folder = ? AND uid = ?
+                    "#,
+                    params![id, account, folder, uid],
+                )?;
+            }
+            tx.execute(
+                "UPDATE collections SET updated_at = ? WHERE id = ?",
+                params![now, id],
+            )?;
+            tx.commit()?;
+            Ok(removed)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Messages in a collection, most recently added first.
+    pub async fn collection_items(&self, id: i64) -> Result<Vec<CollectionItem>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<CollectionItem>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT i.account_email, i.uid, m.subject_encrypted, m.sender_email, m.date,
+                       i.added_at, i.folder
+                FROM collection_items i
+                LEFT JOIN messages m
+                    ON m.account_email = i.account_email AND m.folder = i.folder
+                    AND m.uid = i.uid
+                WHERE i.collection_id = ?
+                ORDER BY i.added_at DESC, i.rowid DESC
+                "#,
+            )?;
+            let mut rows = stmt.query(params![id])?;
+            let mut items = Vec::new();
+            while let Some(row) = rows.next()? {
+                let subject_enc: Option<String> = row.get(2)?;
+                items.push(CollectionItem {
+                    account_email: row.get(0)?,
+                    folder: row.get(6)?,
+                    uid: row.get(1)?,
+                    subject: subject_enc
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()?,
+                    sender_email: row.get(3)?,
+                    date: row.get(4)?,
+                    added_at: row.get(5)?,
+                });
+            }
+            Ok(items)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn record_audit(
+        &self,
+        account_email: Option<&str>,
+        action: &str,
+        target: Option<&str>,
+        detail: Value,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let account = account_email.map(str::to_owned);
+        let action = action.to_owned();
+        let target = target.map(str::to_owned);
+        let detail_json = serde_json::to_string(&detail)
+            .map_err(|err| StorageError::Serialization(err.to_string()))?;
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                INSERT INTO audit_log (account_email, action, target, detail_json, created_at)
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+                params![account, action, target, detail_json, Utc::now().timestamp()],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Most recent audit entries, optionally limited to one account.
+    pub async fn audit_log(
+        &self,
+        account_email: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<AuditEntry>> {
+        let conn = self.conn.clone();
+        let account = account_email.map(str::to_owned);
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<AuditEntry>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, account_email, action, target, detail_json, created_at
+                FROM audit_log
+                WHERE ?1 IS NULL OR account_email = ?1
+                ORDER BY created_at DESC, id DESC
+                LIMIT ?2
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, limit as i64])?;
+            let mut entries = Vec::new();
+            while let Some(row) = rows.next()? {
+                let detail_json: Option<String> = row.get(4)?;
+                let detail = detail_json
+                    .as_deref()
+                    .map(serde_json::from_str::<Value>)
+                    .transpose()
+                    .map_err(|err| StorageError::Serialization(err.to_string()))?
+                    .unwrap_or(Value::Null);
+                entries.push(AuditEntry {
+                    id: row.get(0)?,
+                    account_email: row.get(1)?,
+                    action: row.get(2)?,
+                    target: row.get(3)?,
+                    detail,
+                    created_at: row.get(5)?,
+                });
+            }
+            Ok(entries)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Records that `sender` is being auto-answered for this activation.
+    /// Returns `false` when the sender was already answered, which is what
+    /// keeps the responder to one reply per sender.
+    /// Records that a cached message also has a copy in `target_folder`.
+    pub async fn record_message_copy(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+        target_folder: &str,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let target_folder = target_folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                INSERT INTO message_folders (account_email, folder, uid, target_folder, copied_at)
+                VALUES (?, ?, ?, ?, ?)
+                ON CONFLICT(account_email, folder, uid, target_folder) DO UPDATE SET
+                    copied_at = excluded.copied_at
+                "#,
+                params![account, folder, uid, target_folder, Utc::now().timestamp()],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Other folders known to hold a copy of the message, oldest copy first.
+    pub async fn message_folders(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<Vec<String>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<String>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT target_folder FROM message_folders
+                WHERE account_email = ? AND folder = ? AND uid = ?
+                ORDER BY copied_at ASC
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, folder, uid])?;
+            let mut folders = Vec::new();
+            while let Some(row) = rows.next()? {
+                folders.push(row.get(0)?);
+            }
+            Ok(folders)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Keeps copy associations in step with a server-side folder rename or
+    /// delete. `to` of `None` drops the associations.
+    pub async fn update_message_folder(
+        &self,
+        account_email: &str,
+        from: &str,
+        to: Option<&str>,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let from = from.to_owned();
+        let to = to.map(str::to_owned);
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = conn.lock();
+            let changed = match to {
+                Some(to) => conn.execute(
+                    r#"
+                    UPDATE OR REPLACE message_folders SET target_folder = ?
+                    WHERE account_email = ? AND target_folder = ?
+                    "#,
+                    params![to, account, from],
+                )?,
+                None => conn.execute(
+                    "DELETE FROM message_folders WHERE account_email = ? AND target_folder = ?",
+                    params![account, from],
+                )?,
+            };
+            Ok(changed)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Returns the unfinished migration for this source/target pair, or starts
+    /// a new one at UID 0.
+    pub async fn start_or_resume_migration(
+        &self,
+        source_email: &str,
+        target_email: &str,
+        source_folder: &str,
+        target_folder: &str,
+    ) -> Result<AccountMigration> {
+        let conn = self.conn.clone();
+        let source_email = source_email.to_owned();
+        let target_email = target_email.to_owned();
+        let source_folder = source_folder.to_owned();
+        let target_folder = target_folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<AccountMigration> {
+            let conn = conn.lock();
+            let existing = conn
+                .query_row(
+                    r#"
+                    SELECT id FROM account_migrations
+                    WHERE source_email = ? AND target_email = ?
+                      AND source_folder = ? AND target_folder = ?
+                      AND status != 'completed'
+                    ORDER BY id DESC
+                    LIMIT 1
+                    "#,
+                    params![source_email, target_email, source_folder, target_folder],
+                    |row| row.get::<_, i64>(0),
+                )
+                .optional()?;
+
+            let now = Utc::now().timestamp();
+            let id = match existing {
+                Some(id) => {
+                    conn.execute(
+                        r#"
+                        UPDATE account_migrations
+                        SET status = 'running', error = NULL, updated_at = ?
+                        WHERE id = ?
+                        "#,
+                        params![now, id],
+                    )?;
+                    id
+                }
+                None => {
+                    conn.execute(
+                        r#"
+                        INSERT INTO account_migrations (
+                            source_email, target_email, source_folder, target_folder,
+                            status, created_at, updated_at
+                        )
+                        VALUES (?, ?, ?, ?, 'running', ?, ?)
+                        "#,
+                        params![
+                            source_email,
+                            target_email,
+                            source_folder,
+                            target_folder,
+                            now,
+                            now
+                        ],
+                    )?;
+                    conn.last_insert_rowid()
+                }
+            };
+
+            query_account_migration(&conn, id)?
+                .ok_or(StorageError::Database(rusqlite::Error::QueryReturnedNoRows))
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn account_migration(&self, id: i64) -> Result<Option<AccountMigration>> {
+        let conn = self.conn.clone();
+
+        let join_result =
+            tokio::task::spawn_blocking(move || -> Result<Option<AccountMigration>> {
+                let conn = conn.lock();
+                query_account_migration(&conn, id)
+            })
+            .await
+            .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Persists progress and status; called after every appended batch.
+    pub async fn save_migration(&self, migration: &AccountMigration) -> Result<()> {
+        let conn = self.conn.clone();
+        let migration = migration.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                UPDATE account_migrations
+                SET last_uid = ?, total = ?, copied = ?, failed = ?, failed_uids = ?,
+                    status = ?, error = ?, updated_at = ?
+                WHERE id = ?
+                "#,
+                params![
+                    migration.last_uid as i64,
+                    migration.total as i64,
+                    migration.copied as i64,
+                    migration.failed as i64,
+                    migration
+                        .failed_uids
+                        .iter()
+                        .map(u32::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    migration.status,
+                    migration.error,
+                    Utc::now().timestamp(),
+                    migration.id
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Stores recognized image text. An empty `text` still records that the
+    /// message was processed so it is not picked again.
+    pub async fn save_message_ocr(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+        text: &str,
+        image_count: usize,
+        engine: &str,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let text = text.to_owned();
+        let engine = engine.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let encrypted = cipher.encrypt_string(&text)?;
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                INSERT INTO message_ocr (
+                    account_email, folder, uid, text_encrypted, image_count, engine,
+                    created_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(account_email, folder, uid) DO UPDATE SET
+                    text_encrypted = excluded.text_encrypted,
+                    image_count = excluded.image_count,
+                    engine = excluded.engine,
+                    created_at = excluded.created_at
+                "#,
+                params![
+                    account,
+                    folder,
+                    uid,
+                    encrypted,
+                    image_count as i64,
+                    engine,
+                    Utc::now().timestamp()
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Replaces the attachments recorded for a message with `attachments`.
+    pub async fn save_message_attachments(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+        attachments: &[Attachment],
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let attachments = attachments.to_vec();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM message_attachments \
+                 WHERE account_email = ? AND folder = ? AND uid = ?",
+                params![account, folder, uid],
+            )?;
+            {
+                let mut insert = tx.prepare(
+                    r#"
+                    INSERT INTO message_attachments (
+                        account_email, folder, uid, position, filename_encrypted,
+                        content_type, size, risks
+                    ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )?;
+                for attachment in &attachments {
+                    let risks = serde_json::to_string(&attachment.risks)
+                        .map_err(|err| StorageError::Serialization(err.to_string()))?;
+                    insert.execute(params![
+                        account,
+                        folder,
+                        uid,
+                        attachment.index as i64,
+                        cipher.encrypt_string(&attachment.filename)?,
+                        attachment.content_type,
+                        attachment.size as i64,
+                        risks,
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Attachments recorded when the message was last fetched in full.
+    pub async fn message_attachments(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<Vec<Attachment>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<Attachment>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT position, filename_encrypted, content_type, size, risks
+                FROM message_attachments
+                WHERE account_email = ? AND folder = ? AND uid = ?
+                ORDER BY position
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, folder, uid])?;
+            let mut attachments = Vec::new();
+            while let Some(row) = rows.next()? {
+                let filename_enc: String = row.get(1)?;
+                let risks: String = row.get(4)?;
+                let risks: Vec<AttachmentRisk> = serde_json::from_str(&risks)
+                    .map_err(|err| StorageError::Serialization(err.to_string()))?;
+                attachments.push(Attachment {
+                    index: row.get::<_, i64>(0)? as usize,
+                    filename: cipher.decrypt_string(&filename_enc)?,
+                    content_type: row.get(2)?,
+                    size: row.get::<_, i64>(3)? as usize,
+                    risks,
+                });
+            }
+            Ok(attachments)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Scores cached messages for quarantine from their spam score, links,
+    /// and recorded attachments. Messages at or above the threshold are
+    /// quarantined; held messages that no longer score that high are let
+    /// out, and released ones stay released. Returns how many were newly
+    /// quarantined.
+    pub async fn refresh_quarantine(&self, account_email: Option<&str>) -> Result<usize> {
+        let conn = self.conn.clone();
+        let account = account_email.map(|value| value.to_owned());
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut assessed = Vec::new();
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    SELECT m.account_email, m.uid, m.spam_score,
+                        (SELECT COUNT(*) FROM message_links l
+                         WHERE l.account_email = m.account_email AND l.folder = m.folder
+                           AND l.uid = m.uid AND l.mismatch != 0),
+                        (SELECT COUNT(*) FROM message_links l
+                         WHERE l.account_email = m.account_email AND l.folder = m.folder
+                           AND l.uid = m.uid
+                           AND EXISTS(
+                               SELECT 1 FROM link_blocklist b
+                               WHERE l.domain = b.domain OR l.domain LIKE '%.' || b.domain
+                           )),
+                        (SELECT COUNT(*) FROM message_attachments a
+                         WHERE a.account_email = m.account_email AND a.folder = m.folder
+                           AND a.uid = m.uid AND a.risks != '[]'),
+                        m.folder
+                    FROM messages m
+                    WHERE (?1 IS NULL OR m.account_email = ?1)
+                      AND m.folder = 'INBOX'
+                      AND m.tombstoned_at IS NULL
+                    "#,
+                )?;
+                let mut rows = stmt.query(params![account])?;
+                while let Some(row) = rows.next()? {
+                    let signals = Signals {
+                        spam_score: row.get(2)?,
+                        deceptive_links: row.get::<_, i64>(3)? as usize,
+                        blocked_links: row.get::<_, i64>(4)? as usize,
+                        risky_attachments: row.get::<_, i64>(5)? as usize,
+                    };
+                    let account: String = row.get(0)?;
+                    let folder: String = row.get(6)?;
+                    let uid: u32 = row.get(1)?;
+                    assessed.push((account, folder, uid, quarantine::assess(&signals)));
+                }
+            }
+
+            let mut quarantined = 0;
+            {
+                let mut insert = tx.prepare(
+                    r#"
+                    INSERT INTO quarantine (
+                        account_email, folder, uid, score, reasons, quarantined_at
+                    )
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    ON CONFLICT(account_email, folder, uid) DO NOTHING
+                    "#,
+                )?;
+                let mut update = tx.prepare(
+                    "UPDATE quarantine SET score = ?, reasons = ? \
+                     WHERE account_email = ? AND folder = ? AND uid = ?",
+                )?;
+                let mut release = tx.prepare(
+                    "DELETE FROM quarantine \
+                     WHERE account_email = ? AND folder = ? AND uid = ? AND released_at IS NULL",
+                )?;
+                for (account, folder, uid, assessment) in &assessed {
+                    if !assessment.quarantined() {
+                        release.execute(params![account, folder, uid])?;
+                        continue;
+                    }
+                    let reasons = serde_json::to_string(&assessment.reasons)
+                        .map_err(|err| StorageError::Serialization(err.to_string()))?;
+                    let score = assessment.score;
+                    if insert.execute(params![account, folder, uid, score, reasons, now])? > 0 {
+                        quarantined += 1;
+                    } else {
+                        update.execute(params![score, reasons, account, folder, uid])?;
+                    }
+                }
+            }
+            tx.commit()?;
+            Ok(quarantined)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Messages held in quarantine, riskiest first.
+    pub async fn quarantined_messages(
+        &self,
+        account_email: &str,
+        fields: &FieldMask,
+    ) -> Result<Vec<QuarantinedMessage>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let decrypted = self.decrypted.clone();
+        let account = account_email.to_owned();
+        let fields = fields.clone();
+
+        let result = tokio::task::spawn_blocking(move || -> Result<Vec<QuarantinedMessage>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT {MESSAGE_ROW_COLUMNS}, q.score, q.reasons, q.quarantined_at
+                {MESSAGE_ROW_JOINS}
+                JOIN quarantine q
+                    ON q.account_email = m.account_email AND q.folder = m.folder
+                    AND q.uid = m.uid
+                WHERE m.account_email = ? AND m.tombstoned_at IS NULL AND q.released_at IS NULL
+                ORDER BY q.score DESC, m.date_ts DESC, m.id DESC
+                "#
+            ))?;
+            let mut rows = stmt.query(params![account])?;
+            let mut messages = Vec::new();
+            while let Some(row) = rows.next()? {
+                let reasons: String = row.get(MESSAGE_ROW_WIDTH + 1)?;
+                messages.push(QuarantinedMessage {
+                    message: message_row_from_row(row, &cipher, &decrypted, &fields)?,
+                    score: row.get(MESSAGE_ROW_WIDTH)?,
+                    reasons: serde_json::from_str(&reasons)
+                        .map_err(|err| StorageError::Serialization(err.to_string()))?,
+                    quarantined_at: row.get(MESSAGE_ROW_WIDTH + 2)?,
+                });
+            }
+            Ok(messages)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        result
+    }
+
+    /// Whether the message is held in quarantine.
+    pub async fn is_quarantined(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<bool> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let conn = conn.lock();
+            let held = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM quarantine \
+                 WHERE account_email = ? AND folder = ? AND uid = ? AND released_at IS NULL)",
+                params![account, folder, uid],
+                |row| row.get(0),
+            )?;
+            Ok(held)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Lets a message out of quarantine for good. Returns false when it was
+    /// not held.
+    pub async fn release_from_quarantine(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<bool> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let conn = conn.lock();
+            let released = conn.execute(
+                "UPDATE quarantine SET released_at = ? \
+                 WHERE account_email = ? AND folder = ? AND uid = ? AND released_at IS NULL",
+                params![Utc::now().timestamp(), account, folder, uid],
+            )?;
+            Ok(released > 0)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// The UIDs among `uids` not yet scanned for one-time codes.
+    pub async fn unscanned_for_one_time_codes(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uids: Vec<u32>,
+    ) -> Result<Vec<u32>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<u32>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                "SELECT EXISTS(SELECT 1 FROM one_time_codes \
+                 WHERE account_email = ? AND folder = ? AND uid = ?)",
+            )?;
+            let mut unscanned = Vec::new();
+            for uid in uids {
+                let scanned: bool =
+                    stmt.query_row(params![account, folder, uid], |row| row.get(0))?;
+                if !scanned {
+                    unscanned.push(uid);
+                }
+            }
+            Ok(unscanned)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Records that a message was scanned, with the code or reset link
+    /// found in it. Returns false when it was scanned before.
+    pub async fn record_one_time_code(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+        detection: Option<&Detection>,
+        expires_at: i64,
+    ) -> Result<bool> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let code = detection
+            .and_then(|detection| detection.code.as_deref())
+            .map(|code| self.cipher.encrypt_string(code))
+            .transpose()?;
+        let link = detection
+            .and_then(|detection| detection.link.as_deref())
+            .map(|link| self.cipher.encrypt_string(link))
+            .transpose()?;
+        let kind = detection.map(|detection| detection.kind.as_str());
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let now = Utc::now().timestamp();
+            let conn = conn.lock();
+            let inserted = conn.execute(
+                r#"
+                INSERT OR IGNORE INTO one_time_codes (
+                    account_email, folder, uid, kind, code_encrypted, link_encrypted, received_at,
+                    expires_at
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+                params![account, folder, uid, kind, code, link, now, expires_at],
+            )?;
+            Ok(inserted > 0)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Codes and links that have not expired, newest first.
+    pub async fn one_time_codes(&self, account_email: Option<&str>) -> Result<Vec<OneTimeCode>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.map(|value| value.to_owned());
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<OneTimeCode>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT c.account_email, c.uid, c.kind, c.code_encrypted, c.link_encrypted,
+                       m.sender_email, m.subject_encrypted, c.received_at, c.expires_at,
+                       c.folder
+                FROM one_time_codes c
+                JOIN messages m
+                    ON m.account_email = c.account_email
+                   AND m.folder = c.folder
+                   AND m.uid = c.uid
+                WHERE (?1 IS NULL OR c.account_email = ?1)
+                  AND c.kind IS NOT NULL
+                  AND c.expires_at > ?2
+                  AND m.tombstoned_at IS NULL
+                ORDER BY c.received_at DESC
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, Utc::now().timestamp()])?;
+            let mut codes = Vec::new();
+            while let Some(row) = rows.next()? {
+                let decrypt = |index: usize| -> Result<Option<String>> {
+                    row.get::<_, Option<String>>(index)?
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()
+                };
+                let Some(kind) = OtpKind::parse(&row.get::<_, String>(2)?) else {
+                    continue;
+                };
+                codes.push(OneTimeCode {
+                    account_email: row.get(0)?,
+                    folder: row.get(9)?,
+                    uid: row.get(1)?,
+                    kind,
+                    code: decrypt(3)?,
+                    link: decrypt(4)?,
+                    sender_email: row.get(5)?,
+                    subject: decrypt(6)?.unwrap_or_default(),
+                    received_at: row.get(7)?,
+                    expires_at: row.get(8)?,
+                });
+            }
+            Ok(codes)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Erases codes and links that expired by `now`, masking the code in
+    /// the cached snippet too. Returns how many were erased.
+    pub async fn expire_one_time_codes(&self, now: i64) -> Result<usize> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let decrypted = self.decrypted.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut expired = Vec::new();
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    SELECT c.account_email, c.folder, c.uid, c.code_encrypted,
+                           m.snippet_encrypted
+                    FROM one_time_codes c
+                    LEFT JOIN messages m
+                        ON m.account_email = c.account_email
+                       AND m.folder = c.folder
+                       AND m.uid = c.uid
+                    WHERE c.expires_at <= ?
+                      AND (c.code_encrypted IS NOT NULL OR c.link_encrypted IS NOT NULL)
+                    "#,
+                )?;
+                let mut rows = stmt.query(params![now])?;
+                while let Some(row) = rows.next()? {
+                    let account: String = row.get(0)?;
+                    let folder: String = row.get(1)?;
+                    let uid: u32 = row.get(2)?;
+                    let code = row
+                        .get::<_, Option<String>>(3)?
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()?;
+                    let snippet = row
+                        .get::<_, Option<String>>(4)?
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()?;
+                    let redacted = code
+                        .zip(snippet)
+                        .and_then(|(code, snippet)| otp::redact_code(&snippet, &code))
+                        .map(|snippet| cipher.encrypt_string(&snippet))
+                        .transpose()?;
+                    expired.push((account, folder, uid, redacted));
+                }
+            }
+            for (account, folder, uid, redacted) in &expired {
+                tx.execute(
+                    "UPDATE one_time_codes SET code_encrypted = NULL, link_encrypted = NULL \
+                     WHERE account_email = ? AND folder = ? AND uid = ?",
+                    params![account, folder, uid],
+                )?;
+                if let Some(snippet) = redacted {
+                    tx.execute(
+                        "UPDATE messages SET snippet_encrypted = ? \
+                         WHERE account_email = ? AND folder = ? AND uid = ?",
+                        params![snippet, account, folder, uid],
+                    )?;
+                }
+            }
+            tx.commit()?;
+            for (account, folder, uid, _) in &expired {
+                decrypted.invalidate(account, folder, *uid);
+            }
+            Ok(expired.len())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Of the given INBOX UIDs, those not yet read for calendar invitations.
+    pub async fn unscanned_for_calendar(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uids: Vec<u32>,
+    ) -> Result<Vec<u32>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<u32>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                "SELECT 1 FROM calendar_scans WHERE account_email = ? AND folder = ? AND uid = ?",
+            )?;
+            let mut unscanned = Vec::new();
+            for uid in uids {
+                if !stmt.exists(params![account, folder, uid])? {
+                    unscanned.push(uid);
+                }
+            }
+            Ok(unscanned)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Marks a message as read for invitations and stores its events. An
+    /// event already stored is replaced unless the stored version is newer.
+    /// Returns how many events were stored.
+    pub async fn record_calendar_scan(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+        events: Vec<CalendarEvent>,
+    ) -> Result<usize> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT OR REPLACE INTO calendar_scans (account_email, folder, uid, checked_at) \
+                 VALUES (?, ?, ?, ?)",
+                params![account, folder, uid, Utc::now().timestamp()],
+            )?;
+            let mut stored = 0;
+            for event in &events {
+                let summary = cipher.encrypt_string(&event.summary)?;
+                let location = event
+                    .location
+                    .as_deref()
+                    .map(|location| cipher.encrypt_string(location))
+                    .transpose()?;
+                stored += tx.execute(
+                    r#"
+                    INSERT INTO calendar_events (
+                        account_email, event_uid, uid, sequence, summary_encrypted,
+                        location_encrypted, starts_at, ends_at, all_day, status, busy
+                    )
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                    ON CONFLICT(account_email, event_uid) DO UPDATE SET
+                        uid = excluded.uid,
+                        sequence = excluded.sequence,
+                        summary_encrypted = excluded.summary_encrypted,
+                        location_encrypted = excluded.location_encrypted,
+                        starts_at = excluded.starts_at,
+                        ends_at = excluded.ends_at,
+                        all_day = excluded.all_day,
+                        status = excluded.status,
+                        busy = excluded.busy
+                    WHERE excluded.sequence >= calendar_events.sequence
+                    "#,
+                    params![
+                        account,
+                        event.event_uid,
+                        uid,
+                        event.sequence,
+                        summary,
+                        location,
+                        event.starts_at,
+                        event.ends_at,
+                        event.all_day,
+                        event.status.as_str(),
+                        event.busy,
+                    ],
+                )?;
+            }
+            tx.commit()?;
+            Ok(stored)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Events overlapping `start..end`, earliest first, for one account or
+    /// all. Cancelled events are left out.
+    pub async fn calendar_events_between(
+        &self,
+        account_email: Option<&str>,
+        start: i64,
+        end: i64,
+    ) -> Result<Vec<CalendarEvent>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.map(|value| value.to_owned());
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<CalendarEvent>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT event_uid, sequence, summary_encrypted, location_encrypted,
+                       starts_at, ends_at, all_day, status, busy
+                FROM calendar_events
+                WHERE (?1 IS NULL OR account_email = ?1)
+                  AND starts_at < ?3
+                  AND ends_at > ?2
+                  AND status != 'cancelled'
+                ORDER BY starts_at, ends_at
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, start, end])?;
+            let mut events = Vec::new();
+            while let Some(row) = rows.next()? {
+                let decrypt = |index: usize| -> Result<Option<String>> {
+                    row.get::<_, Option<String>>(index)?
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()
+                };
+                let Some(status) = EventStatus::parse(&row.get::<_, String>(7)?) else {
+                    continue;
+                };
+                events.push(CalendarEvent {
+                    event_uid: row.get(0)?,
+                    sequence: row.get(1)?,
+                    summary: decrypt(2)?.unwrap_or_default(),
+                    location: decrypt(3)?,
+                    starts_at: row.get(4)?,
+                    ends_at: row.get(5)?,
+                    all_day: row.get(6)?,
+                    status,
+                    busy: row.get(8)?,
+                });
+            }
+            Ok(events)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Events the CalDAV calendar does not hold yet, or holds an older
+    /// version of, oldest first, with their account. Cancellations are only
+    /// pushed for events that were pushed before.
+    pub async fn calendar_events_to_push(
+        &self,
+        account_email: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, CalendarEvent)>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.map(|value| value.to_owned());
+
+        let result =
+            tokio::task::spawn_blocking(move || -> Result<Vec<(String, CalendarEvent)>> {
+                let conn = conn.lock();
+                let mut stmt = conn.prepare(
+                    r#"
+                SELECT e.account_email, e.event_uid, e.sequence, e.summary_encrypted,
+                       e.location_encrypted, e.starts_at, e.ends_at, e.all_day, e.status, e.busy
+                FROM calendar_events e
+                LEFT JOIN caldav_pushes p
+                    ON p.account_email = e.account_email
+                   AND p.event_uid = e.event_uid
+                WHERE (?1 IS NULL OR e.account_email = ?1)
+                  AND (p.event_uid IS NULL OR p.sequence < e.sequence)
+                  AND (e.status != 'cancelled' OR p.event_uid IS NOT NULL)
+                ORDER BY e.starts_at
+                LIMIT ?2
+                "#,
+                )?;
+                let mut rows = stmt.query(params![account, limit as i64])?;
+                let mut events = Vec::new();
+                while let Some(row) = rows.next()? {
+                    let decrypt = |index: usize| -> Result<Option<String>> {
+                        row.get::<_, Option<String>>(index)?
+                            .map(|value| cipher.decrypt_string(&value))
+                            .transpose()
+                    };
+                    let Some(status) = EventStatus::parse(&row.get::<_, String>(8)?) else {
+                        continue;
+                    };
+                    let event = CalendarEvent {
+                        event_uid: row.get(1)?,
+                        sequence: row.get(2)?,
+                        summary: decrypt(3)?.unwrap_or_default(),
+                        location: decrypt(4)?,
+                        starts_at: row.get(5)?,
+                        ends_at: row.get(6)?,
+                        all_day: row.get(7)?,
+                        status,
+                        busy: row.get(9)?,
+                    };
+                    events.push((row.get(0)?, event));
+                }
+                Ok(events)
+            })
+            .await
+            .map_err(map_join_error)?;
+
+        result
+    }
+
+    /// Logs a push attempt and, when the calendar now holds the event,
+    /// remembers which version it holds.
+    pub async fn record_caldav_push(
+        &self,
+        account_email: &str,
+        event: &CalendarEvent,
+        outcome: PushOutcome,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let event_uid = event.event_uid.clone();
+        let sequence = event.sequence;
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            if outcome.status != PushStatus::Failed {
+                tx.execute(
+                    r#"
+                    INSERT OR REPLACE INTO caldav_pushes (
+                        account_email, event_uid, sequence, etag, pushed_at
+                    )
+                    VALUES (?, ?, ?, ?, ?)
+                    "#,
+                    params![account, event_uid, sequence, outcome.etag, now],
+                )?;
+            }
+            tx.execute(
+                r#"
+                INSERT INTO caldav_sync_log (
+                    account_email, event_uid, sequence, status, detail, logged_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?)
+                "#,
+                params![
+                    account,
+                    event_uid,
+                    sequence,
+                    outcome.status.as_str(),
+                    outcome.detail,
+                    now
+                ],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn caldav_sync_log(&self, limit: usize) -> Result<Vec<CalDavLogEntry>> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<CalDavLogEntry>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT account_email, event_uid, sequence, status, detail, logged_at
+                FROM caldav_sync_log
+                ORDER BY logged_at DESC, id DESC
+                LIMIT ?
+                "#,
+            )?;
+            let mut rows = stmt.query(params![limit as i64])?;
+            let mut entries = Vec::new();
+            while let Some(row) = rows.next()? {
+                let Some(status) = PushStatus::parse(&row.get::<_, String>(3)?) else {
+                    continue;
+                };
+                entries.push(CalDavLogEntry {
+                    account_email: row.get(0)?,
+                    event_uid: row.get(1)?,
+                    sequence: row.get(2)?,
+                    status,
+                    detail: row.get(4)?,
+                    logged_at: row.get(5)?,
+                });
+            }
+            Ok(entries)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Recognized image text, or `None` if OCR has not run or found nothing.
+    pub async fn message_ocr_text(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<Option<String>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<String>> {
+            let conn = conn.lock();
+            let encrypted: Option<String> = conn
+                .query_row(
+                    "SELECT text_encrypted FROM message_ocr \
+                     WHERE account_email = ? AND folder = ? AND uid = ?",
+                    params![account, folder, uid],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let text = encrypted
+                .map(|value| cipher.decrypt_string(&value))
+                .transpose()?;
+            Ok(text.filter(|value| !value.trim().is_empty()))
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Newest messages with little or no text that OCR has not processed.
+    pub async fn ocr_candidates(
+        &self,
+        account_email: &str,
+        folder: &str,
+        max_snippet_chars: usize,
+        limit: usize,
+    ) -> Result<Vec<u32>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<u32>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT m.uid, m.snippet_encrypted
+                FROM messages m
+                LEFT JOIN message_ocr o
+                    ON o.account_email = m.account_email AND o.folder = m.folder
+                    AND o.uid = m.uid
+                WHERE m.account_email = ? AND m.folder = ? AND o.uid IS NULL
+                    AND m.tombstoned_at IS NULL
+                ORDER BY m.updated_at DESC, m.id DESC
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, folder])?;
+            let mut uids = Vec::new();
+            while let Some(row) = rows.next()? {
+                if uids.len() >= limit {
+                    break;
+                }
+                let uid: u32 = row.get(0)?;
+                let snippet_enc: Option<String> = row.get(1)?;
+                let snippet_len = snippet_enc
+                    .map(|value| cipher.decrypt_string(&value))
+                    .transpose()?
+                    .map_or(0, |snippet| snippet.trim().len());
+                if snippet_len < max_snippet_chars {
+                    uids.push(uid);
+                }
+            }
+            Ok(uids)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Newest messages whose attachments have not been indexed, those known
+    /// to have attachments first.
+    pub async fn attachment_index_candidates(
+        &self,
+        account_email: &str,
+        folder: &str,
+        limit: usize,
+    ) -> Result<Vec<u32>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<u32>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT m.uid
+                FROM messages m
+                WHERE m.account_email = ?1 AND m.folder = ?2 AND m.attachments_indexed = 0
+                    AND m.tombstoned_at IS NULL
+                ORDER BY EXISTS (
+                        SELECT 1 FROM message_attachments a
+                        WHERE a.account_email = m.account_email AND a.folder = m.folder
+                            AND a.uid = m.uid
+                    ) DESC,
+                    m.updated_at DESC, m.id DESC
+                LIMIT ?3
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, folder, limit as i64])?;
+            let mut uids = Vec::new();
+            while let Some(row) = rows.next()? {
+                uids.push(row.get(0)?);
+            }
+            Ok(uids)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Stores the text read from a message's attachments and marks it
+    /// indexed. An empty `text` only marks it.
+    pub async fn save_attachment_text(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+        text: &str,
+        documents: usize,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+        let text = text.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let encrypted = if text.is_empty() {
+                None
+            } else {
+                Some(cipher.encrypt_string(&text)?)
+            };
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            match encrypted {
+                Some(encrypted) => tx.execute(
+                    r#"
+                    INSERT INTO attachment_text (
+                        account_email, folder, uid, text_encrypted, documents, indexed_at
+                    )
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    ON CONFLICT(account_email, folder, uid) DO UPDATE SET
+                        text_encrypted = excluded.text_encrypted,
+                        documents = excluded.documents,
+                        indexed_at = excluded.indexed_at
+                    "#,
+                    params![
+                        account,
+                        folder,
+                        uid,
+                        encrypted,
+                        documents as i64,
+                        Utc::now().timestamp()
+                    ],
+                )?,
+                None => tx.execute(
+                    "DELETE FROM attachment_text \
+                     WHERE account_email = ? AND folder = ? AND uid = ?",
+                    params![account, folder, uid],
+                )?,
+            };
+            tx.execute(
+                "UPDATE messages SET attachments_indexed = 1 \
+                 WHERE account_email = ? AND folder = ? AND uid = ?",
+                params![account, folder, uid],
+            )?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// The `cid:` images stored for a message.
+    pub async fn inline_images(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<Vec<InlineImage>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<InlineImage>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT content_id, content_type, data_encrypted
+                FROM inline_images
+                WHERE account_email = ? AND folder = ? AND uid = ?
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, folder, uid])?;
+            let mut images = Vec::new();
+            while let Some(row) = rows.next()? {
+                let encrypted: String = row.get(2)?;
+                images.push(InlineImage {
+                    content_id: row.get(0)?,
+                    content_type: row.get(1)?,
+                    data: cipher.decrypt_bytes(&encrypted)?,
+                });
+            }
+            Ok(images)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Replaces the `cid:` images stored for a message.
+    pub async fn save_inline_images(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+        images: Vec<InlineImage>,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let encrypted = images
+                .iter()
+                .map(|image| cipher.encrypt_bytes(&image.data))
+                .collect::<Result<Vec<_>>>()?;
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            tx.execute(
+                "DELETE FROM inline_images \
+                 WHERE account_email = ? AND folder = ? AND uid = ?",
+                params![account, folder, uid],
+            )?;
+            for (image, data) in images.iter().zip(encrypted) {
+                tx.execute(
+                    r#"
+                    INSERT OR REPLACE INTO inline_images (
+                        account_email, folder, uid, content_id, content_type, data_encrypted
+                    )
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    "#,
+                    params![
+                        account,
+                        folder,
+                        uid,
+                        image.content_id,
+                        image.content_type,
+                        data
+                    ],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Links extracted from a message body, in the order they appear.
+    pub async fn message_links(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<Vec<MessageLink>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<MessageLink>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT
+                    l.url_encrypted,
+                    l.domain,
+                    l.display_text_encrypted,
+                    l.mismatch,
+                    EXISTS(
+                        SELECT 1 FROM link_blocklist b
+                        WHERE l.domain = b.domain OR l.domain LIKE '%.' || b.domain
+                    )
+                FROM message_links l
+                WHERE l.account_email = ? AND l.folder = ? AND l.uid = ?
+                ORDER BY l.position
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, folder, uid])?;
+            let mut links = Vec::new();
+            while let Some(row) = rows.next()? {
+                let url_enc: String = row.get(0)?;
+                let display_enc: Option<String> = row.get(2)?;
+                links.push(MessageLink {
+                    url: cipher.decrypt_string(&url_enc)?,
+                    domain: row.get(1)?,
+                    display_text: display_enc
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()?,
+                    mismatch: row.get::<_, i64>(3)? != 0,
+                    blocked: row.get::<_, i64>(4)? != 0,
+                });
+            }
+            Ok(links)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Trackers found in a message body, or `None` if no body has been
+    /// cached since detection was added.
+    pub async fn message_trackers(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+    ) -> Result<Option<Vec<Tracker>>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<Vec<Tracker>>> {
+            let conn = conn.lock();
+            let raw: Option<String> = conn
+                .query_row(
+                    "SELECT trackers_detected FROM messages \
+                     WHERE account_email = ? AND folder = ? AND uid = ?",
+                    params![account, folder, uid],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .flatten();
+            raw.map(|json| {
+                serde_json::from_str(&json)
+                    .map_err(|err| StorageError::Serialization(err.to_string()))
+            })
+            .transpose()
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Whether remote images are shown for mail from `sender_email`.
+    pub async fn images_allowed_for_sender(&self, sender_email: &str) -> Result<bool> {
+        let conn = self.conn.clone();
+        let email = sender_email.to_lowercase();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let conn = conn.lock();
+            let allowed = conn
+                .query_row(
+                    "SELECT 1 FROM image_allowlist WHERE sender_email = ?",
+                    params![email],
+                    |_| Ok(()),
+                )
+                .optional()?;
+            Ok(allowed.is_some())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn set_images_allowed_for_sender(
+        &self,
+        sender_email: &str,
+        allowed: bool,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let email = sender_email.to_lowercase();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            if allowed {
+                conn.execute(
+                    "INSERT OR IGNORE INTO image_allowlist (sender_email, added_at) VALUES (?, ?)",
+                    params![email, Utc::now().timestamp()],
+                )?;
+            } else {
+                conn.execute(
+                    "DELETE FROM image_allowlist WHERE sender_email = ?",
+                    params![email],
+                )?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn image_allowlist(&self) -> Result<Vec<String>> {
+        let conn = self.conn.clone();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<String>> {
+            let conn = conn.lock();
+            let mut stmt =
+                conn.prepare("SELECT sender_email FROM image_allowlist ORDER BY sender_email")?;
+            let mut rows = stmt.query([])?;
+            let mut senders = Vec::new();
+            while let Some(row) = rows.next()? {
+                senders.push(row.get(0)?);
+            }
+            Ok(senders)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn link_blocklist(&self) -> Result<Vec<String>> {
+        let conn = self.conn.clone();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<String>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare("SELECT domain FROM link_blocklist ORDER BY domain")?;
+            let mut rows = stmt.query([])?;
+            let mut domains = Vec::new();
+            while let Some(row) = rows.next()? {
+                domains.push(row.get(0)?);
+            }
+            Ok(domains)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Replaces the blocklist. Domains are expected to be normalized already.
+    pub async fn set_link_blocklist(&self, domains: Vec<String>) -> Result<()> {
+        let conn = self.conn.clone();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM link_blocklist", [])?;
+            for domain in domains {
+                tx.execute(
+                    "INSERT OR IGNORE INTO link_blocklist (domain, added_at) VALUES (?, ?)",
+                    params![domain, now],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn claim_autoreply(
+        &self,
+        account_email: &str,
+        sender_email: &str,
+        activation: i64,
+        uid: u32,
+    ) -> Result<bool> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let sender = sender_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let conn = conn.lock();
+            let inserted = conn.execute(
+                r#"
+                INSERT OR IGNORE INTO autoreply_log (
+                    account_email, sender_email, activation, uid, replied_at
+                )
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+                params![account, sender, activation, uid, Utc::now().timestamp()],
+            )?;
+            Ok(inserted > 0)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn autoreply_log(
+        &self,
+        account_email: &str,
+        limit: usize,
+    ) -> Result<Vec<AutoReplyLogEntry>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<AutoReplyLogEntry>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT sender_email, CAST(uid AS INTEGER), activation, replied_at
+                FROM autoreply_log
+                WHERE account_email = ?
+                ORDER BY replied_at DESC
+                LIMIT ?
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, limit as i64])?;
+            let mut entries = Vec::new();
+            while let Some(row) = rows.next()? {
+                entries.push(AutoReplyLogEntry {
+                    sender_email: row.get(0)?,
+                    uid: row.get(1)?,
+                    activation: row.get(2)?,
+                    replied_at: row.get(3)?,
+                });
+            }
+            Ok(entries)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn create_mail_merge(
+        &self,
+        account_email: &str,
+        subject_template: &str,
+        body_template: &str,
+        rate_per_minute: u32,
+    ) -> Result<i64> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let subject_template = subject_template.to_owned();
+        let body_template = body_template.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<i64> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                INSERT INTO mail_merges (
+                    account_email, subject_template_encrypted, body_template_encrypted,
+                    rate_per_minute, created_at
+                )
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+                params![
+                    account,
+                    cipher.encrypt_string(&subject_template)?,
+                    cipher.encrypt_string(&body_template)?,
+                    rate_per_minute as i64,
+                    Utc::now().timestamp()
+                ],
+            )?;
+            Ok(conn.last_insert_rowid())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn enqueue_outbox(&self, rows: Vec<OutboxInsert>) -> Result<usize> {
+        if rows.is_empty() {
+            return Ok(0);
+        }
+
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let count = rows.len();
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    INSERT INTO outbox (
+                        account_email, merge_id, recipient, subject_encrypted, body_encrypted,
+                        status, scheduled_at, created_at, updated_at, message_id, uploads_json,
+                        in_reply_to, reference_ids, auto_submitted
+                    )
+                    VALUES (?, ?, ?, ?, ?, 'queued', ?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )?;
+                let mut attachment_stmt = tx.prepare(
+                    r#"
+                    INSERT INTO outbox_attachments (
+                        outbox_id, filename, content_type, data_encrypted
+                    )
+                    VALUES (?, ?, ?, ?)
+                    "#,
+                )?;
+                for row in rows {
+                    let message_id = new_message_id(&row.account_email);
+                    let uploads = uploads_json(&row.uploads)?;
+                    let outbox_id = stmt.insert(params![
+                        row.account_email,
+                        row.merge_id,
+                        row.recipient,
+                        cipher.encrypt_string(&row.subject)?,
+                        cipher.encrypt_string(&row.body)?,
+                        row.scheduled_at,
+                        now,
+                        now,
+                        message_id,
+                        uploads,
+                        row.in_reply_to,
+                        reference_ids(&row.references),
+                        row.auto_submitted
+                    ])?;
+                    for attachment in row.attachments {
+                        attachment_stmt.execute(params![
+                            outbox_id,
+                            attachment.filename,
+                            attachment.content_type,
+                            cipher.encrypt_bytes(&attachment.data)?
+                        ])?;
+                    }
+                }
+            }
+            tx.commit()?;
+            Ok(count)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Records a message being sent right away, outside the queue, and
+    /// returns its outbox id and the Message-ID to send it with. Its
+    /// attachments go out with it and are not kept.
+    pub async fn record_send(&self, row: OutboxInsert) -> Result<(i64, String)> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<(i64, String)> {
+            let now = Utc::now().timestamp();
+            let message_id = new_message_id(&row.account_email);
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                INSERT INTO outbox (
+                    account_email, merge_id, recipient, subject_encrypted, body_encrypted,
+                    status, attempts, scheduled_at, created_at, updated_at, message_id,
+                    uploads_json, in_reply_to, reference_ids, auto_submitted
+                )
+                VALUES (?, ?, ?, ?, ?, 'sending', 1, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+                params![
+                    row.account_email,
+                    row.merge_id,
+                    row.recipient,
+                    cipher.encrypt_string(&row.subject)?,
+                    cipher.encrypt_string(&row.body)?,
+                    row.scheduled_at,
+                    now,
+                    now,
+                    message_id,
+                    uploads_json(&row.uploads)?,
+                    row.in_reply_to,
+                    reference_ids(&row.references),
+                    row.auto_submitted
+                ],
+            )?;
+            Ok((conn.last_insert_rowid(), message_id))
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn mail_merge_status(&self, merge_id: i64) -> Result<Option<MailMergeStatus>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<MailMergeStatus>> {
+            let conn = conn.lock();
+            let merge: Option<(String, i64, i64)> = conn
+                .query_row(
+                    "SELECT account_email, rate_per_minute, created_at FROM mail_merges WHERE id = ?",
+                    params![merge_id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .optional()?;
+            let Some((account_email, rate_per_minute, created_at)) = merge else {
+                return Ok(None);
+            };
+
+            let mut stmt = conn.prepare(&format!(
+                r#"
+                SELECT id, account_email, merge_id, recipient, subject_encrypted, status,
+                       attempts, last_error, scheduled_at, updated_at, message_id,
+                       {OUTBOX_DELIVERY_STATUS}, uploads_json, delivery_path
+                FROM outbox
+                WHERE merge_id = ?
+                ORDER BY scheduled_at, id
+                "#
+            ))?;
+            let mut rows = stmt.query(params![merge_id])?;
+            let mut items = Vec::new();
+            while let Some(row) = rows.next()? {
+                items.push(outbox_item_from_row(row, &cipher)?);
+            }
+
+            let count = |status: &str| items.iter().filter(|item| item.status == status).count();
+            Ok(Some(MailMergeStatus {
+                id: merge_id,
+                account_email,
+                rate_per_minute: rate_per_minute.max(0) as u32,
+                created_at,
+                total: items.len(),
+                queued: count("queued") + count("sending"),
+                sent: count("sent"),
+                failed: count("failed"),
+                cancelled: count("cancelled"),
+                items,
+            }))
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Of the given INBOX UIDs, those not yet checked for a delivery report.
+    pub async fn unscanned_bounce_uids(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uids: Vec<u32>,
+    ) -> Result<Vec<u32>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<u32>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                "SELECT 1 FROM bounce_scans WHERE account_email = ? AND folder = ? AND uid = ?",
+            )?;
+            let mut unscanned = Vec::new();
+            for uid in uids {
+                if !stmt.exists(params![account, folder, uid])? {
+                    unscanned.push(uid);
+                }
+            }
+            Ok(unscanned)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Marks a message as checked and stores the failures its report names,
+    /// each linked to the outbox entry it answers: by Message-ID when the
+    /// report quotes the original headers, otherwise by the most recent
+    /// entry sent to that recipient (with the same subject, if known).
+    /// Returns the bounces added.
+    pub async fn record_bounce(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uid: u32,
+        report: Option<DeliveryReport>,
+    ) -> Result<Vec<Bounce>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<Bounce>> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT OR REPLACE INTO bounce_scans (account_email, folder, uid, checked_at) \
+                 VALUES (?, ?, ?, ?)",
+                params![account, folder, uid, now],
+            )?;
+            let mut added = Vec::new();
+            if let Some(report) = report {
+                let original_subject = report
+                    .original_subject
+                    .as_deref()
+                    .map(|subject| cipher.encrypt_string(subject))
+                    .transpose()?;
+                for failure in &report.failed {
+                    let outbox_id = match_outbox(&tx, &cipher, &account, &report, &failure.email)?;
+                    let inserted = tx.execute(
+                        r#"
+                        INSERT OR IGNORE INTO bounces (
+                            account_email, uid, recipient, status_code, diagnostic,
+                            original_subject_encrypted, outbox_id, detected_at
+                        )
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                        "#,
+                        params![
+                            account,
+                            uid,
+                            failure.email,
+                            failure.status,
+                            failure.diagnostic,
+                            original_subject,
+                            outbox_id,
+                            now
+                        ],
+                    )?;
+                    if inserted > 0 {
+                        added.push(Bounce {
+                            id: tx.last_insert_rowid(),
+                            account_email: account.clone(),
+                            uid,
+                            recipient: failure.email.clone(),
+                            status_code: failure.status.clone(),
+                            diagnostic: failure.diagnostic.clone(),
+                            original_subject: report.original_subject.clone(),
+                            outbox_id,
+                            detected_at: now,
+                        });
+                    }
+                }
+            }
+            tx.commit()?;
+            Ok(added)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Delivery failures for an account, newest first.
+    pub async fn list_bounces(&self, account_email: &str, limit: usize) -> Result<Vec<Bounce>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<Bounce>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, account_email, uid, recipient, status_code, diagnostic,
+                       original_subject_encrypted, outbox_id, detected_at
+                FROM bounces
+                WHERE account_email = ?
+                ORDER BY detected_at DESC, id DESC
+                LIMIT ?
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, limit as i64])?;
+            let mut bounces = Vec::new();
+            while let Some(row) = rows.next()? {
+                let subject_enc: Option<String> = row.get(6)?;
+                bounces.push(Bounce {
+                    id: row.get(0)?,
+                    account_email: row.get(1)?,
+                    uid: row.get(2)?,
+                    recipient: row.get(3)?,
+                    status_code: row.get(4)?,
+                    diagnostic: row.get(5)?,
+                    original_subject: subject_enc
+                        .as_deref()
+                        .map(|value| cipher.decrypt_string(value))
+                        .transpose()?,
+                    outbox_id: row.get(7)?,
+                    detected_at: row.get(8)?,
+                });
+            }
+            Ok(bounces)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Stores Sent folder headers as `(uid, message)` pairs, one per
+    /// recipient. Returns how many were new.
+    pub async fn record_sent_messages(
+        &self,
+        account_email: &str,
+        rows: Vec<(u32, SentMessage)>,
+    ) -> Result<usize> {
+        if rows.is_empty() {
+            return Ok(0);
+        }
+
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut added = 0usize;
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    INSERT OR IGNORE INTO sent_messages (
+                        account_email, uid, recipient, subject_encrypted, sent_at
+                    )
+                    VALUES (?, ?, ?, ?, ?)
+                    "#,
+                )?;
+                for (uid, message) in rows {
+                    added += stmt.execute(params![
+                        account,
+                        uid,
+                        message.recipient,
+                        cipher.encrypt_string(&message.subject)?,
+                        message.sent_at
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(added)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Everything the account sent, plus the cached mail received from
+    /// those recipients since the oldest send.
+    pub async fn send_history(
+        &self,
+        account_email: &str,
+    ) -> Result<(Vec<SentMessage>, Vec<ReceivedMessage>)> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(
+            move || -> Result<(Vec<SentMessage>, Vec<ReceivedMessage>)> {
+                let conn = conn.lock();
+                let mut sent = Vec::new();
+                let mut stmt = conn.prepare(
+                    "SELECT recipient, subject_encrypted, sent_at FROM sent_messages \
+                     WHERE account_email = ?",
+                )?;
+                let mut rows = stmt.query(params![account])?;
+                while let Some(row) = rows.next()? {
+                    sent.push(SentMessage {
+                        recipient: row.get(0)?,
+                        subject: cipher.decrypt_string(&row.get::<_, String>(1)?)?,
+                        sent_at: row.get(2)?,
+                    });
+                }
+
+                let mut received = Vec::new();
+                let mut stmt = conn.prepare(
+                    r#"
+                    SELECT m.sender_email, m.subject_encrypted, m.date_ts
+                    FROM messages m
+                    WHERE m.account_email = ?1
+                      AND m.date_ts >= (
+                          SELECT MIN(sent_at) FROM sent_messages WHERE account_email = ?1
+                      )
+                      AND m.sender_email IN (
+                          SELECT recipient FROM sent_messages WHERE account_email = ?1
+                      )
+                    "#,
+                )?;
+                let mut rows = stmt.query(params![account])?;
+                while let Some(row) = rows.next()? {
+                    let subject = row
+                        .get::<_, Option<String>>(1)?
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()?
+                        .unwrap_or_default();
+                    received.push(ReceivedMessage {
+                        sender: row.get(0)?,
+                        subject,
+                        received_at: row.get(2)?,
+                    });
+                }
+                Ok((sent, received))
+            },
+        )
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// `(received_at, tags)` for every live cached message of the account
+    /// received within the bounds, for the arrival heat map.
+    pub async fn arrival_samples(
+        &self,
+        account_email: &str,
+        since: Option<i64>,
+        until: Option<i64>,
+    ) -> Result<Vec<(i64, Vec<String>)>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+
+        let join_result =
+            tokio::task::spawn_blocking(move || -> Result<Vec<(i64, Vec<String>)>> {
+                let conn = conn.lock();
+                let mut stmt = conn.prepare(
+                    r#"
+                SELECT m.date_ts, ar.categories
+                FROM messages m
+                LEFT JOIN analysis_results ar ON ar.message_id = m.id
+                WHERE m.account_email = ?1
+                  AND m.tombstoned_at IS NULL
+                  AND m.date_ts IS NOT NULL
+                  AND (?2 IS NULL OR m.date_ts >= ?2)
+                  AND (?3 IS NULL OR m.date_ts < ?3)
+                "#,
+                )?;
+                let mut rows = stmt.query(params![account, since, until])?;
+                let mut samples = Vec::new();
+                while let Some(row) = rows.next()? {
+                    let categories_json: Option<String> = row.get(1)?;
+                    let tags = categories_json
+                        .as_deref()
+                        .and_then(|value| serde_json::from_str::<Vec<String>>(value).ok())
+                        .unwrap_or_default();
+                    samples.push((row.get(0)?, tags));
+                }
+                Ok(samples)
+            })
+            .await
+            .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Stored analysis priorities for the given UIDs; unanalyzed ones are
+    /// missing from the map.
+    pub async fn message_priorities(
+        &self,
+        account_email: &str,
+        folder: &str,
+        uids: Vec<u32>,
+    ) -> Result<HashMap<u32, String>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<HashMap<u32, String>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT json_extract(ar.metadata_json, '$.priority')
+                FROM messages m
+                JOIN analysis_results ar ON ar.message_id = m.id
+                WHERE m.account_email = ? AND m.folder = ? AND m.uid = ?
+                    "#,
+            )?;
+            let mut priorities = HashMap::new();
+            for uid in uids {
+                let priority: Option<String> = stmt
+                    .query_row(params![account, folder, uid], |row| row.get(0))
+                    .optional()?
+                    .flatten();
+                if let Some(priority) = priority {
+                    priorities.insert(uid, priority);
+                }
+            }
+            Ok(priorities)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Records focus decisions for messages seen for the first time. Returns
+    /// the UIDs that were new; messages decided before are left as they were.
+    pub async fn record_focus_decisions(
+        &self,
+        account_email: &str,
+        folder: &str,
+        decisions: Vec<FocusDecision>,
+    ) -> Result<Vec<u32>> {
+        if decisions.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let folder = folder.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<u32>> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut recorded = Vec::new();
+            {
+                let mut stmt = tx.prepare(
+                    r#"
+                    INSERT OR IGNORE INTO focus_decisions (
+                        account_email, folder, uid, reason, held, decided_at
+                    )
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    "#,
+                )?;
+                for decision in decisions {
+                    let FocusDecision { uid, reason, held } = decision;
+                    if stmt.execute(params![account, folder, uid, reason, held, now])? > 0 {
+                        recorded.push(uid);
+                    }
+                }
+            }
+            tx.commit()?;
+            Ok(recorded)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Urgent messages focus mode let through, newest first.
+    pub async fn focused_messages(
+        &self,
+        account_email: &str,
+        limit: usize,
+    ) -> Result<Vec<FocusMessage>> {
+        self.focus_messages(Some(account_email), "fd.reason IS NOT NULL", limit, None)
+            .await
+    }
+
+    /// Held messages still waiting for a review, for one account or all.
+    pub async fn held_focus_messages(
+        &self,
+        account_email: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<FocusMessage>> {
+        self.focus_messages(
+            account_email,
+            "fd.held = 1 AND fd.reviewed_at IS NULL",
+            limit,
+            None,
+        )
+        .await
+    }
+
+    /// Marks every held message as reviewed and returns them.
+    pub async fn take_focus_review(&self, limit: usize) -> Result<Vec<FocusMessage>> {
+        self.focus_messages(
+            None,
+            "fd.held = 1 AND fd.reviewed_at IS NULL",
+            limit,
+            Some(Utc::now().timestamp()),
+        )
+        .await
+    }
+
+    /// Loads focus decisions matching `condition` joined with their live
+    /// messages. With `reviewed_at`, every held message still unreviewed is
+    /// stamped in the same transaction, including those beyond `limit`.
+    async fn focus_messages(
+        &self,
+        account_email: Option<&str>,
+        condition: &'static str,
+        limit: usize,
+        reviewed_at: Option<i64>,
+    ) -> Result<Vec<FocusMessage>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.map(|value| value.to_owned());
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<FocusMessage>> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut messages = Vec::new();
+            {
+                let mut stmt = tx.prepare(&format!(
+                    r#"
+                    SELECT fd.account_email, fd.uid, m.sender_email, m.sender_display,
+                           m.subject_encrypted, m.date, fd.reason, fd.decided_at, fd.folder
+                    FROM focus_decisions fd
+                    JOIN messages m
+                        ON m.account_email = fd.account_email
+                       AND m.folder = fd.folder
+                       AND m.uid = fd.uid
+                    WHERE (?1 IS NULL OR fd.account_email = ?1)
+                      AND m.tombstoned_at IS NULL
+                      AND {condition}
+                    ORDER BY fd.decided_at DESC, m.date_ts DESC
+                    LIMIT ?2
+                    "#
+                ))?;
+                let mut rows = stmt.query(params![account, limit as i64])?;
+                while let Some(row) = rows.next()? {
+                    let subject = row
+                        .get::<_, Option<String>>(4)?
+                        .map(|value| cipher.decrypt_string(&value))
+                        .transpose()?
+                        .unwrap_or_default();
+                    messages.push(FocusMessage {
+                        account_email: row.get(0)?,
+                        folder: row.get(8)?,
+                        uid: row.get(1)?,
+                        sender_email: row.get(2)?,
+                        sender_display: row.get(3)?,
+                        subject,
+                        date: row.get(5)?,
+                        reason: row.get(6)?,
+                        decided_at: row.get(7)?,
+                    });
+                }
+            }
+            if let Some(reviewed_at) = reviewed_at {
+                tx.execute(
+                    "UPDATE focus_decisions SET reviewed_at = ?1 \
+                     WHERE (?2 IS NULL OR account_email = ?2) AND held = 1 AND reviewed_at IS NULL",
+                    params![reviewed_at, account],
+                )?;
+            }
+            tx.commit()?;
+            Ok(messages)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Claims up to `limit` queued entries of `accounts` that are due by
+    /// `now`, oldest first: each is marked `sending` and its attempt
+    /// counted, so the next drain does not pick it up again.
+    pub async fn claim_due_outbox(
+        &self,
+        accounts: Vec<String>,
+        now: i64,
+        limit: usize,
+    ) -> Result<Vec<OutboxMessage>> {
+        if accounts.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<OutboxMessage>> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut claimed = Vec::new();
+            {
+                let placeholders = vec!["?"; accounts.len()].join(", ");
+                let mut stmt = tx.prepare(&format!(
+                    r#"
+                    SELECT id, account_email, recipient, subject_encrypted, body_encrypted,
+                           message_id, attempts, in_reply_to, reference_ids, auto_submitted
+                    FROM outbox
+                    WHERE status = 'queued' AND scheduled_at <= ?
+                      AND account_email IN ({placeholders})
+                    ORDER BY scheduled_at, id
+                    LIMIT ?
+                    "#
+                ))?;
+                let mut values: Vec<rusqlite::types::Value> = vec![now.into()];
+                values.extend(accounts.into_iter().map(Into::into));
+                values.push((limit as i64).into());
+                let mut rows = stmt.query(params_from_iter(values))?;
+                while let Some(row) = rows.next()? {
+                    let account_email: String = row.get(1)?;
+                    let subject: String = row.get(3)?;
+                    let body: String = row.get(4)?;
+                    let message_id: Option<String> = row.get(5)?;
+                    let attempts: i64 = row.get(6)?;
+                    let references: Option<String> = row.get(8)?;
+                    claimed.push(OutboxMessage {
+                        id: row.get(0)?,
+                        recipient: row.get(2)?,
+                        subject: cipher.decrypt_string(&subject)?,
+                        body: cipher.decrypt_string(&body)?,
+                        message_id: message_id.unwrap_or_else(|| new_message_id(&account_email)),
+                        attempts: attempts + 1,
+                        attachments: Vec::new(),
+                        in_reply_to: row.get(7)?,
+                        references: references
+                            .map(|ids| ids.split_whitespace().map(str::to_string).collect())
+                            .unwrap_or_default(),
+                        auto_submitted: row.get(9)?,
+                        account_email,
+                    });
+                }
+            }
+            {
+                let mut attachment_stmt = tx.prepare(
+                    r#"
+                    SELECT filename, content_type, data_encrypted
+                    FROM outbox_attachments
+                    WHERE outbox_id = ?
+                    ORDER BY id
+                    "#,
+                )?;
+                let mut claim_stmt = tx.prepare(
+                    r#"
+                    UPDATE outbox
+                    SET status = 'sending', attempts = ?, message_id = ?, updated_at = ?
+                    WHERE id = ?
+                    "#,
+                )?;
+                for message in &mut claimed {
+                    let mut rows = attachment_stmt.query(params![message.id])?;
+                    while let Some(row) = rows.next()? {
+                        let data: String = row.get(2)?;
+                        message.attachments.push(OutboxAttachment {
+                            filename: row.get(0)?,
+                            content_type: row.get(1)?,
+                            data: cipher.decrypt_bytes(&data)?,
+                        });
+                    }
+                    claim_stmt.execute(params![
+                        message.attempts,
+                        message.message_id,
+                        now,
+                        message.id
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(claimed)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Puts a claimed entry back in the queue after a failure worth
+    /// retrying, to be sent again from `retry_at`.
+    pub async fn retry_outbox(&self, outbox_id: i64, error: &str, retry_at: i64) -> Result<()> {
+        let conn = self.conn.clone();
+        let error = error.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                UPDATE outbox
+                SET status = 'queued', last_error = ?, scheduled_at = ?, updated_at = ?
+                WHERE id = ?
+                "#,
+                params![error, retry_at, Utc::now().timestamp(), outbox_id],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Marks an outbox entry sent and records the route it took. Its
+    /// attachments are no longer needed and are dropped.
+    pub async fn mark_outbox_sent(&self, outbox_id: i64, path: DeliveryPath) -> Result<()> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                UPDATE outbox
+                SET status = 'sent', delivery_path = ?, last_error = NULL, updated_at = ?
+                WHERE id = ?
+                "#,
+                params![path.as_key(), Utc::now().timestamp(), outbox_id],
+            )?;
+            conn.execute(
+                "DELETE FROM outbox_attachments WHERE outbox_id = ?",
+                params![outbox_id],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn mark_outbox_failed(&self, outbox_id: i64, error: &str) -> Result<()> {
+        let conn = self.conn.clone();
+        let error = error.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                UPDATE outbox
+                SET status = 'failed', last_error = ?, updated_at = ?
+                WHERE id = ?
+                "#,
+                params![error, Utc::now().timestamp(), outbox_id],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Cancels every still-queued message of a merge; returns how many were cancelled.
+    pub async fn cancel_mail_merge(&self, merge_id: i64) -> Result<usize> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let conn = conn.lock();
+            let updated = conn.execute(
+                r#"
+                UPDATE outbox
+                SET status = 'cancelled', updated_at = ?
+                WHERE merge_id = ? AND status = 'queued'
+                "#,
+                params![Utc::now().timestamp(), merge_id],
+            )?;
+            Ok(updated)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Distinct cached senders for an account, optionally filtered by a
+    /// substring of the address. Blocked senders are never returned.
+    pub async fn contact_candidates(
+        &self,
+        account_email: &str,
+        filter: Option<&str>,
+    ) -> Result<Vec<(String, Option<String>)>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let pattern = filter
+            .map(|value| value.trim().to_lowercase())
+            .filter(|value| !value.is_empty())
+            .map(|value| format!("%{value}%"));
+
+        let join_result =
+            tokio::task::spawn_blocking(move || -> Result<Vec<(String, Option<String>)>> {
+                let conn = conn.lock();
+                let mut stmt = conn.prepare(
+                    r#"
+                    SELECT m.sender_email, MAX(m.sender_display)
+                    FROM messages m
+                    LEFT JOIN sender_status ss
+                        ON ss.sender_email = m.sender_email AND ss.scope = 'global'
+                    LEFT JOIN sender_status sa
+                        ON sa.sender_email = m.sender_email AND sa.scope = m.account_email
+                    LEFT JOIN sender_status sd
+                        ON sd.sender_email = substr(m.sender_email, instr(m.sender_email, '@'))
+                        AND sd.scope = 'global'
+                    WHERE m.account_email = ?1
+                      AND COALESCE(sa.status, NULLIF(ss.status, 'neutral'), sd.status, 'neutral')
+                          != 'blocked'
+                      AND (?2 IS NULL OR m.sender_email LIKE ?2)
+                    GROUP BY m.sender_email
+                    ORDER BY m.sender_email
+                    "#,
+                )?;
+                let mut rows = stmt.query(params![account, pattern])?;
+                let mut contacts = Vec::new();
+                while let Some(row) = rows.next()? {
+                    contacts.push((row.get(0)?, row.get(1)?));
+                }
+                Ok(contacts)
+            })
+            .await
+            .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Adds cards not yet in the address book, matched by `UID` and by first
+    /// address. Returns how many were added.
+    pub async fn add_contacts(&self, cards: Vec<Card>, source: &str) -> Result<usize> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let source = source.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut added = 0;
+            {
+                let mut known =
+                    tx.prepare("SELECT 1 FROM contacts WHERE uid = ?1 OR primary_email = ?2")?;
+                let mut insert = tx.prepare(
+                    r#"
+                    INSERT INTO contacts (
+                        uid, full_name, primary_email, card_encrypted, source, updated_at
+                    )
+                    VALUES (?, ?, ?, ?, ?, ?)
+                    "#,
+                )?;
+                for card in cards {
+                    if known.exists(params![card.uid, card.primary_email()])? {
+                        continue;
+                    }
+                    added += insert.execute(params![
+                        card.uid,
+                        card.full_name,
+                        card.primary_email(),
+                        cipher.encrypt_string(&card.to_vcard())?,
+                        source,
+                        now
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(added)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Contacts by name, optionally those whose name or first address
+    /// contains `filter`.
+    pub async fn contacts(&self, filter: Option<&str>) -> Result<Vec<Contact>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let pattern = filter
+            .map(|value| value.trim().to_lowercase())
+            .filter(|value| !value.is_empty())
+            .map(|value| format!("%{value}%"));
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<Contact>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, card_encrypted, source, sync_enabled,
+                       href IS NOT NULL AND synced_at IS NOT NULL AND updated_at <= synced_at,
+                       updated_at
+                FROM contacts
+                WHERE ?1 IS NULL OR lower(full_name) LIKE ?1 OR primary_email LIKE ?1
+                ORDER BY lower(full_name), id
+                "#,
+            )?;
+            let mut rows = stmt.query(params![pattern])?;
+            let mut contacts = Vec::new();
+            while let Some(row) = rows.next()? {
+                let Some(card) = decrypt_card(&cipher, &row.get::<_, String>(1)?)? else {
+                    continue;
+                };
+                contacts.push(Contact {
+                    id: row.get(0)?,
+                    card,
+                    source: row.get(2)?,
+                    sync_enabled: row.get(3)?,
+                    synced: row.get(4)?,
+                    updated_at: row.get(5)?,
+                });
+            }
+            Ok(contacts)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Applies a local edit to a contact. Returns false when there is no
+    /// such contact.
+    pub async fn update_contact(&self, id: i64, edit: CardEdit) -> Result<bool> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let conn = conn.lock();
+            let encrypted: Option<String> = conn
+                .query_row(
+                    "SELECT card_encrypted FROM contacts WHERE id = ?",
+                    params![id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let Some(encrypted) = encrypted else {
+                return Ok(false);
+            };
+            let Some(mut card) = decrypt_card(&cipher, &encrypted)? else {
+                return Ok(false);
+            };
+            edit.apply(&mut card);
+            let now = Utc::now().timestamp();
+            card.revision = Some(now);
+            conn.execute(
+                r#"
+                UPDATE contacts
+                SET full_name = ?, primary_email = ?, card_encrypted = ?, updated_at = ?
+                WHERE id = ?
+                "#,
+                params![
+                    card.full_name,
+                    card.primary_email(),
+                    cipher.encrypt_string(&card.to_vcard())?,
+                    now,
+                    id
+                ],
+            )?;
+            Ok(true)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Opts a contact in to or out of CardDAV sync. Returns false when there
+    /// is no such contact.
+    pub async fn set_contact_sync(&self, id: i64, enabled: bool) -> Result<bool> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            let conn = conn.lock();
+            let changed = conn.execute(
+                "UPDATE contacts SET sync_enabled = ? WHERE id = ?",
+                params![enabled, id],
+            )?;
+            Ok(changed > 0)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Contacts linked to a CardDAV card or opted in to sync.
+    pub async fn carddav_contacts(&self) -> Result<Vec<ContactSyncState>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<ContactSyncState>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT id, card_encrypted, href, etag, sync_enabled,
+                       synced_at IS NULL OR updated_at > synced_at, updated_at
+                FROM contacts
+                WHERE href IS NOT NULL OR sync_enabled != 0
+                "#,
+            )?;
+            let mut rows = stmt.query([])?;
+            let mut contacts = Vec::new();
+            while let Some(row) = rows.next()? {
+                let Some(card) = decrypt_card(&cipher, &row.get::<_, String>(1)?)? else {
+                    continue;
+                };
+                contacts.push(ContactSyncState {
+                    id: row.get(0)?,
+                    card,
+                    href: row.get(2)?,
+                    etag: row.get(3)?,
+                    sync_enabled: row.get(4)?,
+                    modified: row.get(5)?,
+                    updated_at: row.get(6)?,
+                });
+            }
+            Ok(contacts)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Stores a card pulled from CardDAV over contact `id`, or as a new
+    /// contact opted in to sync. The contact counts as synced afterwards.
+    pub async fn apply_remote_contact(
+        &self,
+        id: Option<i64>,
+        card: Card,
+        href: &str,
+        etag: Option<&str>,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let href = href.to_owned();
+        let etag = etag.map(str::to_owned);
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let now = Utc::now().timestamp();
+            let encrypted = cipher.encrypt_string(&card.to_vcard())?;
+            let conn = conn.lock();
+            match id {
+                Some(id) => conn.execute(
+                    r#"
+                    UPDATE contacts
+                    SET uid = ?, full_name = ?, primary_email = ?, card_encrypted = ?,
+                        href = ?, etag = ?, updated_at = ?, synced_at = ?
+                    WHERE id = ?
+                    "#,
+                    params![
+                        card.uid,
+                        card.full_name,
+                        card.primary_email(),
+                        encrypted,
+                        href,
+                        etag,
+                        now,
+                        now,
+                        id
+                    ],
+                )?,
+                None => conn.execute(
+                    r#"
+                    INSERT INTO contacts (
+                        uid, full_name, primary_email, card_encrypted, source, sync_enabled,
+                        href, etag, updated_at, synced_at
+                    )
+                    VALUES (?, ?, ?, ?, 'carddav', 1, ?, ?, ?, ?)
+                    ON CONFLICT(uid) DO UPDATE SET
+                        full_name = excluded.full_name,
+                        primary_email = excluded.primary_email,
+                        card_encrypted = excluded.card_encrypted,
+                        href = excluded.href,
+                        etag = excluded.etag,
+                        updated_at = excluded.updated_at,
+                        synced_at = excluded.synced_at
+                    "#,
+                    params![
+                        card.uid,
+                        card.full_name,
+                        card.primary_email(),
+                        encrypted,
+                        href,
+                        etag,
+                        now,
+                        now
+                    ],
+                )?,
+            };
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Records which server card a contact is linked to. With `synced`, the
+    /// contact now matches it; otherwise the local edit is still pending.
+    pub async fn link_contact(
+        &self,
+        id: i64,
+        href: &str,
+        etag: Option<&str>,
+        synced: bool,
+    ) -> Result<()> {
+        let conn = self.conn.clone();
+        let href = href.to_owned();
+        let etag = etag.map(str::to_owned);
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                UPDATE contacts
+                SET href = ?1, etag = ?2,
+                    synced_at = CASE WHEN ?3 THEN MAX(updated_at, ?4) ELSE synced_at END
+                WHERE id = ?5
+                "#,
+                params![href, etag, synced, Utc::now().timestamp(), id],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Drops the server link of contacts whose card was deleted there. The
+    /// contacts stay, opted out of sync so they are not pushed back.
+    pub async fn unlink_contacts(&self, ids: Vec<i64>) -> Result<usize> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut unlinked = 0;
+            {
+                let mut stmt = tx.prepare(
+                    "UPDATE contacts \
+                     SET href = NULL, etag = NULL, synced_at = NULL, sync_enabled = 0 \
+                     WHERE id = ?",
+                )?;
+                for id in ids {
+                    unlinked += stmt.execute(params![id])?;
+                }
+            }
+            tx.commit()?;
+            Ok(unlinked)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn save_llm_benchmark(&self, benchmark: &LlmBenchmark) -> Result<()> {
+        let conn = self.conn.clone();
+        let benchmark = benchmark.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            let conn = conn.lock();
+            conn.execute(
+                r#"
+                INSERT INTO llm_benchmarks (
+                    model_id, samples, tokens_per_second, time_to_first_token_ms,
+                    json_valid_rate, process_rss_bytes, model_rss_bytes, completed_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(model_id) DO UPDATE SET
+                    samples = excluded.samples,
+                    tokens_per_second = excluded.tokens_per_second,
+                    time_to_first_token_ms = excluded.time_to_first_token_ms,
+                    json_valid_rate = excluded.json_valid_rate,
+                    process_rss_bytes = excluded.process_rss_bytes,
+                    model_rss_bytes = excluded.model_rss_bytes,
+                    completed_at = excluded.completed_at
+                "#,
+                params![
+                    benchmark.model_id,
+                    benchmark.samples as i64,
+                    benchmark.tokens_per_second,
+                    benchmark.time_to_first_token_ms,
+                    benchmark.json_valid_rate,
+                    benchmark.process_rss_bytes.map(|value| value as i64),
+                    benchmark.model_rss_bytes.map(|value| value as i64),
+                    benchmark.completed_at
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn llm_benchmarks(&self) -> Result<Vec<LlmBenchmark>> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<LlmBenchmark>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT model_id, samples, tokens_per_second, time_to_first_token_ms,
+                       json_valid_rate, process_rss_bytes, model_rss_bytes, completed_at
+                FROM llm_benchmarks
+                "#,
+            )?;
+            let mut rows = stmt.query([])?;
+            let mut items = Vec::new();
+            while let Some(row) = rows.next()? {
+                items.push(LlmBenchmark {
+                    model_id: row.get(0)?,
+                    samples: row.get::<_, i64>(1)?.max(0) as usize,
+                    tokens_per_second: row.get(2)?,
+                    time_to_first_token_ms: row.get(3)?,
+                    json_valid_rate: row.get(4)?,
+                    process_rss_bytes: row
+                        .get::<_, Option<i64>>(5)?
+                        .map(|value| value.max(0) as u64),
+                    model_rss_bytes: row
+                        .get::<_, Option<i64>>(6)?
+                        .map(|value| value.max(0) as u64),
+                    completed_at: row.get(7)?,
+                });
+            }
+            Ok(items)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Every sender rule in every scope, neutral ones included.
+    pub async fn list_statuses(&self) -> Result<Vec<SenderRule>> {
+        let conn = self.conn.clone();
+        let result = tokio::task::spawn_blocking(move || -> Result<Vec<SenderRule>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT sender_email, scope, status, updated_at, origin, origin_ref, expires_at
+                FROM sender_status
+                ORDER BY sender_email, scope != 'global', scope
+                "#,
+            )?;
+            let mut rows = stmt.query([])?;
+            let mut items = Vec::new();
+            while let Some(row) = rows.next()? {
+                items.push(SenderRule {
+                    sender_email: row.get(0)?,
+                    scope: row.get(1)?,
+                    status: row.get(2)?,
+                    updated_at: row.get(3)?,
+                    origin: row.get(4)?,
+                    origin_ref: row.get(5)?,
+                    expires_at: row.get(6)?,
+                });
+            }
+            Ok(items)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        result
+    }
+
+    /// Replaces the breach list. A domain listed twice keeps its last entry.
+    pub async fn replace_breaches(&self, breaches: Vec<Breach>) -> Result<usize> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM breaches", ())?;
+            {
+                let mut insert = tx.prepare(
+                    r#"
+                    INSERT OR REPLACE INTO breaches (
+                        domain, name, breach_date, added_date, data_classes
+                    ) VALUES (?, ?, ?, ?, ?)
+                    "#,
+                )?;
+                for breach in &breaches {
+                    let data_classes = serde_json::to_string(&breach.data_classes)
+                        .map_err(|err| StorageError::Serialization(err.to_string()))?;
+                    insert.execute(params![
+                        breach.domain,
+                        breach.name,
+                        breach.breach_date,
+                        breach.added_date,
+                        data_classes,
+                    ])?;
+                }
+            }
+            let stored = tx.query_row("SELECT COUNT(*) FROM breaches", (), |row| {
+                row.get::<_, i64>(0)
+            })?;
+            tx.commit()?;
+            Ok(stored as usize)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub async fn breaches(&self) -> Result<Vec<Breach>> {
         let conn = self.conn.clone();
-        let email = sender_email.to_lowercase();
-        let result = tokio::task::spawn_blocking(move || -> Result<SenderStatus> {
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<Breach>> {
             let conn = conn.lock();
-            let mut stmt =
-                conn.prepare("SELECT status FROM sender_status WHERE sender_email = ?")?;
-            let status: Option<String> = stmt
-                .query_row(params![email], |row| row.get(0))
-                .optional()?;
-            Ok(status
-                .map(|value| SenderStatus::from_str(&value))
-                .unwrap_or(SenderStatus::Neutral))
+            let mut stmt = conn.prepare(
+                "SELECT domain, name, breach_date, added_date, data_classes FROM breaches",
+            )?;
+            let mut rows = stmt.query(())?;
+            let mut breaches = Vec::new();
+            while let Some(row) = rows.next()? {
+                let data_classes: String = row.get(4)?;
+                breaches.push(Breach {
+                    domain: row.get(0)?,
+                    name: row.get(1)?,
+                    breach_date: row.get(2)?,
+                    added_date: row.get(3)?,
+                    data_classes: serde_json::from_str(&data_classes)
+                        .map_err(|err| StorageError::Serialization(err.to_string()))?,
+                });
+            }
+            Ok(breaches)
         })
         .await
         .map_err(map_join_error)?;
 
-        result
+        join_result
+    }
+
+    /// Senders and subjects of the account's messages dated `since` or
+    /// later, for the breach checks.
+    pub async fn security_messages(
+        &self,
+        account_email: &str,
+        since: i64,
+    ) -> Result<Vec<SecurityMessage>> {
+        let conn = self.conn.clone();
+        let cipher = self.cipher.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<SecurityMessage>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT uid, sender_email, subject_encrypted, date_ts
+                FROM messages
+                WHERE account_email = ? AND date_ts >= ? AND tombstoned_at IS NULL
+                ORDER BY date_ts
+                "#,
+            )?;
+            let mut rows = stmt.query(params![account, since])?;
+            let mut messages = Vec::new();
+            while let Some(row) = rows.next()? {
+                let subject = row
+                    .get::<_, Option<String>>(2)?
+                    .map(|value| cipher.decrypt_string(&value))
+                    .transpose()?
+                    .unwrap_or_default();
+                messages.push(SecurityMessage {
+                    uid: row.get(0)?,
+                    sender_email: row.get(1)?,
+                    subject,
+                    date_ts: row.get(3)?,
+                });
+            }
+            Ok(messages)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Domains the user trusts for the lookalike checks: those of the
+    /// account's frequent senders and of saved contacts.
+    pub async fn known_sender_domains(&self, account_email: &str) -> Result<Vec<String>> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<String>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT lower(substr(sender_email, instr(sender_email, '@') + 1))
+                FROM sender_aggregates
+                WHERE account_email = ?1 AND message_count >= ?2 AND instr(sender_email, '@') > 0
+                UNION
+                SELECT lower(substr(primary_email, instr(primary_email, '@') + 1))
+                FROM contacts
+                WHERE instr(primary_email, '@') > 0
+                "#,
+            )?;
+            let domains = stmt
+                .query_map(
+                    params![account, lookalike::FREQUENT_SENDER_MIN_MESSAGES],
+                    |row| row.get(0),
+                )?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(domains)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Merges a blocklist into the global rules. Entries the user has a
+    /// manual rule for keep it; entries an earlier import of `origin_ref`
+    /// added but the list no longer has are dropped.
+    pub async fn merge_blocklist(
+        &self,
+        origin_ref: &str,
+        entries: Vec<String>,
+    ) -> Result<BlocklistMerge> {
+        let conn = self.conn.clone();
+        let origin_ref = origin_ref.to_owned();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<BlocklistMerge> {
+            let now = Utc::now().timestamp();
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let mut merge = BlocklistMerge::default();
+            {
+                tx.execute(
+                    "CREATE TEMP TABLE IF NOT EXISTS blocklist_import \
+                     (sender_email TEXT PRIMARY KEY)",
+                    [],
+                )?;
+                tx.execute("DELETE FROM temp.blocklist_import", [])?;
+                let mut insert = tx.prepare(
+                    "INSERT OR IGNORE INTO temp.blocklist_import (sender_email) VALUES (?)",
+                )?;
+                for entry in &entries {
+                    insert.execute(params![entry])?;
+                }
+
+                // Profile-only rows are neutral and take the import; any other
+                // manual rule wins over the list.
+                merge.applied = tx.execute(
+                    r#"
+                    INSERT INTO sender_status (
+                        sender_email, scope, status, updated_at, origin, origin_ref
+                    )
+                    SELECT sender_email, 'global', 'blocked', ?1, 'imported', ?2
+                    FROM temp.blocklist_import WHERE true
+                    ON CONFLICT(sender_email, scope) DO UPDATE SET
+                        status = 'blocked',
+                        updated_at = excluded.updated_at,
+                        origin = 'imported',
+                        origin_ref = excluded.origin_ref
+                    WHERE sender_status.origin = 'imported' OR sender_status.status = 'neutral'
+                    "#,
+                    params![now, origin_ref],
+                )?;
+                merge.kept_manual = entries.len().saturating_sub(merge.applied);
+
+                merge.removed = remove_imported(
+                    &tx,
+                    Some(&origin_ref),
+                    "sender_email NOT IN (SELECT sender_email FROM temp.blocklist_import)",
+                )?;
+                tx.execute("DELETE FROM temp.blocklist_import", [])?;
+            }
+            tx.commit()?;
+            Ok(merge)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Removes imported rules: those from one list when `origin_ref` is set,
+    /// otherwise every imported rule. Returns how many were removed.
+    pub async fn remove_imported_rules(&self, origin_ref: Option<&str>) -> Result<usize> {
+        let conn = self.conn.clone();
+        let origin_ref = origin_ref.map(|value| value.to_owned());
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let removed = remove_imported(&tx, origin_ref.as_deref(), "true")?;
+            tx.commit()?;
+            Ok(removed)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
     }
 
-    pub async fn list_statuses(&self) -> Result<Vec<(String, SenderStatus)>> {
+    /// Globally blocked senders and domains, manual and imported.
+    pub async fn global_blocklist(&self) -> Result<Vec<String>> {
         let conn = self.conn.clone();
-        let result = tokio::task::spawn_blocking(move || -> Result<Vec<(String, SenderStatus)>> {
+        let result = tokio::task::spawn_blocking(move || -> Result<Vec<String>> {
             let conn = conn.lock();
-            let mut stmt = conn.prepare("SELECT sender_email, status FROM sender_status")?;
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT sender_email FROM sender_status
+                WHERE scope = 'global' AND status = 'blocked'
+                ORDER BY sender_email
+                "#,
+            )?;
             let mut rows = stmt.query([])?;
-            let mut items = Vec::new();
+            let mut entries = Vec::new();
             while let Some(row) = rows.next()? {
-                let email: String = row.get(0)?;
-                let status: String = row.get(1)?;
-                items.push((email, SenderStatus::from_str(&status)));
+                entries.push(row.get(0)?);
             }
-            Ok(items)
+            Ok(entries)
         })
         .await
         .map_err(map_join_error)?;
@@ -1694,6 +9991,27 @@ impl Storage {
         Ok(())
     }
 
+    pub async fn list_settings(&self) -> Result<Vec<(String, String)>> {
+        let conn = self.conn.clone();
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<(String, String)>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                "SELECT key, value FROM app_settings WHERE value IS NOT NULL ORDER BY key",
+            )?;
+            let mut rows = stmt.query([])?;
+            let mut settings = Vec::new();
+            while let Some(row) = rows.next()? {
+                settings.push((row.get(0)?, row.get(1)?));
+            }
+            Ok(settings)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
     pub async fn get_setting(&self, key: &str) -> Result<Option<String>> {
         let conn = self.conn.clone();
         let key = key.to_owned();
@@ -1711,6 +10029,69 @@ impl Storage {
         join_result
     }
 
+    pub async fn headers_only(&self, account_email: &str) -> Result<bool> {
+        let conn = self.conn.clone();
+        let account = account_email.to_owned();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<bool> {
+            headers_only(&conn.lock(), &account)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Turns headers-only caching on or off for an account. Turning it on
+    /// also drops the snippets, bodies, and links already cached for the
+    /// account; returns how many messages lost their content.
+    pub async fn set_headers_only(&self, account_email: &str, enabled: bool) -> Result<usize> {
+        let conn = self.conn.clone();
+        let decrypted = self.decrypted.clone();
+        let account = account_email.to_owned();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<usize> {
+            let mut conn = conn.lock();
+            let tx = conn.transaction()?;
+            let key = headers_only_setting_key(&account);
+            if !enabled {
+                tx.execute("DELETE FROM app_settings WHERE key = ?", params![key])?;
+                tx.commit()?;
+                return Ok(0);
+            }
+            tx.execute(
+                r#"
+                INSERT INTO app_settings (key, value)
+                VALUES (?, 'true')
+                ON CONFLICT(key) DO UPDATE SET value = excluded.value
+                "#,
+                params![key],
+            )?;
+            let purged = tx.execute(
+                r#"
+                UPDATE messages
+                SET snippet_encrypted = NULL, body_encrypted = NULL
+                WHERE account_email = ?
+                  AND (snippet_encrypted IS NOT NULL OR body_encrypted IS NOT NULL)
+                "#,
+                params![account],
+            )?;
+            tx.execute(
+                "UPDATE deleted_messages SET snippet_encrypted = NULL WHERE account_email = ?",
+                params![account],
+            )?;
+            tx.execute(
+                "DELETE FROM message_links WHERE account_email = ?",
+                params![account],
+            )?;
+            tx.commit()?;
+            decrypted.clear();
+            Ok(purged)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
     pub async fn upsert_account(&self, account: &Account) -> Result<()> {
         let conn = self.conn.clone();
         let account = account.clone();
@@ -1719,12 +10100,16 @@ impl Storage {
             let now = Utc::now().timestamp();
             conn.execute(
                 r#"
-                INSERT INTO accounts (email, provider, custom_host, custom_port, created_at, updated_at)
-                VALUES (?, ?, ?, ?, ?, ?)
+                INSERT INTO accounts (
+                    email, provider, custom_host, custom_port, oauth_client_id,
+                    created_at, updated_at
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?)
                 ON CONFLICT(email) DO UPDATE SET
                     provider = excluded.provider,
                     custom_host = excluded.custom_host,
                     custom_port = excluded.custom_port,
+                    oauth_client_id = excluded.oauth_client_id,
                     updated_at = excluded.updated_at
                 "#,
                 params![
@@ -1732,6 +10117,7 @@ impl Storage {
                     account.provider.as_key(),
                     account.custom_host,
                     account.custom_port.map(|value| value as i64),
+                    account.oauth_client_id,
                     now,
                     now
                 ],
@@ -1765,7 +10151,7 @@ impl Storage {
             let conn = conn.lock();
             let mut stmt = conn.prepare(
                 r#"
-                SELECT email, provider, custom_host, custom_port
+                SELECT email, provider, custom_host, custom_port, oauth_client_id
                 FROM accounts
                 WHERE email = ?
                 "#,
@@ -1782,6 +10168,7 @@ impl Storage {
                         provider,
                         custom_host: row.get(2)?,
                         custom_port: port.map(|value| value as u16),
+                        oauth_client_id: row.get(4)?,
                     })
                 })
                 .optional()?;
@@ -1793,33 +10180,264 @@ impl Storage {
         join_result
     }
 
-    pub async fn list_accounts(&self) -> Result<Vec<AccountRecord>> {
+    pub async fn list_accounts(&self) -> Result<Vec<AccountRecord>> {
+        let conn = self.conn.clone();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<AccountRecord>> {
+            let conn = conn.lock();
+            let mut stmt = conn.prepare(
+                r#"
+                SELECT email, provider, custom_host, custom_port, oauth_client_id
+                FROM accounts
+                ORDER BY email
+                "#,
+            )?;
+
+            let mut rows = stmt.query([])?;
+            let mut accounts = Vec::new();
+            while let Some(row) = rows.next()? {
+                let provider_key: String = row.get(1)?;
+                if let Some(provider) = Provider::from_key(&provider_key) {
+                    let port: Option<i64> = row.get(3)?;
+                    accounts.push(AccountRecord {
+                        email: row.get(0)?,
+                        provider,
+                        custom_host: row.get(2)?,
+                        custom_port: port.map(|value| value as u16),
+                        oauth_client_id: row.get(4)?,
+                    });
+                }
+            }
+            Ok(accounts)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    pub fn db_path() -> Option<&'static PathBuf> {
+        DB_PATH.get()
+    }
+
+    /// Runs `PRAGMA integrity_check` and, if it fails, tries in order: a WAL
+    /// checkpoint, discarding the WAL (rolling back to the last checkpoint),
+    /// and restoring the newest backup that passes the check. The damaged
+    /// file is kept next to the database rather than deleted.
+    pub async fn verify_and_recover(&self) -> Result<StorageHealthReport> {
+        let conn = self.conn.clone();
+        let db_path = DB_PATH.get().cloned().ok_or_else(|| {
+            StorageError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Database path is not initialized",
+            ))
+        })?;
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<StorageHealthReport> {
+            let mut guard = conn.lock();
+            let now = Utc::now().timestamp();
+            let mut report = StorageHealthReport {
+                status: "ok".into(),
+                problems: integrity_problems(&guard),
+                actions: Vec::new(),
+                restored_from: None,
+                quarantined: None,
+                checked_at: now,
+            };
+            if report.problems.is_empty() {
+                return Ok(report);
+            }
+
+            report.actions.push("wal_checkpoint".into());
+            let _ = guard.pragma_update(None, "wal_checkpoint", "TRUNCATE");
+            if integrity_problems(&guard).is_empty() {
+                report.status = "recovered".into();
+                return Ok(report);
+            }
+
+            // Release the file before touching it on disk.
+            let placeholder = Connection::open_in_memory()?;
+            let previous = std::mem::replace(&mut *guard, placeholder);
+            let _ = previous.close();
+
+            let wal_path = sidecar_path(&db_path, "-wal");
+            if wal_path.exists() {
+                report.actions.push("wal_rollback".into());
+                let discarded = sidecar_path(&db_path, &format!("-wal.{now}.bad"));
+                let _ = fs::rename(&wal_path, discarded);
+                let _ = fs::remove_file(sidecar_path(&db_path, "-shm"));
+                if let Ok(reopened) = open_database(&db_path) {
+                    if integrity_problems(&reopened).is_empty() {
+                        *guard = reopened;
+                        report.status = "recovered".into();
+                        return Ok(report);
+                    }
+                }
+            }
+
+            let data_dir = db_path.parent().map(Path::to_path_buf).unwrap_or_default();
+            let quarantine = sidecar_path(&db_path, &format!(".{now}.corrupt"));
+            for backup in list_backups(&data_dir) {
+                report.actions.push(format!("restore:{}", backup.display()));
+                let candidate = match Connection::open(&backup) {
+                    Ok(candidate) => candidate,
+                    Err(_) => continue,
+                };
+                let healthy = integrity_problems(&candidate).is_empty();
+                let _ = candidate.close();
+                if !healthy {
+                    continue;
+                }
+
+                // Errors here fall through to the "failed" path, which still
+                // reopens a database so the handle is never left in memory.
+                if report.quarantined.is_none() {
+                    if fs::rename(&db_path, &quarantine).is_err() {
+                        break;
+                    }
+                    let _ = fs::remove_file(sidecar_path(&db_path, "-wal"));
+                    let _ = fs::remove_file(sidecar_path(&db_path, "-shm"));
+                    report.quarantined = Some(quarantine.clone());
+                }
+                if fs::copy(&backup, &db_path).is_err() {
+                    break;
+                }
+                if let Ok(restored) = open_database(&db_path) {
+                    *guard = restored;
+                    report.status = "restored".into();
+                    report.restored_from = Some(backup);
+                    return Ok(report);
+                }
+            }
+
+            // Nothing worked: keep using whatever opens so the app can still
+            // show the report, even if some reads fail.
+            report.status = "failed".into();
+            if let Ok(reopened) = open_database(&db_path) {
+                *guard = reopened;
+            }
+            Ok(report)
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Writes a consistent snapshot with `VACUUM INTO` unless one newer than
+    /// `min_age_secs` exists, then prunes to `BACKUP_KEEP` files. Returns the
+    /// new snapshot, if any.
+    pub async fn create_backup(&self, min_age_secs: i64) -> Result<Option<PathBuf>> {
+        let conn = self.conn.clone();
+        let Some(data_dir) = DB_PATH
+            .get()
+            .and_then(|path| path.parent().map(Path::to_path_buf))
+        else {
+            return Ok(None);
+        };
+
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Option<PathBuf>> {
+            let now = Utc::now();
+            let existing = list_backups(&data_dir);
+            let newest_age = existing
+                .first()
+                .and_then(|path| fs::metadata(path).ok())
+                .and_then(|metadata| metadata.modified().ok())
+                .and_then(|modified| modified.elapsed().ok())
+                .map(|elapsed| elapsed.as_secs() as i64);
+            if newest_age.is_some_and(|age| age < min_age_secs) {
+                return Ok(None);
+            }
+
+            let backup_dir = data_dir.join(BACKUP_DIR);
+            fs::create_dir_all(&backup_dir)?;
+            let path = backup_dir.join(format!("{BACKUP_PREFIX}{}.db", now.format("%Y%m%d%H%M%S")));
+            conn.lock()
+                .execute("VACUUM INTO ?", params![path.to_string_lossy()])?;
+
+            for stale in list_backups(&data_dir).into_iter().skip(BACKUP_KEEP) {
+                shred_file(&stale)?;
+            }
+            Ok(Some(path))
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Writes a consistent copy of the database to `path` and checks that
+    /// the copy opens cleanly. Used when moving the data directory.
+    pub async fn snapshot_to(&self, path: PathBuf) -> Result<()> {
+        let conn = self.conn.clone();
+        let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
+            conn.lock()
+                .execute("VACUUM INTO ?", params![path.to_string_lossy()])?;
+            let problems = integrity_problems(&Connection::open(&path)?);
+            if !problems.is_empty() {
+                let _ = fs::remove_file(&path);
+                return Err(StorageError::Io(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Database copy failed verification: {}", problems.join("; ")),
+                )));
+            }
+            Ok(())
+        })
+        .await
+        .map_err(map_join_error)?;
+
+        join_result
+    }
+
+    /// Closes the database and shreds it together with its WAL/SHM files,
+    /// backups, everything in `stores` (folders of cached files such as
+    /// avatars and attachments, wherever they live), and the master key. The
+    /// handle keeps working against an empty in-memory database until the
+    /// app restarts. Returns the files that were removed.
+    pub async fn wipe_files(&self, stores: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        // Decrypted text must not outlive the data it came from.
+        self.decrypted.clear();
+        self.pending_writes.lock().clear();
         let conn = self.conn.clone();
-        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<AccountRecord>> {
-            let conn = conn.lock();
-            let mut stmt = conn.prepare(
-                r#"
-                SELECT email, provider, custom_host, custom_port
-                FROM accounts
-                ORDER BY email
-                "#,
-            )?;
+        let db_path = DB_PATH.get().cloned().ok_or_else(|| {
+            StorageError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "Database path is not initialized",
+            ))
+        })?;
 
-            let mut rows = stmt.query([])?;
-            let mut accounts = Vec::new();
-            while let Some(row) = rows.next()? {
-                let provider_key: String = row.get(1)?;
-                if let Some(provider) = Provider::from_key(&provider_key) {
-                    let port: Option<i64> = row.get(3)?;
-                    accounts.push(AccountRecord {
-                        email: row.get(0)?,
-                        provider,
-                        custom_host: row.get(2)?,
-                        custom_port: port.map(|value| value as u16),
-                    });
+        let join_result = tokio::task::spawn_blocking(move || -> Result<Vec<PathBuf>> {
+            let mut guard = conn.lock();
+            let _ = guard.pragma_update(None, "wal_checkpoint", "TRUNCATE");
+
+            let mut replacement = Connection::open_in_memory()?;
+            Self::apply_migrations(&mut replacement)?;
+            let previous = std::mem::replace(&mut *guard, replacement);
+            previous
+                .close()
+                .map_err(|(_, err)| StorageError::Database(err))?;
+
+            let data_dir = db_path.parent().map(Path::to_path_buf).unwrap_or_default();
+            let mut targets = vec![db_path.clone()];
+            for suffix in ["-wal", "-shm", "-journal"] {
+                targets.push(sidecar_path(&db_path, suffix));
+            }
+            targets.extend(list_backups(&data_dir));
+
+            let mut removed = Vec::new();
+            for path in targets {
+                if shred_file(&path)? {
+                    removed.push(path);
                 }
             }
-            Ok(accounts)
+            for store in &stores {
+                removed.extend(shred_dir(store)?);
+            }
+            // The key goes last so a failure above never leaves an unreadable database.
+            let key_path = data_dir.join("master.key");
+            if shred_file(&key_path)? {
+                removed.push(key_path);
+            }
+            Ok(removed)
         })
         .await
         .map_err(map_join_error)?;
@@ -1827,10 +10445,6 @@ impl Storage {
         join_result
     }
 
-    pub fn db_path() -> Option<&'static PathBuf> {
-        DB_PATH.get()
-    }
-
     pub async fn upsert_analysis(&self, rows: Vec<AnalysisInsert>) -> Result<()> {
         if rows.is_empty() {
             return Ok(());
@@ -1840,95 +10454,7 @@ impl Storage {
         let join_result = tokio::task::spawn_blocking(move || -> Result<()> {
             let mut conn = conn.lock();
             let tx = conn.transaction()?;
-            {
-                let mut stmt = tx.prepare(
-                    r#"
-                    INSERT INTO analysis_results (
-                        message_id,
-                        summary,
-                        sentiment,
-                        categories,
-                        metadata_json,
-                        model_id,
-                        analyzed,
-                        analyzed_at,
-                        analysis_confidence,
-                        validator_model_id,
-                        validation_status,
-                        validation_confidence,
-                        validation_notes,
-                        validated_at
-                    )
-                    SELECT id, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
-                    FROM messages
-                    WHERE account_email = ? AND uid = ?
-                    ON CONFLICT(message_id) DO UPDATE SET
-                        summary = excluded.summary,
-                        sentiment = excluded.sentiment,
-                        categories = excluded.categories,
-                        metadata_json = excluded.metadata_json,
-                        model_id = excluded.model_id,
-                        analyzed = excluded.analyzed,
-                        analyzed_at = excluded.analyzed_at,
-                        analysis_confidence = excluded.analysis_confidence,
-                        validator_model_id = excluded.validator_model_id,
-                        validation_status = excluded.validation_status,
-                        validation_confidence = excluded.validation_confidence,
-                        validation_notes = excluded.validation_notes,
-                        validated_at = excluded.validated_at
-                    "#,
-                )?;
-
-                for row in rows {
-                    let categories_json = if row.categories.is_empty() {
-                        None
-                    } else {
-                        Some(
-                            serde_json::to_string(&row.categories)
-                                .map_err(|err| StorageError::Serialization(err.to_string()))?,
-                        )
-                    };
-
-                    let summary = row.summary.as_deref();
-                    let sentiment = row.sentiment.as_deref();
-                    let categories = categories_json.as_deref();
-                    let metadata_json = if row.metadata_json.is_null() {
-                        None
-                    } else {
-                        Some(
-                            serde_json::to_string(&row.metadata_json)
-                                .map_err(|err| StorageError::Serialization(err.to_string()))?,
-                        )
-                    };
-                    let model_id = row.model_id.as_deref();
-                    let analyzed = if row.analyzed { 1 } else { 0 };
-                    let analyzed_at = row.analyzed_at;
-                    let analysis_confidence = row.analysis_confidence;
-                    let validator_model_id = row.validation.validator_model_id.as_deref();
-                    let validation_status = row.validation.status.as_deref();
-                    let validation_confidence = row.validation.confidence;
-                    let validation_notes = row.validation.notes.as_deref();
-                    let validated_at = row.validation.validated_at;
-
-                    stmt.execute(params![
-                        summary,
-                        sentiment,
-                        categories,
-                        metadata_json.as_deref(),
-                        model_id,
-                        analyzed,
-                        analyzed_at,
-                        analysis_confidence,
-                        validator_model_id,
-                        validation_status,
-                        validation_confidence,
-                        validation_notes,
-                        validated_at,
-                        row.account_email,
-                        row.uid
-                    ])?;
-                }
-            }
+            write_analysis(&tx, rows)?;
             tx.commit()?;
             Ok(())
         })
@@ -1941,6 +10467,448 @@ impl Storage {
     }
 }
 
+/// Inserts or updates cached messages, their links, and detected trackers
+/// inside the caller's transaction.
+fn write_messages(
+    tx: &Connection,
+    cipher: &Cipher,
+    decrypted: &DecryptCache,
+    rows: Vec<MessageInsert>,
+) -> Result<Vec<FlagConflict>> {
+    let now = Utc::now().timestamp();
+    let policies = flag_policies(tx)?;
+    let mut headers_only_accounts: HashMap<String, bool> = HashMap::new();
+    let mut conflicts = Vec::new();
+    let mut folder_uid_validity =
+        tx.prepare("SELECT uid_validity FROM folder_state WHERE account_email = ? AND folder = ?")?;
+    let mut stmt = tx.prepare(
+        r#"
+        INSERT INTO messages (
+            account_email,
+            folder,
+            uid,
+            sender_email,
+            sender_display,
+            subject_encrypted,
+            date,
+            snippet_encrypted,
+            body_encrypted,
+            flags,
+            created_at,
+            updated_at,
+            date_ts,
+            uidvalidity,
+            message_key,
+            size_bytes,
+            dmarc_aligned,
+            thread_id
+        ) VALUES (?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)
+        ON CONFLICT(account_email, folder, uid) DO UPDATE SET
+            sender_email=excluded.sender_email,
+            sender_display=excluded.sender_display,
+            subject_encrypted=excluded.subject_encrypted,
+            date=excluded.date,
+            date_ts=excluded.date_ts,
+            snippet_encrypted=COALESCE(
+                excluded.snippet_encrypted, messages.snippet_encrypted
+            ),
+            body_encrypted=COALESCE(excluded.body_encrypted, messages.body_encrypted),
+            flags=CASE WHEN ?19 THEN excluded.flags ELSE messages.flags END,
+            updated_at=excluded.updated_at,
+            size_bytes=COALESCE(excluded.size_bytes, messages.size_bytes),
+            dmarc_aligned=COALESCE(excluded.dmarc_aligned, messages.dmarc_aligned),
+            thread_id=COALESCE(excluded.thread_id, messages.thread_id),
+            uidvalidity=COALESCE(messages.uidvalidity, excluded.uidvalidity),
+            message_key=COALESCE(messages.message_key, excluded.message_key),
+            -- The server still lists it, so only a delete made here stands.
+            tombstoned_at=CASE messages.tombstone_reason
+                WHEN 'local' THEN messages.tombstoned_at END,
+            tombstone_reason=CASE messages.tombstone_reason
+                WHEN 'local' THEN messages.tombstone_reason END
+        "#,
+    )?;
+    let mut clear_links =
+        tx.prepare("DELETE FROM message_links WHERE account_email = ? AND folder = ? AND uid = ?")?;
+    let mut set_trackers = tx.prepare(
+        "UPDATE messages SET trackers_detected = ? \
+         WHERE account_email = ? AND folder = ? AND uid = ?",
+    )?;
+    let mut insert_link = tx.prepare(
+        r#"
+        INSERT INTO message_links (
+            account_email, folder, uid, position, url_encrypted, domain,
+            display_text_encrypted, mismatch
+        ) VALUES (?,?,?,?,?,?,?,?)
+        "#,
+    )?;
+
+    for mut row in rows {
+        let skip_content = match headers_only_accounts.get(&row.account_email) {
+            Some(skip) => *skip,
+            None => {
+                let skip = headers_only(tx, &row.account_email)?;
+                headers_only_accounts.insert(row.account_email.clone(), skip);
+                skip
+            }
+        };
+        if skip_content {
+            row.snippet = None;
+            row.body = None;
+        }
+        let subject_enc = cipher.encrypt_string(&row.subject)?;
+        let snippet_enc = row
+            .snippet
+            .as_ref()
+            .map(|value| cipher.encrypt_string(value))
+            .transpose()?;
+        let body_enc = row
+            .body
+            .as_ref()
+            .map(|value| cipher.encrypt_bytes(value))
+            .transpose()?;
+
+        let uid = row.uid;
+        let uid_validity: Option<i64> = folder_uid_validity
+            .query_row(params![row.account_email, row.folder], |row| row.get(0))
+            .optional()?;
+        let flags = match row.flags.as_deref() {
+            Some(server_flags) => reconcile_flags(
+                tx,
+                &policies,
+                (&row.account_email, &row.folder, uid),
+                Some(server_flags).filter(|flags| !flags.is_empty()),
+                now,
+                &mut conflicts,
+            )?,
+            None => None,
+        };
+        stmt.execute(params![
+            row.account_email,
+            row.folder,
+            uid,
+            row.sender_email.to_lowercase(),
+            row.sender_display,
+            subject_enc,
+            row.date,
+            snippet_enc,
+            body_enc,
+            flags,
+            now,
+            now,
+            row.date.as_deref().and_then(message_timestamp),
+            uid_validity,
+            message_key(
+                &row.account_email,
+                &row.folder,
+                uid_validity,
+                i64::from(uid)
+            ),
+            row.size,
+            row.dmarc_aligned,
+            row.thread_id,
+            row.flags.is_some(),
+        ])?;
+        decrypted.invalidate(&row.account_email, &row.folder, uid);
+
+        // Links are re-derived whenever a body arrives; rows without
+        // one keep what was extracted before.
+        let Some(body) = row.body.as_ref() else {
+            continue;
+        };
+        let detected = serde_json::to_string(&trackers::detect(body))
+            .map_err(|err| StorageError::Serialization(err.to_string()))?;
+        set_trackers.execute(params![detected, row.account_email, row.folder, uid])?;
+        clear_links.execute(params![row.account_email, row.folder, uid])?;
+        for (position, link) in links::extract_links(body).into_iter().enumerate() {
+            let display_enc = link
+                .display_text
+                .as_ref()
+                .map(|value| cipher.encrypt_string(value))
+                .transpose()?;
+            insert_link.execute(params![
+                row.account_email,
+                row.folder,
+                uid,
+                position as i64,
+                cipher.encrypt_string(&link.url)?,
+                link.domain,
+                display_enc,
+                link.mismatch as i64,
+            ])?;
+        }
+    }
+    Ok(conflicts)
+}
+
+fn headers_only(conn: &Connection, account_email: &str) -> Result<bool> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM app_settings WHERE key = ?",
+            params![headers_only_setting_key(account_email)],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value.as_deref() == Some("true"))
+}
+
+fn flag_policies(conn: &Connection) -> Result<FlagPolicies> {
+    let json: Option<String> = conn
+        .query_row(
+            "SELECT value FROM app_settings WHERE key = ?",
+            params![flag_sync::SETTING_KEY],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(json
+        .and_then(|json| {
+            serde_json::from_str(&json)
+                .map_err(|err| warn!(?err, "ignoring invalid flag conflict policies"))
+                .ok()
+        })
+        .unwrap_or_default())
+}
+
+/// The flags to cache for a message the server reports with `server_flags`:
+/// the server's list with pending local edits laid over it. Edits the server
+/// already agrees with are dropped. When another client changed the message
+/// since an edit was made and the server disagrees with it, the flag's
+/// policy decides and the conflict is recorded.
+fn reconcile_flags(
+    tx: &Connection,
+    policies: &FlagPolicies,
+    (account_email, folder, uid): (&str, &str, u32),
+    server_flags: Option<&str>,
+    now: i64,
+    conflicts: &mut Vec<FlagConflict>,
+) -> Result<Option<String>> {
+    let pending = {
+        let mut stmt = tx.prepare_cached(
+            r#"
+            SELECT flag, enabled, base_flags, base_refreshed_at, changed_at
+            FROM pending_flag_changes
+            WHERE account_email = ? AND folder = ? AND uid = ?
+            "#,
+        )?;
+        let rows = stmt.query_map(params![account_email, folder, uid], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, bool>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, i64>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })?;
+        rows.collect::<rusqlite::Result<Vec<_>>>()?
+    };
+
+    let mut flags = server_flags.map(str::to_owned);
+    for (flag, enabled, base_flags, refreshed_at, changed_at) in pending {
+        let server_enabled = flag_sync::has_flag(server_flags, &flag);
+        let drop_change = |tx: &Connection| -> Result<()> {
+            tx.execute(
+                "DELETE FROM pending_flag_changes \
+                 WHERE account_email = ? AND folder = ? AND uid = ? AND flag = ?",
+                params![account_email, folder, uid, flag],
+            )?;
+            Ok(())
+        };
+        if server_enabled == enabled {
+            drop_change(tx)?;
+            continue;
+        }
+        if !flag_sync::same_flags(base_flags.as_deref(), server_flags) {
+            let policy = policies.policy_for(&flag);
+            let winner = flag_sync::resolve(policy, changed_at, refreshed_at, now);
+            tx.execute(
+                r#"
+                INSERT INTO flag_conflicts (
+                    account_email, folder, uid, flag, local_enabled, server_enabled, policy,
+                    winner, local_changed_at, detected_at
+                ) VALUES (?,?,?,?,?,?,?,?,?,?)
+                "#,
+                params![
+                    account_email,
+                    folder,
+                    uid,
+                    flag,
+                    enabled,
+                    server_enabled,
+                    policy.as_str(),
+                    winner.as_str(),
+                    changed_at,
+                    now,
+                ],
+            )?;
+            conflicts.push(FlagConflict {
+                id: tx.last_insert_rowid(),
+                account_email: account_email.to_owned(),
+                folder: folder.to_owned(),
+                uid,
+                flag: flag.clone(),
+                local_enabled: enabled,
+                server_enabled,
+                policy,
+                winner,
+                local_changed_at: changed_at,
+                detected_at: now,
+            });
+            if winner == Winner::Server {
+                drop_change(tx)?;
+                continue;
+            }
+            // The edit still has to reach the server; measure later changes
+            // against what the server holds now.
+            tx.execute(
+                r#"
+                UPDATE pending_flag_changes SET base_flags = ?, base_refreshed_at = ?
+                WHERE account_email = ? AND folder = ? AND uid = ?
+                "#,
+                params![server_flags, now, account_email, folder, uid],
+            )?;
+        }
+        flags = flag_sync::with_flag(flags.as_deref(), &flag, enabled);
+    }
+    Ok(flags)
+}
+
+/// Inserts or updates analyses for messages already in `messages`.
+fn write_analysis(tx: &Connection, rows: Vec<AnalysisInsert>) -> Result<()> {
+    let mut stmt = tx.prepare(
+        r#"
+        INSERT INTO analysis_results (
+            message_id,
+            summary,
+            sentiment,
+            categories,
+            metadata_json,
+            model_id,
+            analyzed,
+            analyzed_at,
+            analysis_confidence,
+            validator_model_id,
+            validation_status,
+            validation_confidence,
+            validation_notes,
+            validated_at,
+            analysis_fingerprint,
+            stale
+        )
+        SELECT id, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0
+        FROM messages
+        WHERE message_key = ?18
+           OR (?18 IS NULL AND account_email = ?15 AND folder = ?16 AND uid = ?17)
+        ON CONFLICT(message_id) DO UPDATE SET
+            summary = excluded.summary,
+            sentiment = excluded.sentiment,
+            categories = excluded.categories,
+            metadata_json = excluded.metadata_json,
+            model_id = excluded.model_id,
+            analyzed = excluded.analyzed,
+            analyzed_at = excluded.analyzed_at,
+            analysis_confidence = excluded.analysis_confidence,
+            validator_model_id = excluded.validator_model_id,
+            validation_status = excluded.validation_status,
+            validation_confidence = excluded.validation_confidence,
+            validation_notes = excluded.validation_notes,
+            validated_at = excluded.validated_at,
+            analysis_fingerprint = excluded.analysis_fingerprint,
+            stale = 0
+        "#,
+    )?;
+
+    for row in rows {
+        let categories_json = if row.categories.is_empty() {
+            None
+        } else {
+            Some(
+                serde_json::to_string(&row.categories)
+                    .map_err(|err| StorageError::Serialization(err.to_string()))?,
+            )
+        };
+
+        let summary = row.summary.as_deref();
+        let sentiment = row.sentiment.as_deref();
+        let categories = categories_json.as_deref();
+        let metadata_json = if row.metadata_json.is_null() {
+            None
+        } else {
+            Some(
+                serde_json::to_string(&row.metadata_json)
+                    .map_err(|err| StorageError::Serialization(err.to_string()))?,
+            )
+        };
+        let model_id = row.model_id.as_deref();
+        let analyzed = if row.analyzed { 1 } else { 0 };
+        let analyzed_at = row.analyzed_at;
+        let analysis_confidence = row.analysis_confidence;
+        let validator_model_id = row.validation.validator_model_id.as_deref();
+        let validation_status = row.validation.status.as_deref();
+        let validation_confidence = row.validation.confidence;
+        let validation_notes = row.validation.notes.as_deref();
+        let validated_at = row.validation.validated_at;
+        let fingerprint = row.fingerprint.as_deref();
+
+        stmt.execute(params![
+            summary,
+            sentiment,
+            categories,
+            metadata_json.as_deref(),
+            model_id,
+            analyzed,
+            analyzed_at,
+            analysis_confidence,
+            validator_model_id,
+            validation_status,
+            validation_confidence,
+            validation_notes,
+            validated_at,
+            fingerprint,
+            row.account_email,
+            row.folder,
+            row.uid,
+            row.message_key
+        ])?;
+    }
+    Ok(())
+}
+
+fn query_account_migration(conn: &Connection, id: i64) -> Result<Option<AccountMigration>> {
+    let migration = conn
+        .query_row(
+            r#"
+            SELECT id, source_email, target_email, source_folder, target_folder, last_uid,
+                   total, copied, failed, status, error, created_at, updated_at, failed_uids
+            FROM account_migrations
+            WHERE id = ?
+            "#,
+            params![id],
+            |row| {
+                Ok(AccountMigration {
+                    id: row.get(0)?,
+                    source_email: row.get(1)?,
+                    target_email: row.get(2)?,
+                    source_folder: row.get(3)?,
+                    target_folder: row.get(4)?,
+                    last_uid: row.get::<_, i64>(5)? as u32,
+                    total: row.get::<_, i64>(6)? as usize,
+                    copied: row.get::<_, i64>(7)? as usize,
+                    failed: row.get::<_, i64>(8)? as usize,
+                    failed_uids: row
+                        .get::<_, String>(13)?
+                        .split_whitespace()
+                        .filter_map(|uid| uid.parse().ok())
+                        .collect(),
+                    status: row.get(9)?,
+                    error: row.get(10)?,
+                    created_at: row.get(11)?,
+                    updated_at: row.get(12)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(migration)
+}
+
 impl Cipher {
     fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
         if bytes.len() != 32 {
@@ -1991,14 +10959,1179 @@ impl Cipher {
         let bytes = self.decrypt_bytes(data)?;
         String::from_utf8(bytes).map_err(|_| StorageError::Decryption)
     }
+
+    /// A keyed digest of `value` for columns that are looked up by value
+    /// and so cannot hold ciphertext.
+    fn digest(&self, value: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(self.key.expose_secret())
+            .expect("HMAC accepts any key length");
+        mac.update(value.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+fn analysis_example_from_row(row: &rusqlite::Row<'_>, cipher: &Cipher) -> Result<AnalysisExample> {
+    let subject_enc: Option<String> = row.get(4)?;
+    let snippet_enc: Option<String> = row.get(5)?;
+    let categories_json: Option<String> = row.get(6)?;
+
+    let subject = subject_enc
+        .as_ref()
+        .map(|value| cipher.decrypt_string(value))
+        .transpose()?
+        .unwrap_or_default();
+    let snippet = snippet_enc
+        .as_ref()
+        .map(|value| cipher.decrypt_string(value))
+        .transpose()?;
+    let categories = categories_json
+        .as_ref()
+        .map(|value| {
+            serde_json::from_str::<Vec<String>>(value)
+                .map_err(|err| StorageError::Serialization(err.to_string()))
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(AnalysisExample {
+        id: row.get(0)?,
+        account_email: row.get(1)?,
+        sender_email: row.get(2)?,
+        sender_domain: row.get(3)?,
+        subject,
+        snippet,
+        categories,
+        priority: row.get(7)?,
+        sentiment: row.get(8)?,
+        created_at: row.get(9)?,
+    })
 }
 
 pub fn normalize_sender(email: &str) -> String {
     email.trim().to_lowercase()
 }
 
+/// Model id recorded on analyses that were copied from a sender profile.
+pub const SENDER_PROFILE_MODEL_ID: &str = "sender-profile";
+
+const AUTOMATED_LOCAL_PARTS: &[&str] = &[
+    "noreply",
+    "no-reply",
+    "no_reply",
+    "donotreply",
+    "do-not-reply",
+    "notification",
+    "notifications",
+    "mailer-daemon",
+    "bounce",
+    "newsletter",
+    "alerts",
+    "updates",
+];
+
+const AUTOMATED_TAGS: &[&str] = &[
+    "newsletter",
+    "promotions",
+    "marketing",
+    "updates",
+    "receipts",
+    "shipping",
+    "system-alert",
+];
+
+#[derive(Default)]
+struct SenderAggregate {
+    samples: usize,
+    tag_counts: HashMap<String, usize>,
+    tag_sets: HashMap<String, usize>,
+    priorities: HashMap<String, usize>,
+}
+
+impl SenderAggregate {
+    fn into_profile(self, sender_email: &str, now: i64) -> SenderProfile {
+        let samples = self.samples.max(1) as f64;
+
+        let (typical_set, set_count) = self
+            .tag_sets
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+            .unwrap_or_default();
+        let typical_tags = typical_set
+            .split(',')
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| tag.to_string())
+            .collect::<Vec<_>>();
+
+        let (typical_priority, priority_count) = self
+            .priorities
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+            .map(|(priority, count)| (Some(priority), count))
+            .unwrap_or((None, self.samples));
+
+        let automated_hits = self
+            .tag_counts
+            .iter()
+            .filter(|(tag, _)| AUTOMATED_TAGS.contains(&tag.as_str()))
+            .map(|(_, count)| *count)
+            .max()
+            .unwrap_or(0);
+        let local_part = sender_email.split('@').next().unwrap_or_default();
+        let automated_address = AUTOMATED_LOCAL_PARTS
+            .iter()
+            .any(|marker| local_part.contains(marker));
+        let kind = if automated_address || automated_hits as f64 / samples >= 0.5 {
+            "automated"
+        } else {
+            "human"
+        };
+
+        let confidence = (set_count as f64 / samples).min(priority_count as f64 / samples);
+
+        SenderProfile {
+            kind: kind.to_string(),
+            typical_tags,
+            typical_priority,
+            sample_count: self.samples as i64,
+            confidence,
+            updated_at: Some(now),
+        }
+    }
+}
+
+fn template_from_row(row: &rusqlite::Row<'_>, cipher: &Cipher) -> Result<EmailTemplate> {
+    let subject_enc: String = row.get(2)?;
+    let body_enc: String = row.get(3)?;
+    let subject = cipher.decrypt_string(&subject_enc)?;
+    let body = cipher.decrypt_string(&body_enc)?;
+
+    let mut variables = mail_merge::placeholders(&subject);
+    for name in mail_merge::placeholders(&body) {
+        if !variables.contains(&name) {
+            variables.push(name);
+        }
+    }
+
+    Ok(EmailTemplate {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        subject,
+        body,
+        variables,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    })
+}
+
+/// Selects an outbox row's delivery status: bounced when a delivery report
+/// names it, sent once it has gone out without one.
+const OUTBOX_DELIVERY_STATUS: &str = r#"
+    CASE
+        WHEN EXISTS (SELECT 1 FROM bounces b WHERE b.outbox_id = outbox.id) THEN 'bounced'
+        WHEN outbox.status = 'sent' THEN 'sent'
+    END
+"#;
+
+/// Copies a cached message into `deleted_messages` and tombstones it.
+/// `None` when the message is not cached.
+fn archive_cached(
+    conn: &Connection,
+    cipher: &Cipher,
+    account_email: &str,
+    folder: &str,
+    uid: u32,
+    now: i64,
+) -> Result<Option<DeletedMessageRow>> {
+    let source = {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT 
+                m.uid,
+                m.sender_email,
+                m.sender_display,
+                m.subject_encrypted,
+                m.snippet_encrypted,
+                m.date,
+                m.flags,
+                ar.summary,
+                ar.sentiment,
+                ar.categories,
+                m.id,
+                m.folder,
+                m.uidvalidity,
+                m.message_key
+            FROM messages m
+            LEFT JOIN analysis_results ar ON ar.message_id = m.id
+            WHERE m.account_email = ? AND m.folder = ? AND m.uid = ? AND m.tombstoned_at IS NULL
+            "#,
+        )?;
+
+        stmt.query_row(params![account_email, folder, uid], |row| {
+            Ok((
+                row.get::<_, u32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, Option<String>>(5)?,
+                row.get::<_, Option<String>>(6)?,
+                row.get::<_, Option<String>>(7)?,
+                row.get::<_, Option<String>>(8)?,
+                row.get::<_, Option<String>>(9)?,
+                row.get::<_, i64>(10)?,
+                row.get::<_, String>(11)?,
+                row.get::<_, Option<i64>>(12)?,
+                row.get::<_, String>(13)?,
+            ))
+        })
+        .optional()?
+    };
+
+    let Some((
+        uid,
+        sender_email,
+        sender_display,
+        subject_encrypted,
+        snippet_encrypted,
+        date,
+        _flags,
+        analysis_summary,
+        analysis_sentiment,
+        analysis_categories,
+        message_id,
+        folder,
+        uid_validity,
+        message_key,
+    )) = source
+    else {
+        return Ok(None);
+    };
+
+    let categories_json = analysis_categories;
+
+    let account_key = account_email.to_owned();
+    let uid_insert = uid;
+    let sender_email_insert = sender_email.clone();
+    let sender_display_insert = sender_display.clone();
+    let subject_insert = subject_encrypted.clone();
+    let date_insert = date.clone();
+    let snippet_insert = snippet_encrypted.clone();
+    let summary_insert = analysis_summary.clone();
+    let sentiment_insert = analysis_sentiment.clone();
+    let categories_insert = categories_json.clone();
+
+    conn.execute(
+        r#"
+        INSERT INTO deleted_messages (
+            account_email,
+            uid,
+            sender_email,
+            sender_display,
+            subject_encrypted,
+            date,
+            snippet_encrypted,
+            flags,
+            analysis_summary,
+            analysis_sentiment,
+            analysis_categories,
+            deleted_at,
+            remote_deleted_at,
+            remote_error,
+            folder,
+            uidvalidity,
+            message_key
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, NULL, ?, ?, ?, ?, NULL, NULL, ?, ?, ?)
+        ON CONFLICT(account_email, folder, uid) DO UPDATE SET
+            sender_email = excluded.sender_email,
+            sender_display = excluded.sender_display,
+            subject_encrypted = excluded.subject_encrypted,
+            date = excluded.date,
+            snippet_encrypted = excluded.snippet_encrypted,
+            flags = excluded.flags,
+            analysis_summary = excluded.analysis_summary,
+            analysis_sentiment = excluded.analysis_sentiment,
+            analysis_categories = excluded.analysis_categories,
+            deleted_at = excluded.deleted_at,
+            remote_deleted_at = NULL,
+            remote_error = NULL,
+            uidvalidity = excluded.uidvalidity,
+            message_key = excluded.message_key
+        "#,
+        params![
+            account_key,
+            uid_insert,
+            sender_email_insert,
+            sender_display_insert,
+            subject_insert,
+            date_insert,
+            snippet_insert,
+            summary_insert,
+            sentiment_insert,
+            categories_insert,
+            now,
+            folder,
+            uid_validity,
+            message_key.clone(),
+        ],
+    )?;
+
+    conn.execute(
+        "UPDATE messages SET tombstoned_at = ?, tombstone_reason = ? WHERE id = ?",
+        params![now, TOMBSTONE_LOCAL, message_id],
+    )?;
+
+    let subject = cipher.decrypt_string(&subject_encrypted)?;
+    let snippet = snippet_encrypted
+        .as_ref()
+        .map(|value| cipher.decrypt_string(value))
+        .transpose()?;
+    let categories: Vec<String> = categories_json
+        .as_ref()
+        .map(|value| {
+            serde_json::from_str(value).map_err(|err| StorageError::Serialization(err.to_string()))
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(Some(DeletedMessageRow {
+        folder,
+        uid,
+        message_key,
+        uid_validity: uid_validity_from(uid_validity),
+        sender_email,
+        sender_display,
+        subject,
+        snippet,
+        date,
+        analysis_summary,
+        analysis_sentiment,
+        analysis_categories: categories,
+        deleted_at: now,
+        remote_deleted_at: None,
+        remote_error: None,
+    }))
+}
+
+/// A Message-ID, without brackets, on the account's own domain.
+/// `References` as stored on an outbox entry: space-separated, or NULL.
+fn reference_ids(references: &[String]) -> Option<String> {
+    (!references.is_empty()).then(|| references.join(" "))
+}
+
+fn new_message_id(account_email: &str) -> String {
+    let domain = account_email
+        .rsplit_once('@')
+        .map_or("localhost", |(_, domain)| domain)
+        .to_lowercase();
+    format!("{}@{domain}", uuid::Uuid::new_v4())
+}
+
+fn uploads_json(uploads: &[AttachmentUpload]) -> Result<Option<String>> {
+    if uploads.is_empty() {
+        return Ok(None);
+    }
+    serde_json::to_string(uploads)
+        .map(Some)
+        .map_err(|err| StorageError::Serialization(err.to_string()))
+}
+
+/// The outbox entry a failure report for `recipient` answers, if any.
+fn match_outbox(
+    conn: &Connection,
+    cipher: &Cipher,
+    account_email: &str,
+    report: &DeliveryReport,
+    recipient: &str,
+) -> Result<Option<i64>> {
+    if let Some(message_id) = report.original_message_id.as_deref() {
+        let found = conn
+            .query_row(
+                "SELECT id FROM outbox WHERE account_email = ? AND message_id = ?",
+                params![account_email, bounces::normalize_message_id(message_id)],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT id, subject_encrypted FROM outbox
+        WHERE account_email = ? AND lower(recipient) = ? AND status IN ('sent', 'sending')
+        ORDER BY updated_at DESC, id DESC
+        LIMIT 50
+        "#,
+    )?;
+    let mut rows = stmt.query(params![account_email, recipient])?;
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let Some(original) = report.original_subject.as_deref() else {
+            return Ok(Some(id));
+        };
+        let subject = cipher.decrypt_string(&row.get::<_, String>(1)?)?;
+        if subject.trim().eq_ignore_ascii_case(original.trim()) {
+            return Ok(Some(id));
+        }
+    }
+    Ok(None)
+}
+
+fn outbox_item_from_row(row: &rusqlite::Row<'_>, cipher: &Cipher) -> Result<OutboxItem> {
+    let subject_enc: String = row.get(4)?;
+    let uploads: Option<String> = row.get(12)?;
+    Ok(OutboxItem {
+        id: row.get(0)?,
+        account_email: row.get(1)?,
+        merge_id: row.get(2)?,
+        recipient: row.get(3)?,
+        subject: cipher.decrypt_string(&subject_enc)?,
+        status: row.get(5)?,
+        attempts: row.get(6)?,
+        last_error: row.get(7)?,
+        scheduled_at: row.get(8)?,
+        updated_at: row.get(9)?,
+        message_id: row.get(10)?,
+        delivery_status: row.get(11)?,
+        uploads: match uploads {
+            Some(json) => serde_json::from_str(&json)
+                .map_err(|err| StorageError::Serialization(err.to_string()))?,
+            None => Vec::new(),
+        },
+        delivery_path: row.get(13)?,
+    })
+}
+
+fn load_spam_model(conn: &Connection) -> Result<SpamModel> {
+    let mut model = SpamModel::default();
+
+    let mut stmt = conn.prepare("SELECT label, COUNT(*) FROM spam_training GROUP BY label")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let label: String = row.get(0)?;
+        let count: i64 = row.get(1)?;
+        match SpamLabel::parse(&label) {
+            Some(SpamLabel::Spam) => model.spam_docs = count.max(0) as u64,
+            Some(SpamLabel::Ham) => model.ham_docs = count.max(0) as u64,
+            None => {}
+        }
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT token_hmac, spam_count, ham_count FROM spam_tokens \
+         WHERE spam_count > 0 OR ham_count > 0",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let token: String = row.get(0)?;
+        let spam_count: i64 = row.get(1)?;
+        let ham_count: i64 = row.get(2)?;
+        model
+            .tokens
+            .insert(token, (spam_count.max(0) as u64, ham_count.max(0) as u64));
+    }
+
+    Ok(model)
+}
+
+/// Moves one training document to `label`, undoing any earlier label first.
+/// Returns `false` when the document already had that label.
+fn apply_spam_training(
+    conn: &Connection,
+    cipher: &Cipher,
+    key: &str,
+    tokens: &[String],
+    label: Option<SpamLabel>,
+) -> Result<bool> {
+    let existing: Option<(String, String)> = conn
+        .query_row(
+            "SELECT label, tokens_encrypted FROM spam_training WHERE document_key = ?",
+            params![key],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    if let Some((previous_label, previous_tokens)) = &existing {
+        if label.map(|value| value.as_str()) == Some(previous_label.as_str()) {
+            return Ok(false);
+        }
+        let previous_tokens: Vec<String> =
+            serde_json::from_str(&cipher.decrypt_string(previous_tokens)?)
+                .map_err(|err| StorageError::Serialization(err.to_string()))?;
+        if let Some(previous) = SpamLabel::parse(previous_label) {
+            adjust_spam_tokens(conn, cipher, &previous_tokens, previous, -1)?;
+        }
+    }
+
+    match label {
+        Some(label) => {
+            adjust_spam_tokens(conn, cipher, tokens, label, 1)?;
+            let tokens_json = serde_json::to_string(tokens)
+                .map_err(|err| StorageError::Serialization(err.to_string()))?;
+            conn.execute(
+                r#"
+                INSERT INTO spam_training(document_key, label, tokens_encrypted, trained_at)
+                VALUES(?, ?, ?, ?)
+                ON CONFLICT(document_key) DO UPDATE SET
+                    label = excluded.label,
+                    tokens_encrypted = excluded.tokens_encrypted,
+                    trained_at = excluded.trained_at
+                "#,
+                params![
+                    key,
+                    label.as_str(),
+                    cipher.encrypt_string(&tokens_json)?,
+                    Utc::now().timestamp()
+                ],
+            )?;
+            Ok(true)
+        }
+        None => {
+            if existing.is_none() {
+                return Ok(false);
+            }
+            conn.execute(
+                "DELETE FROM spam_training WHERE document_key = ?",
+                params![key],
+            )?;
+            Ok(true)
+        }
+    }
+}
+
+/// Token counts are keyed by [`Cipher::digest`] so the words a user's mail
+/// is made of are not readable from the database.
+fn adjust_spam_tokens(
+    conn: &Connection,
+    cipher: &Cipher,
+    tokens: &[String],
+    label: SpamLabel,
+    delta: i64,
+) -> Result<()> {
+    let (spam_delta, ham_delta) = match label {
+        SpamLabel::Spam => (delta, 0),
+        SpamLabel::Ham => (0, delta),
+    };
+    let mut stmt = conn.prepare_cached(
+        r#"
+        INSERT INTO spam_tokens(token_hmac, spam_count, ham_count)
+        VALUES(?1, MAX(?2, 0), MAX(?3, 0))
+        ON CONFLICT(token_hmac) DO UPDATE SET
+            spam_count = MAX(spam_count + ?2, 0),
+            ham_count = MAX(ham_count + ?3, 0)
+        "#,
+    )?;
+    for token in tokens {
+        stmt.execute(params![cipher.digest(token), spam_delta, ham_delta])?;
+    }
+    Ok(())
+}
+
+/// Replaces the plaintext `spam_tokens.token` column of older databases with
+/// keyed digests. The counts are rebuilt from `spam_training`, which keeps
+/// each document's tokens encrypted.
+fn rekey_spam_tokens(conn: &Connection, cipher: &Cipher) -> Result<()> {
+    if !column_exists(conn, "spam_tokens", "token")? {
+        return Ok(());
+    }
+    let tx = conn.unchecked_transaction()?;
+    tx.execute_batch(
+        r#"
+        DROP TABLE spam_tokens;
+        CREATE TABLE spam_tokens (
+            token_hmac TEXT PRIMARY KEY,
+            spam_count INTEGER NOT NULL DEFAULT 0,
+            ham_count INTEGER NOT NULL DEFAULT 0
+        );
+        "#,
+    )?;
+    {
+        let mut stmt = tx.prepare("SELECT label, tokens_encrypted FROM spam_training")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let label: String = row.get(0)?;
+            let tokens_encrypted: String = row.get(1)?;
+            let tokens: Vec<String> =
+                serde_json::from_str(&cipher.decrypt_string(&tokens_encrypted)?)
+                    .map_err(|err| StorageError::Serialization(err.to_string()))?;
+            if let Some(label) = SpamLabel::parse(&label) {
+                adjust_spam_tokens(&tx, cipher, &tokens, label, 1)?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Subject and, when `with_snippet`, snippet of a message, taken from the
+/// decrypt cache while the row's `updated_at` still matches.
+fn decrypt_listing_text(
+    cipher: &Cipher,
+    cache: &DecryptCache,
+    (account, folder, uid, updated_at): (&str, &str, u32, i64),
+    subject_enc: &str,
+    snippet_enc: Option<&str>,
+    with_snippet: bool,
+) -> Result<(String, Option<String>)> {
+    let cached = cache.get(account, folder, uid, updated_at);
+    let subject = match &cached {
+        Some(entry) => entry.subject.clone(),
+        None => cipher.decrypt_string(subject_enc)?,
+    };
+    let snippet = match cached.as_ref().and_then(|entry| entry.snippet.clone()) {
+        Some(snippet) => Some(snippet),
+        None if with_snippet => Some(
+            snippet_enc
+                .map(|value| cipher.decrypt_string(value))
+                .transpose()?,
+        ),
+        None => None,
+    };
+    let entry = Decrypted { subject, snippet };
+    if cached.as_ref() != Some(&entry) {
+        cache.insert(account, folder, uid, updated_at, entry.clone());
+    }
+    let snippet = entry.snippet.filter(|_| with_snippet).flatten();
+    Ok((entry.subject, snippet))
+}
+
+/// Columns [`message_row_from_row`] reads, in order. Queries that select
+/// more add their columns after these.
+const MESSAGE_ROW_COLUMNS: &str = r#"
+    m.id, m.uid, m.sender_email, m.sender_display, m.subject_encrypted, m.date,
+    m.snippet_encrypted, m.body_encrypted IS NOT NULL AS body_cached, m.flags,
+    COALESCE(sa.status, NULLIF(ss.status, 'neutral'), sd.status, 'neutral'),
+    ar.summary, ar.sentiment, ar.categories,
+    ar.metadata_json, ar.model_id, COALESCE(ar.analyzed, 0), ar.analyzed_at,
+    ar.analysis_confidence, ar.validator_model_id, ar.validation_status,
+    ar.validation_confidence, ar.validation_notes, ar.validated_at,
+    m.spam_score, m.account_email, m.updated_at, m.message_key, m.size_bytes,
+    m.thread_id, m.folder"#;
+/// How many columns [`MESSAGE_ROW_COLUMNS`] selects.
+const MESSAGE_ROW_WIDTH: usize = 30;
+
+/// The joins [`MESSAGE_ROW_COLUMNS`] needs: global, account, and domain
+/// sender rules plus the analysis.
+const MESSAGE_ROW_JOINS: &str = r#"
+    FROM messages m
+    LEFT JOIN sender_status ss
+        ON ss.sender_email = m.sender_email AND ss.scope = 'global'
+    LEFT JOIN sender_status sa
+        ON sa.sender_email = m.sender_email AND sa.scope = m.account_email
+    LEFT JOIN sender_status sd
+        ON sd.sender_email = substr(m.sender_email, instr(m.sender_email, '@'))
+        AND sd.scope = 'global'
+    LEFT JOIN analysis_results ar ON ar.message_id = m.id"#;
+
+/// Reads a row selected with [`MESSAGE_ROW_COLUMNS`]. The snippet, analysis
+/// categories, and analysis metadata are only decrypted or parsed when
+/// `fields` includes them.
+fn message_row_from_row(
+    row: &rusqlite::Row<'_>,
+    cipher: &Cipher,
+    cache: &DecryptCache,
+    fields: &FieldMask,
+) -> Result<MessageRow> {
+    let uid: u32 = row.get(1)?;
+    let sender_email: String = row.get(2)?;
+    let sender_display = row
+        .get::<_, Option<String>>(3)?
+        .unwrap_or_else(|| sender_email.clone());
+
+    let account_email: String = row.get(24)?;
+    let folder: String = row.get(29)?;
+    let subject_enc: String = row.get(4)?;
+    let snippet_enc: Option<String> = row.get(6)?;
+    let (subject, snippet) = decrypt_listing_text(
+        cipher,
+        cache,
+        (&account_email, &folder, uid, row.get(25)?),
+        &subject_enc,
+        snippet_enc.as_deref(),
+        fields.includes("snippet"),
+    )?;
+
+    let status_value: String = row.get(9)?;
+
+    let categories_json: Option<String> = row.get(12)?;
+    let analysis_categories = categories_json
+        .as_ref()
+        .filter(|_| fields.includes("analysis_categories"))
+        .map(|value| {
+            serde_json::from_str::<Vec<String>>(value)
+                .map_err(|err| StorageError::Serialization(err.to_string()))
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    let metadata_json: Option<String> = row.get(13)?;
+    let analysis_metadata = metadata_json
+        .as_ref()
+        .filter(|_| fields.includes("analysis_metadata"))
+        .map(|value| {
+            serde_json::from_str::<Value>(value)
+                .map_err(|err| StorageError::Serialization(err.to_string()))
+        })
+        .transpose()?;
+
+    Ok(MessageRow {
+        id: row.get(0)?,
+        folder,
+        uid,
+        message_key: row.get(26)?,
+        sender_email,
+        sender_display,
+        subject,
+        date: row.get(5)?,
+        snippet,
+        flags: row.get(8)?,
+        status: SenderStatus::from_str(&status_value),
+        analysis_summary: row.get(10)?,
+        analysis_sentiment: row.get(11)?,
+        analysis_categories,
+        analysis_metadata,
+        analysis_model_id: row.get(14)?,
+        analysis_analyzed: row.get::<_, i64>(15)? != 0,
+        analysis_analyzed_at: row.get(16)?,
+        analysis_confidence: row.get(17)?,
+        analysis_validator_model_id: row.get(18)?,
+        analysis_validation_status: row.get(19)?,
+        analysis_validation_confidence: row.get(20)?,
+        analysis_validation_notes: row.get(21)?,
+        analysis_validated_at: row.get(22)?,
+        spam_score: row.get(23)?,
+        body_cached: row.get::<_, i64>(7)? != 0,
+        size_bytes: row.get(27)?,
+        thread_id: row.get(28)?,
+    })
+}
+
+fn sender_profile_from_row(
+    row: &rusqlite::Row<'_>,
+    offset: usize,
+) -> Result<Option<SenderProfile>> {
+    let kind: Option<String> = row.get(offset)?;
+    let Some(kind) = kind else {
+        return Ok(None);
+    };
+
+    let tags_json: Option<String> = row.get(offset + 1)?;
+    let typical_tags = tags_json
+        .as_ref()
+        .map(|value| {
+            serde_json::from_str::<Vec<String>>(value)
+                .map_err(|err| StorageError::Serialization(err.to_string()))
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(Some(SenderProfile {
+        kind,
+        typical_tags,
+        typical_priority: row.get(offset + 2)?,
+        sample_count: row.get(offset + 3)?,
+        confidence: row.get::<_, Option<f64>>(offset + 4)?.unwrap_or(0.0),
+        updated_at: row.get(offset + 5)?,
+    }))
+}
+
+/// Reads `reason_encrypted, origin_uid, created_at` from `sender_block_notes`
+/// starting at `offset`.
+fn block_note_from_row(
+    row: &rusqlite::Row<'_>,
+    offset: usize,
+    cipher: &Cipher,
+) -> Result<Option<BlockNote>> {
+    let Some(created_at) = row.get::<_, Option<i64>>(offset + 2)? else {
+        return Ok(None);
+    };
+    let reason = row
+        .get::<_, Option<String>>(offset)?
+        .map(|value| cipher.decrypt_string(&value))
+        .transpose()?;
+    Ok(Some(BlockNote {
+        reason,
+        origin_uid: row.get(offset + 1)?,
+        created_at,
+    }))
+}
+
+pub fn sender_domain(email: &str) -> Option<String> {
+    let normalized = normalize_sender(email);
+    normalized
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_string())
+        .filter(|domain| !domain.is_empty())
+}
+
 pub fn sender_fingerprint(email: &str) -> String {
     let normalized = normalize_sender(email);
     let digest = Sha256::digest(normalized.as_bytes());
     hex::encode(digest)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn cipher_for(key: [u8; 32]) -> Cipher {
+        Cipher::from_bytes(key.to_vec()).unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn encrypted_values_round_trip(
+            key in any::<[u8; 32]>(),
+            text in "\\PC{0,200}",
+            bytes in proptest::collection::vec(any::<u8>(), 0..1024),
+        ) {
+            let cipher = cipher_for(key);
+            prop_assert_eq!(cipher.decrypt_string(&cipher.encrypt_string(&text)?)?, text);
+            prop_assert_eq!(cipher.decrypt_bytes(&cipher.encrypt_bytes(&bytes)?)?, bytes);
+        }
+
+        #[test]
+        fn tampered_or_foreign_ciphertext_is_rejected(
+            key in any::<[u8; 32]>(),
+            other in any::<[u8; 32]>(),
+            bytes in proptest::collection::vec(any::<u8>(), 0..256),
+            flip in any::<prop::sample::Index>(),
+        ) {
+            let cipher = cipher_for(key);
+            let encrypted = cipher.encrypt_bytes(&bytes)?;
+            let mut combined = general_purpose::STANDARD.decode(&encrypted).unwrap();
+            let at = flip.index(combined.len());
+            combined[at] ^= 0x01;
+            let tampered = general_purpose::STANDARD.encode(combined);
+            prop_assert!(cipher.decrypt_bytes(&tampered).is_err());
+            if other != key {
+                prop_assert!(cipher_for(other).decrypt_bytes(&encrypted).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn wiping_a_store_shreds_nested_files_and_folders() {
+        let dir = tempfile::tempdir().unwrap();
+        let avatars = dir.path().join("avatars");
+        let attachments = dir.path().join("elsewhere").join("attachments");
+        fs::create_dir_all(&avatars).unwrap();
+        let account = attachments.join("me@example.test");
+        fs::create_dir_all(&account).unwrap();
+        fs::write(avatars.join("example.org.png"), b"png").unwrap();
+        fs::write(account.join("7-report.pdf"), b"pdf").unwrap();
+
+        let mut removed = shred_dir(&avatars).unwrap();
+        removed.extend(shred_dir(&attachments).unwrap());
+
+        assert_eq!(removed.len(), 2);
+        assert!(!avatars.exists());
+        assert!(!attachments.exists());
+        assert!(dir.path().join("elsewhere").exists());
+        assert!(shred_dir(&avatars).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_same_uid_in_two_folders_stays_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path()).unwrap();
+        let account = "me@example.test";
+        let insert = |folder: &str| MessageInsert {
+            account_email: account.into(),
+            folder: folder.into(),
+            uid: 7,
+            sender_display: "Ann Lee".into(),
+            sender_email: "ann@example.org".into(),
+            subject: format!("In {folder}"),
+            date: None,
+            snippet: None,
+            body: None,
+            flags: None,
+            size: None,
+            dmarc_aligned: None,
+            thread_id: None,
+        };
+        storage
+            .upsert_messages(vec![insert("INBOX"), insert("Archive")])
+            .await
+            .unwrap();
+
+        let messages = storage.messages_for_analysis(account).await.unwrap();
+        let inbox = messages.iter().find(|m| m.folder == "INBOX").unwrap();
+        storage
+            .upsert_analysis(vec![AnalysisInsert {
+                message_key: Some(inbox.message_key.clone()),
+                account_email: account.into(),
+                folder: "Archive".into(),
+                uid: 7,
+                summary: Some("Analyzed".into()),
+                sentiment: None,
+                categories: Vec::new(),
+                metadata_json: Value::Null,
+                model_id: None,
+                analyzed: true,
+                analyzed_at: Some(1),
+                analysis_confidence: None,
+                validation: AnalysisValidation::default(),
+                fingerprint: None,
+            }])
+            .await
+            .unwrap();
+        let analyzed = storage
+            .messages_for_analysis(account)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.folder, m.existing_analysis.analyzed))
+            .collect::<HashMap<_, _>>();
+        assert!(analyzed["INBOX"]);
+        assert!(!analyzed["Archive"]);
+
+        let archived = storage
+            .archive_message(account, "Archive", 7)
+            .await
+            .unwrap()
+            .unwrap();
+        let pending = storage.pending_remote_deletes(account, 10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].folder, "Archive");
+        assert_eq!(pending[0].message_key, archived.message_key);
+        storage
+            .mark_deleted_remote(&archived.message_key, Some(1), None)
+            .await
+            .unwrap();
+        assert!(storage
+            .pending_remote_deletes(account, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        storage
+            .queue_flag_changes(account, "INBOX", &[7], &["flagged".into()], true)
+            .await
+            .unwrap();
+        let queued = storage.pending_flag_changes(account).await.unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].folder, "INBOX");
+    }
+
+    #[tokio::test]
+    async fn marking_a_read_message_unread_and_back_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(dir.path()).unwrap();
+        let account = "me@example.test";
+        storage
+            .upsert_messages(vec![MessageInsert {
+                account_email: account.into(),
+                folder: "INBOX".into(),
+                uid: 3,
+                sender_display: "Ann Lee".into(),
+                sender_email: "ann@example.org".into(),
+                subject: "Read already".into(),
+                date: None,
+                snippet: None,
+                body: None,
+                flags: Some("seen flagged".into()),
+                size: None,
+                dmarc_aligned: None,
+                thread_id: None,
+            }])
+            .await
+            .unwrap();
+        let cached_flags = || {
+            storage
+                .conn
+                .lock()
+                .query_row(
+                    "SELECT flags FROM messages WHERE account_email = ? AND uid = 3",
+                    params![account],
+                    |row| row.get::<_, Option<String>>(0),
+                )
+                .unwrap()
+        };
+
+        // Either spelling of the flag is the one the cache holds.
+        let unread = ["\\Seen".to_string()];
+        let changed = storage
+            .queue_flag_changes(account, "INBOX", &[3], &unread, false)
+            .await
+            .unwrap();
+        assert_eq!(changed, 1);
+        assert_eq!(cached_flags().as_deref(), Some("flagged"));
+        let queued = storage.pending_flag_changes(account).await.unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(
+            (queued[0].flag.as_str(), queued[0].enabled),
+            ("seen", false)
+        );
+
+        storage
+            .queue_flag_changes(account, "INBOX", &[3], &["seen".into()], true)
+            .await
+            .unwrap();
+        assert!(flag_sync::same_flags(
+            cached_flags().as_deref(),
+            Some("seen flagged")
+        ));
+        assert!(storage
+            .pending_flag_changes(account)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn text_uids_from_before_folders_migrate_to_integers() {
+        let dir = tempfile::tempdir().unwrap();
+        // The cache as it was when UIDs were TEXT and everything was INBOX.
+        Connection::open(dir.path().join(data_dir::DATABASE_FILE))
+            .unwrap()
+            .execute_batch(
+                r#"
+                CREATE TABLE messages (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    account_email TEXT NOT NULL,
+                    uid TEXT NOT NULL,
+                    sender_email TEXT NOT NULL,
+                    sender_display TEXT,
+                    subject_encrypted TEXT,
+                    date TEXT,
+                    snippet_encrypted TEXT,
+                    body_encrypted TEXT,
+                    flags TEXT,
+                    created_at INTEGER NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    UNIQUE(account_email, uid)
+                );
+                CREATE TABLE deleted_messages (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    account_email TEXT NOT NULL,
+                    uid TEXT NOT NULL,
+                    sender_email TEXT NOT NULL,
+                    sender_display TEXT,
+                    subject_encrypted TEXT,
+                    date TEXT,
+                    snippet_encrypted TEXT,
+                    flags TEXT,
+                    analysis_summary TEXT,
+                    analysis_sentiment TEXT,
+                    analysis_categories TEXT,
+                    deleted_at INTEGER NOT NULL,
+                    remote_deleted_at INTEGER,
+                    remote_error TEXT,
+                    UNIQUE(account_email, uid)
+                );
+                CREATE TABLE analysis_results (
+                    message_id INTEGER PRIMARY KEY,
+                    summary TEXT,
+                    sentiment TEXT,
+                    categories TEXT,
+                    metadata_json TEXT,
+                    model_id TEXT,
+                    analyzed INTEGER NOT NULL DEFAULT 0,
+                    analyzed_at INTEGER
+                );
+                CREATE TABLE message_links (
+                    account_email TEXT NOT NULL,
+                    uid TEXT NOT NULL,
+                    position INTEGER NOT NULL,
+                    url_encrypted TEXT NOT NULL,
+                    domain TEXT NOT NULL,
+                    display_text_encrypted TEXT,
+                    mismatch INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY(account_email, uid, position)
+                );
+                CREATE INDEX idx_message_links_domain ON message_links(domain);
+
+                INSERT INTO messages
+                    (id, account_email, uid, sender_email, created_at, updated_at)
+                VALUES
+                    (1, 'me@example.test', '42', 'ann@example.org', 1, 1),
+                    (2, 'me@example.test', 'draft-1', 'ann@example.org', 1, 1);
+                INSERT INTO analysis_results (message_id, summary, analyzed)
+                VALUES (1, 'Quarterly numbers', 1), (2, 'Unsendable', 1);
+                INSERT INTO deleted_messages
+                    (account_email, uid, sender_email, analysis_summary, deleted_at)
+                VALUES ('me@example.test', '7', 'bob@example.org', 'Old news', 5);
+                INSERT INTO message_links
+                    (account_email, uid, position, url_encrypted, domain)
+                VALUES
+                    ('me@example.test', '42', 0, 'link', 'example.org'),
+                    ('me@example.test', 'x', 0, 'link', 'example.org');
+                "#,
+            )
+            .unwrap();
+
+        let storage = Storage::open(dir.path()).unwrap();
+        let conn = storage.conn.lock();
+        let messages = conn
+            .prepare("SELECT id, folder, uid, typeof(uid) FROM messages")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(messages, [(1, "INBOX".into(), 42, "integer".into())]);
+        let summaries = conn
+            .prepare("SELECT message_id, summary FROM analysis_results")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(summaries, [(1, "Quarterly numbers".into())]);
+        let deleted = conn
+            .query_row(
+                "SELECT folder, uid, typeof(uid), analysis_summary FROM deleted_messages",
+                [],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            deleted,
+            ("INBOX".into(), 7, "integer".into(), "Old news".into())
+        );
+
+        // The side table was set aside, made again, and its rows copied back.
+        let links = conn
+            .prepare("SELECT folder, uid, typeof(uid) FROM message_links")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(links, [("INBOX".into(), 42, "integer".into())]);
+        let leftovers = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE name LIKE '%_by_uid'",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap();
+        assert_eq!(leftovers, 0);
+    }
+}
@@ -0,0 +1,174 @@
+//! Subject lines: candidates suggested by the LLM from a draft's body, and
+//! the check that stops a message going out with an empty or placeholder
+//! subject.
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Candidates returned by [`parse_candidates`].
+pub const CANDIDATE_COUNT: usize = 3;
+/// Longer subjects get cut off in most inbox lists.
+pub const MAX_SUBJECT_CHARS: usize = 78;
+/// Most body text a suggestion prompt carries.
+pub const MAX_PROMPT_BODY_CHARS: usize = 2000;
+
+/// Subjects, lowercase and without punctuation, that say nothing about the
+/// message.
+const PLACEHOLDERS: &[&str] = &[
+    "no subject",
+    "subject",
+    "untitled",
+    "test",
+    "hi",
+    "hello",
+    "hey",
+    "re",
+    "fwd",
+    "fw",
+    "question",
+    "update",
+    "todo",
+    "tbd",
+    "xxx",
+    "asdf",
+    "draft",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SubjectIssue {
+    Empty,
+    /// A subject like "test" or "(no subject)" that says nothing.
+    Placeholder {
+        subject: String,
+    },
+}
+
+/// Why `subject` is not worth sending, if it is not.
+pub fn check(subject: &str) -> Option<SubjectIssue> {
+    let words = subject
+        .split(|ch: char| !ch.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        return Some(SubjectIssue::Empty);
+    }
+    // "Re: Fwd: test" is a placeholder too once the prefixes are gone.
+    let text = words
+        .iter()
+        .skip_while(|word| matches!(word.as_str(), "re" | "fwd" | "fw"))
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (text.is_empty() || PLACEHOLDERS.contains(&text.as_str())).then(|| SubjectIssue::Placeholder {
+        subject: subject.trim().to_string(),
+    })
+}
+
+pub fn prompt(body: &str) -> String {
+    let body: String = body.trim().chars().take(MAX_PROMPT_BODY_CHARS).collect();
+    format!(
+        "Suggest {CANDIDATE_COUNT} different subject lines for this email, each under \
+         {MAX_SUBJECT_CHARS} characters, specific to its content.\n\
+         Respond with a JSON array of strings only.\n\n{body}\n"
+    )
+}
+
+/// Up to [`CANDIDATE_COUNT`] distinct, usable subjects from the model's
+/// reply, which is a JSON array or, failing that, one subject per line.
+pub fn parse_candidates(raw: &str) -> Vec<String> {
+    let from_json = raw
+        .find('[')
+        .zip(raw.rfind(']'))
+        .filter(|(start, end)| start < end)
+        .and_then(|(start, end)| serde_json::from_str::<Vec<Value>>(&raw[start..=end]).ok())
+        .map(|values| {
+            values
+                .into_iter()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect::<Vec<_>>()
+        });
+    let lines = from_json.unwrap_or_else(|| raw.lines().map(str::to_string).collect());
+
+    let mut candidates: Vec<String> = Vec::new();
+    for line in lines {
+        let subject = clean(&line);
+        if subject.is_empty()
+            || check(&subject).is_some()
+            || candidates
+                .iter()
+                .any(|existing| existing.eq_ignore_ascii_case(&subject))
+        {
+            continue;
+        }
+        candidates.push(subject);
+        if candidates.len() == CANDIDATE_COUNT {
+            break;
+        }
+    }
+    candidates
+}
+
+/// Strips list markers, quotes, and a `Subject:` label, and shortens the
+/// subject to [`MAX_SUBJECT_CHARS`] on a word boundary.
+fn clean(line: &str) -> String {
+    let mut text = line.trim().trim_start_matches(['-', '*', '•']).trim_start();
+    let digits = text.len()
+        - text
+            .trim_start_matches(|ch: char| ch.is_ascii_digit())
+            .len();
+    if digits > 0 && matches!(text[digits..].chars().next(), Some('.' | ')')) {
+        text = text[digits + 1..].trim_start();
+    }
+    if let Some(label) = text
+        .get(..8)
+        .filter(|label| label.eq_ignore_ascii_case("subject:"))
+    {
+        text = text[label.len()..].trim();
+    }
+    let text = text
+        .trim_matches(|ch: char| matches!(ch, '"' | '\'' | '`'))
+        .trim();
+    if text.chars().count() <= MAX_SUBJECT_CHARS {
+        return text.to_string();
+    }
+    let clipped: String = text.chars().take(MAX_SUBJECT_CHARS).collect();
+    match clipped.rfind(' ') {
+        Some(space) => clipped[..space].trim_end().to_string(),
+        None => clipped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_empty_and_placeholder_subjects() {
+        assert_eq!(check("  "), Some(SubjectIssue::Empty));
+        assert_eq!(
+            check("Re: (No Subject)"),
+            Some(SubjectIssue::Placeholder {
+                subject: "Re: (No Subject)".into()
+            })
+        );
+        assert!(check("Fwd:").is_some());
+        assert_eq!(check("Q3 budget review moved to Friday"), None);
+        assert_eq!(clean("2024 plans"), "2024 plans");
+    }
+
+    #[test]
+    fn parses_json_or_listed_candidates() {
+        assert_eq!(
+            parse_candidates(
+                "Sure:\n[\"Budget review\", \"budget review\", \"test\", \"Q3 plan\"]"
+            ),
+            vec!["Budget review".to_string(), "Q3 plan".to_string()]
+        );
+        assert_eq!(
+            parse_candidates("1. Subject: \"Lunch on Friday?\"\n2) Team offsite\n- Hi"),
+            vec!["Lunch on Friday?".to_string(), "Team offsite".to_string()]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Fixture mail: single messages built field by field, and whole mailboxes
+//! generated from a handful of senders. Generation is deterministic, so a
+//! test that fails fails the same way every run.
+
+use chrono::{DateTime, Duration, FixedOffset, TimeZone};
+
+/// Senders [`mailbox`] rotates through: people, a newsletter, and a
+/// notification robot.
+const SENDERS: &[(&str, &str)] = &[
+    ("Ann Lee", "ann@example.org"),
+    ("Bob Stone", "bob@example.net"),
+    ("Weekly Digest", "digest@news.example.com"),
+    ("Carol Diaz", "carol@example.org"),
+    ("Build Bot", "noreply@ci.example.com"),
+];
+
+const SUBJECTS: &[&str] = &[
+    "Quarterly planning",
+    "Lunch on Friday?",
+    "Your weekly digest",
+    "Re: Design review notes",
+    "Build failed on main",
+    "Invoice attached",
+];
+
+#[derive(Debug, Clone)]
+pub struct FixtureMessage {
+    pub from: String,
+    pub to: Vec<String>,
+    pub subject: String,
+    pub body: String,
+    pub date: DateTime<FixedOffset>,
+    pub message_id: String,
+    pub in_reply_to: Option<String>,
+    /// Extra headers, written after the standard ones.
+    pub headers: Vec<(String, String)>,
+    /// Flags in the form the client stores them (`seen`, `flagged`, ...).
+    pub flags: Vec<String>,
+}
+
+impl FixtureMessage {
+    pub fn new(from: &str, subject: &str) -> Self {
+        let date = epoch();
+        Self {
+            from: from.to_string(),
+            to: vec![format!("Test User <{}>", TEST_RECIPIENT)],
+            subject: subject.to_string(),
+            body: format!("{subject}\r\n\r\nThis is a fixture message."),
+            message_id: format!("{}.{}@fixtures.test", date.timestamp(), slug(subject)),
+            date,
+            in_reply_to: None,
+            headers: Vec::new(),
+            flags: Vec::new(),
+        }
+    }
+
+    pub fn to(mut self, recipients: &[&str]) -> Self {
+        self.to = recipients
+            .iter()
+            .map(|address| address.to_string())
+            .collect();
+        self
+    }
+
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+
+    pub fn date(mut self, date: DateTime<FixedOffset>) -> Self {
+        self.date = date;
+        self
+    }
+
+    pub fn message_id(mut self, message_id: &str) -> Self {
+        self.message_id = message_id.trim_matches(['<', '>']).to_string();
+        self
+    }
+
+    pub fn in_reply_to(mut self, message_id: &str) -> Self {
+        self.in_reply_to = Some(message_id.trim_matches(['<', '>']).to_string());
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn flag(mut self, flag: &str) -> Self {
+        self.flags.push(flag.to_string());
+        self
+    }
+
+    /// The message as RFC 5322 bytes with CRLF line endings.
+    pub fn raw(&self) -> Vec<u8> {
+        let mut headers = vec![
+            ("Date".to_string(), self.date.to_rfc2822()),
+            ("From".to_string(), self.from.clone()),
+            ("To".to_string(), self.to.join(", ")),
+            ("Subject".to_string(), self.subject.clone()),
+            ("Message-ID".to_string(), format!("<{}>", self.message_id)),
+        ];
+        if let Some(parent) = &self.in_reply_to {
+            headers.push(("In-Reply-To".to_string(), format!("<{parent}>")));
+            headers.push(("References".to_string(), format!("<{parent}>")));
+        }
+        headers.push(("MIME-Version".to_string(), "1.0".to_string()));
+        headers.push((
+            "Content-Type".to_string(),
+            "text/plain; charset=utf-8".to_string(),
+        ));
+        headers.extend(self.headers.iter().cloned());
+
+        let mut raw = String::new();
+        for (name, value) in headers {
+            raw.push_str(&format!("{name}: {value}\r\n"));
+        }
+        raw.push_str("\r\n");
+        raw.push_str(&self.body.replace("\r\n", "\n").replace('\n', "\r\n"));
+        raw.push_str("\r\n");
+        raw.into_bytes()
+    }
+}
+
+/// The address fixture mail is sent to.
+pub const TEST_RECIPIENT: &str = "me@example.test";
+
+/// Midnight UTC on 1 March 2024; generated mail counts back from here.
+pub fn epoch() -> DateTime<FixedOffset> {
+    FixedOffset::east_opt(0)
+        .unwrap()
+        .with_ymd_and_hms(2024, 3, 1, 0, 0, 0)
+        .unwrap()
+}
+
+/// `count` messages, oldest first, one every six hours before [`epoch`].
+/// Every third is seen, every seventh flagged, and the digest sender's
+/// mail carries list headers, as newsletters do.
+pub fn mailbox(count: usize) -> Vec<FixtureMessage> {
+    (0..count)
+        .map(|index| {
+            let (name, address) = SENDERS[index % SENDERS.len()];
+            let subject = SUBJECTS[index % SUBJECTS.len()];
+            let date = epoch() - Duration::hours(6 * (count - index) as i64);
+            let mut message = FixtureMessage::new(&format!("{name} <{address}>"), subject)
+                .date(date)
+                .message_id(&format!("fixture-{index}@fixtures.test"))
+                .body(&format!(
+                    "Hello,\n\nFixture message {index} from {name} about {subject}.\n"
+                ));
+            if address.starts_with("digest@") {
+                message = message
+                    .header("List-Id", "Weekly Digest <digest.news.example.com>")
+                    .header("Precedence", "bulk");
+            }
+            if index % 3 == 0 {
+                message = message.flag("seen");
+            }
+            if index % 7 == 0 {
+                message = message.flag("flagged");
+            }
+            message
+        })
+        .collect()
+}
+
+fn slug(text: &str) -> String {
+    text.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase()
+}
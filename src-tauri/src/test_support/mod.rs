@@ -0,0 +1,11 @@
+//! End-to-end test helpers, built with the `test-support` feature: an IMAP
+//! server that runs inside the test process and fixture mail to fill it.
+//! Provider code talks to it exactly as to a real server, over TLS with a
+//! certificate the client is made to trust, so integration tests exercise
+//! the real command sequences.
+
+pub mod fixtures;
+pub mod server;
+
+pub use fixtures::FixtureMessage;
+pub use server::{trusted_root, TestServer};
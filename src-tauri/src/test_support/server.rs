@@ -0,0 +1,1467 @@
+//! An IMAP server inside the test process: TLS on a loopback port, folders
+//! held in memory, one thread per connection. It speaks the part of
+//! IMAP4rev1 the provider code uses (LOGIN, SELECT, FETCH, SEARCH, STORE,
+//! COPY, EXPUNGE, APPEND, LIST, STATUS, with and without UID) plus IDLE,
+//! and logs every command, so a test can check how the client batched its
+//! work as well as what ended up on the server.
+
+use super::fixtures::FixtureMessage;
+use crate::models::{Credentials, Provider};
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use mailparse::{MailAddr, MailHeaderMap};
+use native_tls::{Certificate, Identity, TlsAcceptor, TlsStream};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair, KeyUsagePurpose};
+use secrecy::SecretString;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The password every test account logs in with.
+pub const PASSWORD: &str = "test-password";
+const DELIMITER: &str = "/";
+const SYSTEM_FLAGS: &[&str] = &["\\Answered", "\\Flagged", "\\Deleted", "\\Seen", "\\Draft"];
+/// How often an idling connection looks for new mail.
+const IDLE_POLL: Duration = Duration::from_millis(50);
+
+struct Tls {
+    acceptor: TlsAcceptor,
+    root: Certificate,
+}
+
+/// One certificate authority per test process, and a `localhost`
+/// certificate from it that every server presents.
+static TLS: Lazy<Tls> = Lazy::new(|| {
+    let ca_key = KeyPair::generate().expect("generate the test CA key");
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).expect("test CA parameters");
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "PersonalMailClient test CA");
+    let ca = ca_params.self_signed(&ca_key).expect("sign the test CA");
+
+    let key = KeyPair::generate().expect("generate the test server key");
+    let params = CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()])
+        .expect("test server certificate parameters");
+    let certificate = params
+        .signed_by(&key, &ca, &ca_key)
+        .expect("sign the test server certificate");
+    let identity =
+        Identity::from_pkcs8(certificate.pem().as_bytes(), key.serialize_pem().as_bytes())
+            .expect("load the test server identity");
+
+    Tls {
+        acceptor: TlsAcceptor::new(identity).expect("build the test TLS acceptor"),
+        root: Certificate::from_der(ca.der()).expect("load the test CA certificate"),
+    }
+});
+
+/// The authority test servers' certificates chain to, once one has
+/// started. `test-support` builds of the IMAP client trust it.
+pub fn trusted_root() -> Option<Certificate> {
+    Lazy::get(&TLS).map(|tls| tls.root.clone())
+}
+
+#[derive(Debug, Clone)]
+struct StoredMessage {
+    uid: u32,
+    flags: BTreeSet<String>,
+    internal_date: DateTime<FixedOffset>,
+    raw: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Folder {
+    uid_validity: u32,
+    uid_next: u32,
+    messages: Vec<StoredMessage>,
+}
+
+impl Folder {
+    fn append(
+        &mut self,
+        flags: BTreeSet<String>,
+        internal_date: DateTime<FixedOffset>,
+        raw: Vec<u8>,
+    ) -> u32 {
+        let uid = self.uid_next;
+        self.uid_next += 1;
+        self.messages.push(StoredMessage {
+            uid,
+            flags,
+            internal_date,
+            raw,
+        });
+        uid
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    folders: BTreeMap<String, Folder>,
+    commands: Vec<String>,
+}
+
+impl State {
+    fn create(&mut self, name: &str) -> bool {
+        if self.folders.contains_key(name) {
+            return false;
+        }
+        let uid_validity = self.folders.len() as u32 + 1;
+        self.folders.insert(
+            name.to_string(),
+            Folder {
+                uid_validity,
+                uid_next: 1,
+                messages: Vec::new(),
+            },
+        );
+        true
+    }
+
+    fn folder_mut(&mut self, name: &str) -> &mut Folder {
+        let name = folder_name(name);
+        self.create(&name);
+        self.folders.get_mut(&name).unwrap()
+    }
+}
+
+/// A running server with one account, `user<port>@example.test`, so tests
+/// running side by side never share pooled sessions. Stops on drop.
+pub struct TestServer {
+    port: u16,
+    email: String,
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    accept: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    /// Starts a server with an empty INBOX.
+    pub fn start() -> Self {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).expect("bind the test IMAP server");
+        let port = listener.local_addr().expect("test server address").port();
+        let email = format!("user{port}@example.test");
+        let state = Arc::new(Mutex::new(State::default()));
+        state.lock().create("INBOX");
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let accept = {
+            let acceptor = TLS.acceptor.clone();
+            let state = state.clone();
+            let stop = stop.clone();
+            let email = email.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let acceptor = acceptor.clone();
+                    let state = state.clone();
+                    let email = email.clone();
+                    let stop = stop.clone();
+                    thread::spawn(move || {
+                        if let Ok(stream) = acceptor.accept(stream) {
+                            let _ = Connection::new(stream, state, email, stop).serve();
+                        }
+                    });
+                }
+            })
+        };
+
+        Self {
+            port,
+            email,
+            state,
+            stop,
+            accept: Some(accept),
+        }
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// Credentials for the server's account, as a custom provider account.
+    pub fn credentials(&self) -> Credentials {
+        Credentials::new(
+            Provider::Custom,
+            self.email.clone(),
+            SecretString::new(PASSWORD.to_string()),
+            Some("localhost".to_string()),
+            Some(self.port),
+        )
+    }
+
+    /// Puts a message in `folder`, creating the folder if needed, and
+    /// returns its UID.
+    pub fn deliver(&self, folder: &str, message: &FixtureMessage) -> u32 {
+        let flags = message.flags.iter().map(|flag| imap_flag(flag)).collect();
+        self.state
+            .lock()
+            .folder_mut(folder)
+            .append(flags, message.date, message.raw())
+    }
+
+    pub fn deliver_all(&self, folder: &str, messages: &[FixtureMessage]) -> Vec<u32> {
+        messages
+            .iter()
+            .map(|message| self.deliver(folder, message))
+            .collect()
+    }
+
+    pub fn folders(&self) -> Vec<String> {
+        self.state.lock().folders.keys().cloned().collect()
+    }
+
+    /// UIDs in `folder`, ascending; empty when it does not exist.
+    pub fn uids(&self, folder: &str) -> Vec<u32> {
+        self.state
+            .lock()
+            .folders
+            .get(&folder_name(folder))
+            .map(|folder| folder.messages.iter().map(|message| message.uid).collect())
+            .unwrap_or_default()
+    }
+
+    /// A message's flags in IMAP spelling (`\Seen`, `$Important`, ...).
+    pub fn flags(&self, folder: &str, uid: u32) -> Option<Vec<String>> {
+        let state = self.state.lock();
+        let folder = state.folders.get(&folder_name(folder))?;
+        let message = folder.messages.iter().find(|message| message.uid == uid)?;
+        Some(message.flags.iter().cloned().collect())
+    }
+
+    /// Replaces a message's flags, as another client would.
+    pub fn set_flags(&self, folder: &str, uid: u32, flags: &[&str]) {
+        let mut state = self.state.lock();
+        let folder = state.folder_mut(folder);
+        if let Some(message) = folder
+            .messages
+            .iter_mut()
+            .find(|message| message.uid == uid)
+        {
+            message.flags = flags.iter().map(|flag| imap_flag(flag)).collect();
+        }
+    }
+
+    /// Changes a folder's UIDVALIDITY, as a server rebuilding it would.
+    pub fn set_uid_validity(&self, folder: &str, uid_validity: u32) {
+        self.state.lock().folder_mut(folder).uid_validity = uid_validity;
+    }
+
+    /// Commands received so far, without tags, e.g. `UID FETCH 1:3 (UID)`.
+    /// Passwords are left out.
+    pub fn commands(&self) -> Vec<String> {
+        self.state.lock().commands.clone()
+    }
+
+    pub fn clear_commands(&self) {
+        self.state.lock().commands.clear();
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wakes the accept loop so it sees the stop flag.
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Atom(String),
+    Str(Vec<u8>),
+    List(Vec<Token>),
+}
+
+impl Token {
+    fn text(&self) -> Option<String> {
+        match self {
+            Token::Atom(atom) => Some(atom.clone()),
+            Token::Str(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            Token::List(_) => None,
+        }
+    }
+}
+
+enum Failure {
+    No(String),
+    Bad(String),
+}
+
+type Outcome = Result<String, Failure>;
+
+fn bad(message: impl Into<String>) -> Failure {
+    Failure::Bad(message.into())
+}
+
+struct Selected {
+    name: String,
+    read_only: bool,
+    /// Messages the client has been told about.
+    exists: usize,
+}
+
+struct Connection {
+    stream: TlsStream<TcpStream>,
+    buffer: Vec<u8>,
+    state: Arc<Mutex<State>>,
+    email: String,
+    authenticated: bool,
+    selected: Option<Selected>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Connection {
+    fn new(
+        stream: TlsStream<TcpStream>,
+        state: Arc<Mutex<State>>,
+        email: String,
+        stop: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            stream,
+            buffer: Vec::new(),
+            state,
+            email,
+            authenticated: false,
+            selected: None,
+            stop,
+        }
+    }
+
+    fn serve(mut self) -> io::Result<()> {
+        self.write(b"* OK IMAP4rev1 test server ready\r\n")?;
+        while let Some(request) = self.read_command()? {
+            let tokens = match Parser::new(&request).tokens() {
+                Ok(tokens) => tokens,
+                Err(err) => {
+                    self.write(format!("* BAD {err}\r\n").as_bytes())?;
+                    continue;
+                }
+            };
+            let (Some(Token::Atom(tag)), Some(Token::Atom(command))) =
+                (tokens.first(), tokens.get(1))
+            else {
+                self.write(b"* BAD Missing tag or command\r\n")?;
+                continue;
+            };
+            let tag = tag.clone();
+            let mut command = command.to_ascii_uppercase();
+            let mut args = &tokens[2..];
+            let uid = command == "UID";
+            if uid {
+                let Some(Token::Atom(inner)) = args.first() else {
+                    self.write(format!("{tag} BAD UID needs a command\r\n").as_bytes())?;
+                    continue;
+                };
+                command = inner.to_ascii_uppercase();
+                args = &args[1..];
+            }
+            self.log(&request, &command);
+
+            if command == "LOGOUT" {
+                self.write(b"* BYE Logging out\r\n")?;
+                self.write(format!("{tag} OK LOGOUT completed\r\n").as_bytes())?;
+                return Ok(());
+            }
+            let reply = match self.dispatch(&command, args, uid) {
+                Ok(text) => format!("{tag} OK {text}\r\n"),
+                Err(Failure::No(text)) => format!("{tag} NO {text}\r\n"),
+                Err(Failure::Bad(text)) => format!("{tag} BAD {text}\r\n"),
+            };
+            self.write(reply.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Logs the command's first line without its tag.
+    fn log(&self, request: &[u8], command: &str) {
+        let line = if command == "LOGIN" {
+            "LOGIN".to_string()
+        } else {
+            let first = request
+                .split(|byte| *byte == b'\n')
+                .next()
+                .unwrap_or_default();
+            let text = String::from_utf8_lossy(first);
+            let text = text.trim_end();
+            text.split_once(' ')
+                .map_or(text, |(_, rest)| rest)
+                .to_string()
+        };
+        self.state.lock().commands.push(line);
+    }
+
+    fn dispatch(&mut self, command: &str, args: &[Token], uid: bool) -> Outcome {
+        match command {
+            "CAPABILITY" => {
+                self.write_untagged("* CAPABILITY IMAP4rev1 IDLE\r\n")?;
+                Ok("CAPABILITY completed".into())
+            }
+            "NOOP" => Ok("NOOP completed".into()),
+            "LOGIN" => self.login(args),
+            _ if !self.authenticated => Err(bad("Log in first")),
+            "SELECT" | "EXAMINE" => self.select(args, command == "EXAMINE"),
+            "CREATE" => {
+                let name = mailbox_arg(args.first())?;
+                if self.state.lock().create(&name) {
+                    Ok("CREATE completed".into())
+                } else {
+                    Err(Failure::No(format!("{name} already exists")))
+                }
+            }
+            "LIST" | "LSUB" => self.list(command, args),
+            "STATUS" => self.status(args),
+            "APPEND" => self.append(args),
+            "CLOSE" => {
+                if !self
+                    .selected
+                    .as_ref()
+                    .is_some_and(|selected| selected.read_only)
+                {
+                    self.expunge(false)?;
+                }
+                self.selected = None;
+                Ok("CLOSE completed".into())
+            }
+            "EXPUNGE" => self.expunge(true),
+            "FETCH" => self.fetch(args, uid),
+            "STORE" => self.store(args, uid),
+            "COPY" => self.copy(args, uid),
+            "SEARCH" => self.search(args, uid),
+            "IDLE" => self.idle(),
+            other => Err(bad(format!("Unsupported command {other}"))),
+        }
+    }
+
+    fn login(&mut self, args: &[Token]) -> Outcome {
+        let user = args.first().and_then(Token::text).unwrap_or_default();
+        let password = args.get(1).and_then(Token::text).unwrap_or_default();
+        if user.eq_ignore_ascii_case(&self.email) && password == PASSWORD {
+            self.authenticated = true;
+            Ok("LOGIN completed".into())
+        } else {
+            Err(Failure::No("Invalid credentials".into()))
+        }
+    }
+
+    fn select(&mut self, args: &[Token], read_only: bool) -> Outcome {
+        let name = mailbox_arg(args.first())?;
+        let (exists, uid_validity, uid_next) = {
+            let state = self.state.lock();
+            let folder = state
+                .folders
+                .get(&name)
+                .ok_or_else(|| Failure::No(format!("{name} does not exist")))?;
+            (folder.messages.len(), folder.uid_validity, folder.uid_next)
+        };
+        let flags = SYSTEM_FLAGS.join(" ");
+        self.write_untagged(&format!(
+            "* FLAGS ({flags})\r\n\
+             * OK [PERMANENTFLAGS ({flags} \\*)] Flags permitted\r\n\
+             * {exists} EXISTS\r\n\
+             * 0 RECENT\r\n\
+             * OK [UIDVALIDITY {uid_validity}] UIDs valid\r\n\
+             * OK [UIDNEXT {uid_next}] Predicted next UID\r\n"
+        ))?;
+        self.selected = Some(Selected {
+            name,
+            read_only,
+            exists,
+        });
+        Ok(if read_only {
+            "[READ-ONLY] EXAMINE completed".into()
+        } else {
+            "[READ-WRITE] SELECT completed".into()
+        })
+    }
+
+    fn list(&mut self, command: &str, args: &[Token]) -> Outcome {
+        let pattern = args.get(1).and_then(Token::text).unwrap_or_default();
+        let names = self
+            .state
+            .lock()
+            .folders
+            .keys()
+            .filter(|name| wildcard_match(&pattern, name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in names {
+            let mut line = format!("* {command} (\\HasNoChildren) \"{DELIMITER}\" ").into_bytes();
+            line.extend(string(name.as_bytes()));
+            line.extend_from_slice(b"\r\n");
+            self.write_untagged_bytes(&line)?;
+        }
+        Ok(format!("{command} completed"))
+    }
+
+    fn status(&mut self, args: &[Token]) -> Outcome {
+        let name = mailbox_arg(args.first())?;
+        let Some(Token::List(items)) = args.get(1) else {
+            return Err(bad("STATUS needs a list of items"));
+        };
+        let values = {
+            let state = self.state.lock();
+            let folder = state
+                .folders
+                .get(&name)
+                .ok_or_else(|| Failure::No(format!("{name} does not exist")))?;
+            let unseen = folder
+                .messages
+                .iter()
+                .filter(|message| !message.flags.contains("\\Seen"))
+                .count();
+            items
+                .iter()
+                .map(|item| {
+                    let item = item.text().unwrap_or_default().to_ascii_uppercase();
+                    let value = match item.as_str() {
+                        "MESSAGES" => folder.messages.len() as u32,
+                        "UNSEEN" => unseen as u32,
+                        "RECENT" => 0,
+                        "UIDNEXT" => folder.uid_next,
+                        "UIDVALIDITY" => folder.uid_validity,
+                        other => return Err(bad(format!("Unknown STATUS item {other}"))),
+                    };
+                    Ok(format!("{item} {value}"))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let mut line = b"* STATUS ".to_vec();
+        line.extend(string(name.as_bytes()));
+        line.extend(format!(" ({})\r\n", values.join(" ")).into_bytes());
+        self.write_untagged_bytes(&line)?;
+        Ok("STATUS completed".into())
+    }
+
+    fn append(&mut self, args: &[Token]) -> Outcome {
+        let name = mailbox_arg(args.first())?;
+        let Some(Token::Str(raw)) = args.last() else {
+            return Err(bad("APPEND needs a message literal"));
+        };
+        let mut flags = BTreeSet::new();
+        let mut internal_date = None;
+        for option in &args[1..args.len() - 1] {
+            match option {
+                Token::List(list) => {
+                    flags = list
+                        .iter()
+                        .filter_map(Token::text)
+                        .map(|flag| imap_flag(&flag))
+                        .collect();
+                }
+                other => {
+                    let text = other.text().unwrap_or_default();
+                    let date = DateTime::parse_from_str(text.trim(), "%d-%b-%Y %H:%M:%S %z")
+                        .map_err(|_| bad(format!("Bad date-time {text}")))?;
+                    internal_date = Some(date);
+                }
+            }
+        }
+        let internal_date = internal_date.unwrap_or_else(|| chrono::Utc::now().into());
+        let mut state = self.state.lock();
+        let folder = state
+            .folders
+            .get_mut(&name)
+            .ok_or_else(|| Failure::No(format!("{name} does not exist")))?;
+        folder.append(flags, internal_date, raw.clone());
+        Ok("APPEND completed".into())
+    }
+
+    fn expunge(&mut self, report: bool) -> Outcome {
+        let name = self.selected_name()?;
+        let expunged = {
+            let mut state = self.state.lock();
+            let folder = state.folder_mut(&name);
+            let mut expunged = Vec::new();
+            let mut position = 0;
+            folder.messages.retain(|message| {
+                position += 1;
+                let deleted = message.flags.contains("\\Deleted");
+                if deleted {
+                    // Each EXPUNGE renumbers the messages after it.
+                    expunged.push(position - expunged.len());
+                }
+                !deleted
+            });
+            expunged
+        };
+        if report {
+            for sequence in expunged {
+                self.write_untagged(&format!("* {sequence} EXPUNGE\r\n"))?;
+            }
+        }
+        Ok("EXPUNGE completed".into())
+    }
+
+    fn fetch(&mut self, args: &[Token], uid: bool) -> Outcome {
+        let name = self.selected_name()?;
+        let set = atom_arg(args.first(), "a message set")?;
+        let mut items = match args.get(1) {
+            Some(Token::List(list)) => list.iter().filter_map(Token::text).collect::<Vec<_>>(),
+            Some(Token::Atom(atom)) => match atom.to_ascii_uppercase().as_str() {
+                "ALL" => vec!["FLAGS", "INTERNALDATE", "RFC822.SIZE", "ENVELOPE"],
+                "FAST" => vec!["FLAGS", "INTERNALDATE", "RFC822.SIZE"],
+                _ => vec![atom.as_str()],
+            }
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+            _ => return Err(bad("FETCH needs data items")),
+        };
+        if uid && !items.iter().any(|item| item.eq_ignore_ascii_case("UID")) {
+            items.insert(0, "UID".into());
+        }
+
+        let mut state = self.state.lock();
+        let folder = state.folder_mut(&name);
+        let selected = select_messages(&folder.messages, &set, uid)?;
+        let mut response = Vec::new();
+        for index in selected {
+            let message = &folder.messages[index];
+            let mut parts = Vec::new();
+            let mut mark_seen = false;
+            for item in &items {
+                parts.push(fetch_item(item, message, &mut mark_seen).map_err(bad)?);
+            }
+            response.extend(format!("* {} FETCH (", index + 1).into_bytes());
+            response.extend(parts.join(&b' '));
+            response.extend_from_slice(b")\r\n");
+            if mark_seen
+                && !self
+                    .selected
+                    .as_ref()
+                    .is_some_and(|selected| selected.read_only)
+            {
+                folder.messages[index].flags.insert("\\Seen".into());
+            }
+        }
+        drop(state);
+        self.write_untagged_bytes(&response)?;
+        Ok("FETCH completed".into())
+    }
+
+    fn store(&mut self, args: &[Token], uid: bool) -> Outcome {
+        let name = self.selected_name()?;
+        let set = atom_arg(args.first(), "a message set")?;
+        let operation = atom_arg(args.get(1), "a flag operation")?.to_ascii_uppercase();
+        let flags = match args.get(2) {
+            Some(Token::List(list)) => list.iter().filter_map(Token::text).collect::<Vec<_>>(),
+            Some(token) => token.text().into_iter().collect(),
+            None => return Err(bad("STORE needs flags")),
+        };
+        let flags = flags
+            .iter()
+            .map(|flag| imap_flag(flag))
+            .collect::<BTreeSet<_>>();
+        let silent = operation.ends_with(".SILENT");
+
+        let mut state = self.state.lock();
+        let folder = state.folder_mut(&name);
+        let selected = select_messages(&folder.messages, &set, uid)?;
+        let mut response = String::new();
+        for index in selected {
+            let message = &mut folder.messages[index];
+            match operation.trim_end_matches(".SILENT") {
+                "+FLAGS" => message.flags.extend(flags.iter().cloned()),
+                "-FLAGS" => message.flags.retain(|flag| !flags.contains(flag)),
+                "FLAGS" => message.flags = flags.clone(),
+                other => return Err(bad(format!("Unknown STORE operation {other}"))),
+            }
+            if !silent {
+                let uid_item = if uid {
+                    format!("UID {} ", message.uid)
+                } else {
+                    String::new()
+                };
+                response.push_str(&format!(
+                    "* {} FETCH ({uid_item}FLAGS ({}))\r\n",
+                    index + 1,
+                    flag_list(&message.flags)
+                ));
+            }
+        }
+        drop(state);
+        self.write_untagged(&response)?;
+        Ok("STORE completed".into())
+    }
+
+    fn copy(&mut self, args: &[Token], uid: bool) -> Outcome {
+        let name = self.selected_name()?;
+        let set = atom_arg(args.first(), "a message set")?;
+        let target = mailbox_arg(args.get(1))?;
+        let mut state = self.state.lock();
+        if !state.folders.contains_key(&target) {
+            return Err(Failure::No(format!("{target} does not exist")));
+        }
+        let source = state.folder_mut(&name);
+        let copies = select_messages(&source.messages, &set, uid)?
+            .into_iter()
+            .map(|index| source.messages[index].clone())
+            .collect::<Vec<_>>();
+        let destination = state.folder_mut(&target);
+        for message in copies {
+            destination.append(message.flags, message.internal_date, message.raw);
+        }
+        Ok("COPY completed".into())
+    }
+
+    fn search(&mut self, args: &[Token], uid: bool) -> Outcome {
+        let name = self.selected_name()?;
+        let mut args = args;
+        if matches!(args.first(), Some(Token::Atom(atom)) if atom.eq_ignore_ascii_case("CHARSET")) {
+            args = args.get(2..).unwrap_or_default();
+        }
+        let criteria = parse_search(&mut args.iter())?;
+        let mut state = self.state.lock();
+        let folder = state.folder_mut(&name);
+        let max = (
+            folder.messages.len() as u32,
+            folder.messages.last().map_or(0, |m| m.uid),
+        );
+        let found = folder
+            .messages
+            .iter()
+            .enumerate()
+            .filter(|(index, message)| criteria.matches(message, *index as u32 + 1, max))
+            .map(|(index, message)| {
+                if uid {
+                    message.uid.to_string()
+                } else {
+                    (index + 1).to_string()
+                }
+            })
+            .collect::<Vec<_>>();
+        drop(state);
+        let mut line = "* SEARCH".to_string();
+        for value in found {
+            line.push(' ');
+            line.push_str(&value);
+        }
+        line.push_str("\r\n");
+        self.write_untagged(&line)?;
+        Ok("SEARCH completed".into())
+    }
+
+    /// Announces mail delivered to the selected folder as `EXISTS` until
+    /// the client sends `DONE`. A server being stopped says `BYE` instead,
+    /// so a client idling on it notices.
+    fn idle(&mut self) -> Outcome {
+        let name = self.selected_name()?;
+        let count = |state: &State| {
+            state
+                .folders
+                .get(&name)
+                .map_or(0, |folder| folder.messages.len())
+        };
+        let mut announced = self
+            .selected
+            .as_ref()
+            .map_or(0, |selected| selected.exists)
+            .min(count(&self.state.lock()));
+        let failed = |err: io::Error| bad(err.to_string());
+        self.write_untagged("+ idling\r\n")?;
+        self.stream
+            .get_ref()
+            .set_read_timeout(Some(IDLE_POLL))
+            .map_err(failed)?;
+        let done = loop {
+            if self.stop.load(Ordering::SeqCst) {
+                self.write_untagged("* BYE Server stopping\r\n")?;
+                break false;
+            }
+            let exists = count(&self.state.lock());
+            if exists > announced {
+                self.write_untagged(&format!("* {exists} EXISTS\r\n"))?;
+            }
+            announced = exists;
+            match self.read_line() {
+                Ok(Some(line)) => break line.eq_ignore_ascii_case(b"DONE\r\n"),
+                Ok(None) => break false,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(err) => return Err(failed(err)),
+            }
+        };
+        self.stream
+            .get_ref()
+            .set_read_timeout(None)
+            .map_err(failed)?;
+        if let Some(selected) = &mut self.selected {
+            selected.exists = announced;
+        }
+        if done {
+            Ok("IDLE terminated".into())
+        } else {
+            Err(bad("Expected DONE"))
+        }
+    }
+
+    fn selected_name(&self) -> Result<String, Failure> {
+        self.selected
+            .as_ref()
+            .map(|selected| selected.name.clone())
+            .ok_or_else(|| bad("Select a mailbox first"))
+    }
+
+    fn write_untagged(&mut self, text: &str) -> Result<(), Failure> {
+        self.write_untagged_bytes(text.as_bytes())
+    }
+
+    fn write_untagged_bytes(&mut self, bytes: &[u8]) -> Result<(), Failure> {
+        self.write(bytes).map_err(|err| bad(err.to_string()))
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.stream.write_all(bytes)?;
+        self.stream.flush()
+    }
+
+    /// One command with its literals inline, or `None` once the client
+    /// hangs up.
+    fn read_command(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut command = Vec::new();
+        loop {
+            let Some(line) = self.read_line()? else {
+                return Ok(None);
+            };
+            command.extend_from_slice(&line);
+            let Some((length, synchronizing)) = literal_length(&line) else {
+                return Ok(Some(command));
+            };
+            if synchronizing {
+                self.write(b"+ Ready for literal data\r\n")?;
+            }
+            let Some(data) = self.read_exact(length)? else {
+                return Ok(None);
+            };
+            command.extend(data);
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(end) = self.buffer.windows(2).position(|pair| pair == b"\r\n") {
+                return Ok(Some(self.buffer.drain(..end + 2).collect()));
+            }
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn read_exact(&mut self, length: usize) -> io::Result<Option<Vec<u8>>> {
+        while self.buffer.len() < length {
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(self.buffer.drain(..length).collect()))
+    }
+
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0u8; 8192];
+        let read = self.stream.read(&mut chunk)?;
+        self.buffer.extend_from_slice(&chunk[..read]);
+        Ok(read > 0)
+    }
+}
+
+/// The length of the literal a command line ends with, and whether the
+/// client waits for a continuation before sending it.
+fn literal_length(line: &[u8]) -> Option<(usize, bool)> {
+    let line = line.strip_suffix(b"\r\n")?.strip_suffix(b"}")?;
+    let open = line.iter().rposition(|byte| *byte == b'{')?;
+    let inner = &line[open + 1..];
+    let (digits, synchronizing) = match inner.strip_suffix(b"+") {
+        Some(digits) => (digits, false),
+        None => (inner, true),
+    };
+    let length = std::str::from_utf8(digits).ok()?.parse().ok()?;
+    Some((length, synchronizing))
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Self { input, position: 0 }
+    }
+
+    fn tokens(&mut self) -> Result<Vec<Token>, String> {
+        self.list(None)
+    }
+
+    fn list(&mut self, close: Option<u8>) -> Result<Vec<Token>, String> {
+        let mut tokens = Vec::new();
+        loop {
+            while self.peek() == Some(b' ') {
+                self.position += 1;
+            }
+            match self.peek() {
+                None | Some(b'\r') | Some(b'\n') => {
+                    return match close {
+                        Some(_) => Err("Unterminated list".into()),
+                        None => Ok(tokens),
+                    };
+                }
+                Some(byte) if Some(byte) == close => {
+                    self.position += 1;
+                    return Ok(tokens);
+                }
+                Some(b'(') => {
+                    self.position += 1;
+                    tokens.push(Token::List(self.list(Some(b')'))?));
+                }
+                Some(b'"') => tokens.push(self.quoted()?),
+                Some(b'{') => tokens.push(self.literal()?),
+                Some(_) => tokens.push(self.atom()),
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).copied()
+    }
+
+    /// An atom, keeping bracketed sections such as
+    /// `BODY.PEEK[HEADER.FIELDS (FROM)]` whole.
+    fn atom(&mut self) -> Token {
+        let start = self.position;
+        let mut depth = 0usize;
+        while let Some(byte) = self.peek() {
+            match byte {
+                b'[' => depth += 1,
+                b']' => depth = depth.saturating_sub(1),
+                b' ' | b'(' | b')' | b'\r' | b'\n' if depth == 0 => break,
+                _ => {}
+            }
+            self.position += 1;
+        }
+        Token::Atom(String::from_utf8_lossy(&self.input[start..self.position]).into_owned())
+    }
+
+    fn quoted(&mut self) -> Result<Token, String> {
+        self.position += 1;
+        let mut value = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err("Unterminated quoted string".into()),
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(Token::Str(value));
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    value.extend(self.peek());
+                    self.position += 1;
+                }
+                Some(byte) => {
+                    value.push(byte);
+                    self.position += 1;
+                }
+            }
+        }
+    }
+
+    fn literal(&mut self) -> Result<Token, String> {
+        let rest = &self.input[self.position..];
+        let close = rest
+            .iter()
+            .position(|byte| *byte == b'}')
+            .ok_or("Unterminated literal")?;
+        let length: usize = std::str::from_utf8(&rest[1..close])
+            .ok()
+            .map(|digits| digits.trim_end_matches('+'))
+            .and_then(|digits| digits.parse().ok())
+            .ok_or("Bad literal length")?;
+        let start = self.position + close + 1 + 2;
+        let end = start + length;
+        let data = self.input.get(start..end).ok_or("Short literal")?;
+        self.position = end;
+        Ok(Token::Str(data.to_vec()))
+    }
+}
+
+fn mailbox_arg(token: Option<&Token>) -> Result<String, Failure> {
+    token
+        .and_then(Token::text)
+        .map(|name| folder_name(&name))
+        .ok_or_else(|| bad("Missing mailbox name"))
+}
+
+fn atom_arg(token: Option<&Token>, what: &str) -> Result<String, Failure> {
+    token
+        .and_then(Token::text)
+        .ok_or_else(|| bad(format!("Missing {what}")))
+}
+
+/// INBOX is case-insensitive; every other name is kept as given.
+fn folder_name(name: &str) -> String {
+    if name.eq_ignore_ascii_case("INBOX") {
+        "INBOX".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// A flag in IMAP spelling, from either that or the client's stored form.
+fn imap_flag(flag: &str) -> String {
+    let bare = flag.trim().trim_start_matches('\\');
+    SYSTEM_FLAGS
+        .iter()
+        .find(|system| system[1..].eq_ignore_ascii_case(bare))
+        .map_or_else(|| flag.trim().to_string(), |system| system.to_string())
+}
+
+fn flag_list(flags: &BTreeSet<String>) -> String {
+    flags.iter().cloned().collect::<Vec<_>>().join(" ")
+}
+
+/// LIST patterns: `*` matches anything, `%` anything but the delimiter.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|skip| matches(rest, &name[skip..])),
+            Some(('%', rest)) => (0..=name.len())
+                .take_while(|skip| *skip == 0 || name[*skip - 1].to_string() != DELIMITER)
+                .any(|skip| matches(rest, &name[skip..])),
+            Some((ch, rest)) => name.first() == Some(ch) && matches(rest, &name[1..]),
+        }
+    }
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    matches(&pattern, &name)
+}
+
+/// Indexes of the messages a sequence or UID set names, in mailbox order.
+fn select_messages(
+    messages: &[StoredMessage],
+    set: &str,
+    uid: bool,
+) -> Result<Vec<usize>, Failure> {
+    let max = if uid {
+        messages.last().map_or(0, |message| message.uid)
+    } else {
+        messages.len() as u32
+    };
+    let ranges = parse_set(set, max)?;
+    Ok(messages
+        .iter()
+        .enumerate()
+        .filter(|(index, message)| {
+            let value = if uid { message.uid } else { *index as u32 + 1 };
+            ranges
+                .iter()
+                .any(|(low, high)| (*low..=*high).contains(&value))
+        })
+        .map(|(index, _)| index)
+        .collect())
+}
+
+fn parse_set(set: &str, max: u32) -> Result<Vec<(u32, u32)>, Failure> {
+    let number = |text: &str| -> Result<u32, Failure> {
+        if text == "*" {
+            return Ok(max);
+        }
+        text.parse()
+            .map_err(|_| bad(format!("Bad message set {set}")))
+    };
+    set.split(',')
+        .map(|part| match part.split_once(':') {
+            Some((low, high)) => {
+                let (low, high) = (number(low)?, number(high)?);
+                Ok((low.min(high), low.max(high)))
+            }
+            None => number(part).map(|value| (value, value)),
+        })
+        .collect()
+}
+
+fn fetch_item(
+    item: &str,
+    message: &StoredMessage,
+    mark_seen: &mut bool,
+) -> Result<Vec<u8>, String> {
+    let upper = item.to_ascii_uppercase();
+    let (header, text) = split_message(&message.raw);
+    let mut out = Vec::new();
+    match upper.as_str() {
+        "UID" => out.extend(format!("UID {}", message.uid).into_bytes()),
+        "FLAGS" => out.extend(format!("FLAGS ({})", flag_list(&message.flags)).into_bytes()),
+        "INTERNALDATE" => out.extend(
+            format!(
+                "INTERNALDATE \"{}\"",
+                message.internal_date.format("%d-%b-%Y %H:%M:%S %z")
+            )
+            .into_bytes(),
+        ),
+        "RFC822.SIZE" => out.extend(format!("RFC822.SIZE {}", message.raw.len()).into_bytes()),
+        "ENVELOPE" => {
+            out.extend_from_slice(b"ENVELOPE ");
+            out.extend(envelope(&message.raw));
+        }
+        "RFC822" => {
+            *mark_seen = true;
+            out.extend_from_slice(b"RFC822 ");
+            out.extend(literal(&message.raw));
+        }
+        "RFC822.HEADER" => {
+            out.extend_from_slice(b"RFC822.HEADER ");
+            out.extend(literal(header));
+        }
+        "RFC822.TEXT" => {
+            *mark_seen = true;
+            out.extend_from_slice(b"RFC822.TEXT ");
+            out.extend(literal(text));
+        }
+        _ if upper.starts_with("BODY[") || upper.starts_with("BODY.PEEK[") => {
+            let open = item.find('[').unwrap_or_default();
+            let close = item.rfind(']').ok_or("Unterminated section")?;
+            let section = &item[open + 1..close];
+            let mut data = section_bytes(section, &message.raw)?;
+            let mut name = format!("BODY[{section}]");
+            if let Some(partial) = item[close + 1..]
+                .strip_prefix('<')
+                .and_then(|partial| partial.strip_suffix('>'))
+            {
+                let (origin, length) = partial.split_once('.').ok_or("Bad partial range")?;
+                let origin: usize = origin.parse().map_err(|_| "Bad partial origin")?;
+                let length: usize = length.parse().map_err(|_| "Bad partial length")?;
+                let start = origin.min(data.len());
+                let end = start.saturating_add(length).min(data.len());
+                data = data[start..end].to_vec();
+                name.push_str(&format!("<{origin}>"));
+            }
+            if !upper.starts_with("BODY.PEEK") {
+                *mark_seen = true;
+            }
+            out.extend(name.into_bytes());
+            out.push(b' ');
+            out.extend(literal(&data));
+        }
+        _ => return Err(format!("Unsupported FETCH item {item}")),
+    }
+    Ok(out)
+}
+
+/// The header block, blank line included, and the body.
+fn split_message(raw: &[u8]) -> (&[u8], &[u8]) {
+    match raw.windows(4).position(|window| window == b"\r\n\r\n") {
+        Some(end) => raw.split_at(end + 4),
+        None => (raw, &[]),
+    }
+}
+
+fn section_bytes(section: &str, raw: &[u8]) -> Result<Vec<u8>, String> {
+    let (header, text) = split_message(raw);
+    let upper = section.to_ascii_uppercase();
+    match upper.as_str() {
+        "" => Ok(raw.to_vec()),
+        "HEADER" => Ok(header.to_vec()),
+        "TEXT" => Ok(text.to_vec()),
+        _ if upper.starts_with("HEADER.FIELDS") => {
+            let exclude = upper.starts_with("HEADER.FIELDS.NOT");
+            let names = upper
+                .split_once('(')
+                .and_then(|(_, rest)| rest.split_once(')'))
+                .map(|(names, _)| {
+                    names
+                        .split_whitespace()
+                        .map(str::to_string)
+                        .collect::<Vec<_>>()
+                })
+                .ok_or("HEADER.FIELDS needs a field list")?;
+            Ok(header_fields(header, &names, exclude))
+        }
+        _ => Err(format!("Unsupported section {section}")),
+    }
+}
+
+/// The header fields named in `names` (or all others, when `exclude`),
+/// folded lines included, followed by the blank line.
+fn header_fields(header: &[u8], names: &[String], exclude: bool) -> Vec<u8> {
+    let text = String::from_utf8_lossy(header);
+    let mut out = String::new();
+    let mut keep = false;
+    for line in text.split_inclusive("\r\n") {
+        if line == "\r\n" {
+            break;
+        }
+        if !line.starts_with([' ', '\t']) {
+            let name = line
+                .split(':')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_uppercase();
+            keep = names.contains(&name) != exclude;
+        }
+        if keep {
+            out.push_str(line);
+        }
+    }
+    out.push_str("\r\n");
+    out.into_bytes()
+}
+
+/// The RFC 3501 ENVELOPE structure for a message.
+fn envelope(raw: &[u8]) -> Vec<u8> {
+    let headers = mailparse::parse_headers(raw)
+        .map(|(headers, _)| headers)
+        .unwrap_or_default();
+    let value = |name: &str| {
+        headers.get_first_header(name).map(|header| {
+            String::from_utf8_lossy(header.get_value_raw())
+                .replace("\r\n", "")
+                .replace('\n', "")
+                .trim()
+                .to_string()
+        })
+    };
+    let from = address_list(value("From"));
+    let sender = value("Sender").map_or_else(|| from.clone(), |sender| address_list(Some(sender)));
+    let reply_to =
+        value("Reply-To").map_or_else(|| from.clone(), |reply| address_list(Some(reply)));
+
+    let mut out = b"(".to_vec();
+    let fields = [
+        nstring(value("Date").as_deref()),
+        nstring(value("Subject").as_deref()),
+        from,
+        sender,
+        reply_to,
+        address_list(value("To")),
+        address_list(value("Cc")),
+        address_list(value("Bcc")),
+        nstring(value("In-Reply-To").as_deref()),
+        nstring(value("Message-ID").as_deref()),
+    ];
+    out.extend(fields.join(&b' '));
+    out.push(b')');
+    out
+}
+
+fn address_list(value: Option<String>) -> Vec<u8> {
+    let Some(list) = value.and_then(|value| mailparse::addrparse(&value).ok()) else {
+        return b"NIL".to_vec();
+    };
+    if list.is_empty() {
+        return b"NIL".to_vec();
+    }
+    let single = |name: Option<&str>, address: &str| {
+        let (mailbox, host) = address.rsplit_once('@').unwrap_or((address, ""));
+        let mut out = b"(".to_vec();
+        out.extend(nstring(name));
+        out.extend_from_slice(b" NIL ");
+        out.extend(nstring(Some(mailbox)));
+        out.push(b' ');
+        out.extend(nstring(Some(host).filter(|host| !host.is_empty())));
+        out.push(b')');
+        out
+    };
+    let mut out = b"(".to_vec();
+    for address in list.iter() {
+        match address {
+            MailAddr::Single(info) => {
+                out.extend(single(info.display_name.as_deref(), &info.addr));
+            }
+            MailAddr::Group(group) => {
+                out.extend_from_slice(b"(NIL NIL ");
+                out.extend(nstring(Some(&group.group_name)));
+                out.extend_from_slice(b" NIL)");
+                for member in &group.addrs {
+                    out.extend(single(member.display_name.as_deref(), &member.addr));
+                }
+                out.extend_from_slice(b"(NIL NIL NIL NIL)");
+            }
+        }
+    }
+    out.push(b')');
+    out
+}
+
+fn nstring(value: Option<&str>) -> Vec<u8> {
+    match value {
+        Some(value) => string(value.as_bytes()),
+        None => b"NIL".to_vec(),
+    }
+}
+
+/// A quoted string when the bytes allow one, a literal otherwise.
+fn string(value: &[u8]) -> Vec<u8> {
+    let quotable = value
+        .iter()
+        .all(|byte| (0x20..0x7f).contains(byte) && *byte != b'"' && *byte != b'\\');
+    if quotable {
+        let mut out = b"\"".to_vec();
+        out.extend_from_slice(value);
+        out.push(b'"');
+        out
+    } else {
+        literal(value)
+    }
+}
+
+fn literal(data: &[u8]) -> Vec<u8> {
+    let mut out = format!("{{{}}}\r\n", data.len()).into_bytes();
+    out.extend_from_slice(data);
+    out
+}
+
+enum Search {
+    All,
+    Set(String),
+    Uid(String),
+    Since(NaiveDate),
+    Before(NaiveDate),
+    On(NaiveDate),
+    Larger(usize),
+    Smaller(usize),
+    Flag(&'static str, bool),
+    Header(String, String),
+    Body(String),
+    Not(Box<Search>),
+    Or(Box<Search>, Box<Search>),
+    And(Vec<Search>),
+}
+
+fn parse_search<'a>(tokens: &mut impl Iterator<Item = &'a Token>) -> Result<Search, Failure> {
+    let mut keys = Vec::new();
+    while let Some(key) = parse_key(tokens)? {
+        keys.push(key);
+    }
+    if keys.is_empty() {
+        return Err(bad("SEARCH needs criteria"));
+    }
+    Ok(Search::And(keys))
+}
+
+fn parse_key<'a>(tokens: &mut impl Iterator<Item = &'a Token>) -> Result<Option<Search>, Failure> {
+    let Some(token) = tokens.next() else {
+        return Ok(None);
+    };
+    let key = match token {
+        Token::List(list) => return parse_search(&mut list.iter()).map(Some),
+        Token::Str(_) => return Err(bad("Expected a search key")),
+        Token::Atom(atom) => atom.to_ascii_uppercase(),
+    };
+    let mut argument = |what: &str| {
+        tokens
+            .next()
+            .and_then(Token::text)
+            .ok_or_else(|| bad(format!("{key} needs {what}")))
+    };
+    let date = |text: String| {
+        NaiveDate::parse_from_str(&text, "%d-%b-%Y").map_err(|_| bad(format!("Bad date {text}")))
+    };
+    let size = |text: String| text.parse().map_err(|_| bad(format!("Bad size {text}")));
+    let flag = |name: &str| {
+        SYSTEM_FLAGS
+            .iter()
+            .copied()
+            .find(|flag| flag[1..].eq_ignore_ascii_case(name))
+    };
+
+    let search = match key.as_str() {
+        "ALL" => Search::All,
+        "UID" => Search::Uid(argument("a UID set")?),
+        "SINCE" => Search::Since(date(argument("a date")?)?),
+        "BEFORE" => Search::Before(date(argument("a date")?)?),
+        "ON" => Search::On(date(argument("a date")?)?),
+        "LARGER" => Search::Larger(size(argument("a size")?)?),
+        "SMALLER" => Search::Smaller(size(argument("a size")?)?),
+        "FROM" | "TO" | "CC" | "BCC" | "SUBJECT" => {
+            Search::Header(key.clone(), argument("a string")?.to_lowercase())
+        }
+        "BODY" | "TEXT" => Search::Body(argument("a string")?.to_lowercase()),
+        "NOT" => Search::Not(Box::new(
+            parse_key(tokens)?.ok_or_else(|| bad("NOT needs a key"))?,
+        )),
+        "OR" => {
+            let left = parse_key(tokens)?.ok_or_else(|| bad("OR needs two keys"))?;
+            let right = parse_key(tokens)?.ok_or_else(|| bad("OR needs two keys"))?;
+            Search::Or(Box::new(left), Box::new(right))
+        }
+        _ if key.starts_with(|ch: char| ch.is_ascii_digit() || ch == '*') => {
+            Search::Set(key.clone())
+        }
+        _ => match key.strip_prefix("UN").and_then(flag) {
+            Some(system) => Search::Flag(system, false),
+            None => match flag(&key) {
+                Some(system) => Search::Flag(system, true),
+                None => return Err(bad(format!("Unsupported search key {key}"))),
+            },
+        },
+    };
+    Ok(Some(search))
+}
+
+impl Search {
+    /// `max` is the highest sequence number and the highest UID.
+    fn matches(&self, message: &StoredMessage, sequence: u32, max: (u32, u32)) -> bool {
+        let in_set = |set: &str, value: u32, max: u32| {
+            parse_set(set, max)
+                .map(|ranges| {
+                    ranges
+                        .iter()
+                        .any(|(low, high)| (*low..=*high).contains(&value))
+                })
+                .unwrap_or(false)
+        };
+        let day = message.internal_date.date_naive();
+        match self {
+            Search::All => true,
+            Search::Set(set) => in_set(set, sequence, max.0),
+            Search::Uid(set) => in_set(set, message.uid, max.1),
+            Search::Since(date) => day >= *date,
+            Search::Before(date) => day < *date,
+            Search::On(date) => day == *date,
+            Search::Larger(size) => message.raw.len() > *size,
+            Search::Smaller(size) => message.raw.len() < *size,
+            Search::Flag(flag, set) => message.flags.contains(*flag) == *set,
+            Search::Header(name, needle) => mailparse::parse_headers(&message.raw)
+                .map(|(headers, _)| {
+                    headers
+                        .get_all_values(name)
+                        .iter()
+                        .any(|value| value.to_lowercase().contains(needle))
+                })
+                .unwrap_or(false),
+            Search::Body(needle) => String::from_utf8_lossy(split_message(&message.raw).1)
+                .to_lowercase()
+                .contains(needle),
+            Search::Not(inner) => !inner.matches(message, sequence, max),
+            Search::Or(left, right) => {
+                left.matches(message, sequence, max) || right.matches(message, sequence, max)
+            }
+            Search::And(keys) => keys.iter().all(|key| key.matches(message, sequence, max)),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Conversation threads, for muting. A message's thread is named by the
+//! first Message-ID in its `References` header, which replies carry
+//! forward from the message that started the conversation, falling back to
+//! `In-Reply-To` and then the message's own Message-ID. Mail arriving in a
+//! muted thread is marked read and archived.
+
+use serde::Serialize;
+
+/// Lifecycle muted mail is moved to on arrival.
+pub const MUTED_LIFECYCLE: &str = "archived";
+
+/// A thread the user muted in one account.
+#[derive(Debug, Clone, Serialize)]
+pub struct MutedThread {
+    pub thread_id: String,
+    /// Subject of the thread's newest cached message.
+    pub subject: Option<String>,
+    /// Cached messages in the thread.
+    pub message_count: usize,
+    pub muted_at: i64,
+}
+
+/// The thread a message belongs to, from its raw headers and envelope.
+/// `None` when the message names no Message-ID at all.
+pub fn thread_id(
+    raw_headers: Option<&str>,
+    in_reply_to: Option<&str>,
+    message_id: Option<&str>,
+) -> Option<String> {
+    let references = raw_headers.and_then(|headers| {
+        let unfolded = headers.replace("\r\n ", " ").replace("\r\n\t", " ");
+        unfolded.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("references")
+                .then(|| first_message_id(value))
+                .flatten()
+        })
+    });
+    references
+        .or_else(|| in_reply_to.and_then(first_message_id))
+        .or_else(|| message_id.and_then(first_message_id))
+}
+
+/// The Message-IDs in a message's `References` header, oldest first and
+/// without brackets, for a reply to carry forward.
+pub fn references(raw_headers: &str) -> Vec<String> {
+    let unfolded = raw_headers.replace("\r\n ", " ").replace("\r\n\t", " ");
+    unfolded
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("references")
+                .then(|| message_ids(value))
+        })
+        .unwrap_or_default()
+}
+
+/// Every `<id>` in a header value, without brackets, case kept.
+pub fn message_ids(value: &str) -> Vec<String> {
+    value
+        .split('<')
+        .skip(1)
+        .filter_map(|part| part.split_once('>'))
+        .map(|(id, _)| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
+
+/// A thread id as the frontend passes it back: trimmed, lowercase, and
+/// without angle brackets.
+pub fn normalize_thread_id(value: &str) -> Option<String> {
+    first_message_id(value).or_else(|| {
+        let value = value.trim().to_lowercase();
+        (!value.is_empty() && !value.contains(char::is_whitespace)).then_some(value)
+    })
+}
+
+/// The first `<id>` in a header value, lowercase and without brackets.
+fn first_message_id(value: &str) -> Option<String> {
+    let start = value.find('<')? + 1;
+    let end = start + value[start..].find('>')?;
+    let id = value[start..end].trim().to_lowercase();
+    (!id.is_empty()).then_some(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_threads_by_their_first_message() {
+        let headers = "References: <Root@example.com>\r\n <reply-1@example.com>\r\nX: y\r\n";
+        assert_eq!(
+            thread_id(Some(headers), Some("<reply-1@example.com>"), Some("<r2@x>")),
+            Some("root@example.com".into())
+        );
+        assert_eq!(
+            thread_id(Some("X: y\r\n"), Some("<Parent@x>"), Some("<self@x>")),
+            Some("parent@x".into())
+        );
+        assert_eq!(
+            thread_id(None, None, Some("<self@x>")),
+            Some("self@x".into())
+        );
+        assert_eq!(thread_id(None, None, Some("no brackets")), None);
+        assert_eq!(
+            references(headers),
+            ["Root@example.com", "reply-1@example.com"]
+        );
+        assert_eq!(
+            normalize_thread_id(" Root@Example.com "),
+            Some("root@example.com".into())
+        );
+    }
+}
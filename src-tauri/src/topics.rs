@@ -0,0 +1,270 @@
+//! Topic clustering for the topic-based inbox view. Messages are embedded as
+//! hashed TF-IDF vectors (no model required) and grouped with k-means; each
+//! cluster is labelled with its most characteristic terms.
+//!
+//! Similarity is lexical, not semantic: the local model in [`crate::llm`]
+//! offers no embeddings, and clustering has to work before any model is
+//! downloaded. Messages about the same thing in different words can
+//! therefore land in different topics. Model embeddings would replace the
+//! vectors built by `embed_all`; labels come from terms either way.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+const EMBEDDING_DIMS: usize = 512;
+const KMEANS_ITERATIONS: usize = 12;
+const LABEL_TERMS: usize = 3;
+
+const STOPWORDS: &[&str] = &[
+    "the", "and", "for", "you", "your", "are", "with", "this", "that", "from", "have", "has",
+    "was", "were", "will", "not", "but", "our", "can", "all", "any", "out", "about", "more",
+    "there", "here", "what", "when", "which", "who", "how", "its", "into", "than", "then", "them",
+    "they", "their", "been", "also", "just", "only", "over", "some", "such", "per", "via",
+    "please", "thanks", "thank", "hello", "dear", "regards", "email", "mail", "com", "www", "http",
+    "https", "view", "click",
+];
+
+#[derive(Debug, Clone)]
+pub struct TopicDocument {
+    pub message_id: i64,
+    pub sender_email: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TopicCluster {
+    pub label: String,
+    pub keywords: Vec<String>,
+    /// (message id, cosine similarity to the cluster centroid)
+    pub members: Vec<(i64, f32)>,
+}
+
+/// Members and summed term weights of one cluster while it is labelled.
+type ClusterTerms = (Vec<(i64, f32)>, HashMap<String, f32>);
+
+struct Embedded {
+    message_id: i64,
+    terms: Vec<(String, f32)>,
+    vector: Vec<(usize, f32)>,
+}
+
+/// Groups documents into at most `max_topics` clusters. Documents with no
+/// informative terms are left unassigned.
+pub fn cluster(documents: &[TopicDocument], max_topics: usize) -> Vec<TopicCluster> {
+    let embedded = embed_all(documents);
+    if embedded.is_empty() || max_topics == 0 {
+        return Vec::new();
+    }
+
+    let k = ((embedded.len() as f64 / 2.0).sqrt().round() as usize).clamp(1, max_topics);
+    let mut centroids = initial_centroids(&embedded, k);
+    let mut assignments = vec![0usize; embedded.len()];
+
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut changed = false;
+        for (index, document) in embedded.iter().enumerate() {
+            let best = nearest(&centroids, &document.vector).0;
+            if assignments[index] != best {
+                assignments[index] = best;
+                changed = true;
+            }
+        }
+
+        let mut sums = vec![vec![0f32; EMBEDDING_DIMS]; centroids.len()];
+        for (document, cluster) in embedded.iter().zip(&assignments) {
+            for (dim, weight) in &document.vector {
+                sums[*cluster][*dim] += weight;
+            }
+        }
+        for (centroid, sum) in centroids.iter_mut().zip(sums) {
+            if sum.iter().any(|value| *value != 0.0) {
+                *centroid = normalize_dense(sum);
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    let mut clusters: Vec<ClusterTerms> = vec![(Vec::new(), HashMap::new()); centroids.len()];
+    for (document, cluster) in embedded.iter().zip(&assignments) {
+        let similarity = dot(&centroids[*cluster], &document.vector);
+        let (members, terms) = &mut clusters[*cluster];
+        members.push((document.message_id, similarity));
+        for (term, weight) in &document.terms {
+            *terms.entry(term.clone()).or_insert(0.0) += weight;
+        }
+    }
+
+    let mut topics = clusters
+        .into_iter()
+        .filter(|(members, _)| !members.is_empty())
+        .map(|(mut members, terms)| {
+            let mut ranked = terms.into_iter().collect::<Vec<_>>();
+            ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            let keywords = ranked
+                .into_iter()
+                .take(LABEL_TERMS * 3)
+                .map(|(term, _)| term)
+                .collect::<Vec<_>>();
+            let label = keywords
+                .iter()
+                .take(LABEL_TERMS)
+                .map(|term| term.trim_start_matches("from:").to_string())
+                .collect::<Vec<_>>()
+                .join(" / ");
+            members.sort_by(|a, b| b.1.total_cmp(&a.1));
+            TopicCluster {
+                label,
+                keywords,
+                members,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    topics.sort_by_key(|topic| Reverse(topic.members.len()));
+    topics
+}
+
+fn embed_all(documents: &[TopicDocument]) -> Vec<Embedded> {
+    let term_sets = documents.iter().map(terms_for).collect::<Vec<_>>();
+
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for terms in &term_sets {
+        for term in terms.keys() {
+            *document_frequency.entry(term.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    // Terms seen once carry no grouping signal; terms in most mail are noise.
+    let total = documents.len() as f32;
+    let max_frequency = ((total * 0.5).ceil() as usize).max(2);
+
+    let mut embedded = Vec::new();
+    for (document, terms) in documents.iter().zip(&term_sets) {
+        let mut weighted = Vec::new();
+        let mut dense: HashMap<usize, f32> = HashMap::new();
+        for (term, count) in terms {
+            let frequency = document_frequency[term.as_str()];
+            if frequency < 2 || frequency > max_frequency {
+                continue;
+            }
+            let weight = (1.0 + (*count as f32).ln()) * (total / frequency as f32).ln();
+            if weight <= 0.0 {
+                continue;
+            }
+            *dense.entry(hash_dimension(term)).or_insert(0.0) += weight;
+            weighted.push((term.clone(), weight));
+        }
+
+        if weighted.is_empty() {
+            continue;
+        }
+
+        let norm = dense
+            .values()
+            .map(|value| value * value)
+            .sum::<f32>()
+            .sqrt();
+        let mut vector = dense
+            .into_iter()
+            .map(|(dim, value)| (dim, value / norm))
+            .collect::<Vec<_>>();
+        vector.sort_by_key(|(dim, _)| *dim);
+
+        embedded.push(Embedded {
+            message_id: document.message_id,
+            terms: weighted,
+            vector,
+        });
+    }
+
+    embedded
+}
+
+fn terms_for(document: &TopicDocument) -> HashMap<String, usize> {
+    let mut terms = HashMap::new();
+
+    if let Some((_, domain)) = document.sender_email.trim().to_lowercase().rsplit_once('@') {
+        if !domain.is_empty() {
+            terms.insert(format!("from:{domain}"), 2);
+        }
+    }
+
+    for word in document
+        .text
+        .to_lowercase()
+        .split(|ch: char| !ch.is_alphanumeric())
+    {
+        let length = word.chars().count();
+        if !(3..=24).contains(&length)
+            || STOPWORDS.contains(&word)
+            || word.chars().any(|ch| ch.is_ascii_digit())
+        {
+            continue;
+        }
+        *terms.entry(word.to_string()).or_insert(0) += 1;
+    }
+
+    terms
+}
+
+/// Deterministic farthest-point seeding so repeated runs give stable topics.
+fn initial_centroids(embedded: &[Embedded], k: usize) -> Vec<Vec<f32>> {
+    let mut centroids = vec![to_dense(&embedded[0].vector)];
+    while centroids.len() < k {
+        let candidate = embedded
+            .iter()
+            .map(|document| (document, nearest(&centroids, &document.vector).1))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        match candidate {
+            Some((document, similarity)) if similarity < 0.999 => {
+                centroids.push(to_dense(&document.vector));
+            }
+            _ => break,
+        }
+    }
+    centroids
+}
+
+fn nearest(centroids: &[Vec<f32>], vector: &[(usize, f32)]) -> (usize, f32) {
+    centroids
+        .iter()
+        .enumerate()
+        .map(|(index, centroid)| (index, dot(centroid, vector)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, 0.0))
+}
+
+fn dot(dense: &[f32], sparse: &[(usize, f32)]) -> f32 {
+    sparse.iter().map(|(dim, value)| dense[*dim] * value).sum()
+}
+
+fn to_dense(sparse: &[(usize, f32)]) -> Vec<f32> {
+    let mut dense = vec![0f32; EMBEDDING_DIMS];
+    for (dim, value) in sparse {
+        dense[*dim] = *value;
+    }
+    dense
+}
+
+fn normalize_dense(mut values: Vec<f32>) -> Vec<f32> {
+    let norm = values.iter().map(|value| value * value).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for value in &mut values {
+            *value /= norm;
+        }
+    }
+    values
+}
+
+fn hash_dimension(term: &str) -> usize {
+    // FNV-1a keeps bucket assignment stable across runs and platforms.
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in term.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash % EMBEDDING_DIMS as u64) as usize
+}
@@ -0,0 +1,220 @@
+//! Tracking pixels and click-tracking redirects in HTML mail. Detection is
+//! local: known tracker domains plus the usual shape of an open pixel (a
+//! 1x1 or hidden image). Tracked links are only rewritten when the real
+//! destination is embedded in the redirect URL; opaque redirects are left
+//! alone, because resolving them would register the click.
+
+use crate::body_text;
+use crate::links;
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+/// Hosts that serve open-tracking pixels.
+const PIXEL_DOMAINS: &[&str] = &[
+    "google-analytics.com",
+    "doubleclick.net",
+    "mixpanel.com",
+    "list-manage.com",
+    "mcsv.net",
+    "mailchimp.com",
+    "sendgrid.net",
+    "mandrillapp.com",
+    "mailgun.org",
+    "exacttarget.com",
+    "hubspot.com",
+    "hs-analytics.net",
+    "rs6.net",
+    "mailtrack.io",
+    "yesware.com",
+    "bananatag.com",
+    "getnotify.com",
+    "pixel.facebook.com",
+    "sparkpostmail.com",
+    "klaviyomail.com",
+];
+
+/// Hosts that sit between a link and its destination to count clicks.
+const CLICK_DOMAINS: &[&str] = &[
+    "list-manage.com",
+    "sendgrid.net",
+    "mandrillapp.com",
+    "mailgun.org",
+    "hubspotlinks.com",
+    "hs-sites.com",
+    "rs6.net",
+    "exacttarget.com",
+    "sparkpostmail.com",
+    "klaviyomail.com",
+    "mailtrack.io",
+    "safelinks.protection.outlook.com",
+    "urldefense.com",
+    "awstrack.me",
+    "mjt.lu",
+];
+
+/// Host labels that mark a sender's own tracking subdomain.
+const CLICK_HOST_PREFIXES: &[&str] = &["click.", "clicks.", "links.", "track.", "trk.", "email."];
+
+/// Query parameters redirectors commonly carry the destination in.
+const DESTINATION_PARAMS: &[&str] = &[
+    "url",
+    "u",
+    "redirect",
+    "redirect_url",
+    "redirect_uri",
+    "target",
+    "dest",
+    "destination",
+    "link",
+    "r",
+];
+
+static IMG_TAG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<img\b[^>]*>").expect("img tag pattern is valid"));
+static ANCHOR_HREF: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)(<a\b[^>]*?\bhref\s*=\s*)(["'])([^"']+)(["'])"#)
+        .expect("anchor href pattern is valid")
+});
+static HIDDEN_STYLE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)display\s*:\s*none|visibility\s*:\s*hidden|(width|height)\s*:\s*[01]px")
+        .expect("hidden style pattern is valid")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TrackerKind {
+    Pixel,
+    Click,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tracker {
+    pub kind: TrackerKind,
+    pub domain: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CleanedHtml {
+    pub html: String,
+    pub trackers_removed: usize,
+    pub links_rewritten: usize,
+}
+
+/// Trackers in the HTML parts of a cached body, one entry per kind and
+/// domain.
+pub fn detect(raw: &[u8]) -> Vec<Tracker> {
+    let parts = body_text::decode_body_parts(raw);
+    let mut trackers = Vec::new();
+    for html in &parts.html {
+        for tag in IMG_TAG.find_iter(html) {
+            if let Some(domain) = pixel_domain(tag.as_str()) {
+                push_unique(&mut trackers, TrackerKind::Pixel, domain);
+            }
+        }
+        for anchor in ANCHOR_HREF.captures_iter(html) {
+            if let Some(domain) = click_domain(&anchor[3].replace("&amp;", "&")) {
+                push_unique(&mut trackers, TrackerKind::Click, domain);
+            }
+        }
+    }
+    trackers
+}
+
+/// Removes tracking pixels and points tracked links at their embedded
+/// destinations.
+pub fn strip(html: &str) -> CleanedHtml {
+    let mut trackers_removed = 0;
+    let without_pixels = IMG_TAG.replace_all(html, |tag: &Captures| {
+        if pixel_domain(&tag[0]).is_some() {
+            trackers_removed += 1;
+            String::new()
+        } else {
+            tag[0].to_string()
+        }
+    });
+
+    let mut links_rewritten = 0;
+    let rewritten = ANCHOR_HREF.replace_all(&without_pixels, |anchor: &Captures| {
+        let href = anchor[3].replace("&amp;", "&");
+        match click_domain(&href).and(embedded_destination(&href)) {
+            Some(destination) => {
+                links_rewritten += 1;
+                let quote = &anchor[2];
+                let escaped = destination.replace('&', "&amp;").replace(quote, "");
+                format!("{}{quote}{escaped}{}", &anchor[1], &anchor[4])
+            }
+            None => anchor[0].to_string(),
+        }
+    });
+
+    CleanedHtml {
+        html: rewritten.into_owned(),
+        trackers_removed,
+        links_rewritten,
+    }
+}
+
+fn push_unique(trackers: &mut Vec<Tracker>, kind: TrackerKind, domain: String) {
+    if !trackers
+        .iter()
+        .any(|tracker| tracker.kind == kind && tracker.domain == domain)
+    {
+        trackers.push(Tracker { kind, domain });
+    }
+}
+
+/// Domain of an `<img>` tag that looks like an open pixel.
+fn pixel_domain(tag: &str) -> Option<String> {
+    let src = attribute(tag, "src")?;
+    let domain = links::url_domain(&src)?;
+    let tiny = ["width", "height"].iter().any(|name| {
+        attribute(tag, name)
+            .and_then(|value| value.trim_end_matches("px").trim().parse::<u32>().ok())
+            .is_some_and(|value| value <= 1)
+    });
+    let hidden = attribute(tag, "style").is_some_and(|style| HIDDEN_STYLE.is_match(&style));
+    (tiny || hidden || matches_domain(&domain, PIXEL_DOMAINS)).then_some(domain)
+}
+
+/// Domain of a link that goes through a click tracker.
+fn click_domain(href: &str) -> Option<String> {
+    let domain = links::url_domain(href)?;
+    // A sender's own `click.` subdomain only counts when it visibly
+    // redirects elsewhere; plenty of those hosts serve real pages.
+    let own_redirect = CLICK_HOST_PREFIXES
+        .iter()
+        .any(|prefix| domain.starts_with(prefix))
+        && embedded_destination(href).is_some();
+    let tracked = matches_domain(&domain, CLICK_DOMAINS) || own_redirect;
+    tracked.then_some(domain)
+}
+
+/// The destination a redirect URL carries in its query, if any.
+pub fn embedded_destination(href: &str) -> Option<String> {
+    let url = Url::parse(href).ok()?;
+    url.query_pairs()
+        .find(|(name, value)| {
+            DESTINATION_PARAMS.contains(&name.to_lowercase().as_str())
+                && links::url_domain(value).is_some()
+        })
+        .map(|(_, value)| value.into_owned())
+}
+
+fn matches_domain(host: &str, domains: &[&str]) -> bool {
+    domains
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{domain}")))
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!(r#"(?i)\b{name}\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#);
+    let captures = Regex::new(&pattern).ok()?.captures(tag)?;
+    captures
+        .iter()
+        .skip(1)
+        .flatten()
+        .next()
+        .map(|value| value.as_str().to_string())
+}
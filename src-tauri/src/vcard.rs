@@ -0,0 +1,314 @@
+//! vCard reading and writing for the contacts table. Names, addresses,
+//! phone numbers, organization, and note are understood; every other
+//! property (photos, postal addresses, birthdays) is carried along as it
+//! was read, so a card survives a round trip through this client intact.
+//! Cards are written as vCard 3.0, which every address book accepts.
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::calendar::{escape, fold, split_property, unescape, unfold};
+
+const PRODUCT_ID: &str = "-//PersonalMailClient//Contacts//EN";
+/// Properties written from the modelled fields, or by the writer itself.
+const MODELLED: &[&str] = &[
+    "BEGIN", "END", "VERSION", "PRODID", "UID", "FN", "N", "EMAIL", "TEL", "ORG", "NOTE", "REV",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Card {
+    pub uid: String,
+    pub full_name: String,
+    /// Lowercase.
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
+    pub organization: Option<String>,
+    pub note: Option<String>,
+    /// When the card last changed (`REV`), Unix seconds.
+    pub revision: Option<i64>,
+    /// Unfolded lines of the properties not modelled above.
+    #[serde(skip)]
+    pub other: Vec<String>,
+}
+
+impl Card {
+    /// A new card for someone known only from their mail.
+    pub fn from_sender(email: &str, display_name: Option<&str>) -> Self {
+        let email = email.trim().to_lowercase();
+        let full_name = display_name
+            .map(str::trim)
+            .filter(|name| !name.is_empty() && !name.contains('@'))
+            .map_or_else(|| email.clone(), str::to_string);
+        Card {
+            uid: Uuid::new_v4().to_string(),
+            full_name,
+            emails: vec![email],
+            revision: Some(Utc::now().timestamp()),
+            ..Card::default()
+        }
+    }
+
+    pub fn primary_email(&self) -> Option<&str> {
+        self.emails.first().map(String::as_str)
+    }
+
+    /// The card as a vCard 3.0 document.
+    pub fn to_vcard(&self) -> String {
+        let mut lines = vec![
+            "BEGIN:VCARD".to_string(),
+            "VERSION:3.0".to_string(),
+            format!("PRODID:{PRODUCT_ID}"),
+            format!("UID:{}", escape(&self.uid)),
+            format!("FN:{}", escape(&self.full_name)),
+            format!("N:{}", structured_name(&self.full_name)),
+        ];
+        for email in &self.emails {
+            lines.push(format!("EMAIL;TYPE=INTERNET:{}", escape(email)));
+        }
+        for phone in &self.phones {
+            lines.push(format!("TEL:{}", escape(phone)));
+        }
+        if let Some(organization) = &self.organization {
+            lines.push(format!("ORG:{}", escape(organization)));
+        }
+        if let Some(note) = &self.note {
+            lines.push(format!("NOTE:{}", escape(note)));
+        }
+        if let Some(revision) = self
+            .revision
+            .and_then(|rev| Utc.timestamp_opt(rev, 0).single())
+        {
+            lines.push(format!("REV:{}", revision.format("%Y%m%dT%H%M%SZ")));
+        }
+        lines.extend(self.other.iter().cloned());
+        lines.push("END:VCARD".to_string());
+
+        lines
+            .iter()
+            .map(|line| fold(line))
+            .collect::<Vec<_>>()
+            .join("\r\n")
+            + "\r\n"
+    }
+}
+
+/// The fields a user edits by hand.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CardEdit {
+    pub full_name: String,
+    pub emails: Vec<String>,
+    pub phones: Vec<String>,
+    pub organization: Option<String>,
+    pub note: Option<String>,
+}
+
+impl CardEdit {
+    /// Trims every field and lowercases addresses; rejects an edit that
+    /// leaves neither a name nor an address.
+    pub fn normalized(self) -> Result<Self, String> {
+        let mut emails = Vec::new();
+        for email in self.emails {
+            let email = email.trim().to_lowercase();
+            if !email.is_empty() && !emails.contains(&email) {
+                emails.push(email);
+            }
+        }
+        let full_name = match self.full_name.trim() {
+            "" => emails
+                .first()
+                .cloned()
+                .ok_or_else(|| "A contact needs a name or an email address".to_string())?,
+            name => name.to_string(),
+        };
+        Ok(Self {
+            full_name,
+            emails,
+            phones: self
+                .phones
+                .into_iter()
+                .map(|phone| phone.trim().to_string())
+                .filter(|phone| !phone.is_empty())
+                .collect(),
+            organization: trimmed(self.organization),
+            note: trimmed(self.note),
+        })
+    }
+
+    /// Writes the edit over `card`, keeping its `UID` and the properties
+    /// not edited here.
+    pub fn apply(self, card: &mut Card) {
+        card.full_name = self.full_name;
+        card.emails = self.emails;
+        card.phones = self.phones;
+        card.organization = self.organization;
+        card.note = self.note;
+    }
+}
+
+/// Every card in a vCard document, which may hold many. Cards without a
+/// `UID` get one.
+pub fn parse_cards(text: &str) -> Vec<Card> {
+    let mut cards = Vec::new();
+    let mut current: Option<Card> = None;
+    for line in unfold(text) {
+        let Some((name, _, value)) = split_property(&line) else {
+            continue;
+        };
+        // Apple and Google prefix grouped properties, as in `item1.EMAIL`.
+        let name = name.rsplit('.').next().unwrap_or_default();
+        match (name, current.as_mut()) {
+            ("BEGIN", _) if value.eq_ignore_ascii_case("VCARD") => current = Some(Card::default()),
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VCARD") => {
+                let Some(mut card) = current.take() else {
+                    continue;
+                };
+                if card.uid.is_empty() {
+                    card.uid = Uuid::new_v4().to_string();
+                }
+                if card.full_name.is_empty() {
+                    card.full_name = card.primary_email().unwrap_or_default().to_string();
+                }
+                cards.push(card);
+            }
+            (_, None) => {}
+            ("UID", Some(card)) => card.uid = unescape(&value),
+            ("FN", Some(card)) => card.full_name = unescape(&value),
+            ("N", Some(card)) if card.full_name.is_empty() => {
+                card.full_name = name_from_structured(&value);
+            }
+            ("EMAIL", Some(card)) => {
+                let email = unescape(&value).to_lowercase();
+                if !email.is_empty() && !card.emails.contains(&email) {
+                    card.emails.push(email);
+                }
+            }
+            ("TEL", Some(card)) => {
+                let phone = unescape(&value);
+                let phone = phone.trim_start_matches("tel:").to_string();
+                if !phone.is_empty() {
+                    card.phones.push(phone);
+                }
+            }
+            ("ORG", Some(card)) => {
+                card.organization = split_components(&value)
+                    .into_iter()
+                    .find(|part| !part.is_empty());
+            }
+            ("NOTE", Some(card)) => card.note = Some(unescape(&value)).filter(|n| !n.is_empty()),
+            ("REV", Some(card)) => card.revision = parse_revision(&value),
+            ("VERSION" | "PRODID" | "N", Some(_)) => {}
+            (_, Some(card)) if !MODELLED.contains(&name) => card.other.push(line.clone()),
+            _ => {}
+        }
+    }
+    cards
+}
+
+fn trimmed(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// `N` from a display name: the last word is taken as the family name.
+fn structured_name(full_name: &str) -> String {
+    let mut words = full_name.split_whitespace().collect::<Vec<_>>();
+    let family = if words.len() > 1 { words.pop() } else { None };
+    format!(
+        "{};{};;;",
+        escape(family.unwrap_or_default()),
+        escape(&words.join(" "))
+    )
+}
+
+fn name_from_structured(value: &str) -> String {
+    let parts = split_components(value);
+    let family = parts.first().map(String::as_str).unwrap_or_default();
+    let given = parts.get(1).map(String::as_str).unwrap_or_default();
+    format!("{given} {family}").trim().to_string()
+}
+
+/// Splits a structured value on unescaped semicolons and unescapes each part.
+fn split_components(value: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut escaped = false;
+    for ch in value.chars() {
+        match ch {
+            _ if escaped => {
+                current.push('\\');
+                current.push(ch);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            ';' => parts.push(unescape(&std::mem::take(&mut current))),
+            _ => current.push(ch),
+        }
+    }
+    parts.push(unescape(&current));
+    parts
+}
+
+/// `REV` in basic or extended ISO 8601, as a date-time or a date.
+fn parse_revision(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(value) {
+        return Some(parsed.timestamp());
+    }
+    let compact = value.replace(['-', ':'], "");
+    let compact = compact.trim_end_matches('Z');
+    if let Ok(parsed) = NaiveDateTime::parse_from_str(compact, "%Y%m%dT%H%M%S") {
+        return Some(Utc.from_utc_datetime(&parsed).timestamp());
+    }
+    NaiveDate::parse_from_str(compact, "%Y%m%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| Utc.from_utc_datetime(&date).timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_cards_and_keeps_unknown_properties() {
+        let text = "BEGIN:VCARD\r\nVERSION:4.0\r\nUID:urn:uuid:1\r\nFN:Ada Lovelace\r\n\
+                    item1.EMAIL;TYPE=work:Ada@Example.com\r\nTEL;TYPE=cell:+44 20 7946\r\n \
+                    0000\r\nORG:Analytical Engines\\, Ltd;Research\r\n\
+                    BDAY:18151210\r\nREV:2026-10-01T08:00:00Z\r\nEND:VCARD\r\n\
+                    BEGIN:VCARD\r\nVERSION:3.0\r\nN:Babbage;Charles;;;\r\nEND:VCARD\r\n";
+        let cards = parse_cards(text);
+        assert_eq!(cards.len(), 2);
+
+        let ada = &cards[0];
+        assert_eq!(ada.uid, "urn:uuid:1");
+        assert_eq!(ada.emails, vec!["ada@example.com"]);
+        assert_eq!(ada.phones, vec!["+44 20 79460000"]);
+        assert_eq!(ada.organization.as_deref(), Some("Analytical Engines, Ltd"));
+        assert_eq!(ada.other, vec!["BDAY:18151210"]);
+        let rev = Utc.with_ymd_and_hms(2026, 10, 1, 8, 0, 0).unwrap();
+        assert_eq!(ada.revision, Some(rev.timestamp()));
+
+        assert_eq!(cards[1].full_name, "Charles Babbage");
+        assert!(!cards[1].uid.is_empty());
+    }
+
+    #[test]
+    fn written_cards_read_back_the_same() {
+        let card = Card {
+            uid: "abc".into(),
+            full_name: "Grace Brewster Hopper".into(),
+            emails: vec!["grace@example.com".into(), "gbh@example.org".into()],
+            phones: vec!["+1 555 0100".into()],
+            organization: Some("Navy; Reserve".into()),
+            note: Some("Met at the conference,\nsecond day".into()),
+            revision: Some(1_790_000_000),
+            other: vec!["BDAY:19061209".into()],
+        };
+        let text = card.to_vcard();
+        assert!(text.contains("N:Hopper;Grace Brewster;;;"));
+        assert_eq!(parse_cards(&text), vec![card]);
+    }
+}
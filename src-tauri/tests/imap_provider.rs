@@ -0,0 +1,218 @@
+//! Provider operations end to end against the in-process IMAP server.
+//! Run with `cargo test --features test-support`.
+
+use personal_mail_client::providers::idle::{self, IdleExit, NewMail};
+use personal_mail_client::providers::{self, BatchResult};
+use personal_mail_client::test_support::{fixtures, FixtureMessage, TestServer};
+use std::time::Duration;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+async fn collect_batches(
+    server: &TestServer,
+    since_uid: Option<u32>,
+    chunk_size: usize,
+    lite: bool,
+) -> Vec<BatchResult> {
+    let (mut receiver, handle) =
+        providers::fetch_all(&server.credentials(), since_uid, chunk_size, None, lite)
+            .await
+            .unwrap();
+    let mut batches = Vec::new();
+    while let Some(batch) = receiver.recv().await {
+        batches.push(batch);
+    }
+    handle.await.unwrap().unwrap();
+    batches
+}
+
+fn uids(batches: &[BatchResult]) -> Vec<u32> {
+    batches
+        .iter()
+        .flat_map(|batch| &batch.messages)
+        .map(|envelope| envelope.summary.uid)
+        .collect()
+}
+
+#[tokio::test]
+async fn fetch_recent_returns_the_newest_messages() {
+    let server = TestServer::start();
+    server.deliver_all("INBOX", &fixtures::mailbox(30));
+
+    let recent = providers::fetch_recent(&server.credentials(), 10)
+        .await
+        .unwrap();
+    let mut uids = recent
+        .iter()
+        .map(|summary| summary.uid)
+        .collect::<Vec<_>>();
+    uids.sort_unstable();
+    assert_eq!(uids, (21..=30).collect::<Vec<_>>());
+
+    let summary = |uid: u32| recent.iter().find(|summary| summary.uid == uid).unwrap();
+    assert_eq!(summary(30).subject, "Invoice attached");
+    assert_eq!(summary(30).sender.email, "noreply@ci.example.com");
+    assert_eq!(summary(30).to, vec![fixtures::TEST_RECIPIENT.to_string()]);
+    assert!(!summary(30).auto_submitted);
+    // The digest's list headers mark it as bulk mail.
+    assert_eq!(summary(28).sender.email, "digest@news.example.com");
+    assert!(summary(28).auto_submitted);
+}
+
+#[tokio::test]
+async fn fetch_all_streams_batches_of_the_chunk_size() {
+    let server = TestServer::start();
+    server.deliver_all("INBOX", &fixtures::mailbox(120));
+
+    let batches = collect_batches(&server, None, 50, false).await;
+    let shape = batches
+        .iter()
+        .map(|batch| (batch.index, batch.total, batch.requested, batch.fetched))
+        .collect::<Vec<_>>();
+    assert_eq!(shape, vec![(1, 3, 50, 50), (2, 3, 50, 50), (3, 3, 20, 20)]);
+    assert_eq!(uids(&batches), (1..=120).collect::<Vec<_>>());
+    assert!(batches.iter().all(|batch| batch.uid_validity == Some(1)));
+    let envelope_fetches = server
+        .commands()
+        .iter()
+        .filter(|command| command.starts_with("UID FETCH") && command.contains("ENVELOPE"))
+        .count();
+    assert_eq!(envelope_fetches, 3);
+
+    // Fixture 0 is seen and flagged, fixture 1 neither.
+    assert_eq!(
+        batches[0].messages[0].flags,
+        vec!["flagged".to_string(), "seen".to_string()]
+    );
+    assert!(batches[0].messages[1].flags.is_empty());
+
+    // An incremental sync only asks for what arrived after the last UID.
+    let newer = collect_batches(&server, Some(100), 50, true).await;
+    assert_eq!(uids(&newer), (101..=120).collect::<Vec<_>>());
+    assert_eq!(newer.len(), 1);
+}
+
+#[tokio::test]
+async fn delete_messages_moves_the_batch_to_trash_at_once() {
+    let server = TestServer::start();
+    server.deliver_all("INBOX", &fixtures::mailbox(10));
+    let credentials = server.credentials();
+
+    providers::delete_messages(&credentials, "INBOX", &[2, 3, 4, 8], Some(1))
+        .await
+        .unwrap();
+    assert_eq!(server.uids("INBOX"), vec![1, 5, 6, 7, 9, 10]);
+    assert_eq!(server.uids("Trash"), vec![1, 2, 3, 4]);
+    let commands = server.commands();
+    let copies = commands
+        .iter()
+        .filter(|command| command.starts_with("UID COPY"))
+        .collect::<Vec<_>>();
+    assert_eq!(copies, vec!["UID COPY 2,3,4,8 \"Trash\""]);
+    assert_eq!(
+        commands
+            .iter()
+            .filter(|command| command.as_str() == "EXPUNGE")
+            .count(),
+        1
+    );
+
+    // After the server rebuilds INBOX the old UIDs may name other mail, so
+    // nothing is deleted.
+    server.set_uid_validity("INBOX", 7);
+    assert!(
+        providers::delete_messages(&credentials, "INBOX", &[1], Some(1))
+            .await
+            .is_err()
+    );
+    assert_eq!(server.uids("INBOX"), vec![1, 5, 6, 7, 9, 10]);
+}
+
+#[tokio::test]
+async fn flag_changes_reach_the_server_and_come_back() {
+    let server = TestServer::start();
+    let uid = server.deliver(
+        "INBOX",
+        &FixtureMessage::new("Ann Lee <ann@example.org>", "Flags"),
+    );
+    let credentials = server.credentials();
+
+    let updated = providers::store_flags(
+        &credentials,
+        "INBOX",
+        &[uid],
+        None,
+        &["seen".to_string(), "$Important".to_string()],
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        updated,
+        vec![(uid, vec!["$Important".to_string(), "seen".to_string()])]
+    );
+    assert_eq!(
+        server.flags("INBOX", uid),
+        Some(vec!["$Important".to_string(), "\\Seen".to_string()])
+    );
+
+    providers::store_flags(
+        &credentials,
+        "INBOX",
+        &[uid],
+        None,
+        &["seen".to_string()],
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        server.flags("INBOX", uid),
+        Some(vec!["$Important".to_string()])
+    );
+
+    // Another client's change shows up on the next sync.
+    server.set_flags("INBOX", uid, &["flagged"]);
+    let envelopes = providers::fetch_envelopes(&credentials, &[uid])
+        .await
+        .unwrap();
+    assert_eq!(envelopes[0].flags, vec!["flagged".to_string()]);
+}
+
+#[tokio::test]
+async fn idle_reports_mail_as_it_arrives() {
+    let server = TestServer::start();
+    server.deliver_all("INBOX", &fixtures::mailbox(2));
+    let cancel = CancellationToken::new();
+    let (mut arrivals, handle) = idle::listen(&server.credentials(), "INBOX", cancel.clone());
+
+    while !server.commands().iter().any(|command| command == "IDLE") {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    server.deliver(
+        "INBOX",
+        &FixtureMessage::new("Ann Lee <ann@example.org>", "Pushed"),
+    );
+    let arrival = timeout(Duration::from_secs(10), arrivals.recv())
+        .await
+        .expect("new mail reported")
+        .unwrap();
+    assert_eq!(
+        arrival,
+        NewMail {
+            exists: 3,
+            uid_next: Some(4)
+        }
+    );
+
+    // Stopping the server ends the IDLE it is in, so the listener sees the
+    // cancellation without waiting out the round.
+    cancel.cancel();
+    drop(server);
+    let exit = timeout(Duration::from_secs(10), handle)
+        .await
+        .expect("listener stopped")
+        .unwrap()
+        .unwrap();
+    assert_eq!(exit, IdleExit::Cancelled);
+}
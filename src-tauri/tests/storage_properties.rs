@@ -0,0 +1,105 @@
+//! Properties of the local cache through its public API: repeating a sync
+//! batch changes nothing, and reopening a database, which runs the
+//! migrations again, keeps every message readable.
+
+use personal_mail_client::storage::{MessageInsert, Storage};
+use proptest::collection::{btree_map, vec};
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+use tokio::runtime::Runtime;
+
+const ACCOUNT: &str = "me@example.test";
+
+/// Subject and body by UID.
+type Mailbox = BTreeMap<u32, (String, Vec<u8>)>;
+
+fn mailbox() -> impl Strategy<Value = Mailbox> {
+    btree_map(
+        1u32..100_000,
+        ("\\PC{0,80}", vec(any::<u8>(), 0..512)),
+        1..12,
+    )
+}
+
+fn inserts(mailbox: &Mailbox) -> Vec<MessageInsert> {
+    mailbox
+        .iter()
+        .map(|(uid, (subject, body))| MessageInsert {
+            account_email: ACCOUNT.to_string(),
+            folder: "INBOX".to_string(),
+            uid: *uid,
+            sender_display: "Ann Lee".to_string(),
+            sender_email: "ann@example.org".to_string(),
+            subject: subject.clone(),
+            date: Some("2024-03-01T00:00:00+00:00".to_string()),
+            snippet: Some(subject.chars().take(40).collect()),
+            body: Some(body.clone()),
+            flags: Some(String::new()),
+            size: Some(body.len() as u32),
+            dmarc_aligned: None,
+            thread_id: None,
+        })
+        .collect()
+}
+
+/// What the cache holds for the account, read back through the API.
+async fn contents(storage: &Storage) -> Mailbox {
+    let summaries = storage
+        .recent_message_summaries(ACCOUNT, 1_000)
+        .await
+        .unwrap();
+    let mut contents = Mailbox::new();
+    for summary in summaries {
+        let body = storage
+            .message_body(ACCOUNT, "INBOX", summary.uid)
+            .await
+            .unwrap()
+            .map(|body| body.to_vec())
+            .unwrap_or_default();
+        contents.insert(summary.uid, (summary.subject, body));
+    }
+    contents
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn repeating_a_batch_changes_nothing(mailbox in mailbox()) {
+        let dir = tempfile::tempdir().unwrap();
+        Runtime::new().unwrap().block_on(async {
+            let storage = Storage::open(dir.path()).unwrap();
+            storage.upsert_batch(inserts(&mailbox), Vec::new()).await.unwrap();
+            let first = contents(&storage).await;
+            prop_assert_eq!(&first, &mailbox);
+
+            storage.upsert_batch(inserts(&mailbox), Vec::new()).await.unwrap();
+            prop_assert_eq!(
+                storage.message_count_for_account(ACCOUNT).await.unwrap(),
+                mailbox.len()
+            );
+            prop_assert_eq!(contents(&storage).await, first);
+            Ok(())
+        })?;
+    }
+
+    #[test]
+    fn reopening_keeps_every_message(mailbox in mailbox()) {
+        let dir = tempfile::tempdir().unwrap();
+        Runtime::new().unwrap().block_on(async {
+            {
+                let storage = Storage::open(dir.path()).unwrap();
+                storage.upsert_batch(inserts(&mailbox), Vec::new()).await.unwrap();
+            }
+            for _ in 0..2 {
+                let storage = Storage::open(dir.path()).unwrap();
+                prop_assert_eq!(
+                    storage.message_count_for_account(ACCOUNT).await.unwrap(),
+                    mailbox.len()
+                );
+                prop_assert_eq!(&contents(&storage).await, &mailbox);
+            }
+            Ok(())
+        })?;
+    }
+}
@@ -6,7 +6,8 @@ const providerLabels: Record<Provider, string> = {
   gmail: "Gmail",
   outlook: "Outlook / Live",
   yahoo: "Yahoo Mail",
-  custom: "Custom IMAP"
+  custom: "Custom IMAP",
+  demo: "Demo mailbox"
 };
 
 interface AccountListProps {
@@ -56,6 +56,16 @@ const providerPalettes: Record<ProviderKey | "default", ProviderPalette> = {
     avatarBg: "rgba(45, 212, 191, 0.45)",
     avatarColor: "#ecfeff"
   },
+  demo: {
+    label: "Demo mailbox",
+    icon: "🧪",
+    gradient: "linear-gradient(135deg, #b45309 0%, #f59e0b 100%)",
+    chipBg: "rgba(251, 191, 36, 0.28)",
+    chipBorder: "rgba(251, 191, 36, 0.5)",
+    chipColor: "#fef3c7",
+    avatarBg: "rgba(251, 191, 36, 0.45)",
+    avatarColor: "#fffbeb"
+  },
   default: {
     label: "Mailbox",
     icon: "📫",
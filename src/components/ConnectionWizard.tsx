@@ -84,6 +84,14 @@ const providerGuidance: Record<Provider, {
       'Testing the connection will confirm the IMAP handshake succeeds before saving.'
     ],
     footnote: 'If your server requires STARTTLS on port 143, update the advanced settings on the next step.'
+  },
+  demo: {
+    title: 'Try the app with generated mail',
+    points: [
+      'The demo mailbox is generated on this computer; nothing connects to a mail server.',
+      'Choose how many messages, senders, and how much spam it holds.',
+      'Changes you make last until the app quits.'
+    ]
   }
 };
 
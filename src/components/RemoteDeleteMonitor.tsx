@@ -37,17 +37,17 @@ export default function RemoteDeleteMonitor({
 
   const normalizedEmail = accountEmail.trim().toLowerCase();
   const snapshot = metrics?.latest ?? null;
-  const overrideMode: RemoteDeleteOverrideMode = snapshot?.override_mode ?? "auto";
+  const overrideMode: RemoteDeleteOverrideMode = snapshot?.overrideMode ?? "auto";
   const nextMode: RemoteDeleteOverrideMode =
     overrideMode === "force-batch" ? "auto" : "force-batch";
 
   const queuePending = progress?.pending ?? snapshot?.pending ?? 0;
-  const queueBacklog = snapshot?.total_pending ?? queuePending;
+  const queueBacklog = snapshot?.totalPending ?? queuePending;
   const queueCompleted = progress?.completed ?? snapshot?.processed ?? 0;
   const queueFailed = progress?.failed ?? snapshot?.failed ?? 0;
   const lastUpdated = snapshot ? new Date(snapshot.timestamp * 1000) : null;
-  const ratePerMinute = snapshot?.rate_per_minute ?? 0;
-  const batchSize = snapshot?.batch_size ?? 0;
+  const ratePerMinute = snapshot?.ratePerMinute ?? 0;
+  const batchSize = snapshot?.batchSize ?? 0;
   const modeLabel = snapshot?.mode ?? "idle";
 
   useEffect(() => {
@@ -16,9 +16,9 @@ export function StatusBanner({
 }: StatusBannerProps) {
   const statusMessage = useMemo(() => {
     if (isSyncing && syncProgress) {
-      const { batch, total_batches, fetched, stored } = syncProgress;
-      if (total_batches > 0) {
-        const progressPercent = Math.round((batch / total_batches) * 100);
+      const { batch, totalBatches, fetched, stored } = syncProgress;
+      if (totalBatches > 0) {
+        const progressPercent = Math.round((batch / totalBatches) * 100);
         return `Syncing... ${progressPercent}% complete (${fetched} fetched, ${stored} stored)`;
       }
       return `Syncing... ${fetched} messages fetched, ${stored} stored`;
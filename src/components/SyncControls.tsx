@@ -20,12 +20,12 @@ export function SyncControls({
   const progressText = useMemo(() => {
     if (!syncProgress) return null;
 
-    const { batch, total_batches, fetched, stored, elapsed_ms } = syncProgress;
-    const elapsedSeconds = (elapsed_ms / 1000).toFixed(1);
+    const { batch, totalBatches, fetched, stored, elapsedMs } = syncProgress;
+    const elapsedSeconds = (elapsedMs / 1000).toFixed(1);
 
-    if (total_batches > 0) {
-      const progressPercent = total_batches > 0 ? Math.round((batch / total_batches) * 100) : 0;
-      return `Batch ${batch}/${total_batches} (${progressPercent}%) - ${fetched} fetched, ${stored} stored - ${elapsedSeconds}s`;
+    if (totalBatches > 0) {
+      const progressPercent = totalBatches > 0 ? Math.round((batch / totalBatches) * 100) : 0;
+      return `Batch ${batch}/${totalBatches} (${progressPercent}%) - ${fetched} fetched, ${stored} stored - ${elapsedSeconds}s`;
     }
 
     return `${fetched} messages fetched, ${stored} stored - ${elapsedSeconds}s`;
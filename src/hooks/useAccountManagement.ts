@@ -8,7 +8,8 @@ const providerLabels: Record<Provider, string> = {
   gmail: "Gmail",
   outlook: "Outlook / Live",
   yahoo: "Yahoo Mail",
-  custom: "Custom IMAP"
+  custom: "Custom IMAP",
+  demo: "Demo mailbox"
 };
 
 const errorMessage = (err: unknown) => (err instanceof Error ? err.message : String(err));
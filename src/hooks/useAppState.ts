@@ -10,6 +10,12 @@ import type {
   RemoteDeleteStatusPayload,
   RemoteDeleteUpdate,
   RemoteDeleteQueuedPayload,
+  MessagesRemovedPayload,
+  FlagConflictsPayload,
+  SenderMutesExpiredPayload,
+  FocusAlertPayload,
+  FocusReviewPayload,
+  LocalUpdatePayload,
   RemoteDeleteMetricsResponse,
   RemoteDeleteOverrideMode
 } from "../types";
@@ -28,7 +34,8 @@ const providerLabels: Record<Provider, string> = {
   gmail: "Gmail",
   outlook: "Outlook / Live",
   yahoo: "Yahoo Mail",
-  custom: "Custom IMAP"
+  custom: "Custom IMAP",
+  demo: "Demo mailbox"
 };
 
 type RemoteDeleteCounters = {
@@ -128,7 +135,7 @@ export function useAppState() {
           if (existingSet.has(update.uid)) {
             existingSet.delete(update.uid);
             completedInc += 1;
-            if (update.remote_error && update.remote_error.length > 0) {
+            if (update.remoteError && update.remoteError.length > 0) {
               failedInc += 1;
             }
           }
@@ -275,11 +282,11 @@ export function useAppState() {
 
     listen<RemoteDeleteQueuedPayload>("remote-delete-queued", (event) => {
       if (!event.payload) return;
-      const { account_email, uids } = event.payload;
+      const { accountEmail, uids } = event.payload;
       if (!uids || uids.length === 0) {
         return;
       }
-      registerRemoteDeletes(account_email, uids);
+      registerRemoteDeletes(accountEmail, uids);
     })
       .then((unlisten) => {
         if (!mounted) {
@@ -305,16 +312,16 @@ export function useAppState() {
     listen<RemoteDeleteStatusPayload>("remote-delete-status", (event) => {
       if (!event.payload) return;
       const payload = event.payload;
-      emailState.updateDeletedEmailStatus(payload.account_email, payload.updates);
-      applyRemoteDeleteUpdates(payload.account_email, payload.updates);
+      emailState.updateDeletedEmailStatus(payload.accountEmail, payload.updates);
+      applyRemoteDeleteUpdates(payload.accountEmail, payload.updates);
 
-      const existing = deletedEmailsRef.current[payload.account_email] ?? [];
+      const existing = deletedEmailsRef.current[payload.accountEmail] ?? [];
       for (const update of payload.updates) {
         const target = existing.find((item) => item.uid === update.uid);
         const subject = target?.subject?.trim() ? target.subject : "(No subject)";
 
-        if (typeof update.remote_error === "string" && update.remote_error) {
-          notifyError(`Remote delete failed for "${subject}": ${update.remote_error}`);
+        if (typeof update.remoteError === "string" && update.remoteError) {
+          notifyError(`Remote delete failed for "${subject}": ${update.remoteError}`);
         }
       }
     })
@@ -335,6 +342,187 @@ export function useAppState() {
     };
   }, [emailState.updateDeletedEmailStatus, applyRemoteDeleteUpdates, notifyError]);
 
+  useEffect(() => {
+    let mounted = true;
+    let cleanup: (() => void) | undefined;
+
+    listen<MessagesRemovedPayload>("messages-removed", (event) => {
+      if (!event.payload) return;
+      const { accountEmail, uids, messageKeys } = event.payload;
+      if (!messageKeys || messageKeys.length === 0) {
+        return;
+      }
+      emailState.removeMessages(accountEmail, uids, messageKeys);
+    })
+      .then((unlisten) => {
+        if (!mounted) {
+          unlisten();
+        } else {
+          cleanup = unlisten;
+        }
+      })
+      .catch((err) => {
+        console.error("Failed to register removed messages listener", err);
+      });
+
+    return () => {
+      mounted = false;
+      if (cleanup) cleanup();
+    };
+  }, [emailState.removeMessages]);
+
+  useEffect(() => {
+    let mounted = true;
+    let cleanup: (() => void) | undefined;
+
+    listen<FlagConflictsPayload>("flag-conflicts", (event) => {
+      if (!event.payload) return;
+      const { accountEmail, conflicts } = event.payload;
+      const overridden = conflicts.filter((conflict) => conflict.winner === "server").length;
+      if (overridden > 0) {
+        notifyInfo(
+          `${overridden} flag change${overridden === 1 ? "" : "s"} for ${accountEmail} ` +
+            "lost to edits made on another device"
+        );
+      }
+    })
+      .then((unlisten) => {
+        if (!mounted) {
+          unlisten();
+        } else {
+          cleanup = unlisten;
+        }
+      })
+      .catch((err) => {
+        console.error("Failed to register flag conflict listener", err);
+      });
+
+    return () => {
+      mounted = false;
+      if (cleanup) cleanup();
+    };
+  }, [notifyInfo]);
+
+  useEffect(() => {
+    let mounted = true;
+    let cleanup: (() => void) | undefined;
+
+    listen<SenderMutesExpiredPayload>("sender-mutes-expired", (event) => {
+      if (!event.payload) return;
+      const senders = Array.from(new Set(event.payload.rules.map((rule) => rule.sender_email)));
+      if (senders.length === 0) return;
+      notifyInfo(
+        senders.length === 1
+          ? `Mute for ${senders[0]} has ended`
+          : `Mutes for ${senders.length} senders have ended`
+      );
+    })
+      .then((unlisten) => {
+        if (!mounted) {
+          unlisten();
+        } else {
+          cleanup = unlisten;
+        }
+      })
+      .catch((err) => {
+        console.error("Failed to register sender mute listener", err);
+      });
+
+    return () => {
+      mounted = false;
+      if (cleanup) cleanup();
+    };
+  }, [notifyInfo]);
+
+  useEffect(() => {
+    let mounted = true;
+    let cleanup: (() => void) | undefined;
+
+    listen<FocusAlertPayload>("focus-alert", (event) => {
+      if (!event.payload) return;
+      for (const message of event.payload.messages) {
+        const sender = message.sender_display || message.sender_email;
+        notifyInfo(`${sender}: ${message.subject || "(no subject)"}`);
+      }
+    })
+      .then((unlisten) => {
+        if (!mounted) {
+          unlisten();
+        } else {
+          cleanup = unlisten;
+        }
+      })
+      .catch((err) => {
+        console.error("Failed to register focus alert listener", err);
+      });
+
+    return () => {
+      mounted = false;
+      if (cleanup) cleanup();
+    };
+  }, [notifyInfo]);
+
+  useEffect(() => {
+    let mounted = true;
+    let cleanup: (() => void) | undefined;
+
+    listen<FocusReviewPayload>("focus-review", (event) => {
+      if (!event.payload) return;
+      const count = event.payload.messages.length;
+      if (count === 0) return;
+      const senders = new Set(event.payload.messages.map((message) => message.sender_email)).size;
+      notifyInfo(
+        `Focus review: ${count} held message${count === 1 ? "" : "s"} from ` +
+          `${senders} sender${senders === 1 ? "" : "s"}`
+      );
+    })
+      .then((unlisten) => {
+        if (!mounted) {
+          unlisten();
+        } else {
+          cleanup = unlisten;
+        }
+      })
+      .catch((err) => {
+        console.error("Failed to register focus review listener", err);
+      });
+
+    return () => {
+      mounted = false;
+      if (cleanup) cleanup();
+    };
+  }, [notifyInfo]);
+
+  useEffect(() => {
+    let mounted = true;
+    let cleanup: (() => void) | undefined;
+
+    listen<LocalUpdatePayload>("local-update-rolled-back", (event) => {
+      if (!event.payload) return;
+      const count = event.payload.uids.length;
+      if (count === 0) return;
+      notifyError(
+        `Undid ${event.payload.action} on ${count} message${count === 1 ? "" : "s"}: ` +
+          `${event.payload.error ?? "the server refused the change"}`
+      );
+    })
+      .then((unlisten) => {
+        if (!mounted) {
+          unlisten();
+        } else {
+          cleanup = unlisten;
+        }
+      })
+      .catch((err) => {
+        console.error("Failed to register local update rollback listener", err);
+      });
+
+    return () => {
+      mounted = false;
+      if (cleanup) cleanup();
+    };
+  }, [notifyError]);
+
   useEffect(() => {
     let mounted = true;
     let cleanup: (() => void) | undefined;
@@ -342,7 +530,7 @@ export function useAppState() {
     listen<RemoteDeleteMetricsResponse>("remote-delete-metrics", (event) => {
       if (!event.payload) return;
       const payload = event.payload;
-      const normalized = payload.account_email.trim().toLowerCase();
+      const normalized = payload.accountEmail.trim().toLowerCase();
       remoteDeleteMetricsFetchedAtRef.current[normalized] = Date.now();
       setRemoteDeleteMetricsByAccount((prev) => ({
         ...prev,
@@ -1,6 +1,12 @@
 import { useCallback, useRef, useState } from "react";
 import { invoke } from "@tauri-apps/api/tauri";
-import type { DeletedEmail, EmailSummary, SenderGroup, SenderStatus } from "../types";
+import type {
+  DeletedEmail,
+  EmailSummary,
+  RemoteDeleteUpdate,
+  SenderGroup,
+  SenderStatus
+} from "../types";
 
 const MIN_CACHE_FETCH = 1_000;
 const MAX_CACHE_FETCH = 50_000;
@@ -224,6 +230,41 @@ export function useEmailState() {
     });
   }, []);
 
+  // Cached rows are matched by message key, since a UID only names a message
+  // within its folder; the recent list holds INBOX summaries, by UID.
+  const removeMessages = useCallback((accountEmail: string, uids: string[], messageKeys: string[]) => {
+    const removed = new Set(uids);
+    const removedKeys = new Set(messageKeys);
+    setSenderGroupsByAccount((prev) => {
+      const current = prev[accountEmail] ?? [];
+      const updated = current
+        .map((group) => {
+          const filtered = group.messages.filter(
+            (message) => !(message.message_key && removedKeys.has(message.message_key))
+          );
+          if (filtered.length === group.messages.length) return group;
+          return {
+            ...group,
+            messages: filtered,
+            message_count: filtered.length
+          };
+        })
+        .filter((group) => group.message_count > 0);
+      return {
+        ...prev,
+        [accountEmail]: updated
+      };
+    });
+
+    setEmailsByAccount((prev) => {
+      const current = prev[accountEmail] ?? [];
+      return {
+        ...prev,
+        [accountEmail]: current.filter((message) => !removed.has(message.uid))
+      };
+    });
+  }, []);
+
   const addDeletedEmail = useCallback((accountEmail: string, email: DeletedEmail) => {
     setDeletedEmailsByAccount((prev) => {
       const existing = prev[accountEmail] ?? [];
@@ -236,7 +277,7 @@ export function useEmailState() {
   }, []);
 
   const updateDeletedEmailStatus = useCallback(
-    (accountEmail: string, updates: Array<{ uid: string; remote_deleted_at?: number | null; remote_error?: string | null }>) => {
+    (accountEmail: string, updates: RemoteDeleteUpdate[]) => {
       if (!updates.length) return;
       setDeletedEmailsByAccount((prev) => {
         const existing = prev[accountEmail];
@@ -245,8 +286,8 @@ export function useEmailState() {
         const updateMap = new Map<string, { remote_deleted_at?: number | null; remote_error?: string | null }>();
         for (const update of updates) {
           updateMap.set(update.uid, {
-            remote_deleted_at: update.remote_deleted_at,
-            remote_error: update.remote_error
+            remote_deleted_at: update.remoteDeletedAt,
+            remote_error: update.remoteError
           });
         }
 
@@ -286,6 +327,7 @@ export function useEmailState() {
     clearAccountData,
     updateSenderStatus,
     deleteMessageFromGroups,
+    removeMessages,
     addDeletedEmail,
     updateDeletedEmailStatus,
     setEmailsByAccount,
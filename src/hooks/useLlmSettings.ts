@@ -60,10 +60,10 @@ export function useLlmSettings() {
   // Listen for download progress events
   useEffect(() => {
     const unlisten = listen("model-download-progress", (event) => {
-      const payload = event.payload as { model_id: string; downloaded: number; total: number; progress: number };
+      const payload = event.payload as { modelId: string; downloaded: number; total: number; progress: number };
       setDownloadProgress(prev => ({
         ...prev,
-        [payload.model_id]: {
+        [payload.modelId]: {
           downloaded: payload.downloaded,
           total: payload.total,
           progress: payload.progress
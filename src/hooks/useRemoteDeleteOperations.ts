@@ -74,7 +74,7 @@ export function useRemoteDeleteOperations() {
           if (existingSet.has(update.uid)) {
             existingSet.delete(update.uid);
             completedInc += 1;
-            if (update.remote_error && update.remote_error.length > 0) {
+            if (update.remoteError && update.remoteError.length > 0) {
               failedInc += 1;
             }
           }
@@ -124,11 +124,11 @@ export function useRemoteDeleteOperations() {
 
     listen<RemoteDeleteQueuedPayload>("remote-delete-queued", (event) => {
       if (!event.payload) return;
-      const { account_email, uids } = event.payload;
+      const { accountEmail, uids } = event.payload;
       if (!uids || uids.length === 0) {
         return;
       }
-      registerRemoteDeletes(account_email, uids);
+      registerRemoteDeletes(accountEmail, uids);
     })
       .then((unlisten) => {
         if (!mounted) {
@@ -155,7 +155,7 @@ export function useRemoteDeleteOperations() {
       if (!event.payload) return;
       const payload = event.payload;
       // Note: updateDeletedEmailStatus and notifications are handled in the parent hook
-      applyRemoteDeleteUpdates(payload.account_email, payload.updates);
+      applyRemoteDeleteUpdates(payload.accountEmail, payload.updates);
     })
       .then((unlisten) => {
         if (!mounted) {
@@ -181,7 +181,7 @@ export function useRemoteDeleteOperations() {
     listen<RemoteDeleteMetricsResponse>("remote-delete-metrics", (event) => {
       if (!event.payload) return;
       const payload = event.payload;
-      const normalized = payload.account_email.trim().toLowerCase();
+      const normalized = payload.accountEmail.trim().toLowerCase();
       remoteDeleteMetricsFetchedAtRef.current[normalized] = Date.now();
       setRemoteDeleteMetricsByAccount((prev) => ({
         ...prev,
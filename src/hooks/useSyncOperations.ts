@@ -145,10 +145,10 @@ export function useSyncOperations({
           [payload.email]: payload
         }));
 
-        if (payload.total_batches > 0) {
+        if (payload.totalBatches > 0) {
           const progressLimit = Math.max(
             maxCachedItemsByAccount.current[payload.email] ?? 0,
-            payload.total_batches > 0 ? payload.total_batches * 50 : payload.fetched,
+            payload.totalBatches > 0 ? payload.totalBatches * 50 : payload.fetched,
             MIN_CACHE_FETCH
           );
           loadCachedEmails(payload.email, progressLimit).catch((err) => {
@@ -256,10 +256,10 @@ export function useSyncOperations({
         [account.email]: {
           email: account.email,
           batch: 0,
-          total_batches: 0,
+          totalBatches: 0,
           fetched: 0,
           stored: 0,
-          elapsed_ms: 0
+          elapsedMs: 0
         }
       }));
 
@@ -362,10 +362,10 @@ export function useSyncOperations({
         [account.email]: {
           email: account.email,
           batch: 0,
-          total_batches: 0,
+          totalBatches: 0,
           fetched: 0,
           stored: 0,
-          elapsed_ms: 0
+          elapsedMs: 0
         }
       }));
 
@@ -435,7 +435,8 @@ export function useSyncOperations({
       try {
         await invoke("set_sender_status", {
           senderEmail,
-          status
+          status,
+          account: accountEmail
         });
         updateSenderStatus(accountEmail, senderEmail, status);
         notifySuccess(`Marked ${senderEmail} as ${status}.`);
@@ -2,6 +2,7 @@ import { invoke } from "@tauri-apps/api/tauri";
 import type {
   Account,
   ConnectAccountResponse,
+  DemoConfig,
   Provider,
   SavedAccount
 } from "../types";
@@ -14,6 +15,13 @@ export interface ConnectAccountRequest {
   customPort?: number;
 }
 
+export interface ConnectAccountOAuthRequest {
+  provider: Provider;
+  email: string;
+  /** The OAuth client registered with the provider for this app. */
+  clientId: string;
+}
+
 export interface TestAccountConnectionRequest {
   provider: Provider;
   email: string;
@@ -40,6 +48,23 @@ export async function connectAccount(request: ConnectAccountRequest): Promise<Co
   });
 }
 
+/** Connects the demo account; without a config the last one used is kept. */
+export async function connectDemoAccount(config?: DemoConfig): Promise<ConnectAccountResponse> {
+  return invoke<ConnectAccountResponse>("connect_demo_account", { config });
+}
+
+/**
+ * Connects a Gmail, Outlook, or Yahoo account through the provider's consent
+ * page in the browser instead of an app password.
+ */
+export async function connectAccountOAuth(request: ConnectAccountOAuthRequest): Promise<ConnectAccountResponse> {
+  return invoke<ConnectAccountResponse>("connect_account_oauth", {
+    provider: request.provider,
+    email: request.email,
+    clientId: request.clientId
+  });
+}
+
 export async function testAccountConnection(request: TestAccountConnectionRequest): Promise<void> {
   await invoke("test_account_connection", {
     provider: request.provider,
@@ -0,0 +1,11 @@
+import { invoke } from "@tauri-apps/api/tauri";
+import type { AnalysisCoverage } from "../types";
+
+export async function analysisCoverage(email: string): Promise<AnalysisCoverage> {
+  return invoke<AnalysisCoverage>("analysis_coverage", { email });
+}
+
+/** Starts a bulk run over only the messages without a current analysis. */
+export async function analyzeMissing(email: string): Promise<string> {
+  return invoke<string>("analyze_missing", { email });
+}
@@ -92,6 +92,7 @@ interface BulkAnalysisContextValue {
 }
 
 interface BulkProgressPayload {
+  schemaVersion?: number;
   runId: string;
   status: "starting" | "processed" | "error" | "completed";
   total?: number;
@@ -109,6 +110,9 @@ interface BulkProgressPayload {
   accountEmail?: string;
   messageUid?: string;
   force?: boolean;
+  target?: string;
+  fastPath?: boolean;
+  classifier?: "rules" | "llm";
   result?: {
     summary?: string | null;
     sentiment?: string | null;
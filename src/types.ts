@@ -1,4 +1,12 @@
-export type Provider = "gmail" | "outlook" | "yahoo" | "custom";
+export type Provider = "gmail" | "outlook" | "yahoo" | "custom" | "demo";
+
+/** What the demo account's generated mailbox is built from. */
+export interface DemoConfig {
+  size: number;
+  senders: number;
+  spamRatio: number;
+  seed: number;
+}
 
 export interface Account {
   provider: Provider;
@@ -6,6 +14,8 @@ export interface Account {
   display_name?: string | null;
   custom_host?: string | null;
   custom_port?: number | null;
+  /** Set when the account signs in with OAuth instead of a password. */
+  oauth_client_id?: string | null;
 }
 
 export interface MailAddress {
@@ -18,10 +28,28 @@ export interface EmailSummary {
   subject: string;
   sender: MailAddress;
   date?: string | null;
+  size?: number | null;
+  dmarc_aligned?: boolean | null;
+  thread_id?: string | null;
+  message_id?: string | null;
+  references?: string[];
+}
+
+export interface MutedThread {
+  thread_id: string;
+  subject?: string | null;
+  message_count: number;
+  muted_at: number;
+}
+
+export interface SizeSearchResult {
+  source: "cache" | "server";
+  messages: EmailSummary[];
 }
 
 export interface DeletedEmail {
   uid: string;
+  message_key?: string;
   subject: string;
   sender_email: string;
   sender_display?: string | null;
@@ -35,20 +63,441 @@ export interface DeletedEmail {
   remote_error?: string | null;
 }
 
+/** Fields every event from `events.rs` carries. */
+export interface VersionedEvent {
+  schemaVersion: number;
+}
+
 export interface RemoteDeleteUpdate {
   uid: string;
-  remote_deleted_at?: number | null;
-  remote_error?: string | null;
+  messageKey: string;
+  remoteDeletedAt?: number | null;
+  remoteError?: string | null;
 }
 
-export interface RemoteDeleteStatusPayload {
-  account_email: string;
+export interface RemoteDeleteStatusPayload extends VersionedEvent {
+  accountEmail: string;
   updates: RemoteDeleteUpdate[];
 }
 
-export interface RemoteDeleteQueuedPayload {
+export interface RemoteDeleteQueuedPayload extends VersionedEvent {
+  accountEmail: string;
+  uids: string[];
+  messageKeys: string[];
+}
+
+export interface MessagesRemovedPayload extends VersionedEvent {
+  accountEmail: string;
+  uids: string[];
+  messageKeys: string[];
+}
+
+export type FlagConflictPolicy = "server-wins" | "local-wins" | "newest-wins";
+
+export interface FlagConflict {
+  id: number;
+  account_email: string;
+  uid: string;
+  flag: string;
+  local_enabled: boolean;
+  server_enabled: boolean;
+  policy: FlagConflictPolicy;
+  winner: "local" | "server";
+  local_changed_at: number;
+  detected_at: number;
+}
+
+export interface FlagConflictsPayload extends VersionedEvent {
+  accountEmail: string;
+  conflicts: FlagConflict[];
+}
+
+export interface FlagConflictPolicies {
+  default: FlagConflictPolicy;
+  flags: Record<string, FlagConflictPolicy>;
+}
+
+export interface Bounce {
+  id: number;
   account_email: string;
+  uid: string;
+  recipient: string;
+  status_code?: string | null;
+  diagnostic?: string | null;
+  original_subject?: string | null;
+  outbox_id?: number | null;
+  detected_at: number;
+}
+
+export interface RecipientInsight {
+  recipient: string;
+  sent: number;
+  replied: number;
+  response_rate: number;
+  median_reply_secs?: number | null;
+  last_sent_at: number;
+}
+
+export interface HourInsight {
+  hour: number;
+  sent: number;
+  replied: number;
+  response_rate: number;
+  median_reply_secs?: number | null;
+}
+
+export interface SendInsights {
+  account_email: string;
+  sent: number;
+  replied: number;
+  response_rate: number;
+  median_reply_secs?: number | null;
+  recipients: RecipientInsight[];
+  by_hour: HourInsight[];
+  best_hours: number[];
+  computed_at: number;
+}
+
+export interface HeatmapRange {
+  since?: number | null;
+  until?: number | null;
+}
+
+export interface HeatmapGrid {
+  total: number;
+  /** Seven rows, Monday first, of 24 local hours. */
+  counts: number[][];
+}
+
+export interface TagHeatmap extends HeatmapGrid {
+  tag: string;
+}
+
+export interface InboxHeatmap {
+  account_email: string;
+  since?: number | null;
+  until?: number | null;
+  overall: HeatmapGrid;
+  tags: TagHeatmap[];
+}
+
+export interface FocusWindow {
+  /** 0 is Monday. */
+  days: number[];
+  startMinute: number;
+  endMinute: number;
+}
+
+export interface FocusSettings {
+  enabled: boolean;
+  windows: FocusWindow[];
+  vipSenders: string[];
+  priorities: string[];
+  directToMe: boolean;
+  /** Minutes after local midnight. */
+  reviewTimes: number[];
+}
+
+export type FocusReason = "vip" | "priority" | "direct_to_me";
+
+export interface FocusMessage {
+  account_email: string;
+  uid: string;
+  sender_email: string;
+  sender_display?: string | null;
+  subject: string;
+  date?: string | null;
+  reason?: FocusReason | null;
+  decided_at: number;
+}
+
+export interface FocusListing {
+  active: boolean;
+  messages: FocusMessage[];
+}
+
+export interface FocusAlertPayload extends VersionedEvent {
+  accountEmail: string;
+  messages: FocusMessage[];
+}
+
+export interface FocusReviewPayload extends VersionedEvent {
+  messages: FocusMessage[];
+}
+
+export type BulkActionKind =
+  | "mark_read"
+  | "mark_unread"
+  | "label"
+  | "unlabel"
+  | "move"
+  | "delete"
+  | "block_sender";
+
+export interface BulkItemResult {
+  uid: string;
+  ok: boolean;
+  error?: string | null;
+}
+
+export interface BulkActionReport {
+  action: BulkActionKind;
+  succeeded: number;
+  failed: number;
+  results: BulkItemResult[];
+}
+
+export interface LocalUpdatePayload extends VersionedEvent {
+  accountEmail: string;
+  action: BulkActionKind | "set_flags" | "clear_flags";
+  uids: string[];
+  target?: string | null;
+  error?: string | null;
+}
+
+export type AttachmentRisk =
+  | "executable"
+  | "script"
+  | "macro_document"
+  | "double_extension"
+  | "oversized_archive";
+
+export interface MessageAttachment {
+  index: number;
+  filename: string;
+  content_type: string;
+  size: number;
+  risks: AttachmentRisk[];
+}
+
+export type QuarantineReason =
+  | "spam"
+  | "deceptive_link"
+  | "blocked_link"
+  | "risky_attachment";
+
+/** A message hidden from listings until released from quarantine. */
+export interface QuarantinedMessage extends AnalyzedMessage {
+  sender_email: string;
+  sender_display: string;
+  score: number;
+  reasons: QuarantineReason[];
+  quarantined_at: number;
+}
+
+export interface BreachCheckSettings {
+  checkAccounts: boolean;
+  rangeUrl?: string | null;
+}
+
+export type SecurityAlertKind =
+  | "breached_sender"
+  | "password_reset_flood"
+  | "account_in_breach"
+  | "lookalike_sender";
+
+export interface LookalikeDomain {
+  domain: string;
+  resembles: string;
+  reason: "homoglyph" | "typo";
+}
+
+export interface SecurityAlert {
+  kind: SecurityAlertKind;
+  severity: "warning" | "critical";
+  account_email: string;
+  message: string;
+  breach?: string | null;
   uids: string[];
+  latest_at?: number | null;
+  lookalike?: LookalikeDomain | null;
+}
+
+export interface OtpSettings {
+  enabled: boolean;
+  expireAfterSecs: number;
+  confirmWithLlm: boolean;
+}
+
+export type OtpKind = "code" | "reset_link";
+
+export interface OneTimeCode {
+  account_email: string;
+  uid: string;
+  kind: OtpKind;
+  code?: string | null;
+  link?: string | null;
+  sender_email: string;
+  subject: string;
+  received_at: number;
+  expires_at: number;
+}
+
+export interface AvailabilityRange {
+  start?: number | null;
+  end?: number | null;
+  question?: string | null;
+}
+
+export interface AvailabilityBlock {
+  start: number;
+  end: number;
+  busy: boolean;
+  events: string[];
+}
+
+export interface Availability {
+  blocks: AvailabilityBlock[];
+  free: boolean;
+}
+
+export interface CalDavSettings {
+  enabled: boolean;
+  calendarUrl?: string | null;
+  username?: string | null;
+}
+
+export type CalDavPushStatus = "pushed" | "already_present" | "failed";
+
+export interface CalDavLogEntry {
+  account_email: string;
+  event_uid: string;
+  sequence: number;
+  status: CalDavPushStatus;
+  detail?: string | null;
+  logged_at: number;
+}
+
+export interface CalDavExportReport {
+  pushed: number;
+  already_present: number;
+  failed: number;
+}
+
+export interface CardDavSettings {
+  enabled: boolean;
+  addressbookUrl?: string | null;
+  username?: string | null;
+  conflictPolicy: FlagConflictPolicy;
+}
+
+export interface CardDavSyncReport {
+  pulled: number;
+  pushed: number;
+  conflicts: number;
+  failed: number;
+  unlinked: number;
+}
+
+export type ContactSource = "mail" | "carddav" | "import";
+
+export interface Contact {
+  id: number;
+  uid: string;
+  full_name: string;
+  emails: string[];
+  phones: string[];
+  organization?: string | null;
+  note?: string | null;
+  revision?: number | null;
+  source: ContactSource;
+  sync_enabled: boolean;
+  synced: boolean;
+  updated_at: number;
+}
+
+export interface ContactEdit {
+  fullName: string;
+  emails: string[];
+  phones: string[];
+  organization?: string | null;
+  note?: string | null;
+}
+
+export interface VCardImportReport {
+  cards: number;
+  imported: number;
+}
+
+export interface VCardExportReport {
+  path: string;
+  contacts: number;
+}
+
+export interface AvatarSettings {
+  bimi: boolean;
+  favicon: boolean;
+  gravatar: boolean;
+}
+
+export type AvatarSource = "bimi" | "favicon" | "gravatar";
+
+export interface DnsSettings {
+  brandChecks: boolean;
+  autodiscoverMx: boolean;
+}
+
+export interface SenderAvatar {
+  source: AvatarSource;
+  data_uri: string;
+}
+
+export type DmarcPolicy = "none" | "quarantine" | "reject";
+
+export interface SenderBrand {
+  sender: string;
+  domain: string;
+  dmarc_policy?: DmarcPolicy | null;
+  bimi_logo?: string | null;
+  dmarc_aligned?: boolean | null;
+  brand_verified: boolean;
+}
+
+export interface OtpReceivedPayload extends VersionedEvent {
+  accountEmail: string;
+  uid: string;
+  kind: OtpKind;
+  code?: string | null;
+  link?: string | null;
+  senderEmail: string;
+  subject: string;
+  expiresAt: number;
+}
+
+/** `new-mail`: IDLE saw mail arrive and the incremental sync has run. */
+export interface NewMailPayload extends VersionedEvent {
+  accountEmail: string;
+  exists: number;
+  synced: boolean;
+}
+
+export type LlmDataClass =
+  | "sender"
+  | "subject"
+  | "snippet"
+  | "body"
+  | "attachments"
+  | "user_prompt";
+
+/** The furthest LLM backend a data class may reach. */
+export type LlmReach = "none" | "local" | "remote";
+
+export interface LlmPrivacyPolicy {
+  classes: Partial<Record<LlmDataClass, LlmReach>>;
+}
+
+export interface SenderRule {
+  sender_email: string;
+  scope: string;
+  status: SenderStatus;
+  updated_at: number;
+  origin: string;
+  origin_ref?: string | null;
+  expires_at?: number | null;
+}
+
+export interface SenderMutesExpiredPayload extends VersionedEvent {
+  rules: SenderRule[];
 }
 
 export interface RemoteDeleteProgressSummary {
@@ -61,16 +510,16 @@ export interface RemoteDeleteProgressSummary {
 export type RemoteDeleteOverrideMode = "auto" | "force-batch";
 
 export interface RemoteDeleteMetricsSnapshot {
-  account_email: string;
+  accountEmail: string;
   timestamp: number;
   mode: string;
-  batch_size: number;
+  batchSize: number;
   processed: number;
   failed: number;
   pending: number;
-  total_pending: number;
-  rate_per_minute: number;
-  override_mode: RemoteDeleteOverrideMode;
+  totalPending: number;
+  ratePerMinute: number;
+  overrideMode: RemoteDeleteOverrideMode;
 }
 
 export interface RemoteDeleteMetricsHistoryEntry {
@@ -80,8 +529,8 @@ export interface RemoteDeleteMetricsHistoryEntry {
   pending: number;
 }
 
-export interface RemoteDeleteMetricsResponse {
-  account_email: string;
+export interface RemoteDeleteMetricsResponse extends Partial<VersionedEvent> {
+  accountEmail: string;
   latest: RemoteDeleteMetricsSnapshot;
   history: RemoteDeleteMetricsHistoryEntry[];
 }
@@ -90,6 +539,7 @@ export type SenderStatus = "neutral" | "allowed" | "blocked";
 
 export interface AnalyzedMessage {
   uid: string;
+  message_key?: string;
   subject: string;
   date?: string | null;
   snippet?: string | null;
@@ -100,14 +550,59 @@ export interface AnalyzedMessage {
   analysis_categories: string[];
 }
 
+export interface BlockNote {
+  reason?: string | null;
+  origin_uid?: string | null;
+  created_at: number;
+}
+
+export interface PolicyRuleEvaluation {
+  entry: string;
+  scope: string;
+  status: SenderStatus;
+  origin: string;
+  origin_ref?: string | null;
+  level: "account_address" | "global_address" | "account_domain" | "global_domain";
+  outcome: "decides" | "overridden_by_allow" | "shadowed" | "neutral";
+}
+
+export interface PolicyDecision {
+  sender_email: string;
+  status: SenderStatus;
+  blocked: boolean;
+  protected: boolean;
+  explanation: string;
+  rules: PolicyRuleEvaluation[];
+}
+
 export interface SenderGroup {
   sender_email: string;
   sender_display: string;
   status: SenderStatus;
+  block_note?: BlockNote | null;
+  noise?: NoiseScore | null;
   message_count: number;
   messages: AnalyzedMessage[];
 }
 
+export interface NoiseScore {
+  score: number;
+  confidence: number;
+  open_rate: number;
+  reply_rate: number;
+  triage_confidence?: number | null;
+}
+
+export interface NoisySender {
+  sender_email: string;
+  sender_display: string;
+  status: SenderStatus;
+  message_count: number;
+  unread_count: number;
+  latest_date?: string | null;
+  noise: NoiseScore;
+}
+
 export interface SavedAccount {
   provider: Provider;
   email: string;
@@ -135,19 +630,29 @@ export interface SyncReport {
   duration_ms: number;
 }
 
-export interface SyncProgress {
+export interface SyncProgress extends Partial<VersionedEvent> {
   email: string;
   batch: number;
-  total_batches: number;
+  totalBatches: number;
   fetched: number;
   stored: number;
-  elapsed_ms: number;
+  elapsedMs: number;
 }
 
+export type LlmBackend = "llama" | "mock";
+
 export interface LlmStatus {
+  backend: LlmBackend;
   configured_path?: string | null;
   loaded: boolean;
   last_error?: string | null;
+  redact_prompts?: boolean;
+}
+
+/** A canned reply for the mock backend; `$1` in `response` is filled from the match. */
+export interface MockRule {
+  pattern: string;
+  response: string;
 }
 
 export interface KnownLlmModel {
@@ -165,3 +670,150 @@ export interface KnownLlmModel {
   active: boolean;
   installed_size_bytes?: number | null;
 }
+
+export interface Recipients {
+  to: string[];
+  cc?: string[];
+  bcc?: string[];
+}
+
+export type RecipientWarning =
+  | { kind: "large_reply_all"; count: number }
+  | { kind: "many_visible_recipients"; count: number }
+  | { kind: "list_addresses"; addresses: string[] }
+  | { kind: "duplicates"; addresses: string[] };
+
+export type PolicyAction = "off" | "warn" | "block";
+
+export interface SendPolicy {
+  allowedDomains: string[];
+  outsideAllowList: PolicyAction;
+  mixedRecipients: PolicyAction;
+  maxAttachmentBytes?: number | null;
+  oversizedAttachments: PolicyAction;
+}
+
+export interface OutgoingAttachment {
+  filename: string;
+  size: number;
+}
+
+export interface PolicyResult {
+  rule: "outside_allow_list" | "mixed_recipients" | "oversized_attachment";
+  action: PolicyAction;
+  message: string;
+  subjects: string[];
+}
+
+export type SubjectIssue = { kind: "empty" } | { kind: "placeholder"; subject: string };
+
+export interface RecipientReview {
+  recipients: string[];
+  recipient_count: number;
+  external_domains: string[];
+  suggest_bcc: string[];
+  warnings: RecipientWarning[];
+  policy: PolicyResult[];
+  subject_issue?: SubjectIssue | null;
+  requires_confirmation: boolean;
+  blocked: boolean;
+}
+
+export type DraftTone = "neutral" | "formal" | "friendly" | "urgent" | "negative";
+
+export interface FlaggedPhrase {
+  phrase: string;
+  start: number;
+  end: number;
+}
+
+export interface DraftStats {
+  word_count: number;
+  char_count: number;
+  sentence_count: number;
+  reading_time_secs: number;
+  readability_grade?: number | null;
+  tone: DraftTone;
+  flagged: FlaggedPhrase[];
+}
+
+export type RewriteInstruction = "more_formal" | "shorter" | "friendlier" | "fix_grammar";
+
+export interface DraftChange {
+  kind: "equal" | "insert" | "delete";
+  text: string;
+}
+
+export interface DraftRewrite {
+  instruction: RewriteInstruction;
+  rewritten: string;
+  changes: DraftChange[];
+}
+
+export interface DraftCheckSettings {
+  riskyPhrases: string[];
+}
+
+export type UploadBackendConfig =
+  | { kind: "web_dav"; url: string; username: string; publicUrl?: string | null }
+  | {
+      kind: "s3";
+      endpoint: string;
+      region: string;
+      bucket: string;
+      accessKeyId: string;
+      pathStyle?: boolean;
+      linkExpirySecs?: number;
+    };
+
+export interface UploadSettings {
+  enabled: boolean;
+  thresholdBytes: number;
+  backend?: UploadBackendConfig | null;
+}
+
+export type DeliveryPath = "smtp" | "gmail_api" | "graph";
+
+export interface AttachmentUpload {
+  filename: string;
+  size: number;
+  backend: string;
+  url: string;
+  uploaded_at: number;
+}
+
+export interface OutgoingMessageAttachment {
+  filename: string;
+  content_type: string;
+  data: number[];
+}
+
+export interface OutgoingMessage {
+  to: string[];
+  cc?: string[];
+  bcc?: string[];
+  subject: string;
+  body: string;
+  in_reply_to?: string | null;
+  references?: string[];
+  attachments?: OutgoingMessageAttachment[];
+}
+
+export interface SendOutcome {
+  sent: boolean;
+  review: RecipientReview;
+  message_id?: string | null;
+  delivery_path?: DeliveryPath | null;
+  uploads: AttachmentUpload[];
+  saved_to_sent: boolean;
+}
+
+export interface AnalysisCoverage {
+  account_email: string;
+  total: number;
+  analyzed: number;
+  unanalyzed: number;
+  failed: number;
+  /** Analyzed by another model, prompt, or tag set than the current one. */
+  stale: number;
+}
@@ -28,8 +28,8 @@ export function buildSyncStatusPills({
   const pills: StatusPill[] = [];
 
   const progressPercent =
-    syncProgress && syncProgress.total_batches > 0
-      ? Math.min(100, Math.round((syncProgress.batch / syncProgress.total_batches) * 100))
+    syncProgress && syncProgress.totalBatches > 0
+      ? Math.min(100, Math.round((syncProgress.batch / syncProgress.totalBatches) * 100))
       : null;
 
   if (isSyncing) {
@@ -40,10 +40,10 @@ export function buildSyncStatusPills({
     });
     
     // Show batch progress during sync
-    if (syncProgress && syncProgress.total_batches > 0) {
+    if (syncProgress && syncProgress.totalBatches > 0) {
       pills.push({
         key: "progress",
-        text: `Batch ${syncProgress.batch}/${syncProgress.total_batches}`,
+        text: `Batch ${syncProgress.batch}/${syncProgress.totalBatches}`,
         tone: "warning"
       });
     }